@@ -1,7 +1,15 @@
-use notcraft_common::prelude::*;
+use notcraft_common::{
+    content_pack::{ContentPacks, BASE_PACK},
+    prelude::*,
+    world::registry::{BlockId, BlockRegistry},
+};
 use rand::Rng;
 use serde::Deserialize;
-use std::{collections::HashMap, fs::File, path::Path};
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::{Path, PathBuf},
+};
 
 use crate::{
     client::audio::{AudioId, AudioState, EmitterParameters},
@@ -132,12 +140,13 @@ fn resolve_inheritance<'n>(
 fn resolve_node(
     node: &ManifestNode,
     manifest: &AudioManifest,
+    dirs: &[PathBuf],
     state: &mut AudioState,
     last_name: &str,
 ) -> Result<AudioNode> {
     Ok(match node {
         ManifestNode::Ref(name) => {
-            resolve_node(get_node_ref(name, manifest)?, manifest, state, name)?
+            resolve_node(get_node_ref(name, manifest)?, manifest, dirs, state, name)?
         }
         ManifestNode::Pool {
             inherit: _,
@@ -169,9 +178,12 @@ fn resolve_node(
             for pattern in patterns.iter() {
                 // TODO: does this allow attackers to use `..` to escape the resources dir?
                 // would it even matter?
-                for path in glob::glob(&format!("resources/audio/{pattern}"))? {
-                    let id = state.add(File::open(path?)?)?;
-                    items.push(1, Box::new(AudioNode::Sound { id, params }));
+                for dir in dirs.iter() {
+                    let pattern = dir.join(pattern);
+                    for path in glob::glob(&pattern.to_string_lossy())? {
+                        let id = state.add(File::open(path?)?)?;
+                        items.push(1, Box::new(AudioNode::Sound { id, params }));
+                    }
                 }
             }
             AudioNode::Choice(items)
@@ -180,7 +192,7 @@ fn resolve_node(
         ManifestNode::Choice(choices) => {
             let mut items = WeightedList::default();
             for choice in choices.iter() {
-                let node = resolve_node(choice, manifest, state, last_name)?;
+                let node = resolve_node(choice, manifest, dirs, state, last_name)?;
                 items.push(1, Box::new(node));
             }
             AudioNode::Choice(items)
@@ -188,17 +200,17 @@ fn resolve_node(
         ManifestNode::Weighted(choices) => {
             let mut items = WeightedList::default();
             for &(weight, ref choice) in choices.iter() {
-                let node = resolve_node(choice, manifest, state, last_name)?;
+                let node = resolve_node(choice, manifest, dirs, state, last_name)?;
                 items.push(weight, Box::new(node));
             }
             AudioNode::Choice(items)
         }
         ManifestNode::Layered { default, layers } => AudioNode::Layered {
-            default: Box::new(resolve_node(default, manifest, state, last_name)?),
+            default: Box::new(resolve_node(default, manifest, dirs, state, last_name)?),
             layers: {
                 let mut out = Vec::with_capacity(layers.len());
                 for &(probability, ref layer) in layers.iter() {
-                    let node = resolve_node(layer, manifest, state, last_name)?;
+                    let node = resolve_node(layer, manifest, dirs, state, last_name)?;
                     out.push((probability, Box::new(node)));
                 }
                 out
@@ -237,6 +249,19 @@ impl RandomizedAudioPools {
         self.sound_idx_map.get(name).copied().map(SoundId)
     }
 
+    /// the sound a block makes for `kind`, like `break`, `place`, or `step`.
+    /// blocks share their sounds with the rest of their sound group, which are
+    /// found at `blocks/<kind>/<group>` in the manifest.
+    pub fn block_sound(
+        &self,
+        registry: &BlockRegistry,
+        id: BlockId,
+        kind: &str,
+    ) -> Option<SoundId> {
+        let group = registry.get(id).sound_group()?;
+        self.id(&format!("blocks/{}/{}", kind, group))
+    }
+
     pub fn select<R, F>(&self, rng: &mut R, id: SoundId, mut func: F)
     where
         R: Rng + ?Sized,
@@ -273,12 +298,34 @@ where
     }
 }
 
-pub fn load_audio<P: AsRef<Path>>(path: P, state: &mut AudioState) -> Result<RandomizedAudioPools> {
-    let manifest: AudioManifest = ron::from_str(&std::fs::read_to_string(path)?)?;
+fn read_manifest(path: &Path) -> Result<AudioManifest> {
+    Ok(ron::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+/// loads the sounds in the audio manifest at `path`, along with the
+/// `audio/manifest.ron` of every enabled content pack. sound files are looked
+/// for next to the manifest, and in the `audio` directory of every enabled
+/// content pack.
+pub fn load_audio<P: AsRef<Path>>(
+    path: P,
+    state: &mut AudioState,
+    packs: &mut ContentPacks,
+) -> Result<RandomizedAudioPools> {
+    let mut layers = vec![];
+    let mut dirs = vec![];
+    for (pack, path) in packs.layers(path.as_ref(), "audio/manifest.ron") {
+        match read_manifest(&path) {
+            Ok(manifest) => layers.push((pack, manifest.0.into_iter().collect())),
+            Err(err) if pack == BASE_PACK => return Err(err),
+            Err(err) => log::error!("failed to read sounds from content pack '{pack}': {err}"),
+        }
+        dirs.extend(path.parent().map(Path::to_path_buf));
+    }
+    let manifest = AudioManifest(packs.merge("sound", layers).into_iter().collect());
 
     let mut pools = RandomizedAudioPools::default();
     for (name, node) in manifest.0.iter() {
-        match resolve_node(node, &manifest, state, name) {
+        match resolve_node(node, &manifest, &dirs, state, name) {
             Ok(resolved) => {
                 pools.sound_idx_map.insert(name.into(), pools.sounds.len());
                 pools.sounds.push(resolved);
@@ -2,10 +2,18 @@ use ambisonic::{
     rodio::{Decoder, Source},
     Ambisonic, AmbisonicBuilder, SoundController,
 };
+use super::{
+    mixer::{save_audio_settings, update_audio_mixer, AudioBus, AudioMixer, BusSource, SharedGain},
+    settings::SettingsFile,
+};
+use bevy_ecs::system::SystemParam;
 use nalgebra::{Point3, SimdComplexField, Vector3};
 use notcraft_common::{prelude::*, transform::Transform};
 use num_traits::Pow;
-use rand::distributions::{Distribution, Uniform};
+use rand::{
+    distributions::{Distribution, Uniform},
+    Rng,
+};
 use std::{
     collections::HashMap,
     io::{Cursor, Read},
@@ -82,7 +90,8 @@ impl Default for EmitterParameters {
     }
 }
 
-pub struct AudioEmitter {
+/// a sound that was started by an [`AudioEvent`], and is still playing.
+pub struct PlayingSound {
     sound: SoundController,
     start: Instant,
     duration: Option<Duration>,
@@ -91,9 +100,24 @@ pub struct AudioEmitter {
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
 struct DespawnEmitter;
 
-impl std::fmt::Debug for AudioEmitter {
+#[derive(SystemParam)]
+pub struct CurrentListener<'a> {
+    active: Res<'a, ActiveAudioListener>,
+    query: Query<'a, (&'static Transform, &'static AudioListener)>,
+}
+
+impl<'a> CurrentListener<'a> {
+    pub fn transform(&self) -> Option<&Transform> {
+        self.active
+            .0
+            .and_then(|active| self.query.get(active).ok())
+            .map(|(transform, _)| transform)
+    }
+}
+
+impl std::fmt::Debug for PlayingSound {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("AudioEmitter")
+        f.debug_struct("PlayingSound")
             .field("start", &self.start)
             .field("duration", &self.duration)
             .finish_non_exhaustive()
@@ -102,25 +126,24 @@ impl std::fmt::Debug for AudioEmitter {
 
 fn update_emitters(
     mut cmd: Commands,
-    active_listener: Res<ActiveAudioListener>,
-    listener_query: Query<(&Transform, &AudioListener)>,
+    listener: CurrentListener,
     emitter_query: Query<(
         Entity,
         &Transform,
-        &mut AudioEmitter,
+        &mut PlayingSound,
         Option<&DespawnEmitter>,
     )>,
 ) {
-    let (listener_transform, _) = match active_listener.0.and_then(|e| listener_query.get(e).ok()) {
-        Some(it) => it,
-        _ => return,
+    let listener_transform = match listener.transform() {
+        Some(transform) => transform,
+        None => return,
     };
 
     emitter_query.for_each_mut(|(entity, transform, mut emitter, despawn)| {
         match emitter.duration {
             Some(duration) if emitter.start.elapsed() > duration => match despawn {
                 Some(DespawnEmitter) => cmd.entity(entity).despawn(),
-                None => drop(cmd.entity(entity).remove::<AudioEmitter>()),
+                None => drop(cmd.entity(entity).remove::<PlayingSound>()),
             },
 
             _ => {
@@ -146,37 +169,45 @@ fn curve_audio_amplitude(distance: f32) -> f32 {
     }
 }
 
-// fn make_spatial_source() -> impl ambisonic::rodio::Source {}
+/// decodes `source`, picking its pitch and amplitude from the ranges in its
+/// parameters.
+fn decode_source<R: Rng + ?Sized>(
+    state: &AudioState,
+    source: &ParameterizedSource,
+    rng: &mut R,
+) -> impl Source<Item = f32> + Send {
+    let decoded = match &source.source {
+        &EmitterSource::Sample(id) => Decoder::new(Cursor::new(state.get(id))),
+    };
+    let params = &source.params;
+    let speed = Uniform::new_inclusive(params.min_pitch, params.max_pitch).sample(rng);
+    let amplitude = Uniform::new_inclusive(params.min_amplitude, params.max_amplitude).sample(rng);
+    // TODO: unwrap
+    decoded
+        .unwrap()
+        .convert_samples()
+        .speed(speed)
+        .amplify(amplitude)
+}
 
 fn process_audio_events(
     mut cmd: Commands,
     audio_scene: NonSend<Ambisonic>,
     state: Res<AudioState>,
+    mut mixer: ResMut<AudioMixer>,
     mut events: EventReader<AudioEvent>,
-    active_listener: Res<ActiveAudioListener>,
-    listener_query: Query<(&Transform, &AudioListener)>,
+    listener: CurrentListener,
     emitter_query: Query<(Entity, &Transform)>,
 ) {
-    let (listener_transform, _) = match active_listener.0.and_then(|e| listener_query.get(e).ok()) {
-        Some(it) => it,
-        _ => return,
+    let listener_transform = match listener.transform() {
+        Some(transform) => transform,
+        None => return,
     };
 
     let mut rng = rand::thread_rng();
     for event in events.iter() {
-        let source = match &event.source().source {
-            &EmitterSource::Sample(id) => Decoder::new(Cursor::new(state.get(id))),
-        };
-        let params = &event.source().params;
-        let speed = Uniform::new_inclusive(params.min_pitch, params.max_pitch).sample(&mut rng);
-        let amplitude =
-            Uniform::new_inclusive(params.min_amplitude, params.max_amplitude).sample(&mut rng);
-        // TODO: unwrap
-        let source = source
-            .unwrap()
-            .convert_samples()
-            .speed(speed)
-            .amplify(amplitude);
+        let source = decode_source(&state, event.source(), &mut rng);
+        let source = mixer.route(event.source().bus, source, Instant::now());
         match event {
             AudioEvent::PlaySpatial(entity, _) => {
                 if let Ok((entity, transform)) = emitter_query.get(*entity) {
@@ -192,7 +223,7 @@ fn process_audio_events(
                         source.amplify(curve_audio_amplitude(audio_pos.coords.magnitude())),
                         audio_pos.into(),
                     );
-                    cmd.entity(entity).insert(AudioEmitter {
+                    cmd.entity(entity).insert(PlayingSound {
                         sound,
                         start: Instant::now(),
                         duration,
@@ -213,7 +244,7 @@ fn process_audio_events(
                 cmd.spawn()
                     .insert(Transform::to(pos))
                     .insert(DespawnEmitter)
-                    .insert(AudioEmitter {
+                    .insert(PlayingSound {
                         sound,
                         start: Instant::now(),
                         duration,
@@ -228,6 +259,131 @@ fn process_audio_events(
     }
 }
 
+/// how many [`AudioEmitter`]s can play at once. the closest ones get to play,
+/// and the rest are virtualized until some of the closer ones go away.
+const MAX_PLAYING_EMITTERS: usize = 16;
+/// emitters that are already playing keep playing until they're this many
+/// times further away than their range, so that they don't stop and start
+/// over and over again while the listener stands right at the edge of it.
+const EMITTER_RANGE_MARGIN: f32 = 1.1;
+/// emitters fade out over this fraction of their range, so that they're
+/// already silent by the time they stop playing.
+const EMITTER_FADE: f32 = 0.25;
+
+/// a sound that plays on a loop from wherever its entity is, for as long as
+/// the entity has this component, like flowing water.
+///
+/// emitters further away than their `range` are virtualized, which means that
+/// they stop playing entirely until the listener comes back in range, and start
+/// over from the beginning when it does. only the closest
+/// `MAX_PLAYING_EMITTERS` are ever played at once.
+#[derive(Debug)]
+pub struct AudioEmitter {
+    pub source: ParameterizedSource,
+    /// how far away the emitter can be heard from, in blocks.
+    pub range: f32,
+}
+
+impl AudioEmitter {
+    pub fn new(source: ParameterizedSource, range: f32) -> Self {
+        Self { source, range }
+    }
+}
+
+/// an [`AudioEmitter`] that's playing.
+struct LoopingSound {
+    sound: SoundController,
+    /// how loud the emitter is from where the listener is.
+    gain: Arc<SharedGain>,
+}
+
+/// every [`AudioEmitter`] that's playing, by the entity it's attached to.
+/// these are kept out of the entities themselves, so that they can still be
+/// stopped after their entity is despawned.
+#[derive(Default)]
+struct LoopingSounds {
+    playing: HashMap<Entity, LoopingSound>,
+}
+
+/// how much an emitter `distance` blocks away is faded out, from 1 for not at
+/// all to 0 at the edge of its range.
+fn emitter_fade(distance: f32, range: f32) -> f32 {
+    let fade_distance = range * EMITTER_FADE;
+    util::clamp(0.0, 1.0, (range - distance) / fade_distance)
+}
+
+/// picks the emitters that should be playing out of `emitters`, which are
+/// `(entity, distance, range)` for every emitter, closest first.
+fn audible_emitters<I, F>(emitters: I, is_playing: F) -> Vec<(Entity, f32)>
+where
+    I: Iterator<Item = (Entity, f32, f32)>,
+    F: Fn(Entity) -> bool,
+{
+    let mut audible = emitters
+        .filter(|&(entity, distance, range)| match is_playing(entity) {
+            true => distance <= range * EMITTER_RANGE_MARGIN,
+            false => distance <= range,
+        })
+        .map(|(entity, distance, _)| (entity, distance))
+        .collect::<Vec<_>>();
+    audible.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+    audible.truncate(MAX_PLAYING_EMITTERS);
+    audible
+}
+
+fn update_looping_emitters(
+    audio_scene: NonSend<Ambisonic>,
+    state: Res<AudioState>,
+    mut mixer: ResMut<AudioMixer>,
+    mut sounds: ResMut<LoopingSounds>,
+    listener: CurrentListener,
+    emitter_query: Query<(Entity, &Transform, &AudioEmitter)>,
+) {
+    let listener_transform = match listener.transform() {
+        Some(transform) => transform,
+        None => return,
+    };
+    let matrix = listener_transform.to_matrix().try_inverse().unwrap();
+    let audio_pos = |transform: &Transform| matrix.transform_point(&transform.pos());
+
+    let emitters = emitter_query.iter().map(|(entity, transform, emitter)| {
+        let distance = audio_pos(transform).coords.magnitude();
+        (entity, distance, emitter.range)
+    });
+    let audible = audible_emitters(emitters, |entity| sounds.playing.contains_key(&entity));
+
+    // this also stops the sounds of emitters that were removed or despawned
+    sounds.playing.retain(|entity, looping| {
+        let keep = audible.iter().any(|(audible, _)| audible == entity);
+        if !keep {
+            looping.sound.stop();
+        }
+        keep
+    });
+
+    let mut rng = rand::thread_rng();
+    for (entity, distance) in audible {
+        let (_, transform, emitter) = emitter_query.get(entity).unwrap();
+        let pos = audio_pos(transform);
+        let gain = curve_audio_amplitude(distance) * emitter_fade(distance, emitter.range);
+
+        match sounds.playing.get_mut(&entity) {
+            Some(looping) => {
+                looping.sound.adjust_position(pos.into());
+                looping.gain.set(gain);
+            }
+            None => {
+                let gain = Arc::new(SharedGain::new(gain));
+                let source = decode_source(&state, &emitter.source, &mut rng).repeat_infinite();
+                let source = mixer.route(emitter.source.bus, source, Instant::now());
+                let source = BusSource::with_gain(source, Arc::clone(&gain));
+                let sound = audio_scene.play_at(source, pos.into());
+                sounds.playing.insert(entity, LoopingSound { sound, gain });
+            }
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct AudioId(usize);
 
@@ -235,6 +391,8 @@ pub struct AudioId(usize);
 pub struct ParameterizedSource {
     pub source: EmitterSource,
     pub params: EmitterParameters,
+    /// which mixer bus the sound plays through. defaults to effects.
+    pub bus: AudioBus,
 }
 
 impl ParameterizedSource {
@@ -242,9 +400,15 @@ impl ParameterizedSource {
         Self {
             source: EmitterSource::Sample(id),
             params: Default::default(),
+            bus: AudioBus::Effects,
         }
     }
 
+    pub fn with_bus(mut self, bus: AudioBus) -> Self {
+        self.bus = bus;
+        self
+    }
+
     pub fn with_parameters(mut self, value: EmitterParameters) -> Self {
         self.params = value;
         self
@@ -286,7 +450,7 @@ impl ParameterizedSource {
 #[derive(Debug)]
 pub enum AudioEvent {
     /// Notifies the sound system to play a 3D sound at the given entity's
-    /// location, and attaches an [`AudioEmitter`] component to the entity.
+    /// location, and attaches a [`PlayingSound`] component to the entity.
     /// If the entity is moved, the audio emitter will be as well. The component
     /// will be removed from the entity when the sound is done playing.
     PlaySpatial(Entity, ParameterizedSource),
@@ -323,10 +487,47 @@ impl Plugin for AudioPlugin {
         app.insert_non_send_resource(AmbisonicBuilder::default().build());
         app.insert_resource(AudioState::new().expect("failed to init audio"));
         app.insert_resource(ActiveAudioListener(None));
+        app.init_resource::<LoopingSounds>();
+
+        let settings = app
+            .world()
+            .get_resource::<SettingsFile>()
+            .expect("`AudioPlugin` added before `SettingsFile` was inserted!")
+            .settings()
+            .audio
+            .clone();
+        app.insert_resource(AudioMixer::new(&settings));
+        app.insert_resource(settings);
 
         app.add_event::<AudioEvent>();
 
         app.add_system_to_stage(CoreStage::PostUpdate, update_emitters.system());
         app.add_system_to_stage(CoreStage::PostUpdate, process_audio_events.system());
+        app.add_system_to_stage(CoreStage::PostUpdate, update_looping_emitters.system());
+        app.add_system_to_stage(CoreStage::PostUpdate, update_audio_mixer.system());
+        app.add_system(save_audio_settings.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_closest_emitters_in_range_play() {
+        let [near, playing, far] = [Entity::new(0), Entity::new(1), Entity::new(2)];
+        let emitters = [(far, 20.5, 20.0), (playing, 21.0, 20.0), (near, 5.0, 20.0)];
+        let audible = audible_emitters(emitters.into_iter(), |entity| entity == playing);
+        // emitters that are already playing get a little more room
+        assert_eq!(audible, [(near, 5.0), (playing, 21.0)]);
+
+        let crowd = (0..40).map(|id| (Entity::new(id), 40.0 - id as f32, 50.0));
+        let audible = audible_emitters(crowd, |_| false);
+        assert_eq!(audible.len(), MAX_PLAYING_EMITTERS);
+        assert_eq!(audible[0], (Entity::new(39), 1.0));
+
+        assert_eq!(emitter_fade(5.0, 20.0), 1.0);
+        assert_eq!(emitter_fade(17.5, 20.0), 0.5);
+        assert_eq!(emitter_fade(21.0, 20.0), 0.0);
     }
 }
@@ -0,0 +1,88 @@
+//! a small tooltip next to the crosshair, saying what the block being looked
+//! at is, its ID, and which content pack it came from. it can be turned off
+//! with `/tooltip off`.
+
+use crate::client::{
+    render::{font, renderer::ImmediateUi},
+    settings::SettingsFile,
+};
+use notcraft_common::{
+    game_state::playing,
+    lang::tr,
+    prelude::*,
+    targeting::CurrentTarget,
+    world::{chunk::ChunkAccess, registry::BlockRegistry, BlockPos},
+};
+use std::sync::Arc;
+
+/// how far the tooltip is from the crosshair, in UI pixels.
+const OFFSET: [f32; 2] = [8.0, -6.0];
+const PADDING: f32 = 2.0;
+const LINE_HEIGHT: f32 = font::GLYPH_HEIGHT + 2.0;
+
+fn tooltip_lines(registry: &BlockRegistry, access: &mut ChunkAccess, pos: BlockPos) -> Vec<String> {
+    let id = match access.block(pos) {
+        Some(id) => id,
+        None => return vec![],
+    };
+    let block = registry.get(id);
+    let name = match block.state_name() {
+        Some(state) => format!("{} ({})", block.display_name(), state),
+        None => block.display_name().into(),
+    };
+    vec![
+        name,
+        tr!("tooltip.id", block.name(), id.raw()),
+        tr!("tooltip.pack", block.pack()),
+    ]
+}
+
+fn draw_block_tooltip(
+    settings_file: Res<SettingsFile>,
+    target: Res<CurrentTarget>,
+    mut access: ResMut<ChunkAccess>,
+    mut ui: ResMut<ImmediateUi>,
+) {
+    if !settings_file.settings().block_tooltip {
+        return;
+    }
+    let hit = match target.0 {
+        Some(hit) => hit,
+        None => return,
+    };
+
+    let registry = Arc::clone(access.registry());
+    let lines = tooltip_lines(&registry, &mut access, hit.pos);
+    if lines.is_empty() {
+        return;
+    }
+
+    let [left, top] = OFFSET;
+    let widest = lines.iter().map(|line| font::text_width(line));
+    let width = widest.fold(0.0, f32::max) + 2.0 * PADDING;
+    let height = lines.len() as f32 * LINE_HEIGHT + 2.0 * PADDING - 2.0;
+    let background = [0.05, 0.05, 0.05, 0.7];
+    ui.rect([left, top - height], [left + width, top], background);
+
+    for (idx, line) in lines.iter().enumerate() {
+        let y = top - PADDING - font::GLYPH_HEIGHT - idx as f32 * LINE_HEIGHT;
+        let color = match idx {
+            0 => [1.0; 4],
+            _ => [0.7, 0.7, 0.7, 1.0],
+        };
+        ui.text([left + PADDING, y], line, color);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BlockTooltipPlugin {}
+
+impl Plugin for BlockTooltipPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(
+            draw_block_tooltip
+                .system()
+                .with_run_criteria(playing.system()),
+        );
+    }
+}
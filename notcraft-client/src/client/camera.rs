@@ -1,10 +1,85 @@
+//! cameras, and the projections they draw the world with.
+//!
+//! a camera's field of view comes from [`CameraSettings`], and eases towards
+//! whatever its [`FovModifiers`] call for, like sprinting or zooming in. its
+//! near and far planes follow how far away it can see loaded terrain. anything
+//! that draws from or culls against a camera's projection picks all of this up
+//! on its own.
+
+use super::settings::SettingsFile;
 use bevy_ecs::system::SystemParam;
 use nalgebra::{Matrix4, Perspective3, Point3};
-use notcraft_common::{prelude::*, transform::Transform};
+use notcraft_common::{clock::GameTime, prelude::*, transform::Transform, world::chunk};
+
+/// the narrowest and widest field of view the settings are allowed to ask for,
+/// in degrees.
+pub const MIN_FOV: f32 = 10.0;
+pub const MAX_FOV: f32 = 150.0;
+
+/// how quickly the field of view catches up to where it's headed. higher is
+/// snappier.
+const FOV_EASE_RATE: f32 = 12.0;
+
+/// the far plane for cameras that aren't following anything that loads chunks.
+const DEFAULT_FAR: f32 = 1000.0;
+/// keeping the near plane within this fraction of the far plane keeps the depth
+/// buffer precise enough that distant faces don't fight.
+const NEAR_FAR_RATIO: f32 = 4000.0;
+/// the near plane never gets any further away than this, so that it doesn't cut
+/// into walls the player is standing right up against.
+const MAX_NEAR: f32 = 0.1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    /// the vertical field of view, in degrees.
+    pub fov: f32,
+    /// how many degrees wider the field of view gets while sprinting.
+    pub sprint_fov: f32,
+    /// the vertical field of view while holding the zoom key, in degrees.
+    pub zoom_fov: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            fov: 90.0,
+            sprint_fov: 10.0,
+            zoom_fov: 30.0,
+        }
+    }
+}
+
+impl CameraSettings {
+    /// the vertical field of view that a camera eases towards, in radians.
+    /// zooming in wins out over sprinting.
+    pub fn target_fovy(&self, modifiers: FovModifiers) -> f32 {
+        let degrees = if modifiers.zooming {
+            self.zoom_fov
+        } else if modifiers.sprinting {
+            self.fov + self.sprint_fov
+        } else {
+            self.fov
+        };
+        util::clamp(MIN_FOV, MAX_FOV, degrees).to_radians()
+    }
+}
+
+/// what's currently widening or narrowing a camera's field of view.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct FovModifiers {
+    pub sprinting: bool,
+    pub zooming: bool,
+}
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Camera {
     pub projection: Perspective3<f32>,
+    pub fov_modifiers: FovModifiers,
+    /// how many chunks around the camera are loaded, which decides where its
+    /// near and far planes go. `None` for cameras that nothing loads chunks
+    /// around.
+    pub render_distance: Option<usize>,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Default)]
@@ -14,16 +89,71 @@ impl Camera {
     pub fn projection_matrix(&self) -> Matrix4<f32> {
         self.projection.into()
     }
+
+    /// the near and far planes, far enough out to see the corners of the
+    /// farthest loaded chunks.
+    pub fn clip_planes(&self) -> (f32, f32) {
+        let far = match self.render_distance {
+            Some(radius) => {
+                let reach = (radius + 1) * chunk::CHUNK_LENGTH;
+                reach as f32 * f32::sqrt(3.0)
+            }
+            None => DEFAULT_FAR,
+        };
+        (f32::min(far / NEAR_FAR_RATIO, MAX_NEAR), far)
+    }
+
+    /// how much to scale mouse movement by, so that looking around while
+    /// zoomed in doesn't move the view any further across the screen than it
+    /// would otherwise.
+    pub fn look_sensitivity(&self, settings: &CameraSettings) -> f32 {
+        let base = settings.target_fovy(FovModifiers::default());
+        f32::min(self.projection.fovy() / base, 1.0)
+    }
+
+    /// moves the projection `ease` of the way towards the field of view the
+    /// settings ask for, and snaps the clip planes to where they belong.
+    fn update_projection(&mut self, settings: &CameraSettings, ease: f32) {
+        let target = settings.target_fovy(self.fov_modifiers);
+        let fovy = self.projection.fovy();
+        self.projection.set_fovy(fovy + (target - fovy) * ease);
+
+        let (near, far) = self.clip_planes();
+        self.projection.set_znear_and_zfar(near, far);
+    }
 }
 
 impl Default for Camera {
     fn default() -> Self {
         Camera {
-            projection: Perspective3::new(1.0, std::f32::consts::PI / 2.0, 0.1, 1000.0),
+            projection: Perspective3::new(1.0, std::f32::consts::PI / 2.0, 0.1, DEFAULT_FAR),
+            fov_modifiers: FovModifiers::default(),
+            render_distance: None,
         }
     }
 }
 
+fn update_projections(
+    time: Res<GameTime>,
+    settings_file: Res<SettingsFile>,
+    cameras: Query<&mut Camera>,
+) {
+    let settings = &settings_file.settings().camera;
+    let ease = 1.0 - f32::exp(-FOV_EASE_RATE * time.delta_seconds());
+    cameras.for_each_mut(|mut camera| camera.update_projection(settings, ease));
+}
+
+#[derive(Debug, Default)]
+pub struct CameraPlugin {}
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // everything that changes what a camera's projection should be does so
+        // during `Update`, and everything that uses it does so while rendering.
+        app.add_system_to_stage(CoreStage::PostUpdate, update_projections.system());
+    }
+}
+
 #[derive(SystemParam)]
 pub struct CurrentCamera<'a> {
     active: Res<'a, ActiveCamera>,
@@ -39,6 +169,13 @@ impl<'a> CurrentCamera<'a> {
             .unwrap_or(point![0.0, 0.0, 0.0])
     }
 
+    pub fn transform(&self) -> Option<&Transform> {
+        self.active
+            .0
+            .and_then(|active| self.query.get(active).ok())
+            .map(|(_, transform)| transform)
+    }
+
     pub fn view(&self) -> Matrix4<f32> {
         self.active
             .0
@@ -58,3 +195,39 @@ impl<'a> CurrentCamera<'a> {
         proj
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zooming_eases_in_and_slows_looking_around() {
+        let settings = CameraSettings::default();
+        let mut camera = Camera {
+            render_distance: Some(7),
+            ..Default::default()
+        };
+        camera.update_projection(&settings, 1.0);
+        assert!((camera.projection.fovy() - 90f32.to_radians()).abs() < 1e-5);
+        assert_eq!(camera.look_sensitivity(&settings), 1.0);
+
+        let (near, far) = camera.clip_planes();
+        assert!(far > 8.0 * 32.0 && far < 1000.0);
+        assert!((camera.projection.zfar() - far).abs() < 0.1);
+        assert!(near <= MAX_NEAR);
+
+        camera.fov_modifiers.zooming = true;
+        camera.update_projection(&settings, 0.5);
+        assert!((camera.projection.fovy() - 60f32.to_radians()).abs() < 1e-5);
+        assert!((camera.look_sensitivity(&settings) - 2.0 / 3.0).abs() < 1e-5);
+
+        // sprinting makes the view wider, but not any more sensitive
+        camera.fov_modifiers = FovModifiers {
+            sprinting: true,
+            zooming: false,
+        };
+        camera.update_projection(&settings, 1.0);
+        assert!((camera.projection.fovy() - 100f32.to_radians()).abs() < 1e-5);
+        assert_eq!(camera.look_sensitivity(&settings), 1.0);
+    }
+}
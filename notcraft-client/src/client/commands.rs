@@ -0,0 +1,1056 @@
+use crate::{
+    audio_pool::RandomizedAudioPools,
+    client::{
+        audio::{AudioEmitter, ParameterizedSource},
+        camera::{CurrentCamera, MAX_FOV, MIN_FOV},
+        free_camera::{FreeCamera, MAX_SPEED, MIN_SPEED},
+        logging,
+        mixer::{AudioBus, AudioSettings},
+        render::{
+            render_scale::{RenderScale, MAX_SCALE, MIN_SCALE},
+            renderer::AntiAliasing,
+        },
+        settings::SettingsFile,
+        texture_pack::{self, ReloadTextures},
+        window::WindowSettings,
+    },
+    make_ray, player_spawn_translation, player_translation_at, PlayerController,
+};
+use nalgebra::{Point3, Vector3};
+use notcraft_common::{
+    console::{add_console_command, ConsoleCommand, ConsoleLabel},
+    content_pack::{self, ContentPacks, CONTENT_PACK_DIR},
+    explosion::{ExplosionEvent, MAX_EXPLOSION_RADIUS},
+    game_mode::GameMode,
+    item_drop::DroppedItem,
+    lang::{self, tr},
+    physics::RigidBody,
+    prelude::*,
+    stats::{format_duration, WorldStats},
+    targeting::TargetingConfig,
+    teleport::TeleportEvent,
+    transform::Transform,
+    weather::{Weather, WeatherKind},
+    world::{
+        block_entity::{BlockEntity, SignData},
+        chunk::{ChunkAccess, ChunkSectionPos},
+        pregen::{PregenQueue, MAX_PREGEN_RADIUS},
+        query::find_nearest_block,
+        relight::RelightQueue,
+        spawn::{is_safe_spawn, WorldSpawn},
+        trace_ray, BlockPos, VoxelWorld, WorldPos,
+    },
+};
+use std::{path::Path, sync::Arc};
+
+// how far `/locate` looks when no radius is given.
+const DEFAULT_LOCATE_RADIUS: i32 = 64;
+// how many entries `/log` shows when no count is given.
+const DEFAULT_LOG_COUNT: usize = 20;
+// how big `/explode` explosions are when no radius or power is given.
+const DEFAULT_EXPLOSION_RADIUS: f32 = 4.0;
+const DEFAULT_EXPLOSION_POWER: f32 = 4.0;
+// how far away the block `/explode` blows up can be.
+const EXPLODE_RANGE: f32 = 64.0;
+// how far away `/emitter` sounds can be heard when no range is given.
+const DEFAULT_EMITTER_RANGE: f32 = 24.0;
+// how many chunks around the player `/relight` relights when no radius is
+// given, and the most it can be given.
+const DEFAULT_RELIGHT_RADIUS: i32 = 2;
+const MAX_RELIGHT_RADIUS: i32 = 16;
+
+/// marks the looping sounds placed with `/emitter`, so that `/emitter clear`
+/// doesn't remove any others.
+#[derive(Copy, Clone, Debug, Default)]
+struct CommandEmitter;
+
+fn player_block_pos(
+    player_controller: &PlayerController,
+    transforms: &Query<&Transform>,
+) -> Result<BlockPos> {
+    match transforms.get(player_controller.player) {
+        Ok(transform) => Ok(WorldPos::new(transform.translation.vector).into()),
+        Err(_) => bail!(tr!("command.no_player_position")),
+    }
+}
+
+fn run_locate(command: &ConsoleCommand, access: &mut ChunkAccess, origin: BlockPos) -> Result<()> {
+    let name = command.arg::<String>(0)?;
+    let radius = command.optional_arg(1)?.unwrap_or(DEFAULT_LOCATE_RADIUS);
+    let id = match access.registry().try_lookup(&name) {
+        Some(id) => id,
+        None => bail!(tr!("command.locate.unknown_block", name)),
+    };
+
+    match find_nearest_block(access, origin, |block| block == id, radius) {
+        Some(pos) => {
+            let [dx, dy, dz] = [pos.x - origin.x, pos.y - origin.y, pos.z - origin.z];
+            let distance = ((dx * dx + dy * dy + dz * dz) as f32).sqrt();
+            let distance = format!("{:.1}", distance);
+            let message = tr!("command.locate.found", name, pos.x, pos.y, pos.z, distance);
+            println!("{}", message);
+        }
+        None => println!("{}", tr!("command.locate.not_found", name, radius)),
+    }
+
+    Ok(())
+}
+
+fn locate_command(
+    player_controller: Res<PlayerController>,
+    transforms: Query<&Transform>,
+    mut access: ResMut<ChunkAccess>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("locate")) {
+        let result = player_block_pos(&player_controller, &transforms)
+            .and_then(|origin| run_locate(command, &mut access, origin));
+        if let Err(err) = result {
+            println!("{}", tr!("command.failed", "locate", err));
+        }
+    }
+}
+
+fn run_setspawn(
+    command: &ConsoleCommand,
+    access: &mut ChunkAccess,
+    spawn: &mut WorldSpawn,
+    player_pos: BlockPos,
+) -> Result<()> {
+    let pos = match command.args.len() {
+        0 => player_pos,
+        3 => BlockPos::new([command.arg(0)?, command.arg(1)?, command.arg(2)?]),
+        _ => bail!(tr!("command.setspawn.bad_position")),
+    };
+
+    if is_safe_spawn(access, pos) == Some(false) {
+        let message = tr!("command.setspawn.unsafe", pos.x, pos.y, pos.z);
+        println!("{}", message);
+    }
+
+    spawn.set(pos);
+    println!("{}", tr!("command.setspawn.set", pos.x, pos.y, pos.z));
+    Ok(())
+}
+
+fn setspawn_command(
+    player_controller: Res<PlayerController>,
+    transforms: Query<&Transform>,
+    mut access: ResMut<ChunkAccess>,
+    mut spawn: ResMut<WorldSpawn>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("setspawn")) {
+        let result = player_block_pos(&player_controller, &transforms)
+            .and_then(|pos| run_setspawn(command, &mut access, &mut spawn, pos));
+        if let Err(err) = result {
+            println!("{}", tr!("command.failed", "setspawn", err));
+        }
+    }
+}
+
+fn run_tp(command: &ConsoleCommand, spawn: &WorldSpawn) -> Result<Vector3<f32>> {
+    Ok(match command.args.len() {
+        1 if command.args[0] == "spawn" => player_spawn_translation(spawn),
+        3 => {
+            let [x, y, z] = [command.arg(0)?, command.arg(1)?, command.arg(2)?];
+            player_translation_at(nalgebra::point![x, y, z])
+        }
+        _ => bail!(tr!("command.tp.bad_target")),
+    })
+}
+
+fn tp_command(
+    player_controller: Res<PlayerController>,
+    spawn: Res<WorldSpawn>,
+    mut commands: EventReader<ConsoleCommand>,
+    mut teleport_events: EventWriter<TeleportEvent>,
+) {
+    for command in commands.iter().filter(|command| command.is("tp")) {
+        match run_tp(command, &spawn) {
+            Ok(target) => teleport_events.send(TeleportEvent {
+                entity: player_controller.player,
+                target: target.into(),
+            }),
+            Err(err) => println!("{}", tr!("command.failed", "tp", err)),
+        }
+    }
+}
+
+fn run_gamemode(command: &ConsoleCommand, game_mode: &mut GameMode) -> Result<()> {
+    match command.optional_arg::<GameMode>(0)? {
+        Some(mode) => {
+            *game_mode = mode;
+            println!("{}", tr!("command.gamemode.set", mode.name()));
+        }
+        None => println!("{}", tr!("command.gamemode.current", game_mode.name())),
+    }
+    Ok(())
+}
+
+fn gamemode_command(
+    mut cmd: Commands,
+    mut player_controller: ResMut<PlayerController>,
+    mut game_modes: Query<&mut GameMode>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("gamemode")) {
+        let player = player_controller.player;
+        let result = match game_modes.get_mut(player) {
+            Ok(mut game_mode) => {
+                run_gamemode(command, &mut game_mode).map(|()| game_mode.allows_flight())
+            }
+            Err(_) => Err(anyhow!(tr!("command.no_player_game_mode"))),
+        };
+        let can_fly = match result {
+            Ok(can_fly) => can_fly,
+            Err(err) => {
+                println!("{}", tr!("command.failed", "gamemode", err));
+                continue;
+            }
+        };
+
+        // players that can't fly anymore fall back down
+        if player_controller.flying && !can_fly {
+            player_controller.flying = false;
+            cmd.entity(player).insert(RigidBody::default());
+        }
+    }
+}
+
+fn weather_command(mut weather: ResMut<Weather>, mut commands: EventReader<ConsoleCommand>) {
+    for command in commands.iter().filter(|command| command.is("weather")) {
+        match command.arg::<WeatherKind>(0) {
+            Ok(kind) => {
+                weather.set_random_duration(kind);
+                println!("{}", tr!("command.weather.set", kind.name()));
+            }
+            Err(err) => println!("{}", tr!("command.failed", "weather", err)),
+        }
+    }
+}
+
+fn antialiasing_command(
+    mut anti_aliasing: ResMut<AntiAliasing>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("antialiasing")) {
+        match command.arg::<AntiAliasing>(0) {
+            Ok(mode) => {
+                *anti_aliasing = mode;
+                println!("{}", tr!("command.antialiasing.set", mode));
+            }
+            Err(err) => println!("{}", tr!("command.failed", "antialiasing", err)),
+        }
+    }
+}
+
+fn run_resolution(command: &ConsoleCommand, settings: &mut WindowSettings) -> Result<()> {
+    let [width, height] = [command.arg(0)?, command.arg(1)?];
+    if width == 0 || height == 0 {
+        bail!(tr!("command.resolution.too_small"));
+    }
+
+    settings.resolution = [width, height];
+    println!("{}", tr!("command.resolution.set", width, height));
+    Ok(())
+}
+
+fn resolution_command(
+    mut settings: ResMut<WindowSettings>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("resolution")) {
+        if let Err(err) = run_resolution(command, &mut settings) {
+            println!("{}", tr!("command.failed", "resolution", err));
+        }
+    }
+}
+
+fn vsync_command(mut settings: ResMut<WindowSettings>, mut commands: EventReader<ConsoleCommand>) {
+    for command in commands.iter().filter(|command| command.is("vsync")) {
+        match command.arg::<String>(0).as_deref() {
+            Ok("on") => settings.vsync = true,
+            Ok("off") => settings.vsync = false,
+            Ok(other) => {
+                let err = tr!("command.vsync.bad_value", other);
+                println!("{}", tr!("command.failed", "vsync", err));
+                continue;
+            }
+            Err(err) => {
+                println!("{}", tr!("command.failed", "vsync", err));
+                continue;
+            }
+        }
+        // the graphics context can't be recreated once the game is running
+        println!("{}", tr!("command.vsync.set", command.args[0]));
+    }
+}
+
+fn uiscale_command(
+    mut settings: ResMut<WindowSettings>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("uiscale")) {
+        match command.arg::<f32>(0) {
+            Ok(scale) if scale.is_finite() && scale > 0.0 => {
+                settings.ui_scale = scale;
+                println!("{}", tr!("command.uiscale.set", scale));
+            }
+            Ok(_) => {
+                let err = tr!("command.uiscale.not_positive");
+                println!("{}", tr!("command.failed", "uiscale", err));
+            }
+            Err(err) => println!("{}", tr!("command.failed", "uiscale", err)),
+        }
+    }
+}
+
+fn run_maxfps(command: &ConsoleCommand, settings: &mut WindowSettings) -> Result<()> {
+    if command.arg::<String>(0)? == "off" {
+        settings.max_fps = None;
+        println!("{}", tr!("command.maxfps.off"));
+        return Ok(());
+    }
+
+    let fps = command.arg::<u32>(0)?;
+    if fps == 0 {
+        bail!(tr!("command.maxfps.too_low"));
+    }
+
+    settings.max_fps = Some(fps);
+    println!("{}", tr!("command.maxfps.set", fps));
+    Ok(())
+}
+
+fn maxfps_command(mut settings: ResMut<WindowSettings>, mut commands: EventReader<ConsoleCommand>) {
+    for command in commands.iter().filter(|command| command.is("maxfps")) {
+        if let Err(err) = run_maxfps(command, &mut settings) {
+            println!("{}", tr!("command.failed", "maxfps", err));
+        }
+    }
+}
+
+fn run_renderscale(
+    command: &ConsoleCommand,
+    settings: &mut WindowSettings,
+    current: &RenderScale,
+) -> Result<()> {
+    let render_scale = &mut settings.render_scale;
+    let setting = match command.optional_arg::<String>(0)? {
+        Some(setting) => setting,
+        None => {
+            let (scale, now) = (render_scale.scale, current.current());
+            let target = render_scale.target_frame_ms;
+            let automatic = if render_scale.automatic { "on" } else { "off" };
+            println!("{}", tr!("command.renderscale.scale", scale, now));
+            println!("{}", tr!("command.renderscale.auto", automatic));
+            println!("{}", tr!("command.renderscale.target", target));
+            return Ok(());
+        }
+    };
+
+    let value = command.arg::<String>(1)?;
+    match setting.as_str() {
+        "scale" => match value.parse::<f32>() {
+            Ok(scale) if (MIN_SCALE..=MAX_SCALE).contains(&scale) => render_scale.scale = scale,
+            _ => bail!(tr!("command.renderscale.bad_scale", MIN_SCALE, MAX_SCALE)),
+        },
+        "auto" => render_scale.automatic = parse_on_off(&value)?,
+        "target" => match value.parse::<f32>() {
+            Ok(millis) if millis.is_finite() && millis > 0.0 => {
+                render_scale.target_frame_ms = millis
+            }
+            _ => bail!(tr!("command.renderscale.bad_target")),
+        },
+        other => bail!(tr!("command.renderscale.bad_setting", other)),
+    }
+    println!("{}", tr!("command.renderscale.set", setting, value));
+    Ok(())
+}
+
+fn renderscale_command(
+    mut settings: ResMut<WindowSettings>,
+    current: Res<RenderScale>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("renderscale")) {
+        if let Err(err) = run_renderscale(command, &mut settings, &current) {
+            println!("{}", tr!("command.failed", "renderscale", err));
+        }
+    }
+}
+
+fn run_language(command: &ConsoleCommand, settings_file: &mut SettingsFile) -> Result<()> {
+    let code = command.arg::<String>(0)?;
+    lang::load_language(Path::new(lang::LANGUAGE_DIR), &code)?;
+    settings_file.update(|settings| settings.language = code.clone())?;
+    println!("{}", tr!("command.language.set", code));
+    Ok(())
+}
+
+fn language_command(
+    mut settings_file: ResMut<SettingsFile>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("language")) {
+        if let Err(err) = run_language(command, &mut settings_file) {
+            println!("{}", tr!("command.failed", "language", err));
+        }
+    }
+}
+
+fn run_texturepack(
+    command: &ConsoleCommand,
+    settings_file: &mut SettingsFile,
+    reload_events: &mut EventWriter<ReloadTextures>,
+) -> Result<()> {
+    let packs = texture_pack::available_packs()?;
+    let name = match command.optional_arg::<String>(0)? {
+        Some(name) => name,
+        None => {
+            let current = settings_file.settings().texture_pack.as_deref();
+            println!("{}", tr!("command.texturepack.current", current.unwrap_or("none")));
+            println!("{}", tr!("command.texturepack.available", packs.join(", ")));
+            return Ok(());
+        }
+    };
+
+    let pack = match name.as_str() {
+        "none" => None,
+        _ if packs.contains(&name) => Some(name),
+        _ => bail!(tr!("command.texturepack.unknown", name)),
+    };
+    settings_file.update(|settings| settings.texture_pack = pack.clone())?;
+    println!("{}", tr!("command.texturepack.loading"));
+    reload_events.send(ReloadTextures { pack });
+    Ok(())
+}
+
+fn texturepack_command(
+    mut settings_file: ResMut<SettingsFile>,
+    mut commands: EventReader<ConsoleCommand>,
+    mut reload_events: EventWriter<ReloadTextures>,
+) {
+    for command in commands.iter().filter(|command| command.is("texturepack")) {
+        if let Err(err) = run_texturepack(command, &mut settings_file, &mut reload_events) {
+            println!("{}", tr!("command.failed", "texturepack", err));
+        }
+    }
+}
+
+fn reloadtextures_command(
+    settings_file: Res<SettingsFile>,
+    mut commands: EventReader<ConsoleCommand>,
+    mut reload_events: EventWriter<ReloadTextures>,
+) {
+    for _ in commands.iter().filter(|command| command.is("reloadtextures")) {
+        let pack = settings_file.settings().texture_pack.clone();
+        println!("{}", tr!("command.texturepack.loading"));
+        reload_events.send(ReloadTextures { pack });
+    }
+}
+
+fn run_packs(
+    command: &ConsoleCommand,
+    settings_file: &mut SettingsFile,
+    content_packs: &ContentPacks,
+) -> Result<()> {
+    let installed = content_pack::available_packs(CONTENT_PACK_DIR)?;
+    let action = match command.optional_arg::<String>(0)? {
+        Some(action) => action,
+        None => {
+            let loaded = content_packs.enabled().join(", ");
+            println!("{}", tr!("command.packs.loaded", loaded));
+            println!("{}", tr!("command.packs.available", installed.join(", ")));
+            for conflict in content_packs.conflicts() {
+                println!("{}", tr!("command.packs.conflict", conflict));
+            }
+            return Ok(());
+        }
+    };
+
+    let name = command.arg::<String>(1)?;
+    let enabled = &settings_file.settings().content_packs;
+    let enable = match action.as_str() {
+        "enable" if enabled.contains(&name) => bail!(tr!("command.packs.already_enabled", name)),
+        "enable" if !installed.contains(&name) => bail!(tr!("command.packs.unknown", name)),
+        "enable" => true,
+        "disable" if !enabled.contains(&name) => bail!(tr!("command.packs.not_enabled", name)),
+        "disable" => false,
+        other => bail!(tr!("command.packs.bad_action", other)),
+    };
+
+    settings_file.update(|settings| match enable {
+        true => settings.content_packs.push(name.clone()),
+        false => settings.content_packs.retain(|pack| *pack != name),
+    })?;
+    match enable {
+        true => println!("{}", tr!("command.packs.enabled", name)),
+        false => println!("{}", tr!("command.packs.disabled", name)),
+    }
+    Ok(())
+}
+
+fn packs_command(
+    mut settings_file: ResMut<SettingsFile>,
+    content_packs: Res<ContentPacks>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("packs")) {
+        if let Err(err) = run_packs(command, &mut settings_file, &content_packs) {
+            println!("{}", tr!("command.failed", "packs", err));
+        }
+    }
+}
+
+fn run_compass(command: &ConsoleCommand, settings_file: &mut SettingsFile) -> Result<()> {
+    let shown = match command.arg::<String>(0)?.as_str() {
+        "on" => true,
+        "off" => false,
+        other => bail!(tr!("command.compass.bad_value", other)),
+    };
+    settings_file.update(|settings| settings.compass = shown)?;
+    println!("{}", tr!("command.compass.set", command.args[0]));
+    Ok(())
+}
+
+fn compass_command(
+    mut settings_file: ResMut<SettingsFile>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("compass")) {
+        if let Err(err) = run_compass(command, &mut settings_file) {
+            println!("{}", tr!("command.failed", "compass", err));
+        }
+    }
+}
+
+fn run_tooltip(command: &ConsoleCommand, settings_file: &mut SettingsFile) -> Result<()> {
+    let shown = match command.arg::<String>(0)?.as_str() {
+        "on" => true,
+        "off" => false,
+        other => bail!(tr!("command.tooltip.bad_value", other)),
+    };
+    settings_file.update(|settings| settings.block_tooltip = shown)?;
+    println!("{}", tr!("command.tooltip.set", command.args[0]));
+    Ok(())
+}
+
+fn tooltip_command(
+    mut settings_file: ResMut<SettingsFile>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("tooltip")) {
+        if let Err(err) = run_tooltip(command, &mut settings_file) {
+            println!("{}", tr!("command.failed", "tooltip", err));
+        }
+    }
+}
+
+fn run_fov(command: &ConsoleCommand, settings_file: &mut SettingsFile) -> Result<()> {
+    let fov = match command.optional_arg::<f32>(0)? {
+        Some(fov) => fov,
+        None => {
+            let fov = settings_file.settings().camera.fov;
+            println!("{}", tr!("command.fov.show", fov));
+            return Ok(());
+        }
+    };
+    if !(MIN_FOV..=MAX_FOV).contains(&fov) {
+        bail!(tr!("command.fov.out_of_range", MIN_FOV, MAX_FOV));
+    }
+    settings_file.update(|settings| settings.camera.fov = fov)?;
+    println!("{}", tr!("command.fov.set", fov));
+    Ok(())
+}
+
+fn fov_command(mut settings_file: ResMut<SettingsFile>, mut commands: EventReader<ConsoleCommand>) {
+    for command in commands.iter().filter(|command| command.is("fov")) {
+        if let Err(err) = run_fov(command, &mut settings_file) {
+            println!("{}", tr!("command.failed", "fov", err));
+        }
+    }
+}
+
+fn run_volume(command: &ConsoleCommand, settings: &mut AudioSettings) -> Result<()> {
+    let show = |settings: &AudioSettings, bus: AudioBus| {
+        let percent = (100.0 * settings.volume(bus)).round();
+        println!("{}", tr!("command.volume.show", bus.name(), percent));
+    };
+
+    let bus = match command.optional_arg::<String>(0)? {
+        Some(name) => match AudioBus::from_name(&name) {
+            Some(bus) => bus,
+            None => {
+                let names = AudioBus::ALL.map(AudioBus::name).join(", ");
+                bail!(tr!("command.volume.bad_bus", name, names));
+            }
+        },
+        None => {
+            AudioBus::ALL.into_iter().for_each(|bus| show(settings, bus));
+            return Ok(());
+        }
+    };
+
+    let percent = match command.optional_arg::<f32>(1)? {
+        Some(percent) => percent,
+        None => {
+            show(settings, bus);
+            return Ok(());
+        }
+    };
+    if !(0.0..=100.0).contains(&percent) {
+        bail!(tr!("command.volume.bad_volume"));
+    }
+
+    *settings.volume_mut(bus) = percent / 100.0;
+    println!("{}", tr!("command.volume.set", bus.name(), percent));
+    Ok(())
+}
+
+fn volume_command(mut settings: ResMut<AudioSettings>, mut commands: EventReader<ConsoleCommand>) {
+    for command in commands.iter().filter(|command| command.is("volume")) {
+        if let Err(err) = run_volume(command, &mut settings) {
+            println!("{}", tr!("command.failed", "volume", err));
+        }
+    }
+}
+
+fn stats_command(stats: Res<WorldStats>, mut commands: EventReader<ConsoleCommand>) {
+    for _ in commands.iter().filter(|command| command.is("stats")) {
+        let played = format_duration(stats.time_played);
+        println!("{}", tr!("command.stats.time_played", played));
+        let distance = stats.distance_traveled.round();
+        println!("{}", tr!("command.stats.distance", distance));
+        println!("{}", tr!("command.stats.deaths", stats.deaths));
+        let (broken, placed) = (stats.total_broken(), stats.total_placed());
+        println!("{}", tr!("command.stats.blocks", broken, placed));
+
+        let mut names = stats.blocks_broken.keys().collect::<Vec<_>>();
+        names.extend(stats.blocks_placed.keys());
+        names.sort();
+        names.dedup();
+        for name in names {
+            let broken = stats.blocks_broken.get(name).copied().unwrap_or(0);
+            let placed = stats.blocks_placed.get(name).copied().unwrap_or(0);
+            println!("{}", tr!("command.stats.block", name, broken, placed));
+        }
+    }
+}
+
+fn log_command(mut commands: EventReader<ConsoleCommand>) {
+    for command in commands.iter().filter(|command| command.is("log")) {
+        match command.optional_arg(0) {
+            Ok(count) => {
+                let entries = logging::recent_entries(count.unwrap_or(DEFAULT_LOG_COUNT));
+                if entries.is_empty() {
+                    println!("{}", tr!("command.log.empty"));
+                }
+                for entry in entries {
+                    println!("{}", entry);
+                }
+            }
+            Err(err) => println!("{}", tr!("command.failed", "log", err)),
+        }
+    }
+}
+
+fn run_sign(
+    command: &ConsoleCommand,
+    camera: &CurrentCamera,
+    targeting: &TargetingConfig,
+    access: &mut ChunkAccess,
+) -> Result<()> {
+    let sign = SignData::new(&command.args.join(" "))?;
+    let transform = match camera.transform() {
+        Some(transform) => transform,
+        None => bail!(tr!("command.no_player_position")),
+    };
+
+    let ray = make_ray(transform, &-Vector3::z());
+    let pos = match targeting.target(access, ray) {
+        Some(hit) => hit.pos,
+        None => bail!(tr!("command.sign.no_sign")),
+    };
+    if !matches!(access.block_entity(pos), Some(BlockEntity::Sign(_))) {
+        bail!(tr!("command.sign.no_sign"));
+    }
+
+    access.set_block_entity(pos, BlockEntity::Sign(sign));
+    println!("{}", tr!("command.sign.set", pos.x, pos.y, pos.z));
+    Ok(())
+}
+
+fn sign_command(
+    camera: CurrentCamera,
+    targeting: Res<TargetingConfig>,
+    mut access: ResMut<ChunkAccess>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("sign")) {
+        if let Err(err) = run_sign(command, &camera, &targeting, &mut access) {
+            println!("{}", tr!("command.failed", "sign", err));
+        }
+    }
+}
+
+fn parse_on_off(value: &str) -> Result<bool> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        other => bail!(tr!("command.targeting.bad_value", other)),
+    }
+}
+
+fn run_targeting(command: &ConsoleCommand, targeting: &mut TargetingConfig) -> Result<()> {
+    let setting = match command.optional_arg::<String>(0)? {
+        Some(setting) => setting,
+        None => {
+            let on_off = |on| if on { "on" } else { "off" };
+            println!("{}", tr!("command.targeting.reach", targeting.reach));
+            let fluids = on_off(targeting.fluids);
+            println!("{}", tr!("command.targeting.fluids", fluids));
+            let through = on_off(targeting.through_detail_blocks);
+            println!("{}", tr!("command.targeting.detail", through));
+            return Ok(());
+        }
+    };
+
+    let value = command.arg::<String>(1)?;
+    match setting.as_str() {
+        "reach" => match value.parse::<f32>() {
+            Ok(reach) if reach > 0.0 => targeting.reach = reach,
+            _ => bail!(tr!("command.targeting.bad_reach", value)),
+        },
+        "fluids" => targeting.fluids = parse_on_off(&value)?,
+        "detail" => targeting.through_detail_blocks = parse_on_off(&value)?,
+        other => bail!(tr!("command.targeting.bad_setting", other)),
+    }
+    println!("{}", tr!("command.targeting.set", setting, value));
+    Ok(())
+}
+
+fn targeting_command(
+    mut targeting: ResMut<TargetingConfig>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("targeting")) {
+        if let Err(err) = run_targeting(command, &mut targeting) {
+            println!("{}", tr!("command.failed", "targeting", err));
+        }
+    }
+}
+
+fn run_explode(
+    command: &ConsoleCommand,
+    camera: &CurrentCamera,
+    access: &mut ChunkAccess,
+) -> Result<ExplosionEvent> {
+    let radius = command.optional_arg(0)?.unwrap_or(DEFAULT_EXPLOSION_RADIUS);
+    let power = command.optional_arg(1)?.unwrap_or(DEFAULT_EXPLOSION_POWER);
+    if radius.is_nan() || radius <= 0.0 || radius > MAX_EXPLOSION_RADIUS {
+        bail!(tr!("command.explode.bad_radius", MAX_EXPLOSION_RADIUS));
+    }
+    if power.is_nan() || power <= 0.0 {
+        bail!(tr!("command.explode.bad_power"));
+    }
+
+    let transform = match camera.transform() {
+        Some(transform) => transform,
+        None => bail!(tr!("command.no_player_position")),
+    };
+    let ray = make_ray(transform, &-Vector3::z());
+    let pos = match trace_ray(access, ray, EXPLODE_RANGE) {
+        Some(hit) => hit.pos,
+        None => bail!(tr!("command.explode.no_target")),
+    };
+
+    Ok(ExplosionEvent {
+        center: Point3::from(pos.origin()) + vector![0.5, 0.5, 0.5],
+        radius,
+        power,
+    })
+}
+
+fn explode_command(
+    camera: CurrentCamera,
+    mut access: ResMut<ChunkAccess>,
+    mut commands: EventReader<ConsoleCommand>,
+    mut explosion_events: EventWriter<ExplosionEvent>,
+) {
+    for command in commands.iter().filter(|command| command.is("explode")) {
+        match run_explode(command, &camera, &mut access) {
+            Ok(explosion) => explosion_events.send(explosion),
+            Err(err) => println!("{}", tr!("command.failed", "explode", err)),
+        }
+    }
+}
+
+fn run_emitter(
+    command: &ConsoleCommand,
+    camera: &CurrentCamera,
+    access: &mut ChunkAccess,
+    pools: &RandomizedAudioPools,
+) -> Result<(Point3<f32>, AudioEmitter)> {
+    let name = command.arg::<String>(0)?;
+    let id = match pools.id(&name) {
+        Some(id) => id,
+        None => bail!(tr!("command.emitter.unknown_sound", name)),
+    };
+    let range = command.optional_arg(1)?.unwrap_or(DEFAULT_EMITTER_RANGE);
+    if range.is_nan() || range <= 0.0 {
+        bail!(tr!("command.emitter.bad_range"));
+    }
+
+    let transform = match camera.transform() {
+        Some(transform) => transform,
+        None => bail!(tr!("command.no_player_position")),
+    };
+    let ray = make_ray(transform, &-Vector3::z());
+    let pos = match trace_ray(access, ray, EXPLODE_RANGE) {
+        Some(hit) => hit.pos,
+        None => bail!(tr!("command.emitter.no_target")),
+    };
+
+    // layered sounds only loop their first layer
+    let mut source = None;
+    pools.select(&mut rand::thread_rng(), id, |sample, params| {
+        let sample = ParameterizedSource::from_sample(sample).with_parameters(params);
+        source.get_or_insert(sample.with_bus(AudioBus::Ambience));
+    });
+    let source = match source {
+        Some(source) => source,
+        None => bail!(tr!("command.emitter.unknown_sound", name)),
+    };
+
+    let placed = tr!("command.emitter.placed", name, pos.x, pos.y, pos.z);
+    println!("{}", placed);
+    let center = Point3::from(pos.origin()) + vector![0.5, 0.5, 0.5];
+    Ok((center, AudioEmitter::new(source, range)))
+}
+
+fn emitter_command(
+    mut cmd: Commands,
+    camera: CurrentCamera,
+    mut access: ResMut<ChunkAccess>,
+    pools: Res<RandomizedAudioPools>,
+    emitters: Query<Entity, With<CommandEmitter>>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("emitter")) {
+        if command.args.first().map(String::as_str) == Some("clear") {
+            emitters.for_each(|entity| cmd.entity(entity).despawn());
+            let count = emitters.iter().count();
+            println!("{}", tr!("command.emitter.cleared", count));
+            continue;
+        }
+
+        match run_emitter(command, &camera, &mut access, &pools) {
+            Ok((pos, emitter)) => drop(
+                cmd.spawn()
+                    .insert(Transform::to(pos))
+                    .insert(emitter)
+                    .insert(CommandEmitter),
+            ),
+            Err(err) => println!("{}", tr!("command.failed", "emitter", err)),
+        }
+    }
+}
+
+fn killitems_command(
+    mut cmd: Commands,
+    drops: Query<Entity, With<DroppedItem>>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for _ in commands.iter().filter(|command| command.is("killitems")) {
+        drops.for_each(|entity| cmd.entity(entity).despawn());
+        let count = drops.iter().count();
+        println!("{}", tr!("command.killitems.removed", count));
+    }
+}
+
+fn run_relight(
+    command: &ConsoleCommand,
+    camera: &CurrentCamera,
+    world: &VoxelWorld,
+    queue: &mut RelightQueue,
+) -> Result<()> {
+    let radius = command.optional_arg(0)?.unwrap_or(DEFAULT_RELIGHT_RADIUS);
+    if !(0..=MAX_RELIGHT_RADIUS).contains(&radius) {
+        bail!(tr!("command.relight.bad_radius", MAX_RELIGHT_RADIUS));
+    }
+    let center = match camera.transform() {
+        Some(transform) => BlockPos::from(WorldPos::new(transform.translation.vector)),
+        None => bail!(tr!("command.no_player_position")),
+    };
+
+    let queued = queue.queue_around(world, center, radius);
+    let waiting = queue.remaining();
+    println!("{}", tr!("command.relight.queued", queued, waiting));
+    Ok(())
+}
+
+fn relight_command(
+    camera: CurrentCamera,
+    world: Res<Arc<VoxelWorld>>,
+    mut queue: ResMut<RelightQueue>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("relight")) {
+        if let Err(err) = run_relight(command, &camera, &world, &mut queue) {
+            println!("{}", tr!("command.failed", "relight", err));
+        }
+    }
+}
+
+fn run_pregen(
+    command: &ConsoleCommand,
+    camera: &CurrentCamera,
+    queue: &mut PregenQueue,
+) -> Result<()> {
+    if command.arg::<String>(0)? == "cancel" {
+        println!("{}", tr!("command.pregen.cancelled", queue.cancel()));
+        return Ok(());
+    }
+
+    let radius = command.arg::<i32>(0)?;
+    if !(0..=MAX_PREGEN_RADIUS).contains(&radius) {
+        bail!(tr!("command.pregen.bad_radius", MAX_PREGEN_RADIUS));
+    }
+    let center = match camera.transform() {
+        Some(transform) => BlockPos::from(WorldPos::new(transform.translation.vector)),
+        None => bail!(tr!("command.no_player_position")),
+    };
+
+    let queued = queue.queue_around(ChunkSectionPos::from(center).column(), radius);
+    let waiting = queue.remaining();
+    println!("{}", tr!("command.pregen.queued", queued, waiting));
+    Ok(())
+}
+
+fn pregen_command(
+    camera: CurrentCamera,
+    mut queue: ResMut<PregenQueue>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter().filter(|command| command.is("pregen")) {
+        if let Err(err) = run_pregen(command, &camera, &mut queue) {
+            println!("{}", tr!("command.failed", "pregen", err));
+        }
+    }
+}
+
+fn run_freecam(command: &ConsoleCommand, free_camera: &mut FreeCamera) -> Result<()> {
+    let setting = match command.optional_arg::<String>(0)? {
+        Some(setting) => setting,
+        None => {
+            println!("{}", tr!("command.freecam.speed", free_camera.speed));
+            let collision = if free_camera.collision { "on" } else { "off" };
+            println!("{}", tr!("command.freecam.collision", collision));
+            return Ok(());
+        }
+    };
+
+    let value = command.arg::<String>(1)?;
+    match setting.as_str() {
+        "speed" => match value.parse::<f32>() {
+            Ok(speed) if (MIN_SPEED..=MAX_SPEED).contains(&speed) => free_camera.set_speed(speed),
+            _ => bail!(tr!("command.freecam.bad_speed", MIN_SPEED, MAX_SPEED)),
+        },
+        "collision" => free_camera.collision = parse_on_off(&value)?,
+        other => bail!(tr!("command.freecam.bad_setting", other)),
+    }
+    println!("{}", tr!("command.freecam.set", setting, value));
+    Ok(())
+}
+
+fn freecam_command(mut free_camera: ResMut<FreeCamera>, mut commands: EventReader<ConsoleCommand>) {
+    for command in commands.iter().filter(|command| command.is("freecam")) {
+        if let Err(err) = run_freecam(command, &mut free_camera) {
+            println!("{}", tr!("command.failed", "freecam", err));
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CommandsPlugin {}
+
+impl Plugin for CommandsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        add_console_command(app, "locate", "/locate <block> [radius]");
+        add_console_command(app, "setspawn", "/setspawn [x y z]");
+        add_console_command(app, "tp", "/tp <x y z | spawn>");
+        add_console_command(app, "weather", "/weather <clear | rain | snow>");
+        add_console_command(app, "gamemode", "/gamemode [creative | survival]");
+        add_console_command(
+            app,
+            "antialiasing",
+            "/antialiasing <off | fxaa | msaa2 | msaa4 | msaa8>",
+        );
+        app.add_system(locate_command.system().after(ConsoleLabel));
+        app.add_system(setspawn_command.system().after(ConsoleLabel));
+        app.add_system(tp_command.system().after(ConsoleLabel));
+        app.add_system(weather_command.system().after(ConsoleLabel));
+        app.add_system(gamemode_command.system().after(ConsoleLabel));
+        add_console_command(app, "resolution", "/resolution <width> <height>");
+        add_console_command(app, "vsync", "/vsync <on | off>");
+        add_console_command(app, "uiscale", "/uiscale <scale>");
+        add_console_command(app, "maxfps", "/maxfps <fps | off>");
+        app.add_system(antialiasing_command.system().after(ConsoleLabel));
+        app.add_system(resolution_command.system().after(ConsoleLabel));
+        app.add_system(vsync_command.system().after(ConsoleLabel));
+        app.add_system(uiscale_command.system().after(ConsoleLabel));
+        app.add_system(maxfps_command.system().after(ConsoleLabel));
+        add_console_command(
+            app,
+            "renderscale",
+            "/renderscale [scale <scale> | auto <on | off> | target <milliseconds>]",
+        );
+        app.add_system(renderscale_command.system().after(ConsoleLabel));
+        add_console_command(app, "volume", "/volume [bus] [percent]");
+        app.add_system(volume_command.system().after(ConsoleLabel));
+        add_console_command(app, "language", "/language <code>");
+        app.add_system(language_command.system().after(ConsoleLabel));
+        add_console_command(app, "texturepack", "/texturepack [name | none]");
+        app.add_system(texturepack_command.system().after(ConsoleLabel));
+        add_console_command(app, "reloadtextures", "/reloadtextures");
+        app.add_system(reloadtextures_command.system().after(ConsoleLabel));
+        add_console_command(app, "packs", "/packs [enable | disable <name>]");
+        app.add_system(packs_command.system().after(ConsoleLabel));
+        add_console_command(app, "compass", "/compass <on | off>");
+        app.add_system(compass_command.system().after(ConsoleLabel));
+        add_console_command(app, "tooltip", "/tooltip <on | off>");
+        app.add_system(tooltip_command.system().after(ConsoleLabel));
+        add_console_command(app, "fov", "/fov [degrees]");
+        app.add_system(fov_command.system().after(ConsoleLabel));
+        add_console_command(app, "stats", "/stats");
+        app.add_system(stats_command.system().after(ConsoleLabel));
+        add_console_command(app, "log", "/log [count]");
+        app.add_system(log_command.system().after(ConsoleLabel));
+        add_console_command(app, "sign", "/sign [text]");
+        app.add_system(sign_command.system().after(ConsoleLabel));
+        add_console_command(app, "explode", "/explode [radius] [power]");
+        app.add_system(explode_command.system().after(ConsoleLabel));
+        add_console_command(app, "emitter", "/emitter <sound [range] | clear>");
+        app.add_system(emitter_command.system().after(ConsoleLabel));
+        add_console_command(app, "killitems", "/killitems");
+        app.add_system(killitems_command.system().after(ConsoleLabel));
+        add_console_command(app, "relight", "/relight [radius]");
+        app.add_system(relight_command.system().after(ConsoleLabel));
+        add_console_command(app, "pregen", "/pregen <radius | cancel>");
+        app.add_system(pregen_command.system().after(ConsoleLabel));
+        add_console_command(
+            app,
+            "targeting",
+            "/targeting [reach <blocks> | fluids <on | off> | detail <on | off>]",
+        );
+        app.add_system(targeting_command.system().after(ConsoleLabel));
+        add_console_command(
+            app,
+            "freecam",
+            "/freecam [speed <blocks per second> | collision <on | off>]",
+        );
+        app.add_system(freecam_command.system().after(ConsoleLabel));
+    }
+}
@@ -0,0 +1,154 @@
+//! a small compass and the camera's block coordinates, shown at the top of the
+//! screen for finding your way around without opening the debug overlay.
+
+use crate::client::{
+    camera::CurrentCamera,
+    render::{font, renderer::ImmediateUi},
+    settings::SettingsFile,
+    window::UiScale,
+};
+use glium::Display;
+use nalgebra::Vector3;
+use notcraft_common::{
+    game_state::playing,
+    prelude::*,
+    world::{BlockPos, WorldPos},
+};
+use std::rc::Rc;
+
+const MARGIN: f32 = 4.0;
+const PADDING: f32 = 3.0;
+/// how wide the strip of directions is.
+const TAPE_WIDTH: f32 = 120.0;
+/// how many degrees to either side of the facing direction the strip shows.
+const TAPE_SPAN: f32 = 90.0;
+const LINE_HEIGHT: f32 = font::GLYPH_HEIGHT + 2.0;
+
+const DIRECTIONS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+
+/// which way `forward` points, in degrees clockwise from north. north is -Z,
+/// and east is +X.
+pub fn heading(forward: Vector3<f32>) -> f32 {
+    f32::atan2(forward.x, -forward.z)
+        .to_degrees()
+        .rem_euclid(360.0)
+}
+
+/// the closest of the eight compass directions to a heading.
+pub fn direction_name(heading: f32) -> &'static str {
+    let idx = ((heading + 22.5) / 45.0).floor() as usize;
+    DIRECTIONS[idx % DIRECTIONS.len()]
+}
+
+fn draw_compass(
+    camera: CurrentCamera,
+    settings_file: Res<SettingsFile>,
+    display: NonSend<Rc<Display>>,
+    ui_scale: Res<UiScale>,
+    mut ui: ResMut<ImmediateUi>,
+) {
+    if !settings_file.settings().compass {
+        return;
+    }
+    let transform = match camera.transform() {
+        Some(transform) => transform,
+        None => return,
+    };
+    let heading = heading(transform.rotation.to_quaternion() * -Vector3::z());
+    let pos = BlockPos::from(WorldPos::new(transform.pos()));
+
+    let (_, height) = display.get_framebuffer_dimensions();
+    let top = height as f32 / ui_scale.0 / 2.0 - MARGIN;
+    let half_width = TAPE_WIDTH / 2.0;
+    let bottom = top - 3.0 * LINE_HEIGHT - 2.0 * PADDING;
+    let background = [0.05, 0.05, 0.05, 0.6];
+    ui.rect([-half_width, bottom], [half_width, top], background);
+
+    // the strip of directions, which slides past as the camera turns
+    let tape_y = top - PADDING - font::GLYPH_HEIGHT;
+    for step in 0..24 {
+        let angle = step as f32 * 15.0;
+        let offset = (angle - heading + 180.0).rem_euclid(360.0) - 180.0;
+        if offset.abs() > TAPE_SPAN {
+            continue;
+        }
+
+        let x = offset / TAPE_SPAN * (half_width - PADDING - 4.0);
+        match step % 3 {
+            0 => {
+                let name = DIRECTIONS[step / 3];
+                let color = match step % 6 {
+                    0 => [1.0, 1.0, 1.0, 1.0],
+                    _ => [0.6, 0.6, 0.6, 1.0],
+                };
+                let text_x = (x - font::text_width(name) / 2.0).round();
+                ui.text([text_x, tape_y], name, color);
+            }
+            _ => {
+                let tick_y = tape_y + font::GLYPH_HEIGHT / 2.0;
+                let tick = [0.4, 0.4, 0.4, 1.0];
+                ui.rect(
+                    [x.round(), tick_y - 1.0],
+                    [x.round() + 1.0, tick_y + 1.0],
+                    tick,
+                );
+            }
+        }
+    }
+    let marker_y = tape_y - 2.0;
+    ui.rect(
+        [-1.0, marker_y - 1.0],
+        [1.0, marker_y],
+        [1.0, 0.3, 0.3, 1.0],
+    );
+
+    let lines = [
+        format!(
+            "{} {}",
+            direction_name(heading),
+            heading.round() as i32 % 360
+        ),
+        format!("{} {} {}", pos.x, pos.y, pos.z),
+    ];
+    for (idx, line) in lines.iter().enumerate() {
+        let y = tape_y - (idx + 1) as f32 * LINE_HEIGHT - 1.0;
+        let x = (-font::text_width(line) / 2.0).round();
+        ui.text([x, y], line, [1.0, 1.0, 1.0, 1.0]);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CompassPlugin {}
+
+impl Plugin for CompassPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(draw_compass.system().with_run_criteria(playing.system()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::vector;
+
+    #[test]
+    fn heading_goes_clockwise_from_north() {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-3;
+        assert!(close(heading(vector![0.0, 0.0, -1.0]), 0.0));
+        assert!(close(heading(vector![1.0, 0.0, 0.0]), 90.0));
+        assert!(close(heading(vector![0.0, 0.0, 1.0]), 180.0));
+        assert!(close(heading(vector![-1.0, 0.0, 0.0]), 270.0));
+        // looking up or down doesn't change which way is forward
+        assert!(close(heading(vector![1.0, 5.0, -1.0]), 45.0));
+    }
+
+    #[test]
+    fn headings_round_to_the_nearest_direction() {
+        assert_eq!(direction_name(0.0), "N");
+        assert_eq!(direction_name(22.0), "N");
+        assert_eq!(direction_name(23.0), "NE");
+        assert_eq!(direction_name(180.0), "S");
+        assert_eq!(direction_name(300.0), "NW");
+        assert_eq!(direction_name(359.0), "N");
+    }
+}
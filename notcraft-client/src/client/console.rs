@@ -0,0 +1,61 @@
+//! the in-game console, so that commands don't have to be typed into the
+//! terminal that the game was started from.
+//!
+//! `/` opens a text field at the bottom of the screen with a `/` already typed
+//! in, and `Enter` runs what was typed as a [`ConsoleLine`]. what commands
+//! print still goes to the terminal.
+
+use crate::client::{
+    input::InputState,
+    render::renderer::ImmediateUi,
+    text_input::{TextField, TextInput, TextInputEvent},
+    window::UiScale,
+};
+use glium::{glutin::event::VirtualKeyCode, Display};
+use notcraft_common::{console::ConsoleLine, game_state::playing, prelude::*};
+use std::rc::Rc;
+
+const OWNER: &str = "console";
+const MARGIN: f32 = 4.0;
+
+fn open_console(input: Res<InputState>, mut text_input: ResMut<TextInput>) {
+    if !text_input.is_any_focused() && input.key(VirtualKeyCode::Slash).is_rising() {
+        text_input.focus(OWNER, TextField::new("/"));
+    }
+}
+
+fn send_console_lines(
+    mut events: EventReader<TextInputEvent>,
+    mut lines: EventWriter<ConsoleLine>,
+) {
+    for event in events.iter() {
+        if let TextInputEvent::Submitted { owner: OWNER, text } = event {
+            if !text.trim().is_empty() {
+                lines.send(ConsoleLine(text.clone()));
+            }
+        }
+    }
+}
+
+fn draw_console(
+    display: NonSend<Rc<Display>>,
+    ui_scale: Res<UiScale>,
+    mut text_input: ResMut<TextInput>,
+    mut ui: ResMut<ImmediateUi>,
+) {
+    let (width, height) = display.get_framebuffer_dimensions();
+    let [half_width, half_height] = [width, height].map(|len| len as f32 / ui_scale.0 / 2.0);
+    let pos = [-half_width + MARGIN, -half_height + MARGIN];
+    text_input.draw(&mut ui, OWNER, pos, 2.0 * (half_width - MARGIN));
+}
+
+#[derive(Debug, Default)]
+pub struct ConsoleUiPlugin {}
+
+impl Plugin for ConsoleUiPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(open_console.system().with_run_criteria(playing.system()));
+        app.add_system(send_console_lines.system());
+        app.add_system(draw_console.system());
+    }
+}
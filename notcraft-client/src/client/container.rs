@@ -0,0 +1,269 @@
+//! the UI for moving items in and out of containers, like chests.
+//!
+//! containers are opened by right-clicking them, and show the player's
+//! inventory next to the container's. the arrow keys pick an item, and `Enter`
+//! moves one of it to the other side, or the whole stack while holding shift.
+
+use crate::{
+    client::{
+        crafting::{item_icon, CraftingUi, CraftingUiLabel},
+        input::{keys, InputState},
+        render::{font, renderer::ImmediateUi},
+    },
+    PlayerInventory, TerrainManipulation, TerrainManipulator,
+};
+use glium::glutin::event::VirtualKeyCode;
+use nalgebra::{point, vector};
+use notcraft_common::{
+    game_state::playing,
+    inventory::Inventory,
+    lang::tr,
+    prelude::*,
+    targeting::TargetingConfig,
+    transform::Transform,
+    world::{block_entity::BlockEntity, chunk::ChunkAccess, registry::BlockRegistry, BlockPos},
+};
+use std::sync::Arc;
+
+const ROW_HEIGHT: f32 = 20.0;
+const ICON_SIZE: f32 = 16.0;
+const PANEL_WIDTH: f32 = 140.0;
+const PANEL_GAP: f32 = 8.0;
+const PADDING: f32 = 2.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum ContainerColumn {
+    Player,
+    #[default]
+    Container,
+}
+
+impl ContainerColumn {
+    fn other(self) -> Self {
+        match self {
+            Self::Player => Self::Container,
+            Self::Container => Self::Player,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct ContainerUi {
+    /// the container that's open, if any.
+    pub open: Option<BlockPos>,
+    pub column: ContainerColumn,
+    pub selected: usize,
+}
+
+impl ContainerUi {
+    /// opens the container at `pos`, or closes it if it was already open.
+    pub fn toggle(&mut self, pos: BlockPos) {
+        *self = match self.open {
+            Some(open) if open == pos => Self::default(),
+            _ => Self {
+                open: Some(pos),
+                ..Default::default()
+            },
+        };
+    }
+}
+
+/// moves one of the `selected`th item in `from` over to `to`, or all of them.
+fn move_items(from: &mut Inventory, to: &mut Inventory, selected: usize, all: bool) {
+    let (item, count) = match from.iter().nth(selected) {
+        Some((item, count)) => (item.to_owned(), count),
+        None => return,
+    };
+    let count = if all { count } else { 1 };
+    if from.remove(&item, count) {
+        to.add(&item, count);
+    }
+}
+
+fn container_ui_input(
+    input: Res<InputState>,
+    targeting: Res<TargetingConfig>,
+    mut ui: ResMut<ContainerUi>,
+    mut crafting_ui: ResMut<CraftingUi>,
+    mut access: ResMut<ChunkAccess>,
+    mut player_inventory: PlayerInventory,
+    manipulators: Query<(&Transform, &mut TerrainManipulator)>,
+) {
+    let mut eye = None;
+    manipulators.for_each_mut(|(transform, mut manip)| {
+        if let Some(pos) = manip.clicked_container.take() {
+            ui.toggle(pos);
+        }
+        eye = Some(transform.pos());
+    });
+
+    let (pos, eye) = match (ui.open, eye) {
+        (Some(pos), Some(eye)) => (pos, eye),
+        _ => return,
+    };
+
+    // only one of the two UIs can be open at a time, and whichever was opened
+    // last wins.
+    if input.key(VirtualKeyCode::Tab).is_rising() {
+        *ui = ContainerUi::default();
+        return;
+    }
+    crafting_ui.open = false;
+
+    // containers close when they're broken or the player walks away from them
+    let center = point![pos.x as f32, pos.y as f32, pos.z as f32] + vector![0.5, 0.5, 0.5];
+    let in_reach = (center - eye).norm() <= targeting.reach;
+    let mut contents = match access.block_entity(pos) {
+        Some(BlockEntity::Chest(items)) if in_reach => items.clone(),
+        _ => {
+            *ui = ContainerUi::default();
+            return;
+        }
+    };
+    let mut inventory = match player_inventory.get() {
+        Some(inventory) => inventory,
+        None => return,
+    };
+
+    if input.key(keys::ARROW_LEFT).is_rising() || input.key(keys::ARROW_RIGHT).is_rising() {
+        ui.column = ui.column.other();
+    }
+
+    let len = match ui.column {
+        ContainerColumn::Player => inventory.iter().count(),
+        ContainerColumn::Container => contents.iter().count(),
+    };
+    if len == 0 {
+        ui.selected = 0;
+        return;
+    }
+    if input.key(keys::ARROW_UP).is_rising() {
+        ui.selected = (ui.selected + len - 1) % len;
+    }
+    if input.key(keys::ARROW_DOWN).is_rising() {
+        ui.selected = (ui.selected + 1) % len;
+    }
+    ui.selected = ui.selected.min(len - 1);
+
+    if input.key(VirtualKeyCode::Return).is_rising() {
+        match ui.column {
+            ContainerColumn::Player => {
+                move_items(&mut inventory, &mut contents, ui.selected, input.shift())
+            }
+            ContainerColumn::Container => {
+                move_items(&mut contents, &mut inventory, ui.selected, input.shift())
+            }
+        }
+        access.set_block_entity(pos, BlockEntity::Chest(contents));
+    }
+}
+
+fn draw_column(
+    ui: &mut ImmediateUi,
+    registry: &BlockRegistry,
+    title: &str,
+    items: &Inventory,
+    [left, top]: [f32; 2],
+    rows: usize,
+    selected: Option<usize>,
+) {
+    let right = left + PANEL_WIDTH;
+    let bottom = top - (rows + 1) as f32 * ROW_HEIGHT - PADDING;
+    ui.rect([left, bottom], [right, top], [0.05, 0.05, 0.05, 0.8]);
+    ui.text(
+        [
+            left + 2.0 * PADDING,
+            top - ROW_HEIGHT / 2.0 - font::GLYPH_HEIGHT / 2.0,
+        ],
+        title,
+        [1.0; 4],
+    );
+
+    for (idx, (item, count)) in items.iter().enumerate() {
+        let row_top = top - (idx + 1) as f32 * ROW_HEIGHT;
+        let row_bottom = row_top - ROW_HEIGHT + PADDING;
+        let color = match selected == Some(idx) {
+            true => [0.3, 0.3, 0.5, 0.9],
+            false => [0.2, 0.2, 0.2, 0.9],
+        };
+        ui.rect(
+            [left + PADDING, row_bottom],
+            [right - PADDING, row_top],
+            color,
+        );
+
+        let icon = [left + 2.0 * PADDING, row_bottom + 1.0];
+        let icon_max = [icon[0] + ICON_SIZE, icon[1] + ICON_SIZE];
+        match item_icon(registry, item) {
+            Some(texture) => ui.textured_rect(icon, icon_max, texture, [1.0; 4]),
+            None => ui.rect(icon, icon_max, [0.5, 0.5, 0.5, 1.0]),
+        }
+
+        let text_y = row_bottom + (ROW_HEIGHT - PADDING - font::GLYPH_HEIGHT) / 2.0;
+        ui.text([icon_max[0] + 2.0 * PADDING, text_y], item, [1.0; 4]);
+        let count = count.to_string();
+        let count_x = right - 2.0 * PADDING - font::text_width(&count);
+        ui.text([count_x, text_y], &count, [1.0; 4]);
+    }
+}
+
+fn draw_container_ui(
+    container_ui: Res<ContainerUi>,
+    registry: Res<Arc<BlockRegistry>>,
+    mut access: ResMut<ChunkAccess>,
+    mut player_inventory: PlayerInventory,
+    mut ui: ResMut<ImmediateUi>,
+) {
+    let contents = match container_ui.open.and_then(|pos| access.block_entity(pos)) {
+        Some(BlockEntity::Chest(items)) => items,
+        _ => return,
+    };
+    let inventory = match player_inventory.get() {
+        Some(inventory) => inventory,
+        None => return,
+    };
+
+    let rows = usize::max(inventory.iter().count(), contents.iter().count()).max(1);
+    let top = (rows + 1) as f32 * ROW_HEIGHT / 2.0;
+    let selected = |column| Some(container_ui.selected).filter(|_| container_ui.column == column);
+
+    draw_column(
+        &mut ui,
+        &registry,
+        &tr!("container.player"),
+        &inventory,
+        [-PANEL_WIDTH - PANEL_GAP / 2.0, top],
+        rows,
+        selected(ContainerColumn::Player),
+    );
+    draw_column(
+        &mut ui,
+        &registry,
+        &tr!("container.chest"),
+        contents,
+        [PANEL_GAP / 2.0, top],
+        rows,
+        selected(ContainerColumn::Container),
+    );
+}
+
+#[derive(Debug, Default)]
+pub struct ContainerUiPlugin {}
+
+impl Plugin for ContainerUiPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ContainerUi>();
+        app.add_system(
+            container_ui_input
+                .system()
+                .label(ContainerUiLabel)
+                .after(CraftingUiLabel)
+                .after(TerrainManipulation)
+                .with_run_criteria(playing.system()),
+        );
+        app.add_system(draw_container_ui.system().after(ContainerUiLabel));
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub struct ContainerUiLabel;
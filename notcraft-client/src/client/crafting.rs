@@ -0,0 +1,159 @@
+use crate::client::{
+    input::{keys, InputState},
+    render::renderer::ImmediateUi,
+};
+use glium::glutin::event::VirtualKeyCode;
+use notcraft_common::{
+    crafting::{CraftEvent, RecipeRegistry},
+    game_state::playing,
+    inventory::{Inventory, ItemStack},
+    prelude::*,
+    world::registry::{BlockRegistry, TextureId},
+    Side,
+};
+use std::sync::Arc;
+
+const ROW_HEIGHT: f32 = 24.0;
+const ICON_SIZE: f32 = 16.0;
+const PANEL_WIDTH: f32 = 160.0;
+const PADDING: f32 = 4.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct CraftingUi {
+    pub open: bool,
+    pub selected: usize,
+    /// the entity whose inventory is used for crafting.
+    pub target: Option<Entity>,
+}
+
+impl CraftingUi {
+    pub fn for_entity(entity: Entity) -> Self {
+        Self {
+            target: Some(entity),
+            ..Default::default()
+        }
+    }
+}
+
+fn crafting_ui_input(
+    input: Res<InputState>,
+    recipes: Res<RecipeRegistry>,
+    mut ui: ResMut<CraftingUi>,
+    mut craft_events: EventWriter<CraftEvent>,
+) {
+    if input.key(VirtualKeyCode::Tab).is_rising() {
+        ui.open = !ui.open;
+    }
+
+    if !ui.open || recipes.is_empty() {
+        return;
+    }
+
+    if input.key(keys::ARROW_UP).is_rising() {
+        ui.selected = (ui.selected + recipes.len() - 1) % recipes.len();
+    }
+    if input.key(keys::ARROW_DOWN).is_rising() {
+        ui.selected = (ui.selected + 1) % recipes.len();
+    }
+    ui.selected = ui.selected.min(recipes.len() - 1);
+
+    if input.key(VirtualKeyCode::Return).is_rising() {
+        if let (Some(entity), Some((recipe, _))) = (ui.target, recipes.iter().nth(ui.selected)) {
+            craft_events.send(CraftEvent { entity, recipe });
+        }
+    }
+}
+
+pub fn item_icon(registry: &BlockRegistry, item: &str) -> Option<TextureId> {
+    let textures = registry.get(registry.try_lookup(item)?).block_textures()?;
+    let pool = textures.first()?[Side::Front];
+    registry.pool_textures(pool).first().copied()
+}
+
+/// draws an item's icon with its count shown as a row of pips underneath.
+fn draw_stack(ui: &mut ImmediateUi, registry: &BlockRegistry, stack: &ItemStack, pos: [f32; 2]) {
+    let [x, y] = pos;
+    let max = [x + ICON_SIZE, y + ICON_SIZE];
+    match item_icon(registry, &stack.item) {
+        Some(texture) => ui.textured_rect([x, y], max, texture, [1.0; 4]),
+        None => ui.rect([x, y], max, [0.5, 0.5, 0.5, 1.0]),
+    }
+
+    for i in 0..stack.count.min(8) {
+        let px = x + 2.0 * i as f32;
+        ui.rect([px, y - 2.0], [px + 1.0, y - 1.0], [1.0; 4]);
+    }
+}
+
+fn draw_crafting_ui(
+    crafting_ui: Res<CraftingUi>,
+    recipes: Res<RecipeRegistry>,
+    registry: Res<Arc<BlockRegistry>>,
+    inventories: Query<&Inventory>,
+    mut ui: ResMut<ImmediateUi>,
+) {
+    if !crafting_ui.open {
+        return;
+    }
+
+    let empty = Inventory::default();
+    let inventory = crafting_ui
+        .target
+        .and_then(|entity| inventories.get(entity).ok())
+        .unwrap_or(&empty);
+
+    let height = recipes.len() as f32 * ROW_HEIGHT + PADDING;
+    let left = -PANEL_WIDTH / 2.0;
+    let top = height / 2.0;
+    ui.rect([left, -top], [-left, top], [0.05, 0.05, 0.05, 0.8]);
+
+    for (idx, (id, recipe)) in recipes.iter().enumerate() {
+        let row_top = top - PADDING - idx as f32 * ROW_HEIGHT;
+        let row_bottom = row_top - ROW_HEIGHT + PADDING;
+
+        let selected = idx == crafting_ui.selected;
+        let color = match (selected, recipes.can_craft(inventory, id)) {
+            (true, true) => [0.3, 0.6, 0.3, 0.9],
+            (true, false) => [0.6, 0.3, 0.3, 0.9],
+            (false, true) => [0.2, 0.35, 0.2, 0.9],
+            (false, false) => [0.2, 0.2, 0.2, 0.9],
+        };
+        let (row_left, row_right) = (left + PADDING, -left - PADDING);
+        ui.rect([row_left, row_bottom], [row_right, row_top], color);
+
+        let icon_y = row_bottom + (ROW_HEIGHT - PADDING - ICON_SIZE) / 2.0 + 1.0;
+        let mut x = left + 2.0 * PADDING;
+        for ingredient in recipe.ingredients.iter() {
+            draw_stack(&mut ui, &registry, ingredient, [x, icon_y]);
+            x += ICON_SIZE + PADDING;
+        }
+
+        // a little arrow pointing towards the result
+        let ax = -left - 3.0 * PADDING - 2.0 * ICON_SIZE;
+        let ay = icon_y + ICON_SIZE / 2.0;
+        ui.rect([ax, ay - 1.0], [ax + 10.0, ay + 1.0], [1.0; 4]);
+        ui.rect([ax + 8.0, ay - 3.0], [ax + 10.0, ay + 3.0], [1.0; 4]);
+
+        let result_x = -left - 2.0 * PADDING - ICON_SIZE;
+        draw_stack(&mut ui, &registry, &recipe.result, [result_x, icon_y]);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CraftingUiPlugin {}
+
+impl Plugin for CraftingUiPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<CraftingUi>();
+        app.add_system(
+            crafting_ui_input
+                .system()
+                .label(CraftingUiLabel)
+                .with_run_criteria(playing.system()),
+        );
+        app.add_system(draw_crafting_ui.system().after(CraftingUiLabel));
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub struct CraftingUiLabel;
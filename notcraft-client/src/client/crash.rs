@@ -0,0 +1,123 @@
+//! telling the player that the game crashed, instead of just vanishing.
+//!
+//! when anything panics, the usual panic message is printed, and then a message
+//! box pops up with the same message. there's no windowing toolkit for message
+//! boxes, so this leans on whatever the platform has: `MessageBoxW` on
+//! Windows, `osascript` on macOS, and `zenity` or `kdialog` elsewhere. if none
+//! of those work, the message is only printed.
+//!
+//! panics in the middle of writing to a chunk also save a reproduction capsule
+//! first, see [`notcraft_common::world::repro`].
+
+use notcraft_common::world::repro;
+use std::panic::PanicHookInfo;
+
+const TITLE: &str = "Notcraft crashed";
+
+fn panic_message(info: &PanicHookInfo) -> String {
+    let payload = info.payload();
+    let message = match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.as_str(),
+            None => "unknown error",
+        },
+    };
+
+    match info.location() {
+        Some(location) => format!("{}\n\nat {}", message, location),
+        None => message.into(),
+    }
+}
+
+#[cfg(windows)]
+fn show_message_box(title: &str, message: &str) -> bool {
+    use std::{ffi::OsStr, os::windows::ffi::OsStrExt, ptr};
+
+    const MB_ICONERROR: u32 = 0x10;
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn MessageBoxW(
+            window: *mut std::ffi::c_void,
+            text: *const u16,
+            caption: *const u16,
+            kind: u32,
+        ) -> i32;
+    }
+
+    let wide = |text: &str| -> Vec<u16> { OsStr::new(text).encode_wide().chain([0]).collect() };
+    let (title, message) = (wide(title), wide(message));
+    // SAFETY: both strings are nul-terminated, and a null window just means
+    // the message box doesn't belong to any window.
+    unsafe {
+        MessageBoxW(
+            ptr::null_mut(),
+            message.as_ptr(),
+            title.as_ptr(),
+            MB_ICONERROR,
+        ) != 0
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn show_message_box(title: &str, message: &str) -> bool {
+    // the text is passed as arguments so that it doesn't need escaping
+    std::process::Command::new("osascript")
+        .args(["-e", "on run argv"])
+        .args([
+            "-e",
+            "display alert (item 1 of argv) message (item 2 of argv) as critical",
+        ])
+        .args(["-e", "end run", title, message])
+        .status()
+        .is_ok()
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn show_message_box(title: &str, message: &str) -> bool {
+    use std::process::Command;
+
+    // these only fail to run when they aren't installed. closing the message
+    // box instead of pressing OK still counts as showing it.
+    let zenity = Command::new("zenity")
+        .args([
+            "--error",
+            "--no-markup",
+            "--title",
+            title,
+            "--text",
+            message,
+        ])
+        .status();
+    zenity.is_ok()
+        || Command::new("kdialog")
+            .args(["--title", title, "--error", message])
+            .status()
+            .is_ok()
+}
+
+/// shows a message box whenever the game panics, after the usual panic
+/// message has been printed.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        let message = panic_message(info);
+        log::error!("panicked: {}", message);
+        let mut text = format!("{}\n\nthe log might have more details.", message);
+        match repro::write_capsule(&message) {
+            Some(Ok(path)) => {
+                log::error!("saved a reproduction capsule to '{}'", path.display());
+                let saved = format!(" the chunk it happened in was saved to {}", path.display());
+                text.push_str(&saved);
+            }
+            Some(Err(err)) => log::error!("failed to save a reproduction capsule: {}", err),
+            None => {}
+        }
+        if !show_message_box(TITLE, &text) {
+            log::warn!("couldn't show a message box for the crash");
+        }
+    }));
+}
@@ -1,24 +1,61 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use glium::glutin::event::VirtualKeyCode;
 use notcraft_common::{
     aabb::Aabb,
-    debug::drain_debug_events,
+    debug::{
+        drain_debug_events,
+        recording::{EventSummary, Summarize},
+    },
     debug_events,
+    prelude::*,
+    profiler::{FrameProfile, Profiler},
+    task::{PoolKind, TaskPools},
+    transform::Transform,
     world::{
-        chunk::{ChunkSectionPos, CHUNK_LENGTH},
+        chunk::{ChunkSectionPos, DirtyRegion, CHUNK_LENGTH},
         chunk_section_aabb,
         debug::{WorldAccessEvent, WorldLoadEvent},
+        loader::ChunkLoadStats,
         ChunkPos,
     },
 };
 
-use super::render::renderer::{add_debug_box, add_transient_debug_box, DebugBox, DebugBoxKind};
+use super::{
+    camera::{ActiveCamera, Camera},
+    input::InputState,
+    render::{
+        font,
+        render_scale::RenderScale,
+        renderer::{
+            add_debug_box, add_transient_debug_box, DebugBox, DebugBoxKind, FrozenCamera, GpuPass,
+            GpuPassTimes, ImmediateUi, RenderDebugSettings, RenderStats,
+        },
+    },
+};
 
 pub enum MesherEvent {
-    Meshed { cheap: bool, pos: ChunkSectionPos },
+    Meshed {
+        cheap: bool,
+        pos: ChunkSectionPos,
+        /// the part of the section that changed, if only part of it did.
+        dirty: Option<DirtyRegion>,
+    },
     MeshFailed(ChunkSectionPos),
 }
 
+impl Summarize for MesherEvent {
+    fn summarize(&self) -> EventSummary {
+        let (kind, pos) = match *self {
+            MesherEvent::Meshed { cheap, pos, .. } if cheap => ("meshed_cheap", pos),
+            MesherEvent::Meshed { pos, .. } => ("meshed", pos),
+            MesherEvent::MeshFailed(pos) => ("mesh_failed", pos),
+        };
+        let pos = [pos.x, pos.y, pos.z];
+        EventSummary { kind, pos }
+    }
+}
+
 debug_events! {
     events,
     MesherEvent => "mesher",
@@ -31,6 +68,13 @@ pub fn debug_chunk_aabb(pos: ChunkPos) -> Aabb {
     Aabb { min, max }
 }
 
+pub fn dirty_region_aabb(pos: ChunkSectionPos, region: DirtyRegion) -> Aabb {
+    let origin = chunk_section_aabb(pos).min;
+    let [min, max] = [region.min, region.max.map(|n| n + 1)]
+        .map(|[x, y, z]| origin + nalgebra::vector![x as f32, y as f32, z as f32]);
+    Aabb { min, max }
+}
+
 // TODO: make the debug line renderer just a more generic line renderer and
 // require it as a resource here.
 pub fn debug_event_handler() {
@@ -93,18 +137,29 @@ pub fn debug_event_handler() {
     });
 
     drain_debug_events::<MesherEvent, _>(|event| match event {
-        MesherEvent::Meshed { cheap: true, pos } => add_transient_debug_box(
+        MesherEvent::Meshed { cheap, pos, .. } if cheap => add_transient_debug_box(
             Duration::from_secs(1),
             DebugBox::new(chunk_section_aabb(pos))
                 .with_color([1.0, 0.0, 1.0, 0.3])
                 .with_kind(DebugBoxKind::Dashed),
         ),
-        MesherEvent::Meshed { cheap: false, pos } => add_transient_debug_box(
-            Duration::from_secs(1),
-            DebugBox::new(chunk_section_aabb(pos))
-                .with_color([1.0, 1.0, 0.0, 0.3])
-                .with_kind(DebugBoxKind::Dashed),
-        ),
+        MesherEvent::Meshed { pos, dirty, .. } => {
+            add_transient_debug_box(
+                Duration::from_secs(1),
+                DebugBox::new(chunk_section_aabb(pos))
+                    .with_color([1.0, 1.0, 0.0, 0.3])
+                    .with_kind(DebugBoxKind::Dashed),
+            );
+            // the part of the section that actually changed
+            if let Some(dirty) = dirty {
+                add_transient_debug_box(
+                    Duration::from_secs(1),
+                    DebugBox::new(dirty_region_aabb(pos, dirty))
+                        .with_color([1.0, 0.5, 0.0, 0.8])
+                        .with_kind(DebugBoxKind::Solid),
+                );
+            }
+        }
         MesherEvent::MeshFailed(pos) => add_transient_debug_box(
             Duration::from_secs(2),
             DebugBox::new(chunk_section_aabb(pos))
@@ -113,3 +168,346 @@ pub fn debug_event_handler() {
         ),
     });
 }
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct DebugOverlay {
+    pub open: bool,
+}
+
+fn toggle_debug_overlay(
+    input: Res<InputState>,
+    mut overlay: ResMut<DebugOverlay>,
+    mut profiler: ResMut<Profiler>,
+    render_stats: Res<RenderStats>,
+    render_scale: Res<RenderScale>,
+    load_stats: Res<ChunkLoadStats>,
+) {
+    if input.key(VirtualKeyCode::F3).is_rising() {
+        overlay.open = !overlay.open;
+        // there's no sense in paying for profiling when nobody is looking at it
+        profiler.set_recording(overlay.open);
+    }
+
+    if overlay.open && input.key(VirtualKeyCode::F4).is_rising() {
+        match profiler.last_frame() {
+            Some(frame) => log_frame_profile(frame),
+            None => log::info!("no frames have been profiled yet"),
+        }
+        let terrain_stats = render_stats.terrain;
+        log::info!(
+            "terrain: {} draw calls for {} visible meshes, in {} batches",
+            terrain_stats.draw_calls,
+            terrain_stats.visible_meshes,
+            terrain_stats.batches
+        );
+        log::info!("render scale: {:.0}%", 100.0 * render_scale.current());
+        log::info!(
+            "chunks: {} loads and {} unloads in the last minute, unload radius +{}",
+            load_stats.loads_per_minute,
+            load_stats.unloads_per_minute,
+            load_stats.extra_unload_radius
+        );
+        match render_stats.gpu {
+            Some(times) => log_gpu_times(&times),
+            None => log::info!("gpu: timings aren't available"),
+        }
+    }
+}
+
+fn toggle_render_debug(
+    input: Res<InputState>,
+    active_camera: Res<ActiveCamera>,
+    cameras: Query<(&Camera, &Transform)>,
+    mut settings: ResMut<RenderDebugSettings>,
+) {
+    if input.key(VirtualKeyCode::F5).is_rising() {
+        settings.wireframe = !settings.wireframe;
+    }
+    if input.key(VirtualKeyCode::F6).is_rising() {
+        settings.section_grid = !settings.section_grid;
+    }
+    if input.key(VirtualKeyCode::F7).is_rising() {
+        settings.colliders = !settings.colliders;
+    }
+
+    let snapshot = || {
+        active_camera
+            .0
+            .and_then(|entity| cameras.get(entity).ok())
+            .map(|(&camera, &transform)| FrozenCamera { transform, camera })
+    };
+
+    if input.key(VirtualKeyCode::F8).is_rising() {
+        settings.frozen_camera = match settings.frozen_camera {
+            Some(_) => None,
+            None => snapshot(),
+        };
+    }
+    if input.key(VirtualKeyCode::F9).is_rising() {
+        settings.freeze_culling = !settings.freeze_culling;
+        if settings.freeze_culling && settings.frozen_camera.is_none() {
+            settings.frozen_camera = snapshot();
+        }
+    }
+}
+
+fn log_frame_profile(frame: &FrameProfile) {
+    log::info!("frame took {:?}", frame.duration);
+    for (stage, duration) in frame.stages() {
+        log::info!("  {}: {:?}", stage, duration);
+        for (name, duration) in frame.children(stage) {
+            log::info!("    {}: {:?}", name, duration);
+        }
+    }
+}
+
+// how often the busy fraction of each pool is recomputed.
+const POOL_SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Default)]
+struct PoolSamples {
+    last_sample: Option<Instant>,
+    last_busy_times: Vec<Duration>,
+    /// the fraction of the pool's total thread time that was spent running
+    /// tasks over the last sample interval.
+    busy: Vec<f32>,
+}
+
+fn sample_pool_stats(pools: &TaskPools, samples: &mut PoolSamples) {
+    let now = Instant::now();
+    let elapsed = match samples.last_sample {
+        Some(last) if now - last < POOL_SAMPLE_INTERVAL => return,
+        Some(last) => now - last,
+        None => Duration::ZERO,
+    };
+
+    let stats = pools.iter().map(|pool| pool.stats()).collect::<Vec<_>>();
+    samples.busy = stats
+        .iter()
+        .zip(samples.last_busy_times.iter())
+        .map(|(stats, &last)| {
+            let available = elapsed.as_secs_f32() * stats.threads as f32;
+            (stats.busy_time - last).as_secs_f32() / available
+        })
+        .collect();
+
+    samples.last_sample = Some(now);
+    samples.last_busy_times = stats.iter().map(|stats| stats.busy_time).collect();
+}
+
+fn pool_color(kind: PoolKind) -> [f32; 4] {
+    match kind {
+        PoolKind::Generator => [0.3, 0.8, 0.3, 1.0],
+        PoolKind::Mesher => [0.9, 0.8, 0.2, 1.0],
+        PoolKind::Io => [0.3, 0.5, 1.0, 1.0],
+        PoolKind::General => [0.8, 0.4, 0.8, 1.0],
+    }
+}
+
+/// draws one row per thread pool: a swatch identifying the pool, a bar showing
+/// how busy the pool has been recently, and a pip for every queued task.
+fn draw_pool_stats(
+    overlay: Res<DebugOverlay>,
+    pools: Res<TaskPools>,
+    mut samples: Local<PoolSamples>,
+    mut ui: ResMut<ImmediateUi>,
+) {
+    sample_pool_stats(&pools, &mut samples);
+    if !overlay.open {
+        return;
+    }
+
+    const LEFT: f32 = -130.0;
+    const TOP: f32 = 130.0;
+    const ROW_HEIGHT: f32 = 8.0;
+    const BAR_WIDTH: f32 = 60.0;
+    const MAX_PIPS: usize = 32;
+
+    for (idx, pool) in pools.iter().enumerate() {
+        let stats = pool.stats();
+        let top = TOP - idx as f32 * ROW_HEIGHT;
+        let bottom = top - ROW_HEIGHT + 2.0;
+
+        ui.rect([LEFT, bottom], [LEFT + 6.0, top], pool_color(pool.kind()));
+
+        let bar_left = LEFT + 8.0;
+        let busy = samples.busy.get(idx).copied().unwrap_or(0.0);
+        let busy_right = bar_left + BAR_WIDTH * busy.clamp(0.0, 1.0);
+        let background = [0.1, 0.1, 0.1, 0.7];
+        ui.rect([bar_left, bottom], [bar_left + BAR_WIDTH, top], background);
+        ui.rect([bar_left, bottom], [busy_right, top], [0.9, 0.9, 0.9, 0.9]);
+
+        let pips_left = bar_left + BAR_WIDTH + 2.0;
+        for pip in 0..stats.queued.min(MAX_PIPS) {
+            let x = pips_left + 3.0 * pip as f32;
+            ui.rect([x, bottom], [x + 2.0, top], [1.0, 0.3, 0.3, 1.0]);
+        }
+    }
+}
+
+// a simple, stable color for anything with a name.
+fn name_color(name: &str) -> [f32; 4] {
+    let hash = name.bytes().fold(2166136261u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(16777619)
+    });
+    let channel = |shift: u32| 0.35 + 0.65 * ((hash >> shift) & 0xff) as f32 / 255.0;
+    [channel(0), channel(8), channel(16), 1.0]
+}
+
+/// draws a graph of recent frame times, with a line marking 60 fps, then a bar
+/// broken up by how long each stage took last frame, and finally a bar for each
+/// of the slowest systems. stage and system colors are derived from their
+/// names, and `F4` prints the names and timings to the log.
+fn draw_frame_profile(
+    overlay: Res<DebugOverlay>,
+    profiler: Res<Profiler>,
+    mut ui: ResMut<ImmediateUi>,
+) {
+    if !overlay.open {
+        return;
+    }
+
+    const LEFT: f32 = -130.0;
+    const TOP: f32 = 90.0;
+    const GRAPH_HEIGHT: f32 = 40.0;
+    // how many UI units tall a millisecond is in the frame graph, and how many
+    // UI units wide a millisecond is in the stage and system bars.
+    const UNITS_PER_MS: f32 = 1.2;
+    const MAX_SYSTEMS: usize = 8;
+
+    let bottom = TOP - GRAPH_HEIGHT;
+    let background = [0.1, 0.1, 0.1, 0.7];
+    ui.rect([LEFT, bottom], [LEFT + 120.0, TOP], background);
+    for (idx, frame) in profiler.history().enumerate() {
+        let ms = frame.duration.as_secs_f32() * 1000.0;
+        let x = LEFT + idx as f32;
+        let height = f32::min(GRAPH_HEIGHT, ms * UNITS_PER_MS);
+        let color = match ms > 1000.0 / 60.0 {
+            true => [1.0, 0.4, 0.3, 0.9],
+            false => [0.4, 1.0, 0.4, 0.9],
+        };
+        ui.rect([x, bottom], [x + 1.0, bottom + height], color);
+    }
+    let target_y = bottom + UNITS_PER_MS * 1000.0 / 60.0;
+    ui.rect([LEFT, target_y], [LEFT + 120.0, target_y + 0.5], [1.0; 4]);
+
+    let frame = match profiler.last_frame() {
+        Some(frame) => frame,
+        None => return,
+    };
+
+    let mut y = bottom - 2.0;
+    let mut x = LEFT;
+    for (stage, duration) in frame.stages() {
+        let width = duration.as_secs_f32() * 1000.0 * UNITS_PER_MS;
+        ui.rect([x, y - 6.0], [x + width, y], name_color(stage));
+        x += width;
+    }
+    y -= 8.0;
+
+    let mut systems = frame
+        .stages()
+        .into_iter()
+        .flat_map(|(stage, _)| frame.children(stage))
+        .collect::<Vec<_>>();
+    systems.sort_by_key(|&(_, duration)| std::cmp::Reverse(duration));
+    for (name, duration) in systems.into_iter().take(MAX_SYSTEMS) {
+        let width = duration.as_secs_f32() * 1000.0 * UNITS_PER_MS;
+        let right = LEFT + width.max(1.0);
+        ui.rect([LEFT, y - 4.0], [right, y], name_color(name));
+        y -= 5.0;
+    }
+}
+
+fn log_gpu_times(times: &GpuPassTimes) {
+    log::info!("gpu: {:.2}ms", times.total_millis());
+    for pass in GpuPass::ALL {
+        log::info!("  {}: {:.2}ms", pass.name(), times.millis(pass));
+    }
+}
+
+/// draws how many terrain meshes were visible last frame in grey, which is how
+/// many draw calls it would have taken to draw them one by one, and how many
+/// draw calls batching them actually took in white. below that is a bar broken
+/// up by how long each instrumented pass took on the GPU, if that's known.
+fn draw_render_stats(
+    overlay: Res<DebugOverlay>,
+    stats: Res<RenderStats>,
+    mut ui: ResMut<ImmediateUi>,
+) {
+    if !overlay.open {
+        return;
+    }
+
+    const LEFT: f32 = -130.0;
+    const TOP: f32 = -6.0;
+    const MAX_WIDTH: f32 = 120.0;
+    const UNITS_PER_DRAW: f32 = 0.1;
+
+    let width = |count: usize| f32::min(MAX_WIDTH, count as f32 * UNITS_PER_DRAW).max(1.0);
+    let background = [0.1, 0.1, 0.1, 0.7];
+    ui.rect([LEFT, TOP - 10.0], [LEFT + MAX_WIDTH, TOP], background);
+    let meshes_right = LEFT + width(stats.terrain.visible_meshes);
+    ui.rect([LEFT, TOP - 4.0], [meshes_right, TOP], [0.5, 0.5, 0.5, 0.9]);
+    let draws_right = LEFT + width(stats.terrain.draw_calls);
+    let draws_color = [0.9, 0.9, 0.9, 0.9];
+    ui.rect([LEFT, TOP - 10.0], [draws_right, TOP - 6.0], draws_color);
+
+    let times = match stats.gpu {
+        Some(times) => times,
+        None => return,
+    };
+    // the same scale as the stage bar, so the two can be compared
+    const UNITS_PER_MS: f32 = 1.2;
+    let mut x = LEFT;
+    for pass in GpuPass::ALL {
+        let width = f32::min(LEFT + MAX_WIDTH - x, times.millis(pass) * UNITS_PER_MS);
+        ui.rect([x, TOP - 18.0], [x + width, TOP - 12.0], name_color(pass.name()));
+        x += width;
+    }
+}
+
+/// writes out how many chunks were loaded and unloaded over the last minute,
+/// and how much further than usual chunks are being kept loaded because of it.
+fn draw_load_stats(
+    overlay: Res<DebugOverlay>,
+    stats: Res<ChunkLoadStats>,
+    mut ui: ResMut<ImmediateUi>,
+) {
+    if !overlay.open {
+        return;
+    }
+
+    const LEFT: f32 = -130.0;
+    const TOP: f32 = -26.0;
+    const LINE_HEIGHT: f32 = font::GLYPH_HEIGHT + 2.0;
+
+    let lines = [
+        format!("chunk loads/min: {}", stats.loads_per_minute),
+        format!("chunk unloads/min: {}", stats.unloads_per_minute),
+        format!("unload radius: +{}", stats.extra_unload_radius),
+    ];
+    let longest = lines.iter().map(|line| line.chars().count()).max();
+    let right = LEFT + longest.unwrap_or(0) as f32 * font::GLYPH_ADVANCE + 2.0;
+    let bottom = TOP - lines.len() as f32 * LINE_HEIGHT - 2.0;
+    ui.rect([LEFT, bottom], [right, TOP], [0.1, 0.1, 0.1, 0.7]);
+    for (idx, line) in lines.iter().enumerate() {
+        let y = TOP - (idx + 1) as f32 * LINE_HEIGHT;
+        ui.text([LEFT + 1.0, y], line, [1.0; 4]);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DebugOverlayPlugin {}
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<DebugOverlay>();
+        app.add_system(toggle_debug_overlay.system());
+        app.add_system(toggle_render_debug.system());
+        app.add_system(draw_pool_stats.system());
+        app.add_system(draw_frame_profile.system());
+        app.add_system(draw_render_stats.system());
+        app.add_system(draw_load_stats.system());
+    }
+}
@@ -0,0 +1,230 @@
+//! the burst of particles and the bang that go along with explosions, and the
+//! flashing of explosives that are about to go off.
+
+use crate::{
+    audio_pool::RandomizedAudioPools,
+    client::{
+        audio::{AudioEvent, ParameterizedSource},
+        camera::CurrentCamera,
+        crafting::item_icon,
+        render::{
+            mesher::{generation::mesh_box, TerrainMesh},
+            renderer::{ImmediateQuads, MeshHandle, RenderMeshComponent, SharedMeshContext},
+        },
+    },
+};
+use nalgebra::{Point3, Vector3};
+use notcraft_common::{
+    clock::GameTime,
+    explosion::{ExplosionEvent, PrimedExplosive, PRIMED_EXPLOSIVE_SIZE},
+    prelude::*,
+    transform::Transform,
+    world::registry::{BlockId, BlockRegistry},
+};
+use rand::Rng;
+use std::{collections::HashMap, sync::Arc};
+
+/// how many particles an explosion spawns for each block of its radius...
+const PARTICLES_PER_RADIUS: f32 = 24.0;
+/// ...and how many there can be at once, no matter how many explosions there
+/// have been.
+const MAX_PARTICLES: usize = 1024;
+/// how quickly particles slow down, as the fraction of their speed lost per
+/// second.
+const PARTICLE_DRAG: f32 = 2.5;
+/// particles drift upwards at this many blocks per second squared, like smoke.
+const PARTICLE_LIFT: f32 = 1.5;
+
+const FIRE_COLOR: [f32; 3] = [1.0, 0.65, 0.2];
+const SMOKE_COLOR: [f32; 3] = [0.3, 0.3, 0.3];
+
+/// how many seconds a primed explosive spends flashing white and then not,
+/// for each flash.
+const FLASH_PERIOD: f32 = 0.5;
+const FLASH_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.6];
+
+#[derive(Clone, Debug, PartialEq)]
+struct ExplosionParticle {
+    pos: Point3<f32>,
+    velocity: Vector3<f32>,
+    age: f32,
+    lifetime: f32,
+    /// the length of the particle's sides, in blocks.
+    size: f32,
+}
+
+#[derive(Debug, Default)]
+pub struct ExplosionParticles {
+    particles: Vec<ExplosionParticle>,
+}
+
+fn random_direction<R: Rng>(rng: &mut R) -> Vector3<f32> {
+    loop {
+        let v = vector![
+            rng.gen_range(-1.0, 1.0),
+            rng.gen_range(-1.0, 1.0),
+            rng.gen_range(-1.0, 1.0)
+        ];
+        if let Some(direction) = v.try_normalize(1e-3).filter(|_| v.norm() <= 1.0) {
+            return direction;
+        }
+    }
+}
+
+fn spawn_explosion_effects(
+    mut explosion_events: EventReader<ExplosionEvent>,
+    mut particles: ResMut<ExplosionParticles>,
+    pools: Res<RandomizedAudioPools>,
+    mut audio_events: EventWriter<AudioEvent>,
+) {
+    let mut rng = rand::thread_rng();
+    for explosion in explosion_events.iter() {
+        let count = (explosion.radius * PARTICLES_PER_RADIUS) as usize;
+        let count = count.min(MAX_PARTICLES - particles.particles.len());
+        for _ in 0..count {
+            let speed = explosion.radius * rng.gen_range(1.0, 3.0);
+            particles.particles.push(ExplosionParticle {
+                pos: explosion.center,
+                velocity: random_direction(&mut rng) * speed,
+                age: 0.0,
+                lifetime: rng.gen_range(0.6, 1.6),
+                size: rng.gen_range(0.2, 0.6),
+            });
+        }
+
+        if let Some(id) = pools.id("explosion") {
+            pools.select(&mut rng, id, |id, params| {
+                let source = ParameterizedSource::from_sample(id).with_parameters(params);
+                audio_events.send(AudioEvent::SpawnSpatial(explosion.center, source));
+            });
+        }
+    }
+}
+
+fn update_explosion_particles(time: Res<GameTime>, mut particles: ResMut<ExplosionParticles>) {
+    let dt = time.delta_seconds();
+    particles.particles.retain_mut(|particle| {
+        particle.velocity *= f32::max(0.0, 1.0 - PARTICLE_DRAG * dt);
+        particle.velocity.y += PARTICLE_LIFT * dt;
+        particle.pos += particle.velocity * dt;
+        particle.age += dt;
+        particle.age < particle.lifetime
+    });
+}
+
+fn draw_explosion_particles(
+    camera: CurrentCamera,
+    particles: Res<ExplosionParticles>,
+    mut quads: ResMut<ImmediateQuads>,
+) {
+    let transform = match camera.transform() {
+        Some(transform) => transform,
+        None => return,
+    };
+
+    // particles always face the camera
+    let rotation = transform.rotation.to_quaternion();
+    let (right, up) = (rotation * Vector3::x(), rotation * Vector3::y());
+
+    for particle in particles.particles.iter() {
+        // particles start out as fire and burn out into smoke, which grows and
+        // fades away as it goes.
+        let t = particle.age / particle.lifetime;
+        let fire = f32::max(0.0, 1.0 - 3.0 * t);
+        let color = [0, 1, 2].map(|i| SMOKE_COLOR[i] + fire * (FIRE_COLOR[i] - SMOKE_COLOR[i]));
+        let color = [color[0], color[1], color[2], 0.9 * (1.0 - t)];
+
+        let half = 0.5 * particle.size * (1.0 + t);
+        let (right, up) = (right * half, up * half);
+        let pos = particle.pos;
+        quads.quad(
+            [
+                pos - right - up,
+                pos + right - up,
+                pos + right + up,
+                pos - right + up,
+            ],
+            color,
+        );
+    }
+}
+
+/// primed explosives look like the block they came from, and explosives of the
+/// same kind share a mesh.
+#[derive(Default)]
+struct PrimedExplosiveMeshes(HashMap<BlockId, MeshHandle<TerrainMesh>>);
+
+fn attach_primed_explosive_meshes(
+    mut cmd: Commands,
+    mut meshes: Local<PrimedExplosiveMeshes>,
+    registry: Res<Arc<BlockRegistry>>,
+    mesh_context: Res<Arc<SharedMeshContext<TerrainMesh>>>,
+    query: Query<(Entity, &PrimedExplosive), Added<PrimedExplosive>>,
+) {
+    query.for_each(|(entity, primed)| {
+        let handle = meshes.0.entry(primed.block).or_insert_with(|| {
+            let tex_id = item_icon(&registry, registry.name(primed.block)).unwrap_or_default();
+            let size = (16.0 * PRIMED_EXPLOSIVE_SIZE).round() as u16;
+            mesh_context.upload(mesh_box(Vector3::repeat(size), tex_id))
+        });
+
+        cmd.entity(entity)
+            .insert(RenderMeshComponent::new(handle.clone()));
+    });
+}
+
+/// covers every side of the box from `min` to `max` with `color`.
+fn draw_box(quads: &mut ImmediateQuads, min: Point3<f32>, max: Point3<f32>, color: [f32; 4]) {
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for side in [min[axis], max[axis]] {
+            let corner = |cu: f32, cv: f32| {
+                let mut pos = min;
+                pos[axis] = side;
+                pos[u] = cu;
+                pos[v] = cv;
+                pos
+            };
+            quads.quad(
+                [
+                    corner(min[u], min[v]),
+                    corner(max[u], min[v]),
+                    corner(max[u], max[v]),
+                    corner(min[u], max[v]),
+                ],
+                color,
+            );
+        }
+    }
+}
+
+fn draw_fuse_flashes(
+    query: Query<(&Transform, &PrimedExplosive)>,
+    mut quads: ResMut<ImmediateQuads>,
+) {
+    query.for_each(|(transform, primed)| {
+        if (primed.remaining / FLASH_PERIOD).fract() < 0.5 {
+            return;
+        }
+
+        // slightly bigger than the explosive itself, so that the flash isn't
+        // fighting with its faces.
+        let min = transform.pos() - Vector3::repeat(0.01);
+        let max = transform.pos() + Vector3::repeat(PRIMED_EXPLOSIVE_SIZE + 0.01);
+        draw_box(&mut quads, min, max, FLASH_COLOR);
+    });
+}
+
+#[derive(Debug, Default)]
+pub struct ExplosionEffectsPlugin {}
+
+impl Plugin for ExplosionEffectsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ExplosionParticles>();
+        app.add_system(spawn_explosion_effects.system());
+        app.add_system(update_explosion_particles.system());
+        app.add_system(draw_explosion_particles.system());
+        app.add_system(attach_primed_explosive_meshes.system());
+        app.add_system(draw_fuse_flashes.system());
+    }
+}
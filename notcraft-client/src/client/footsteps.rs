@@ -0,0 +1,77 @@
+//! footstep sounds, which come from the sound group of whatever block the
+//! player is walking on.
+
+use crate::{
+    audio_pool::RandomizedAudioPools,
+    client::audio::{AudioEvent, ParameterizedSource},
+    PlayerController,
+};
+use notcraft_common::{
+    clock::GameTime,
+    physics::{AabbCollider, RigidBody},
+    prelude::*,
+    transform::Transform,
+    world::{chunk::ChunkAccess, BlockPos, WorldPos},
+};
+use std::sync::Arc;
+
+/// how far, in blocks, the player walks between footsteps.
+const STEP_LENGTH: f32 = 1.6;
+/// footsteps are a lot quieter than breaking or placing blocks.
+const STEP_AMPLITUDE: f32 = 0.3;
+
+/// how far the player has walked since their last footstep.
+#[derive(Debug, Default)]
+struct StepDistance(f32);
+
+fn play_footsteps(
+    time: Res<GameTime>,
+    player_controller: Res<PlayerController>,
+    mut access: ResMut<ChunkAccess>,
+    mut walked: Local<StepDistance>,
+    pools: Res<RandomizedAudioPools>,
+    mut audio_events: EventWriter<AudioEvent>,
+    query: Query<(&Transform, &AabbCollider, Option<&RigidBody>)>,
+) {
+    let (transform, collider, rigidbody) = match query.get(player_controller.player) {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    // flying players don't have a rigidbody, and don't make any noise
+    let velocity = match rigidbody {
+        Some(rigidbody) if collider.on_ground && !collider.in_liquid => rigidbody.velocity,
+        _ => return,
+    };
+
+    walked.0 += vector![velocity.x, velocity.z].norm() * time.delta_seconds();
+    if walked.0 < STEP_LENGTH {
+        return;
+    }
+    walked.0 = 0.0;
+
+    let aabb = collider.aabb.transformed(transform);
+    let feet = point![aabb.center().x, aabb.min.y, aabb.center().z];
+    let ground = BlockPos::from(WorldPos::new(feet - vector![0.0, 0.05, 0.0]));
+    let registry = Arc::clone(access.registry());
+    let sound = access
+        .block(ground)
+        .and_then(|id| pools.block_sound(&registry, id, "step"));
+
+    if let Some(sound) = sound {
+        pools.select(&mut rand::thread_rng(), sound, |id, mut params| {
+            params.min_amplitude *= STEP_AMPLITUDE;
+            params.max_amplitude *= STEP_AMPLITUDE;
+            let source = ParameterizedSource::from_sample(id).with_parameters(params);
+            audio_events.send(AudioEvent::SpawnSpatial(feet, source));
+        });
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FootstepPlugin {}
+
+impl Plugin for FootstepPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(play_footsteps.system());
+    }
+}
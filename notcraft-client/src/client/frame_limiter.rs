@@ -0,0 +1,167 @@
+//! capping the frame rate, so that turning vsync off doesn't keep a CPU core
+//! busy drawing frames nobody will see.
+//!
+//! the runner asks the limiter how long it has to wait before starting the
+//! next frame. long waits are left to the event loop, which can sleep until
+//! then while still handling window events, and the last little bit is spun
+//! away, since sleeping tends to overshoot by a millisecond or more.
+
+use super::window::WindowSettings;
+use notcraft_common::clock::TimeStep;
+use std::time::{Duration, Instant};
+
+/// waits shorter than this are spun instead of slept.
+pub const SPIN_DURATION: Duration = Duration::from_millis(2);
+
+/// what the window is doing, which decides whether the game is throttled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WindowActivity {
+    pub focused: bool,
+    pub minimized: bool,
+}
+
+impl Default for WindowActivity {
+    fn default() -> Self {
+        Self {
+            focused: true,
+            minimized: false,
+        }
+    }
+}
+
+/// the most frames per second that should be drawn right now, or `None` if
+/// there's no cap.
+pub fn frame_rate_cap(
+    settings: &WindowSettings,
+    activity: WindowActivity,
+    step: TimeStep,
+) -> Option<u32> {
+    let cap = match activity.focused && !activity.minimized {
+        true => settings.max_fps,
+        false => Some(settings.background_fps),
+    };
+
+    // with a fixed time step, drawing frames any faster would make the game run
+    // faster than real time
+    match step {
+        TimeStep::RealTime => cap,
+        TimeStep::Fixed(step) => {
+            let step_fps = (1.0 / step.as_secs_f64()).round().max(1.0) as u32;
+            Some(cap.map_or(step_fps, |cap| cap.min(step_fps)))
+        }
+    }
+}
+
+fn frame_duration(fps: Option<u32>) -> Duration {
+    match fps {
+        Some(fps) => Duration::from_secs(1) / fps.max(1),
+        None => Duration::ZERO,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FrameLimiter {
+    /// when the last frame was supposed to start, which is usually a bit
+    /// before it actually did.
+    last_frame: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new(now: Instant) -> Self {
+        Self { last_frame: now }
+    }
+
+    /// how long to wait before starting the next frame.
+    pub fn remaining(&self, now: Instant, fps: Option<u32>) -> Duration {
+        (self.last_frame + frame_duration(fps)).saturating_duration_since(now)
+    }
+
+    pub fn start_frame(&mut self, now: Instant, fps: Option<u32>) {
+        let target = self.last_frame + frame_duration(fps);
+        let behind = now.saturating_duration_since(target);
+
+        // frames are kept on a steady schedule, so that starting one frame a
+        // little late makes the next wait a little shorter. falling more than a
+        // whole frame behind starts a new schedule instead, since catching up
+        // would mean drawing a bunch of frames back to back.
+        self.last_frame = match now < target || behind > frame_duration(fps) {
+            true => now,
+            false => target,
+        };
+    }
+
+    /// spins until it's time for the next frame. this is only meant for the
+    /// last [`SPIN_DURATION`] or so of the wait.
+    pub fn spin(&self, fps: Option<u32>) {
+        while !self.remaining(Instant::now(), fps).is_zero() {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn frames_stay_on_schedule() {
+        let start = Instant::now();
+        let fps = Some(100);
+        let mut limiter = FrameLimiter::new(start);
+
+        assert_eq!(limiter.remaining(start, fps), 10 * MS);
+        assert_eq!(limiter.remaining(start + 4 * MS, fps), 6 * MS);
+        assert_eq!(limiter.remaining(start + 12 * MS, fps), Duration::ZERO);
+
+        // starting 2ms late takes 2ms off of the next wait
+        limiter.start_frame(start + 12 * MS, fps);
+        assert_eq!(limiter.remaining(start + 12 * MS, fps), 8 * MS);
+
+        // but after a long hitch, the next frame is a whole frame away
+        limiter.start_frame(start + 50 * MS, fps);
+        assert_eq!(limiter.remaining(start + 50 * MS, fps), 10 * MS);
+
+        // going back to an uncapped frame rate doesn't wait at all
+        assert_eq!(limiter.remaining(start + 50 * MS, None), Duration::ZERO);
+        limiter.start_frame(start + 51 * MS, None);
+        assert_eq!(limiter.remaining(start + 51 * MS, fps), 10 * MS);
+    }
+
+    #[test]
+    fn background_windows_are_throttled() {
+        let settings = WindowSettings {
+            max_fps: Some(144),
+            background_fps: 10,
+            ..Default::default()
+        };
+        let activity = |focused, minimized| WindowActivity { focused, minimized };
+        let cap = |settings, activity| frame_rate_cap(settings, activity, TimeStep::RealTime);
+
+        assert_eq!(cap(&settings, activity(true, false)), Some(144));
+        assert_eq!(cap(&settings, activity(false, false)), Some(10));
+        assert_eq!(cap(&settings, activity(true, true)), Some(10));
+
+        let uncapped = WindowSettings {
+            max_fps: None,
+            ..settings
+        };
+        assert_eq!(cap(&uncapped, activity(true, false)), None);
+
+        // a fixed time step never runs faster than real time
+        let step = TimeStep::Fixed(Duration::from_millis(20));
+        assert_eq!(
+            frame_rate_cap(&uncapped, activity(true, false), step),
+            Some(50)
+        );
+        assert_eq!(
+            frame_rate_cap(&settings, activity(true, false), step),
+            Some(50)
+        );
+        assert_eq!(
+            frame_rate_cap(&settings, activity(false, false), step),
+            Some(10)
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! the free camera, which leaves the player behind and flies around on its own.
+//!
+//! while it's flying, the movement keys move the camera instead of the player,
+//! and scrolling changes how fast it goes. it speeds up and slows down instead
+//! of starting and stopping all at once, and stops at terrain unless
+//! `/freecam collision off` turned that off.
+//!
+//! switching between camera modes doesn't cut straight to the new view, but
+//! eases over to it with a [`CameraTransition`].
+
+use nalgebra::{vector, Translation3, Vector3};
+use notcraft_common::{
+    aabb::Aabb,
+    physics::sweep_aabb,
+    prelude::*,
+    transform::{EulerAngles, Transform},
+    world::chunk::ChunkAccess,
+};
+use std::f32::consts::PI;
+
+/// the slowest and fastest the camera can fly, in blocks per second.
+pub const MIN_SPEED: f32 = 1.0;
+pub const MAX_SPEED: f32 = 200.0;
+
+/// how much faster each line scrolled up makes the camera, and how much slower
+/// each line scrolled down does.
+const SCROLL_FACTOR: f32 = 1.2;
+/// how quickly the camera's velocity catches up to where it's being told to
+/// go. higher is snappier.
+const ACCELERATION_RATE: f32 = 6.0;
+/// how wide the cube that stands in for the camera is when it runs into
+/// terrain, so that it stops before the near plane cuts into anything.
+const CAMERA_SIZE: f32 = 0.3;
+
+/// how long switching between camera modes takes, in seconds.
+pub const TRANSITION_TIME: f32 = 0.3;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FreeCamera {
+    /// how fast the camera flies, in blocks per second.
+    pub speed: f32,
+    /// whether the camera stops at terrain instead of flying through it.
+    pub collision: bool,
+    velocity: Vector3<f32>,
+}
+
+impl Default for FreeCamera {
+    fn default() -> Self {
+        Self {
+            speed: 10.0,
+            collision: true,
+            velocity: vector![0.0, 0.0, 0.0],
+        }
+    }
+}
+
+impl FreeCamera {
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = util::clamp(MIN_SPEED, MAX_SPEED, speed);
+    }
+
+    /// speeds the camera up or slows it down by however many lines the mouse
+    /// wheel was scrolled.
+    pub fn scroll(&mut self, lines: f32) {
+        self.set_speed(self.speed * SCROLL_FACTOR.powf(lines));
+    }
+
+    pub fn stop(&mut self) {
+        self.velocity = vector![0.0, 0.0, 0.0];
+    }
+
+    /// moves `transform` along for a frame. `direction` is where the movement
+    /// keys say to go: `x` and `z` are relative to where the camera is facing,
+    /// and `y` is straight up and down.
+    pub fn fly(
+        &mut self,
+        access: &mut ChunkAccess,
+        transform: &mut Transform,
+        direction: Vector3<f32>,
+        dt: f32,
+    ) {
+        let facing = transform.rotation.to_quaternion() * vector![direction.x, 0.0, direction.z];
+        let wish = match (facing + vector![0.0, direction.y, 0.0]).try_normalize(0.0) {
+            Some(wish) => wish * self.speed,
+            None => vector![0.0, 0.0, 0.0],
+        };
+        let ease = 1.0 - f32::exp(-ACCELERATION_RATE * dt);
+        self.velocity += (wish - self.velocity) * ease;
+
+        let delta = self.velocity * dt;
+        let moved = match self.collision {
+            false => delta,
+            true => {
+                let size = vector![CAMERA_SIZE, CAMERA_SIZE, CAMERA_SIZE];
+                let aabb = Aabb::with_dimensions(size).translated(transform.translation.vector);
+                // unloaded terrain stops the camera like a wall would
+                sweep_aabb(access, &aabb, delta).unwrap_or_else(Vector3::zeros)
+            }
+        };
+
+        // whatever got in the camera's way stops it along that axis, so it
+        // doesn't keep pushing into walls after the keys are let go
+        for axis in 0..3 {
+            if (moved[axis] - delta[axis]).abs() > 1e-6 {
+                self.velocity[axis] = 0.0;
+            }
+        }
+        transform.translation.vector += moved;
+    }
+}
+
+/// `to - from`, going whichever way around the circle is shorter.
+fn angle_between(from: f32, to: f32) -> f32 {
+    let difference = (to - from).rem_euclid(2.0 * PI);
+    match difference > PI {
+        true => difference - 2.0 * PI,
+        false => difference,
+    }
+}
+
+fn blend(from: &Transform, to: &Transform, t: f32) -> Transform {
+    // smoothstep, so that the camera doesn't lurch into or out of moving
+    let t = t * t * (3.0 - 2.0 * t);
+    let angle = |from: f32, to: f32| from + angle_between(from, to) * t;
+    Transform {
+        translation: Translation3::from(util::lerp_vec(
+            from.translation.vector,
+            to.translation.vector,
+            t,
+        )),
+        rotation: EulerAngles::new(
+            util::lerp(from.rotation.pitch, to.rotation.pitch, t),
+            angle(from.rotation.yaw, to.rotation.yaw),
+            angle(from.rotation.roll, to.rotation.roll),
+        ),
+        scale: util::lerp_vec(from.scale, to.scale, t),
+    }
+}
+
+/// eases the camera from where it was when the camera mode changed over to
+/// wherever the new mode puts it, which might be moving while it happens.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CameraTransition {
+    from: Transform,
+    elapsed: f32,
+}
+
+impl CameraTransition {
+    pub fn new(from: Transform) -> Self {
+        Self { from, elapsed: 0.0 }
+    }
+
+    /// advances the transition by `dt` seconds, and returns where the camera
+    /// should be on its way to `to`, or `None` once it's gotten there.
+    pub fn step(&mut self, dt: f32, to: &Transform) -> Option<Transform> {
+        self.elapsed += dt;
+        match self.elapsed < TRANSITION_TIME {
+            true => Some(blend(&self.from, to, self.elapsed / TRANSITION_TIME)),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::point;
+
+    #[test]
+    fn transitions_ease_the_short_way_around() {
+        let mut from = Transform::to(point![0.0, 0.0, 0.0]);
+        from.rotation.yaw = 0.9 * PI;
+        let mut to = Transform::to(point![10.0, 0.0, 0.0]);
+        to.rotation.yaw = -0.9 * PI;
+
+        let mut transition = CameraTransition::new(from);
+        let halfway = transition.step(TRANSITION_TIME / 2.0, &to).unwrap();
+        assert!((halfway.translation.vector.x - 5.0).abs() < 1e-4);
+        // turning through 180 degrees is shorter than turning back through 0
+        assert!((halfway.rotation.yaw - PI).abs() < 1e-4);
+
+        assert!(transition.step(TRANSITION_TIME, &to).is_none());
+    }
+
+    #[test]
+    fn scrolling_changes_speed_within_limits() {
+        let mut camera = FreeCamera::default();
+        camera.scroll(1.0);
+        assert!((camera.speed - 12.0).abs() < 1e-4);
+        camera.scroll(-100.0);
+        assert_eq!(camera.speed, MIN_SPEED);
+        camera.scroll(100.0);
+        assert_eq!(camera.speed, MAX_SPEED);
+    }
+}
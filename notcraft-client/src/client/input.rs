@@ -1,5 +1,7 @@
+use super::settings::SettingsFile;
 use glium::{
     glutin::{
+        dpi::PhysicalPosition,
         event::{
             ButtonId, DeviceEvent, DeviceId, ElementState, KeyboardInput, ModifiersState,
             MouseScrollDelta, VirtualKeyCode, WindowEvent,
@@ -8,6 +10,7 @@ use glium::{
     },
     Display,
 };
+use nalgebra::Vector2;
 use notcraft_common::prelude::*;
 use std::{
     collections::{HashMap, HashSet},
@@ -35,6 +38,75 @@ impl From<u32> for DigitalInput {
     }
 }
 
+/// the most that acceleration can multiply mouse movement by.
+const MAX_ACCELERATION: f32 = 4.0;
+/// how many counts the mouse has to move in a single frame for `acceleration`
+/// to be added on top of its movement in full.
+const ACCELERATION_COUNTS: f32 = 20.0;
+/// smoothing is kept a bit under 1, where the view would never move at all.
+pub const MAX_SMOOTHING: f32 = 0.95;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MouseSettings {
+    /// how many degrees the view turns for each count the mouse moves.
+    pub sensitivity: f32,
+    /// how much of the last frame's movement carries over into the next one,
+    /// from 0 to [`MAX_SMOOTHING`]. smooths out jittery mice, at the cost of
+    /// the view lagging behind a little.
+    pub smoothing: f32,
+    /// how much more the view turns when the mouse is moved quickly. 0 turns
+    /// the view the same amount no matter how fast the mouse moves.
+    pub acceleration: f32,
+    pub invert_y: bool,
+    /// whether looking around follows the movement of the mouse itself, or
+    /// the movement of the cursor in the window, which has whatever
+    /// acceleration the OS adds baked in.
+    pub raw_input: bool,
+}
+
+impl Default for MouseSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.10,
+            smoothing: 0.0,
+            acceleration: 0.0,
+            invert_y: false,
+            raw_input: true,
+        }
+    }
+}
+
+/// turns how far the mouse moved into how far to look, according to the mouse
+/// settings.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct LookFilter {
+    smoothed: Vector2<f32>,
+}
+
+impl Default for LookFilter {
+    fn default() -> Self {
+        Self {
+            smoothed: Vector2::zeros(),
+        }
+    }
+}
+
+impl LookFilter {
+    fn apply(&mut self, settings: &MouseSettings, movement: Vector2<f32>) -> Vector2<f32> {
+        let speed = movement.magnitude() / ACCELERATION_COUNTS;
+        let boost = f32::min(1.0 + settings.acceleration * speed, MAX_ACCELERATION);
+        let mut look = settings.sensitivity * boost * movement;
+        if settings.invert_y {
+            look.y = -look.y;
+        }
+
+        let smoothing = util::clamp(0.0, MAX_SMOOTHING, settings.smoothing);
+        self.smoothed = look * (1.0 - smoothing) + self.smoothed * smoothing;
+        self.smoothed
+    }
+}
+
 #[derive(Debug)]
 pub struct InputState {
     physical_map: HashMap<VirtualKeyCode, u32>,
@@ -51,7 +123,17 @@ pub struct InputState {
 
     cursor_dx: f32,
     cursor_dy: f32,
-    pub sensitivity: f32,
+    /// how far the cursor moved inside of the window this frame, as opposed
+    /// to how far the mouse itself moved.
+    window_dx: f32,
+    window_dy: f32,
+    last_cursor_pos: Option<PhysicalPosition<f64>>,
+    pub mouse: MouseSettings,
+    look: LookFilter,
+    look_delta: Vector2<f32>,
+    /// how many lines the mouse wheel was scrolled this frame, positive going
+    /// away from the user.
+    scroll: f32,
 
     cursor_currently_grabbed: bool,
     cursor_should_be_grabbed: AtomicBool,
@@ -59,6 +141,7 @@ pub struct InputState {
     cursor_should_be_hidden: AtomicBool,
 
     focused: bool,
+    keyboard_captured: bool,
 }
 
 impl Default for InputState {
@@ -76,7 +159,13 @@ impl Default for InputState {
 
             cursor_dx: 0.0,
             cursor_dy: 0.0,
-            sensitivity: 0.10,
+            window_dx: 0.0,
+            window_dy: 0.0,
+            last_cursor_pos: None,
+            mouse: MouseSettings::default(),
+            look: LookFilter::default(),
+            look_delta: Vector2::zeros(),
+            scroll: 0.0,
 
             cursor_currently_grabbed: false,
             cursor_should_be_grabbed: false.into(),
@@ -84,6 +173,7 @@ impl Default for InputState {
             cursor_should_be_hidden: false.into(),
 
             focused: true,
+            keyboard_captured: false,
         }
     }
 }
@@ -105,8 +195,30 @@ impl InputState {
         self.cursor_should_be_hidden.load(Ordering::SeqCst)
     }
 
-    pub fn cursor_delta(&self) -> nalgebra::Vector2<f32> {
-        self.sensitivity * nalgebra::vector![self.cursor_dx, self.cursor_dy]
+    /// stops key presses from reaching the game, like while typing into a text
+    /// field, and lets go of any keys that were being held down. modifiers are
+    /// still tracked, so that things like `Ctrl+V` can still be read.
+    pub fn capture_keyboard(&mut self, capture: bool) {
+        if capture && !self.keyboard_captured {
+            let pressed = std::mem::take(&mut self.pressed_keys);
+            self.falling_keys.extend(pressed);
+            self.rising_keys.clear();
+        }
+        self.keyboard_captured = capture;
+    }
+
+    pub fn is_keyboard_captured(&self) -> bool {
+        self.keyboard_captured
+    }
+
+    /// how many degrees to look around by this frame, with the mouse settings
+    /// already applied.
+    pub fn cursor_delta(&self) -> Vector2<f32> {
+        self.look_delta
+    }
+
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll
     }
 
     pub fn key<K: Into<DigitalInput>>(&self, key: K) -> KeyRef {
@@ -151,6 +263,60 @@ impl InputState {
     }
 }
 
+/// the parts of [`InputState`] that carry over from one frame to the next, like
+/// which keys are being held down. replays start out with the same ones that
+/// the recording did.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeldInputs {
+    pub keys: Vec<u32>,
+    pub buttons: Vec<u32>,
+    pub modifiers: ModifiersState,
+    /// which physical key each virtual key has been seen coming from.
+    pub physical_map: Vec<(VirtualKeyCode, u32)>,
+    pub focused: bool,
+}
+
+impl InputState {
+    pub fn held(&self) -> HeldInputs {
+        let sorted = |set: &HashSet<u32>| {
+            let mut items = set.iter().copied().collect::<Vec<_>>();
+            items.sort_unstable();
+            items
+        };
+        let mut physical_map = self
+            .physical_map
+            .iter()
+            .map(|(&key, &code)| (key, code))
+            .collect::<Vec<_>>();
+        physical_map.sort_unstable();
+
+        HeldInputs {
+            keys: sorted(&self.pressed_keys),
+            buttons: sorted(&self.pressed_buttons),
+            modifiers: self.current_modifiers,
+            physical_map,
+            focused: self.focused,
+        }
+    }
+
+    /// replaces everything that's being held down with `held`, as if nothing
+    /// had been pressed or released this frame.
+    pub fn set_held(&mut self, held: &HeldInputs) {
+        self.pressed_keys = held.keys.iter().copied().collect();
+        self.pressed_buttons = held.buttons.iter().copied().collect();
+        self.current_modifiers = held.modifiers;
+        self.physical_map = held.physical_map.iter().copied().collect();
+        self.focused = held.focused;
+
+        self.rising_keys.clear();
+        self.falling_keys.clear();
+        self.rising_buttons.clear();
+        self.falling_buttons.clear();
+        self.cursor_dx = 0.0;
+        self.cursor_dy = 0.0;
+    }
+}
+
 pub struct KeyRef<'s> {
     state: &'s InputState,
     key: DigitalInput,
@@ -211,6 +377,7 @@ pub mod keys {
     pub const RIGHT: u32 = 0x20;
     pub const UP: u32 = 0x39;
     pub const DOWN: u32 = 0x2A;
+    pub const ZOOM: u32 = 0x2E;
 
     pub const ARROW_UP: VirtualKeyCode = VirtualKeyCode::Up;
     pub const ARROW_DOWN: VirtualKeyCode = VirtualKeyCode::Down;
@@ -240,6 +407,10 @@ fn notify_keyboard_input(state: &mut InputState, input: KeyboardInput) {
         }
     }
 
+    if state.keyboard_captured {
+        return;
+    }
+
     // update rising/falling sets
     if pressed && state.pressed_keys.insert(input.scancode) {
         state.rising_keys.insert(input.scancode);
@@ -253,7 +424,24 @@ fn notify_mouse_motion(state: &mut InputState, dx: f64, dy: f64) {
     state.cursor_dy += dy as f32;
 }
 
-fn notify_mouse_scroll(_state: &mut InputState, _delta: MouseScrollDelta) {}
+/// how many pixels of scrolling on a touchpad count as scrolling one line with
+/// a mouse wheel.
+const PIXELS_PER_LINE: f64 = 20.0;
+
+fn notify_cursor_moved(state: &mut InputState, position: PhysicalPosition<f64>) {
+    if let Some(last) = state.last_cursor_pos {
+        state.window_dx += (position.x - last.x) as f32;
+        state.window_dy += (position.y - last.y) as f32;
+    }
+    state.last_cursor_pos = Some(position);
+}
+
+fn notify_mouse_scroll(state: &mut InputState, delta: MouseScrollDelta) {
+    state.scroll += match delta {
+        MouseScrollDelta::LineDelta(_, y) => y,
+        MouseScrollDelta::PixelDelta(pos) => (pos.y / PIXELS_PER_LINE) as f32,
+    };
+}
 
 fn notify_mouse_click(state: &mut InputState, button: ButtonId, elem_state: ElementState) {
     let pressed = matches!(elem_state, ElementState::Pressed);
@@ -287,6 +475,9 @@ pub fn input_compiler(
 
     ctx.cursor_dx = 0.0;
     ctx.cursor_dy = 0.0;
+    ctx.window_dx = 0.0;
+    ctx.window_dy = 0.0;
+    ctx.scroll = 0.0;
 
     for event in device_events.iter() {
         // do this before we discard events so we can refocus the window
@@ -299,6 +490,9 @@ pub fn input_compiler(
         }
 
         match event {
+            &RawInputEvent::Window(_, WindowEvent::CursorMoved { position, .. }) => {
+                notify_cursor_moved(&mut ctx, position)
+            }
             &RawInputEvent::Device(_, DeviceEvent::MouseMotion { delta }) => {
                 notify_mouse_motion(&mut ctx, delta.0, delta.1)
             }
@@ -316,7 +510,26 @@ pub fn input_compiler(
         }
     }
 
+    let state = &mut *ctx;
+    let movement = match state.mouse.raw_input {
+        true => nalgebra::vector![state.cursor_dx, state.cursor_dy],
+        false => nalgebra::vector![state.window_dx, state.window_dy],
+    };
+    state.look_delta = state.look.apply(&state.mouse, movement);
+
     let window = display.gl_window();
+
+    // without raw input, the cursor would eventually run into the edge of the
+    // window and stop moving, so it's put back in the middle every frame.
+    if !ctx.mouse.raw_input && ctx.cursor_currently_grabbed {
+        let size = window.window().inner_size();
+        let center = PhysicalPosition::new(size.width as f64 / 2.0, size.height as f64 / 2.0);
+        match window.window().set_cursor_position(center) {
+            Ok(()) => ctx.last_cursor_pos = Some(center),
+            Err(err) => log::debug!("failed to move the cursor back: {}", err),
+        }
+    }
+
     let should_grab = ctx.cursor_should_be_grabbed.load(Ordering::SeqCst);
     let should_hide = ctx.cursor_should_be_hidden.load(Ordering::SeqCst);
 
@@ -331,19 +544,88 @@ pub fn input_compiler(
     }
 }
 
+/// saves the mouse settings whenever they change.
+fn save_mouse_settings(
+    input: Res<InputState>,
+    mut saved: Local<Option<MouseSettings>>,
+    mut settings_file: ResMut<SettingsFile>,
+) {
+    match saved.replace(input.mouse.clone()) {
+        // these are the settings that were loaded from the file
+        None => return,
+        Some(previous) if previous == input.mouse => return,
+        Some(_) => {}
+    }
+
+    let mouse_settings = input.mouse.clone();
+    if let Err(err) = settings_file.update(|file| file.mouse = mouse_settings) {
+        log::error!("failed to save mouse settings: {}", err);
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum RawInputEvent {
     Window(WindowId, WindowEvent<'static>),
     Device(DeviceId, DeviceEvent),
 }
 
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub struct InputLabel;
+
 #[derive(Debug, Default)]
 pub struct InputPlugin {}
 
 impl Plugin for InputPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.init_resource::<InputState>();
+        let mouse = app
+            .world()
+            .get_resource::<SettingsFile>()
+            .expect("`InputPlugin` added before `SettingsFile` was inserted!")
+            .settings()
+            .mouse
+            .clone();
+
+        app.insert_resource(InputState {
+            mouse,
+            ..Default::default()
+        });
         app.add_event::<RawInputEvent>();
-        app.add_system_to_stage(CoreStage::PreUpdate, input_compiler.system());
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            input_compiler.system().label(InputLabel),
+        );
+        app.add_system(save_mouse_settings.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::vector;
+
+    #[test]
+    fn look_filter_applies_mouse_settings() {
+        let mut settings = MouseSettings {
+            sensitivity: 0.5,
+            invert_y: true,
+            ..Default::default()
+        };
+        let mut filter = LookFilter::default();
+        let look = filter.apply(&settings, vector![4.0, 2.0]);
+        assert_eq!(look, vector![2.0, -1.0]);
+
+        // moving twice as far in one frame turns more than twice as far
+        settings.invert_y = false;
+        settings.acceleration = 1.0;
+        let slow = filter.apply(&settings, vector![10.0, 0.0]).x;
+        let fast = filter.apply(&settings, vector![20.0, 0.0]).x;
+        assert!(fast > 2.0 * slow);
+
+        // smoothing carries some of the last frame over into this one
+        settings.acceleration = 0.0;
+        settings.smoothing = 0.5;
+        let mut filter = LookFilter::default();
+        assert_eq!(filter.apply(&settings, vector![4.0, 0.0]).x, 1.0);
+        assert_eq!(filter.apply(&settings, vector![0.0, 0.0]).x, 0.5);
     }
 }
@@ -0,0 +1,347 @@
+//! the chunk inspector, a debug panel for looking inside the section around a
+//! block.
+//!
+//! `F10` opens it on the block being looked at, and closes it again. it keeps
+//! showing that block until it's closed, even after looking away. `Up`/`Down`
+//! pick one of its buttons and `Enter` presses it: one meshes the section again,
+//! and the other dumps it to a file in `dumps` that can be attached to bug
+//! reports.
+
+use crate::{
+    client::{
+        camera::CurrentCamera,
+        input::{keys, InputState},
+        render::{
+            font,
+            mesher::{
+                tracker::{MeshStatus, MeshTracker},
+                HasTerrainMesh, MesherContext,
+            },
+            renderer::ImmediateUi,
+        },
+    },
+    make_ray,
+};
+use glium::glutin::event::VirtualKeyCode;
+use nalgebra::Vector3;
+use notcraft_common::{
+    codec::{
+        encode::{encode_node, Encode, Encoder},
+        NodeKind,
+    },
+    lang::tr,
+    notification::Notify,
+    prelude::*,
+    targeting::TargetingConfig,
+    world::{
+        chunk::{
+            block_to_index, ChunkAccess, ChunkData, ChunkSection, ChunkSectionPos,
+            CompactedChunkSection,
+        },
+        registry::{BlockId, BlockRegistry},
+        BlockPos, VoxelWorld,
+    },
+};
+use std::{
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// where section dumps are saved, relative to the directory the game was
+/// started in.
+const DUMP_DIR: &str = "dumps";
+
+const LEFT: f32 = 10.0;
+const TOP: f32 = 90.0;
+const MIN_WIDTH: f32 = 100.0;
+const LINE_HEIGHT: f32 = 8.0;
+const BUTTON_HEIGHT: f32 = 10.0;
+const PADDING: f32 = 4.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum InspectorButton {
+    Remesh,
+    Dump,
+}
+
+const BUTTONS: [InspectorButton; 2] = [InspectorButton::Remesh, InspectorButton::Dump];
+
+impl InspectorButton {
+    fn label(self) -> String {
+        match self {
+            InspectorButton::Remesh => tr!("inspector.remesh"),
+            InspectorButton::Dump => tr!("inspector.dump"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct ChunkInspector {
+    /// the block being inspected, or `None` while the inspector is closed.
+    pub target: Option<BlockPos>,
+    selected: usize,
+}
+
+/// a block's name, along with the name of its state if it has more than one.
+fn block_label(registry: &BlockRegistry, id: BlockId) -> String {
+    let block = registry.get(id);
+    match block.state_name() {
+        Some(state) => format!("{} [{}]", block.name(), state),
+        None => block.name().into(),
+    }
+}
+
+/// a section, along with the names of the blocks its IDs stand for, so that
+/// dumps can be made sense of without the registry of whoever made them.
+struct SectionDump<'a> {
+    section: &'a ChunkSection,
+    registry: &'a BlockRegistry,
+}
+
+impl<W: Write> Encode<W> for SectionDump<'_> {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode_map(|mut map| {
+            map.entry("section").encode(self.section)?;
+            // indexed by block ID
+            let names = self.registry.ids().map(|id| block_label(self.registry, id));
+            let names = names.collect::<Vec<_>>();
+            map.entry("block-names")
+                .encode_verbatim_list(names.iter())?;
+            Ok(())
+        })
+    }
+}
+
+fn dump_section(section: &ChunkSection, registry: &BlockRegistry) -> Result<PathBuf> {
+    std::fs::create_dir_all(DUMP_DIR)?;
+    let pos = section.pos();
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let name = format!("section_{}_{}_{}_{}.bin", pos.x, pos.y, pos.z, timestamp);
+    let path = Path::new(DUMP_DIR).join(name);
+
+    let mut file = BufWriter::new(std::fs::File::create(&path)?);
+    encode_node(&mut file, &SectionDump { section, registry })?;
+    file.flush()?;
+    Ok(path)
+}
+
+fn toggle_inspector(
+    input: Res<InputState>,
+    camera: CurrentCamera,
+    targeting: Res<TargetingConfig>,
+    mut access: ResMut<ChunkAccess>,
+    mut inspector: ResMut<ChunkInspector>,
+    mut notify_events: EventWriter<Notify>,
+) {
+    if !input.key(VirtualKeyCode::F10).is_rising() {
+        return;
+    }
+    if inspector.target.take().is_some() {
+        return;
+    }
+
+    let hit = camera
+        .transform()
+        .map(|transform| make_ray(transform, &-Vector3::z()))
+        .and_then(|ray| targeting.target(&mut access, ray));
+    match hit {
+        Some(hit) => {
+            inspector.target = Some(hit.pos);
+            inspector.selected = 0;
+        }
+        None => notify_events.send(Notify::warning(tr!("notify.inspector_no_target"))),
+    }
+}
+
+fn inspector_input(
+    input: Res<InputState>,
+    mut inspector: ResMut<ChunkInspector>,
+    voxel_world: Res<Arc<VoxelWorld>>,
+    ctx: Res<MesherContext>,
+    mut tracker: ResMut<MeshTracker>,
+    mut notify_events: EventWriter<Notify>,
+) {
+    let target = match inspector.target {
+        Some(target) => target,
+        None => return,
+    };
+
+    if input.key(keys::ARROW_UP).is_rising() {
+        inspector.selected = (inspector.selected + BUTTONS.len() - 1) % BUTTONS.len();
+    }
+    if input.key(keys::ARROW_DOWN).is_rising() {
+        inspector.selected = (inspector.selected + 1) % BUTTONS.len();
+    }
+    if !input.key(VirtualKeyCode::Return).is_rising() {
+        return;
+    }
+
+    let pos = ChunkSectionPos::from(target);
+    match BUTTONS[inspector.selected] {
+        InspectorButton::Remesh => {
+            // the cached mesh might be what's wrong with it
+            ctx.invalidate_cached(pos);
+            tracker.request_mesh(pos);
+            log::info!("remeshing section {:?}", pos);
+        }
+        InspectorButton::Dump => {
+            let dumped = match voxel_world.section(pos) {
+                Some(section) => dump_section(&section, &voxel_world.registry),
+                None => Err(anyhow!(tr!("inspector.unloaded"))),
+            };
+            match dumped {
+                Ok(path) => {
+                    log::info!("dumped section {:?} to '{}'", pos, path.display());
+                    let message = tr!("notify.section_dumped", path.display());
+                    notify_events.send(Notify::info(message));
+                }
+                Err(err) => {
+                    log::error!("failed to dump section {:?}: {}", pos, err);
+                    let message = tr!("notify.section_dump_failed", err);
+                    notify_events.send(Notify::error(message));
+                }
+            }
+        }
+    }
+}
+
+fn storage_label<T>(data: &ChunkData<T>) -> String {
+    match data {
+        ChunkData::Homogeneous(_) => tr!("inspector.homogeneous"),
+        ChunkData::Array(_) => tr!("inspector.array"),
+    }
+}
+
+/// like [`storage_label`], but also says how well the blocks compact, which is
+/// how they're kept once the section is unloaded.
+fn block_storage_label(blocks: &ChunkData<BlockId>) -> String {
+    match blocks {
+        ChunkData::Homogeneous(_) => storage_label(blocks),
+        ChunkData::Array(_) => {
+            let runs = CompactedChunkSection::compact(blocks).runs();
+            tr!("inspector.compacted", storage_label(blocks), runs)
+        }
+    }
+}
+
+fn mesh_label(tracker: &MeshTracker, pos: ChunkSectionPos, has_mesh: bool) -> String {
+    let status = match tracker.status(pos) {
+        MeshStatus::Untracked => tr!("inspector.mesh.untracked"),
+        MeshStatus::WaitingOnNeighbors(count) => tr!("inspector.mesh.waiting", count),
+        MeshStatus::Queued => tr!("inspector.mesh.queued"),
+        MeshStatus::Meshed => tr!("inspector.mesh.meshed"),
+    };
+    let entity = match (tracker.terrain_entity(pos), has_mesh) {
+        (Some(entity), true) => tr!("inspector.entity.mesh", entity.id()),
+        (Some(entity), false) => tr!("inspector.entity.no_mesh", entity.id()),
+        (None, _) => tr!("inspector.entity.none"),
+    };
+    tr!("inspector.mesh", status, entity)
+}
+
+/// everything the inspector knows about the section around `target`, one line
+/// at a time.
+fn inspector_lines(
+    target: BlockPos,
+    access: &mut ChunkAccess,
+    tracker: &MeshTracker,
+    has_mesh: bool,
+) -> Vec<String> {
+    let pos = ChunkSectionPos::from(target);
+    let column = pos.column();
+    let mut lines = vec![
+        tr!("inspector.block", target.x, target.y, target.z),
+        tr!("inspector.section", pos.x, pos.y, pos.z, column.x, column.z),
+    ];
+
+    let registry = access.registry().clone();
+    match access.section(pos) {
+        Some(section) => {
+            let index = block_to_index(target);
+            let blocks = section.blocks();
+            lines.push(tr!("inspector.blocks", block_storage_label(blocks)));
+            lines.push(tr!(
+                "inspector.light_storage",
+                storage_label(section.light())
+            ));
+
+            let id = blocks.get(index);
+            lines.push(tr!(
+                "inspector.target",
+                block_label(&registry, id),
+                id.raw()
+            ));
+            let light = section.light().get(index);
+            let [red, green, blue] = light.block();
+            lines.push(tr!("inspector.light", light.sky(), red, green, blue));
+        }
+        None => lines.push(tr!("inspector.unloaded")),
+    }
+
+    lines.push(mesh_label(tracker, pos, has_mesh));
+    lines
+}
+
+fn draw_inspector(
+    inspector: Res<ChunkInspector>,
+    tracker: Res<MeshTracker>,
+    meshes: Query<(), With<HasTerrainMesh>>,
+    mut access: ResMut<ChunkAccess>,
+    mut ui: ResMut<ImmediateUi>,
+) {
+    let target = match inspector.target {
+        Some(target) => target,
+        None => return,
+    };
+
+    let pos = ChunkSectionPos::from(target);
+    let entity = tracker.terrain_entity(pos);
+    let has_mesh = entity.is_some_and(|entity| meshes.get(entity).is_ok());
+    let lines = inspector_lines(target, &mut access, &tracker, has_mesh);
+
+    let text_height = lines.len() as f32 * LINE_HEIGHT;
+    let buttons_height = BUTTONS.len() as f32 * (BUTTON_HEIGHT + 2.0);
+    let bottom = TOP - text_height - buttons_height - 2.0 * PADDING;
+    let longest = lines.iter().map(|line| line.chars().count()).max();
+    let text_width = longest.unwrap_or(0) as f32 * font::GLYPH_ADVANCE;
+    let right = LEFT + f32::max(MIN_WIDTH, text_width + 2.0 * PADDING);
+    ui.rect([LEFT, bottom], [right, TOP], [0.05, 0.05, 0.05, 0.8]);
+
+    let mut y = TOP - PADDING - LINE_HEIGHT;
+    for line in lines.iter() {
+        ui.text([LEFT + PADDING, y], line, [1.0; 4]);
+        y -= LINE_HEIGHT;
+    }
+
+    for (idx, button) in BUTTONS.iter().enumerate() {
+        let top = y + LINE_HEIGHT - 2.0 - idx as f32 * (BUTTON_HEIGHT + 2.0);
+        let color = match idx == inspector.selected {
+            true => [0.3, 0.45, 0.6, 0.9],
+            false => [0.2, 0.2, 0.2, 0.9],
+        };
+        let [button_left, button_right] = [LEFT + PADDING, right - PADDING];
+        ui.rect(
+            [button_left, top - BUTTON_HEIGHT],
+            [button_right, top],
+            color,
+        );
+        let text_y = top - BUTTON_HEIGHT + 2.0;
+        ui.text([button_left + 2.0, text_y], &button.label(), [1.0; 4]);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ChunkInspectorPlugin {}
+
+impl Plugin for ChunkInspectorPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ChunkInspector>();
+        app.add_system(toggle_inspector.system());
+        app.add_system(inspector_input.system());
+        app.add_system(draw_inspector.system());
+    }
+}
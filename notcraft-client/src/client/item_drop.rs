@@ -0,0 +1,49 @@
+//! dropped items look like small copies of the block they are.
+
+use crate::client::{
+    crafting::item_icon,
+    render::{
+        mesher::{generation::mesh_box, TerrainMesh},
+        renderer::{MeshHandle, RenderMeshComponent, SharedMeshContext},
+    },
+};
+use nalgebra::Vector3;
+use notcraft_common::{
+    item_drop::{DroppedItem, ITEM_DROP_SIZE},
+    prelude::*,
+    world::registry::BlockRegistry,
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// drops of the same item share a mesh.
+#[derive(Default)]
+struct ItemDropMeshes(HashMap<String, MeshHandle<TerrainMesh>>);
+
+fn attach_item_drop_meshes(
+    mut cmd: Commands,
+    mut meshes: Local<ItemDropMeshes>,
+    registry: Res<Arc<BlockRegistry>>,
+    mesh_context: Res<Arc<SharedMeshContext<TerrainMesh>>>,
+    query: Query<(Entity, &DroppedItem), Added<DroppedItem>>,
+) {
+    query.for_each(|(entity, dropped)| {
+        let item = &dropped.stack.item;
+        let handle = meshes.0.entry(item.clone()).or_insert_with(|| {
+            let tex_id = item_icon(&registry, item).unwrap_or_default();
+            let size = (16.0 * ITEM_DROP_SIZE).round() as u16;
+            mesh_context.upload(mesh_box(Vector3::repeat(size), tex_id))
+        });
+
+        cmd.entity(entity)
+            .insert(RenderMeshComponent::new(handle.clone()));
+    });
+}
+
+#[derive(Debug, Default)]
+pub struct ItemDropRenderPlugin {}
+
+impl Plugin for ItemDropRenderPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(attach_item_drop_meshes.system());
+    }
+}
@@ -1,7 +1,7 @@
 use crate::util::ChannelPair;
 use glium::{program::SourceCode, texture::TextureCreationError, Display, Program};
-use image::{GenericImageView, ImageError, RgbaImage};
-use notcraft_common::prelude::*;
+use image::{GenericImageView, ImageError, Rgba, RgbaImage};
+use notcraft_common::{prelude::*, world::registry::BlockRegistry};
 use std::{
     collections::{HashMap, HashSet},
     fs::File,
@@ -29,6 +29,8 @@ pub enum TextureLoadError {
     Image(ImageError),
     Texture(TextureCreationError),
     MismatchedDimensions(HashSet<(u32, u32)>),
+    /// an animated texture whose height isn't a multiple of its frame count.
+    UnevenFrames(PathBuf, u32),
 }
 
 impl std::error::Error for TextureLoadError {}
@@ -45,6 +47,12 @@ impl std::fmt::Display for TextureLoadError {
                     write!(f, "({}, {}), ", x, y)?;
                 }
             }
+            TextureLoadError::UnevenFrames(path, frames) => write!(
+                f,
+                "'{}' can't be split into {} frames",
+                path.display(),
+                frames
+            )?,
         }
         Ok(())
     }
@@ -59,39 +67,85 @@ pub struct BlockTextures {
     pub width: u32,
     pub height: u32,
     pub unknown_texture: Arc<RgbaImage>,
-    pub block_textures: HashMap<PathBuf, Arc<RgbaImage>>,
+    /// the frames of each texture, in order. textures that aren't animated
+    /// have just the one.
+    pub block_textures: HashMap<PathBuf, Vec<Arc<RgbaImage>>>,
+}
+
+impl BlockTextures {
+    /// a single pixel stand-in for every texture in the registry, for the
+    /// renderer to use until the real textures are loaded.
+    pub fn placeholder(registry: &BlockRegistry) -> Self {
+        let unknown_texture = Arc::new(RgbaImage::from_pixel(1, 1, Rgba([255, 0, 255, 255])));
+        let block_textures = registry
+            .texture_paths()
+            .map(|path| {
+                let animation = registry.texture_animation(path);
+                let frames = animation.map_or(1, |anim| anim.frames as usize);
+                (path.to_owned(), vec![Arc::clone(&unknown_texture); frames])
+            })
+            .collect();
+
+        Self {
+            width: 1,
+            height: 1,
+            unknown_texture,
+            block_textures,
+        }
+    }
 }
 
 struct BlockTextureLoadContext<'env> {
-    base_path: &'env Path,
+    /// the directories that textures are looked for in, most preferred first.
+    layers: &'env [PathBuf],
     found_dimensions: HashSet<(u32, u32)>,
 }
 
 impl<'env> BlockTextureLoadContext<'env> {
-    fn new(base_path: &'env Path) -> Self {
+    fn new(layers: &'env [PathBuf]) -> Self {
         Self {
-            base_path,
+            layers,
             found_dimensions: Default::default(),
         }
     }
 
-    fn load(&mut self, path: &Path) -> Result<Option<RgbaImage>, TextureLoadError> {
-        let texture_path = self.base_path.join(path);
-        log::trace!("loading block texture from {}", texture_path.display());
-        let image = match image::open(&texture_path) {
-            Ok(image) => image,
-            Err(ImageError::IoError(err)) if err.kind() == ErrorKind::NotFound => {
-                log::warn!(
-                    "block texture '{}' was not found in {}!",
-                    path.display(),
-                    texture_path.display()
-                );
-                return Ok(None);
+    fn load(
+        &mut self,
+        path: &Path,
+        frames: u32,
+    ) -> Result<Option<Vec<RgbaImage>>, TextureLoadError> {
+        for layer in self.layers {
+            let texture_path = layer.join(path);
+            log::trace!("loading block texture from {}", texture_path.display());
+            match image::open(&texture_path) {
+                Ok(image) => {
+                    let (width, height) = image.dimensions();
+                    if height % frames != 0 {
+                        return Err(TextureLoadError::UnevenFrames(path.into(), frames));
+                    }
+                    let frame_height = height / frames;
+                    self.found_dimensions.insert((width, frame_height));
+
+                    let image = image.to_rgba();
+                    let frames = (0..frames)
+                        .map(|frame| {
+                            let y = frame * frame_height;
+                            image.view(0, y, width, frame_height).to_image()
+                        })
+                        .collect();
+                    return Ok(Some(frames));
+                }
+                Err(ImageError::IoError(err)) if err.kind() == ErrorKind::NotFound => {}
+                Err(other) => return Err(other.into()),
             }
-            Err(other) => return Err(other.into()),
-        };
-        self.found_dimensions.insert(image.dimensions());
-        Ok(Some(image.to_rgba()))
+        }
+
+        log::warn!(
+            "block texture '{}' was not found in any of {:?}!",
+            path.display(),
+            self.layers
+        );
+        Ok(None)
     }
 
     fn dimensions(&self) -> Option<(u32, u32)> {
@@ -103,26 +157,24 @@ impl<'env> BlockTextureLoadContext<'env> {
     }
 }
 
-pub fn load_block_textures<'a, P, I>(
-    base_path: P,
-    paths: I,
-) -> Result<BlockTextures, TextureLoadError>
-where
-    P: AsRef<Path>,
-    I: IntoIterator<Item = &'a Path>,
-{
-    let mut ctx = BlockTextureLoadContext::new(base_path.as_ref());
+/// loads every texture in the registry, looking in each of `layers` in turn,
+/// so that textures in earlier layers override ones in later layers.
+pub fn load_block_textures(
+    layers: &[PathBuf],
+    registry: &BlockRegistry,
+) -> Result<BlockTextures, TextureLoadError> {
+    let mut ctx = BlockTextureLoadContext::new(layers);
 
-    let paths = paths.into_iter();
-
-    let unknown_texture = Arc::new(ctx.load(Path::new("unknown.png"))?.unwrap());
+    let unknown_texture = ctx.load(Path::new("unknown.png"), 1)?.unwrap().remove(0);
+    let unknown_texture = Arc::new(unknown_texture);
 
     let mut block_textures = HashMap::new();
-    for path in paths {
-        let texture = ctx
-            .load(path)?
-            .map(Arc::new)
-            .unwrap_or_else(|| Arc::clone(&unknown_texture));
+    for path in registry.texture_paths() {
+        let frames = registry.texture_animation(path).map_or(1, |anim| anim.frames);
+        let texture = match ctx.load(path, frames)? {
+            Some(frames) => frames.into_iter().map(Arc::new).collect(),
+            None => vec![Arc::clone(&unknown_texture); frames as usize],
+        };
         block_textures.insert(path.to_owned(), texture);
     }
 
@@ -794,7 +846,9 @@ pub fn file_watcher(watcher: Res<FileWatcher>, mut watcher_events: EventWriter<n
 pub fn hot_reload_shaders(
     mut shaders: NonSendMut<ShaderLoaderState>,
     mut watcher_events: EventReader<notify::Event>,
+    mut notify_events: EventWriter<notcraft_common::notification::Notify>,
 ) -> Result<()> {
+    use notcraft_common::{lang::tr, notification::Notify};
     use notify::{event::ModifyKind, EventKind};
 
     for event in watcher_events.iter() {
@@ -808,6 +862,8 @@ pub fn hot_reload_shaders(
                         let abs_path = path.canonicalize()?;
                         if let Err(err) = notify_shader_modified(&mut shaders, &abs_path) {
                             log::error!("shader hot-reload failed: {}", err);
+                            let message = tr!("notify.shader_reload_failed", err);
+                            notify_events.send(Notify::error(message));
                         }
                     }
                 }
@@ -0,0 +1,98 @@
+//! the loading screen, which is shown while the game's assets are loaded, and
+//! hands over to the main menu once they're all ready. see
+//! [`notcraft_common::assets`] for how loaders report their progress.
+//!
+//! assets that fail to load are listed under the progress bar, and can be
+//! loaded again with `Enter`, or the game can be quit with `Escape`.
+
+use crate::client::{input::InputState, render::renderer::ImmediateUi};
+use bevy_app::AppExit;
+use glium::glutin::event::VirtualKeyCode;
+use notcraft_common::{
+    assets::{AssetLoadProgress, REGISTRY, SHADERS, SOUNDS, TEXTURES},
+    game_state::GameState,
+    lang::tr,
+    prelude::*,
+};
+
+const BAR_WIDTH: f32 = 160.0;
+const BAR_HEIGHT: f32 = 6.0;
+const LINE_HEIGHT: f32 = 8.0;
+
+fn finish_loading(progress: Res<AssetLoadProgress>, mut state: ResMut<GameState>) {
+    if *state == GameState::Loading && progress.is_ready() {
+        log::info!("finished loading assets");
+        *state = GameState::MainMenu;
+    }
+}
+
+fn loading_input(
+    input: Res<InputState>,
+    state: Res<GameState>,
+    mut progress: ResMut<AssetLoadProgress>,
+    mut exit_events: EventWriter<AppExit>,
+) {
+    if *state != GameState::Loading || progress.failures().next().is_none() {
+        return;
+    }
+
+    if input.key(VirtualKeyCode::Return).is_rising() {
+        progress.retry_failed();
+    } else if input.key(VirtualKeyCode::Escape).is_rising() {
+        exit_events.send(AppExit);
+    }
+}
+
+fn draw_loading_screen(
+    state: Res<GameState>,
+    progress: Res<AssetLoadProgress>,
+    mut ui: ResMut<ImmediateUi>,
+) {
+    if *state != GameState::Loading {
+        return;
+    }
+
+    // nothing behind the loading screen is worth looking at yet
+    ui.rect([-1000.0, -1000.0], [1000.0, 1000.0], [0.08, 0.08, 0.1, 1.0]);
+
+    let [left, right] = [-BAR_WIDTH / 2.0, BAR_WIDTH / 2.0];
+    let [bottom, top] = [-BAR_HEIGHT / 2.0, BAR_HEIGHT / 2.0];
+    let border = [left - 1.0, bottom - 1.0];
+    ui.rect(border, [right + 1.0, top + 1.0], [1.0; 4]);
+    ui.rect([left, bottom], [right, top], [0.0, 0.0, 0.0, 1.0]);
+    let filled = left + BAR_WIDTH * progress.fraction();
+    ui.rect([left, bottom], [filled, top], [0.25, 0.55, 0.3, 1.0]);
+
+    let mut line = bottom - 2.0 * LINE_HEIGHT;
+    let mut failed = false;
+    for (name, err) in progress.failures() {
+        let text = tr!("loading.failed", name, err);
+        ui.text([left, line], &text, [1.0, 0.4, 0.4, 1.0]);
+        line -= LINE_HEIGHT;
+        failed = true;
+    }
+    if failed {
+        ui.text([left, line - LINE_HEIGHT], &tr!("loading.retry"), [1.0; 4]);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LoadingScreenPlugin {}
+
+impl Plugin for LoadingScreenPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // the registry and the shaders are loaded before there's anything to
+        // draw the loading screen with, but they're listed anyways, so that
+        // the bar shows how much really had to be loaded.
+        let mut progress = AssetLoadProgress::default();
+        for name in [REGISTRY, SHADERS, TEXTURES, SOUNDS] {
+            progress.register(name);
+        }
+        app.insert_resource(progress);
+        app.insert_resource(GameState::Loading);
+
+        app.add_system(loading_input.system());
+        app.add_system(finish_loading.system());
+        app.add_system_to_stage(CoreStage::PostUpdate, draw_loading_screen.system());
+    }
+}
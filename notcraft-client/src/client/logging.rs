@@ -0,0 +1,423 @@
+//! the game's logger.
+//!
+//! every entry is printed to the terminal and kept in a small ring buffer, so
+//! that recent entries can be looked at with the `/log` console command. they
+//! can also be written to a log file, which gets rotated out at the start of
+//! every run and whenever it grows too big, so the log of a run that crashed is
+//! still around after starting the game back up.
+//!
+//! which entries are logged is decided per module, from the `logging` section
+//! of the settings file. `RUST_LOG` can still be used to override those levels,
+//! like `RUST_LOG=warn,notcraft_common::world=debug`.
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use notcraft_common::prelude::*;
+use parking_lot::{Mutex, RwLock};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+// how many of the most recent entries are kept around for `/log`.
+const RECENT_ENTRY_COUNT: usize = 512;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingSettings {
+    /// the level for everything that isn't mentioned in `modules`.
+    pub level: LevelFilter,
+    /// levels for specific modules, like `notcraft_common::world`. when more
+    /// than one of these matches, the most specific one is used.
+    pub modules: BTreeMap<String, LevelFilter>,
+    /// whether to write the log to files in the log directory too.
+    pub write_to_file: bool,
+    /// the size, in bytes, that the log file can grow to before it's rotated.
+    pub max_file_size: u64,
+    /// how many old log files are kept around, not counting the current one.
+    pub max_old_files: usize,
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        let mut modules = BTreeMap::new();
+        modules.insert("notcraft".into(), LevelFilter::Info);
+        modules.insert("notcraft_common".into(), LevelFilter::Info);
+
+        Self {
+            level: LevelFilter::Warn,
+            modules,
+            write_to_file: true,
+            max_file_size: 4 * 1024 * 1024,
+            max_old_files: 4,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+    /// how long after the game started that this was logged.
+    pub time: Duration,
+    pub level: Level,
+    /// the module that logged this, unless a different target was given.
+    pub target: String,
+    /// the name of the thread this was logged from, if it has one.
+    pub thread: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = self.time.as_secs_f64();
+        write!(f, "[{:>10.3}s {:<5} {}", time, self.level, self.target)?;
+        match &self.thread {
+            Some(thread) => write!(f, " ({})] {}", thread, self.message),
+            None => write!(f, "] {}", self.message),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct LevelFilters {
+    default: LevelFilter,
+    // sorted from the longest module path to the shortest, so that the first
+    // match is always the most specific one.
+    modules: Vec<(String, LevelFilter)>,
+}
+
+fn is_in_module(target: &str, module: &str) -> bool {
+    match target.strip_prefix(module) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+impl LevelFilters {
+    fn new(settings: &LoggingSettings) -> Self {
+        let mut filters = Self {
+            default: settings.level,
+            modules: vec![],
+        };
+        for (module, &level) in settings.modules.iter() {
+            filters.set(module, level);
+        }
+        filters
+    }
+
+    fn set(&mut self, module: &str, level: LevelFilter) {
+        match self.modules.iter_mut().find(|(name, _)| name == module) {
+            Some((_, old_level)) => *old_level = level,
+            None => {
+                self.modules.push((module.into(), level));
+                self.modules
+                    .sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then(a.cmp(b)));
+            }
+        }
+    }
+
+    /// applies directives like `warn,notcraft_common::world=debug`, which is
+    /// the format `RUST_LOG` has always used. a directive without a module
+    /// changes the default level.
+    fn apply_directives(&mut self, directives: &str) -> Result<()> {
+        for directive in directives.split(',').map(str::trim) {
+            match directive.split_once('=') {
+                _ if directive.is_empty() => {}
+                Some((module, level)) => match level.parse() {
+                    Ok(level) => self.set(module, level),
+                    Err(_) => bail!("unknown log level '{}' for '{}'", level, module),
+                },
+                // `RUST_LOG=notcraft_common` turns on everything for that module
+                None => match directive.parse() {
+                    Ok(level) => self.default = level,
+                    Err(_) => self.set(directive, LevelFilter::Trace),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .find(|(module, _)| is_in_module(target, module))
+            .map_or(self.default, |&(_, level)| level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        let modules = self.modules.iter().map(|&(_, level)| level);
+        modules.fold(self.default, Ord::max)
+    }
+}
+
+struct LogFile {
+    dir: PathBuf,
+    max_size: u64,
+    max_old_files: usize,
+    writer: BufWriter<File>,
+    size: u64,
+}
+
+impl LogFile {
+    fn path(dir: &Path, age: usize) -> PathBuf {
+        match age {
+            0 => dir.join("notcraft.log"),
+            _ => dir.join(format!("notcraft.{}.log", age)),
+        }
+    }
+
+    /// moves every log file back by one, so that `notcraft.log` becomes
+    /// `notcraft.1.log` and so on, and deletes the oldest one.
+    fn rotate(dir: &Path, max_old_files: usize) -> std::io::Result<()> {
+        let oldest = Self::path(dir, max_old_files);
+        if oldest.exists() {
+            std::fs::remove_file(oldest)?;
+        }
+        for age in (0..max_old_files).rev() {
+            let path = Self::path(dir, age);
+            if path.exists() {
+                std::fs::rename(path, Self::path(dir, age + 1))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn open(dir: &Path, settings: &LoggingSettings) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Self::rotate(dir, settings.max_old_files)?;
+        let file = File::create(Self::path(dir, 0))?;
+
+        Ok(Self {
+            dir: dir.into(),
+            max_size: settings.max_file_size,
+            max_old_files: settings.max_old_files,
+            writer: BufWriter::new(file),
+            size: 0,
+        })
+    }
+
+    fn write(&mut self, entry: &LogEntry) -> std::io::Result<()> {
+        let line = format!("{}\n", entry);
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.writer.flush()?;
+            Self::rotate(&self.dir, self.max_old_files)?;
+            self.writer = BufWriter::new(File::create(Self::path(&self.dir, 0))?);
+            self.size = 0;
+        }
+
+        self.writer.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+
+        // anything important is written out right away, in case the game is
+        // about to crash.
+        if entry.level <= Level::Warn {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+struct Logger {
+    start: Instant,
+    filters: RwLock<LevelFilters>,
+    recent: Mutex<VecDeque<LogEntry>>,
+    file: Mutex<Option<LogFile>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filters.read().level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let entry = LogEntry {
+            time: self.start.elapsed(),
+            level: record.level(),
+            target: record.target().into(),
+            thread: std::thread::current().name().map(Into::into),
+            message: record.args().to_string(),
+        };
+
+        eprintln!("{}", entry);
+
+        let mut file = self.file.lock();
+        if let Some(Err(err)) = file.as_mut().map(|file| file.write(&entry)) {
+            // logging this would just try to write to the broken file again
+            eprintln!("stopped writing to the log file: {}", err);
+            *file = None;
+        }
+        drop(file);
+
+        let mut recent = self.recent.lock();
+        if recent.len() == RECENT_ENTRY_COUNT {
+            recent.pop_front();
+        }
+        recent.push_back(entry);
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.file.lock().as_mut() {
+            let _ = file.writer.flush();
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref LOGGER: Logger = Logger {
+        start: Instant::now(),
+        filters: RwLock::new(LevelFilters::new(&LoggingSettings::default())),
+        recent: Mutex::new(VecDeque::with_capacity(RECENT_ENTRY_COUNT)),
+        file: Mutex::new(None),
+    };
+}
+
+fn set_filters(settings: &LoggingSettings) {
+    let mut filters = LevelFilters::new(settings);
+    let env_error = match std::env::var("RUST_LOG") {
+        Ok(directives) => filters.apply_directives(&directives).err(),
+        Err(_) => None,
+    };
+
+    log::set_max_level(filters.max_level());
+    *LOGGER.filters.write() = filters;
+
+    if let Some(err) = env_error {
+        log::warn!("ignoring the rest of RUST_LOG: {}", err);
+    }
+}
+
+/// starts logging with the default settings, which only goes to the terminal
+/// until [`configure`] is called.
+pub fn init() {
+    if log::set_logger(&*LOGGER).is_err() {
+        return;
+    }
+    set_filters(&LoggingSettings::default());
+}
+
+/// switches over to the levels in `settings`, and starts writing to a log file
+/// in `log_dir` if the settings say to. everything that was logged before this
+/// was called is written to the log file too.
+pub fn configure(settings: &LoggingSettings, log_dir: &Path) {
+    set_filters(settings);
+
+    if !settings.write_to_file {
+        *LOGGER.file.lock() = None;
+        return;
+    }
+
+    let result = LogFile::open(log_dir, settings).and_then(|mut file| {
+        for entry in LOGGER.recent.lock().iter() {
+            file.write(entry)?;
+        }
+        Ok(file)
+    });
+
+    match result {
+        Ok(file) => *LOGGER.file.lock() = Some(file),
+        Err(err) => log::error!(
+            "failed to open log file in '{}': {}",
+            log_dir.display(),
+            err
+        ),
+    }
+}
+
+/// the last `count` entries that were logged, oldest first.
+pub fn recent_entries(count: usize) -> Vec<LogEntry> {
+    let recent = LOGGER.recent.lock();
+    let skip = recent.len().saturating_sub(count);
+    recent.iter().skip(skip).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(message: &str) -> LogEntry {
+        LogEntry {
+            time: Duration::from_millis(1500),
+            level: Level::Info,
+            target: "notcraft::client".into(),
+            thread: None,
+            message: message.into(),
+        }
+    }
+
+    #[test]
+    fn most_specific_module_wins() {
+        let mut filters = LevelFilters::new(&LoggingSettings::default());
+        filters
+            .apply_directives(
+                "error, notcraft_common::world=debug,notcraft_common::world::chunk=off",
+            )
+            .unwrap();
+
+        assert_eq!(filters.level("glium::context"), LevelFilter::Error);
+        assert_eq!(filters.level("notcraft"), LevelFilter::Info);
+        assert_eq!(filters.level("notcraft::client::render"), LevelFilter::Info);
+        assert_eq!(filters.level("notcraft_common::world"), LevelFilter::Debug);
+        assert_eq!(
+            filters.level("notcraft_common::world::lighting"),
+            LevelFilter::Debug
+        );
+        assert_eq!(
+            filters.level("notcraft_common::world::chunk"),
+            LevelFilter::Off
+        );
+        // only whole module names count
+        assert_eq!(
+            filters.level("notcraft_common::worldgen"),
+            LevelFilter::Info
+        );
+        assert_eq!(filters.max_level(), LevelFilter::Debug);
+
+        assert!(filters.apply_directives("notcraft=loud").is_err());
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = LoggingSettings::default();
+        let text = ron::ser::to_string_pretty(&settings, Default::default()).unwrap();
+        assert_eq!(ron::from_str::<LoggingSettings>(&text).unwrap(), settings);
+    }
+
+    #[test]
+    fn log_files_rotate() {
+        let dir = std::env::temp_dir().join(format!("notcraft-log-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let settings = LoggingSettings {
+            max_file_size: 150,
+            max_old_files: 2,
+            ..Default::default()
+        };
+        let read = |age| std::fs::read_to_string(LogFile::path(&dir, age)).unwrap();
+
+        // each entry is a bit under half the file size, so every two entries
+        // start a new file.
+        let mut file = LogFile::open(&dir, &settings).unwrap();
+        for message in ["a", "b", "c", "d", "e"] {
+            file.write(&entry(&format!("{:<30}", message))).unwrap();
+        }
+        file.writer.flush().unwrap();
+        assert!(read(0).contains(" e "));
+        assert!(read(1).contains(" c ") && read(1).contains(" d "));
+        assert!(read(2).contains(" a ") && read(2).contains(" b "));
+
+        // starting another run moves the current log back too, dropping the
+        // oldest one.
+        drop(LogFile::open(&dir, &settings).unwrap());
+        assert!(read(0).is_empty());
+        assert!(read(1).contains(" e "));
+        assert!(read(2).contains(" c "));
+        assert!(!LogFile::path(&dir, 3).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
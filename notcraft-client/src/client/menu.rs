@@ -0,0 +1,362 @@
+//! the main menu and the pause menu, and moving between them and the game.
+//!
+//! menus are navigated with the arrow keys and `Enter`, and `Escape` pauses and
+//! unpauses the game. see [`GameState`] for what stops while a menu is open.
+//!
+//! both menus can open the settings page, where `Left`/`Right` change the
+//! selected setting. changes take effect right away, and are saved to the
+//! settings file as they're made.
+
+use crate::client::{
+    input::{keys, InputState, MouseSettings, MAX_SMOOTHING},
+    render::{font, renderer::ImmediateUi},
+};
+use bevy_app::AppExit;
+use glium::glutin::event::VirtualKeyCode;
+use notcraft_common::{game_state::GameState, lang::tr, prelude::*, world::persistence::SaveEvent};
+
+const BUTTON_WIDTH: f32 = 80.0;
+const BUTTON_HEIGHT: f32 = 16.0;
+const BUTTON_GAP: f32 = 6.0;
+
+const SETTINGS_WIDTH: f32 = 140.0;
+const ROW_HEIGHT: f32 = 10.0;
+const ROW_GAP: f32 = 2.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum MenuButton {
+    Play,
+    Resume,
+    Settings,
+    SaveAndQuit,
+}
+
+impl MenuButton {
+    fn color(self) -> [f32; 3] {
+        match self {
+            MenuButton::Play | MenuButton::Resume => [0.25, 0.55, 0.3],
+            MenuButton::Settings => [0.3, 0.4, 0.55],
+            MenuButton::SaveAndQuit => [0.6, 0.25, 0.25],
+        }
+    }
+}
+
+fn menu_buttons(state: GameState) -> &'static [MenuButton] {
+    match state {
+        GameState::MainMenu => &[
+            MenuButton::Play,
+            MenuButton::Settings,
+            MenuButton::SaveAndQuit,
+        ],
+        GameState::Paused => &[
+            MenuButton::Resume,
+            MenuButton::Settings,
+            MenuButton::SaveAndQuit,
+        ],
+        GameState::Loading | GameState::Playing => &[],
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum MouseSetting {
+    Sensitivity,
+    Smoothing,
+    Acceleration,
+    InvertY,
+    RawInput,
+}
+
+const MOUSE_SETTINGS: [MouseSetting; 5] = [
+    MouseSetting::Sensitivity,
+    MouseSetting::Smoothing,
+    MouseSetting::Acceleration,
+    MouseSetting::InvertY,
+    MouseSetting::RawInput,
+];
+
+/// moves `value` by `steps` steps of `step`, keeping it on a multiple of `step`
+/// so that float error doesn't build up as it's moved back and forth.
+fn nudge(value: f32, step: f32, steps: f32, min: f32, max: f32) -> f32 {
+    util::clamp(min, max, ((value / step).round() + steps) * step)
+}
+
+impl MouseSetting {
+    fn label(self, mouse: &MouseSettings) -> String {
+        let on_off = |on| if on { tr!("menu.on") } else { tr!("menu.off") };
+        match self {
+            MouseSetting::Sensitivity => {
+                let sensitivity = format!("{:.2}", mouse.sensitivity);
+                tr!("menu.mouse.sensitivity", sensitivity)
+            }
+            MouseSetting::Smoothing => {
+                let smoothing = format!("{:.0}%", 100.0 * mouse.smoothing);
+                tr!("menu.mouse.smoothing", smoothing)
+            }
+            MouseSetting::Acceleration => {
+                let acceleration = format!("{:.1}", mouse.acceleration);
+                tr!("menu.mouse.acceleration", acceleration)
+            }
+            MouseSetting::InvertY => tr!("menu.mouse.invert_y", on_off(mouse.invert_y)),
+            MouseSetting::RawInput => tr!("menu.mouse.raw_input", on_off(mouse.raw_input)),
+        }
+    }
+
+    fn is_toggle(self) -> bool {
+        matches!(self, MouseSetting::InvertY | MouseSetting::RawInput)
+    }
+
+    /// moves the setting up or down by `steps`, or flips it if it's something
+    /// that's either on or off.
+    fn adjust(self, mouse: &mut MouseSettings, steps: f32) {
+        match self {
+            MouseSetting::Sensitivity => {
+                mouse.sensitivity = nudge(mouse.sensitivity, 0.01, steps, 0.01, 1.0)
+            }
+            MouseSetting::Smoothing => {
+                mouse.smoothing = nudge(mouse.smoothing, 0.05, steps, 0.0, MAX_SMOOTHING)
+            }
+            MouseSetting::Acceleration => {
+                mouse.acceleration = nudge(mouse.acceleration, 0.1, steps, 0.0, 2.0)
+            }
+            MouseSetting::InvertY => mouse.invert_y = !mouse.invert_y,
+            MouseSetting::RawInput => mouse.raw_input = !mouse.raw_input,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct Menu {
+    selected: usize,
+    /// whether the cursor was grabbed when the game was paused, so that it
+    /// can be grabbed again when the game is resumed.
+    cursor_was_grabbed: bool,
+    /// set once the game has asked for everything to be saved, so that it can
+    /// exit on the next frame, after the save has happened.
+    quitting: bool,
+    settings_open: bool,
+    /// which row of the settings page is selected. the row after the last
+    /// setting goes back to the menu.
+    selected_setting: usize,
+}
+
+fn release_cursor(input: &InputState, menu: &mut Menu) {
+    menu.cursor_was_grabbed = input.is_cursor_grabbed();
+    input.grab_cursor(false);
+    input.hide_cursor(false);
+}
+
+fn restore_cursor(input: &InputState, grabbed: bool) {
+    input.grab_cursor(grabbed);
+    input.hide_cursor(grabbed);
+}
+
+fn settings_input(input: &mut InputState, menu: &mut Menu) {
+    let rows = MOUSE_SETTINGS.len() + 1;
+    if input.key(keys::ARROW_UP).is_rising() {
+        menu.selected_setting = (menu.selected_setting + rows - 1) % rows;
+    }
+    if input.key(keys::ARROW_DOWN).is_rising() {
+        menu.selected_setting = (menu.selected_setting + 1) % rows;
+    }
+
+    let enter = input.key(VirtualKeyCode::Return).is_rising();
+    let right = input.key(keys::ARROW_RIGHT).is_rising() as i32;
+    let steps = (right - input.key(keys::ARROW_LEFT).is_rising() as i32) as f32;
+    match MOUSE_SETTINGS.get(menu.selected_setting).copied() {
+        Some(setting) if steps != 0.0 => setting.adjust(&mut input.mouse, steps),
+        Some(setting) if enter && setting.is_toggle() => setting.adjust(&mut input.mouse, 1.0),
+        None if enter => menu.settings_open = false,
+        _ => {}
+    }
+}
+
+fn menu_input(
+    mut input: ResMut<InputState>,
+    mut state: ResMut<GameState>,
+    mut menu: ResMut<Menu>,
+    mut save_events: EventWriter<SaveEvent>,
+    mut exit_events: EventWriter<AppExit>,
+) {
+    if menu.quitting {
+        exit_events.send(AppExit);
+        return;
+    }
+
+    let escape = input.key(VirtualKeyCode::Escape).is_rising();
+    if menu.settings_open && matches!(*state, GameState::MainMenu | GameState::Paused) {
+        match escape {
+            true => menu.settings_open = false,
+            false => settings_input(&mut input, &mut menu),
+        }
+        return;
+    }
+
+    match *state {
+        GameState::Playing if escape => {
+            release_cursor(&input, &mut menu);
+            menu.selected = 0;
+            *state = GameState::Paused;
+            return;
+        }
+        GameState::Paused if escape => {
+            restore_cursor(&input, menu.cursor_was_grabbed);
+            *state = GameState::Playing;
+            return;
+        }
+        GameState::Loading | GameState::Playing => return,
+        _ => {}
+    }
+
+    let buttons = menu_buttons(*state);
+    if input.key(keys::ARROW_UP).is_rising() {
+        menu.selected = (menu.selected + buttons.len() - 1) % buttons.len();
+    }
+    if input.key(keys::ARROW_DOWN).is_rising() {
+        menu.selected = (menu.selected + 1) % buttons.len();
+    }
+    menu.selected = menu.selected.min(buttons.len() - 1);
+
+    if !input.key(VirtualKeyCode::Return).is_rising() {
+        return;
+    }
+
+    match buttons[menu.selected] {
+        MenuButton::Play => {
+            restore_cursor(&input, true);
+            *state = GameState::Playing;
+        }
+        MenuButton::Resume => {
+            restore_cursor(&input, menu.cursor_was_grabbed);
+            *state = GameState::Playing;
+        }
+        MenuButton::Settings => {
+            menu.settings_open = true;
+            menu.selected_setting = 0;
+        }
+        MenuButton::SaveAndQuit => {
+            log::info!("{}", tr!("menu.saving"));
+            save_events.send(SaveEvent);
+            menu.quitting = true;
+        }
+    }
+}
+
+/// draws the little picture on a button, since the UI can't draw text yet.
+fn draw_button_icon(ui: &mut ImmediateUi, button: MenuButton, center: [f32; 2]) {
+    let [x, y] = center;
+    match button {
+        // a triangle pointing right, made out of columns that get shorter
+        MenuButton::Play | MenuButton::Resume => {
+            for column in 0..5 {
+                let left = x - 4.0 + 2.0 * column as f32;
+                let height = 5.0 - column as f32;
+                ui.rect([left, y - height], [left + 2.0, y + height], [1.0; 4]);
+            }
+        }
+        // three sliders, with their knobs at different spots
+        MenuButton::Settings => {
+            for (row, knob) in [(4.0, -3.0), (0.0, 4.0), (-4.0, 0.0)] {
+                ui.rect([x - 8.0, y + row - 0.5], [x + 8.0, y + row + 0.5], [1.0; 4]);
+                let [left, right] = [x + knob - 1.5, x + knob + 1.5];
+                ui.rect([left, y + row - 1.5], [right, y + row + 1.5], [1.0; 4]);
+            }
+        }
+        // a floppy disk with an arrow pointing out of the door
+        MenuButton::SaveAndQuit => {
+            ui.rect([x - 10.0, y - 5.0], [x, y + 5.0], [1.0; 4]);
+            ui.rect([x - 8.0, y + 1.0], [x - 2.0, y + 4.0], [0.2, 0.2, 0.2, 1.0]);
+            ui.rect([x + 3.0, y - 1.0], [x + 10.0, y + 1.0], [1.0; 4]);
+            ui.rect([x + 8.0, y - 3.0], [x + 10.0, y + 3.0], [1.0; 4]);
+        }
+    }
+}
+
+fn draw_settings(ui: &mut ImmediateUi, menu: &Menu, mouse: &MouseSettings) {
+    let mut rows = MOUSE_SETTINGS
+        .iter()
+        .map(|setting| setting.label(mouse))
+        .collect::<Vec<_>>();
+    rows.push(tr!("menu.back"));
+
+    let stride = ROW_HEIGHT + ROW_GAP;
+    let top = (rows.len() as f32 * stride - ROW_GAP) / 2.0;
+    let [left, right] = [-SETTINGS_WIDTH / 2.0, SETTINGS_WIDTH / 2.0];
+    let title = tr!("menu.settings");
+    let title_pos = [-font::text_width(&title) / 2.0, top + ROW_HEIGHT];
+    ui.text(title_pos, &title, [1.0; 4]);
+
+    for (idx, row) in rows.iter().enumerate() {
+        let row_top = top - idx as f32 * stride;
+        let color = match idx == menu.selected_setting {
+            true => [0.3, 0.45, 0.6, 0.9],
+            false => [0.2, 0.2, 0.2, 0.9],
+        };
+        ui.rect([left, row_top - ROW_HEIGHT], [right, row_top], color);
+        ui.text([left + 3.0, row_top - ROW_HEIGHT + 2.0], row, [1.0; 4]);
+    }
+
+    let hint = tr!("menu.settings_hint");
+    let bottom = top - rows.len() as f32 * stride;
+    let hint_pos = [-font::text_width(&hint) / 2.0, bottom - 8.0];
+    ui.text(hint_pos, &hint, [0.7, 0.7, 0.7, 1.0]);
+}
+
+fn draw_menu(
+    state: Res<GameState>,
+    menu: Res<Menu>,
+    input: Res<InputState>,
+    mut ui: ResMut<ImmediateUi>,
+) {
+    let buttons = menu_buttons(*state);
+    if buttons.is_empty() {
+        return;
+    }
+
+    // dim everything behind the menu
+    ui.rect([-1000.0, -1000.0], [1000.0, 1000.0], [0.0, 0.0, 0.0, 0.5]);
+    if menu.settings_open {
+        draw_settings(&mut ui, &menu, &input.mouse);
+        return;
+    }
+
+    let stride = BUTTON_HEIGHT + BUTTON_GAP;
+    let top = (buttons.len() as f32 * stride - BUTTON_GAP) / 2.0;
+    let left = -BUTTON_WIDTH / 2.0;
+    for (idx, &button) in buttons.iter().enumerate() {
+        let button_top = top - idx as f32 * stride;
+        let button_bottom = button_top - BUTTON_HEIGHT;
+
+        let selected = idx == menu.selected;
+        if selected {
+            let border = [left - 1.0, button_bottom - 1.0];
+            ui.rect(border, [-left + 1.0, button_top + 1.0], [1.0; 4]);
+        }
+
+        let [r, g, b] = button.color();
+        let brightness = if selected { 1.3 } else { 1.0 };
+        let color = [r * brightness, g * brightness, b * brightness, 1.0];
+        ui.rect([left, button_bottom], [-left, button_top], color);
+
+        let center = [0.0, button_bottom + BUTTON_HEIGHT / 2.0];
+        draw_button_icon(&mut ui, button, center);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MenuPlugin {}
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // the game starts on the loading screen when there is one, which hands
+        // over to the main menu once everything is loaded.
+        if app.world().get_resource::<GameState>().is_none() {
+            app.insert_resource(GameState::MainMenu);
+        }
+        app.init_resource::<Menu>();
+        app.add_system(menu_input.system().label(MenuLabel));
+        app.add_system(draw_menu.system().after(MenuLabel));
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub struct MenuLabel;
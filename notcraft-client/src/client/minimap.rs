@@ -0,0 +1,531 @@
+//! a top-down map of the terrain, shown as a small minimap in the corner of the
+//! screen, or as a fullscreen map of every chunk column that's been explored.
+//!
+//! each block column is colored by the top visible block in it, and shaded
+//! by whether it's higher or lower than the column to its north, which gives
+//! hills a bit of relief.
+
+use crate::client::{
+    camera::CurrentCamera,
+    input::InputState,
+    loader::{self, BlockTextures},
+    render::renderer::ImmediateUi,
+    window::UiScale,
+};
+use glium::{glutin::event::VirtualKeyCode, Display};
+use image::{Rgba, RgbaImage};
+use nalgebra::Vector3;
+use notcraft_common::{
+    game_state::playing,
+    prelude::*,
+    profiler::ProfileSystemExt,
+    world::{
+        chunk::{
+            index_to_pos, pos_to_index, Chunk, ChunkData, ChunkSectionPos, CHUNK_LENGTH,
+            CHUNK_LENGTH_2,
+        },
+        generation::{Climate, ClimateSampler},
+        registry::{BlockId, BlockMeshType, BlockRegistry},
+        ChunkPos, VoxelWorld, WorldEvent, WorldGenerator, WorldPos,
+    },
+    Side,
+};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    sync::Arc,
+};
+
+/// how many changed chunk columns are redrawn each frame, closest to the
+/// camera first.
+const COLUMNS_PER_FRAME: usize = 8;
+
+const MINIMAP_SIZE: f32 = 96.0;
+const MARGIN: f32 = 4.0;
+const BORDER: f32 = 1.0;
+
+/// UI units per block at each zoom level of the minimap, from farthest out to
+/// closest in.
+const MINIMAP_ZOOM: &[f32] = &[0.75, 1.5, 3.0];
+/// UI units per block at each zoom level of the fullscreen map.
+const FULLSCREEN_ZOOM: &[f32] = &[0.125, 0.25, 0.5, 1.0, 2.0];
+
+/// the smallest that a single cell of the minimap gets drawn, in UI units. once
+/// blocks get smaller than this, each cell covers several blocks instead, so
+/// that zooming out doesn't turn the map into tens of thousands of quads.
+const MINIMAP_MIN_CELL: f32 = 1.5;
+const FULLSCREEN_MIN_CELL: f32 = 3.0;
+
+/// used for blocks without any textures.
+const MISSING_COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+/// used for columns where none of the sections holding the surface are loaded,
+/// so only the height of the generated surface is known.
+const UNLOADED_COLOR: [f32; 3] = [0.12, 0.12, 0.14];
+
+fn srgb_to_linear(pixel: Rgba<u8>) -> [f32; 3] {
+    [pixel[0], pixel[1], pixel[2]].map(|c| (c as f32 / 255.0).powf(2.2))
+}
+
+/// the alpha-weighted average of every pixel in a texture.
+fn average_color(image: &RgbaImage) -> [f32; 3] {
+    let mut sum = [0.0; 3];
+    let mut weight = 0.0;
+    for &pixel in image.pixels() {
+        let alpha = pixel[3] as f32 / 255.0;
+        let color = srgb_to_linear(pixel);
+        for i in 0..3 {
+            sum[i] += alpha * color[i];
+        }
+        weight += alpha;
+    }
+
+    match weight > 0.0 {
+        true => sum.map(|c| c / weight),
+        false => MISSING_COLOR,
+    }
+}
+
+/// the average color of every block texture, which is what a block looks like
+/// from far enough away that it only covers a pixel or two of the map.
+#[derive(Clone, Debug)]
+pub struct BlockMapColors {
+    /// one for each layer of the block texture array.
+    layers: Vec<[f32; 3]>,
+    colormap: RgbaImage,
+}
+
+impl BlockMapColors {
+    pub fn new(registry: &BlockRegistry, textures: &BlockTextures) -> Result<Self> {
+        let layers = registry
+            .texture_paths()
+            .flat_map(|path| &textures.block_textures[path])
+            .map(|image| average_color(image))
+            .collect();
+        let colormap = loader::load_texture("resources/textures/grass_colormap.png")?;
+
+        Ok(Self { layers, colormap })
+    }
+
+    fn block_color(&self, registry: &BlockRegistry, id: BlockId) -> [f32; 3] {
+        let faces = registry
+            .get(id)
+            .block_textures()
+            .and_then(|faces| faces.first());
+        faces
+            .and_then(|faces| registry.pool_textures(faces[Side::Top]).first())
+            .and_then(|texture| self.layers.get(texture.0))
+            .copied()
+            .unwrap_or(MISSING_COLOR)
+    }
+
+    /// the same color that tinted blocks are multiplied by in the terrain
+    /// shader.
+    fn tint(&self, climate: Climate) -> [f32; 3] {
+        let (width, height) = self.colormap.dimensions();
+        // the colormap is flipped when it gets uploaded, so humidity goes up
+        // from the bottom of the image.
+        let x = climate.temperature * (width - 1) as f32;
+        let y = (1.0 - climate.humidity) * (height - 1) as f32;
+        srgb_to_linear(*self.colormap.get_pixel(x.round() as u32, y.round() as u32))
+    }
+}
+
+fn is_visible(registry: &BlockRegistry, id: BlockId) -> bool {
+    registry.get(id).mesh_type() != BlockMeshType::None
+}
+
+/// the local height of the top visible block of a column of a section.
+fn top_visible(
+    registry: &BlockRegistry,
+    blocks: &ChunkData<BlockId>,
+    x: usize,
+    z: usize,
+) -> Option<usize> {
+    if let &ChunkData::Homogeneous(id) = blocks {
+        return is_visible(registry, id).then_some(CHUNK_LENGTH - 1);
+    }
+    (0..CHUNK_LENGTH)
+        .rev()
+        .find(|&y| is_visible(registry, blocks.get([x, y, z])))
+}
+
+/// what a chunk column looks like from above.
+#[derive(Clone, Debug)]
+struct ColumnMap {
+    /// the height of the top visible block in each column, in the same XZ
+    /// order as the surface heightmap.
+    heights: Box<[i32]>,
+    /// the color of the top visible block in each column, before shading.
+    colors: Box<[[f32; 3]]>,
+}
+
+impl ColumnMap {
+    fn new(
+        chunk: &Chunk,
+        registry: &BlockRegistry,
+        colors: &BlockMapColors,
+        climate: &ClimateSampler,
+    ) -> Self {
+        let mut sections = chunk
+            .sections()
+            .values()
+            .map(|section| section.snapshot())
+            .collect::<Vec<_>>();
+        sections.sort_unstable_by_key(|section| -section.pos().y);
+        let surface = chunk.heights();
+
+        let mut heights = Vec::with_capacity(CHUNK_LENGTH_2);
+        let mut column_colors = Vec::with_capacity(CHUNK_LENGTH_2);
+        for x in 0..CHUNK_LENGTH {
+            for z in 0..CHUNK_LENGTH {
+                let top = sections.iter().find_map(|section| {
+                    let y = top_visible(registry, section.blocks(), x, z)?;
+                    let id = section.blocks().get([x, y, z]);
+                    Some((index_to_pos(section.pos().y, y), id))
+                });
+
+                let (height, color) = match top {
+                    Some((y, id)) if registry.get(id).tinted() => {
+                        let wx = index_to_pos(chunk.pos().x, x) as f32;
+                        let wz = index_to_pos(chunk.pos().z, z) as f32;
+                        let tint = colors.tint(climate.sample(wx, wz));
+                        let color = colors.block_color(registry, id);
+                        (y, [0, 1, 2].map(|i| color[i] * tint[i]))
+                    }
+                    Some((y, id)) => (y, colors.block_color(registry, id)),
+                    // the world generator still knows where the surface is, even if none of it
+                    // has been loaded yet.
+                    None => (surface.data()[CHUNK_LENGTH * x + z], UNLOADED_COLOR),
+                };
+                heights.push(height);
+                column_colors.push(color);
+            }
+        }
+
+        Self {
+            heights: heights.into_boxed_slice(),
+            colors: column_colors.into_boxed_slice(),
+        }
+    }
+}
+
+/// every chunk column that has been seen since the game started, as seen from
+/// above. columns are kept around after they're unloaded, so that the
+/// fullscreen map shows everywhere that's been explored.
+#[derive(Debug, Default)]
+pub struct WorldMap {
+    columns: HashMap<ChunkPos, ColumnMap>,
+    /// columns that were loaded or changed since they were last drawn.
+    dirty: HashSet<ChunkPos>,
+}
+
+impl WorldMap {
+    /// the height and unshaded color of the top visible block at a position.
+    pub fn surface(&self, x: i32, z: i32) -> Option<(i32, [f32; 3])> {
+        let column = self.columns.get(&ChunkPos {
+            x: x.div_euclid(CHUNK_LENGTH as i32),
+            z: z.div_euclid(CHUNK_LENGTH as i32),
+        })?;
+        let idx = CHUNK_LENGTH * pos_to_index(x) + pos_to_index(z);
+        Some((column.heights[idx], column.colors[idx]))
+    }
+
+    fn shaded_color(&self, x: i32, z: i32) -> Option<[f32; 4]> {
+        let (height, [r, g, b]) = self.surface(x, z)?;
+        let north = self.surface(x, z - 1).map_or(height, |(north, _)| north);
+        let shade = match height.cmp(&north) {
+            Ordering::Greater => 1.15,
+            Ordering::Equal => 1.0,
+            Ordering::Less => 0.8,
+        };
+        Some([r * shade, g * shade, b * shade, 1.0])
+    }
+}
+
+fn mark_changed_columns(mut events: EventReader<WorldEvent>, mut map: ResMut<WorldMap>) {
+    for event in events.iter() {
+        match event {
+            WorldEvent::Loaded(chunk) => map.dirty.insert(chunk.pos()),
+            WorldEvent::LoadedSection(section) | WorldEvent::ModifiedSection(section, _) => {
+                map.dirty.insert(section.pos().column())
+            }
+            _ => false,
+        };
+    }
+}
+
+fn redraw_changed_columns(
+    camera: CurrentCamera,
+    voxel_world: Res<Arc<VoxelWorld>>,
+    registry: Res<Arc<BlockRegistry>>,
+    generator: Res<Arc<WorldGenerator>>,
+    colors: Res<BlockMapColors>,
+    mut map: ResMut<WorldMap>,
+) {
+    let focus = ChunkSectionPos::from(WorldPos::new(camera.pos())).column();
+    let distance = |pos: &ChunkPos| (pos.x - focus.x).abs() + (pos.z - focus.z).abs();
+
+    for _ in 0..COLUMNS_PER_FRAME {
+        let pos = match map.dirty.iter().min_by_key(|pos| distance(pos)) {
+            Some(&pos) => pos,
+            None => break,
+        };
+        map.dirty.remove(&pos);
+
+        // if the column was unloaded before it got redrawn, whatever was last
+        // seen of it is kept.
+        if let Some(chunk) = voxel_world.chunk(pos) {
+            let column = ColumnMap::new(&chunk, &registry, &colors, generator.climate());
+            map.columns.insert(pos, column);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MapView {
+    pub fullscreen: bool,
+    /// index into the zoom levels of the minimap.
+    pub minimap_zoom: usize,
+    /// index into the zoom levels of the fullscreen map.
+    pub fullscreen_zoom: usize,
+}
+
+impl Default for MapView {
+    fn default() -> Self {
+        Self {
+            fullscreen: false,
+            minimap_zoom: 1,
+            fullscreen_zoom: 2,
+        }
+    }
+}
+
+fn map_input(input: Res<InputState>, mut view: ResMut<MapView>) {
+    if input.key(VirtualKeyCode::M).is_rising() {
+        view.fullscreen = !view.fullscreen;
+    }
+
+    let view = &mut *view;
+    let (zoom, levels) = match view.fullscreen {
+        true => (&mut view.fullscreen_zoom, FULLSCREEN_ZOOM),
+        false => (&mut view.minimap_zoom, MINIMAP_ZOOM),
+    };
+    if input.key(VirtualKeyCode::Equals).is_rising() {
+        *zoom = usize::min(*zoom + 1, levels.len() - 1);
+    }
+    if input.key(VirtualKeyCode::Minus).is_rising() {
+        *zoom = zoom.saturating_sub(1);
+    }
+}
+
+/// the part of the map being drawn, and where it's drawn to.
+struct MapArea {
+    min: [f32; 2],
+    max: [f32; 2],
+    /// the block position at the center of the area.
+    center: [f32; 2],
+    /// UI units per block.
+    scale: f32,
+    min_cell: f32,
+}
+
+impl MapArea {
+    /// where the north-west corner of a block ends up on the screen.
+    fn to_ui(&self, x: f32, z: f32) -> [f32; 2] {
+        let mid = [
+            (self.min[0] + self.max[0]) / 2.0,
+            (self.min[1] + self.max[1]) / 2.0,
+        ];
+        [
+            mid[0] + (x - self.center[0]) * self.scale,
+            mid[1] - (z - self.center[1]) * self.scale,
+        ]
+    }
+}
+
+fn draw_map(ui: &mut ImmediateUi, map: &WorldMap, area: &MapArea) {
+    // cells are a power of two blocks across so that they line up with chunks,
+    // and always sample the same block no matter where the camera is.
+    let cell = ((area.min_cell / area.scale).ceil() as usize)
+        .next_power_of_two()
+        .min(CHUNK_LENGTH) as i32;
+
+    let half_width = (area.max[0] - area.min[0]) / area.scale / 2.0;
+    let half_height = (area.max[1] - area.min[1]) / area.scale / 2.0;
+    let first_x = (area.center[0] - half_width).floor() as i32;
+    let first_x = first_x.div_euclid(cell) * cell;
+    let last_x = (area.center[0] + half_width).ceil() as i32;
+    let first_z = (area.center[1] - half_height).floor() as i32;
+    let first_z = first_z.div_euclid(cell) * cell;
+    let last_z = (area.center[1] + half_height).ceil() as i32;
+
+    let clip = |[x, y]: [f32; 2]| {
+        [
+            x.clamp(area.min[0], area.max[0]),
+            y.clamp(area.min[1], area.max[1]),
+        ]
+    };
+
+    for z in (first_z..last_z).step_by(cell as usize) {
+        // neighboring cells of the same color get drawn as one quad.
+        let mut run: Option<(i32, [f32; 4])> = None;
+        let mut flush = |run: Option<(i32, [f32; 4])>, end: i32| {
+            if let Some((start, color)) = run {
+                let [left, top] = area.to_ui(start as f32, z as f32);
+                let [right, bottom] = area.to_ui(end as f32, (z + cell) as f32);
+                ui.rect(clip([left, bottom]), clip([right, top]), color);
+            }
+        };
+
+        for x in (first_x..last_x).step_by(cell as usize) {
+            let color = map.shaded_color(x, z);
+            match (run, color) {
+                (Some((_, current)), Some(color)) if current == color => {}
+                (_, color) => {
+                    flush(run, x);
+                    run = color.map(|color| (x, color));
+                }
+            }
+        }
+        flush(run, last_x.div_euclid(cell) * cell + cell);
+    }
+}
+
+fn draw_player_marker(ui: &mut ImmediateUi, area: &MapArea, forward: Vector3<f32>) {
+    let [x, y] = area.to_ui(area.center[0], area.center[1]);
+    ui.rect([x - 1.5, y - 1.5], [x + 1.5, y + 1.5], [1.0, 1.0, 1.0, 1.0]);
+
+    let facing = nalgebra::vector![forward.x, -forward.z];
+    if let Some(facing) = facing.try_normalize(1e-4) {
+        let [dx, dy] = [x + 4.0 * facing.x, y + 4.0 * facing.y];
+        ui.rect(
+            [dx - 1.0, dy - 1.0],
+            [dx + 1.0, dy + 1.0],
+            [1.0, 0.3, 0.3, 1.0],
+        );
+    }
+}
+
+fn draw_minimap(
+    camera: CurrentCamera,
+    view: Res<MapView>,
+    map: Res<WorldMap>,
+    display: NonSend<Rc<Display>>,
+    ui_scale: Res<UiScale>,
+    mut ui: ResMut<ImmediateUi>,
+) {
+    let transform = match camera.transform() {
+        Some(transform) => transform,
+        None => return,
+    };
+    let pos = transform.pos();
+    let forward = transform.rotation.to_quaternion() * -Vector3::z();
+
+    let (width, height) = display.get_framebuffer_dimensions();
+    let right = width as f32 / ui_scale.0 / 2.0 - MARGIN;
+    let top = height as f32 / ui_scale.0 / 2.0 - MARGIN;
+
+    let area = match view.fullscreen {
+        true => MapArea {
+            min: [-right, -top],
+            max: [right, top],
+            center: [pos.x, pos.z],
+            scale: FULLSCREEN_ZOOM[view.fullscreen_zoom],
+            min_cell: FULLSCREEN_MIN_CELL,
+        },
+        false => MapArea {
+            min: [-right, top - MINIMAP_SIZE],
+            max: [-right + MINIMAP_SIZE, top],
+            center: [pos.x, pos.z],
+            scale: MINIMAP_ZOOM[view.minimap_zoom],
+            min_cell: MINIMAP_MIN_CELL,
+        },
+    };
+
+    let [left, bottom] = area.min;
+    let [right, top] = area.max;
+    let border = [0.8, 0.8, 0.8, 0.9];
+    ui.rect(
+        [left - BORDER, bottom - BORDER],
+        [right + BORDER, top + BORDER],
+        border,
+    );
+    ui.rect([left, bottom], [right, top], [0.05, 0.05, 0.05, 0.9]);
+
+    draw_map(&mut ui, &map, &area);
+    draw_player_marker(&mut ui, &area, forward);
+}
+
+#[derive(Debug, Default)]
+pub struct MinimapPlugin {}
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<WorldMap>();
+        app.init_resource::<MapView>();
+        app.add_system(mark_changed_columns.system());
+        app.add_system(redraw_changed_columns.system().profiled());
+        app.add_system(
+            map_input
+                .system()
+                .label(MapInputLabel)
+                .with_run_criteria(playing.system()),
+        );
+        app.add_system(
+            draw_minimap
+                .system()
+                .after(MapInputLabel)
+                .with_run_criteria(playing.system()),
+        );
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub struct MapInputLabel;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(height: impl Fn(usize, usize) -> i32) -> ColumnMap {
+        let heights = (0..CHUNK_LENGTH_2)
+            .map(|idx| height(idx / CHUNK_LENGTH, idx % CHUNK_LENGTH))
+            .collect();
+        ColumnMap {
+            heights,
+            colors: vec![[0.5; 3]; CHUNK_LENGTH_2].into_boxed_slice(),
+        }
+    }
+
+    #[test]
+    fn surface_lookup_handles_negative_positions() {
+        let mut map = WorldMap::default();
+        map.columns
+            .insert(ChunkPos { x: -1, z: 0 }, column(|x, z| (x + z) as i32));
+
+        let last = CHUNK_LENGTH as i32 - 1;
+        assert_eq!(map.surface(-1, 3).map(|(height, _)| height), Some(last + 3));
+        assert_eq!(
+            map.surface(-(CHUNK_LENGTH as i32), 0).map(|(h, _)| h),
+            Some(0)
+        );
+        assert_eq!(map.surface(0, 0), None);
+        assert_eq!(map.surface(-1, -1), None);
+    }
+
+    #[test]
+    fn columns_are_shaded_by_their_northern_neighbor() {
+        let mut map = WorldMap::default();
+        map.columns
+            .insert(ChunkPos { x: 0, z: 0 }, column(|_, z| [0, 1, 1, 0][z % 4]));
+
+        let brightness = |z| map.shaded_color(0, z).unwrap()[0];
+        // the northern edge of the explored area has nothing to compare to.
+        assert_eq!(brightness(0), 0.5);
+        assert!(brightness(1) > 0.5);
+        assert_eq!(brightness(2), 0.5);
+        assert!(brightness(3) < 0.5);
+        assert_eq!(map.shaded_color(0, -1), None);
+    }
+}
@@ -0,0 +1,341 @@
+//! mixer buses, which let groups of sounds be turned up and down together.
+//!
+//! every sound plays through one of the buses, and every bus plays through the
+//! master bus. sounds read their bus's volume while they play, so changing a
+//! volume affects sounds that are already playing too. volumes never jump
+//! straight to a new value, and instead fade over a short time so that they
+//! don't click. music is also ducked (turned down) while lots of effects are
+//! playing at once, so that it doesn't get lost in the noise.
+
+use super::settings::SettingsFile;
+use ambisonic::rodio::Source;
+use notcraft_common::prelude::*;
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// how quickly bus volumes fade towards their target, in full volume per
+/// second.
+const FADE_SPEED: f32 = 4.0;
+/// how many effects have to be playing at once for music to get ducked.
+const DUCK_THRESHOLD: usize = 6;
+/// how loud music is while it's ducked.
+const DUCK_VOLUME: f32 = 0.4;
+/// music ducks quickly, but takes its time coming back up, so that it doesn't
+/// pump up and down during a burst of effects.
+const DUCK_ATTACK_SPEED: f32 = 3.0;
+const DUCK_RELEASE_SPEED: f32 = 0.5;
+/// how long effects without a known length are assumed to play for.
+const UNKNOWN_EFFECT_DURATION: Duration = Duration::from_secs(1);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AudioBus {
+    Master,
+    Music,
+    Effects,
+    Ambience,
+    Ui,
+}
+
+impl AudioBus {
+    pub const ALL: [AudioBus; 5] = [
+        AudioBus::Master,
+        AudioBus::Music,
+        AudioBus::Effects,
+        AudioBus::Ambience,
+        AudioBus::Ui,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AudioBus::Master => "master",
+            AudioBus::Music => "music",
+            AudioBus::Effects => "effects",
+            AudioBus::Ambience => "ambience",
+            AudioBus::Ui => "ui",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|bus| bus.name() == name)
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// the volume of every bus, from 0 to 1.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub music: f32,
+    pub effects: f32,
+    pub ambience: f32,
+    pub ui: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            music: 0.7,
+            effects: 1.0,
+            ambience: 1.0,
+            ui: 1.0,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub fn volume(&self, bus: AudioBus) -> f32 {
+        match bus {
+            AudioBus::Master => self.master,
+            AudioBus::Music => self.music,
+            AudioBus::Effects => self.effects,
+            AudioBus::Ambience => self.ambience,
+            AudioBus::Ui => self.ui,
+        }
+    }
+
+    pub fn volume_mut(&mut self, bus: AudioBus) -> &mut f32 {
+        match bus {
+            AudioBus::Master => &mut self.master,
+            AudioBus::Music => &mut self.music,
+            AudioBus::Effects => &mut self.effects,
+            AudioBus::Ambience => &mut self.ambience,
+            AudioBus::Ui => &mut self.ui,
+        }
+    }
+}
+
+/// a gain that's written on the main thread and read by sounds on the audio
+/// thread.
+#[derive(Debug)]
+pub struct SharedGain(AtomicU32);
+
+impl SharedGain {
+    pub fn new(gain: f32) -> Self {
+        Self(AtomicU32::new(gain.to_bits()))
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, gain: f32) {
+        self.0.store(gain.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// a source that's playing through a mixer bus, or through some other gain
+/// that can change while it plays.
+pub struct BusSource<S> {
+    inner: S,
+    gain: Arc<SharedGain>,
+}
+
+impl<S> BusSource<S> {
+    /// plays `source` at `gain`, on top of whichever bus it's routed through.
+    pub fn with_gain(source: S, gain: Arc<SharedGain>) -> Self {
+        Self {
+            inner: source,
+            gain,
+        }
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for BusSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.inner.next().map(|sample| sample * self.gain.get())
+    }
+}
+
+impl<S: Source<Item = f32>> Source for BusSource<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+#[derive(Debug)]
+pub struct AudioMixer {
+    /// the faded volume of each bus, indexed by `AudioBus::index`.
+    volumes: [f32; 5],
+    /// the gain each bus's sounds are actually played at, which includes the
+    /// master volume and ducking.
+    gains: [Arc<SharedGain>; 5],
+    /// how much music is turned down by ducking, from 1 for not at all down
+    /// to `DUCK_VOLUME`.
+    duck: f32,
+    /// when each of the effects that are playing will finish.
+    playing_effects: Vec<Instant>,
+    last_update: Option<Instant>,
+}
+
+impl AudioMixer {
+    pub fn new(settings: &AudioSettings) -> Self {
+        let mut mixer = Self {
+            volumes: AudioBus::ALL.map(|bus| settings.volume(bus)),
+            gains: AudioBus::ALL.map(|_| Arc::new(SharedGain::new(1.0))),
+            duck: 1.0,
+            playing_effects: vec![],
+            last_update: None,
+        };
+        mixer.update_gains();
+        mixer
+    }
+
+    /// plays `source` through `bus`. `now` is when the source starts playing.
+    pub fn route<S>(&mut self, bus: AudioBus, source: S, now: Instant) -> BusSource<S>
+    where
+        S: Source<Item = f32>,
+    {
+        if bus == AudioBus::Effects {
+            let duration = source.total_duration();
+            let duration = duration.unwrap_or(UNKNOWN_EFFECT_DURATION);
+            self.playing_effects.push(now + duration);
+        }
+
+        BusSource {
+            inner: source,
+            gain: Arc::clone(&self.gains[bus.index()]),
+        }
+    }
+
+    /// the gain that sounds on `bus` are played at right now.
+    pub fn gain(&self, bus: AudioBus) -> f32 {
+        self.gains[bus.index()].get()
+    }
+
+    pub fn is_ducking(&self) -> bool {
+        self.playing_effects.len() >= DUCK_THRESHOLD
+    }
+
+    fn update_gains(&mut self) {
+        let master = self.volumes[AudioBus::Master.index()];
+        for bus in AudioBus::ALL {
+            let gain = match bus {
+                AudioBus::Master => master,
+                AudioBus::Music => master * self.volumes[bus.index()] * self.duck,
+                _ => master * self.volumes[bus.index()],
+            };
+            self.gains[bus.index()].set(gain);
+        }
+    }
+
+    /// fades every bus towards its volume in `settings`, and ducks music if
+    /// there are enough effects playing.
+    pub fn update(&mut self, settings: &AudioSettings, now: Instant) {
+        let dt = match self.last_update.replace(now) {
+            Some(last) => now.saturating_duration_since(last).as_secs_f32(),
+            None => 0.0,
+        };
+
+        self.playing_effects.retain(|&end| end > now);
+        for bus in AudioBus::ALL {
+            let volume = &mut self.volumes[bus.index()];
+            *volume = approach(*volume, settings.volume(bus), FADE_SPEED * dt);
+        }
+
+        self.duck = match self.is_ducking() {
+            true => approach(self.duck, DUCK_VOLUME, DUCK_ATTACK_SPEED * dt),
+            false => approach(self.duck, 1.0, DUCK_RELEASE_SPEED * dt),
+        };
+        self.update_gains();
+    }
+}
+
+/// moves `value` towards `target`, by no more than `step`.
+fn approach(value: f32, target: f32, step: f32) -> f32 {
+    match value < target {
+        true => f32::min(value + step, target),
+        false => f32::max(value - step, target),
+    }
+}
+
+pub fn update_audio_mixer(settings: Res<AudioSettings>, mut mixer: ResMut<AudioMixer>) {
+    mixer.update(&settings, Instant::now());
+}
+
+/// saves the audio settings whenever they change.
+pub fn save_audio_settings(
+    settings: Res<AudioSettings>,
+    mut saved: Local<Option<AudioSettings>>,
+    mut settings_file: ResMut<SettingsFile>,
+) {
+    match saved.replace(settings.clone()) {
+        // these are the settings that were loaded from the file
+        None => return,
+        Some(previous) if previous == *settings => return,
+        Some(_) => {}
+    }
+
+    let audio_settings = settings.clone();
+    if let Err(err) = settings_file.update(|file| file.audio = audio_settings) {
+        log::error!("failed to save audio settings: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volumes_fade_and_music_ducks() {
+        let mut settings = AudioSettings::default();
+        let mut mixer = AudioMixer::new(&settings);
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        mixer.update(&settings, at(0));
+        assert_eq!(mixer.gain(AudioBus::Effects), 1.0);
+
+        // turning the master volume down fades everything out together
+        settings.master = 0.0;
+        mixer.update(&settings, at(100));
+        let halfway = mixer.gain(AudioBus::Effects);
+        assert!(halfway > 0.0 && halfway < 1.0);
+        mixer.update(&settings, at(1000));
+        assert_eq!(mixer.gain(AudioBus::Effects), 0.0);
+        assert_eq!(mixer.gain(AudioBus::Music), 0.0);
+
+        // a burst of effects ducks the music until they've finished
+        settings.master = 1.0;
+        mixer.update(&settings, at(2000));
+        let music = mixer.gain(AudioBus::Music);
+        assert_eq!(music, settings.music);
+        // (silence goes on forever, so these count as playing for
+        // `UNKNOWN_EFFECT_DURATION`)
+        for _ in 0..DUCK_THRESHOLD {
+            let effect = ambisonic::rodio::source::Zero::<f32>::new(1, 44100);
+            mixer.route(AudioBus::Effects, effect, at(2000));
+        }
+        assert!(mixer.is_ducking());
+        mixer.update(&settings, at(2500));
+        assert!((mixer.gain(AudioBus::Music) - DUCK_VOLUME * music).abs() < 1e-4);
+        assert_eq!(mixer.gain(AudioBus::Effects), 1.0);
+
+        mixer.update(&settings, at(4500));
+        assert!(!mixer.is_ducking());
+        mixer.update(&settings, at(10000));
+        assert_eq!(mixer.gain(AudioBus::Music), music);
+    }
+}
@@ -0,0 +1,49 @@
+use crate::client::render::{
+    mesher::{generation::mesh_box, TerrainMesh},
+    renderer::{MeshHandle, RenderMeshComponent, SharedMeshContext},
+};
+use notcraft_common::{
+    mob::{Mob, MobSettings},
+    prelude::*,
+    world::VoxelWorld,
+};
+use std::sync::Arc;
+
+/// all mobs currently look the same, so they all share a single mesh.
+#[derive(Default)]
+struct MobMesh(Option<MeshHandle<TerrainMesh>>);
+
+fn attach_mob_meshes(
+    mut cmd: Commands,
+    mut mob_mesh: Local<MobMesh>,
+    settings: Res<MobSettings>,
+    voxel_world: Res<Arc<VoxelWorld>>,
+    mesh_context: Res<Arc<SharedMeshContext<TerrainMesh>>>,
+    query: Query<Entity, Added<Mob>>,
+) {
+    query.for_each(|entity| {
+        let handle = mob_mesh.0.get_or_insert_with(|| {
+            let registry = &voxel_world.registry;
+            let tex_id = registry
+                .texture_pool("test_mob")
+                .or_else(|| registry.texture_pool("unknown"))
+                .map(|pool| registry.pool_textures(pool)[0])
+                .unwrap_or_default();
+
+            let size = (16.0 * settings.dimensions).map(|extent| extent.round() as u16);
+            mesh_context.upload(mesh_box(size, tex_id))
+        });
+
+        cmd.entity(entity)
+            .insert(RenderMeshComponent::new(handle.clone()));
+    });
+}
+
+#[derive(Debug, Default)]
+pub struct MobRenderPlugin {}
+
+impl Plugin for MobRenderPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(attach_mob_meshes.system());
+    }
+}
@@ -1,6 +1,35 @@
 pub mod audio;
+pub mod block_tooltip;
 pub mod camera;
+pub mod commands;
+pub mod compass;
+pub mod console;
+pub mod container;
+pub mod crafting;
+pub mod crash;
 pub mod debug;
+pub mod explosion;
+pub mod footsteps;
+pub mod frame_limiter;
+pub mod free_camera;
 pub mod input;
+pub mod inspector;
+pub mod item_drop;
 pub mod loader;
+pub mod loading;
+pub mod logging;
+pub mod menu;
+pub mod minimap;
+pub mod mixer;
+pub mod mob;
+pub mod particles;
+pub mod remote_player;
 pub mod render;
+pub mod replay;
+pub mod screenshot;
+pub mod settings;
+pub mod sign;
+pub mod text_input;
+pub mod texture_pack;
+pub mod toast;
+pub mod window;
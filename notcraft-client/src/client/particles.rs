@@ -0,0 +1,249 @@
+//! little bits of blocks that the player kicks up while moving around: dust
+//! from the ground while sprinting, and splashes while wading through shallow
+//! water. each particle shows a small piece of the texture of the block it came
+//! from.
+
+use crate::{
+    client::{camera::CurrentCamera, render::renderer::ImmediateQuads},
+    PlayerController,
+};
+use nalgebra::{Point3, Vector3};
+use notcraft_common::{
+    clock::GameTime,
+    movement::MovementCommands,
+    physics::{AabbCollider, RigidBody},
+    prelude::*,
+    transform::Transform,
+    world::{
+        chunk::ChunkAccess,
+        registry::{BlockRegistry, TextureId},
+        BlockPos, WorldPos,
+    },
+    Side,
+};
+use rand::Rng;
+use std::sync::Arc;
+
+/// the most particles there can be at once.
+const MAX_PARTICLES: usize = 512;
+/// how many particles are kicked up for each second spent sprinting or wading.
+const DUST_PER_SECOND: f32 = 24.0;
+const SPLASH_PER_SECOND: f32 = 40.0;
+/// how fast, in blocks per second, the player has to be moving along the
+/// ground before anything gets kicked up.
+const MIN_SPEED: f32 = 1.0;
+/// how fast particles fall, in blocks per second squared.
+const PARTICLE_GRAVITY: f32 = 16.0;
+/// how much of its block's texture each particle shows, from 0 to 1.
+const UV_SIZE: f32 = 0.25;
+
+#[derive(Clone, Debug, PartialEq)]
+struct BlockParticle {
+    pos: Point3<f32>,
+    velocity: Vector3<f32>,
+    age: f32,
+    lifetime: f32,
+    /// the length of the particle's sides, in blocks.
+    size: f32,
+    tex: TextureId,
+    /// the corner of the part of `tex` that this particle shows.
+    uv_min: [f32; 2],
+}
+
+#[derive(Debug, Default)]
+pub struct BlockParticles {
+    particles: Vec<BlockParticle>,
+    /// how many particles should have been spawned but weren't, since only
+    /// whole particles can be spawned each frame.
+    dust_owed: f32,
+    splash_owed: f32,
+}
+
+/// the texture on top of the block at `pos`, if it has one.
+fn top_texture(
+    access: &mut ChunkAccess,
+    registry: &BlockRegistry,
+    pos: BlockPos,
+) -> Option<TextureId> {
+    let textures = registry.get(access.block(pos)?).block_textures()?;
+    let pool = textures.first()?[Side::Top];
+    registry.pool_textures(pool).first().copied()
+}
+
+/// takes the whole number of particles out of `owed`.
+fn take_whole(owed: &mut f32) -> usize {
+    let count = owed.floor();
+    *owed -= count;
+    count as usize
+}
+
+fn spawn_particle<R: Rng>(
+    particles: &mut BlockParticles,
+    rng: &mut R,
+    pos: Point3<f32>,
+    velocity: Vector3<f32>,
+    tex: TextureId,
+) {
+    if particles.particles.len() >= MAX_PARTICLES {
+        return;
+    }
+    // a random pixel-aligned piece of the texture
+    let mut corner = || (rng.gen_range(0, 12) as f32) / 16.0;
+    let uv_min = [corner(), corner()];
+    particles.particles.push(BlockParticle {
+        pos,
+        velocity,
+        age: 0.0,
+        lifetime: rng.gen_range(0.3, 0.7),
+        size: rng.gen_range(0.06, 0.14),
+        tex,
+        uv_min,
+    });
+}
+
+fn spawn_movement_particles(
+    time: Res<GameTime>,
+    player_controller: Res<PlayerController>,
+    mut access: ResMut<ChunkAccess>,
+    mut particles: ResMut<BlockParticles>,
+    query: Query<(
+        &Transform,
+        &AabbCollider,
+        &MovementCommands,
+        Option<&RigidBody>,
+    )>,
+) {
+    let dt = time.delta_seconds();
+    let (transform, collider, commands, rigidbody) = match query.get(player_controller.player) {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    // flying players don't have a rigidbody, and don't touch anything
+    let velocity = match rigidbody {
+        Some(rigidbody) => rigidbody.velocity,
+        None => return,
+    };
+    let ground_velocity = vector![velocity.x, 0.0, velocity.z];
+    if ground_velocity.norm() < MIN_SPEED {
+        particles.dust_owed = 0.0;
+        particles.splash_owed = 0.0;
+        return;
+    }
+
+    let registry = Arc::clone(access.registry());
+    let aabb = collider.aabb.transformed(transform);
+    let center = aabb.center();
+    let block_at = |y: f32| BlockPos::from(WorldPos::new([center.x, y, center.z]));
+    let mut rng = rand::thread_rng();
+    let dims = aabb.dimensions();
+    let underfoot = |rng: &mut rand::rngs::ThreadRng, y: f32| {
+        let dx = rng.gen_range(-0.5, 0.5) * dims.x;
+        let dz = rng.gen_range(-0.5, 0.5) * dims.z;
+        point![center.x + dx, y, center.z + dz]
+    };
+
+    let sprinting = commands
+        .latest()
+        .is_some_and(|command| command.input.sprint);
+    if sprinting && collider.on_ground && !collider.in_liquid {
+        let ground = block_at(aabb.min.y - 0.05);
+        if let Some(tex) = top_texture(&mut access, &registry, ground) {
+            particles.dust_owed += DUST_PER_SECOND * dt;
+            for _ in 0..take_whole(&mut particles.dust_owed) {
+                // dust gets flicked up and back, behind the player
+                let velocity = vector![
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(1.5, 3.0),
+                    rng.gen_range(-1.0, 1.0)
+                ] - 0.15 * ground_velocity;
+                let pos = underfoot(&mut rng, aabb.min.y + 0.05);
+                spawn_particle(&mut particles, &mut rng, pos, velocity, tex);
+            }
+        }
+    } else {
+        particles.dust_owed = 0.0;
+    }
+
+    // shallow water is deep enough to reach the player's feet, but not their
+    // waist.
+    let feet = block_at(aabb.min.y + 0.05);
+    let waist = block_at(aabb.min.y + 1.0);
+    let is_liquid = |access: &mut ChunkAccess, pos| {
+        access
+            .block(pos)
+            .is_some_and(|id| registry.get(id).liquid())
+    };
+    if is_liquid(&mut access, feet) && !is_liquid(&mut access, waist) {
+        if let Some(tex) = top_texture(&mut access, &registry, feet) {
+            particles.splash_owed += SPLASH_PER_SECOND * dt;
+            for _ in 0..take_whole(&mut particles.splash_owed) {
+                let velocity = vector![
+                    rng.gen_range(-1.5, 1.5),
+                    rng.gen_range(2.5, 4.5),
+                    rng.gen_range(-1.5, 1.5)
+                ] + 0.2 * ground_velocity;
+                let pos = underfoot(&mut rng, feet.y as f32 + 0.9);
+                spawn_particle(&mut particles, &mut rng, pos, velocity, tex);
+            }
+        }
+    } else {
+        particles.splash_owed = 0.0;
+    }
+}
+
+fn update_block_particles(time: Res<GameTime>, mut particles: ResMut<BlockParticles>) {
+    let dt = time.delta_seconds();
+    particles.particles.retain_mut(|particle| {
+        particle.velocity.y -= PARTICLE_GRAVITY * dt;
+        particle.pos += particle.velocity * dt;
+        particle.age += dt;
+        particle.age < particle.lifetime
+    });
+}
+
+fn draw_block_particles(
+    camera: CurrentCamera,
+    particles: Res<BlockParticles>,
+    mut quads: ResMut<ImmediateQuads>,
+) {
+    let transform = match camera.transform() {
+        Some(transform) => transform,
+        None => return,
+    };
+
+    // particles always face the camera
+    let rotation = transform.rotation.to_quaternion();
+    let (right, up) = (rotation * Vector3::x(), rotation * Vector3::y());
+
+    for particle in particles.particles.iter() {
+        // particles shrink away towards the end of their life
+        let t = particle.age / particle.lifetime;
+        let half = 0.5 * particle.size * f32::min(1.0, 2.0 * (1.0 - t));
+        let (right, up) = (right * half, up * half);
+        let [u, v] = particle.uv_min;
+        let pos = particle.pos;
+        quads.textured_quad(
+            [
+                pos - right - up,
+                pos + right - up,
+                pos + right + up,
+                pos - right + up,
+            ],
+            [[u, v + UV_SIZE], [u + UV_SIZE, v]],
+            particle.tex,
+            [1.0; 4],
+        );
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BlockParticlePlugin {}
+
+impl Plugin for BlockParticlePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<BlockParticles>();
+        app.add_system(spawn_movement_particles.system());
+        app.add_system(update_block_particles.system());
+        app.add_system(draw_block_particles.system());
+    }
+}
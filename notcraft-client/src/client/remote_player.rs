@@ -0,0 +1,198 @@
+//! drawing other players: a box for their body, another for their head that
+//! looks up and down with them, and their name floating above it all.
+
+use crate::client::{
+    camera::CurrentCamera,
+    crafting::CraftingUiLabel,
+    menu::MenuLabel,
+    render::{
+        font,
+        mesher::{generation::mesh_box, TerrainMesh},
+        renderer::{ImmediateUi, MeshHandle, RenderMeshComponent, SharedMeshContext},
+    },
+    window::UiScale,
+};
+use glium::Display;
+use nalgebra::{vector, Point3, Vector3};
+use notcraft_common::{
+    net::remote_player::{RemotePlayer, RemotePlayerInterpolation},
+    prelude::*,
+    transform::{EulerAngles, Transform},
+    util,
+    world::{chunk::ChunkAccess, trace_ray, Ray3, VoxelWorld},
+};
+use std::{rc::Rc, sync::Arc};
+
+// remote players are positioned by the center of their collider, the same as
+// the local player, which is 1.7 blocks tall.
+const FEET_OFFSET: f32 = -0.85;
+const BODY_DIMENSIONS: Vector3<f32> = vector![0.6, 1.25, 0.35];
+const HEAD_SIZE: f32 = 0.45;
+
+/// nametags start fading out past this many blocks away...
+const NAMETAG_FADE_START: f32 = 24.0;
+/// ...and are gone completely by this many.
+const NAMETAG_FADE_END: f32 = 32.0;
+const NAMETAG_PADDING: f32 = 1.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum BodyPart {
+    Body,
+    Head,
+}
+
+/// one box of a remote player's model. each part is its own entity so that it
+/// can be drawn with its own transform, and follows `owner` around.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct ModelPart {
+    owner: Entity,
+    part: BodyPart,
+}
+
+/// every remote player looks the same, so they all share these meshes.
+#[derive(Default)]
+struct PlayerMeshes(Option<[MeshHandle<TerrainMesh>; 2]>);
+
+fn attach_player_models(
+    mut cmd: Commands,
+    mut meshes: Local<PlayerMeshes>,
+    voxel_world: Res<Arc<VoxelWorld>>,
+    mesh_context: Res<Arc<SharedMeshContext<TerrainMesh>>>,
+    query: Query<Entity, Added<RemotePlayer>>,
+) {
+    query.for_each(|owner| {
+        let [body, head] = meshes.0.get_or_insert_with(|| {
+            let registry = &voxel_world.registry;
+            let tex_id = registry
+                .texture_pool("player")
+                .or_else(|| registry.texture_pool("unknown"))
+                .map(|pool| registry.pool_textures(pool)[0])
+                .unwrap_or_default();
+
+            let mesh = |size: Vector3<f32>| {
+                let size = (16.0 * size).map(|extent| extent.round() as u16);
+                mesh_context.upload(mesh_box(size, tex_id))
+            };
+            [
+                mesh(BODY_DIMENSIONS),
+                mesh(vector![HEAD_SIZE, HEAD_SIZE, HEAD_SIZE]),
+            ]
+        });
+
+        for (part, mesh) in [(BodyPart::Body, &*body), (BodyPart::Head, &*head)] {
+            cmd.spawn()
+                .insert(Transform::default())
+                .insert(ModelPart { owner, part })
+                .insert(RenderMeshComponent::new(mesh.clone()));
+        }
+    });
+}
+
+/// where the model part's mesh goes. box meshes have a corner at their origin,
+/// so they're shifted over by half their width to center them on whatever
+/// they turn around.
+fn pose_part(part: BodyPart, owner: &Transform, pitch: f32) -> Transform {
+    let feet = owner.translation.vector + vector![0.0, FEET_OFFSET, 0.0];
+    let yaw = owner.rotation.yaw;
+    let (pivot, rotation, size) = match part {
+        BodyPart::Body => (feet, EulerAngles::new(0.0, yaw, 0.0), BODY_DIMENSIONS),
+        BodyPart::Head => (
+            feet + vector![0.0, BODY_DIMENSIONS.y, 0.0],
+            EulerAngles::new(pitch, yaw, 0.0),
+            vector![HEAD_SIZE, HEAD_SIZE, HEAD_SIZE],
+        ),
+    };
+
+    let corner = rotation.to_quaternion() * vector![-size.x / 2.0, 0.0, -size.z / 2.0];
+    Transform {
+        rotation,
+        ..Transform::to(Point3::from(pivot + corner))
+    }
+}
+
+fn pose_player_models(
+    mut cmd: Commands,
+    owners: Query<(&Transform, &RemotePlayer), Without<ModelPart>>,
+    parts: Query<(Entity, &ModelPart, &mut Transform)>,
+) {
+    parts.for_each_mut(
+        |(entity, part, mut transform)| match owners.get(part.owner) {
+            Ok((owner, player)) => *transform = pose_part(part.part, owner, player.pitch),
+            // the player left
+            Err(_) => cmd.entity(entity).despawn(),
+        },
+    );
+}
+
+fn draw_nametags(
+    camera: CurrentCamera,
+    display: NonSend<Rc<Display>>,
+    ui_scale: Res<UiScale>,
+    mut access: ResMut<ChunkAccess>,
+    mut ui: ResMut<ImmediateUi>,
+    players: Query<(&Transform, &RemotePlayer)>,
+) {
+    let dimensions = display.get_framebuffer_dimensions();
+    let view_proj = camera.projection(dimensions).to_homogeneous() * camera.view();
+    let half_width = dimensions.0 as f32 / ui_scale.0 / 2.0;
+    let half_height = dimensions.1 as f32 / ui_scale.0 / 2.0;
+    let eye = camera.pos();
+
+    players.for_each(|(transform, player)| {
+        let above_head = FEET_OFFSET + BODY_DIMENSIONS.y + HEAD_SIZE + 0.3;
+        let tag = Point3::from(transform.translation.vector + vector![0.0, above_head, 0.0]);
+        let to_tag = tag - eye;
+        let distance = to_tag.norm();
+        if distance >= NAMETAG_FADE_END {
+            return;
+        }
+
+        // the ray stops a little short of the tag, so that it doesn't get hidden
+        // by whatever block the tag itself is sitting in.
+        let ray = Ray3 {
+            origin: eye,
+            direction: to_tag / distance,
+        };
+        if distance > 1.0 && trace_ray(&mut access, ray, distance - 1.0).is_some() {
+            return;
+        }
+
+        let clip = view_proj * tag.to_homogeneous();
+        // behind the camera
+        if clip.w <= 0.0 {
+            return;
+        }
+        let [x, y] = [clip.x / clip.w * half_width, clip.y / clip.w * half_height];
+
+        let opacity = 1.0 - util::invlerp(NAMETAG_FADE_START, NAMETAG_FADE_END, distance);
+        let opacity = opacity.clamp(0.0, 1.0);
+        let width = font::text_width(&player.name);
+        let min = [x - width / 2.0, y];
+        let max = [x + width / 2.0, y + font::GLYPH_HEIGHT];
+        let background = [0.0, 0.0, 0.0, 0.4 * opacity];
+        ui.rect(
+            [min[0] - NAMETAG_PADDING, min[1] - NAMETAG_PADDING],
+            [max[0] + NAMETAG_PADDING, max[1] + NAMETAG_PADDING],
+            background,
+        );
+        ui.text(min, &player.name, [1.0, 1.0, 1.0, opacity]);
+    });
+}
+
+#[derive(Debug, Default)]
+pub struct RemotePlayerRenderPlugin {}
+
+impl Plugin for RemotePlayerRenderPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(attach_player_models.system());
+        app.add_system(pose_player_models.system().after(RemotePlayerInterpolation));
+        // nametags are part of the world, so they go under the rest of the UI
+        app.add_system(
+            draw_nametags
+                .system()
+                .after(RemotePlayerInterpolation)
+                .before(MenuLabel)
+                .before(CraftingUiLabel),
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! a tiny built-in pixel font, so that the UI can draw text without needing any
+//! font files.
+//!
+//! every glyph is 3 pixels wide and 5 pixels tall. lowercase letters are drawn
+//! as uppercase, and anything without a glyph is drawn as a `?`.
+
+pub const GLYPH_WIDTH: f32 = 3.0;
+pub const GLYPH_HEIGHT: f32 = 5.0;
+/// how far apart the left edges of two glyphs next to each other are.
+pub const GLYPH_ADVANCE: f32 = GLYPH_WIDTH + 1.0;
+
+/// the rows of a glyph, from top to bottom. the leftmost pixel of each row is
+/// bit 2.
+pub type Glyph = [u8; 5];
+
+pub fn glyph(ch: char) -> Glyph {
+    match ch.to_ascii_uppercase() {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        ';' => [0b000, 0b010, 0b000, 0b010, 0b100],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        '"' => [0b101, 0b101, 0b000, 0b000, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '[' => [0b011, 0b010, 0b010, 0b010, 0b011],
+        ']' => [0b110, 0b010, 0b010, 0b010, 0b110],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '*' => [0b000, 0b101, 0b010, 0b101, 0b000],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+/// how wide `text` is when drawn, without the gap after the last glyph.
+pub fn text_width(text: &str) -> f32 {
+    match text.chars().count() {
+        0 => 0.0,
+        len => len as f32 * GLYPH_ADVANCE - 1.0,
+    }
+}
+
+/// calls `func` with the left end, right end, and row of every horizontal run
+/// of lit pixels in a glyph. rows count down from the top.
+pub fn glyph_runs<F>(glyph: Glyph, mut func: F)
+where
+    F: FnMut(u8, u8, u8),
+{
+    for (row, bits) in glyph.iter().enumerate() {
+        let mut column = 0;
+        while column < 3 {
+            if bits & (0b100 >> column) == 0 {
+                column += 1;
+                continue;
+            }
+            let start = column;
+            while column < 3 && bits & (0b100 >> column) != 0 {
+                column += 1;
+            }
+            func(start, column, row as u8);
+        }
+    }
+}
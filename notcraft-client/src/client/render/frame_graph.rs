@@ -0,0 +1,306 @@
+//! the order that render passes run in, worked out from what they draw into.
+//!
+//! each pass says which render targets it reads and which it writes, and the
+//! graph puts every pass that writes a target before the passes that read it.
+//! passes that write the same target run in the order they were added, so that
+//! e.g. the sky is drawn into the world before the terrain is drawn over it.
+//!
+//! a pass can also resolve one target into another, which copies a
+//! multisampled target into one that can be sampled. passes aren't allowed to
+//! sample multisampled targets themselves, which the graph checks once the
+//! targets exist.
+//!
+//! adding a new pass is just a matter of saying what it reads and writes, and
+//! the passes around it don't need to know about it.
+
+use super::renderer::{RenderLabel, RenderTargets};
+use bevy_ecs::schedule::ParallelSystemDescriptor;
+use notcraft_common::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PassDesc {
+    name: &'static str,
+    reads: Vec<String>,
+    writes: Vec<String>,
+    /// `(source, target)` pairs this pass resolves, which are also in `reads`
+    /// and `writes`.
+    resolves: Vec<(String, String)>,
+}
+
+impl PassDesc {
+    pub fn reads(&mut self, target: impl Into<String>) -> &mut Self {
+        self.reads.push(target.into());
+        self
+    }
+
+    pub fn writes(&mut self, target: impl Into<String>) -> &mut Self {
+        self.writes.push(target.into());
+        self
+    }
+
+    /// copies `source` into `target` when this pass runs, averaging the samples
+    /// of each pixel if `source` is multisampled.
+    pub fn resolves(&mut self, source: impl Into<String>, target: impl Into<String>) -> &mut Self {
+        let (source, target) = (source.into(), target.into());
+        self.reads.push(source.clone());
+        self.writes.push(target.clone());
+        self.resolves.push((source, target));
+        self
+    }
+
+    fn samples(&self, target: &str) -> bool {
+        let resolved = self.resolves.iter().any(|(source, _)| source == target);
+        !resolved && self.reads.iter().any(|read| read == target)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FrameGraphBuilder {
+    passes: Vec<PassDesc>,
+}
+
+impl FrameGraphBuilder {
+    pub fn pass(&mut self, name: &'static str) -> &mut PassDesc {
+        self.passes.push(PassDesc {
+            name,
+            ..Default::default()
+        });
+        self.passes.last_mut().unwrap()
+    }
+
+    /// works out which passes each pass has to run after, and puts them in an
+    /// order that satisfies all of that.
+    pub fn build(self) -> Result<FrameGraph> {
+        let mut names = HashSet::new();
+        for pass in self.passes.iter() {
+            if !names.insert(pass.name) {
+                bail!("render pass '{}' was added twice", pass.name);
+            }
+        }
+
+        let mut writers = HashMap::<&str, Vec<usize>>::new();
+        for (idx, pass) in self.passes.iter().enumerate() {
+            for target in pass.writes.iter() {
+                let pass_writers = writers.entry(target).or_default();
+                if pass_writers.last() != Some(&idx) {
+                    pass_writers.push(idx);
+                }
+            }
+        }
+
+        let mut dependencies = vec![Vec::<usize>::new(); self.passes.len()];
+        for (idx, pass) in self.passes.iter().enumerate() {
+            for target in pass.writes.iter() {
+                let earlier = writers[&**target]
+                    .iter()
+                    .take_while(|&&writer| writer != idx);
+                dependencies[idx].extend(earlier.last());
+            }
+            for target in pass.reads.iter() {
+                let pass_writers = match writers.get(&**target) {
+                    Some(pass_writers) => pass_writers,
+                    None => bail!(
+                        "render pass '{}' reads '{}', but no pass writes it",
+                        pass.name,
+                        target
+                    ),
+                };
+                // a pass that reads and writes the same target only has to wait
+                // for the passes that wrote it before, which its place among
+                // the writers already takes care of
+                if !pass_writers.contains(&idx) {
+                    dependencies[idx].extend(pass_writers);
+                }
+            }
+            dependencies[idx].sort_unstable();
+            dependencies[idx].dedup();
+        }
+
+        // always picks the earliest added pass that's ready, so that passes
+        // that don't care about each other stay in the order they were added.
+        let mut order = Vec::with_capacity(self.passes.len());
+        let mut done = vec![false; self.passes.len()];
+        while order.len() < self.passes.len() {
+            let ready = (0..self.passes.len()).find(|&idx| {
+                !done[idx] && dependencies[idx].iter().all(|&dependency| done[dependency])
+            });
+            match ready {
+                Some(idx) => {
+                    done[idx] = true;
+                    order.push(idx);
+                }
+                None => {
+                    let stuck = (0..self.passes.len()).filter(|&idx| !done[idx]);
+                    let stuck = stuck.map(|idx| self.passes[idx].name).collect::<Vec<_>>();
+                    bail!("render passes {:?} depend on each other", stuck);
+                }
+            }
+        }
+
+        let after = order
+            .iter()
+            .map(|&idx| {
+                let dependencies = dependencies[idx].iter();
+                dependencies.map(|&dependency| self.passes[dependency].name)
+            })
+            .map(Iterator::collect)
+            .collect();
+        let mut passes = self.passes;
+        let passes = order.iter().map(|&idx| std::mem::take(&mut passes[idx]));
+
+        Ok(FrameGraph {
+            passes: passes.collect(),
+            after,
+        })
+    }
+}
+
+/// every render pass, in the order they run in.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameGraph {
+    passes: Vec<PassDesc>,
+    /// the names of the passes that each pass runs after.
+    after: Vec<Vec<&'static str>>,
+}
+
+impl FrameGraph {
+    pub fn builder() -> FrameGraphBuilder {
+        FrameGraphBuilder::default()
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.passes.iter().position(|pass| pass.name == name)
+    }
+
+    pub fn order(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.passes.iter().map(|pass| pass.name)
+    }
+
+    /// the passes that `name` has to run after.
+    pub fn after(&self, name: &str) -> &[&'static str] {
+        match self.index(name) {
+            Some(idx) => &self.after[idx],
+            None => &[],
+        }
+    }
+
+    /// the `(source, target)` pairs that the pass `name` resolves.
+    pub fn resolves(&self, name: &str) -> impl Iterator<Item = (&str, &str)> + '_ {
+        let resolves = self.index(name).map(|idx| self.passes[idx].resolves.iter());
+        let resolves = resolves.into_iter().flatten();
+        resolves.map(|(source, target)| (&**source, &**target))
+    }
+
+    /// labels `system` as the pass `name`, and orders it after whichever passes
+    /// it depends on.
+    pub fn system<S>(&self, name: &'static str, system: S) -> ParallelSystemDescriptor
+    where
+        S: ParallelSystemDescriptorCoercion,
+    {
+        assert!(
+            self.index(name).is_some(),
+            "render pass '{}' isn't in the frame graph",
+            name
+        );
+        let after = self.after(name).iter();
+        after.fold(system.label(RenderLabel(name)), |system, &dependency| {
+            system.after(RenderLabel(dependency))
+        })
+    }
+
+    /// makes sure that every target the passes use exists, and that they're
+    /// only resolved into targets of the same size that aren't multisampled.
+    /// the targets are made again whenever the window is resized or MSAA is
+    /// turned on or off, so this needs to be checked again after that.
+    pub fn validate(&self, targets: &RenderTargets) -> Result<()> {
+        for pass in self.passes.iter() {
+            for target in pass.reads.iter().chain(pass.writes.iter()) {
+                let desc = targets.desc(target)?;
+                if desc.samples.is_some() && pass.samples(target) {
+                    bail!(
+                        "render pass '{}' samples '{}', which is multisampled and needs to be \
+                         resolved first",
+                        pass.name,
+                        target
+                    );
+                }
+            }
+
+            for (source, target) in pass.resolves.iter() {
+                let (source_desc, target_desc) = (targets.desc(source)?, targets.desc(target)?);
+                let problem = if target_desc.samples.is_some() {
+                    "is multisampled"
+                } else if targets.size(source)? != targets.size(target)? {
+                    "is a different size"
+                } else if source_desc.kind != target_desc.kind {
+                    "has different textures"
+                } else {
+                    continue;
+                };
+                bail!(
+                    "'{}' can't be resolved into '{}', which {}",
+                    source,
+                    target,
+                    problem
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(graph: &FrameGraph) -> Vec<&'static str> {
+        graph.order().collect()
+    }
+
+    #[test]
+    fn passes_run_after_what_they_read() {
+        let mut builder = FrameGraph::builder();
+        builder.pass("post").reads("world_resolved").writes("final");
+        builder.pass("terrain").reads("shadow").writes("world");
+        builder.pass("sky").writes("world");
+        builder.pass("resolve").resolves("world", "world_resolved");
+        builder
+            .pass("lighting")
+            .reads("world_resolved")
+            .writes("world_resolved");
+        builder.pass("shadows").writes("shadow");
+        let graph = builder.build().unwrap();
+
+        assert_eq!(
+            order(&graph),
+            ["shadows", "terrain", "sky", "resolve", "lighting", "post"]
+        );
+        assert_eq!(graph.after("terrain"), ["shadows"]);
+        // writers of the same target stay in the order they were added
+        assert_eq!(graph.after("sky"), ["terrain"]);
+        assert_eq!(graph.after("lighting"), ["resolve"]);
+        assert_eq!(graph.after("post"), ["resolve", "lighting"]);
+        assert_eq!(
+            graph.resolves("resolve").collect::<Vec<_>>(),
+            [("world", "world_resolved")]
+        );
+    }
+
+    #[test]
+    fn invalid_graphs_are_rejected() {
+        let mut builder = FrameGraph::builder();
+        builder.pass("a").reads("b").writes("a");
+        builder.pass("b").reads("a").writes("b");
+        assert!(builder.build().is_err());
+
+        let mut builder = FrameGraph::builder();
+        builder.pass("post").reads("world").writes("final");
+        assert!(builder.build().is_err());
+
+        let mut builder = FrameGraph::builder();
+        builder.pass("sky").writes("world");
+        builder.pass("sky").writes("world");
+        assert!(builder.build().is_err());
+    }
+}
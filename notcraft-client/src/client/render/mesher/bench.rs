@@ -0,0 +1,118 @@
+//! benchmarks for meshing representative chunks with each mesher.
+//!
+//! the client is a binary crate, so these can't go in a `benches` directory
+//! like the ones in `notcraft-common`. instead, they're ignored tests that
+//! drive criterion themselves, which can be run with:
+//!
+//! ```text
+//! cargo test --release -p notcraft mesher::bench -- --ignored --nocapture
+//! ```
+
+use super::{
+    generation::{ChunkNeighbors, LightingType, MeshCreationContext, MeshJob},
+    MesherMode,
+};
+use criterion::{black_box, BatchSize, Criterion};
+use notcraft_common::world::{
+    chunk::{Chunk, ChunkSection, ChunkSectionPos, CHUNK_LENGTH},
+    generation::{
+        preset::WorldGenPreset, ChunkGenerator, ClimateSampler, SurfaceHeighmapCache,
+        SurfaceHeightmap,
+    },
+    registry::load_registry,
+    ChunkPos, VoxelWorld,
+};
+use std::sync::Arc;
+
+const SEED: u64 = 1;
+
+/// generates the section at `pos` along with all of its neighbors, using the
+/// surface heights that `heights` gives for each column.
+fn make_world<F>(pos: ChunkSectionPos, mut heights: F) -> Arc<VoxelWorld>
+where
+    F: FnMut(ChunkPos) -> SurfaceHeightmap,
+{
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../resources/blocks.json");
+    let registry = load_registry(path).unwrap();
+    let generator = ChunkGenerator::new_default(&registry);
+
+    let world = VoxelWorld::new(&registry);
+    for x in pos.x - 1..=pos.x + 1 {
+        for z in pos.z - 1..=pos.z + 1 {
+            let column = ChunkPos { x, z };
+            let heights = heights(column);
+            let chunk = Chunk::initialize(column, heights.clone());
+            for y in pos.y - 1..=pos.y + 1 {
+                let data = generator.make_chunk(SEED, column.section(y), &heights);
+                let section = ChunkSection::initialize(column.section(y), data, &registry);
+                chunk.sections_mut().insert(y, Arc::new(section));
+            }
+            world.insert_chunk(Arc::new(chunk));
+        }
+    }
+
+    world
+}
+
+/// a section with the real, hilly surface running through it, and one deep
+/// enough underground for caves to be carved out of it.
+fn terrains() -> Vec<(&'static str, ChunkSectionPos, Arc<VoxelWorld>)> {
+    let cache = SurfaceHeighmapCache::default();
+    let preset = WorldGenPreset::default();
+    let surface_column = ChunkPos { x: 3, z: -2 };
+    let surface = cache.surface_heights(SEED, &preset, surface_column);
+    let surface_pos = surface_column.section(surface.min().div_euclid(CHUNK_LENGTH as i32));
+    let underground_pos = ChunkPos { x: 0, z: 0 }.section(0);
+
+    vec![
+        (
+            "surface",
+            surface_pos,
+            make_world(surface_pos, |column| {
+                cache.surface_heights(SEED, &preset, column)
+            }),
+        ),
+        (
+            "underground",
+            underground_pos,
+            make_world(underground_pos, |_| SurfaceHeightmap::flat(256)),
+        ),
+    ]
+}
+
+#[test]
+#[ignore = "benchmark"]
+fn meshing() {
+    let mut criterion = Criterion::default();
+    let mut group = criterion.benchmark_group("meshing");
+
+    let climate = Arc::new(ClimateSampler::new(SEED));
+    for (terrain, pos, world) in terrains() {
+        for mode in [MesherMode::Simple, MesherMode::Greedy] {
+            let name = format!("{}/{}", format!("{:?}", mode).to_lowercase(), terrain);
+            group.bench_function(name, |b| {
+                b.iter_batched(
+                    || {
+                        let neighbors = ChunkNeighbors::lock(&world, pos).unwrap();
+                        MeshCreationContext::new(
+                            pos,
+                            neighbors,
+                            &world.registry,
+                            &climate,
+                            LightingType::Smooth,
+                        )
+                    },
+                    |ctx| {
+                        let mut job = MeshJob::new(ctx, mode);
+                        while !job.step() {}
+                        black_box(job.finish())
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+
+    group.finish();
+    criterion.final_summary();
+}
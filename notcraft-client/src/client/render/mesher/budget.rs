@@ -0,0 +1,98 @@
+//! decides how many mesh jobs get started each frame.
+//!
+//! starting more jobs gets through a backlog of sections faster, but the jobs
+//! compete with the rest of the game for time, so the budget follows how long
+//! recent frames have been taking. it grows by one job at a time while frames
+//! are comfortably quick and there's a backlog to get through, shrinks slowly
+//! when frames are running long, and is cut in half when a frame spikes.
+
+use std::time::Duration;
+
+/// how long frames are allowed to take before meshing starts backing off.
+const TARGET_FRAME_TIME: f32 = 1.0 / 60.0;
+/// frames this much quicker than the target on average leave enough headroom
+/// for more jobs.
+const HEADROOM: f32 = 0.8;
+/// a single frame that takes this many times longer than the target is a
+/// spike.
+const SPIKE_FACTOR: f32 = 1.5;
+/// how much each frame counts towards the average frame time.
+const SMOOTHING: f32 = 0.1;
+
+const MIN_JOBS: usize = 1;
+const MAX_JOBS: usize = 32;
+const INITIAL_JOBS: usize = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshBudget {
+    jobs: usize,
+    /// the smoothed frame time, in seconds.
+    average_frame_time: f32,
+}
+
+impl Default for MeshBudget {
+    fn default() -> Self {
+        Self {
+            jobs: INITIAL_JOBS,
+            average_frame_time: TARGET_FRAME_TIME,
+        }
+    }
+}
+
+impl MeshBudget {
+    /// how many jobs can be started this frame.
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// adjusts the budget for a frame that took `frame_time`, with `backlog`
+    /// sections waiting to be meshed, and returns the new budget.
+    pub fn update(&mut self, frame_time: Duration, backlog: usize) -> usize {
+        let frame_time = frame_time.as_secs_f32();
+        self.average_frame_time += SMOOTHING * (frame_time - self.average_frame_time);
+
+        if frame_time > SPIKE_FACTOR * TARGET_FRAME_TIME {
+            self.jobs /= 2;
+        } else if self.average_frame_time > TARGET_FRAME_TIME {
+            self.jobs -= 1;
+        } else if self.average_frame_time < HEADROOM * TARGET_FRAME_TIME && backlog > self.jobs {
+            self.jobs += 1;
+        }
+
+        self.jobs = self.jobs.clamp(MIN_JOBS, MAX_JOBS);
+        self.jobs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_follows_frame_time() {
+        let quick = Duration::from_millis(5);
+        let slow = Duration::from_millis(20);
+        let spike = Duration::from_millis(100);
+        let mut budget = MeshBudget::default();
+
+        // nothing to do means no reason to grow, however quick frames are
+        for _ in 0..100 {
+            budget.update(quick, 0);
+        }
+        assert_eq!(budget.jobs(), INITIAL_JOBS);
+
+        for _ in 0..100 {
+            budget.update(quick, 1000);
+        }
+        assert_eq!(budget.jobs(), MAX_JOBS);
+
+        budget.update(spike, 1000);
+        assert_eq!(budget.jobs(), MAX_JOBS / 2);
+
+        // a run of slow frames drags the average up and the budget down
+        for _ in 0..100 {
+            budget.update(slow, 1000);
+        }
+        assert_eq!(budget.jobs(), MIN_JOBS);
+    }
+}
@@ -0,0 +1,117 @@
+//! a cache of recently made terrain meshes, so that sections which are
+//! unloaded and then loaded again without anything in them changing don't have
+//! to be meshed all over again.
+//!
+//! a section's mesh depends on its own blocks and light as well as the layer
+//! of blocks just outside of it, so each mesh is stored along with a hash of
+//! all of that, and is only reused when the hash still matches. sections are
+//! also thrown out of the cache whenever a block in or next to them changes.
+
+use super::TerrainMesh;
+use notcraft_common::world::chunk::ChunkSectionPos;
+use parking_lot::Mutex;
+use std::{collections::HashMap, sync::Arc};
+
+#[derive(Clone, Debug)]
+struct CachedMesh {
+    hash: u64,
+    mesh: TerrainMesh,
+    /// when this was last used, so that the least recently used mesh can be
+    /// thrown out when the cache is full.
+    last_used: u64,
+}
+
+#[derive(Debug)]
+pub struct MeshCache {
+    /// the most meshes that can be kept at once.
+    capacity: usize,
+    meshes: HashMap<ChunkSectionPos, CachedMesh>,
+    /// counts up every time the cache is used.
+    clock: u64,
+}
+
+pub type SharedMeshCache = Arc<Mutex<MeshCache>>;
+
+impl MeshCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            meshes: HashMap::with_capacity(capacity),
+            clock: 0,
+        }
+    }
+
+    /// the mesh that was made for `pos` the last time it had contents that
+    /// hashed to `hash`.
+    pub fn get(&mut self, pos: ChunkSectionPos, hash: u64) -> Option<TerrainMesh> {
+        self.clock += 1;
+        let cached = self.meshes.get_mut(&pos).filter(|cached| cached.hash == hash)?;
+        cached.last_used = self.clock;
+        Some(cached.mesh.clone())
+    }
+
+    pub fn insert(&mut self, pos: ChunkSectionPos, hash: u64, mesh: &TerrainMesh) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.clock += 1;
+        if !self.meshes.contains_key(&pos) && self.meshes.len() >= self.capacity {
+            let oldest = self
+                .meshes
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(&pos, _)| pos);
+            if let Some(oldest) = oldest {
+                self.meshes.remove(&oldest);
+            }
+        }
+
+        self.meshes.insert(pos, CachedMesh {
+            hash,
+            mesh: mesh.clone(),
+            last_used: self.clock,
+        });
+    }
+
+    /// throws out the meshes of `pos` and all of its neighbors, whose meshes
+    /// all depend on what's in `pos`.
+    pub fn invalidate(&mut self, pos: ChunkSectionPos) {
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    self.meshes.remove(&pos.offset([dx, dy, dz]));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_evicts_least_recently_used() {
+        let section = |x| ChunkSectionPos { x, y: 0, z: 0 };
+        let mesh = TerrainMesh::default();
+        let mut cache = MeshCache::new(2);
+
+        cache.insert(section(0), 10, &mesh);
+        cache.insert(section(10), 20, &mesh);
+        // a different hash means the section's contents changed
+        assert!(cache.get(section(0), 11).is_none());
+        assert!(cache.get(section(0), 10).is_some());
+
+        // the second section was used least recently, so it makes room
+        cache.insert(section(20), 30, &mesh);
+        assert_eq!(cache.meshes.len(), 2);
+        assert!(cache.get(section(10), 20).is_none());
+        assert!(cache.get(section(0), 10).is_some());
+
+        // changing a block next to a cached section throws it out too
+        cache.invalidate(section(1));
+        assert!(cache.get(section(0), 10).is_none());
+        assert!(cache.get(section(20), 30).is_some());
+    }
+}
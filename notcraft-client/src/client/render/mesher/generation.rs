@@ -5,7 +5,12 @@
 //! [`MeshBuilder`] is driven by the [`MeshCreationContext`], which holds all
 //! the state necessary to mesh a single chunk.
 
-use std::sync::Arc;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    str::FromStr,
+    sync::Arc,
+};
 
 use crossbeam_channel::Sender;
 use nalgebra::{Point3, Vector3};
@@ -14,15 +19,17 @@ use rand::{prelude::SliceRandom, rngs::SmallRng, FromEntropy};
 use notcraft_common::{
     prelude::*,
     world::{
-        chunk::{ChunkData, ChunkSectionPos, ChunkSectionSnapshot, CHUNK_LENGTH},
-        lighting::LightValue,
+        chunk::{ChunkData, ChunkSectionPos, ChunkSectionSnapshot, DirtyRegion, CHUNK_LENGTH},
+        generation::ClimateSampler,
+        lighting::{LightValue, FULL_SKY_LIGHT},
+        orientation::Orientation,
         registry::{BlockId, BlockMeshType, BlockRegistry, TextureId},
-        VoxelWorld,
+        BlockPos, VoxelWorld,
     },
     Side,
 };
 
-use super::{TerrainMesh, TerrainVertex};
+use super::{MesherMode, TerrainMesh, TerrainVertex, UvTransform};
 
 pub struct ChunkNeighbors {
     chunks: Vec<ChunkSectionSnapshot>,
@@ -66,6 +73,29 @@ impl ChunkNeighbors {
             ChunkData::Array(arr) => arr[[mx, my, mz]],
         }
     }
+
+    /// the blocks of the section `offset` sections away from the one being
+    /// meshed.
+    fn section_blocks(&self, offset: Vector3<ChunkAxisOffset>) -> &ChunkData<BlockId> {
+        let [dx, dy, dz] = [0, 1, 2].map(|axis| (offset[axis] + 1) as usize);
+        self.chunks[9 * dx + 3 * dy + dz].blocks()
+    }
+
+    /// a hash of everything that the center section's mesh depends on: its own
+    /// blocks and light, and the layer of blocks and light just outside of it.
+    pub fn content_hash(&self) -> u64 {
+        const LEN: ChunkAxisOffset = CHUNK_LENGTH as ChunkAxisOffset;
+        let mut hasher = DefaultHasher::new();
+        for x in -1..=LEN {
+            for y in -1..=LEN {
+                for z in -1..=LEN {
+                    self.id([x, y, z]).hash(&mut hasher);
+                    self.light([x, y, z]).hash(&mut hasher);
+                }
+            }
+        }
+        hasher.finish()
+    }
 }
 
 fn chunks_index_and_offset(n: ChunkAxisOffset) -> (usize, usize) {
@@ -85,6 +115,9 @@ pub struct VoxelQuad {
     ao: FaceAo,
     light: FaceLight,
     id: BlockId,
+    /// which neighbors a block with connected textures connects to, see
+    /// `RegistryRef::connected_textures`.
+    connections: u8,
     width: ChunkAxis,
     height: ChunkAxis,
 }
@@ -95,6 +128,7 @@ impl From<VoxelFace> for VoxelQuad {
             ao: face.ao,
             id: face.id,
             light: face.light,
+            connections: face.connections,
             width: 1,
             height: 1,
         }
@@ -105,16 +139,20 @@ impl From<VoxelFace> for VoxelQuad {
 struct VoxelFace {
     ao: FaceAo,
     light: FaceLight,
+    /// each way an orientable block faces is a different block ID, so faces
+    /// of blocks facing different ways are never merged together.
     id: BlockId,
+    connections: u8,
     visited: bool,
 }
 
 impl VoxelFace {
-    fn new(ao: FaceAo, light: FaceLight, id: BlockId) -> Self {
+    fn new(ao: FaceAo, light: FaceLight, id: BlockId, connections: u8) -> Self {
         Self {
             ao,
             light,
             id,
+            connections,
             visited: false,
         }
     }
@@ -129,10 +167,25 @@ impl VoxelFace {
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum LightingType {
+    /// each corner of a face gets the average light of the cells around that
+    /// corner, which is blended across the face.
     Smooth,
+    /// every face is lit by the single cell in front of it.
     Simple,
 }
 
+impl FromStr for LightingType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "smooth" => Self::Smooth,
+            "simple" => Self::Simple,
+            other => bail!("unknown lighting type '{}'", other),
+        })
+    }
+}
+
 pub struct MeshCreationContext {
     registry: Arc<BlockRegistry>,
     chunks: ChunkNeighbors,
@@ -173,12 +226,16 @@ impl MeshCreationContext {
         pos: ChunkSectionPos,
         neighbors: ChunkNeighbors,
         registry: &Arc<BlockRegistry>,
+        climate: &Arc<ClimateSampler>,
+        lighting_type: LightingType,
     ) -> Self {
         let mesh_constructor = MeshBuilder {
             registry: Arc::clone(registry),
             terrain_mesh: Default::default(),
             // transparency_mesh: Default::default(),
             rng: SmallRng::from_entropy(),
+            climate: Arc::clone(climate),
+            origin: pos.origin(),
         };
 
         MeshCreationContext {
@@ -187,17 +244,19 @@ impl MeshCreationContext {
             pos,
             slice: vec![VoxelFace::default(); notcraft_common::world::chunk::CHUNK_LENGTH_2],
             mesh_constructor,
-            lighting_type: LightingType::Simple,
+            lighting_type,
         }
     }
 
+    /// whether the block at `pos` darkens the corners of faces next to it.
+    fn contributes_ao(&self, pos: Point3<ChunkAxisOffset>) -> bool {
+        let block = self.registry.get(self.chunks.id(pos));
+        matches!(block.mesh_type(), BlockMeshType::FullCube) && !block.liquid()
+    }
+
     fn face_ao(&self, pos: Point3<ChunkAxis>, side: Side) -> FaceAo {
         let pos = pos.cast::<ChunkAxisOffset>();
-        let contributes_ao = |pos| {
-            let id = self.chunks.id(pos);
-            matches!(self.registry.get(id).mesh_type(), BlockMeshType::FullCube)
-                && !self.registry.get(id).liquid()
-        };
+        let contributes_ao = |pos| self.contributes_ao(pos);
 
         let neg_neg = contributes_ao(pos + side.uvl_to_xyz(-1, -1, 1));
         let neg_cen = contributes_ao(pos + side.uvl_to_xyz(-1, 0, 1));
@@ -221,44 +280,58 @@ impl MeshCreationContext {
         )
     }
 
+    /// which of the face's neighbors in its plane are the same block as it,
+    /// for blocks with connected textures. other blocks never connect.
+    fn face_connections(&self, pos: Point3<ChunkAxis>, side: Side, id: BlockId) -> u8 {
+        let block = self.registry.get(id);
+        if block.connected_textures().is_none() {
+            return 0;
+        }
+
+        let pos = pos.cast::<ChunkAxisOffset>();
+        let (u, v) = texture_axes(side);
+        let neighbors = [pos + u, pos + v, pos - u, pos - v];
+        neighbors
+            .iter()
+            .enumerate()
+            .filter(|&(_, &neighbor)| block.same_block(self.chunks.id(neighbor)))
+            .fold(0, |connections, (bit, _)| connections | 1 << bit)
+    }
+
     fn face_light(&self, pos: Point3<ChunkAxis>, side: Side) -> FaceLight {
         match self.lighting_type {
             LightingType::Smooth => {
                 let pos = pos.cast::<ChunkAxisOffset>();
-                let light = |pos| self.chunks.light(pos);
-
-                let nn = light(pos + side.uvl_to_xyz(-1, -1, 1));
-                let nc = light(pos + side.uvl_to_xyz(-1, 0, 1));
-                let np = light(pos + side.uvl_to_xyz(-1, 1, 1));
-                let cn = light(pos + side.uvl_to_xyz(0, -1, 1));
-                let cc = light(pos + side.uvl_to_xyz(0, 0, 1));
-                let cp = light(pos + side.uvl_to_xyz(0, 1, 1));
-                let pn = light(pos + side.uvl_to_xyz(1, -1, 1));
-                let pc = light(pos + side.uvl_to_xyz(1, 0, 1));
-                let pp = light(pos + side.uvl_to_xyz(1, 1, 1));
-
-                let neg_neg = LightValue::combine_max(
-                    LightValue::combine_max(nn, nc),
-                    LightValue::combine_max(cn, cc),
-                );
-                let neg_pos = LightValue::combine_max(
-                    LightValue::combine_max(np, nc),
-                    LightValue::combine_max(cp, cc),
-                );
-                let pos_neg = LightValue::combine_max(
-                    LightValue::combine_max(pn, pc),
-                    LightValue::combine_max(cn, cc),
-                );
-                let pos_pos = LightValue::combine_max(
-                    LightValue::combine_max(pp, pc),
-                    LightValue::combine_max(cp, cc),
-                );
+
+                // averages the four cells in front of the face that touch the corner
+                // at (du, dv), which are the same cells that AO looks at. cells that
+                // are filled in are always dark, so they're left out, along with the
+                // diagonal cell when both of the cells next to it are filled in,
+                // since no light could get through to the corner from there anyways.
+                let corner = |du, dv| {
+                    let center = pos + side.uvl_to_xyz(0, 0, 1);
+                    let u = pos + side.uvl_to_xyz(du, 0, 1);
+                    let v = pos + side.uvl_to_xyz(0, dv, 1);
+                    let diagonal = pos + side.uvl_to_xyz(du, dv, 1);
+
+                    let u_open = !self.contributes_ao(u);
+                    let v_open = !self.contributes_ao(v);
+                    let diagonal_open = (u_open || v_open) && !self.contributes_ao(diagonal);
+
+                    let samples = [
+                        Some(center),
+                        u_open.then_some(u),
+                        v_open.then_some(v),
+                        diagonal_open.then_some(diagonal),
+                    ];
+                    LightValue::average(samples.iter().flatten().map(|&pos| self.chunks.light(pos)))
+                };
 
                 FaceLight {
-                    neg_neg,
-                    neg_pos,
-                    pos_neg,
-                    pos_pos,
+                    neg_neg: corner(-1, -1),
+                    neg_pos: corner(-1, 1),
+                    pos_neg: corner(1, -1),
+                    pos_pos: corner(1, 1),
                 }
             }
 
@@ -358,111 +431,292 @@ impl MeshCreationContext {
         }
     }
 
-    fn mesh_slice(
-        &mut self,
-        side: Side,
-        make_coordinate: impl Fn(ChunkAxis, ChunkAxis, ChunkAxis) -> Point3<ChunkAxis>,
-    ) {
+    fn mesh_layer(&mut self, side: Side, layer: ChunkAxis) {
         let normal = side.normal::<ChunkAxisOffset>();
-        for layer in 0..(CHUNK_LENGTH as ChunkAxis) {
-            for u in 0..(CHUNK_LENGTH as ChunkAxis) {
-                for v in 0..(CHUNK_LENGTH as ChunkAxis) {
-                    let pos = make_coordinate(layer, u, v);
-                    let cur_id = self.chunks.id(pos.cast());
-                    let neighbor_id = self.chunks.id(pos.cast() + normal);
-
-                    let face = should_add_face(&self.registry, cur_id, neighbor_id)
-                        .then(|| {
-                            VoxelFace::new(
-                                self.face_ao(pos, side),
-                                self.face_light(pos, side),
-                                cur_id,
-                            )
-                        })
-                        .unwrap_or(VoxelFace::visited());
-                    self.slice[idx(u, v)] = face;
-                }
+        for u in 0..(CHUNK_LENGTH as ChunkAxis) {
+            for v in 0..(CHUNK_LENGTH as ChunkAxis) {
+                let pos = slice_coordinate(side, layer, u, v);
+                let cur_id = self.chunks.id(pos.cast());
+                let neighbor_id = self.chunks.id(pos.cast() + normal);
+
+                let face = should_add_face(&self.registry, cur_id, neighbor_id)
+                    .then(|| {
+                        VoxelFace::new(
+                            self.face_ao(pos, side),
+                            self.face_light(pos, side),
+                            cur_id,
+                            self.face_connections(pos, side, cur_id),
+                        )
+                    })
+                    .unwrap_or(VoxelFace::visited());
+                self.slice[idx(u, v)] = face;
             }
+        }
+
+        self.submit_quads(side, |u, v| slice_coordinate(side, layer, u, v));
+    }
 
-            self.submit_quads(side, |u, v| make_coordinate(layer, u, v));
+    /// the block that fills the whole section being meshed, if there is one
+    /// and it can only ever have faces on the outside of the section.
+    fn homogeneous_block(&self) -> Option<BlockId> {
+        match self.chunks.section_blocks(vector![0, 0, 0]) {
+            &ChunkData::Homogeneous(id) => match self.registry.get(id).mesh_type() {
+                BlockMeshType::Cross | BlockMeshType::Boxes => None,
+                BlockMeshType::None | BlockMeshType::FullCube => Some(id),
+            },
+            ChunkData::Array(_) => None,
         }
     }
 
-    pub fn mesh_simple(mut self, sender: Sender<CompletedMesh>) {
-        for x in 0..(CHUNK_LENGTH as ChunkAxis) {
-            for z in 0..(CHUNK_LENGTH as ChunkAxis) {
-                for y in 0..(CHUNK_LENGTH as ChunkAxis) {
-                    let pos = point![x, y, z];
-                    let cur_id = self.chunks.id(pos.cast());
-                    let cur_light = self.chunks.light(pos.cast());
-                    match self.registry.get(cur_id).mesh_type() {
-                        BlockMeshType::None => {}
-                        BlockMeshType::Cross => {
-                            mesh_cross(&mut self.mesh_constructor, cur_id, pos, cur_light)
-                        }
-                        BlockMeshType::FullCube => Side::enumerate(|side| {
-                            let normal = side.normal::<ChunkAxisOffset>();
-                            let neighbor_id = self.chunks.id(pos.cast() + normal);
-                            if should_add_face(&self.registry, cur_id, neighbor_id) {
-                                let ao = self.face_ao(pos, side);
-                                let light = self.face_light(pos, side);
-                                mesh_full_cube_side(
-                                    &mut self.mesh_constructor,
-                                    VoxelQuad {
-                                        ao,
-                                        id: cur_id,
-                                        light,
-                                        width: 1,
-                                        height: 1,
-                                    },
-                                    side,
-                                    pos,
-                                );
-                            }
-                        }),
+    /// meshes the outermost layer on `side` of a section that is entirely
+    /// made of `id`, skipping it if the neighboring section on that side can't
+    /// make any of its faces visible.
+    fn mesh_boundary(&mut self, id: BlockId, side: Side) {
+        let normal = side.normal::<ChunkAxisOffset>();
+        let exposed = match self.chunks.section_blocks(normal) {
+            &ChunkData::Homogeneous(neighbor) => should_add_face(&self.registry, id, neighbor),
+            ChunkData::Array(_) => true,
+        };
+
+        if exposed {
+            const LAST: ChunkAxis = CHUNK_LENGTH as ChunkAxis - 1;
+            self.mesh_layer(side, if side.facing_positive() { LAST } else { 0 });
+        }
+    }
+
+    fn mesh_simple_column(&mut self, x: ChunkAxis) {
+        for z in 0..(CHUNK_LENGTH as ChunkAxis) {
+            for y in 0..(CHUNK_LENGTH as ChunkAxis) {
+                let pos = point![x, y, z];
+                let cur_id = self.chunks.id(pos.cast());
+                let cur_light = self.chunks.light(pos.cast());
+                match self.registry.get(cur_id).mesh_type() {
+                    BlockMeshType::None => {}
+                    BlockMeshType::Cross => {
+                        mesh_cross(&mut self.mesh_constructor, cur_id, pos, cur_light)
+                    }
+                    BlockMeshType::Boxes => {
+                        mesh_boxes(&mut self.mesh_constructor, cur_id, pos, cur_light)
                     }
+                    BlockMeshType::FullCube => Side::enumerate(|side| {
+                        let normal = side.normal::<ChunkAxisOffset>();
+                        let neighbor_id = self.chunks.id(pos.cast() + normal);
+                        if should_add_face(&self.registry, cur_id, neighbor_id) {
+                            let ao = self.face_ao(pos, side);
+                            let light = self.face_light(pos, side);
+                            let connections = self.face_connections(pos, side, cur_id);
+                            mesh_full_cube_side(
+                                &mut self.mesh_constructor,
+                                VoxelQuad {
+                                    ao,
+                                    id: cur_id,
+                                    light,
+                                    connections,
+                                    width: 1,
+                                    height: 1,
+                                },
+                                side,
+                                pos,
+                            );
+                        }
+                    }),
                 }
             }
         }
-
-        sender
-            .send(CompletedMesh::Completed {
-                pos: self.pos,
-                terrain: self.mesh_constructor.terrain_mesh,
-            })
-            .unwrap();
     }
 
-    pub fn mesh_greedy(mut self, sender: Sender<CompletedMesh>) {
-        for x in 0..(CHUNK_LENGTH as ChunkAxis) {
-            for z in 0..(CHUNK_LENGTH as ChunkAxis) {
-                for y in 0..(CHUNK_LENGTH as ChunkAxis) {
-                    let pos = point![x, y, z];
-                    let id = self.chunks.id(pos.cast());
-                    let light = self.chunks.light(pos.cast());
-                    if matches!(self.registry.get(id).mesh_type(), BlockMeshType::Cross) {
-                        // TODO: light
-                        mesh_cross(&mut self.mesh_constructor, id, pos, light)
-                    }
+    /// meshes everything that isn't a full cube, which greedy meshing doesn't
+    /// handle.
+    fn mesh_model_column(&mut self, x: ChunkAxis) {
+        for z in 0..(CHUNK_LENGTH as ChunkAxis) {
+            for y in 0..(CHUNK_LENGTH as ChunkAxis) {
+                let pos = point![x, y, z];
+                let id = self.chunks.id(pos.cast());
+                let light = self.chunks.light(pos.cast());
+                match self.registry.get(id).mesh_type() {
+                    // TODO: light
+                    BlockMeshType::Cross => mesh_cross(&mut self.mesh_constructor, id, pos, light),
+                    BlockMeshType::Boxes => mesh_boxes(&mut self.mesh_constructor, id, pos, light),
+                    BlockMeshType::None | BlockMeshType::FullCube => {}
                 }
             }
         }
+    }
+}
 
-        self.mesh_slice(Side::Right, |layer, u, v| point!(layer, u, v));
-        self.mesh_slice(Side::Left, |layer, u, v| point!(layer, u, v));
+// the order that greedy meshing visits each side in.
+const GREEDY_SIDES: [Side; 6] = [
+    Side::Right,
+    Side::Left,
+    Side::Top,
+    Side::Bottom,
+    Side::Front,
+    Side::Back,
+];
+
+/// the directions that texture coordinates go in along a face, which have to
+/// match `uvTable` in `terrain/unpack.glsl`.
+fn texture_axes(side: Side) -> (Vector3<ChunkAxisOffset>, Vector3<ChunkAxisOffset>) {
+    match side {
+        Side::Right | Side::Left => (vector![0, 0, 1], vector![0, 1, 0]),
+        Side::Top | Side::Bottom => (vector![1, 0, 0], vector![0, 0, 1]),
+        Side::Front | Side::Back => (vector![1, 0, 0], vector![0, 1, 0]),
+    }
+}
 
-        self.mesh_slice(Side::Top, |layer, u, v| point!(u, layer, v));
-        self.mesh_slice(Side::Bottom, |layer, u, v| point!(u, layer, v));
+/// how the texture on `side` of a block facing `orientation` has to be changed
+/// to look like the block's own face was turned along with the block.
+fn uv_transform(orientation: Orientation, side: Side) -> UvTransform {
+    let (u, v) = texture_axes(side);
+    let (model_u, model_v) = texture_axes(orientation.model_side(side));
+    let model_u = orientation.to_world(model_u.cast());
+    let model_v = orientation.to_world(model_v.cast());
+
+    let swap = model_u.dot(&u.cast()) == 0;
+    let (u, v) = if swap { (v, u) } else { (u, v) };
+    UvTransform {
+        swap,
+        flip_u: model_u.dot(&u.cast()) < 0,
+        flip_v: model_v.dot(&v.cast()) < 0,
+    }
+}
 
-        self.mesh_slice(Side::Front, |layer, u, v| point!(u, v, layer));
-        self.mesh_slice(Side::Back, |layer, u, v| point!(u, v, layer));
+fn slice_coordinate(side: Side, layer: ChunkAxis, u: ChunkAxis, v: ChunkAxis) -> Point3<ChunkAxis> {
+    match side {
+        Side::Right | Side::Left => point!(layer, u, v),
+        Side::Top | Side::Bottom => point!(u, layer, v),
+        Side::Front | Side::Back => point!(u, v, layer),
+    }
+}
 
-        sender
-            .send(CompletedMesh::Completed {
-                pos: self.pos,
-                terrain: self.mesh_constructor.terrain_mesh,
-            })
-            .unwrap();
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+enum MeshStage {
+    /// meshing the blocks in the YZ plane at this X coordinate. greedy meshing
+    /// only handles cross-type blocks here.
+    Column(ChunkAxis),
+    /// greedy meshing a single layer for the side at this index into
+    /// [`GREEDY_SIDES`].
+    Layer {
+        side: usize,
+        layer: ChunkAxis,
+    },
+    /// greedy meshing only the outermost layer for the side at this index into
+    /// [`GREEDY_SIDES`], for sections filled with a single block. nothing on
+    /// the inside of such a section can ever be seen, so there are no columns
+    /// or inner layers to go through.
+    Boundary(usize),
+    Done,
+}
+
+/// a chunk mesh that can be built a little bit at a time. each call to
+/// [`MeshJob::step`] does a small, roughly constant amount of work (a single
+/// column or layer of the chunk), so that meshing can be spread across several
+/// frames when it has to run on the main thread.
+pub struct MeshJob {
+    ctx: MeshCreationContext,
+    mode: MesherMode,
+    stage: MeshStage,
+    homogeneous: Option<BlockId>,
+    /// the part of the section that changed since it was last meshed, or
+    /// `None` if it's being meshed for the first time. the whole section is
+    /// still meshed either way, but this is what an incremental mesher would
+    /// have to redo.
+    dirty: Option<DirtyRegion>,
+}
+
+impl MeshJob {
+    pub fn new(ctx: MeshCreationContext, mode: MesherMode) -> Self {
+        // the simple mesher is kept around as a reference for the greedy one,
+        // so it always goes the long way around.
+        let homogeneous = match mode {
+            MesherMode::Simple => None,
+            MesherMode::Greedy => ctx.homogeneous_block(),
+        };
+        let stage = match homogeneous {
+            Some(_) => MeshStage::Boundary(0),
+            None => MeshStage::Column(0),
+        };
+
+        Self {
+            ctx,
+            mode,
+            stage,
+            homogeneous,
+            dirty: None,
+        }
+    }
+
+    /// scopes the job to the part of the section that changed.
+    pub fn with_dirty(mut self, dirty: Option<DirtyRegion>) -> Self {
+        self.dirty = dirty;
+        self
+    }
+
+    pub fn pos(&self) -> ChunkSectionPos {
+        self.ctx.pos
+    }
+
+    pub fn dirty(&self) -> Option<DirtyRegion> {
+        self.dirty
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.stage == MeshStage::Done
+    }
+
+    /// does the next unit of work, returning true when there is nothing left
+    /// to do.
+    pub fn step(&mut self) -> bool {
+        const LAST: ChunkAxis = CHUNK_LENGTH as ChunkAxis - 1;
+
+        self.stage = match (self.stage, self.mode) {
+            (MeshStage::Column(x), MesherMode::Simple) => {
+                self.ctx.mesh_simple_column(x);
+                match x {
+                    LAST => MeshStage::Done,
+                    _ => MeshStage::Column(x + 1),
+                }
+            }
+            (MeshStage::Column(x), MesherMode::Greedy) => {
+                self.ctx.mesh_model_column(x);
+                match x {
+                    LAST => MeshStage::Layer { side: 0, layer: 0 },
+                    _ => MeshStage::Column(x + 1),
+                }
+            }
+            (MeshStage::Layer { side, layer }, _) => {
+                self.ctx.mesh_layer(GREEDY_SIDES[side], layer);
+                match (side + 1 == GREEDY_SIDES.len(), layer) {
+                    (true, LAST) => MeshStage::Done,
+                    (false, LAST) => MeshStage::Layer {
+                        side: side + 1,
+                        layer: 0,
+                    },
+                    _ => MeshStage::Layer {
+                        side,
+                        layer: layer + 1,
+                    },
+                }
+            }
+            (MeshStage::Boundary(side), _) => {
+                let id = self.homogeneous.expect("boundary stage of a mixed section");
+                self.ctx.mesh_boundary(id, GREEDY_SIDES[side]);
+                match side + 1 == GREEDY_SIDES.len() {
+                    true => MeshStage::Done,
+                    false => MeshStage::Boundary(side + 1),
+                }
+            }
+            (MeshStage::Done, _) => MeshStage::Done,
+        };
+
+        self.is_done()
+    }
+
+    pub fn finish(self) -> CompletedMesh {
+        CompletedMesh::Completed {
+            pos: self.ctx.pos,
+            terrain: self.ctx.mesh_constructor.terrain_mesh,
+        }
     }
 }
 
@@ -477,6 +731,14 @@ pub enum CompletedMesh {
     },
 }
 
+/// hands a finished mesh back to the main thread. the mesher might have shut
+/// down while the mesh was being made, in which case nobody wants it anymore.
+pub fn send_completed(sender: &Sender<CompletedMesh>, mesh: CompletedMesh) {
+    if sender.send(mesh).is_err() {
+        log::debug!("dropping a finished mesh, since the mesher has shut down");
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Default)]
 struct FaceAo(u8);
 
@@ -511,6 +773,24 @@ pub struct MeshBuilder {
     // transparency_mesh: TerrainTransparencyMesh,
     registry: Arc<BlockRegistry>,
     rng: SmallRng,
+    climate: Arc<ClimateSampler>,
+    /// the block position of the section's minimum corner.
+    origin: BlockPos,
+}
+
+impl MeshBuilder {
+    /// tints `vertex` by the climate at its position, for blocks that are
+    /// tinted. vertex positions are in 16ths of a block from the section's
+    /// origin.
+    fn tint(&self, id: BlockId, pos: [u16; 3], vertex: TerrainVertex) -> TerrainVertex {
+        if !self.registry.get(id).tinted() {
+            return vertex;
+        }
+
+        let x = self.origin.x as f32 + pos[0] as f32 / 16.0;
+        let z = self.origin.z as f32 + pos[2] as f32 / 16.0;
+        vertex.with_climate(self.climate.sample(x, z))
+    }
 }
 
 pub fn mesh_cross(ctx: &mut MeshBuilder, id: BlockId, pos: Point3<ChunkAxis>, light: LightValue) {
@@ -532,14 +812,9 @@ pub fn mesh_cross(ctx: &mut MeshBuilder, id: BlockId, pos: Point3<ChunkAxis>, li
 
     let mut vert = |sway, offset: Vector3<_>| {
         let pos = (16 * pos) + offset;
-        ctx.terrain_mesh.vertices.push(TerrainVertex::pack(
-            pos.into(),
-            sway,
-            Side::Right,
-            light,
-            tex_id,
-            3,
-        ));
+        let vertex = TerrainVertex::pack(pos.into(), sway, Side::Right, light, tex_id, 3);
+        let vertex = ctx.tint(id, pos.into(), vertex);
+        ctx.terrain_mesh.vertices.push(vertex);
     };
 
     // we dont just use 1 here because of some weird wrapping behavior in the
@@ -599,19 +874,26 @@ pub fn mesh_full_cube_side(
         .indices
         .extend(indices.iter().copied().map(|idx| idx_start + idx));
 
-    let tex_id = choose_face_texture(ctx, quad.id, side).0 as u16;
+    // orientable blocks show the face that was turned towards `side`
+    let orientation = ctx.registry.get(quad.id).orientation();
+    let model_side = orientation.map_or(side, |orientation| orientation.model_side(side));
+    let uv = orientation.map_or(UvTransform::default(), |orientation| {
+        uv_transform(orientation, side)
+    });
+
+    let tex_id = match ctx.registry.get(quad.id).connected_textures() {
+        Some(tiles) => ctx.registry.pool_textures(tiles)[quad.connections as usize],
+        None => choose_face_texture(ctx, quad.id, model_side),
+    };
+    let tex_id = tex_id.0 as u16;
     let wind_sway = ctx.registry.get(quad.id).wind_sway();
 
     let mut vert = |offset: Vector3<_>, ao, light| {
         let pos: Point3<u16> = (16 * pos) + (16 * offset);
-        ctx.terrain_mesh.vertices.push(TerrainVertex::pack(
-            pos.into(),
-            wind_sway,
-            side,
-            light,
-            tex_id,
-            ao,
-        ));
+        let vertex = TerrainVertex::pack(pos.into(), wind_sway, side, light, tex_id, ao)
+            .with_uv_transform(uv);
+        let vertex = ctx.tint(quad.id, pos.into(), vertex);
+        ctx.terrain_mesh.vertices.push(vertex);
     };
 
     let h = if side.facing_positive() { 1 } else { 0 };
@@ -642,6 +924,100 @@ pub fn mesh_full_cube_side(
     }
 }
 
+/// creates a mesh for a fully-lit box with its minimum corner at the origin,
+/// for things like entities that aren't part of the terrain. `size` is in
+/// 16ths of a block.
+pub fn mesh_box(size: Vector3<u16>, tex_id: TextureId) -> TerrainMesh {
+    let mut mesh = TerrainMesh::default();
+    push_box(&mut mesh, Vector3::zeros(), size, FULL_SKY_LIGHT, tex_id);
+    mesh
+}
+
+/// meshes every one of a block's boxes. their faces are never culled, since
+/// they don't usually line up with the faces of the blocks around them.
+pub fn mesh_boxes(ctx: &mut MeshBuilder, id: BlockId, pos: Point3<ChunkAxis>, light: LightValue) {
+    let tex_id = choose_face_texture(ctx, id, Side::Front);
+    let registry = Arc::clone(&ctx.registry);
+    for aabb in registry.get(id).collision_shape() {
+        let units =
+            |corner: Point3<f32>| corner.coords.map(|extent| (16.0 * extent).round() as u16);
+        let (min, max) = (units(aabb.min), units(aabb.max));
+        let origin = 16 * pos.coords;
+        push_box(
+            &mut ctx.terrain_mesh,
+            origin + min,
+            origin + max,
+            light,
+            tex_id,
+        );
+    }
+}
+
+/// adds all six faces of a box to `mesh`. the corners are in 16ths of a block.
+fn push_box(
+    mesh: &mut TerrainMesh,
+    min: Vector3<u16>,
+    max: Vector3<u16>,
+    light: LightValue,
+    tex_id: TextureId,
+) {
+    Side::enumerate(|side| {
+        let clockwise = match side {
+            Side::Top => false,
+            Side::Bottom => true,
+            Side::Front => true,
+            Side::Back => false,
+            Side::Right => false,
+            Side::Left => true,
+        };
+        let indices = if clockwise {
+            NORMAL_QUAD_CW
+        } else {
+            NORMAL_QUAD_CCW
+        };
+
+        let idx_start = mesh.vertices.len() as u32;
+        mesh.indices
+            .extend(indices.iter().copied().map(|idx| idx_start + idx));
+
+        let mut vert = |x, y, z| {
+            mesh.vertices.push(TerrainVertex::pack(
+                [x, y, z],
+                false,
+                side,
+                light,
+                tex_id.0 as u16,
+                3,
+            ));
+        };
+
+        let h = |axis: usize| match side.facing_positive() {
+            true => max[axis],
+            false => min[axis],
+        };
+        match side {
+            Side::Left | Side::Right => {
+                vert(h(0), max.y, min.z);
+                vert(h(0), max.y, max.z);
+                vert(h(0), min.y, min.z);
+                vert(h(0), min.y, max.z);
+            }
+            Side::Top | Side::Bottom => {
+                vert(min.x, h(1), max.z);
+                vert(max.x, h(1), max.z);
+                vert(min.x, h(1), min.z);
+                vert(max.x, h(1), min.z);
+            }
+            Side::Front | Side::Back => {
+                vert(min.x, max.y, h(2));
+                vert(max.x, max.y, h(2));
+                vert(min.x, min.y, h(2));
+                vert(max.x, min.y, h(2));
+            }
+        }
+    });
+}
+
 fn ao_value(side1: bool, corner: bool, side2: bool) -> u8 {
     if side1 && side2 {
         0
@@ -650,11 +1026,576 @@ fn ao_value(side1: bool, corner: bool, side2: bool) -> u8 {
     }
 }
 
+// blocks that somehow end up without any textures are drawn with the first
+// texture, instead of taking the mesher down with them.
 fn choose_face_texture(ctx: &mut MeshBuilder, id: BlockId, side: Side) -> TextureId {
-    let pool_ids = ctx.registry.get(id).block_textures().unwrap();
-    let pool_ids = pool_ids.choose(&mut ctx.rng).unwrap();
-    let pool_id = pool_ids[side];
+    let pool_ids = ctx.registry.get(id).block_textures();
+    let pool_ids = pool_ids.map(Vec::as_slice).unwrap_or_default();
+    let pool_id = match pool_ids.choose(&mut ctx.rng) {
+        Some(pool_ids) => pool_ids[side],
+        None => return TextureId::default(),
+    };
 
     let tex_ids = ctx.registry.pool_textures(pool_id);
-    *tex_ids.choose(&mut ctx.rng).unwrap()
+    tex_ids.choose(&mut ctx.rng).copied().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::MAX_TEXTURE_ID;
+    use notcraft_common::world::{
+        chunk::{ArrayChunk, ChunkSection, CHUNK_LENGTH_3},
+        registry::{load_registry, AIR_BLOCK},
+    };
+    use std::collections::{HashMap, HashSet};
+
+    // one block, in the 16ths of a block that mesh vertices are measured in.
+    const UNIT: i32 = 16;
+    const SECTION_UNITS: i32 = UNIT * CHUNK_LENGTH as i32;
+
+    fn registry() -> Arc<BlockRegistry> {
+        load_registry(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../resources/blocks.json"
+        ))
+        .unwrap()
+    }
+
+    fn section_of(block: [i32; 3]) -> ChunkSectionPos {
+        let [x, y, z] = block.map(|n| n.div_euclid(CHUNK_LENGTH as i32));
+        ChunkSectionPos { x, y, z }
+    }
+
+    /// a handful of stone blocks in an otherwise empty world.
+    struct Shape {
+        registry: Arc<BlockRegistry>,
+        blocks: HashSet<[i32; 3]>,
+        block: &'static str,
+        /// blocks that are in a different state than the block's first one.
+        states: HashMap<[i32; 3], &'static str>,
+    }
+
+    impl Shape {
+        fn new<I: IntoIterator<Item = [i32; 3]>>(blocks: I) -> Self {
+            Self {
+                registry: registry(),
+                blocks: blocks.into_iter().collect(),
+                block: "stone",
+                states: HashMap::new(),
+            }
+        }
+
+        /// makes the shape out of `block` instead of stone.
+        fn with_block(mut self, block: &'static str) -> Self {
+            self.block = block;
+            self
+        }
+
+        /// puts the block at `block` into the state called `state`.
+        fn with_state(mut self, block: [i32; 3], state: &'static str) -> Self {
+            self.states.insert(block, state);
+            self
+        }
+
+        fn block_id(&self, block: [i32; 3]) -> BlockId {
+            let id = self.registry.lookup(self.block);
+            match self.states.get(&block) {
+                Some(state) => self.registry.get(id).state(state).unwrap(),
+                None => id,
+            }
+        }
+
+        fn is_solid(&self, block: [i32; 3]) -> bool {
+            self.blocks.contains(&block)
+        }
+
+        /// the section at `pos`, which is homogeneous if it is completely
+        /// empty or completely filled, like it would be in a real world.
+        fn section(&self, pos: ChunkSectionPos) -> ChunkSection {
+            let block_id = self.registry.lookup(self.block);
+            let mut data = ArrayChunk::homogeneous(AIR_BLOCK);
+            let mut count = 0;
+            let min = [pos.x, pos.y, pos.z].map(|n| n * CHUNK_LENGTH as i32);
+            for &block in self
+                .blocks
+                .iter()
+                .filter(|&&block| section_of(block) == pos)
+            {
+                let local = [0, 1, 2].map(|axis| (block[axis] - min[axis]) as usize);
+                data[local] = self.block_id(block);
+                count += 1;
+            }
+
+            let data = match count {
+                0 => ChunkData::Homogeneous(AIR_BLOCK),
+                CHUNK_LENGTH_3 => ChunkData::Homogeneous(block_id),
+                _ => ChunkData::Array(data),
+            };
+            ChunkSection::initialize(pos, data, &self.registry)
+        }
+
+        fn sections(&self) -> HashSet<ChunkSectionPos> {
+            self.blocks.iter().copied().map(section_of).collect()
+        }
+
+        fn mesh(&self, pos: ChunkSectionPos, mode: MesherMode) -> TerrainMesh {
+            let mut job = self.mesh_job(pos, mode);
+            while !job.step() {}
+            match job.finish() {
+                CompletedMesh::Completed { terrain, .. } => terrain,
+                CompletedMesh::Failed { .. } => panic!("meshing {:?} failed", pos),
+            }
+        }
+
+        fn mesh_job(&self, pos: ChunkSectionPos, mode: MesherMode) -> MeshJob {
+            let mut chunks = Vec::with_capacity(27);
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        chunks.push(self.section(pos.offset([dx, dy, dz])).snapshot());
+                    }
+                }
+            }
+
+            let neighbors = ChunkNeighbors { chunks };
+            let climate = Arc::new(ClimateSampler::new(0));
+            let ctx = MeshCreationContext::new(
+                pos,
+                neighbors,
+                &self.registry,
+                &climate,
+                LightingType::Smooth,
+            );
+            MeshJob::new(ctx, mode)
+        }
+
+        /// every block face that touches air, as the solid block and the side
+        /// of it that faces the air.
+        fn exposed_faces(&self) -> HashSet<([i32; 3], Side)> {
+            let mut faces = HashSet::new();
+            for &block in self.blocks.iter() {
+                Side::enumerate(|side| {
+                    let normal = side.normal::<i32>();
+                    let neighbor = [0, 1, 2].map(|axis| block[axis] + normal[axis]);
+                    if !self.is_solid(neighbor) {
+                        faces.insert((block, side));
+                    }
+                });
+            }
+            faces
+        }
+    }
+
+    fn side_from_normal(axis: usize, positive: bool) -> Side {
+        match (axis, positive) {
+            (0, true) => Side::Right,
+            (0, false) => Side::Left,
+            (1, true) => Side::Top,
+            (1, false) => Side::Bottom,
+            (2, true) => Side::Front,
+            _ => Side::Back,
+        }
+    }
+
+    fn unpack_position(vertex: &TerrainVertex) -> [i32; 3] {
+        let packed = vertex.pos_ao;
+        [packed >> 22, packed >> 12, packed >> 2].map(|n| (n & 0x3ff) as i32)
+    }
+
+    fn cross(a: [i32; 3], b: [i32; 3], c: [i32; 3]) -> [i32; 3] {
+        let u = [0, 1, 2].map(|axis| b[axis] - a[axis]);
+        let v = [0, 1, 2].map(|axis| c[axis] - a[axis]);
+        [
+            u[1] * v[2] - u[2] * v[1],
+            u[2] * v[0] - u[0] * v[2],
+            u[0] * v[1] - u[1] * v[0],
+        ]
+    }
+
+    /// the triangles of a mesh, with vertex positions relative to the world
+    /// origin instead of the section.
+    fn triangles(pos: ChunkSectionPos, mesh: &TerrainMesh) -> Vec<[[i32; 3]; 3]> {
+        let origin = [pos.x, pos.y, pos.z].map(|n| n * SECTION_UNITS);
+        let vertex = |index: u32| {
+            let local = unpack_position(&mesh.vertices()[index as usize]);
+            [0, 1, 2].map(|axis| origin[axis] + local[axis])
+        };
+
+        assert_eq!(mesh.indices().len() % 3, 0);
+        mesh.indices()
+            .chunks(3)
+            .map(|tri| [vertex(tri[0]), vertex(tri[1]), vertex(tri[2])])
+            .collect()
+    }
+
+    /// splits the quads of a full-cube mesh back up into the block faces that
+    /// they cover, checking along the way that each quad is a flat rectangle
+    /// along the grid, and that both of its triangles are wound so that they
+    /// face out of the solid side of the shape.
+    fn covered_faces(
+        shape: &Shape,
+        pos: ChunkSectionPos,
+        mesh: &TerrainMesh,
+    ) -> Vec<([i32; 3], Side)> {
+        let triangles = triangles(pos, mesh);
+        assert_eq!(
+            triangles.len() % 2,
+            0,
+            "quads should be made of two triangles"
+        );
+
+        let mut faces = Vec::new();
+        for quad in triangles.chunks(2) {
+            let normals = quad
+                .iter()
+                .map(|&[a, b, c]| cross(a, b, c))
+                .collect::<Vec<_>>();
+            let axis = (0..3)
+                .find(|&axis| normals[0][axis] != 0)
+                .expect("degenerate triangle");
+            for normal in normals.iter() {
+                for other in (0..3).filter(|&other| other != axis) {
+                    assert_eq!(
+                        normal[other], 0,
+                        "quad isn't aligned to the grid: {:?}",
+                        quad
+                    );
+                }
+                assert_eq!(
+                    normal[axis].signum(),
+                    normals[0][axis].signum(),
+                    "triangles of {:?} face different ways",
+                    quad
+                );
+            }
+
+            let corners = quad.iter().flatten().copied().collect::<HashSet<_>>();
+            assert_eq!(
+                corners.len(),
+                4,
+                "quad should have four distinct corners: {:?}",
+                quad
+            );
+            let min = [0, 1, 2].map(|n| corners.iter().map(|c| c[n]).min().unwrap());
+            let max = [0, 1, 2].map(|n| corners.iter().map(|c| c[n]).max().unwrap());
+            assert_eq!(min[axis], max[axis], "quad isn't flat: {:?}", quad);
+            for corner in corners.iter() {
+                for n in 0..3 {
+                    assert!(
+                        corner[n] == min[n] || corner[n] == max[n],
+                        "quad isn't a rectangle: {:?}",
+                        quad
+                    );
+                    assert_eq!(
+                        corner[n] % UNIT,
+                        0,
+                        "quad isn't on block boundaries: {:?}",
+                        quad
+                    );
+                }
+            }
+
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let plane = min[axis] / UNIT;
+            for a in min[u] / UNIT..max[u] / UNIT {
+                for b in min[v] / UNIT..max[v] / UNIT {
+                    let mut below = [0; 3];
+                    below[axis] = plane - 1;
+                    below[u] = a;
+                    below[v] = b;
+                    let mut above = below;
+                    above[axis] = plane;
+
+                    let (block, positive) = match (shape.is_solid(below), shape.is_solid(above)) {
+                        (true, false) => (below, true),
+                        (false, true) => (above, false),
+                        _ => panic!("quad {:?} isn't between a solid block and air", quad),
+                    };
+
+                    // terrain is drawn with counter-clockwise triangles culled,
+                    // so faces that can be seen are clockwise from the outside,
+                    // and their normals point into the block.
+                    assert_eq!(
+                        normals[0][axis] < 0,
+                        positive,
+                        "quad {:?} is wound the wrong way",
+                        quad
+                    );
+                    faces.push((block, side_from_normal(axis, positive)));
+                }
+            }
+        }
+        faces
+    }
+
+    /// meshes every section the shape touches, checking that together the
+    /// meshes cover each exposed face of the shape exactly once, and nothing
+    /// else.
+    fn check_coverage(shape: &Shape, mode: MesherMode) -> HashMap<ChunkSectionPos, TerrainMesh> {
+        let meshes = shape
+            .sections()
+            .into_iter()
+            .map(|pos| (pos, shape.mesh(pos, mode)))
+            .collect::<HashMap<_, _>>();
+
+        let mut covered = HashSet::new();
+        for (&pos, mesh) in meshes.iter() {
+            assert_eq!(mesh.indices().len() % 6, 0);
+            assert_eq!(mesh.vertices().len() * 6, mesh.indices().len() * 4);
+
+            for face in covered_faces(shape, pos, mesh) {
+                assert_eq!(
+                    section_of(face.0),
+                    pos,
+                    "{:?} was meshed by the wrong section",
+                    face
+                );
+                assert!(covered.insert(face), "{:?} was meshed more than once", face);
+            }
+        }
+
+        let expected = shape.exposed_faces();
+        let missing = expected.difference(&covered).collect::<Vec<_>>();
+        let extra = covered.difference(&expected).collect::<Vec<_>>();
+        assert!(
+            missing.is_empty(),
+            "faces missing from the mesh: {:?}",
+            missing
+        );
+        assert!(
+            extra.is_empty(),
+            "faces that shouldn't be meshed: {:?}",
+            extra
+        );
+
+        meshes
+    }
+
+    /// checks that the triangles of all the meshes together close up with no
+    /// gaps, by checking that every edge is shared with a triangle going the
+    /// other way. greedy meshes have T-junctions where quads of different sizes
+    /// meet, so this only works for simple meshes.
+    fn check_watertight(meshes: &HashMap<ChunkSectionPos, TerrainMesh>) {
+        let mut edges = HashMap::<([i32; 3], [i32; 3]), i32>::new();
+        for (&pos, mesh) in meshes.iter() {
+            for [a, b, c] in triangles(pos, mesh) {
+                for (from, to) in [(a, b), (b, c), (c, a)] {
+                    *edges.entry((from, to)).or_default() += 1;
+                    *edges.entry((to, from)).or_default() -= 1;
+                }
+            }
+        }
+
+        let open = edges
+            .iter()
+            .filter(|(_, &count)| count != 0)
+            .collect::<Vec<_>>();
+        assert!(open.is_empty(), "mesh has open edges: {:?}", open);
+    }
+
+    fn check_shape(shape: &Shape) -> [HashMap<ChunkSectionPos, TerrainMesh>; 2] {
+        let simple = check_coverage(shape, MesherMode::Simple);
+        check_watertight(&simple);
+
+        let quads = shape.exposed_faces().len();
+        let vertices = simple
+            .values()
+            .map(|mesh| mesh.vertices().len())
+            .sum::<usize>();
+        let indices = simple
+            .values()
+            .map(|mesh| mesh.indices().len())
+            .sum::<usize>();
+        assert_eq!(vertices, 4 * quads);
+        assert_eq!(indices, 6 * quads);
+
+        let greedy = check_coverage(shape, MesherMode::Greedy);
+        [simple, greedy]
+    }
+
+    fn quad_count(meshes: &HashMap<ChunkSectionPos, TerrainMesh>) -> usize {
+        meshes.values().map(|mesh| mesh.indices().len() / 6).sum()
+    }
+
+    #[test]
+    fn single_block() {
+        let shape = Shape::new([[5, 6, 7]]);
+        let [simple, greedy] = check_shape(&shape);
+        assert_eq!(quad_count(&simple), 6);
+        assert_eq!(quad_count(&greedy), 6);
+    }
+
+    #[test]
+    fn plane() {
+        let len = CHUNK_LENGTH as i32;
+        let shape = Shape::new((0..len).flat_map(|x| (0..len).map(move |z| [x, 3, z])));
+        let [simple, greedy] = check_shape(&shape);
+        assert_eq!(
+            quad_count(&simple),
+            2 * len as usize * len as usize + 4 * len as usize
+        );
+        // the whole top, the whole bottom, and one strip along each edge
+        assert_eq!(quad_count(&greedy), 6);
+    }
+
+    #[test]
+    fn staircase() {
+        let shape = Shape::new((0..6).flat_map(|step| {
+            (0..=step).flat_map(move |y| (0..3).map(move |z| [10 + step, y + 2, z + 8]))
+        }));
+        let [simple, greedy] = check_shape(&shape);
+        assert!(quad_count(&greedy) < quad_count(&simple));
+    }
+
+    #[test]
+    fn connected_textures_join_neighbors() {
+        // a row of three blocks along X, so the top faces connect to the right
+        // (bit 0) and left (bit 2) of their textures.
+        let shape = Shape::new([[4, 4, 4], [5, 4, 4], [6, 4, 4]]).with_block("polished_stone");
+        let [simple, greedy] = check_shape(&shape);
+        // the middle block's top has a different tile than its neighbors, so
+        // the row's top can't be merged into one quad.
+        assert!(quad_count(&greedy) > 6);
+
+        let registry = &shape.registry;
+        let tiles = registry
+            .get(registry.lookup("polished_stone"))
+            .connected_textures()
+            .unwrap();
+        let tiles = registry.pool_textures(tiles);
+
+        let mesh = &simple[&section_of([4, 4, 4])];
+        let mut top_tiles = HashMap::new();
+        for quad in mesh.vertices().chunks(4) {
+            let positions = quad.iter().map(unpack_position).collect::<Vec<_>>();
+            if positions.iter().all(|pos| pos[1] == 5 * UNIT) {
+                let x = positions.iter().map(|pos| pos[0]).min().unwrap() / UNIT;
+                let texture = quad[0].light_flags_side_id & MAX_TEXTURE_ID as u32;
+                top_tiles.insert(x, texture as usize);
+            }
+        }
+
+        assert_eq!(top_tiles[&4], tiles[0b0001].0);
+        assert_eq!(top_tiles[&5], tiles[0b0101].0);
+        assert_eq!(top_tiles[&6], tiles[0b0100].0);
+    }
+
+    #[test]
+    fn only_tinted_blocks_are_tinted() {
+        let is_tinted = |vertex: &TerrainVertex| vertex.tint & 1 << 15 != 0;
+        for (block, tinted) in [("grass", true), ("stone", false)] {
+            let shape = Shape::new([[5, 6, 7]]).with_block(block);
+            let [simple, _] = check_shape(&shape);
+            let mesh = &simple[&section_of([5, 6, 7])];
+            assert!(mesh.vertices().iter().all(|vertex| is_tinted(vertex) == tinted));
+        }
+    }
+
+    #[test]
+    fn shapes_straddling_section_borders() {
+        let len = CHUNK_LENGTH as i32;
+        // a cube sitting on the corner where eight sections meet
+        let cube = Shape::new((-2..2).flat_map(|x| {
+            (-2..2).flat_map(move |y| (-2..2).map(move |z| [len + x, len + y, len + z]))
+        }));
+        let [simple, _] = check_shape(&cube);
+        assert_eq!(simple.len(), 8);
+
+        // a wall that is cut in half by the border between two sections, with
+        // a lone block in each section that only touches the wall on one side.
+        let wall = Shape::new(
+            (0..4)
+                .flat_map(|y| (0..4).flat_map(move |z| [[len - 1, y, z], [len, y, z]]))
+                .chain([[len - 2, 1, 1], [len + 1, 2, 2]]),
+        );
+        check_shape(&wall);
+    }
+
+    #[test]
+    fn homogeneous_sections_only_mesh_their_boundary() {
+        let len = CHUNK_LENGTH as i32;
+        // a completely filled section sitting on another one, with a single
+        // block on top of it so that its top face isn't all the same.
+        let shape = Shape::new(
+            (0..len)
+                .flat_map(|x| (-len..len).flat_map(move |y| (0..len).map(move |z| [x, y, z])))
+                .chain([[5, len, 5]]),
+        );
+        let [_, greedy] = check_shape(&shape);
+        let pos = section_of([0, 0, 0]);
+
+        let mut fast = shape.mesh_job(pos, MesherMode::Greedy);
+        assert_eq!(fast.stage, MeshStage::Boundary(0));
+        let mut steps = 1;
+        while !fast.step() {
+            steps += 1;
+        }
+        assert_eq!(steps, GREEDY_SIDES.len());
+
+        // going through every layer has to come up with the same quads.
+        let mut slow = shape.mesh_job(pos, MesherMode::Greedy);
+        slow.stage = MeshStage::Column(0);
+        while !slow.step() {}
+
+        let quads = |job: MeshJob| match job.finish() {
+            CompletedMesh::Completed { terrain, .. } => terrain
+                .vertices()
+                .chunks(4)
+                .map(|quad| quad.iter().map(unpack_position).collect::<Vec<_>>())
+                .collect::<Vec<_>>(),
+            CompletedMesh::Failed { .. } => panic!("meshing {:?} failed", pos),
+        };
+        let fast = quads(fast);
+        assert_eq!(fast, quads(slow));
+        assert_eq!(fast.len(), greedy[&pos].vertices().len() / 4);
+        // the bottom is covered by the section below, and the top is split
+        // up around the block sitting on it.
+        assert!(fast.len() > 5);
+        assert!(!fast.iter().any(|quad| quad.iter().all(|v| v[1] == 0)));
+    }
+
+    #[test]
+    fn oriented_blocks_turn_their_faces() {
+        // a log lying along X, so its ends are on its right and left.
+        let log = Shape::new([[5, 6, 7]])
+            .with_block("log")
+            .with_state([5, 6, 7], "right");
+        let texture = |pool: &str| {
+            let pool = log.registry.texture_pool(pool).unwrap();
+            log.registry.pool_textures(pool)[0].0 as u32
+        };
+        let (side, end) = (texture("log-side"), texture("log-top"));
+
+        let [simple, _] = check_shape(&log);
+        let mesh = &simple[&section_of([5, 6, 7])];
+        for quad in mesh.vertices().chunks(4) {
+            let positions = quad.iter().map(unpack_position).collect::<Vec<_>>();
+            let on_x = positions.iter().all(|pos| pos[0] == positions[0][0]);
+            let texture = quad[0].light_flags_side_id & MAX_TEXTURE_ID as u32;
+            assert_eq!(texture, if on_x { end } else { side });
+            // the texture runs along X now, instead of along Y like it
+            // does for an upright log, so its axes are swapped everywhere.
+            assert_eq!(quad[0].tint >> 16 & 1, 1);
+        }
+
+        let upright = Shape::new([[5, 6, 7]]).with_block("log");
+        let [simple, _] = check_shape(&upright);
+        let mesh = &simple[&section_of([5, 6, 7])];
+        assert!(mesh.vertices().iter().all(|vertex| vertex.tint >> 16 == 0));
+    }
+
+    #[test]
+    fn differently_oriented_faces_are_not_merged() {
+        let row = [[4, 4, 4], [5, 4, 4], [6, 4, 4]];
+        let same = Shape::new(row).with_block("log");
+        let [_, greedy] = check_shape(&same);
+        assert_eq!(quad_count(&greedy), 6);
+
+        let mixed = Shape::new(row)
+            .with_block("log")
+            .with_state([5, 4, 4], "front");
+        let [_, greedy] = check_shape(&mixed);
+        assert!(quad_count(&greedy) > 6);
+    }
 }
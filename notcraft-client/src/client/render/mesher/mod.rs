@@ -5,25 +5,44 @@ use crate::client::{
 use anyhow::Result;
 use crossbeam_channel::{Receiver, Sender};
 use glium::{backend::Facade, index::PrimitiveType, IndexBuffer, VertexBuffer};
+use parking_lot::Mutex;
 use notcraft_common::{
     aabb::Aabb,
     debug::send_debug_event,
     prelude::*,
+    profile_scope,
+    profiler::ProfileSystemExt,
+    task::{PoolKind, TaskPools},
     world::{
-        chunk::{ChunkData, ChunkSectionPos, ChunkSectionSnapshot, CHUNK_LENGTH},
+        chunk::{ChunkData, ChunkSectionPos, ChunkSectionSnapshot, DirtyRegion, CHUNK_LENGTH},
+        generation::{Climate, ClimateSampler},
         lighting::LightValue,
         registry::BlockId,
-        VoxelWorld,
+        VoxelWorld, WorldGenerator,
     },
     Faces, Side,
 };
-use std::{collections::HashSet, str::FromStr, sync::Arc};
+use std::{
+    collections::{HashSet, VecDeque},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use self::{
-    generation::{should_add_face, ChunkNeighbors, CompletedMesh, MeshCreationContext},
+    budget::MeshBudget,
+    cache::{MeshCache, SharedMeshCache},
+    generation::{
+        send_completed, should_add_face, ChunkNeighbors, CompletedMesh, LightingType,
+        MeshCreationContext, MeshJob,
+    },
     tracker::{update_tracker, MeshTracker},
 };
 
+#[cfg(test)]
+mod bench;
+pub mod budget;
+pub mod cache;
 pub mod generation;
 pub mod tracker;
 
@@ -34,16 +53,188 @@ pub struct MesherContext {
     mesh_tx: Sender<CompletedMesh>,
     mesh_rx: Receiver<CompletedMesh>,
     mode: MesherMode,
+    lighting: LightingType,
+    cooperative: Option<CooperativeMesher>,
+    cache: Option<SharedMeshCache>,
 }
 
 impl MesherContext {
-    fn new(mode: MesherMode) -> Self {
+    fn new(
+        mode: MesherMode,
+        lighting: LightingType,
+        cooperative_budget: Option<Duration>,
+        cache_capacity: Option<usize>,
+    ) -> Self {
         let (mesh_tx, mesh_rx) = crossbeam_channel::unbounded();
+        let cache = cache_capacity.map(|capacity| Arc::new(Mutex::new(MeshCache::new(capacity))));
         Self {
             completed_meshes: Default::default(),
             mesh_tx,
             mesh_rx,
             mode,
+            lighting,
+            cooperative: cooperative_budget
+                .map(|budget| CooperativeMesher::new(mode, lighting, budget, cache.clone())),
+            cache,
+        }
+    }
+
+    /// throws out any cached meshes that depended on the contents of `pos`.
+    pub fn invalidate_cached(&self, pos: ChunkSectionPos) {
+        if let Some(cache) = self.cache.as_ref() {
+            cache.lock().invalidate(pos);
+        }
+    }
+}
+
+/// starts meshing the section at `pos`, or if it hasn't changed since the last
+/// time it was meshed, returns the mesh from back then. the hash of the
+/// section's contents is returned too, for caching the mesh once it's done.
+fn start_mesh(
+    cache: Option<&SharedMeshCache>,
+    world: &Arc<VoxelWorld>,
+    climate: &Arc<ClimateSampler>,
+    pos: ChunkSectionPos,
+    lighting: LightingType,
+    mode: MesherMode,
+    dirty: Option<DirtyRegion>,
+) -> Option<Result<TerrainMesh, (MeshJob, Option<u64>)>> {
+    let neighbors = ChunkNeighbors::lock(world, pos)?;
+    let hash = cache.map(|_| neighbors.content_hash());
+    if let (Some(cache), Some(hash)) = (cache, hash) {
+        if let Some(terrain) = cache.lock().get(pos, hash) {
+            return Some(Ok(terrain));
+        }
+    }
+
+    let mesher = MeshCreationContext::new(pos, neighbors, &world.registry, climate, lighting);
+    Some(Err((MeshJob::new(mesher, mode).with_dirty(dirty), hash)))
+}
+
+/// sends a finished mesh back to the main thread, keeping a copy of it in the
+/// cache if there is one.
+fn finish_mesh(
+    cache: Option<&SharedMeshCache>,
+    sender: &Sender<CompletedMesh>,
+    job: MeshJob,
+    hash: Option<u64>,
+) {
+    let completed = job.finish();
+    if let (Some(cache), Some(hash), CompletedMesh::Completed { pos, terrain }) =
+        (cache, hash, &completed)
+    {
+        cache.lock().insert(*pos, hash, terrain);
+    }
+    send_completed(sender, completed);
+}
+
+// don't let the cooperative mesher pull in more chunks than it could ever hope
+// to get through in a reasonable amount of time; the tracker will hand them to
+// us again when we have room for them.
+const MAX_COOPERATIVE_PENDING: usize = 8;
+
+/// runs mesh jobs on the main thread instead of on the rayon pool, spending at
+/// most `budget` on meshing each frame. jobs that don't finish within the
+/// budget are picked back up where they left off on the next frame.
+struct CooperativeMesher {
+    mode: MesherMode,
+    lighting: LightingType,
+    budget: Duration,
+    pending: VecDeque<(ChunkSectionPos, Option<DirtyRegion>)>,
+    current: Option<MeshJob>,
+    /// the content hash of the section that `current` is meshing.
+    current_hash: Option<u64>,
+    cache: Option<SharedMeshCache>,
+}
+
+impl std::fmt::Debug for CooperativeMesher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CooperativeMesher")
+            .field("mode", &self.mode)
+            .field("lighting", &self.lighting)
+            .field("budget", &self.budget)
+            .field("pending", &self.pending)
+            .field("current", &self.current.as_ref().map(MeshJob::pos))
+            .finish()
+    }
+}
+
+impl CooperativeMesher {
+    fn new(
+        mode: MesherMode,
+        lighting: LightingType,
+        budget: Duration,
+        cache: Option<SharedMeshCache>,
+    ) -> Self {
+        Self {
+            mode,
+            lighting,
+            budget,
+            pending: Default::default(),
+            current: None,
+            current_hash: None,
+            cache,
+        }
+    }
+
+    fn start_next_job(
+        &mut self,
+        world: &Arc<VoxelWorld>,
+        climate: &Arc<ClimateSampler>,
+        sender: &Sender<CompletedMesh>,
+    ) {
+        while let Some((pos, dirty)) = self.pending.pop_front() {
+            let cache = self.cache.as_ref();
+            match start_mesh(cache, world, climate, pos, self.lighting, self.mode, dirty) {
+                Some(Ok(terrain)) => {
+                    send_completed(sender, CompletedMesh::Completed { pos, terrain });
+                    send_debug_event(MesherEvent::Meshed {
+                        cheap: true,
+                        pos,
+                        dirty,
+                    });
+                }
+                Some(Err((job, hash))) => {
+                    self.current = Some(job);
+                    self.current_hash = hash;
+                    return;
+                }
+                None => {
+                    send_completed(sender, CompletedMesh::Failed { pos });
+                    send_debug_event(MesherEvent::MeshFailed(pos));
+                }
+            }
+        }
+    }
+
+    fn run(
+        &mut self,
+        world: &Arc<VoxelWorld>,
+        climate: &Arc<ClimateSampler>,
+        sender: &Sender<CompletedMesh>,
+    ) {
+        let start = Instant::now();
+        while start.elapsed() < self.budget {
+            if self.current.is_none() {
+                self.start_next_job(world, climate, sender);
+            }
+
+            let job = match self.current.as_mut() {
+                Some(job) => job,
+                None => break,
+            };
+
+            if job.step() {
+                let (pos, dirty) = (job.pos(), job.dirty());
+                if let Some(job) = self.current.take() {
+                    finish_mesh(self.cache.as_ref(), sender, job, self.current_hash);
+                }
+                send_debug_event(MesherEvent::Meshed {
+                    cheap: false,
+                    pos,
+                    dirty,
+                });
+            }
         }
     }
 }
@@ -70,6 +261,13 @@ impl FromStr for MesherMode {
 #[derive(Debug)]
 pub struct ChunkMesherPlugin {
     pub mode: MesherMode,
+    pub lighting: LightingType,
+    /// when set, meshing happens on the main thread for at most this long each
+    /// frame instead of on the rayon thread pool.
+    pub cooperative_budget: Option<Duration>,
+    /// when set, up to this many meshes are kept around after being made, so
+    /// that sections which are reloaded unchanged don't need to be remeshed.
+    pub cache_capacity: Option<usize>,
 }
 
 impl ChunkMesherPlugin {
@@ -77,12 +275,30 @@ impl ChunkMesherPlugin {
         self.mode = mode;
         self
     }
+
+    pub fn with_lighting(mut self, lighting: LightingType) -> Self {
+        self.lighting = lighting;
+        self
+    }
+
+    pub fn with_cooperative_budget(mut self, budget: Option<Duration>) -> Self {
+        self.cooperative_budget = budget;
+        self
+    }
+
+    pub fn with_cache_capacity(mut self, capacity: Option<usize>) -> Self {
+        self.cache_capacity = capacity;
+        self
+    }
 }
 
 impl Default for ChunkMesherPlugin {
     fn default() -> Self {
         Self {
             mode: MesherMode::Simple,
+            lighting: LightingType::Smooth,
+            cooperative_budget: None,
+            cache_capacity: None,
         }
     }
 }
@@ -90,13 +306,43 @@ impl Default for ChunkMesherPlugin {
 impl Plugin for ChunkMesherPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.insert_resource(MeshTracker::default());
-        app.insert_resource(MesherContext::new(self.mode));
-        app.add_system(update_tracker.system());
-        app.add_system(queue_mesh_jobs.system());
-        app.add_system(update_completed_meshes.system());
+        app.insert_resource(MesherContext::new(
+            self.mode,
+            self.lighting,
+            self.cooperative_budget,
+            self.cache_capacity,
+        ));
+        app.add_system(update_tracker.system().profiled());
+        app.add_system(
+            queue_mesh_jobs
+                .system()
+                .profiled()
+                .label(MesherLabel::QueueJobs),
+        );
+        app.add_system(
+            run_cooperative_mesh_jobs
+                .system()
+                .profiled()
+                .label(MesherLabel::RunCooperative)
+                .after(MesherLabel::QueueJobs),
+        );
+        app.add_system(
+            update_completed_meshes
+                .system()
+                .profiled()
+                .after(MesherLabel::RunCooperative),
+        );
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, SystemLabel)]
+enum MesherLabel {
+    QueueJobs,
+    RunCooperative,
+}
+
+/// marks the entities of chunk sections, whose meshes never move and so can be
+/// batched together with their neighbors' when drawn.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Default)]
 pub struct HasTerrainMesh;
 
@@ -114,7 +360,8 @@ fn update_completed_meshes(
                     if voxel_world.section(pos).is_some() {
                         let mesh_handle = mesh_context.upload(terrain);
                         cmd.entity(entity)
-                            .insert(RenderMeshComponent::new(mesh_handle));
+                            .insert(RenderMeshComponent::new(mesh_handle))
+                            .insert(HasTerrainMesh);
                     }
                 }
             }
@@ -157,73 +404,136 @@ fn homogenous_should_mesh(
     Some(faces.any(|&face| face))
 }
 
-fn queue_mesh_job(ctx: &mut MesherContext, world: &Arc<VoxelWorld>, chunk: &ChunkSectionSnapshot) {
+fn queue_mesh_job(
+    ctx: &mut MesherContext,
+    pools: &TaskPools,
+    world: &Arc<VoxelWorld>,
+    climate: &Arc<ClimateSampler>,
+    chunk: &ChunkSectionSnapshot,
+    dirty: Option<DirtyRegion>,
+) {
+    let pos = chunk.pos();
+    ctx.completed_meshes.insert(pos);
+
+    if let Some(cooperative) = ctx.cooperative.as_mut() {
+        cooperative.pending.push_back((pos, dirty));
+        return;
+    }
+
     let world = Arc::clone(world);
+    let climate = Arc::clone(climate);
     let sender = ctx.mesh_tx.clone();
-    let pos = chunk.pos();
+    let cache = ctx.cache.clone();
     let mode = ctx.mode;
+    let lighting = ctx.lighting;
 
     // note that we explicittly dont move the locked chunk to the new thread,
     // because otherwise we would keep the chunk locked while no progress on
     // meshing the chunk would be made.
-    rayon::spawn(move || {
-        if let Some(neighbors) = ChunkNeighbors::lock(&world, pos) {
-            let mesher = MeshCreationContext::new(pos, neighbors, &world.registry);
-            match mode {
-                MesherMode::Simple => mesher.mesh_simple(sender),
-                MesherMode::Greedy => mesher.mesh_greedy(sender),
+    pools.spawn(PoolKind::Mesher, move || {
+        profile_scope!("mesh_chunk");
+        match start_mesh(cache.as_ref(), &world, &climate, pos, lighting, mode, dirty) {
+            Some(Ok(terrain)) => {
+                send_completed(&sender, CompletedMesh::Completed { pos, terrain });
+                send_debug_event(MesherEvent::Meshed {
+                    cheap: true,
+                    pos,
+                    dirty,
+                });
+            }
+            Some(Err((mut job, hash))) => {
+                while !job.step() {}
+                let dirty = job.dirty();
+                finish_mesh(cache.as_ref(), &sender, job, hash);
+                send_debug_event(MesherEvent::Meshed {
+                    cheap: false,
+                    pos,
+                    dirty,
+                });
+            }
+            None => {
+                send_completed(&sender, CompletedMesh::Failed { pos });
+                send_debug_event(MesherEvent::MeshFailed(pos));
             }
-            send_debug_event(MesherEvent::Meshed { cheap: false, pos });
-        } else {
-            sender.send(CompletedMesh::Failed { pos }).unwrap();
-            send_debug_event(MesherEvent::MeshFailed(pos));
         }
     });
-
-    ctx.completed_meshes.insert(pos);
 }
 
 // returns true if this mesh job was "cheap", meaning that this job shoudln't
 // count towards the number of meshed chunks this frame.
 fn mesh_one(
     ctx: &mut MesherContext,
+    pools: &TaskPools,
     world: &Arc<VoxelWorld>,
+    climate: &Arc<ClimateSampler>,
     chunk: &ChunkSectionSnapshot,
+    dirty: Option<DirtyRegion>,
 ) -> bool {
     let pos = chunk.pos();
     match chunk.blocks() {
         &ChunkData::Homogeneous(id) => match homogenous_should_mesh(world, id, pos) {
-            Some(true) => queue_mesh_job(ctx, world, chunk),
+            Some(true) => queue_mesh_job(ctx, pools, world, climate, chunk, dirty),
             Some(false) | None => {
-                send_debug_event(MesherEvent::Meshed { cheap: true, pos });
+                send_debug_event(MesherEvent::Meshed {
+                    cheap: true,
+                    pos,
+                    dirty,
+                });
                 return true;
             }
         },
 
-        ChunkData::Array(_) => queue_mesh_job(ctx, world, chunk),
+        ChunkData::Array(_) => queue_mesh_job(ctx, pools, world, climate, chunk, dirty),
     }
 
     false
 }
 
 fn queue_mesh_jobs(
+    time: Res<Time>,
     mut ctx: ResMut<MesherContext>,
     mut tracker: ResMut<MeshTracker>,
+    mut budget: Local<MeshBudget>,
+    pools: Res<TaskPools>,
     voxel_world: Res<Arc<VoxelWorld>>,
+    generator: Res<Arc<WorldGenerator>>,
 ) {
-    let mut remaining_this_frame = 4;
+    let climate = generator.climate();
+
+    // sections the player just changed don't have to wait for the budget,
+    // there are only ever a handful of them.
+    while let Some((chunk, dirty)) = tracker.next_urgent(&voxel_world) {
+        let chunk = chunk.snapshot();
+        mesh_one(&mut ctx, &pools, &voxel_world, climate, &chunk, dirty);
+    }
+
+    let mut remaining_this_frame = match &ctx.cooperative {
+        Some(cooperative) => MAX_COOPERATIVE_PENDING.saturating_sub(cooperative.pending.len()),
+        None => budget.update(time.delta(), tracker.backlog()),
+    };
 
     while remaining_this_frame > 0 {
-        let chunk = match tracker.next(&voxel_world).map(|chunk| chunk.snapshot()) {
-            Some(chunk) => chunk,
+        let (chunk, dirty) = match tracker.next(&voxel_world) {
+            Some((chunk, dirty)) => (chunk.snapshot(), dirty),
             None => break,
         };
-        if !mesh_one(&mut ctx, &voxel_world, &chunk) {
+        if !mesh_one(&mut ctx, &pools, &voxel_world, climate, &chunk, dirty) {
             remaining_this_frame -= 1;
         }
     }
 }
 
+fn run_cooperative_mesh_jobs(
+    mut ctx: ResMut<MesherContext>,
+    voxel_world: Res<Arc<VoxelWorld>>,
+    generator: Res<Arc<WorldGenerator>>,
+) {
+    let ctx = &mut *ctx;
+    if let Some(cooperative) = ctx.cooperative.as_mut() {
+        cooperative.run(&voxel_world, generator.climate(), &ctx.mesh_tx);
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Default)]
 #[repr(C)]
 pub struct TerrainVertex {
@@ -236,19 +546,29 @@ pub struct TerrainVertex {
     pub pos_ao: u32,
 
     // - 4 bits for sky light
-    // - 4 bits for block light
+    // - 4 bits for red block light
     // - 1 bit for wind sway
-    // (4 bit residual)
+    // - 4 bits for green block light
     // - 1 bit for side
     // - 2 bits for axis
     // we can compute the UV coordinates from the surface normal and the world position, and we can
     // get the normal via a lookup table using the side
-    // - 16 bits for block id
-    // this seems substantial enough to never ever be a problem
+    // - 4 bits for blue block light
+    // - 12 bits for texture id
+    // this is more layers than most GPUs support in a texture array anyways
     pub light_flags_side_id: u32,
+
+    // - 3 bits for how the texture is turned, for blocks that face different ways
+    // - 1 bit for whether the block is tinted by its climate
+    // - 8 bits for humidity
+    // - 7 bits for temperature
+    pub tint: u32,
 }
 
-glium::implement_vertex!(TerrainVertex, pos_ao, light_flags_side_id);
+glium::implement_vertex!(TerrainVertex, pos_ao, light_flags_side_id, tint);
+
+/// the largest texture id that fits in a [`TerrainVertex`].
+pub const MAX_TEXTURE_ID: u16 = (1 << 12) - 1;
 
 fn pack_side(side: Side) -> u8 {
     match side {
@@ -284,19 +604,53 @@ impl TerrainVertex {
         pos_ao <<= 2;
         pos_ao |= ao as u32;
 
-        // SSSS BBBB f... .DSS  IIII IIII IIII IIII
+        // SSSS RRRR fGGG GDSS  BBBB IIII IIII IIII
+        let [red, green, blue] = light.block();
         let mut light_flags_side_id = 0u32;
-        light_flags_side_id |= (light.raw() as u32) << 8;
-        light_flags_side_id |= (wind_sway as u32) << 7;
-        light_flags_side_id |= pack_side(side) as u32;
-        light_flags_side_id <<= 16;
-        light_flags_side_id |= id as u32;
+        light_flags_side_id |= (light.sky() as u32) << 28;
+        light_flags_side_id |= (red as u32) << 24;
+        light_flags_side_id |= (wind_sway as u32) << 23;
+        light_flags_side_id |= (green as u32) << 19;
+        light_flags_side_id |= (pack_side(side) as u32) << 16;
+        light_flags_side_id |= (blue as u32) << 12;
+        light_flags_side_id |= (id & MAX_TEXTURE_ID) as u32;
 
         Self {
             pos_ao,
             light_flags_side_id,
+            tint: 0,
         }
     }
+
+    /// tints the vertex by the climate it's in, like for grass.
+    pub fn with_climate(mut self, climate: Climate) -> Self {
+        let quantize = |n: f32, bits: u32| (n * ((1 << bits) - 1) as f32).round() as u32;
+        // ---- ---- ---- -UUU THHH HHHH Httt tttt
+        self.tint &= !0xffff;
+        self.tint |= 1 << 15;
+        self.tint |= quantize(climate.humidity, 8) << 7;
+        self.tint |= quantize(climate.temperature, 7);
+        self
+    }
+
+    /// turns the vertex's texture along with the block it belongs to.
+    pub fn with_uv_transform(mut self, transform: UvTransform) -> Self {
+        self.tint &= !(0b111 << 16);
+        self.tint |= (transform.swap as u32) << 16;
+        self.tint |= (transform.flip_u as u32) << 17;
+        self.tint |= (transform.flip_v as u32) << 18;
+        self
+    }
+}
+
+/// how texture coordinates, which come from where a vertex is in the world,
+/// are changed before looking up the texture. the axes are swapped first, and
+/// then each of them is flipped.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Default)]
+pub struct UvTransform {
+    pub swap: bool,
+    pub flip_u: bool,
+    pub flip_v: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Default)]
@@ -333,6 +687,16 @@ pub struct TerrainMesh {
     indices: Vec<u32>,
 }
 
+impl TerrainMesh {
+    pub fn vertices(&self) -> &[TerrainVertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+}
+
 impl UploadableMesh for TerrainMesh {
     type Vertex = TerrainVertex;
 
@@ -18,11 +18,14 @@ use notcraft_common::{
     prelude::*,
     transform::Transform,
     world::{
-        chunk::{ChunkSection, ChunkSectionPos},
-        VoxelWorld, WorldEvent,
+        chunk::{ChunkSection, ChunkSectionPos, DirtyRegion},
+        BlockPos, VoxelWorld, WorldEvent, WorldPos,
     },
 };
 
+use super::MesherContext;
+use crate::client::camera::CurrentCamera;
+
 fn neighbors<F>(pos: ChunkSectionPos, mut func: F)
 where
     F: FnMut(ChunkSectionPos),
@@ -39,12 +42,37 @@ where
     }
 }
 
+/// a section that's ready to be meshed, along with the part of it that changed
+/// since it was last meshed, or `None` if all of it needs meshing.
+pub type MeshRequest = (Arc<ChunkSection>, Option<DirtyRegion>);
+
+/// how far along a section is towards being meshed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MeshStatus {
+    /// the tracker hasn't heard of the section being loaded.
+    Untracked,
+    /// waiting on this many neighbors to load before it can be meshed.
+    WaitingOnNeighbors(usize),
+    /// waiting for its turn to be meshed.
+    Queued,
+    /// meshed already, or being meshed right now.
+    Meshed,
+}
+
 #[derive(Debug, Default)]
 pub struct MeshTracker {
     constraining: HashMap<ChunkSectionPos, HashSet<ChunkSectionPos>>,
     constrained_by: HashMap<ChunkSectionPos, HashSet<ChunkSectionPos>>,
 
     needs_mesh: HashSet<ChunkSectionPos>,
+    /// the part of each section in `needs_mesh` that changed since it was last
+    /// meshed. sections that aren't in here need to be meshed in full.
+    dirty: HashMap<ChunkSectionPos, DirtyRegion>,
+    /// the sections in `needs_mesh` that were modified right next to `focus`,
+    /// which get meshed before anything else.
+    urgent: HashSet<ChunkSectionPos>,
+    /// the section the player is in.
+    focus: Option<ChunkSectionPos>,
 
     loaded: HashSet<ChunkSectionPos>,
     terrain_entities: HashMap<ChunkSectionPos, Entity>,
@@ -87,6 +115,8 @@ impl MeshTracker {
                     .insert(center);
 
                 self.needs_mesh.remove(&neighbor);
+                self.urgent.remove(&neighbor);
+                self.dirty.remove(&neighbor);
             }
         });
     }
@@ -147,12 +177,13 @@ impl MeshTracker {
     }
 
     pub fn add_chunk(&mut self, chunk: ChunkSectionPos, cmd: &mut Commands) {
-        let success = self.loaded.insert(chunk);
-        assert!(
-            success,
-            "chunk {:?} was added to tracker, but it was already tracked",
-            chunk
-        );
+        if !self.loaded.insert(chunk) {
+            log::warn!(
+                "chunk {:?} was added to tracker, but it was already tracked",
+                chunk
+            );
+            return;
+        }
 
         let world_pos: Point3<f32> = chunk.origin().origin().into();
         let transform = Transform::from(world_pos);
@@ -173,15 +204,17 @@ impl MeshTracker {
     }
 
     pub fn remove_chunk(&mut self, chunk: ChunkSectionPos, cmd: &mut Commands) {
-        let success = self.loaded.remove(&chunk);
-        assert!(
-            success,
-            "chunk {:?} was removed from tracker, but it wasn't tracked",
-            chunk
-        );
+        if !self.loaded.remove(&chunk) {
+            log::warn!(
+                "chunk {:?} was removed from tracker, but it wasn't tracked",
+                chunk
+            );
+            return;
+        }
 
-        let entity = self.terrain_entities.remove(&chunk).unwrap();
-        cmd.entity(entity).despawn();
+        if let Some(entity) = self.terrain_entities.remove(&chunk) {
+            cmd.entity(entity).despawn();
+        }
 
         // remove old `constraining` entries that pointed to the removed chunk,
         // upholding one of our `have_data` invariants.
@@ -198,45 +231,117 @@ impl MeshTracker {
         let is_loaded = self.loaded.contains(&chunk);
         if is_unconstrained && is_loaded {
             self.needs_mesh.insert(chunk);
+            self.dirty.remove(&chunk);
+        }
+    }
+
+    /// like [`MeshTracker::request_mesh`], but only `region` of the section
+    /// changed. if the section is right next to the focus, it skips ahead of
+    /// everything else, so that edits made by the player show up right away.
+    pub fn request_remesh(&mut self, chunk: ChunkSectionPos, region: DirtyRegion) {
+        let is_unconstrained = !self.constrained_by.contains_key(&chunk);
+        let is_loaded = self.loaded.contains(&chunk);
+        if is_unconstrained && is_loaded {
+            // a section that's already waiting to be meshed in full stays that
+            // way, since the rest of it might have changed too.
+            if self.needs_mesh.insert(chunk) {
+                self.dirty.insert(chunk, region);
+            } else if let Some(dirty) = self.dirty.get_mut(&chunk) {
+                dirty.include(region);
+            }
+        }
+
+        let near_focus = self.focus.is_some_and(|focus| {
+            let [dx, dy, dz] = [chunk.x - focus.x, chunk.y - focus.y, chunk.z - focus.z];
+            dx.abs() <= 1 && dy.abs() <= 1 && dz.abs() <= 1
+        });
+        if near_focus && self.needs_mesh.contains(&chunk) {
+            self.urgent.insert(chunk);
         }
     }
 
-    pub fn next(&mut self, world: &Arc<VoxelWorld>) -> Option<Arc<ChunkSection>> {
-        let &pos = self.needs_mesh.iter().next()?;
-        let chunk = world.section(pos);
-        assert!(
-            chunk.is_some(),
-            "chunk {:?} was tracked for meshing but didnt exist in the world",
-            pos
-        );
-        assert!(
-            !self.constrained_by.contains_key(&pos),
-            "chunk {:?} was in to-mesh set, but was constrained by {:?}",
-            pos,
-            self.constrained_by[&pos]
-        );
-        self.needs_mesh.remove(&pos);
-        chunk
+    pub fn set_focus(&mut self, focus: ChunkSectionPos) {
+        self.focus = Some(focus);
+    }
+
+    /// how many sections are waiting to be meshed.
+    pub fn backlog(&self) -> usize {
+        self.needs_mesh.len()
+    }
+
+    /// the next urgent section that needs to be meshed, if there is one, and
+    /// the part of it that changed, if only part of it did.
+    pub fn next_urgent(&mut self, world: &Arc<VoxelWorld>) -> Option<MeshRequest> {
+        while let Some(&pos) = self.urgent.iter().next() {
+            self.urgent.remove(&pos);
+            self.needs_mesh.remove(&pos);
+            let dirty = self.dirty.remove(&pos);
+            match world.section(pos) {
+                Some(chunk) => return Some((chunk, dirty)),
+                None => log::debug!("chunk {:?} was unloaded before it could be meshed", pos),
+            }
+        }
+        None
+    }
+
+    pub fn next(&mut self, world: &Arc<VoxelWorld>) -> Option<MeshRequest> {
+        if let Some(chunk) = self.next_urgent(world) {
+            return Some(chunk);
+        }
+
+        loop {
+            let &pos = self.needs_mesh.iter().next()?;
+            assert!(
+                !self.constrained_by.contains_key(&pos),
+                "chunk {:?} was in to-mesh set, but was constrained by {:?}",
+                pos,
+                self.constrained_by[&pos]
+            );
+            self.needs_mesh.remove(&pos);
+            let dirty = self.dirty.remove(&pos);
+
+            // the section can be unloaded from the world before the tracker
+            // hears about it, in which case there's nothing to mesh.
+            match world.section(pos) {
+                Some(chunk) => return Some((chunk, dirty)),
+                None => log::debug!("chunk {:?} was unloaded before it could be meshed", pos),
+            }
+        }
     }
 
     pub fn terrain_entity(&self, pos: ChunkSectionPos) -> Option<Entity> {
         self.terrain_entities.get(&pos).cloned()
     }
+
+    pub fn status(&self, pos: ChunkSectionPos) -> MeshStatus {
+        if !self.loaded.contains(&pos) {
+            return MeshStatus::Untracked;
+        }
+        match self.constrained_by.get(&pos) {
+            Some(constraints) => MeshStatus::WaitingOnNeighbors(constraints.len()),
+            None if self.needs_mesh.contains(&pos) => MeshStatus::Queued,
+            None => MeshStatus::Meshed,
+        }
+    }
 }
 
 pub fn update_tracker(
     mut cmd: Commands,
+    camera: CurrentCamera,
+    ctx: Res<MesherContext>,
     mut tracker: ResMut<MeshTracker>,
     mut events: EventReader<WorldEvent>,
 ) {
+    tracker.set_focus(BlockPos::from(WorldPos::new(camera.pos())).into());
     for event in events.iter() {
         match event {
             WorldEvent::LoadedSection(chunk) => tracker.add_chunk(chunk.pos(), &mut cmd),
             WorldEvent::UnloadedSection(chunk) => tracker.remove_chunk(chunk.pos(), &mut cmd),
-            WorldEvent::ModifiedSection(chunk) => {
+            WorldEvent::ModifiedSection(chunk, region) => {
                 // NOTE: we're choosing to keep chunk meshes for chunks that have already been
                 // meshed, but no longer have enough data to re-mesh
-                tracker.request_mesh(chunk.pos());
+                ctx.invalidate_cached(chunk.pos());
+                tracker.request_remesh(chunk.pos(), *region);
             }
 
             _ => {}
@@ -1,4 +1,7 @@
+pub mod font;
+pub mod frame_graph;
 pub mod mesher;
+pub mod render_scale;
 pub mod renderer;
 
 #[repr(C)]
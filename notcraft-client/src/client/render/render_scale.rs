@@ -0,0 +1,190 @@
+//! drawing the world at a different resolution than the window.
+//!
+//! the world is drawn into targets that are the window's size times the render
+//! scale, and the post pass stretches it back over the window. a scale below
+//! one trades sharpness for speed, and a scale above one draws extra pixels
+//! that get averaged together, which smooths out edges.
+//!
+//! with [`RenderScaleSettings::automatic`] on, the scale drops on its own while
+//! frames take longer than the target frame time, and comes back up to the
+//! chosen scale once they're fast again.
+
+use super::renderer::RenderStats;
+use crate::client::window::WindowSettings;
+use notcraft_common::prelude::*;
+
+/// the smallest and largest render scales that can be chosen.
+pub const MIN_SCALE: f32 = 0.5;
+pub const MAX_SCALE: f32 = 2.0;
+
+/// how much the automatic scale changes by at a time.
+const STEP: f32 = 0.1;
+/// how long the automatic scale waits after changing before it changes again,
+/// in seconds. remaking the world targets isn't free, and frame times take a
+/// moment to settle after the scale changes.
+const ADJUST_INTERVAL: f32 = 1.0;
+/// how quickly the average frame time catches up to new frames. higher
+/// reacts faster, but also to single slow frames.
+const AVERAGE_RATE: f32 = 4.0;
+/// the scale only goes back up once frames take less than this fraction of the
+/// target frame time, so that it doesn't go back and forth around the target.
+const RAISE_BELOW: f32 = 0.7;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderScaleSettings {
+    /// how big the world is drawn compared to the window, from 0.5 to 2.
+    pub scale: f32,
+    /// lowers the scale while frames are slow, but never raises it above
+    /// `scale`.
+    pub automatic: bool,
+    /// how many milliseconds frames can take before the automatic scale drops.
+    pub target_frame_ms: f32,
+}
+
+impl Default for RenderScaleSettings {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            automatic: false,
+            target_frame_ms: 16.0,
+        }
+    }
+}
+
+impl RenderScaleSettings {
+    fn clamped_scale(&self) -> f32 {
+        util::clamp(MIN_SCALE, MAX_SCALE, self.scale)
+    }
+}
+
+/// the render scale that the world is being drawn at right now.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RenderScale {
+    current: f32,
+    average_frame_ms: Option<f32>,
+    cooldown: f32,
+}
+
+impl RenderScale {
+    pub fn new(settings: &RenderScaleSettings) -> Self {
+        Self {
+            current: settings.clamped_scale(),
+            average_frame_ms: None,
+            cooldown: 0.0,
+        }
+    }
+
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+
+    /// takes into account a frame that took `frame_ms` to draw, `dt` seconds
+    /// after the last one.
+    pub fn update(&mut self, settings: &RenderScaleSettings, frame_ms: f32, dt: f32) {
+        let chosen = settings.clamped_scale();
+        if !settings.automatic {
+            *self = Self::new(settings);
+            return;
+        }
+
+        let average = match self.average_frame_ms {
+            Some(average) => average + (frame_ms - average) * (1.0 - f32::exp(-AVERAGE_RATE * dt)),
+            None => frame_ms,
+        };
+        self.average_frame_ms = Some(average);
+        self.current = f32::min(self.current, chosen);
+
+        self.cooldown -= dt;
+        if self.cooldown > 0.0 {
+            return;
+        }
+        // kept to whole steps, so that the scale doesn't drift away from them
+        let step = |scale: f32| (scale / STEP).round() * STEP;
+        let adjusted = if average > settings.target_frame_ms {
+            f32::max(MIN_SCALE, step(self.current - STEP))
+        } else if average < RAISE_BELOW * settings.target_frame_ms {
+            f32::min(chosen, step(self.current + STEP))
+        } else {
+            self.current
+        };
+        if adjusted != self.current {
+            self.current = adjusted;
+            self.cooldown = ADJUST_INTERVAL;
+            // frames drawn at the old scale say nothing about the new one
+            self.average_frame_ms = None;
+        }
+    }
+}
+
+fn update_render_scale(
+    time: Res<Time>,
+    stats: Res<RenderStats>,
+    settings: Res<WindowSettings>,
+    mut scale: ResMut<RenderScale>,
+) {
+    // the GPU's time is what the render scale actually changes, but the whole
+    // frame's time is the best there is when it isn't known.
+    let frame_ms = match stats.gpu {
+        Some(times) => times.total_millis(),
+        None => 1000.0 * time.delta_seconds(),
+    };
+    scale.update(&settings.render_scale, frame_ms, time.delta_seconds());
+}
+
+#[derive(Debug, Default)]
+pub struct RenderScalePlugin {}
+
+impl Plugin for RenderScalePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let settings = app
+            .world()
+            .get_resource::<WindowSettings>()
+            .expect("`RenderScalePlugin` added before `WindowingPlugin`!")
+            .render_scale;
+
+        app.insert_resource(RenderScale::new(&settings));
+        app.add_system(update_render_scale.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn automatic_scale_follows_frame_times() {
+        let mut settings = RenderScaleSettings {
+            automatic: true,
+            ..Default::default()
+        };
+        let mut scale = RenderScale::new(&settings);
+        // it waits between changes
+        scale.update(&settings, 30.0, 0.1);
+        assert!((scale.current() - 0.9).abs() < 1e-4);
+        scale.update(&settings, 30.0, 0.1);
+        assert!((scale.current() - 0.9).abs() < 1e-4);
+
+        for _ in 0..100 {
+            scale.update(&settings, 30.0, 0.1);
+        }
+        assert_eq!(scale.current(), MIN_SCALE);
+
+        // frames that are just under the target aren't fast enough to go back up
+        for _ in 0..100 {
+            scale.update(&settings, 15.0, 0.1);
+        }
+        assert_eq!(scale.current(), MIN_SCALE);
+
+        // but it never goes above the chosen scale
+        for _ in 0..100 {
+            scale.update(&settings, 5.0, 0.1);
+        }
+        assert!((scale.current() - 1.0).abs() < 1e-4);
+
+        settings.automatic = false;
+        settings.scale = 5.0;
+        scale.update(&settings, 30.0, 0.1);
+        assert_eq!(scale.current(), MAX_SCALE);
+    }
+}
@@ -1,37 +1,69 @@
-use super::{super::camera::CurrentCamera, Tex};
+use super::{
+    super::camera::CurrentCamera, font, frame_graph::FrameGraph, render_scale::RenderScale, Tex,
+};
 use crate::{
     client::{
         camera::Camera,
         loader::{self, ShaderLoaderState},
-        render::mesher::TerrainMesh,
+        minimap::BlockMapColors,
+        render::mesher::{HasTerrainMesh, TerrainMesh, TerrainVertex, MAX_TEXTURE_ID},
+        texture_pack::{load_textures, start_texture_loads, ReloadTextures, TextureStreamer},
+        window::UiScale,
     },
     total_float::TotalFloat,
 };
+use anyhow::Context;
 use bevy_ecs::system::SystemParam;
 use crossbeam_channel::{Receiver, Sender};
 use glium::{
     backend::Facade,
+    draw_parameters::TimeElapsedQuery,
     framebuffer::{
-        ColorAttachment, DepthAttachment, DepthStencilAttachment, SimpleFrameBuffer,
-        StencilAttachment, ToColorAttachment, ToDepthAttachment, ToDepthStencilAttachment,
-        ToStencilAttachment,
+        ColorAttachment, DepthAttachment, DepthStencilAttachment, MultiOutputFrameBuffer,
+        SimpleFrameBuffer, StencilAttachment, ToColorAttachment, ToDepthAttachment,
+        ToDepthStencilAttachment, ToStencilAttachment,
     },
     index::{IndexBuffer, PrimitiveType},
     texture::*,
     uniform,
-    uniforms::{AsUniformValue, MagnifySamplerFilter, Sampler, UniformValue},
-    vertex::VertexBuffer,
-    Blend, Display, DrawParameters, Frame, Surface,
+    uniforms::{
+        AsUniformValue, DepthTextureComparison, MagnifySamplerFilter, MinifySamplerFilter, Sampler,
+        UniformValue,
+    },
+    vertex::{PerInstance, VertexBuffer},
+    Blend, BlitMask, BlitTarget, Display, DrawParameters, Frame, Rect, Surface,
 };
+use nalgebra::{Isometry3, Orthographic3};
 use notcraft_common::{
-    aabb::Aabb, math::*, prelude::*, transform::Transform, util, world::registry::BlockRegistry,
+    aabb::Aabb,
+    assets::{AssetLoadProgress, AssetState, SHADERS, TEXTURES},
+    game_state::playing,
+    health::Health,
+    lang::tr,
+    math::*,
+    notification::Notify,
+    physics::AabbCollider,
+    prelude::*,
+    profiler::{profile_stage, ProfileSystemExt},
+    transform::Transform,
+    util,
+    weather::{Weather, WeatherKind},
+    world::{
+        chunk::{ChunkAccess, ChunkSectionPos, CHUNK_LENGTH},
+        chunk_section_aabb,
+        registry::{BlockRegistry, TextureId},
+        BlockPos, WorldGenerator, WorldPos,
+    },
 };
 use parking_lot::RwLock;
+use rand::Rng;
 use std::{
-    collections::{HashMap, HashSet},
+    cell::Cell,
+    collections::{HashMap, HashSet, VecDeque},
     marker::PhantomData,
     path::PathBuf,
     rc::Rc,
+    str::FromStr,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
@@ -45,11 +77,65 @@ struct RendererMisc {
     // FIXME: this shouldn't be here! make a more general static texture loader thingy when this
     // becomes a problem
     block_textures: SrgbTexture2dArray,
+    /// the frame count and frame time of each animated block texture, in the
+    /// column of its first frame's layer.
+    texture_animations: Texture2d,
+    /// the tint of tinted blocks like grass, by temperature and humidity.
+    grass_colormap: SrgbTexture2d,
     crosshair_texture: SrgbTexture2d,
+    heart_texture: SrgbTexture2d,
+    /// a single origin at zero, for drawing terrain meshes that aren't batched.
+    zero_origin: VertexBuffer<ChunkOrigin>,
+}
+
+/// puts every block texture into a texture array, in the same order as the
+/// registry's texture ids.
+fn block_texture_array(
+    display: &Display,
+    registry: &BlockRegistry,
+    textures: &loader::BlockTextures,
+) -> Result<SrgbTexture2dArray> {
+    let textures = registry
+        .texture_paths()
+        .flat_map(|name| &textures.block_textures[name])
+        .map(|map| RawImage2d::from_raw_rgba_reversed(map, map.dimensions()))
+        .collect();
+
+    Ok(SrgbTexture2dArray::with_mipmaps(
+        display,
+        textures,
+        MipmapsOption::NoMipmap,
+    )?)
+}
+
+/// a single row with a texel for each layer of the block texture array. the
+/// first layer of an animated texture holds its frame count and frame time,
+/// and every other layer is a single frame that lasts forever.
+fn texture_animation_table(display: &Display, registry: &BlockRegistry) -> Result<Texture2d> {
+    let mut data = vec![[1.0, 0.0]; registry.texture_layer_count()];
+    for (id, animation) in registry.texture_animations() {
+        data[id.0] = [animation.frames as f32, animation.frame_time];
+    }
+
+    Ok(Texture2d::with_format(
+        display,
+        RawImage2d {
+            data: data.concat().into(),
+            width: registry.texture_layer_count() as u32,
+            height: 1,
+            format: glium::texture::ClientFormat::F32F32,
+        },
+        UncompressedFloatFormat::F32F32,
+        MipmapsOption::NoMipmap,
+    )?)
 }
 
 impl RendererMisc {
-    pub fn new(display: &Rc<Display>, registry: &Arc<BlockRegistry>) -> Result<Self> {
+    pub fn new(
+        display: &Rc<Display>,
+        registry: &Arc<BlockRegistry>,
+        textures: &loader::BlockTextures,
+    ) -> Result<Self> {
         let fullscreen_quad = VertexBuffer::immutable(&**display, &[
             Tex { uv: [-1.0, 1.0] },
             Tex { uv: [1.0, 1.0] },
@@ -77,27 +163,45 @@ impl RendererMisc {
             RawImage2d::from_raw_rgba_reversed(&crosshair_texture, crosshair_texture.dimensions()),
         )?;
 
-        let textures =
-            loader::load_block_textures("resources/textures/blocks", registry.texture_paths())?;
+        let heart_texture = loader::load_texture("resources/textures/heart.png")?;
+        let heart_texture = SrgbTexture2d::new(
+            &**display,
+            RawImage2d::from_raw_rgba_reversed(&heart_texture, heart_texture.dimensions()),
+        )?;
+
+        let grass_colormap = loader::load_texture("resources/textures/grass_colormap.png")?;
+        let grass_colormap = SrgbTexture2d::new(
+            &**display,
+            RawImage2d::from_raw_rgba_reversed(&grass_colormap, grass_colormap.dimensions()),
+        )?;
 
-        let textures = registry
-            .texture_paths()
-            .map(|name| {
-                let map = &textures.block_textures[name];
-                RawImage2d::from_raw_rgba_reversed(map, map.dimensions())
-            })
-            .collect();
+        let texture_count = registry.texture_layer_count();
+        if texture_count > MAX_TEXTURE_ID as usize + 1 {
+            bail!("too many block textures ({})", texture_count);
+        }
+
+        let block_textures = block_texture_array(display, registry, textures)?;
+        let texture_animations = texture_animation_table(display, registry)?;
 
-        let block_textures =
-            SrgbTexture2dArray::with_mipmaps(&**display, textures, MipmapsOption::NoMipmap)?;
+        let zero_origin = VertexBuffer::immutable(&**display, &[ChunkOrigin::default()])?;
 
         Ok(Self {
             fullscreen_quad,
             // crosshair_quad,
             block_textures,
+            texture_animations,
+            grass_colormap,
             crosshair_texture,
+            heart_texture,
+            zero_origin,
         })
     }
+
+    fn zero_origin(&self) -> Result<PerInstance<'_>> {
+        self.zero_origin
+            .per_instance()
+            .map_err(|_| anyhow!("instanced drawing is not supported"))
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, StageLabel)]
@@ -113,7 +217,38 @@ pub enum RenderStage {
 pub struct RenderLabel<T>(pub T);
 
 #[derive(Debug, Default)]
-pub struct RenderPlugin {}
+pub struct RenderPlugin {
+    shadows: ShadowSettings,
+    hdr: HdrSettings,
+    anti_aliasing: AntiAliasing,
+}
+
+impl RenderPlugin {
+    pub fn with_shadows(mut self, shadows: ShadowSettings) -> Self {
+        if shadows.cascades > MAX_SHADOW_CASCADES {
+            log::warn!(
+                "{} shadow cascades were requested, but only up to {} are supported",
+                shadows.cascades,
+                MAX_SHADOW_CASCADES
+            );
+        }
+        self.shadows = ShadowSettings {
+            cascades: usize::min(shadows.cascades, MAX_SHADOW_CASCADES),
+            ..shadows
+        };
+        self
+    }
+
+    pub fn with_hdr(mut self, hdr: HdrSettings) -> Self {
+        self.hdr = hdr;
+        self
+    }
+
+    pub fn with_anti_aliasing(mut self, anti_aliasing: AntiAliasing) -> Self {
+        self.anti_aliasing = anti_aliasing;
+        self
+    }
+}
 
 impl Plugin for RenderPlugin {
     // my god this is awful
@@ -137,6 +272,9 @@ impl Plugin for RenderPlugin {
                 "`RenderPlugin` added before `WorldPlugin`! (no `BlockRegistry` resource exists)",
             );
 
+        app.insert_resource(self.shadows);
+        app.insert_resource(self.hdr);
+        app.insert_resource(self.anti_aliasing);
         app.add_startup_system(util::try_system!(declare_targets));
 
         app.insert_non_send_resource(RenderTargets::new(&display));
@@ -144,15 +282,43 @@ impl Plugin for RenderPlugin {
             // FIXME: * e r r o r   h a n d l i n g *
             ShaderLoaderState::load(&display, PathBuf::from("resources/shaders")).unwrap(),
         );
+
+        // the shaders are needed to draw the loading screen, so they're loaded
+        // right away. the block textures are loaded in the background while
+        // the loading screen is up, and everything is drawn with placeholders
+        // until then.
+        let mut progress = app
+            .world_mut()
+            .get_resource_or_insert_with(AssetLoadProgress::default);
+        progress.finish(SHADERS);
+        progress.register(TEXTURES);
+        let textures = loader::BlockTextures::placeholder(&registry);
+        let misc = RendererMisc::new(&display, &registry, &textures);
+        let map_colors = BlockMapColors::new(&registry, &textures);
+
         app.insert_non_send_resource(DebugLines::new());
-        app.insert_non_send_resource(RendererMisc::new(&display, &registry).unwrap());
+        app.insert_non_send_resource(misc.unwrap());
+        app.insert_resource(map_colors.unwrap());
+        app.add_event::<ReloadTextures>();
+        app.init_resource::<TextureStreamer>();
 
         // mesh context
         let local = LocalMeshContext::<TerrainMesh>::new();
         app.insert_resource(Arc::clone(&local.shared));
         app.insert_non_send_resource(local);
+        app.insert_non_send_resource(TerrainBatches::default());
+        app.insert_non_send_resource(GpuTimers::new(&display));
+        app.init_resource::<RenderStats>();
 
         app.init_resource::<ImmediateLines>();
+        app.init_resource::<ImmediateQuads>();
+        app.init_resource::<SubmergedFog>();
+        app.init_resource::<Precipitation>();
+        app.init_resource::<DayNightCycle>();
+        app.init_resource::<ShadowCascades>();
+        app.init_resource::<ActiveHealthBar>();
+        app.init_resource::<ImmediateUi>();
+        app.init_resource::<RenderDebugSettings>();
 
         app.add_stage_after(
             CoreStage::PostUpdate,
@@ -180,40 +346,140 @@ impl Plugin for RenderPlugin {
             SystemStage::single_threaded(),
         );
 
+        profile_stage(app, RenderStage::BeginRender, "begin_render");
+        profile_stage(app, RenderStage::PreRender, "pre_render");
+        profile_stage(app, RenderStage::Render, "render");
+        profile_stage(app, RenderStage::PostRender, "post_render");
+        profile_stage(app, RenderStage::EndRender, "end_render");
+
+        // the render passes are ordered by what they draw into, see
+        // `frame_graph` for what each of them reads and writes.
+        let graph = frame_graph(&self.shadows).expect("the frame graph is invalid");
         app.add_system_to_stage(
             RenderStage::Render,
-            util::try_system!(render_sky)
-                .label(RenderLabel("sky"))
-                .label(RenderLabel("world")),
+            add_global_debug_lines
+                .system()
+                .label(RenderLabel("add_global_debug_lines")),
+        )
+        .add_system_to_stage(
+            RenderStage::Render,
+            add_render_debug_lines
+                .system()
+                .label(RenderLabel("add_global_debug_lines")),
         )
         .add_system_to_stage(
             RenderStage::Render,
-            util::try_system!(render_post)
-                .label(RenderLabel("post"))
-                .after(RenderLabel("world")),
+            graph.system(
+                "shadows",
+                util::recoverable_system!(render_shadows).profiled(),
+            ),
         )
         .add_system_to_stage(
             RenderStage::Render,
-            util::try_system!(render_terrain)
-                .label(RenderLabel("world"))
-                .label(RenderLabel("terrain"))
-                .after(RenderLabel("sky")),
+            graph.system("sky", util::recoverable_system!(render_sky).profiled()),
         )
         .add_system_to_stage(
             RenderStage::Render,
-            add_global_debug_lines
-                .system()
-                .label(RenderLabel("add_global_debug_lines")),
+            graph.system(
+                "terrain",
+                util::recoverable_system!(render_terrain).profiled(),
+            ),
+        )
+        .add_system_to_stage(
+            RenderStage::Render,
+            graph.system(
+                "precipitation",
+                util::recoverable_system!(render_precipitation).profiled(),
+            ),
         )
         .add_system_to_stage(
             RenderStage::Render,
-            util::try_system!(render_lines)
-                .label(RenderLabel("world"))
-                .after(RenderLabel("terrain"))
+            graph
+                .system("lines", util::recoverable_system!(render_lines).profiled())
                 .after(RenderLabel("add_global_debug_lines")),
+        )
+        .add_system_to_stage(
+            RenderStage::Render,
+            graph.system("quads", util::recoverable_system!(render_quads).profiled()),
+        )
+        .add_system_to_stage(
+            RenderStage::Render,
+            graph.system(
+                "resolve",
+                util::recoverable_system!(resolve_world).profiled(),
+            ),
+        )
+        .add_system_to_stage(
+            RenderStage::Render,
+            graph.system(
+                "lighting",
+                util::recoverable_system!(render_dynamic_lights).profiled(),
+            ),
+        )
+        .add_system_to_stage(
+            RenderStage::Render,
+            graph.system("bloom", util::recoverable_system!(render_bloom).profiled()),
+        )
+        .add_system_to_stage(
+            RenderStage::Render,
+            graph.system("post", util::recoverable_system!(render_post).profiled()),
+        )
+        .add_system_to_stage(
+            RenderStage::Render,
+            graph.system(
+                "hud",
+                util::recoverable_system!(render_health_bar).profiled(),
+            ),
+        )
+        .add_system_to_stage(
+            RenderStage::Render,
+            graph.system("ui", util::recoverable_system!(render_ui).profiled()),
+        );
+        app.insert_resource(graph);
+        app.add_system_to_stage(
+            RenderStage::PreRender,
+            update_submerged_fog.system().profiled(),
+        );
+        app.add_system_to_stage(
+            RenderStage::PreRender,
+            update_terrain_meshes.system().profiled(),
+        );
+        app.add_system(load_textures.system().before(TextureLoads));
+        app.add_system(start_texture_loads.system().label(TextureLoads));
+        app.add_system_to_stage(
+            RenderStage::PreRender,
+            swap_block_textures.system().profiled(),
+        );
+        app.add_system_to_stage(
+            RenderStage::PreRender,
+            update_precipitation
+                .system()
+                .profiled()
+                .with_run_criteria(playing.system()),
+        );
+        app.add_system_to_stage(
+            RenderStage::PreRender,
+            advance_day_night_cycle
+                .system()
+                .profiled()
+                .label(RenderLabel("day_night_cycle"))
+                .with_run_criteria(playing.system()),
+        );
+        app.add_system_to_stage(
+            RenderStage::PreRender,
+            update_shadow_cascades
+                .system()
+                .profiled()
+                .after(RenderLabel("day_night_cycle")),
+        );
+        app.add_system_to_stage(
+            RenderStage::BeginRender,
+            util::try_system!(begin_render).profiled(),
+        );
+        app.add_system_to_stage(
+            RenderStage::EndRender,
+            util::try_system!(end_render).profiled(),
         );
-        app.add_system_to_stage(RenderStage::BeginRender, util::try_system!(begin_render));
-        app.add_system_to_stage(RenderStage::EndRender, util::try_system!(end_render));
     }
 }
 
@@ -222,6 +488,8 @@ pub struct RenderTargets {
     descriptors: HashMap<String, RenderTargetDesc>,
     targets: HashMap<String, ((u32, u32), RenderTarget)>,
     previous_size: (u32, u32),
+    /// what [`RenderTargetSize::RenderScaled`] targets are scaled by.
+    render_scale: f32,
     frame: Option<Frame>,
 }
 
@@ -248,6 +516,9 @@ pub struct RenderTargetDesc {
 #[derive(Copy, Clone, Debug)]
 pub enum RenderTargetSize {
     WindowExact,
+    /// the window's size times the render scale, see
+    /// [`RenderTargets::set_render_scale`].
+    RenderScaled,
     WindowScaledDown(u32),
     WindowScaledUp(u32),
     Exact(u32, u32),
@@ -350,9 +621,13 @@ pub enum DepthStencilTextureFormat {
 }
 
 impl RenderTargetSize {
-    pub fn apply(&self, (width, height): (u32, u32)) -> (u32, u32) {
+    pub fn apply(&self, (width, height): (u32, u32), render_scale: f32) -> (u32, u32) {
         match self {
             &RenderTargetSize::WindowExact => (width, height),
+            &RenderTargetSize::RenderScaled => {
+                let scaled = |size: u32| u32::max(1, (size as f32 * render_scale).round() as u32);
+                (scaled(width), scaled(height))
+            }
             &RenderTargetSize::WindowScaledDown(factor) => (width / factor, height / factor),
             &RenderTargetSize::WindowScaledUp(factor) => (width * factor, height * factor),
             &RenderTargetSize::Exact(width, height) => (width, height),
@@ -532,8 +807,11 @@ impl RenderTarget {
     }
 }
 
-fn make_texture_from_desc(ctx: &Display, desc: RenderTargetDesc) -> anyhow::Result<RenderTarget> {
-    let (width, height) = desc.size.apply(ctx.get_framebuffer_dimensions());
+fn make_texture_from_desc(
+    ctx: &Display,
+    desc: RenderTargetDesc,
+    (width, height): (u32, u32),
+) -> anyhow::Result<RenderTarget> {
     let (color, depth) = match desc.kind {
         RenderTargetKind::ColorOnly { color, .. } => (Some(color), None),
         RenderTargetKind::DepthOnly { depth, .. } => (None, Some(depth)),
@@ -638,16 +916,21 @@ impl RenderTargets {
             descriptors: Default::default(),
             targets: Default::default(),
             previous_size: display.get_framebuffer_dimensions(),
+            render_scale: 1.0,
             frame: None,
         }
     }
 
     pub fn declare_target(&mut self, name: &str, desc: RenderTargetDesc) -> anyhow::Result<()> {
-        let dimensions = desc.size.apply(self.display.get_framebuffer_dimensions());
+        let window = self.display.get_framebuffer_dimensions();
+        let dimensions = desc.size.apply(window, self.render_scale);
         self.descriptors.insert(name.into(), desc);
         self.targets.insert(
             name.into(),
-            (dimensions, make_texture_from_desc(&self.display, desc)?),
+            (
+                dimensions,
+                make_texture_from_desc(&self.display, desc, dimensions)?,
+            ),
         );
         Ok(())
     }
@@ -667,18 +950,109 @@ impl RenderTargets {
             .ok_or_else(|| anyhow::anyhow!("render target '{}' was not registered", name))
     }
 
+    pub fn desc(&self, name: &str) -> Result<&RenderTargetDesc> {
+        self.descriptors
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("render target '{}' was not registered", name))
+    }
+
+    /// the size the target is right now, in pixels.
+    pub fn size(&self, name: &str) -> Result<(u32, u32)> {
+        self.targets
+            .get(name)
+            .map(|&(size, _)| size)
+            .ok_or_else(|| anyhow::anyhow!("render target '{}' was not registered", name))
+    }
+
     pub fn resize(&mut self, dimensions: (u32, u32)) -> anyhow::Result<()> {
         for (name, &desc) in self.descriptors.iter() {
             let (old_dims, buffer) = self.targets.get_mut(name).unwrap();
-            let new_dims = desc.size.apply(dimensions);
+            let new_dims = desc.size.apply(dimensions, self.render_scale);
             if *old_dims != new_dims {
-                *buffer = make_texture_from_desc(&self.display, desc)?;
+                *buffer = make_texture_from_desc(&self.display, desc, new_dims)?;
                 *old_dims = new_dims;
             }
         }
+        self.previous_size = dimensions;
+        Ok(())
+    }
+
+    /// changes how big [`RenderTargetSize::RenderScaled`] targets are compared
+    /// to the window, remaking them if their size changes.
+    pub fn set_render_scale(&mut self, render_scale: f32) -> Result<()> {
+        if self.render_scale != render_scale {
+            self.render_scale = render_scale;
+            self.resize(self.display.get_framebuffer_dimensions())?;
+        }
+        Ok(())
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// copies `source` into `target`, averaging the samples of each pixel if
+    /// `source` is multisampled. both targets need to be the same size.
+    pub fn resolve(&self, source: &str, target: &str) -> Result<()> {
+        let source_target = self.get(source)?;
+        let mask = BlitMask {
+            color: source_target.color().is_some(),
+            depth: source_target.depth().is_some(),
+            stencil: false,
+        };
+
+        let source_buffer = source_target.framebuffer(&self.display)?;
+        let target_buffer = self.get(target)?.framebuffer(&self.display)?;
+        let (width, height) = source_buffer.get_dimensions();
+        target_buffer.blit_buffers_from_simple_framebuffer(
+            &source_buffer,
+            &Rect {
+                left: 0,
+                bottom: 0,
+                width,
+                height,
+            },
+            &BlitTarget {
+                left: 0,
+                bottom: 0,
+                width: width as i32,
+                height: height as i32,
+            },
+            MagnifySamplerFilter::Nearest,
+            mask,
+        );
         Ok(())
     }
 
+    /// a framebuffer that draws into several targets at once. each fragment
+    /// shader output is written to the color texture of the target it's paired
+    /// with, and depth is written to `depth_target`'s depth texture.
+    pub fn multi_framebuffer(
+        &self,
+        outputs: &[(&'static str, &str)],
+        depth_target: &str,
+    ) -> Result<MultiOutputFrameBuffer<'_>> {
+        let mut attachments = Vec::with_capacity(outputs.len());
+        for &(output, target) in outputs {
+            let color = match self.get(target)?.color() {
+                Some(color) => color.as_color_attachment()?,
+                None => bail!("render target '{}' has no color texture", target),
+            };
+            attachments.push((output, color));
+        }
+
+        let depth = match self.get(depth_target)?.depth() {
+            Some(depth) => depth.as_depth_attachment()?,
+            None => bail!("render target '{}' has no depth texture", depth_target),
+        };
+
+        Ok(MultiOutputFrameBuffer::with_depth_buffer(
+            &*self.display,
+            attachments,
+            depth,
+        )?)
+    }
+
     // clear all textures that have specified clear values.
     pub fn reset(&mut self) -> Result<()> {
         let new_dims = self.display.get_framebuffer_dimensions();
@@ -699,15 +1073,162 @@ impl RenderTargets {
     }
 }
 
-fn declare_targets(mut targets: NonSendMut<RenderTargets>) -> Result<()> {
+/// declares the targets that the world is drawn into, which are multisampled
+/// when MSAA is on, along with the targets they're resolved into. they're all
+/// sized by the render scale instead of the window.
+fn declare_world_targets(targets: &mut RenderTargets, anti_aliasing: AntiAliasing) -> Result<()> {
+    let samples = anti_aliasing.samples();
     targets.declare_target("world", RenderTargetDesc {
-        size: RenderTargetSize::WindowExact,
+        size: RenderTargetSize::RenderScaled,
         kind: RenderTargetKind::ColorDepth {
             color: ColorTextureFormat::UncompressedFloat(UncompressedFloatFormat::F16F16F16),
             depth: DepthStencilTextureFormat::DepthFormat(DepthFormat::F32),
             clear_color: None, // completely filled in with sky pass
             clear_depth: Some(1.0),
         },
+        samples,
+    })?;
+
+    // the terrain's surface color and normals, which dynamic lights are drawn
+    // on top of once the terrain is done.
+    targets.declare_target("gbuffer_albedo", RenderTargetDesc {
+        size: RenderTargetSize::RenderScaled,
+        kind: RenderTargetKind::ColorOnly {
+            color: ColorTextureFormat::UncompressedFloat(UncompressedFloatFormat::F16F16F16),
+            clear_color: Some([0.0; 4]),
+        },
+        samples,
+    })?;
+    targets.declare_target("gbuffer_normal", RenderTargetDesc {
+        size: RenderTargetSize::RenderScaled,
+        kind: RenderTargetKind::ColorOnly {
+            color: ColorTextureFormat::UncompressedFloat(UncompressedFloatFormat::F16F16F16),
+            clear_color: Some([0.0; 4]),
+        },
+        samples,
+    })?;
+
+    for name in WORLD_TARGETS {
+        targets.declare_resolve_target(&resolved_target_name(name), name)?;
+    }
+
+    Ok(())
+}
+
+/// every render pass, along with the targets it reads from and draws into.
+fn frame_graph(shadows: &ShadowSettings) -> Result<FrameGraph> {
+    let shadow_maps = (0..usize::max(1, shadows.cascades)).map(shadow_target_name);
+    let shadow_maps = shadow_maps.collect::<Vec<_>>();
+
+    let mut builder = FrameGraph::builder();
+    let shadow_pass = builder.pass("shadows");
+    for name in shadow_maps.iter() {
+        shadow_pass.writes(name);
+    }
+
+    builder.pass("sky").writes("world");
+    let terrain = builder.pass("terrain");
+    for name in shadow_maps.iter() {
+        terrain.reads(name);
+    }
+    for name in WORLD_TARGETS {
+        terrain.writes(name);
+    }
+    builder.pass("precipitation").writes("world");
+    builder.pass("lines").writes("world");
+    builder.pass("quads").writes("world");
+
+    let resolve = builder.pass("resolve");
+    for name in WORLD_TARGETS {
+        resolve.resolves(name, resolved_target_name(name));
+    }
+    let lighting = builder.pass("lighting");
+    for name in WORLD_TARGETS {
+        lighting.reads(resolved_target_name(name));
+    }
+    lighting.writes("world_resolved");
+
+    // each bloom level is blurred out of the one before it
+    let bloom = builder.pass("bloom").reads("world_resolved");
+    let mut source = String::from("bloom_bright");
+    bloom.writes(&source);
+    for level in 0..BLOOM_LEVELS {
+        let blur = bloom_blur_target_name(level);
+        bloom.reads(&source).writes(&blur).reads(&blur);
+        source = bloom_target_name(level);
+        bloom.writes(&source);
+    }
+
+    let post = builder.pass("post").reads("world_resolved");
+    for level in 0..BLOOM_LEVELS {
+        post.reads(bloom_target_name(level));
+    }
+    // the post target is only drawn into when FXAA is on
+    post.writes("post").reads("post").writes("final");
+    builder.pass("hud").writes("final");
+    builder.pass("ui").writes("final");
+
+    builder.build()
+}
+
+fn declare_targets(
+    mut targets: NonSendMut<RenderTargets>,
+    graph: Res<FrameGraph>,
+    shadows: Res<ShadowSettings>,
+    anti_aliasing: Res<AntiAliasing>,
+) -> Result<()> {
+    // the terrain shader always samples every shadow map, so there's at least one
+    // of them even when shadows are turned off.
+    let shadow_size = match shadows.cascades {
+        0 => RenderTargetSize::Exact(1, 1),
+        _ => RenderTargetSize::Exact(shadows.resolution, shadows.resolution),
+    };
+    for cascade in 0..usize::max(1, shadows.cascades) {
+        targets.declare_target(&shadow_target_name(cascade), RenderTargetDesc {
+            size: shadow_size,
+            kind: RenderTargetKind::DepthOnly {
+                depth: DepthStencilTextureFormat::DepthFormat(DepthFormat::F32),
+                clear_depth: Some(1.0),
+            },
+            samples: None,
+        })?;
+    }
+
+    declare_world_targets(&mut targets, *anti_aliasing)?;
+
+    // the bright parts of the world, and the chain of blurred, downsampled
+    // copies of them that make up the bloom.
+    targets.declare_target("bloom_bright", RenderTargetDesc {
+        size: RenderTargetSize::WindowScaledDown(2),
+        kind: RenderTargetKind::ColorOnly {
+            color: ColorTextureFormat::UncompressedFloat(UncompressedFloatFormat::F16F16F16),
+            clear_color: Some([0.0; 4]),
+        },
+        samples: None,
+    })?;
+    for level in 0..BLOOM_LEVELS {
+        let size = RenderTargetSize::WindowScaledDown(2 << level);
+        let kind = RenderTargetKind::ColorOnly {
+            color: ColorTextureFormat::UncompressedFloat(UncompressedFloatFormat::F16F16F16),
+            clear_color: Some([0.0; 4]),
+        };
+        for name in [bloom_target_name(level), bloom_blur_target_name(level)] {
+            targets.declare_target(&name, RenderTargetDesc {
+                size,
+                kind,
+                samples: None,
+            })?;
+        }
+    }
+
+    // the post pass draws in here when FXAA is on, which then smooths it out
+    // into the final target.
+    targets.declare_target("post", RenderTargetDesc {
+        size: RenderTargetSize::WindowExact,
+        kind: RenderTargetKind::ColorOnly {
+            color: ColorTextureFormat::UncompressedFloat(UncompressedFloatFormat::F16F16F16),
+            clear_color: None, // completely filled in with post pass
+        },
         samples: None,
     })?;
 
@@ -720,7 +1241,7 @@ fn declare_targets(mut targets: NonSendMut<RenderTargets>) -> Result<()> {
         samples: None,
     })?;
 
-    Ok(())
+    graph.validate(&targets)
 }
 
 #[derive(Debug)]
@@ -745,7 +1266,8 @@ impl<M> Clone for MeshHandle<M> {
 
 impl<M> MeshHandle<M> {
     pub fn reupload(&self, mesh: M) {
-        self.0.shared.mesh_sender.send((self.0.id, mesh)).unwrap();
+        // the shared context holds onto the receiver too, so this can't fail
+        let _ = self.0.shared.mesh_sender.send((self.0.id, mesh));
     }
 }
 
@@ -770,28 +1292,60 @@ pub trait UploadableMesh {
     fn upload<F: Facade>(&self, ctx: &F) -> Result<MeshBuffers<Self::Vertex>>;
 }
 
-struct LocalMeshContext<M: UploadableMesh> {
+pub struct LocalMeshContext<M: UploadableMesh> {
     shared: Arc<SharedMeshContext<M>>,
+    /// every mesh that's been sent to us. these are kept around so that batches
+    /// of meshes can be rebuilt without reading anything back from the GPU.
+    sources: HashMap<usize, M>,
+    /// meshes that are drawn on their own. these are only uploaded once
+    /// something asks for them.
     meshes: HashMap<usize, MeshBuffers<M::Vertex>>,
 }
 
 impl<M: UploadableMesh + Send + Sync + 'static> LocalMeshContext<M> {
-    pub fn new() -> Self {
+    fn new() -> Self {
         Self {
             shared: SharedMeshContext::new(),
+            sources: Default::default(),
             meshes: Default::default(),
         }
     }
 
-    fn update<F: Facade>(&mut self, ctx: &F) -> Result<()> {
+    /// takes in new and dropped meshes, returning the ids of every mesh that
+    /// was added, replaced, or dropped.
+    fn update(&mut self) -> HashSet<usize> {
+        let mut changed = HashSet::new();
         for (id, data) in self.shared.mesh_receiver.try_iter() {
-            self.meshes.insert(id, data.upload(ctx)?);
+            self.sources.insert(id, data);
+            self.meshes.remove(&id);
+            changed.insert(id);
         }
 
         for id in self.shared.mesh_dropped_receiver.try_iter() {
+            self.sources.remove(&id);
             self.meshes.remove(&id);
+            changed.insert(id);
         }
 
+        changed
+    }
+
+    /// meshes that fail to upload are forgotten about, so that they aren't
+    /// retried every frame.
+    fn upload<F: Facade>(&mut self, ctx: &F, id: usize) -> Result<()> {
+        if !self.meshes.contains_key(&id) {
+            if let Some(source) = self.sources.get(&id) {
+                match source.upload(ctx) {
+                    Ok(buffers) => {
+                        self.meshes.insert(id, buffers);
+                    }
+                    Err(err) => {
+                        self.sources.remove(&id);
+                        return Err(err);
+                    }
+                }
+            }
+        }
         Ok(())
     }
 }
@@ -821,7 +1375,8 @@ impl<M> SharedMeshContext<M> {
 
     pub fn upload(self: &Arc<Self>, mesh: M) -> MeshHandle<M> {
         let id = self.next_id.fetch_add(1, Ordering::SeqCst);
-        self.mesh_sender.send((id, mesh)).unwrap();
+        // we hold onto the receiver, so this can't fail
+        let _ = self.mesh_sender.send((id, mesh));
         MeshHandle(Arc::new(MeshHandleInner {
             id,
             shared: Arc::clone(&self),
@@ -833,89 +1388,580 @@ impl<M> SharedMeshContext<M> {
 #[derive(Debug)]
 pub struct RenderMeshComponent<M>(MeshHandle<M>);
 
-impl<M> RenderMeshComponent<M> {
-    pub fn new(handle: MeshHandle<M>) -> Self {
-        Self(handle)
-    }
-}
+type TerrainMeshBuffers = MeshBuffers<<TerrainMesh as UploadableMesh>::Vertex>;
 
-#[derive(SystemParam)]
-pub struct RenderParams<'a> {
-    display: NonSend<'a, Rc<Display>>,
-    pub targets: NonSendMut<'a, RenderTargets>,
-    pub shaders: NonSendMut<'a, ShaderLoaderState>,
-}
+/// how many chunk sections along each axis are merged into a single batch.
+/// bigger batches mean fewer draw calls, but coarser culling, and more to
+/// re-upload whenever one of their sections is remeshed.
+const TERRAIN_BATCH_LENGTH: i32 = 4;
 
-impl<'a> RenderParams<'a> {
-    /// Get a reference to the render params's display.
-    pub fn display(&self) -> &Display {
-        &**self.display
-    }
+/// where the chunk section that a vertex belongs to starts. terrain vertices
+/// only know where they are within their own section, so this is needed to
+/// draw several sections at once.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct ChunkOrigin {
+    chunk_origin: [f32; 3],
 }
 
-fn should_draw_aabb(mvp: &Matrix4<f32>, aabb: &Aabb) -> bool {
-    // an AABB is excluded from the test if all its 8 corners lay outside any single
-    // frustum plane. we transform into clip space because the camera frustum planes
-    // have some very nice properties. each plane is 1 unit from the origin along
-    // its respective axis, and points inwards directly towards the origin. because
-    // of this, the test for e.x. the bottom plane is simply `point.y / point.w >
-    // -1.0`. we can just test `point.y > -point.w` though, by multiplying both
-    // sides of the inequality by `point.w`
-
-    // my first attempt at this only tested if each corner was inside the camera
-    // frustum, instead of outside any frustum plane, which led to some false
-    // negatives where the corners would straddle the corner of the frustum, so the
-    // line connecting them would cross through the frustum. this means that the
-    // object might potentially influence the resulting image, but was excluded
-    // because those points weren't actually inside the frustum.
+glium::implement_vertex!(ChunkOrigin, chunk_origin);
 
-    let corners_clip = [
-        mvp * point![aabb.min.x, aabb.min.y, aabb.min.z, 1.0],
-        mvp * point![aabb.max.x, aabb.min.y, aabb.min.z, 1.0],
-        mvp * point![aabb.min.x, aabb.max.y, aabb.min.z, 1.0],
-        mvp * point![aabb.max.x, aabb.max.y, aabb.min.z, 1.0],
-        mvp * point![aabb.min.x, aabb.min.y, aabb.max.z, 1.0],
-        mvp * point![aabb.max.x, aabb.min.y, aabb.max.z, 1.0],
-        mvp * point![aabb.min.x, aabb.max.y, aabb.max.z, 1.0],
-        mvp * point![aabb.max.x, aabb.max.y, aabb.max.z, 1.0],
-    ];
+/// about how many bytes of terrain batches are uploaded each frame. batches
+/// that don't fit wait for a later frame, closest to the camera first, so that
+/// lots of sections being remeshed at once doesn't make for one long frame. at
+/// least one batch is always uploaded, even if it's bigger than this.
+const TERRAIN_UPLOAD_BUDGET: usize = 4 << 20;
 
-    let px = !corners_clip.iter().all(|point| point.x > point.w);
-    let nx = !corners_clip.iter().all(|point| point.x < -point.w);
-    let py = !corners_clip.iter().all(|point| point.y > point.w);
-    let ny = !corners_clip.iter().all(|point| point.y < -point.w);
-    let pz = !corners_clip.iter().all(|point| point.z > point.w);
-    let nz = !corners_clip.iter().all(|point| point.z < -point.w);
+/// batch buffers are made with this much more room than they need, so that a
+/// batch that grows a little after a remesh can be written into the buffers it
+/// already has.
+const BATCH_HEADROOM: f32 = 1.5;
+/// buffers that end up this many times bigger than what's in them are made
+/// again at a smaller size, so that batches that shrink give the memory back.
+const BATCH_SHRINK_FACTOR: usize = 4;
 
-    px && nx && py && ny && pz && nz
+fn batch_capacity(len: usize) -> usize {
+    usize::max(1, (len as f32 * BATCH_HEADROOM) as usize)
 }
 
-pub fn array4x4<T: Copy + Into<[[U; 4]; 4]>, U>(mat: &T) -> [[U; 4]; 4] {
-    (*mat).into()
+fn batch_buffer_fits(capacity: usize, len: usize) -> bool {
+    capacity >= len && capacity / BATCH_SHRINK_FACTOR <= len
 }
 
-pub fn array2<T: Copy + Into<[U; 2]>, U>(vec: &T) -> [U; 2] {
-    (*vec).into()
-}
+/// writes `data` into the start of `previous` if it fits, or into a new buffer
+/// with some room to spare if it doesn't.
+fn write_batch_vertices<T: glium::Vertex>(
+    display: &Display,
+    previous: Option<VertexBuffer<T>>,
+    data: &[T],
+) -> Result<VertexBuffer<T>> {
+    let buffer = match previous {
+        Some(buffer) if batch_buffer_fits(buffer.len(), data.len()) => buffer,
+        _ => VertexBuffer::empty_dynamic(display, batch_capacity(data.len()))?,
+    };
+    if let Some(slice) = buffer.slice(..data.len()) {
+        slice.write(data);
+    }
+    Ok(buffer)
+}
+
+fn write_batch_indices(
+    display: &Display,
+    previous: Option<IndexBuffer<u32>>,
+    data: &[u32],
+) -> Result<IndexBuffer<u32>> {
+    let buffer = match previous {
+        Some(buffer) if batch_buffer_fits(buffer.len(), data.len()) => buffer,
+        _ => {
+            let capacity = batch_capacity(data.len());
+            IndexBuffer::empty_dynamic(display, PrimitiveType::TrianglesList, capacity)?
+        }
+    };
+    if let Some(slice) = buffer.slice(..data.len()) {
+        slice.write(data);
+    }
+    Ok(buffer)
+}
+
+/// the meshes of nearby chunk sections, merged together so they can all be
+/// drawn at once.
+pub struct TerrainBatch {
+    /// these can be bigger than what's in them, see [`BATCH_HEADROOM`]. only
+    /// the first `vertex_count` vertices and `index_count` indices are used.
+    vertices: VertexBuffer<TerrainVertex>,
+    /// the origin of each vertex's section, see [`ChunkOrigin`].
+    origins: VertexBuffer<ChunkOrigin>,
+    indices: IndexBuffer<u32>,
+    vertex_count: usize,
+    index_count: usize,
+    /// the bounds of each section in the batch, in world space.
+    sections: Vec<Aabb>,
+    /// the bounds of the whole batch, in world space.
+    aabb: Aabb,
+}
+
+type BatchVertices<'a> = (
+    glium::vertex::VertexBufferSlice<'a, TerrainVertex>,
+    glium::vertex::VertexBufferSlice<'a, ChunkOrigin>,
+);
+
+impl TerrainBatch {
+    fn vertices(&self) -> BatchVertices<'_> {
+        let count = self.vertex_count;
+        (
+            self.vertices.slice(..count).unwrap(),
+            self.origins.slice(..count).unwrap(),
+        )
+    }
 
-pub fn array3<T: Copy + Into<[U; 3]>, U>(vec: &T) -> [U; 3] {
-    (*vec).into()
-}
+    fn indices(&self) -> glium::index::IndexBufferSlice<'_, u32> {
+        self.indices.slice(..self.index_count).unwrap()
+    }
 
-lazy_static::lazy_static! {
-    static ref DEBUG_BOX_SENDER: RwLock<Option<Sender<DebugBox>>> = RwLock::new(None);
-    static ref TRANSIENT_DEBUG_BOX_SENDER: RwLock<Option<Sender<(Duration, DebugBox)>>> = RwLock::new(None);
-}
+    /// puts together the meshes of `members`, reusing the buffers of the batch
+    /// being replaced where they're big enough. returns `None` if none of the
+    /// members have a mesh, along with how many bytes were uploaded.
+    fn build(
+        display: &Display,
+        members: &HashMap<usize, Vector3<f32>>,
+        sources: &HashMap<usize, TerrainMesh>,
+        previous: Option<Self>,
+    ) -> Result<(Option<Self>, usize)> {
+        let mut vertices = Vec::new();
+        let mut origins = Vec::new();
+        let mut indices = Vec::new();
+        let mut sections = Vec::new();
+        for (id, origin) in members.iter() {
+            let mesh = match sources.get(id) {
+                Some(mesh) => mesh,
+                None => continue,
+            };
+
+            let base = vertices.len() as u32;
+            vertices.extend_from_slice(mesh.vertices());
+            origins.resize(vertices.len(), ChunkOrigin {
+                chunk_origin: (*origin).into(),
+            });
+            indices.extend(mesh.indices().iter().map(|&index| base + index));
+
+            let length = CHUNK_LENGTH as f32;
+            sections.push(Aabb {
+                min: Point3::from(*origin),
+                max: Point3::from(origin + vector![length, length, length]),
+            });
+        }
 
-pub fn add_debug_box(debug_box: DebugBox) {
-    if let Some(sender) = DEBUG_BOX_SENDER.read().as_ref() {
-        sender.send(debug_box).unwrap();
+        let aabb = match sections.split_first() {
+            Some((first, rest)) => rest.iter().fold(*first, |acc, section| Aabb {
+                min: acc.min.inf(&section.min),
+                max: acc.max.sup(&section.max),
+            }),
+            None => return Ok((None, 0)),
+        };
+
+        let (old_vertices, old_origins, old_indices) = match previous {
+            Some(batch) => (
+                Some(batch.vertices),
+                Some(batch.origins),
+                Some(batch.indices),
+            ),
+            None => (None, None, None),
+        };
+        let bytes = std::mem::size_of_val(&vertices[..])
+            + std::mem::size_of_val(&origins[..])
+            + std::mem::size_of_val(&indices[..]);
+        let batch = Self {
+            vertices: write_batch_vertices(display, old_vertices, &vertices)?,
+            origins: write_batch_vertices(display, old_origins, &origins)?,
+            indices: write_batch_indices(display, old_indices, &indices)?,
+            vertex_count: vertices.len(),
+            index_count: indices.len(),
+            sections,
+            aabb,
+        };
+        Ok((Some(batch), bytes))
     }
 }
 
-pub fn add_transient_debug_box(duration: Duration, debug_box: DebugBox) {
+type BatchPos = [i32; 3];
+
+/// chunk sections, grouped into batches by where they are.
+#[derive(Default)]
+pub struct TerrainBatches {
+    /// the meshes in each batch, along with the origins of their sections.
+    members: HashMap<BatchPos, HashMap<usize, Vector3<f32>>>,
+    /// which batch each mesh is in.
+    mesh_batches: HashMap<usize, BatchPos>,
+    batches: HashMap<BatchPos, TerrainBatch>,
+    /// batches whose members changed since they were last built.
+    dirty: HashSet<BatchPos>,
+}
+
+impl TerrainBatches {
+    fn add_mesh(&mut self, id: usize, origin: Vector3<f32>) {
+        if self.mesh_batches.contains_key(&id) {
+            return;
+        }
+
+        let batch_length = (TERRAIN_BATCH_LENGTH * CHUNK_LENGTH as i32) as f32;
+        let pos = origin
+            .map(|coord| (coord / batch_length).floor() as i32)
+            .into();
+        self.members.entry(pos).or_default().insert(id, origin);
+        self.mesh_batches.insert(id, pos);
+        self.dirty.insert(pos);
+    }
+
+    fn remove_mesh(&mut self, id: usize) {
+        if let Some(pos) = self.mesh_batches.remove(&id) {
+            if let Some(members) = self.members.get_mut(&pos) {
+                members.remove(&id);
+            }
+            self.dirty.insert(pos);
+        }
+    }
+
+    /// rebuilds dirty batches until [`TERRAIN_UPLOAD_BUDGET`] runs out,
+    /// starting with the ones closest to `camera`. the rest stay dirty until a
+    /// later frame. batches that fail to build are dropped, so that one bad
+    /// upload doesn't take the rest of the terrain down with it.
+    fn rebuild(
+        &mut self,
+        display: &Display,
+        sources: &HashMap<usize, TerrainMesh>,
+        camera: Point3<f32>,
+    ) {
+        let batch_length = (TERRAIN_BATCH_LENGTH * CHUNK_LENGTH as i32) as f32;
+        let distance = |pos: &BatchPos| {
+            let center = Vector3::from(*pos).map(|coord| (coord as f32 + 0.5) * batch_length);
+            (Point3::from(center) - camera).norm_squared()
+        };
+        let mut dirty = self.dirty.iter().copied().collect::<Vec<_>>();
+        dirty.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+
+        let mut uploaded = 0;
+        for pos in dirty {
+            if uploaded >= TERRAIN_UPLOAD_BUDGET {
+                break;
+            }
+            self.dirty.remove(&pos);
+
+            let previous = self.batches.remove(&pos);
+            let built = match self.members.get(&pos) {
+                Some(members) => TerrainBatch::build(display, members, sources, previous),
+                None => Ok((None, 0)),
+            };
+
+            let built = match built {
+                Ok((built, bytes)) => {
+                    uploaded += bytes;
+                    built
+                }
+                Err(err) => {
+                    log::error!("failed to build terrain batch at {:?}: {}", pos, err);
+                    continue;
+                }
+            };
+
+            match built {
+                Some(batch) => {
+                    self.batches.insert(pos, batch);
+                }
+                None => {
+                    self.batches.remove(&pos);
+                    if self.members.get(&pos).is_some_and(HashMap::is_empty) {
+                        self.members.remove(&pos);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// every terrain mesh. chunk sections are drawn in batches, and anything else
+/// that uses terrain meshes, like mobs, is drawn on its own.
+#[derive(SystemParam)]
+pub struct TerrainMeshes<'a> {
+    sections: Query<
+        'a,
+        (
+            &'static Transform,
+            &'static RenderMeshComponent<TerrainMesh>,
+        ),
+        With<HasTerrainMesh>,
+    >,
+    others: Query<
+        'a,
+        (
+            &'static Transform,
+            &'static RenderMeshComponent<TerrainMesh>,
+        ),
+        Without<HasTerrainMesh>,
+    >,
+    meshes: NonSendMut<'a, LocalMeshContext<TerrainMesh>>,
+    batches: NonSendMut<'a, TerrainBatches>,
+}
+
+impl<'a> TerrainMeshes<'a> {
+    fn update(&mut self, display: &Display, camera: Point3<f32>) {
+        for id in self.meshes.update() {
+            self.batches.remove_mesh(id);
+        }
+
+        // remeshed sections come back with a new mesh id, so they're added
+        // here again after being removed above.
+        for (transform, RenderMeshComponent(handle)) in self.sections.iter() {
+            self.batches
+                .add_mesh(handle.0.id, transform.translation.vector);
+        }
+        self.batches.rebuild(display, &self.meshes.sources, camera);
+
+        // meshes that fail to upload just aren't drawn, see `others`
+        for (_, RenderMeshComponent(handle)) in self.others.iter() {
+            let id = handle.0.id;
+            if let Err(err) = self.meshes.upload(display, id) {
+                log::error!("failed to upload mesh {}, skipping it: {}", id, err);
+            }
+        }
+    }
+
+    fn batches(&self) -> impl Iterator<Item = &TerrainBatch> + '_ {
+        self.batches.batches.values()
+    }
+
+    fn others(&self) -> impl Iterator<Item = (&Transform, &TerrainMeshBuffers)> + '_ {
+        self.others
+            .iter()
+            .filter_map(move |(transform, RenderMeshComponent(handle))| {
+                let buffers = self.meshes.meshes.get(&handle.0.id)?;
+                Some((transform, buffers))
+            })
+    }
+}
+
+/// how many draw calls the terrain took last frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct TerrainDrawStats {
+    /// how many terrain meshes were visible, which is how many draw calls there
+    /// would have been without batching.
+    pub visible_meshes: usize,
+    pub draw_calls: usize,
+    pub batches: usize,
+}
+
+/// the render passes whose time on the GPU is measured.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GpuPass {
+    Sky,
+    Terrain,
+    Lines,
+    Post,
+}
+
+impl GpuPass {
+    pub const ALL: [GpuPass; 4] = [GpuPass::Sky, GpuPass::Terrain, GpuPass::Lines, GpuPass::Post];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GpuPass::Sky => "sky",
+            GpuPass::Terrain => "terrain",
+            GpuPass::Lines => "lines",
+            GpuPass::Post => "post",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// how many milliseconds each pass took on the GPU.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct GpuPassTimes([f32; 4]);
+
+impl GpuPassTimes {
+    pub fn millis(&self, pass: GpuPass) -> f32 {
+        self.0[pass.index()]
+    }
+
+    pub fn total_millis(&self) -> f32 {
+        self.0.iter().sum()
+    }
+}
+
+/// stats about how recent frames were drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct RenderStats {
+    pub terrain: TerrainDrawStats,
+    /// GPU timings come back a few frames late, so these are from a little
+    /// while ago. they're missing when timer queries aren't supported.
+    pub gpu: Option<GpuPassTimes>,
+}
+
+/// how many frames of timer queries can be waiting on the GPU at once. any
+/// more than that, and the oldest ones are thrown away instead of waiting on
+/// them, so that reading timings never stalls the CPU.
+const MAX_PENDING_GPU_FRAMES: usize = 4;
+
+/// a timer query that one or more of a pass's draws are measured with. draws
+/// must be made one after another for the query to include all of them.
+pub struct GpuTimer {
+    pass: GpuPass,
+    query: Option<TimeElapsedQuery>,
+    used: Cell<bool>,
+}
+
+impl GpuTimer {
+    /// the query to put in the draw parameters of the draws being timed.
+    pub fn query(&self) -> Option<&TimeElapsedQuery> {
+        self.used.set(true);
+        self.query.as_ref()
+    }
+}
+
+/// keeps track of the GPU timer queries of the last few frames, and reads them
+/// back once the GPU is done with them.
+pub struct GpuTimers {
+    display: Rc<Display>,
+    /// becomes false the first time a query can't be made, after which nothing
+    /// is timed.
+    supported: bool,
+    /// the queries of each frame that hasn't been read back yet, oldest first.
+    pending: VecDeque<Vec<(GpuPass, TimeElapsedQuery)>>,
+}
+
+impl GpuTimers {
+    fn new(display: &Rc<Display>) -> Self {
+        Self {
+            display: Rc::clone(display),
+            supported: true,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn start(&mut self, pass: GpuPass) -> GpuTimer {
+        let query = match self.supported {
+            true => TimeElapsedQuery::new(&*self.display)
+                .map_err(|err| {
+                    log::warn!("GPU timer queries aren't supported, not timing passes: {:?}", err);
+                    self.supported = false;
+                })
+                .ok(),
+            false => None,
+        };
+
+        GpuTimer {
+            pass,
+            query,
+            used: Cell::new(false),
+        }
+    }
+
+    pub fn finish(&mut self, timer: GpuTimer) {
+        let query = match timer.query {
+            Some(query) if timer.used.get() => query,
+            // a query that was never drawn with never becomes ready
+            _ => return,
+        };
+        // asking whether the query is ready ends it, so that nothing drawn after
+        // this gets counted towards this pass.
+        query.is_ready();
+        if let Some(frame) = self.pending.back_mut() {
+            frame.push((timer.pass, query));
+        }
+    }
+
+    /// starts timing a new frame, and returns the timings of the latest frame
+    /// whose queries have all finished, if there is one.
+    fn begin_frame(&mut self) -> Option<GpuPassTimes> {
+        let mut latest = None;
+        while let Some(frame) = self.pending.front() {
+            if !frame.iter().all(|(_, query)| query.is_ready()) {
+                break;
+            }
+
+            let mut times = GpuPassTimes::default();
+            for (pass, query) in self.pending.pop_front().into_iter().flatten() {
+                times.0[pass.index()] += query.get() as f32 / 1_000_000.0;
+            }
+            latest = Some(times);
+        }
+
+        while self.pending.len() >= MAX_PENDING_GPU_FRAMES {
+            self.pending.pop_front();
+        }
+        if self.supported {
+            self.pending.push_back(vec![]);
+        }
+        latest
+    }
+}
+
+impl<M> RenderMeshComponent<M> {
+    pub fn new(handle: MeshHandle<M>) -> Self {
+        Self(handle)
+    }
+}
+
+#[derive(SystemParam)]
+pub struct RenderParams<'a> {
+    display: NonSend<'a, Rc<Display>>,
+    pub targets: NonSendMut<'a, RenderTargets>,
+    pub shaders: NonSendMut<'a, ShaderLoaderState>,
+    pub timers: NonSendMut<'a, GpuTimers>,
+}
+
+impl<'a> RenderParams<'a> {
+    /// Get a reference to the render params's display.
+    pub fn display(&self) -> &Display {
+        &**self.display
+    }
+}
+
+fn should_draw_aabb(mvp: &Matrix4<f32>, aabb: &Aabb) -> bool {
+    // an AABB is excluded from the test if all its 8 corners lay outside any single
+    // frustum plane. we transform into clip space because the camera frustum planes
+    // have some very nice properties. each plane is 1 unit from the origin along
+    // its respective axis, and points inwards directly towards the origin. because
+    // of this, the test for e.x. the bottom plane is simply `point.y / point.w >
+    // -1.0`. we can just test `point.y > -point.w` though, by multiplying both
+    // sides of the inequality by `point.w`
+
+    // my first attempt at this only tested if each corner was inside the camera
+    // frustum, instead of outside any frustum plane, which led to some false
+    // negatives where the corners would straddle the corner of the frustum, so the
+    // line connecting them would cross through the frustum. this means that the
+    // object might potentially influence the resulting image, but was excluded
+    // because those points weren't actually inside the frustum.
+
+    let corners_clip = [
+        mvp * point![aabb.min.x, aabb.min.y, aabb.min.z, 1.0],
+        mvp * point![aabb.max.x, aabb.min.y, aabb.min.z, 1.0],
+        mvp * point![aabb.min.x, aabb.max.y, aabb.min.z, 1.0],
+        mvp * point![aabb.max.x, aabb.max.y, aabb.min.z, 1.0],
+        mvp * point![aabb.min.x, aabb.min.y, aabb.max.z, 1.0],
+        mvp * point![aabb.max.x, aabb.min.y, aabb.max.z, 1.0],
+        mvp * point![aabb.min.x, aabb.max.y, aabb.max.z, 1.0],
+        mvp * point![aabb.max.x, aabb.max.y, aabb.max.z, 1.0],
+    ];
+
+    let px = !corners_clip.iter().all(|point| point.x > point.w);
+    let nx = !corners_clip.iter().all(|point| point.x < -point.w);
+    let py = !corners_clip.iter().all(|point| point.y > point.w);
+    let ny = !corners_clip.iter().all(|point| point.y < -point.w);
+    let pz = !corners_clip.iter().all(|point| point.z > point.w);
+    let nz = !corners_clip.iter().all(|point| point.z < -point.w);
+
+    px && nx && py && ny && pz && nz
+}
+
+pub fn array4x4<T: Copy + Into<[[U; 4]; 4]>, U>(mat: &T) -> [[U; 4]; 4] {
+    (*mat).into()
+}
+
+pub fn array2<T: Copy + Into<[U; 2]>, U>(vec: &T) -> [U; 2] {
+    (*vec).into()
+}
+
+pub fn array3<T: Copy + Into<[U; 3]>, U>(vec: &T) -> [U; 3] {
+    (*vec).into()
+}
+
+lazy_static::lazy_static! {
+    static ref DEBUG_BOX_SENDER: RwLock<Option<Sender<DebugBox>>> = RwLock::new(None);
+    static ref TRANSIENT_DEBUG_BOX_SENDER: RwLock<Option<Sender<(Duration, DebugBox)>>> = RwLock::new(None);
+}
+
+pub fn add_debug_box(debug_box: DebugBox) {
+    // the renderer might have shut down already, in which case there's nothing
+    // to draw the box anyways
+    if let Some(sender) = DEBUG_BOX_SENDER.read().as_ref() {
+        let _ = sender.send(debug_box);
+    }
+}
+
+pub fn add_transient_debug_box(duration: Duration, debug_box: DebugBox) {
     if let Some(sender) = TRANSIENT_DEBUG_BOX_SENDER.read().as_ref() {
-        sender.send((duration, debug_box)).unwrap();
+        let _ = sender.send((duration, debug_box));
     }
 }
 
@@ -961,6 +2007,59 @@ impl DebugBox {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrozenCamera {
+    pub transform: Transform,
+    pub camera: Camera,
+}
+
+impl FrozenCamera {
+    pub fn view(&self) -> Matrix4<f32> {
+        self.transform
+            .to_matrix()
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity)
+    }
+
+    pub fn projection(&self, (width, height): (u32, u32)) -> nalgebra::Perspective3<f32> {
+        let mut proj = self.camera.projection;
+        proj.set_aspect(width as f32 / height as f32);
+        proj
+    }
+}
+
+/// toggles for visualizing what the renderer is doing.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct RenderDebugSettings {
+    /// draw terrain as wireframe instead of filled triangles.
+    pub wireframe: bool,
+    /// draw the boundaries of the chunk sections around the camera.
+    pub section_grid: bool,
+    /// draw the collider of every entity that has one.
+    pub colliders: bool,
+    /// a snapshot of the camera, whose frustum is drawn so it can be inspected
+    /// from elsewhere.
+    pub frozen_camera: Option<FrozenCamera>,
+    /// cull terrain against the frozen camera instead of the one being rendered
+    /// from, drawing the sections that were culled.
+    pub freeze_culling: bool,
+}
+
+impl RenderDebugSettings {
+    /// the camera that terrain should be culled against, if it isn't the one
+    /// being rendered from.
+    pub fn culling_camera(&self) -> Option<&FrozenCamera> {
+        self.frozen_camera.as_ref().filter(|_| self.freeze_culling)
+    }
+}
+
+#[derive(SystemParam)]
+pub struct TerrainDebug<'a> {
+    settings: Res<'a, RenderDebugSettings>,
+    lines: ResMut<'a, ImmediateLines>,
+    stats: ResMut<'a, RenderStats>,
+}
+
 struct DebugLines {
     debug_box_channel: util::ChannelPair<DebugBox>,
     transient_debug_box_channel: util::ChannelPair<(Duration, DebugBox)>,
@@ -1085,6 +2184,97 @@ impl ImmediateLines {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+#[repr(C)]
+pub struct TexturedQuadVertex {
+    pub pos: [f32; 3],
+    pub uv: [f32; 2],
+    /// layer of the block texture array to sample.
+    pub layer: f32,
+    pub color: [f32; 4],
+}
+glium::implement_vertex!(TexturedQuadVertex, pos, uv, layer, color);
+
+/// quads in world space, like the text on signs, that are cleared every frame.
+/// they can either be flat-colored, or show part of a block texture, like the
+/// dust kicked up by running.
+#[derive(Clone, Debug, Default)]
+pub struct ImmediateQuads {
+    vertices: Vec<ImmediateLineVertex>,
+    textured: Vec<TexturedQuadVertex>,
+}
+
+impl ImmediateQuads {
+    /// `corners` go around the edge of the quad, in either direction.
+    pub fn quad(&mut self, corners: [Point3<f32>; 4], color: [f32; 4]) {
+        let [color_rg, color_ba] = translate_color(color);
+        for idx in [0, 1, 2, 2, 3, 0] {
+            self.vertices.push(ImmediateLineVertex {
+                pos: corners[idx].into(),
+                color_rg,
+                color_ba,
+            });
+        }
+    }
+
+    /// like [`ImmediateQuads::quad`], but shows the part of `tex` between the
+    /// `uv_min` and `uv_max` texture coordinates, tinted by `tint`. `uv_min`
+    /// goes with the first corner, and `uv_max` with the third.
+    pub fn textured_quad(
+        &mut self,
+        corners: [Point3<f32>; 4],
+        [uv_min, uv_max]: [[f32; 2]; 2],
+        tex: TextureId,
+        tint: [f32; 4],
+    ) {
+        let uvs = [
+            uv_min,
+            [uv_max[0], uv_min[1]],
+            uv_max,
+            [uv_min[0], uv_max[1]],
+        ];
+        for idx in [0, 1, 2, 2, 3, 0] {
+            self.textured.push(TexturedQuadVertex {
+                pos: corners[idx].into(),
+                uv: uvs[idx],
+                layer: tex.0 as f32,
+                color: tint,
+            });
+        }
+    }
+
+    /// draws a line of text in the built-in pixel font, with `origin` at the
+    /// bottom left corner of the first glyph. `right` and `up` are how big a
+    /// single pixel of the font is along each direction.
+    pub fn text(
+        &mut self,
+        origin: Point3<f32>,
+        right: Vector3<f32>,
+        up: Vector3<f32>,
+        text: &str,
+        color: [f32; 4],
+    ) {
+        let mut x = 0.0;
+        for ch in text.chars() {
+            font::glyph_runs(font::glyph(ch), |start, end, row| {
+                let top = font::GLYPH_HEIGHT - row as f32;
+                let at = |x: f32, y: f32| origin + x * right + y * up;
+                let [left, right] = [x + start as f32, x + end as f32];
+                self.quad(
+                    [
+                        at(left, top - 1.0),
+                        at(right, top - 1.0),
+                        at(right, top),
+                        at(left, top),
+                    ],
+                    color,
+                );
+            });
+            x += font::GLYPH_ADVANCE;
+        }
+    }
+}
+
 pub fn immediate_draw_box_edges(canvas: &mut LineCanvas, aabb: &Aabb) {
     let [nnn, nnp, npn, npp, pnn, pnp, ppn, ppp] = aabb_corners(aabb);
 
@@ -1141,6 +2331,33 @@ fn debug_lines_camera(canvas: &mut LineCanvas, transform: &Transform, camera: &C
         .line(tri_top);
 }
 
+/// draws the frustum of a camera, cut off at `max_distance` so that cameras
+/// with far away far planes don't draw lines off into the distance.
+fn immediate_draw_frustum(
+    canvas: &mut LineCanvas,
+    transform: &Transform,
+    projection: &nalgebra::Perspective3<f32>,
+    max_distance: f32,
+) {
+    let to_world = transform.to_matrix();
+    let plane_corners = |distance: f32| {
+        let half_height = distance * f32::tan(projection.fovy() / 2.0);
+        let half_width = half_height * projection.aspect();
+        [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]].map(|[x, y]| {
+            let corner = vector![x * half_width, y * half_height, -distance, 1.0];
+            (to_world * corner).xyz()
+        })
+    };
+
+    let near = plane_corners(projection.znear());
+    let far = plane_corners(f32::min(projection.zfar(), max_distance));
+    for i in 0..4 {
+        canvas.goto(near[i]).line(near[(i + 1) % 4]);
+        canvas.goto(far[i]).line(far[(i + 1) % 4]);
+        canvas.goto(near[i]).line(far[i]);
+    }
+}
+
 fn aabb_corners(aabb: &Aabb) -> [Vector3<f32>; 8] {
     [
         vector![aabb.min.x, aabb.min.y, aabb.min.z],
@@ -1159,14 +2376,33 @@ fn debug_lines_debug_box(canvas: &mut LineCanvas, debug_box: &DebugBox) {
     immediate_draw_box_edges(canvas, &debug_box.bounds);
 }
 
-fn begin_render(mut ctx: RenderParams) -> anyhow::Result<()> {
+fn begin_render(
+    mut ctx: RenderParams,
+    graph: Res<FrameGraph>,
+    anti_aliasing: Res<AntiAliasing>,
+    render_scale: Res<RenderScale>,
+    mut stats: ResMut<RenderStats>,
+) -> anyhow::Result<()> {
+    // the world targets were already declared with the starting setting, so
+    // they only need to be remade when it changes later on.
+    if anti_aliasing.is_changed() && !anti_aliasing.is_added() {
+        declare_world_targets(&mut ctx.targets, *anti_aliasing)?;
+        graph.validate(&ctx.targets)?;
+    }
+    ctx.targets.set_render_scale(render_scale.current())?;
     ctx.targets.reset()?;
     ctx.targets.frame = Some(ctx.display().draw());
+    if let Some(times) = ctx.timers.begin_frame() {
+        stats.gpu = Some(times);
+    }
     Ok(())
 }
 
 fn end_render(mut ctx: RenderParams) -> anyhow::Result<()> {
-    let frame = ctx.targets.frame.take().unwrap();
+    let frame = match ctx.targets.frame.take() {
+        Some(frame) => frame,
+        None => bail!("tried to end a frame that was never started"),
+    };
     let result_buf = ctx.targets.get("final")?.framebuffer(ctx.display())?;
     result_buf.fill(&frame, MagnifySamplerFilter::Linear);
     frame.finish()?;
@@ -1207,6 +2443,54 @@ fn add_global_debug_lines(mut lines: ResMut<ImmediateLines>, mut debug: NonSendM
     }
 }
 
+// how far out the frustum of a frozen camera is drawn.
+const FROZEN_FRUSTUM_DISTANCE: f32 = 64.0;
+
+fn add_render_debug_lines(
+    settings: Res<RenderDebugSettings>,
+    display: NonSend<Rc<Display>>,
+    camera: CurrentCamera,
+    colliders: Query<(&Transform, &AabbCollider)>,
+    mut lines: ResMut<ImmediateLines>,
+) {
+    let mut canvas = lines.start_default();
+
+    if settings.section_grid {
+        let center = ChunkSectionPos::from(WorldPos::new(camera.pos()));
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let color = match [x, y, z] == [0, 0, 0] {
+                        true => [0.4, 0.7, 1.0, 0.9],
+                        false => [0.4, 0.7, 1.0, 0.3],
+                    };
+                    let aabb = chunk_section_aabb(center.offset([x, y, z]));
+                    immediate_draw_box_edges(canvas.color(color), &aabb);
+                }
+            }
+        }
+    }
+
+    if settings.colliders {
+        canvas.color([0.3, 1.0, 0.3, 1.0]);
+        for (transform, collider) in colliders.iter() {
+            immediate_draw_box_edges(&mut canvas, &collider.aabb.transformed(transform));
+        }
+    }
+
+    if let Some(frozen) = settings.frozen_camera {
+        let projection = frozen.projection(display.get_framebuffer_dimensions());
+        canvas.color([1.0, 0.5, 0.2, 1.0]);
+        immediate_draw_frustum(
+            &mut canvas,
+            &frozen.transform,
+            &projection,
+            FROZEN_FRUSTUM_DISTANCE,
+        );
+        debug_lines_camera(&mut canvas, &frozen.transform, &frozen.camera);
+    }
+}
+
 fn render_lines(
     mut ctx: RenderParams,
     camera: CurrentCamera,
@@ -1220,6 +2504,7 @@ fn render_lines(
     let vertices = VertexBuffer::immutable(ctx.display(), &lines.vertices)?;
     lines.vertices.clear();
 
+    let timer = ctx.timers.start(GpuPass::Lines);
     for (&TotalFloat(width), buffer) in lines.lines.iter_mut() {
         let indices = IndexBuffer::immutable(ctx.display(), PrimitiveType::LinesList, &buffer)?;
         let view = camera.view();
@@ -1244,47 +2529,839 @@ fn render_lines(
                     write: false,
                     ..Default::default()
                 },
+                time_elapsed_query: timer.query(),
                 ..Default::default()
             },
         )?;
 
         buffer.clear();
     }
+    ctx.timers.finish(timer);
 
     Ok(())
 }
 
-fn render_post(
+fn render_quads(
     mut ctx: RenderParams,
     camera: CurrentCamera,
     misc: NonSend<RendererMisc>,
-    mut time: ShaderTime,
+    mut quads: ResMut<ImmediateQuads>,
 ) -> anyhow::Result<()> {
-    let (elapsed_seconds, elapsed_subseconds) = time.get();
-    let program = ctx.shaders.get("post")?;
-
-    let world_buffer = ctx.targets.get("world")?;
-    let color = world_buffer
-        .color()
-        .unwrap()
-        .uniform()?
-        .magnify_filter(MagnifySamplerFilter::Nearest)
-        .anisotropy(4);
-    let depth = world_buffer
-        .depth()
-        .unwrap()
-        .uniform()?
-        .magnify_filter(MagnifySamplerFilter::Nearest)
-        .anisotropy(4);
-
-    let mut final_buffer = ctx.targets.get("final")?.framebuffer(ctx.display())?;
-    final_buffer.clear_color(0.0, 0.0, 0.0, 0.0);
+    if quads.vertices.is_empty() && quads.textured.is_empty() {
+        return Ok(());
+    }
 
-    let dimensions = ctx.display().get_framebuffer_dimensions();
-    let proj = camera.projection(dimensions);
+    let view = camera.view();
+    let proj = camera.projection(ctx.display.get_framebuffer_dimensions());
+    let mut target = ctx.targets.get("world")?.framebuffer(ctx.display())?;
+    let params = DrawParameters {
+        blend: Blend::alpha_blending(),
+        depth: glium::Depth {
+            test: glium::DepthTest::IfLess,
+            write: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
 
-    final_buffer.draw(
-        &misc.fullscreen_quad,
+    if !quads.vertices.is_empty() {
+        let vertices = VertexBuffer::immutable(ctx.display(), &quads.vertices)?;
+        quads.vertices.clear();
+        target.draw(
+            &vertices,
+            glium::index::NoIndices(PrimitiveType::TrianglesList),
+            &*ctx.shaders.get("debug")?,
+            &uniform! {
+                view: array4x4(&view),
+                projection: array4x4(&proj.to_homogeneous()),
+            },
+            &params,
+        )?;
+    }
+
+    if !quads.textured.is_empty() {
+        let vertices = VertexBuffer::immutable(ctx.display(), &quads.textured)?;
+        quads.textured.clear();
+        target.draw(
+            &vertices,
+            glium::index::NoIndices(PrimitiveType::TrianglesList),
+            &*ctx.shaders.get("textured_quad")?,
+            &uniform! {
+                view: array4x4(&view),
+                projection: array4x4(&proj.to_homogeneous()),
+                blockTextures: misc.block_textures.sampled().magnify_filter(MagnifySamplerFilter::Nearest),
+            },
+            &params,
+        )?;
+    }
+
+    Ok(())
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+#[repr(C)]
+pub struct UiVertex {
+    pub pos: [f32; 2],
+    pub uv: [f32; 2],
+    /// layer of the block texture array to sample, or -1 for an untextured
+    /// quad.
+    pub layer: f32,
+    pub color: [f32; 4],
+}
+glium::implement_vertex!(UiVertex, pos, uv, layer, color);
+
+/// screen-space quads that get drawn over everything else, and are cleared
+/// every frame.
+///
+/// positions are measured in UI units from the center of the screen, with +Y
+/// pointing up. each unit is scaled to a whole number of pixels depending on the
+/// window size, so that block textures stay crisp.
+#[derive(Clone, Debug, Default)]
+pub struct ImmediateUi {
+    vertices: Vec<UiVertex>,
+}
+
+impl ImmediateUi {
+    fn quad(&mut self, min: [f32; 2], max: [f32; 2], layer: f32, color: [f32; 4]) {
+        let vert = |x: usize, y: usize| UiVertex {
+            pos: [[min[0], max[0]][x], [min[1], max[1]][y]],
+            uv: [x as f32, y as f32],
+            layer,
+            color,
+        };
+
+        let corners = [vert(0, 0), vert(1, 0), vert(1, 1), vert(0, 1)];
+        for idx in [0, 1, 2, 2, 3, 0] {
+            self.vertices.push(corners[idx]);
+        }
+    }
+
+    pub fn rect(&mut self, min: [f32; 2], max: [f32; 2], color: [f32; 4]) {
+        self.quad(min, max, -1.0, color);
+    }
+
+    pub fn textured_rect(&mut self, min: [f32; 2], max: [f32; 2], tex: TextureId, tint: [f32; 4]) {
+        self.quad(min, max, tex.0 as f32, tint);
+    }
+
+    /// draws a line of text in the built-in pixel font, with `pos` at the
+    /// bottom left corner of the first glyph. see [`font::text_width`] for how
+    /// much room it takes up.
+    pub fn text(&mut self, pos: [f32; 2], text: &str, color: [f32; 4]) {
+        let [mut x, y] = pos;
+        for ch in text.chars() {
+            font::glyph_runs(font::glyph(ch), |start, end, row| {
+                let top = y + font::GLYPH_HEIGHT - row as f32;
+                let min = [x + start as f32, top - 1.0];
+                self.quad(min, [x + end as f32, top], -1.0, color);
+            });
+            x += font::GLYPH_ADVANCE;
+        }
+    }
+}
+
+fn render_ui(
+    mut ctx: RenderParams,
+    misc: NonSend<RendererMisc>,
+    mut ui: ResMut<ImmediateUi>,
+    ui_scale: Res<UiScale>,
+) -> anyhow::Result<()> {
+    if ui.vertices.is_empty() {
+        return Ok(());
+    }
+
+    let vertices = VertexBuffer::immutable(ctx.display(), &ui.vertices)?;
+    ui.vertices.clear();
+
+    let program = ctx.shaders.get("ui")?;
+    let mut final_buffer = ctx.targets.get("final")?.framebuffer(ctx.display())?;
+    let dimensions = ctx.display().get_framebuffer_dimensions();
+
+    final_buffer.draw(
+        &vertices,
+        glium::index::NoIndices(PrimitiveType::TrianglesList),
+        &program,
+        &uniform! {
+            screenDimensions: [dimensions.0, dimensions.1],
+            uiScale: ui_scale.0,
+            blockTextures: misc.block_textures.sampled().magnify_filter(MagnifySamplerFilter::Nearest),
+        },
+        &glium::DrawParameters {
+            blend: Blend::alpha_blending(),
+            ..Default::default()
+        },
+    )?;
+
+    Ok(())
+}
+
+/// fog parameters used when the camera is inside of a fluid.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct SubmergedFog {
+    pub color: [f32; 3],
+    /// a density of zero means that the camera is not submerged, and the usual
+    /// distance fog should be used instead.
+    pub density: f32,
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+struct TextureLoads;
+
+#[derive(SystemParam)]
+pub struct InitialTextureLoad<'a> {
+    progress: ResMut<'a, AssetLoadProgress>,
+    reload_events: EventWriter<'a, ReloadTextures>,
+}
+
+/// replaces the block textures with ones that were loaded in the background,
+/// once they're ready. if they failed to load, the old ones are kept.
+fn swap_block_textures(
+    display: NonSend<Rc<Display>>,
+    mut misc: NonSendMut<RendererMisc>,
+    registry: Res<Arc<BlockRegistry>>,
+    mut streamer: ResMut<TextureStreamer>,
+    mut map_colors: ResMut<BlockMapColors>,
+    mut notify_events: EventWriter<Notify>,
+    mut initial: InitialTextureLoad,
+) {
+    let streamed = match streamer.take_finished() {
+        Some(streamed) => streamed,
+        None => return,
+    };
+
+    let pack = streamed.pack.as_deref().unwrap_or("default");
+    let loaded = streamed
+        .textures
+        .map_err(anyhow::Error::from)
+        .and_then(|textures| {
+            let array = block_texture_array(&display, &registry, &textures)?;
+            Ok((array, BlockMapColors::new(&registry, &textures)?))
+        });
+    // the first textures are loaded for the loading screen, which shows how
+    // that went instead of a notification.
+    let progress = &mut initial.progress;
+    let starting = matches!(progress.state(TEXTURES), Some(AssetState::Loading { .. }));
+    match loaded {
+        Ok((array, colors)) => {
+            misc.block_textures = array;
+            *map_colors = colors;
+            log::info!("loaded block textures from texture pack '{}'", pack);
+            match starting {
+                true => progress.finish(TEXTURES),
+                false => notify_events.send(Notify::info(tr!("notify.textures_reloaded", pack))),
+            }
+        }
+        // a broken texture pack shouldn't keep the game from starting
+        Err(err) if starting && streamed.pack.is_some() => {
+            log::error!("failed to load texture pack '{}': {}", pack, err);
+            initial.reload_events.send(ReloadTextures { pack: None });
+        }
+        Err(err) if starting => progress.fail(TEXTURES, err),
+        Err(err) => {
+            log::error!("failed to load texture pack '{}': {}", pack, err);
+            let message = tr!("notify.texture_reload_failed", pack, err);
+            notify_events.send(Notify::error(message));
+        }
+    }
+}
+
+fn update_submerged_fog(
+    camera: CurrentCamera,
+    mut access: ResMut<ChunkAccess>,
+    mut fog: ResMut<SubmergedFog>,
+) {
+    let pos = BlockPos::from(WorldPos::new(camera.pos()));
+    let registry = Arc::clone(access.registry());
+    let fluid = access.block(pos).and_then(|id| registry.get(id).fluid());
+
+    *fog = match fluid {
+        Some(fluid) => SubmergedFog {
+            color: fluid.fog_color.unwrap_or_default(),
+            density: fluid.fog_density,
+        },
+        None => SubmergedFog::default(),
+    };
+}
+
+/// the most dynamic lights that are drawn in a single frame. when there are
+/// more than this, only the ones closest to the camera are drawn.
+pub const MAX_DYNAMIC_LIGHTS: usize = 64;
+
+/// a point light that lights up the terrain around it in real time, unlike
+/// block light, which is baked into the terrain's meshes. the light sits at the
+/// entity's [`Transform`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DynamicLight {
+    /// linear RGB. channels can go above 1 for especially bright lights.
+    pub color: [f32; 3],
+    /// how far away the light reaches, in blocks.
+    pub radius: f32,
+}
+
+fn render_dynamic_lights(
+    mut ctx: RenderParams,
+    camera: CurrentCamera,
+    misc: NonSend<RendererMisc>,
+    light_query: Query<(&Transform, &DynamicLight)>,
+) -> anyhow::Result<()> {
+    let center = camera.pos();
+    let mut lights = light_query
+        .iter()
+        .map(|(transform, light)| (Point3::from(transform.translation.vector), *light))
+        .collect::<Vec<_>>();
+    if lights.is_empty() {
+        return Ok(());
+    }
+
+    lights.sort_by_key(|(pos, _)| TotalFloat((pos - center).norm_squared()));
+    lights.truncate(MAX_DYNAMIC_LIGHTS);
+
+    // there's one column per light. the first row holds each light's position
+    // and radius, and the second holds its color.
+    let mut data = Vec::with_capacity(8 * lights.len());
+    for (pos, light) in lights.iter() {
+        data.extend([pos.x, pos.y, pos.z, light.radius]);
+    }
+    for (_, light) in lights.iter() {
+        data.extend(light.color);
+        data.push(0.0);
+    }
+    let light_texture = Texture2d::with_format(
+        ctx.display(),
+        RawImage2d {
+            data: data.into(),
+            width: lights.len() as u32,
+            height: 2,
+            format: glium::texture::ClientFormat::F32F32F32F32,
+        },
+        UncompressedFloatFormat::F32F32F32F32,
+        MipmapsOption::NoMipmap,
+    )?;
+
+    let world_buffer = ctx.targets.get("world_resolved")?;
+    let depth = world_buffer
+        .depth()
+        .context("resolved world target has no depth")?
+        .uniform()?
+        .magnify_filter(MagnifySamplerFilter::Nearest);
+    let albedo = ctx
+        .targets
+        .get("gbuffer_albedo_resolved")?
+        .color()
+        .context("resolved albedo target has no color")?
+        .uniform()?
+        .magnify_filter(MagnifySamplerFilter::Nearest);
+    let normal = ctx
+        .targets
+        .get("gbuffer_normal_resolved")?
+        .color()
+        .context("resolved normal target has no color")?
+        .uniform()?
+        .magnify_filter(MagnifySamplerFilter::Nearest);
+
+    let program = ctx.shaders.get("dynamic_lights")?;
+    let mut target = ctx
+        .targets
+        .get("world_resolved")?
+        .framebuffer(ctx.display())?;
+    let proj = camera.projection(ctx.display().get_framebuffer_dimensions());
+
+    let additive = glium::BlendingFunction::Addition {
+        source: glium::LinearBlendingFactor::One,
+        destination: glium::LinearBlendingFactor::One,
+    };
+    target.draw(
+        &misc.fullscreen_quad,
+        glium::index::NoIndices(PrimitiveType::TrianglesList),
+        &program,
+        &uniform! {
+            depthBuffer: depth,
+            albedoBuffer: albedo,
+            normalBuffer: normal,
+            lights: light_texture
+                .sampled()
+                .minify_filter(MinifySamplerFilter::Nearest)
+                .magnify_filter(MagnifySamplerFilter::Nearest),
+            lightCount: lights.len() as i32,
+            projectionMatrix: array4x4(&proj.to_homogeneous()),
+            viewMatrix: array4x4(&camera.view()),
+        },
+        &glium::DrawParameters {
+            blend: Blend {
+                color: additive,
+                alpha: additive,
+                constant_value: (0.0, 0.0, 0.0, 0.0),
+            },
+            ..Default::default()
+        },
+    )?;
+
+    Ok(())
+}
+
+/// how far away from the camera precipitation falls, in blocks.
+const PRECIPITATION_RADIUS: f32 = 24.0;
+/// how far above the camera precipitation starts falling, in blocks.
+const PRECIPITATION_HEIGHT: f32 = 20.0;
+/// how many particles are falling around the camera at full strength.
+const MAX_PRECIPITATION_PARTICLES: usize = 3000;
+/// the most particles that are spawned in a single frame, so that a burst of
+/// spawns doesn't hammer the heightmap cache.
+const MAX_PRECIPITATION_SPAWNS_PER_FRAME: usize = 300;
+/// how long the streak drawn for each raindrop is, in blocks.
+const RAIN_STREAK_LENGTH: f32 = 0.6;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct PrecipitationParticle {
+    pos: Point3<f32>,
+    kind: WeatherKind,
+    /// the top of the terrain below the particle. it disappears once it falls
+    /// below this.
+    floor: f32,
+    /// offsets the side-to-side drift of snowflakes so they don't all sway in
+    /// lockstep.
+    phase: f32,
+}
+
+/// rain and snow particles falling around the camera.
+#[derive(Clone, Debug, Default)]
+pub struct Precipitation {
+    particles: Vec<PrecipitationParticle>,
+}
+
+fn fall_speed(kind: WeatherKind) -> f32 {
+    match kind {
+        WeatherKind::Rain => 16.0,
+        WeatherKind::Snow => 2.0,
+        WeatherKind::Clear => 0.0,
+    }
+}
+
+fn update_precipitation(
+    time: Res<Time>,
+    camera: CurrentCamera,
+    weather: Res<Weather>,
+    generator: Res<Arc<WorldGenerator>>,
+    mut precipitation: ResMut<Precipitation>,
+) {
+    let dt = time.delta_seconds();
+    let center = camera.pos();
+    let in_range = |pos: &Point3<f32>| {
+        let [dx, dz] = [pos.x - center.x, pos.z - center.z];
+        dx * dx + dz * dz <= PRECIPITATION_RADIUS * PRECIPITATION_RADIUS
+    };
+
+    precipitation.particles.retain_mut(|particle| {
+        particle.pos.y -= fall_speed(particle.kind) * dt;
+        if particle.kind == WeatherKind::Snow {
+            particle.pos.x += 0.5 * dt * (particle.pos.y + particle.phase).sin();
+        }
+        particle.pos.y > particle.floor && in_range(&particle.pos)
+    });
+
+    let mut rng = rand::thread_rng();
+    let mut spawned = 0;
+    for kind in [WeatherKind::Rain, WeatherKind::Snow] {
+        let target = (weather.strength(kind) * MAX_PRECIPITATION_PARTICLES as f32) as usize;
+        let current = precipitation
+            .particles
+            .iter()
+            .filter(|particle| particle.kind == kind)
+            .count();
+
+        for _ in current..target {
+            if spawned >= MAX_PRECIPITATION_SPAWNS_PER_FRAME {
+                return;
+            }
+            spawned += 1;
+
+            let angle = rng.gen_range(0.0, std::f32::consts::TAU);
+            let distance = PRECIPITATION_RADIUS * rng.gen::<f32>().sqrt();
+            let x = center.x + distance * angle.cos();
+            let z = center.z + distance * angle.sin();
+
+            // nothing falls below the surface, so when the camera is deep
+            // underground, there's nowhere for particles to spawn at all.
+            let floor = generator.surface_height(x.floor() as i32, z.floor() as i32) as f32;
+            let top = center.y + PRECIPITATION_HEIGHT;
+            if floor >= top {
+                continue;
+            }
+
+            let bottom = f32::max(floor, center.y - PRECIPITATION_HEIGHT);
+            precipitation.particles.push(PrecipitationParticle {
+                pos: point![x, rng.gen_range(bottom, top), z],
+                kind,
+                floor,
+                phase: rng.gen_range(0.0, std::f32::consts::TAU),
+            });
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+#[repr(C)]
+pub struct PrecipitationVertex {
+    pub pos: [f32; 3],
+    pub alpha: f32,
+}
+glium::implement_vertex!(PrecipitationVertex, pos, alpha);
+
+fn render_precipitation(
+    mut ctx: RenderParams,
+    camera: CurrentCamera,
+    precipitation: Res<Precipitation>,
+) -> anyhow::Result<()> {
+    if precipitation.particles.is_empty() {
+        return Ok(());
+    }
+
+    let center = camera.pos();
+    let mut rain = Vec::new();
+    let mut snow = Vec::new();
+    for particle in precipitation.particles.iter() {
+        // fade particles out towards the edge of the area they fall in, so
+        // there isn't a hard cutoff.
+        let [dx, dz] = [particle.pos.x - center.x, particle.pos.z - center.z];
+        let alpha = 1.0 - (dx * dx + dz * dz).sqrt() / PRECIPITATION_RADIUS;
+        let pos = particle.pos.coords.into();
+
+        match particle.kind {
+            WeatherKind::Rain => {
+                let top = (particle.pos.coords + vector![0.0, RAIN_STREAK_LENGTH, 0.0]).into();
+                rain.push(PrecipitationVertex { pos, alpha });
+                rain.push(PrecipitationVertex { pos: top, alpha });
+            }
+            WeatherKind::Snow => snow.push(PrecipitationVertex { pos, alpha }),
+            WeatherKind::Clear => {}
+        }
+    }
+
+    let view = camera.view();
+    let proj = camera.projection(ctx.display().get_framebuffer_dimensions());
+    let program = ctx.shaders.get("precipitation")?;
+    let mut target = ctx.targets.get("world")?.framebuffer(ctx.display())?;
+
+    let batches = [
+        (rain, PrimitiveType::LinesList, [0.55, 0.6, 0.7, 0.6]),
+        (snow, PrimitiveType::Points, [1.0, 1.0, 1.0, 0.9]),
+    ];
+    for (vertices, primitive, color) in batches {
+        if vertices.is_empty() {
+            continue;
+        }
+
+        let vertices = VertexBuffer::immutable(ctx.display(), &vertices)?;
+        target.draw(
+            &vertices,
+            glium::index::NoIndices(primitive),
+            &program,
+            &uniform! {
+                view: array4x4(&view),
+                projection: array4x4(&proj.to_homogeneous()),
+                color: color,
+            },
+            &DrawParameters {
+                line_width: Some(1.0),
+                point_size: Some(3.0),
+                blend: Blend::alpha_blending(),
+                depth: glium::Depth {
+                    test: glium::DepthTest::IfLess,
+                    write: false,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// how many times the bright parts of the image are shrunk down and blurred.
+/// each level is half the size of the one before it, and spreads the glow out
+/// further. the post shader has a sampler for each of them.
+const BLOOM_LEVELS: usize = 4;
+
+/// how bright a color has to be before it starts to glow.
+const BLOOM_THRESHOLD: f32 = 1.0;
+
+/// how the HDR image is squeezed into the range that the screen can show.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Tonemapper {
+    /// an approximation of the filmic curve from ACES, which keeps a bit of
+    /// contrast in the highlights.
+    Aces,
+    /// a simple curve that never quite reaches white.
+    Reinhard,
+}
+
+impl Tonemapper {
+    /// the value of the post shader's `tonemapper` uniform.
+    fn shader_id(self) -> i32 {
+        match self {
+            Tonemapper::Aces => 0,
+            Tonemapper::Reinhard => 1,
+        }
+    }
+}
+
+impl FromStr for Tonemapper {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "aces" => Self::Aces,
+            "reinhard" => Self::Reinhard,
+            other => bail!("unknown tonemapper '{}'", other),
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HdrSettings {
+    pub tonemapper: Tonemapper,
+    /// colors are multiplied by this before they're tonemapped.
+    pub exposure: f32,
+    /// how much of the blurred bright parts are added back onto the image.
+    /// zero turns bloom off.
+    pub bloom_strength: f32,
+}
+
+impl Default for HdrSettings {
+    fn default() -> Self {
+        Self {
+            tonemapper: Tonemapper::Aces,
+            exposure: 1.0,
+            bloom_strength: 0.6,
+        }
+    }
+}
+
+fn bloom_target_name(level: usize) -> String {
+    format!("bloom{}", level)
+}
+
+/// the intermediate target for the first, horizontal half of a bloom level's
+/// blur.
+fn bloom_blur_target_name(level: usize) -> String {
+    format!("bloom{}_blur", level)
+}
+
+/// how jagged edges are smoothed out.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum AntiAliasing {
+    Off,
+    /// a cheap filter over the finished image, which blurs across the edges it
+    /// finds.
+    #[default]
+    Fxaa,
+    /// draws the world with this many samples per pixel.
+    Msaa(u32),
+}
+
+impl AntiAliasing {
+    fn samples(self) -> Option<u32> {
+        match self {
+            AntiAliasing::Msaa(samples) => Some(samples),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for AntiAliasing {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "off" => Self::Off,
+            "fxaa" => Self::Fxaa,
+            "msaa2" => Self::Msaa(2),
+            "msaa4" => Self::Msaa(4),
+            "msaa8" => Self::Msaa(8),
+            other => bail!("unknown anti-aliasing mode '{}'", other),
+        })
+    }
+}
+
+impl std::fmt::Display for AntiAliasing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AntiAliasing::Off => write!(f, "off"),
+            AntiAliasing::Fxaa => write!(f, "fxaa"),
+            AntiAliasing::Msaa(samples) => write!(f, "msaa{}", samples),
+        }
+    }
+}
+
+/// the targets that the world is drawn into. they're multisampled when MSAA
+/// is on, so everything after the world is drawn reads from their resolved
+/// copies instead.
+const WORLD_TARGETS: [&str; 3] = ["world", "gbuffer_albedo", "gbuffer_normal"];
+
+fn resolved_target_name(name: &str) -> String {
+    format!("{}_resolved", name)
+}
+
+fn resolve_world(ctx: RenderParams, graph: Res<FrameGraph>) -> anyhow::Result<()> {
+    for (source, target) in graph.resolves("resolve") {
+        ctx.targets.resolve(source, target)?;
+    }
+    Ok(())
+}
+
+fn draw_fullscreen<U: glium::uniforms::Uniforms>(
+    target: &mut SimpleFrameBuffer,
+    misc: &RendererMisc,
+    program: &glium::Program,
+    uniforms: &U,
+    params: &DrawParameters,
+) -> Result<()> {
+    target.draw(
+        &misc.fullscreen_quad,
+        glium::index::NoIndices(PrimitiveType::TrianglesList),
+        program,
+        uniforms,
+        params,
+    )?;
+    Ok(())
+}
+
+/// picks out the parts of the world that are brighter than the screen can
+/// show, and blurs them into a chain of smaller and smaller targets, which
+/// the post pass adds back on top of the image.
+fn render_bloom(
+    mut ctx: RenderParams,
+    misc: NonSend<RendererMisc>,
+    hdr: Res<HdrSettings>,
+) -> anyhow::Result<()> {
+    if hdr.bloom_strength <= 0.0 {
+        return Ok(());
+    }
+
+    let bright_program = ctx.shaders.get("bloom_bright")?;
+    let blur_program = ctx.shaders.get("bloom_blur")?;
+
+    let world_buffer = ctx.targets.get("world_resolved")?;
+    let color = world_buffer
+        .color()
+        .context("resolved world target has no color")?
+        .uniform()?
+        .magnify_filter(MagnifySamplerFilter::Linear);
+    let mut bright_buffer = ctx
+        .targets
+        .get("bloom_bright")?
+        .framebuffer(ctx.display())?;
+    draw_fullscreen(&mut bright_buffer, &misc, &bright_program, &uniform! {
+        colorBuffer: color,
+        threshold: BLOOM_THRESHOLD,
+    }, &Default::default())?;
+
+    // each level starts out from the one before it, so the blur compounds as
+    // the levels get smaller.
+    let mut source = String::from("bloom_bright");
+    for level in 0..BLOOM_LEVELS {
+        let target_name = bloom_target_name(level);
+        let blur_name = bloom_blur_target_name(level);
+
+        let input = ctx
+            .targets
+            .get(&source)?
+            .color()
+            .context("bloom target has no color")?
+            .uniform()?;
+        let mut blur_buffer = ctx.targets.get(&blur_name)?.framebuffer(ctx.display())?;
+        draw_fullscreen(&mut blur_buffer, &misc, &blur_program, &uniform! {
+            source: input.magnify_filter(MagnifySamplerFilter::Linear),
+            direction: [1.0f32, 0.0],
+        }, &Default::default())?;
+
+        let input = ctx
+            .targets
+            .get(&blur_name)?
+            .color()
+            .context("bloom blur target has no color")?
+            .uniform()?;
+        let mut level_buffer = ctx.targets.get(&target_name)?.framebuffer(ctx.display())?;
+        draw_fullscreen(&mut level_buffer, &misc, &blur_program, &uniform! {
+            source: input.magnify_filter(MagnifySamplerFilter::Linear),
+            direction: [0.0f32, 1.0],
+        }, &Default::default())?;
+
+        source = target_name;
+    }
+
+    Ok(())
+}
+
+fn render_post(
+    mut ctx: RenderParams,
+    camera: CurrentCamera,
+    misc: NonSend<RendererMisc>,
+    effects: PostEffects,
+    mut time: ShaderTime,
+) -> anyhow::Result<()> {
+    let (elapsed_seconds, elapsed_subseconds) = time.get();
+    let program = ctx.shaders.get("post")?;
+    let fxaa_program = ctx.shaders.get("fxaa")?;
+    let (fog, weather, hdr) = (&effects.fog, &effects.weather, &effects.hdr);
+
+    let bloom_level = |level| -> Result<_> {
+        let target = ctx.targets.get(&bloom_target_name(level))?;
+        Ok(target
+            .color()
+            .context("bloom target has no color")?
+            .uniform()?
+            .magnify_filter(MagnifySamplerFilter::Linear))
+    };
+    let (bloom0, bloom1, bloom2, bloom3) = (
+        bloom_level(0)?,
+        bloom_level(1)?,
+        bloom_level(2)?,
+        bloom_level(3)?,
+    );
+
+    // the world is stretched over the window when it's drawn at a lower render
+    // scale, which the shader smooths out with a bicubic filter that's built
+    // out of a few linear samples.
+    let render_scale = ctx.targets.render_scale();
+    let upscale_filter = match render_scale < 1.0 {
+        true => MagnifySamplerFilter::Linear,
+        false => MagnifySamplerFilter::Nearest,
+    };
+    let world_buffer = ctx.targets.get("world_resolved")?;
+    let color = world_buffer
+        .color()
+        .unwrap()
+        .uniform()?
+        .magnify_filter(upscale_filter)
+        .anisotropy(4);
+    let depth = world_buffer
+        .depth()
+        .unwrap()
+        .uniform()?
+        .magnify_filter(MagnifySamplerFilter::Nearest)
+        .anisotropy(4);
+
+    // with FXAA on, the scene is drawn somewhere else first, and then smoothed
+    // out into the final target.
+    let fxaa = *effects.anti_aliasing == AntiAliasing::Fxaa;
+    let scene_target = if fxaa { "post" } else { "final" };
+    let mut scene_buffer = ctx.targets.get(scene_target)?.framebuffer(ctx.display())?;
+
+    let dimensions = ctx.display().get_framebuffer_dimensions();
+    let proj = camera.projection(dimensions);
+
+    // the post pass draws a few times one after another, and they're all timed
+    // together.
+    let timer = ctx.timers.start(GpuPass::Post);
+    let params = DrawParameters {
+        time_elapsed_query: timer.query(),
+        ..Default::default()
+    };
+
+    scene_buffer.draw(
+        &misc.fullscreen_quad,
         glium::index::NoIndices(PrimitiveType::TrianglesList),
         &program,
         &uniform! {
@@ -1294,14 +3371,41 @@ fn render_post(
             elapsedSeconds: elapsed_seconds,
             elapsedSubseconds: elapsed_subseconds,
             screenDimensions: [dimensions.0, dimensions.1],
+            renderScale: render_scale,
 
             cameraPosWorld: array3(&camera.pos()),
             projectionMatrix: array4x4(&proj.to_homogeneous()),
             viewMatrix: array4x4(&camera.view()),
+
+            submergedFogColor: fog.color,
+            submergedFogDensity: fog.density,
+            precipitationStrength: weather.precipitation(),
+
+            bloom0: bloom0,
+            bloom1: bloom1,
+            bloom2: bloom2,
+            bloom3: bloom3,
+            bloomStrength: hdr.bloom_strength,
+            exposure: hdr.exposure,
+            tonemapper: hdr.tonemapper.shader_id(),
         },
-        &Default::default(),
+        &params,
     )?;
 
+    let mut final_buffer = ctx.targets.get("final")?.framebuffer(ctx.display())?;
+    if fxaa {
+        let scene = ctx
+            .targets
+            .get("post")?
+            .color()
+            .context("post target has no color")?
+            .uniform()?
+            .magnify_filter(MagnifySamplerFilter::Linear);
+        draw_fullscreen(&mut final_buffer, &misc, &fxaa_program, &uniform! {
+            colorBuffer: scene,
+        }, &params)?;
+    }
+
     let (width, height) = ctx.display().get_framebuffer_dimensions();
     let program = ctx.shaders.get("crosshair")?;
     final_buffer.draw(
@@ -1311,8 +3415,50 @@ fn render_post(
         &uniform! {
             screen_width: width as f32,
             screen_height: height as f32,
+            uiScale: effects.ui_scale.0,
             crosshair_texture: misc.crosshair_texture.sampled().magnify_filter(MagnifySamplerFilter::Nearest),
         },
+        &glium::DrawParameters {
+            blend: Blend::alpha_blending(),
+            ..params
+        },
+    )?;
+    ctx.timers.finish(timer);
+
+    Ok(())
+}
+
+/// the entity whose health is shown in the HUD.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct ActiveHealthBar(pub Option<Entity>);
+
+fn render_health_bar(
+    mut ctx: RenderParams,
+    misc: NonSend<RendererMisc>,
+    active: Res<ActiveHealthBar>,
+    health_query: Query<&Health>,
+    ui_scale: Res<UiScale>,
+) -> anyhow::Result<()> {
+    let health = match active.0.and_then(|entity| health_query.get(entity).ok()) {
+        Some(health) => health,
+        None => return Ok(()),
+    };
+
+    let program = ctx.shaders.get("hearts")?;
+    let mut final_buffer = ctx.targets.get("final")?.framebuffer(ctx.display())?;
+    let dimensions = ctx.display().get_framebuffer_dimensions();
+
+    final_buffer.draw(
+        &misc.fullscreen_quad,
+        glium::index::NoIndices(PrimitiveType::TrianglesList),
+        &program,
+        &uniform! {
+            screenDimensions: [dimensions.0, dimensions.1],
+            uiScale: ui_scale.0,
+            health: health.current,
+            maxHealth: health.max,
+            heartTexture: misc.heart_texture.sampled().magnify_filter(MagnifySamplerFilter::Nearest),
+        },
         &glium::DrawParameters {
             blend: Blend::alpha_blending(),
             ..Default::default()
@@ -1326,6 +3472,8 @@ fn render_sky(
     mut ctx: RenderParams,
     camera: CurrentCamera,
     misc: NonSend<RendererMisc>,
+    weather: Res<Weather>,
+    cycle: Res<DayNightCycle>,
     mut time: ShaderTime,
 ) -> anyhow::Result<()> {
     let (elapsed_seconds, elapsed_subseconds) = time.get();
@@ -1334,6 +3482,7 @@ fn render_sky(
 
     let dimensions = ctx.display().get_framebuffer_dimensions();
     let proj = camera.projection(dimensions);
+    let timer = ctx.timers.start(GpuPass::Sky);
     target.draw(
         &misc.fullscreen_quad,
         glium::index::NoIndices(PrimitiveType::TrianglesList),
@@ -1345,9 +3494,15 @@ fn render_sky(
             cameraPosWorld: array3(&camera.pos()),
             projectionMatrix: array4x4(&proj.to_homogeneous()),
             viewMatrix: array4x4(&camera.view()),
+            precipitationStrength: weather.precipitation(),
+            sunDirection: array3(&cycle.sun_direction()),
+        },
+        &DrawParameters {
+            time_elapsed_query: timer.query(),
+            ..Default::default()
         },
-        &Default::default(),
     )?;
+    ctx.timers.finish(timer);
 
     Ok(())
 }
@@ -1371,63 +3526,470 @@ impl<'a> ShaderTime<'a> {
     }
 }
 
+/// how long a full day and night lasts, in seconds.
+pub const DAY_LENGTH: f32 = 1200.0;
+/// how far the sun's path is tilted away from passing straight overhead, so
+/// that shadows at noon don't point straight down.
+const SUN_TILT: f32 = 0.3;
+
+/// the time of day, which decides where the sun is.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DayNightCycle {
+    /// seconds since the start of the day, which begins at sunrise.
+    pub time: f32,
+}
+
+impl Default for DayNightCycle {
+    fn default() -> Self {
+        // start out mid-morning
+        Self {
+            time: 0.15 * DAY_LENGTH,
+        }
+    }
+}
+
+impl DayNightCycle {
+    /// the direction pointing towards the sun. it's below the horizon at night.
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let angle = std::f32::consts::TAU * self.time / DAY_LENGTH;
+        vector![angle.cos(), angle.sin(), SUN_TILT].normalize()
+    }
+}
+
+fn advance_day_night_cycle(time: Res<Time>, mut cycle: ResMut<DayNightCycle>) {
+    cycle.time = (cycle.time + time.delta_seconds()) % DAY_LENGTH;
+}
+
+/// the most shadow cascades that can be used at once. the terrain shader has a
+/// shadow map uniform for each of them.
+pub const MAX_SHADOW_CASCADES: usize = 3;
+
+/// how far away from the camera each shadow cascade reaches, in blocks.
+const SHADOW_CASCADE_DISTANCES: [f32; MAX_SHADOW_CASCADES] = [16.0, 48.0, 160.0];
+
+/// how much further towards the sun each cascade reaches past the part of the
+/// view it covers, so that terrain outside of the view can still cast shadows
+/// into it.
+const SHADOW_CASTER_MARGIN: f32 = 128.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ShadowSettings {
+    /// how many slices the view is split into, each with its own shadow map.
+    /// zero turns shadows off.
+    pub cascades: usize,
+    /// the width and height of each shadow map, in texels.
+    pub resolution: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            cascades: MAX_SHADOW_CASCADES,
+            resolution: 2048,
+        }
+    }
+}
+
+fn shadow_target_name(cascade: usize) -> String {
+    format!("shadow{}", cascade)
+}
+
+/// where each shadow cascade was rendered from this frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShadowCascades {
+    /// transforms world space into the clip space of each cascade's shadow map.
+    view_projections: [Matrix4<f32>; MAX_SHADOW_CASCADES],
+    sun_direction: Vector3<f32>,
+}
+
+impl Default for ShadowCascades {
+    fn default() -> Self {
+        Self {
+            view_projections: [Matrix4::identity(); MAX_SHADOW_CASCADES],
+            sun_direction: Vector3::y(),
+        }
+    }
+}
+
+/// fits a shadow map around the slice of the camera's view between `near` and
+/// `far`, looking down from the sun.
+fn cascade_view_projection(
+    inverse_view: &Matrix4<f32>,
+    projection: &nalgebra::Perspective3<f32>,
+    [near, far]: [f32; 2],
+    sun_direction: &Vector3<f32>,
+    resolution: u32,
+) -> Matrix4<f32> {
+    let tan_y = (projection.fovy() / 2.0).tan();
+    let tan_x = tan_y * projection.aspect();
+    let mut corners = Vec::with_capacity(8);
+    for depth in [near, far] {
+        for (sx, sy) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+            let corner = point![sx * tan_x * depth, sy * tan_y * depth, -depth];
+            corners.push(inverse_view.transform_point(&corner));
+        }
+    }
+
+    // fitting a sphere instead of a box means that the shadow map stays the same
+    // size no matter which way the camera is facing.
+    let center = corners
+        .iter()
+        .map(|corner| corner.coords)
+        .sum::<Vector3<f32>>()
+        / 8.0;
+    let radius = corners
+        .iter()
+        .map(|corner| (corner.coords - center).norm())
+        .fold(0.0, f32::max);
+
+    // snapping the center to whole texels keeps shadow edges from shimmering as
+    // the camera moves around.
+    let up = Vector3::z();
+    let rotation = Isometry3::look_at_rh(&Point3::origin(), &Point3::from(-sun_direction), &up);
+    let texel = 2.0 * radius / resolution as f32;
+    let mut center = rotation.transform_point(&Point3::from(center));
+    center.x = (center.x / texel).floor() * texel;
+    center.y = (center.y / texel).floor() * texel;
+    let center = rotation.inverse_transform_point(&center);
+
+    let eye = center + sun_direction * (radius + SHADOW_CASTER_MARGIN);
+    let view = Isometry3::look_at_rh(&eye, &center, &up);
+    let depth = 2.0 * radius + SHADOW_CASTER_MARGIN;
+    let projection = Orthographic3::new(-radius, radius, -radius, radius, 0.0, depth);
+    projection.as_matrix() * view.to_homogeneous()
+}
+
+fn update_shadow_cascades(
+    display: NonSend<Rc<Display>>,
+    camera: CurrentCamera,
+    settings: Res<ShadowSettings>,
+    cycle: Res<DayNightCycle>,
+    mut cascades: ResMut<ShadowCascades>,
+) {
+    let sun_direction = cycle.sun_direction();
+    cascades.sun_direction = sun_direction;
+
+    let inverse_view = camera
+        .view()
+        .try_inverse()
+        .unwrap_or_else(Matrix4::identity);
+    let projection = camera.projection(display.get_framebuffer_dimensions());
+
+    let mut near = projection.znear();
+    let distances = SHADOW_CASCADE_DISTANCES.iter().take(settings.cascades);
+    for (cascade, &far) in distances.enumerate() {
+        cascades.view_projections[cascade] = cascade_view_projection(
+            &inverse_view,
+            &projection,
+            [near, far],
+            &sun_direction,
+            settings.resolution,
+        );
+        near = far;
+    }
+}
+
+/// takes in new terrain meshes and uploads the batches they changed, which is
+/// done once a frame before anything is drawn with them.
+fn update_terrain_meshes(
+    display: NonSend<Rc<Display>>,
+    camera: CurrentCamera,
+    mut terrain_meshes: TerrainMeshes,
+) {
+    terrain_meshes.update(&display, camera.pos());
+}
+
+fn render_shadows(
+    mut ctx: RenderParams,
+    shadows: ShadowParams,
+    terrain_meshes: TerrainMeshes,
+    misc: NonSend<RendererMisc>,
+) -> anyhow::Result<()> {
+    // nothing casts shadows when the sun is down
+    if shadows.cascades.sun_direction.y <= 0.0 {
+        return Ok(());
+    }
+
+    let program = ctx.shaders.get("terrain_shadow")?;
+    for cascade in 0..shadows.settings.cascades {
+        let viewproj = shadows.cascades.view_projections[cascade];
+        let mut target = ctx
+            .targets
+            .get(&shadow_target_name(cascade))?
+            .framebuffer(ctx.display())?;
+        let uniforms = uniform! {
+            lightViewProjection: array4x4(&viewproj),
+            albedo_maps: misc.block_textures.sampled()
+                .wrap_function(glium::uniforms::SamplerWrapFunction::Repeat)
+                .magnify_filter(MagnifySamplerFilter::Nearest),
+        };
+        let draw_parameters = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            // cross-shaped blocks like flowers are made of single-sided faces,
+            // and should cast shadows from either side.
+            backface_culling: glium::BackfaceCullingMode::CullingDisabled,
+            polygon_offset: glium::draw_parameters::PolygonOffset {
+                factor: 1.5,
+                units: 2.0,
+                fill: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        for batch in terrain_meshes.batches() {
+            if !should_draw_aabb(&viewproj, &batch.aabb) {
+                continue;
+            }
+
+            target.draw(
+                batch.vertices(),
+                batch.indices(),
+                &program,
+                &uniforms.add("model", array4x4(&Matrix4::<f32>::identity())),
+                &draw_parameters,
+            )?;
+        }
+
+        for (transform, buffers) in terrain_meshes.others() {
+            let model = transform.to_matrix();
+            if !should_draw_aabb(&(viewproj * model), &buffers.aabb) {
+                continue;
+            }
+
+            target.draw(
+                (&buffers.vertices, misc.zero_origin()?),
+                &buffers.indices,
+                &program,
+                &uniforms.add("model", array4x4(&model)),
+                &draw_parameters,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(SystemParam)]
+pub struct PostEffects<'a> {
+    fog: Res<'a, SubmergedFog>,
+    weather: Res<'a, Weather>,
+    hdr: Res<'a, HdrSettings>,
+    anti_aliasing: Res<'a, AntiAliasing>,
+    ui_scale: Res<'a, UiScale>,
+}
+
+#[derive(SystemParam)]
+pub struct ShadowParams<'a> {
+    settings: Res<'a, ShadowSettings>,
+    cascades: Res<'a, ShadowCascades>,
+}
+
+/// samples one of the shadow maps with depth comparison turned on, so that the
+/// shader gets back how lit a point is instead of a raw depth.
+fn shadow_map_uniform(
+    targets: &RenderTargets,
+    cascade: usize,
+) -> Result<Sampler<'_, DepthTexture2d>> {
+    match targets.get(&shadow_target_name(cascade))?.depth() {
+        Some(RenderTargetTexture::Depth(texture)) => Ok(texture
+            .sampled()
+            .depth_texture_comparison(Some(DepthTextureComparison::LessOrEqual))
+            .minify_filter(MinifySamplerFilter::Linear)
+            .magnify_filter(MagnifySamplerFilter::Linear)
+            .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp)),
+        _ => bail!("shadow map {} is not a depth texture", cascade),
+    }
+}
+
 fn render_terrain(
     mut ctx: RenderParams,
     camera: CurrentCamera,
-    mesh_query: Query<(&Transform, &RenderMeshComponent<TerrainMesh>)>,
-    mut terrain_meshes: NonSendMut<LocalMeshContext<TerrainMesh>>,
+    terrain_meshes: TerrainMeshes,
     misc: NonSend<RendererMisc>,
     mut time: ShaderTime,
+    shadows: ShadowParams,
+    mut debug: TerrainDebug,
 ) -> anyhow::Result<()> {
-    terrain_meshes.update(ctx.display())?;
+    let polygon_mode = match debug.settings.wireframe {
+        true => glium::PolygonMode::Line,
+        false => glium::PolygonMode::Fill,
+    };
 
     let (elapsed_seconds, elapsed_subseconds) = time.get();
-    let mut target = ctx.targets.get("world")?.framebuffer(ctx.display())?;
+    let mut target = ctx.targets.multi_framebuffer(
+        &[
+            ("b_color", "world"),
+            ("b_albedo", "gbuffer_albedo"),
+            ("b_normal", "gbuffer_normal"),
+        ],
+        "world",
+    )?;
     let program = ctx.shaders.get("terrain")?;
 
+    // unused cascades just reuse the last real shadow map, the shader never
+    // looks at them anyways.
+    let last_cascade = shadows.settings.cascades.saturating_sub(1);
+    let shadow_maps = [0, 1, 2].map(|cascade| usize::min(cascade, last_cascade));
+    let shadow_maps = [
+        shadow_map_uniform(&ctx.targets, shadow_maps[0])?,
+        shadow_map_uniform(&ctx.targets, shadow_maps[1])?,
+        shadow_map_uniform(&ctx.targets, shadow_maps[2])?,
+    ];
+    let shadow_matrices = shadows.cascades.view_projections;
+
     let view = camera.view();
     let proj = camera.projection(ctx.display.get_framebuffer_dimensions());
     let viewproj = proj.as_matrix() * view;
 
-    for (transform, RenderMeshComponent(handle)) in mesh_query.iter() {
-        let buffers = terrain_meshes
-            .meshes
-            .get(&handle.0.id)
-            .expect("RenderMeshComponent existed for entity that was not in terrain_meshes");
+    let dimensions = ctx.display.get_framebuffer_dimensions();
+    let culling_camera = debug.settings.culling_camera().copied();
+    let cull_viewproj = match culling_camera {
+        Some(frozen) => frozen.projection(dimensions).as_matrix() * frozen.view(),
+        None => viewproj,
+    };
+    let mut culled = debug.lines.start_default();
+    culled.color([1.0, 0.0, 0.0, 0.6]);
+
+    let uniforms = uniform! {
+        view: array4x4(&view),
+        projection: array4x4(&proj.to_homogeneous()),
+        albedo_maps: misc.block_textures.sampled()
+            .wrap_function(glium::uniforms::SamplerWrapFunction::Repeat)
+            .magnify_filter(MagnifySamplerFilter::Nearest),
+        textureAnimations: misc.texture_animations.sampled()
+            .minify_filter(MinifySamplerFilter::Nearest)
+            .magnify_filter(MagnifySamplerFilter::Nearest),
+        colormap: misc.grass_colormap.sampled()
+            .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp),
+        elapsedSeconds: elapsed_seconds,
+        elapsedSubseconds: elapsed_subseconds,
+        sunDirection: array3(&shadows.cascades.sun_direction),
+        shadowCascadeCount: shadows.settings.cascades as i32,
+        shadowCascadeDistances: SHADOW_CASCADE_DISTANCES,
+        shadowMap0: shadow_maps[0],
+        shadowMap1: shadow_maps[1],
+        shadowMap2: shadow_maps[2],
+        shadowMatrix0: array4x4(&shadow_matrices[0]),
+        shadowMatrix1: array4x4(&shadow_matrices[1]),
+        shadowMatrix2: array4x4(&shadow_matrices[2]),
+    };
+    let timer = ctx.timers.start(GpuPass::Terrain);
+    let draw_parameters = glium::DrawParameters {
+        depth: glium::Depth {
+            test: glium::DepthTest::IfLess,
+            write: true,
+            ..Default::default()
+        },
+        backface_culling: glium::BackfaceCullingMode::CullCounterClockwise,
+        polygon_mode,
+        time_elapsed_query: timer.query(),
+        ..Default::default()
+    };
+
+    let mut stats = TerrainDrawStats::default();
+    for batch in terrain_meshes.batches() {
+        // sections are still culled one by one, both so that culling can be
+        // inspected with a frozen camera, and so that batches with nothing
+        // visible in them are skipped even when their bounds are in view.
+        let mut visible = 0;
+        for section in batch.sections.iter() {
+            if should_draw_aabb(&cull_viewproj, section) {
+                visible += 1;
+            } else if culling_camera.is_some() {
+                immediate_draw_box_edges(&mut culled, section);
+            }
+        }
 
-        let model = transform.to_matrix();
-        let mvp = viewproj * model;
+        stats.batches += 1;
+        stats.visible_meshes += visible;
+        if visible == 0 {
+            continue;
+        }
 
-        if !should_draw_aabb(&mvp, &buffers.aabb) {
+        stats.draw_calls += 1;
+        target.draw(
+            batch.vertices(),
+            batch.indices(),
+            &program,
+            &uniforms.add("model", array4x4(&Matrix4::<f32>::identity())),
+            &draw_parameters,
+        )?;
+    }
+
+    for (transform, buffers) in terrain_meshes.others() {
+        let model = transform.to_matrix();
+        if !should_draw_aabb(&(cull_viewproj * model), &buffers.aabb) {
+            if culling_camera.is_some() {
+                immediate_draw_box_edges(&mut culled, &buffers.aabb.transformed(transform));
+            }
             continue;
         }
 
+        stats.visible_meshes += 1;
+        stats.draw_calls += 1;
         target.draw(
-            &buffers.vertices,
+            (&buffers.vertices, misc.zero_origin()?),
             &buffers.indices,
             &program,
-            &uniform! {
-            model: array4x4(&model),
-            view: array4x4(&view),
-            projection: array4x4(&proj.to_homogeneous()),
-            albedo_maps: misc.block_textures.sampled()
-                .wrap_function(glium::uniforms::SamplerWrapFunction::Repeat)
-                .magnify_filter(MagnifySamplerFilter::Nearest),
-                elapsedSeconds: elapsed_seconds,
-                elapsedSubseconds: elapsed_subseconds,
-            },
-            &glium::DrawParameters {
-                depth: glium::Depth {
-                    test: glium::DepthTest::IfLess,
-                    write: true,
-                    ..Default::default()
-                },
-                backface_culling: glium::BackfaceCullingMode::CullCounterClockwise,
-                // polygon_mode: glium::PolygonMode::Line,
-                ..Default::default()
-            },
+            &uniforms.add("model", array4x4(&model)),
+            &draw_parameters,
         )?;
     }
 
+    ctx.timers.finish(timer);
+    debug.stats.terrain = stats;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_passes_are_ordered_by_their_targets() {
+        let graph = frame_graph(&ShadowSettings::default()).unwrap();
+        assert_eq!(
+            graph.order().collect::<Vec<_>>(),
+            [
+                "shadows",
+                "sky",
+                "terrain",
+                "precipitation",
+                "lines",
+                "quads",
+                "resolve",
+                "lighting",
+                "bloom",
+                "post",
+                "hud",
+                "ui"
+            ]
+        );
+        assert_eq!(graph.after("terrain"), ["shadows", "sky"]);
+        assert_eq!(graph.after("bloom"), ["resolve", "lighting"]);
+
+        // turning shadows off still leaves a shadow map for the terrain to read
+        let graph = frame_graph(&ShadowSettings {
+            cascades: 0,
+            ..Default::default()
+        });
+        assert!(graph.is_ok());
+    }
+
+    #[test]
+    fn batch_buffers_are_reused_until_they_are_too_small_or_too_big() {
+        let capacity = batch_capacity(1000);
+        assert_eq!(capacity, 1500);
+        assert!(batch_buffer_fits(capacity, 1000));
+        assert!(batch_buffer_fits(capacity, 1500));
+        assert!(!batch_buffer_fits(capacity, 1501));
+        assert!(!batch_buffer_fits(capacity, 300));
+        // empty batches still get a buffer
+        assert_eq!(batch_capacity(0), 1);
+    }
+}
@@ -0,0 +1,918 @@
+//! recording input to a file, and playing it back later.
+//!
+//! a recording starts as soon as the player is let go at the spawn point. it
+//! holds the world seed, everything about the player at that moment, and which
+//! keys were being held down, followed by every input event that came in,
+//! grouped by the frame it came in on. both recording and replaying step the
+//! game forward by the same fixed amount every frame (see
+//! [`notcraft_common::clock`]), so a replay sees each input at exactly the same
+//! point in the game as the recording did. a recording that was stopped cleanly
+//! also remembers where the player ended up, which the replay checks itself
+//! against once it's done.
+//!
+//! like debug logs, a recording starts with a big-endian `u64` format version,
+//! which is followed by a header map node, one map node per frame, and an end
+//! map node, each preceded by its node kind. see the [`notcraft_common::codec`]
+//! module for details on how nodes are encoded.
+//!
+//! anything that isn't driven by input, like mobs wandering around or chunks
+//! finishing loading, isn't recorded. replays that depend on those can still
+//! play out differently than the recording did.
+
+use super::input::{HeldInputs, InputLabel, InputState, RawInputEvent};
+use crate::PlayerController;
+use bevy_app::Events;
+use glium::{
+    glutin::{
+        dpi::PhysicalPosition,
+        event::{
+            DeviceEvent, DeviceId, ElementState, KeyboardInput, ModifiersState, MouseScrollDelta,
+            VirtualKeyCode, WindowEvent,
+        },
+        window::WindowId,
+    },
+    Display,
+};
+use nalgebra::Vector3;
+use notcraft_common::{
+    clock::TimeStep,
+    codec::{
+        decode::{decode_node, Node},
+        encode::{encode_node, Encode, Encoder},
+        NodeKind,
+    },
+    health::Health,
+    physics::RigidBody,
+    prelude::*,
+    transform::Transform,
+    world::{persistence::WorldPersistence, spawn::WorldSpawn},
+};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+    rc::Rc,
+    time::Duration,
+};
+
+pub const RECORDING_FORMAT_VERSION: u64 = 1;
+
+/// how far the game is stepped forward every frame while recording.
+pub const RECORDING_TIME_STEP: Duration = Duration::from_micros(16_667);
+
+struct Fields<'a>(&'a Node);
+
+impl<'a> Fields<'a> {
+    fn get(&self, key: &str) -> Result<&'a Node> {
+        self.0
+            .get(key)
+            .ok_or_else(|| anyhow!("missing field '{}'", key))
+    }
+
+    fn unsigned<T: TryFrom<u128>>(&self, key: &str) -> Result<T> {
+        match self.get(key)?.as_unsigned().map(T::try_from) {
+            Some(Ok(value)) => Ok(value),
+            _ => bail!("field '{}' is not a small enough unsigned integer", key),
+        }
+    }
+
+    fn f32(&self, key: &str) -> Result<f32> {
+        match self.get(key)?.as_f32() {
+            Some(value) => Ok(value),
+            None => bail!("field '{}' is not a 32-bit float", key),
+        }
+    }
+
+    fn f64(&self, key: &str) -> Result<f64> {
+        match self.get(key)?.as_f64() {
+            Some(value) => Ok(value),
+            None => bail!("field '{}' is not a 64-bit float", key),
+        }
+    }
+
+    fn bool(&self, key: &str) -> Result<bool> {
+        match self.get(key)?.as_bool() {
+            Some(value) => Ok(value),
+            None => bail!("field '{}' is not a bool", key),
+        }
+    }
+
+    fn list(&self, key: &str) -> Result<&'a [Node]> {
+        match self.get(key)?.as_list() {
+            Some(items) => Ok(items),
+            None => bail!("field '{}' is not a list", key),
+        }
+    }
+}
+
+fn key_from_index(index: u32) -> Option<VirtualKeyCode> {
+    // SAFETY: `VirtualKeyCode` is `repr(u32)`, and its variants are numbered
+    // from zero without any gaps, ending with `Cut`.
+    (index <= VirtualKeyCode::Cut as u32)
+        .then(|| unsafe { std::mem::transmute::<u32, VirtualKeyCode>(index) })
+}
+
+fn element_state(pressed: bool) -> ElementState {
+    match pressed {
+        true => ElementState::Pressed,
+        false => ElementState::Released,
+    }
+}
+
+/// an input event that gets recorded. these are only the events that
+/// [`InputState`] pays attention to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RecordedInput {
+    Focused(bool),
+    MouseMotion {
+        dx: f64,
+        dy: f64,
+    },
+    /// where the cursor moved to in the window, which is only looked at when
+    /// raw mouse input is turned off.
+    CursorMoved {
+        x: f64,
+        y: f64,
+    },
+    MouseWheel(MouseScrollDelta),
+    Key {
+        scancode: u32,
+        pressed: bool,
+        key: Option<VirtualKeyCode>,
+    },
+    Button {
+        button: u32,
+        pressed: bool,
+    },
+}
+
+impl RecordedInput {
+    pub fn from_raw(event: &RawInputEvent) -> Option<Self> {
+        Some(match *event {
+            RawInputEvent::Window(_, WindowEvent::Focused(focused)) => Self::Focused(focused),
+            RawInputEvent::Device(_, DeviceEvent::MouseMotion { delta: (dx, dy) }) => {
+                Self::MouseMotion { dx, dy }
+            }
+            RawInputEvent::Window(_, WindowEvent::CursorMoved { position, .. }) => {
+                Self::CursorMoved {
+                    x: position.x,
+                    y: position.y,
+                }
+            }
+            RawInputEvent::Device(_, DeviceEvent::MouseWheel { delta }) => Self::MouseWheel(delta),
+            RawInputEvent::Device(_, DeviceEvent::Key(input)) => Self::Key {
+                scancode: input.scancode,
+                pressed: input.state == ElementState::Pressed,
+                key: input.virtual_keycode,
+            },
+            RawInputEvent::Device(_, DeviceEvent::Button { button, state }) => Self::Button {
+                button,
+                pressed: state == ElementState::Pressed,
+            },
+            _ => return None,
+        })
+    }
+
+    pub fn to_raw(&self, window: WindowId) -> RawInputEvent {
+        // SAFETY: the device IDs of input events are never handed back to winit.
+        let device = unsafe { DeviceId::dummy() };
+        let device_event = |event| RawInputEvent::Device(device, event);
+
+        match *self {
+            Self::Focused(focused) => RawInputEvent::Window(window, WindowEvent::Focused(focused)),
+            Self::MouseMotion { dx, dy } => {
+                device_event(DeviceEvent::MouseMotion { delta: (dx, dy) })
+            }
+            Self::CursorMoved { x, y } => {
+                #[allow(deprecated)]
+                let event = WindowEvent::CursorMoved {
+                    device_id: device,
+                    position: PhysicalPosition::new(x, y),
+                    modifiers: ModifiersState::empty(),
+                };
+                RawInputEvent::Window(window, event)
+            }
+            Self::MouseWheel(delta) => device_event(DeviceEvent::MouseWheel { delta }),
+            Self::Key {
+                scancode,
+                pressed,
+                key,
+            } => {
+                #[allow(deprecated)]
+                let input = KeyboardInput {
+                    scancode,
+                    state: element_state(pressed),
+                    virtual_keycode: key,
+                    modifiers: ModifiersState::empty(),
+                };
+                device_event(DeviceEvent::Key(input))
+            }
+            Self::Button { button, pressed } => device_event(DeviceEvent::Button {
+                button,
+                state: element_state(pressed),
+            }),
+        }
+    }
+
+    fn from_node(node: &Node) -> Result<Self> {
+        let fields = Fields(node);
+        let kind = fields.get("kind")?.as_str();
+        Ok(match kind {
+            Some("focus") => Self::Focused(fields.bool("focused")?),
+            Some("motion") => Self::MouseMotion {
+                dx: fields.f64("dx")?,
+                dy: fields.f64("dy")?,
+            },
+            Some("cursor") => Self::CursorMoved {
+                x: fields.f64("x")?,
+                y: fields.f64("y")?,
+            },
+            Some("wheel") => {
+                let [x, y] = [fields.f64("x")?, fields.f64("y")?];
+                Self::MouseWheel(match fields.bool("lines")? {
+                    true => MouseScrollDelta::LineDelta(x as f32, y as f32),
+                    false => MouseScrollDelta::PixelDelta(PhysicalPosition::new(x, y)),
+                })
+            }
+            Some("key") => Self::Key {
+                scancode: fields.unsigned("scancode")?,
+                pressed: fields.bool("pressed")?,
+                key: match node.get("key") {
+                    Some(_) => match key_from_index(fields.unsigned("key")?) {
+                        Some(key) => Some(key),
+                        None => bail!("unknown key {:?}", node.get("key")),
+                    },
+                    None => None,
+                },
+            },
+            Some("button") => Self::Button {
+                button: fields.unsigned("button")?,
+                pressed: fields.bool("pressed")?,
+            },
+            _ => bail!("unknown kind of input {:?}", kind),
+        })
+    }
+}
+
+impl<W: Write> Encode<W> for RecordedInput {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode_map(|mut map| {
+            match *self {
+                Self::Focused(focused) => {
+                    map.entry("kind").encode("focus")?;
+                    map.entry("focused").encode(&focused)?;
+                }
+                Self::MouseMotion { dx, dy } => {
+                    map.entry("kind").encode("motion")?;
+                    map.entry("dx").encode(&dx)?;
+                    map.entry("dy").encode(&dy)?;
+                }
+                Self::CursorMoved { x, y } => {
+                    map.entry("kind").encode("cursor")?;
+                    map.entry("x").encode(&x)?;
+                    map.entry("y").encode(&y)?;
+                }
+                Self::MouseWheel(delta) => {
+                    let (lines, x, y) = match delta {
+                        MouseScrollDelta::LineDelta(x, y) => (true, x as f64, y as f64),
+                        MouseScrollDelta::PixelDelta(pos) => (false, pos.x, pos.y),
+                    };
+                    map.entry("kind").encode("wheel")?;
+                    map.entry("lines").encode(&lines)?;
+                    map.entry("x").encode(&x)?;
+                    map.entry("y").encode(&y)?;
+                }
+                Self::Key {
+                    scancode,
+                    pressed,
+                    key,
+                } => {
+                    map.entry("kind").encode("key")?;
+                    map.entry("scancode").encode(&scancode)?;
+                    map.entry("pressed").encode(&pressed)?;
+                    if let Some(key) = key {
+                        map.entry("key").encode(&(key as u32))?;
+                    }
+                }
+                Self::Button { button, pressed } => {
+                    map.entry("kind").encode("button")?;
+                    map.entry("button").encode(&button)?;
+                    map.entry("pressed").encode(&pressed)?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+struct EncodeHeld<'a>(&'a HeldInputs);
+
+impl<W: Write> Encode<W> for EncodeHeld<'_> {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        let held = self.0;
+        encoder.encode_map(|mut map| {
+            map.entry("keys").encode_verbatim_list(held.keys.iter())?;
+            map.entry("buttons")
+                .encode_verbatim_list(held.buttons.iter())?;
+            map.entry("modifiers").encode(&held.modifiers.bits())?;
+            let keys = held.physical_map.iter().map(|&(key, _)| key as u32);
+            let codes = held.physical_map.iter().map(|&(_, code)| code);
+            map.entry("mapped_keys")
+                .encode_verbatim_list(keys.collect::<Vec<_>>().iter())?;
+            map.entry("mapped_codes")
+                .encode_verbatim_list(codes.collect::<Vec<_>>().iter())?;
+            map.entry("focused").encode(&held.focused)?;
+            Ok(())
+        })
+    }
+}
+
+fn held_from_node(node: &Node) -> Result<HeldInputs> {
+    let fields = Fields(node);
+    let numbers = |key| -> Result<Vec<u32>> {
+        let items = fields.list(key)?.iter();
+        match items
+            .map(|item| item.as_unsigned()?.try_into().ok())
+            .collect()
+        {
+            Some(numbers) => Ok(numbers),
+            None => bail!("field '{}' is not a list of unsigned integers", key),
+        }
+    };
+
+    let keys = numbers("mapped_keys")?.into_iter().map(key_from_index);
+    let keys = match keys.collect::<Option<Vec<_>>>() {
+        Some(keys) => keys,
+        None => bail!("field 'mapped_keys' has an unknown key in it"),
+    };
+
+    Ok(HeldInputs {
+        keys: numbers("keys")?,
+        buttons: numbers("buttons")?,
+        modifiers: ModifiersState::from_bits_truncate(fields.unsigned("modifiers")?),
+        physical_map: keys.into_iter().zip(numbers("mapped_codes")?).collect(),
+        focused: fields.bool("focused")?,
+    })
+}
+
+/// everything about the player that a replay needs to start from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PlayerState {
+    pub translation: Vector3<f32>,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub flying: bool,
+    /// zero while the player is flying, since they don't have a rigidbody then.
+    pub velocity: Vector3<f32>,
+    pub acceleration: Vector3<f32>,
+    pub health: f32,
+}
+
+type PlayerQuery<'a> = (&'a Transform, Option<&'a RigidBody>, &'a Health);
+type PlayerQueryMut<'a> = (&'a mut Transform, Option<&'a mut RigidBody>, &'a mut Health);
+
+impl PlayerState {
+    fn read(controller: &PlayerController, query: &Query<PlayerQuery>) -> Option<Self> {
+        let (transform, rigidbody, health) = query.get(controller.player).ok()?;
+        let rigidbody = rigidbody.copied().unwrap_or_default();
+        Some(Self {
+            translation: transform.translation.vector,
+            yaw: transform.rotation.yaw,
+            pitch: transform.rotation.pitch,
+            flying: controller.flying,
+            velocity: rigidbody.velocity,
+            acceleration: rigidbody.acceleration,
+            health: health.current,
+        })
+    }
+
+    fn apply(
+        &self,
+        cmd: &mut Commands,
+        controller: &mut PlayerController,
+        query: &mut Query<PlayerQueryMut>,
+    ) {
+        let (mut transform, rigidbody, mut health) = match query.get_mut(controller.player) {
+            Ok(player) => player,
+            Err(_) => return,
+        };
+
+        transform.translation.vector = self.translation;
+        transform.rotation.yaw = self.yaw;
+        transform.rotation.pitch = self.pitch;
+        health.current = self.health;
+
+        controller.flying = self.flying;
+        let body = RigidBody {
+            velocity: self.velocity,
+            acceleration: self.acceleration,
+        };
+        match (self.flying, rigidbody) {
+            (true, _) => drop(cmd.entity(controller.player).remove::<RigidBody>()),
+            (false, Some(mut rigidbody)) => *rigidbody = body,
+            (false, None) => drop(cmd.entity(controller.player).insert(body)),
+        }
+    }
+
+    fn from_node(node: &Node) -> Result<Self> {
+        let fields = Fields(node);
+        let vector = |prefix: &str| -> Result<Vector3<f32>> {
+            let field = |axis| fields.f32(&format!("{}{}", prefix, axis));
+            Ok(nalgebra::vector![field("x")?, field("y")?, field("z")?])
+        };
+
+        Ok(Self {
+            translation: vector("")?,
+            yaw: fields.f32("yaw")?,
+            pitch: fields.f32("pitch")?,
+            flying: fields.bool("flying")?,
+            velocity: vector("v")?,
+            acceleration: vector("a")?,
+            health: fields.f32("health")?,
+        })
+    }
+}
+
+impl<W: Write> Encode<W> for PlayerState {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode_map(|mut map| {
+            for (prefix, vector) in [
+                ("", self.translation),
+                ("v", self.velocity),
+                ("a", self.acceleration),
+            ] {
+                map.entry(&format!("{}x", prefix)).encode(&vector.x)?;
+                map.entry(&format!("{}y", prefix)).encode(&vector.y)?;
+                map.entry(&format!("{}z", prefix)).encode(&vector.z)?;
+            }
+            map.entry("yaw").encode(&self.yaw)?;
+            map.entry("pitch").encode(&self.pitch)?;
+            map.entry("flying").encode(&self.flying)?;
+            map.entry("health").encode(&self.health)?;
+            Ok(())
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordingHeader {
+    pub seed: u64,
+    /// how far the game was stepped forward every frame.
+    pub step: Duration,
+    pub player: PlayerState,
+    pub held: HeldInputs,
+}
+
+impl<W: Write> Encode<W> for RecordingHeader {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode_map(|mut map| {
+            map.entry("seed").encode(&self.seed)?;
+            map.entry("step").encode(&(self.step.as_micros() as u64))?;
+            map.entry("player").encode(&self.player)?;
+            map.entry("held").encode(&EncodeHeld(&self.held))?;
+            Ok(())
+        })
+    }
+}
+
+impl RecordingHeader {
+    fn from_node(node: &Node) -> Result<Self> {
+        let fields = Fields(node);
+        Ok(Self {
+            seed: fields.unsigned("seed")?,
+            step: Duration::from_micros(fields.unsigned("step")?),
+            player: PlayerState::from_node(fields.get("player")?)?,
+            held: held_from_node(fields.get("held")?)?,
+        })
+    }
+}
+
+/// all the input that came in during one frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordedFrame {
+    pub inputs: Vec<RecordedInput>,
+}
+
+impl<W: Write> Encode<W> for RecordedFrame {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode_map(|mut map| map.entry("inputs").encode_verbatim_list(self.inputs.iter()))
+    }
+}
+
+/// where the player ended up when the recording was stopped.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct RecordingEnd {
+    player: PlayerState,
+}
+
+impl<W: Write> Encode<W> for RecordingEnd {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode_map(|mut map| map.entry("end").encode(&self.player))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recording {
+    pub header: RecordingHeader,
+    pub frames: Vec<RecordedFrame>,
+    /// `None` when the recording was cut off, like when the game crashed.
+    pub end: Option<PlayerState>,
+}
+
+/// reads either a frame, or where the player was at the end of the recording.
+fn read_frame<R: Read>(reader: &mut R) -> Result<Result<RecordedFrame, PlayerState>> {
+    let node = decode_node(reader)?;
+    if let Some(player) = node.get("end") {
+        return Ok(Err(PlayerState::from_node(player)?));
+    }
+
+    let inputs = Fields(&node).list("inputs")?.iter();
+    let inputs = inputs
+        .map(RecordedInput::from_node)
+        .collect::<Result<_>>()?;
+    Ok(Ok(RecordedFrame { inputs }))
+}
+
+pub fn read_recording(path: &Path) -> Result<Recording> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut version = [0; 8];
+    reader.read_exact(&mut version)?;
+    let version = u64::from_be_bytes(version);
+    if version != RECORDING_FORMAT_VERSION {
+        bail!("unsupported input recording version {}", version);
+    }
+
+    let header = RecordingHeader::from_node(&decode_node(&mut reader)?)?;
+
+    let mut frames = vec![];
+    let mut end = None;
+    // like debug logs, a recording of a game that crashed can be cut off partway
+    // through a frame, so everything that made it is kept.
+    while end.is_none() && !reader.fill_buf()?.is_empty() {
+        match read_frame(&mut reader) {
+            Ok(Ok(frame)) => frames.push(frame),
+            Ok(Err(player)) => end = Some(player),
+            Err(err) => {
+                let count = frames.len();
+                log::warn!(
+                    "stopped reading input recording after {} frames: {}",
+                    count,
+                    err
+                );
+                break;
+            }
+        }
+    }
+
+    Ok(Recording {
+        header,
+        frames,
+        end,
+    })
+}
+
+pub struct InputRecorder {
+    /// `None` once something has gone wrong writing to the recording.
+    out: Option<BufWriter<File>>,
+    started: bool,
+    /// where the player was at the end of the last frame.
+    player: Option<PlayerState>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&RECORDING_FORMAT_VERSION.to_be_bytes())?;
+
+        Ok(Self {
+            out: Some(out),
+            started: false,
+            player: None,
+        })
+    }
+
+    fn write<T: Encode<BufWriter<File>>>(&mut self, node: &T) {
+        let out = match self.out.as_mut() {
+            Some(out) => out,
+            None => return,
+        };
+
+        // everything is flushed right away, so that the recording of a game that
+        // crashed has everything up until the crash.
+        if let Err(err) = encode_node(out, node).and_then(|_| Ok(out.flush()?)) {
+            log::error!("failed to record input, stopping recording: {}", err);
+            self.out = None;
+        }
+    }
+
+    fn start(&mut self, header: &RecordingHeader) {
+        self.write(header);
+        self.started = true;
+        self.player = Some(header.player);
+        log::info!("started recording input");
+    }
+
+    fn record_frame(&mut self, frame: &RecordedFrame) {
+        if self.started {
+            self.write(frame);
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(player) = self.player.filter(|_| self.started) {
+            self.write(&RecordingEnd { player });
+        }
+        self.out = None;
+    }
+}
+
+pub struct InputReplay {
+    recording: Recording,
+    frames: VecDeque<RecordedFrame>,
+    started: bool,
+    finished: bool,
+}
+
+impl InputReplay {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            frames: recording.frames.iter().cloned().collect(),
+            recording,
+            started: false,
+            finished: false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub enum ReplayMode {
+    #[default]
+    Off,
+    Record(InputRecorder),
+    Replay(InputReplay),
+}
+
+fn record_input(mut mode: ResMut<ReplayMode>, mut events: EventReader<RawInputEvent>) {
+    if let ReplayMode::Record(recorder) = &mut *mode {
+        let inputs = events.iter().filter_map(RecordedInput::from_raw).collect();
+        recorder.record_frame(&RecordedFrame { inputs });
+    }
+}
+
+/// swaps out input from the keyboard and mouse for the recorded input.
+fn replay_input(
+    mut mode: ResMut<ReplayMode>,
+    display: NonSend<Rc<Display>>,
+    mut events: ResMut<Events<RawInputEvent>>,
+) {
+    let replay = match &mut *mode {
+        ReplayMode::Replay(replay) if !replay.finished => replay,
+        _ => return,
+    };
+
+    // other events, like the window being resized, still go through
+    let live = events.drain().collect::<Vec<_>>();
+    for event in live {
+        if RecordedInput::from_raw(&event).is_none() {
+            events.send(event);
+        }
+    }
+
+    if replay.started {
+        let window = display.gl_window().window().id();
+        let frame = replay.frames.pop_front().unwrap_or_default();
+        for input in frame.inputs.iter() {
+            events.send(input.to_raw(window));
+        }
+    }
+}
+
+fn start_recording(
+    mut mode: ResMut<ReplayMode>,
+    spawn: Res<WorldSpawn>,
+    persistence: Res<WorldPersistence>,
+    input: Res<InputState>,
+    controller: Res<PlayerController>,
+    query: Query<PlayerQuery>,
+) {
+    let recorder = match &mut *mode {
+        ReplayMode::Record(recorder) => recorder,
+        _ => return,
+    };
+
+    let player = PlayerState::read(&controller, &query);
+    if recorder.started {
+        recorder.player = player.or(recorder.player);
+        return;
+    }
+
+    // the player is held in place until the terrain around the spawn point has
+    // loaded, which takes however long it takes.
+    if let (true, Some(player), Some(seed)) =
+        (spawn.is_settled(), player, persistence.metadata().seed)
+    {
+        recorder.start(&RecordingHeader {
+            seed,
+            step: RECORDING_TIME_STEP,
+            player,
+            held: input.held(),
+        });
+    }
+}
+
+fn describe_difference(expected: &PlayerState, actual: &PlayerState) -> String {
+    let [x, y, z] = [
+        actual.translation.x,
+        actual.translation.y,
+        actual.translation.z,
+    ];
+    let [ex, ey, ez] = [
+        expected.translation.x,
+        expected.translation.y,
+        expected.translation.z,
+    ];
+    format!(
+        "the player ended up at ({}, {}, {}) with {} health, instead of at ({}, {}, {}) with {} \
+         health",
+        x, y, z, actual.health, ex, ey, ez, expected.health
+    )
+}
+
+fn advance_replay(
+    mut cmd: Commands,
+    mut mode: ResMut<ReplayMode>,
+    mut time_step: ResMut<TimeStep>,
+    spawn: Res<WorldSpawn>,
+    mut input: ResMut<InputState>,
+    mut controller: ResMut<PlayerController>,
+    mut query: QuerySet<(Query<PlayerQuery>, Query<PlayerQueryMut>)>,
+) {
+    let replay = match &mut *mode {
+        ReplayMode::Replay(replay) if !replay.finished => replay,
+        _ => return,
+    };
+
+    if !replay.started {
+        if spawn.is_settled() {
+            let header = &replay.recording.header;
+            header
+                .player
+                .apply(&mut cmd, &mut controller, query.q1_mut());
+            input.set_held(&header.held);
+            replay.started = true;
+            log::info!("started replaying {} frames of input", replay.frames.len());
+        }
+        return;
+    }
+
+    if !replay.frames.is_empty() {
+        return;
+    }
+
+    replay.finished = true;
+    *time_step = TimeStep::RealTime;
+
+    let actual = PlayerState::read(&controller, query.q0());
+    match (replay.recording.end, actual) {
+        (None, _) => log::warn!("finished replaying a recording that was cut off"),
+        (Some(expected), Some(actual)) if expected == actual => {
+            log::info!("finished replaying, and everything played out the same")
+        }
+        (Some(expected), Some(actual)) => log::warn!(
+            "finished replaying, but it played out differently: {}",
+            describe_difference(&expected, &actual)
+        ),
+        (Some(_), None) => log::warn!("finished replaying, but the player is gone"),
+    }
+}
+
+/// writes down where the player ended up, if input is being recorded. this
+/// should be called once the game has run its last frame.
+pub fn finish_recording(world: &mut World) {
+    if let Some(mut mode) = world.get_resource_mut::<ReplayMode>() {
+        if let ReplayMode::Record(recorder) = &mut *mode {
+            recorder.finish();
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReplayPlugin {}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ReplayMode>();
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            record_input.system().before(InputLabel),
+        );
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            replay_input.system().before(InputLabel),
+        );
+        app.add_system_to_stage(CoreStage::Last, start_recording.system());
+        app.add_system_to_stage(CoreStage::Last, advance_replay.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recordings_round_trip() {
+        let path = std::env::temp_dir().join(format!("notcraft-replay-{}", std::process::id()));
+        let player = PlayerState {
+            translation: nalgebra::vector![0.5, 64.85, -3.5],
+            yaw: 1.25,
+            pitch: -0.5,
+            flying: false,
+            velocity: nalgebra::vector![0.0, -9.5, 0.125],
+            acceleration: nalgebra::vector![0.0, -32.0, 0.0],
+            health: 17.5,
+        };
+        let header = RecordingHeader {
+            seed: 0xdead_beef_cafe,
+            step: RECORDING_TIME_STEP,
+            player,
+            held: HeldInputs {
+                keys: vec![0x11, 0x2a],
+                buttons: vec![1],
+                modifiers: ModifiersState::SHIFT,
+                physical_map: vec![(VirtualKeyCode::W, 0x11), (VirtualKeyCode::LShift, 0x2a)],
+                focused: true,
+            },
+        };
+        let frames = vec![
+            RecordedFrame {
+                inputs: vec![
+                    RecordedInput::MouseMotion { dx: 0.1, dy: -3.0 },
+                    RecordedInput::CursorMoved { x: 640.5, y: 12.0 },
+                    RecordedInput::Key {
+                        scancode: 0x20,
+                        pressed: true,
+                        key: Some(VirtualKeyCode::Cut),
+                    },
+                    RecordedInput::Key {
+                        scancode: 0x7f,
+                        pressed: false,
+                        key: None,
+                    },
+                ],
+            },
+            RecordedFrame::default(),
+            RecordedFrame {
+                inputs: vec![
+                    RecordedInput::Focused(false),
+                    RecordedInput::Button {
+                        button: 3,
+                        pressed: false,
+                    },
+                    RecordedInput::MouseWheel(MouseScrollDelta::LineDelta(0.0, -1.5)),
+                    RecordedInput::MouseWheel(MouseScrollDelta::PixelDelta(PhysicalPosition::new(
+                        2.0, 0.25,
+                    ))),
+                ],
+            },
+        ];
+
+        let mut recorder = InputRecorder::create(&path).unwrap();
+        // frames from before the recording starts are left out
+        recorder.record_frame(&frames[0]);
+        recorder.start(&header);
+        for frame in frames.iter() {
+            recorder.record_frame(frame);
+        }
+        let end = PlayerState {
+            flying: true,
+            ..player
+        };
+        recorder.player = Some(end);
+        recorder.finish();
+
+        let recording = read_recording(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            recording,
+            Recording {
+                header,
+                frames,
+                end: Some(end),
+            }
+        );
+    }
+}
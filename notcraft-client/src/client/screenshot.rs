@@ -0,0 +1,64 @@
+//! saving what's on screen to a PNG file by pressing `F2`.
+
+use crate::client::input::InputState;
+use glium::{glutin::event::VirtualKeyCode, texture::RawImage2d, Display};
+use image::RgbaImage;
+use notcraft_common::{lang::tr, notification::Notify, prelude::*};
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// where screenshots are saved, relative to the directory the game was started
+/// in.
+const SCREENSHOT_DIR: &str = "screenshots";
+
+fn save_screenshot(display: &Display) -> Result<PathBuf> {
+    // this is the last frame that was shown, since the current one hasn't been
+    // drawn yet.
+    let image: RawImage2d<u8> = display.read_front_buffer()?;
+    let pixels = image.data.into_owned();
+    let image = match RgbaImage::from_raw(image.width, image.height, pixels) {
+        Some(image) => image,
+        None => bail!("the screen had the wrong number of pixels"),
+    };
+
+    std::fs::create_dir_all(SCREENSHOT_DIR)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = Path::new(SCREENSHOT_DIR).join(format!("{}.png", timestamp));
+    // OpenGL's rows go from the bottom up, while images go from the top down
+    image::imageops::flip_vertical(&image).save(&path)?;
+    Ok(path)
+}
+
+fn take_screenshot(
+    input: Res<InputState>,
+    display: NonSend<Rc<Display>>,
+    mut notify_events: EventWriter<Notify>,
+) {
+    if !input.key(VirtualKeyCode::F2).is_rising() {
+        return;
+    }
+
+    match save_screenshot(&display) {
+        Ok(path) => {
+            log::info!("saved screenshot to '{}'", path.display());
+            let message = tr!("notify.screenshot_saved", path.display());
+            notify_events.send(Notify::info(message));
+        }
+        Err(err) => {
+            log::error!("failed to save screenshot: {}", err);
+            notify_events.send(Notify::error(tr!("notify.screenshot_failed", err)));
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ScreenshotPlugin {}
+
+impl Plugin for ScreenshotPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(take_screenshot.system());
+    }
+}
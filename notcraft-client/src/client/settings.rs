@@ -0,0 +1,105 @@
+//! settings that stick around between runs of the game.
+//!
+//! settings are stored in a RON file, which is written back out whenever
+//! something changes them. any fields missing from the file just use their
+//! defaults, so older settings files keep working as new settings are added.
+
+use super::{
+    camera::CameraSettings, input::MouseSettings, logging::LoggingSettings, mixer::AudioSettings,
+    window::WindowSettings,
+};
+use notcraft_common::{item_drop::ItemDropConfig, lang, prelude::*};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub window: WindowSettings,
+    /// the code of the language file in `resources/lang` to show text in.
+    pub language: String,
+    pub logging: LoggingSettings,
+    pub audio: AudioSettings,
+    pub camera: CameraSettings,
+    pub mouse: MouseSettings,
+    /// the name of the directory in `resources/texturepacks` that block
+    /// textures are taken from, before falling back to the base textures.
+    pub texture_pack: Option<String>,
+    /// whether the compass and coordinates are shown at the top of the screen.
+    pub compass: bool,
+    /// whether the block being looked at is described next to the crosshair.
+    pub block_tooltip: bool,
+    /// the names of the directories in `resources/packs` to load content
+    /// from, in order. later packs replace anything from earlier packs that
+    /// has the same name. changes take effect the next time the game starts.
+    pub content_packs: Vec<String>,
+    /// how long dropped items last, and how many there can be.
+    pub item_drops: ItemDropConfig,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window: Default::default(),
+            language: lang::FALLBACK_LANGUAGE.into(),
+            logging: Default::default(),
+            audio: Default::default(),
+            camera: Default::default(),
+            mouse: Default::default(),
+            texture_pack: None,
+            compass: true,
+            block_tooltip: true,
+            content_packs: vec![],
+            item_drops: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct SettingsFile {
+    /// `None` when the settings aren't saved anywhere.
+    path: Option<PathBuf>,
+    settings: Settings,
+}
+
+impl SettingsFile {
+    /// reads the settings in `path`, using the defaults if the file doesn't
+    /// exist yet.
+    pub fn open(path: &Path) -> Result<Self> {
+        let settings = match path.exists() {
+            true => ron::from_str(&std::fs::read_to_string(path)?)?,
+            false => Default::default(),
+        };
+
+        Ok(Self {
+            path: Some(path.into()),
+            settings,
+        })
+    }
+
+    /// the default settings, which are never saved.
+    pub fn unsaved() -> Self {
+        Self::default()
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// changes the settings and writes them out to the settings file right
+    /// away, if there is one.
+    pub fn update<F>(&mut self, func: F) -> Result<()>
+    where
+        F: FnOnce(&mut Settings),
+    {
+        func(&mut self.settings);
+        match &self.path {
+            Some(path) => {
+                let pretty = ron::ser::PrettyConfig::new();
+                let text = ron::ser::to_string_pretty(&self.settings, pretty)?;
+                std::fs::write(path, text)?;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
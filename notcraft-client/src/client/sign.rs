@@ -0,0 +1,184 @@
+//! drawing the text written on signs onto their sides, and editing it.
+//!
+//! right-clicking a sign opens a text field with the sign's text in it, and
+//! `Enter` writes what's in the field back onto the sign.
+
+use crate::{
+    client::{
+        camera::CurrentCamera,
+        render::font,
+        render::renderer::{ImmediateQuads, ImmediateUi},
+        text_input::{TextField, TextInput, TextInputEvent, FIELD_HEIGHT},
+    },
+    TerrainManipulator,
+};
+use nalgebra::{point, vector, Point3, Vector3};
+use notcraft_common::{
+    lang::tr,
+    prelude::*,
+    world::{
+        block_entity::{BlockEntity, SignData, MAX_SIGN_TEXT_LENGTH},
+        chunk::{index_to_block, ChunkAccess, ChunkSectionPos},
+        BlockPos, WorldPos,
+    },
+};
+
+/// signs further away than this many blocks don't have their text drawn.
+const SIGN_TEXT_DISTANCE: f32 = 16.0;
+/// how far off of the sign the text floats, so that it doesn't fight with the
+/// face underneath it.
+const TEXT_OFFSET: f32 = 0.005;
+/// text never gets any bigger than this many blocks per font pixel...
+const MAX_PIXEL_SIZE: f32 = 1.0 / 16.0;
+/// ...and long text is shrunk down to fit in this much of the face.
+const TEXT_WIDTH: f32 = 0.875;
+const TEXT_COLOR: [f32; 4] = [0.1, 0.07, 0.04, 1.0];
+
+const OWNER: &str = "sign";
+/// how far below the middle of the screen the sign's text field goes, so that
+/// the sign itself can still be seen.
+const FIELD_OFFSET: f32 = 24.0;
+
+/// the outward normal of each of the sides of a block that text goes on, and
+/// which way is right when looking at that side.
+const SIDES: [(Vector3<f32>, Vector3<f32>); 4] = [
+    (vector![1.0, 0.0, 0.0], vector![0.0, 0.0, -1.0]),
+    (vector![-1.0, 0.0, 0.0], vector![0.0, 0.0, 1.0]),
+    (vector![0.0, 0.0, 1.0], vector![1.0, 0.0, 0.0]),
+    (vector![0.0, 0.0, -1.0], vector![-1.0, 0.0, 0.0]),
+];
+
+fn draw_sign_text(quads: &mut ImmediateQuads, eye: Point3<f32>, center: Point3<f32>, text: &str) {
+    let width = font::text_width(text);
+    let pixel = f32::min(MAX_PIXEL_SIZE, TEXT_WIDTH / width);
+    let up = vector![0.0, pixel, 0.0];
+
+    for (normal, right) in SIDES {
+        let face = center + normal * (0.5 + TEXT_OFFSET);
+        // the back sides of the text are never visible
+        if (eye - face).dot(&normal) <= 0.0 {
+            continue;
+        }
+        let right = right * pixel;
+        let origin = face - right * (width / 2.0) - up * (font::GLYPH_HEIGHT / 2.0);
+        quads.text(origin, right, up, text, TEXT_COLOR);
+    }
+}
+
+fn draw_signs(
+    camera: CurrentCamera,
+    mut access: ResMut<ChunkAccess>,
+    mut quads: ResMut<ImmediateQuads>,
+) {
+    let eye = camera.pos();
+    let center = ChunkSectionPos::from(WorldPos::new(eye.coords));
+
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                let pos = ChunkSectionPos {
+                    x: center.x + x,
+                    y: center.y + y,
+                    z: center.z + z,
+                };
+                let section = match access.section(pos) {
+                    Some(section) => section,
+                    None => continue,
+                };
+
+                for (index, entity) in section.block_entities().iter() {
+                    let sign = match entity {
+                        BlockEntity::Sign(sign) if !sign.text().is_empty() => sign,
+                        _ => continue,
+                    };
+
+                    let block = index_to_block(pos, index);
+                    let center = point![block.x as f32, block.y as f32, block.z as f32]
+                        + vector![0.5, 0.5, 0.5];
+                    if (center - eye).norm() < SIGN_TEXT_DISTANCE {
+                        draw_sign_text(&mut quads, eye, center, sign.text());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// the sign whose text is being edited, if any.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct SignEditor {
+    editing: Option<BlockPos>,
+}
+
+fn edit_signs(
+    mut editor: Local<SignEditor>,
+    mut text_input: ResMut<TextInput>,
+    mut access: ResMut<ChunkAccess>,
+    mut events: EventReader<TextInputEvent>,
+    manipulators: Query<&mut TerrainManipulator>,
+) {
+    manipulators.for_each_mut(|mut manip| {
+        let pos = match manip.clicked_sign.take() {
+            Some(pos) => pos,
+            None => return,
+        };
+        if let Some(BlockEntity::Sign(sign)) = access.block_entity(pos) {
+            let field = TextField::new(sign.text()).with_max_chars(MAX_SIGN_TEXT_LENGTH);
+            text_input.focus(OWNER, field);
+            editor.editing = Some(pos);
+        }
+    });
+
+    let pos = match editor.editing {
+        Some(pos) if text_input.is_focused(OWNER) => pos,
+        Some(pos) => {
+            for event in events.iter() {
+                if let TextInputEvent::Submitted { owner: OWNER, text } = event {
+                    write_sign(&mut access, pos, text);
+                }
+            }
+            editor.editing = None;
+            return;
+        }
+        None => return,
+    };
+
+    // the field closes if the sign is broken while it's being edited
+    if !matches!(access.block_entity(pos), Some(BlockEntity::Sign(_))) {
+        text_input.close(OWNER);
+        editor.editing = None;
+    }
+}
+
+fn write_sign(access: &mut ChunkAccess, pos: BlockPos, text: &str) {
+    if !matches!(access.block_entity(pos), Some(BlockEntity::Sign(_))) {
+        return;
+    }
+    match SignData::new(text) {
+        Ok(sign) => access.set_block_entity(pos, BlockEntity::Sign(sign)),
+        Err(err) => log::warn!("failed to write on the sign at {:?}: {}", pos, err),
+    }
+}
+
+fn draw_sign_editor(mut text_input: ResMut<TextInput>, mut ui: ResMut<ImmediateUi>) {
+    if !text_input.is_focused(OWNER) {
+        return;
+    }
+    let width = MAX_SIGN_TEXT_LENGTH as f32 * font::GLYPH_ADVANCE + 4.0;
+    let bottom = -FIELD_OFFSET - FIELD_HEIGHT;
+    let title = tr!("sign.editing");
+    let title_pos = [-font::text_width(&title) / 2.0, -FIELD_OFFSET + 2.0];
+    ui.text(title_pos, &title, [1.0; 4]);
+    text_input.draw(&mut ui, OWNER, [-width / 2.0, bottom], width);
+}
+
+#[derive(Debug, Default)]
+pub struct SignRenderPlugin {}
+
+impl Plugin for SignRenderPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(draw_signs.system());
+        app.add_system(edit_signs.system());
+        app.add_system(draw_sign_editor.system());
+    }
+}
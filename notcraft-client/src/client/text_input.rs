@@ -0,0 +1,503 @@
+//! single lines of editable text, shared by everything that has the player
+//! type something in, like the console and signs.
+//!
+//! a [`TextField`] is the text itself along with the cursor and selection. only
+//! one field takes keyboard input at a time: whichever one was last handed to
+//! [`TextInput::focus`]. while a field is focused, key presses don't reach the
+//! rest of the game (see [`InputState::capture_keyboard`]), and typed
+//! characters go into the field instead. text composed with an IME comes in the
+//! same way once it's committed, and the IME's candidate window is moved next
+//! to the field's cursor.
+//!
+//! `Left`/`Right` move the cursor, by whole words while holding `Ctrl`, and
+//! `Home`/`End` move it to either end. holding `Shift` selects what the cursor
+//! moves over. `Backspace` and `Delete` delete the selection, or the character
+//! (or word, with `Ctrl`) next to the cursor. `Ctrl+A` selects everything, and
+//! `Ctrl+C`, `Ctrl+X`, and `Ctrl+V` copy, cut, and paste. `Enter` submits the
+//! field and `Escape` gives up on it, either of which closes it and sends a
+//! [`TextInputEvent`].
+//!
+//! held keys repeat as often as the OS repeats them, since fields read the
+//! window's key events rather than the raw device events that the rest of the
+//! game uses, which don't repeat.
+
+use crate::client::{
+    input::{InputLabel, InputState, RawInputEvent},
+    render::{font, renderer::ImmediateUi},
+    window::UiScale,
+};
+use copypasta::{ClipboardContext, ClipboardProvider};
+use glium::{
+    glutin::{
+        dpi::PhysicalPosition,
+        event::{ElementState, VirtualKeyCode, WindowEvent},
+    },
+    Display,
+};
+use notcraft_common::prelude::*;
+use std::{ops::Range, rc::Rc};
+
+const PADDING: f32 = 2.0;
+/// how tall fields are drawn, in UI units.
+pub const FIELD_HEIGHT: f32 = font::GLYPH_HEIGHT + 2.0 * PADDING;
+
+/// where the cursor goes when it's moved.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    Home,
+    End,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TextField {
+    text: String,
+    /// byte offset of the cursor into `text`, which is always on a character
+    /// boundary.
+    cursor: usize,
+    /// the other end of the selection from the cursor, if anything is selected.
+    anchor: Option<usize>,
+    max_chars: Option<usize>,
+}
+
+impl TextField {
+    /// a field that starts out with `text` in it, with the cursor at the end.
+    pub fn new(text: impl Into<String>) -> Self {
+        let text = text.into();
+        Self {
+            cursor: text.len(),
+            text,
+            anchor: None,
+            max_chars: None,
+        }
+    }
+
+    /// stops more than `max` characters from being typed or pasted in.
+    pub fn with_max_chars(mut self, max: usize) -> Self {
+        if let Some((end, _)) = self.text.char_indices().nth(max) {
+            self.text.truncate(end);
+            self.cursor = self.cursor.min(end);
+        }
+        self.max_chars = Some(max);
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// the byte range of the selected text, if there is any.
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor.filter(|&anchor| anchor != self.cursor)?;
+        Some(anchor.min(self.cursor)..anchor.max(self.cursor))
+    }
+
+    pub fn selected_text(&self) -> Option<&str> {
+        self.selection().map(|range| &self.text[range])
+    }
+
+    /// where `motion` would take the cursor from where it is now.
+    fn target(&self, motion: Motion) -> usize {
+        let (before, after) = self.text.split_at(self.cursor);
+        match motion {
+            Motion::Left => before.char_indices().next_back().map_or(0, |(idx, _)| idx),
+            Motion::Right => self.cursor + after.chars().next().map_or(0, char::len_utf8),
+            // to the start of the word before the cursor, skipping any spaces
+            // right before it
+            Motion::WordLeft => {
+                let trimmed = before.trim_end();
+                let space = trimmed.char_indices().rfind(|(_, ch)| ch.is_whitespace());
+                space.map_or(0, |(idx, ch)| idx + ch.len_utf8())
+            }
+            // to the end of the word after the cursor, likewise
+            Motion::WordRight => {
+                let spaces = after.len() - after.trim_start().len();
+                let word = &after[spaces..];
+                let word_len = word.find(char::is_whitespace).unwrap_or(word.len());
+                self.cursor + spaces + word_len
+            }
+            Motion::Home => 0,
+            Motion::End => self.text.len(),
+        }
+    }
+
+    /// moves the cursor, selecting what it moves over if `select` is set.
+    pub fn move_cursor(&mut self, motion: Motion, select: bool) {
+        let target = match (self.selection(), motion) {
+            // moving off of a selection without extending it goes to whichever
+            // end of the selection is in the direction of the motion
+            (Some(selection), Motion::Left) if !select => selection.start,
+            (Some(selection), Motion::Right) if !select => selection.end,
+            _ => self.target(motion),
+        };
+        if !select {
+            self.anchor = None;
+        } else if self.anchor.is_none() {
+            self.anchor = Some(self.cursor);
+        }
+        self.cursor = target;
+    }
+
+    pub fn select_all(&mut self) {
+        self.anchor = Some(0);
+        self.cursor = self.text.len();
+    }
+
+    fn replace(&mut self, range: Range<usize>, text: &str) {
+        self.cursor = range.start + text.len();
+        self.anchor = None;
+        self.text.replace_range(range, text);
+    }
+
+    /// deletes the selection, or if nothing is selected, whatever is between
+    /// the cursor and where `motion` would move it.
+    pub fn delete(&mut self, motion: Motion) {
+        let range = self.selection().unwrap_or_else(|| {
+            let target = self.target(motion);
+            target.min(self.cursor)..target.max(self.cursor)
+        });
+        self.replace(range, "");
+    }
+
+    /// types `text` in over the selection. tabs and line breaks become spaces,
+    /// other characters that can't be drawn are left out, and anything past
+    /// the field's maximum length is cut off.
+    pub fn insert(&mut self, text: &str) {
+        let range = self.selection().unwrap_or(self.cursor..self.cursor);
+        let room = match self.max_chars {
+            Some(max) => {
+                let replaced = self.text[range.clone()].chars().count();
+                max.saturating_sub(self.text.chars().count() - replaced)
+            }
+            None => usize::MAX,
+        };
+        let text = text.chars().map(|ch| match ch {
+            '\t' | '\n' => ' ',
+            ch => ch,
+        });
+        let text = text.filter(|ch| !ch.is_control()).take(room);
+        self.replace(range, &text.collect::<String>());
+    }
+
+    /// removes the selected text and gives it back.
+    pub fn cut(&mut self) -> Option<String> {
+        let range = self.selection()?;
+        let cut = self.text[range.clone()].to_owned();
+        self.replace(range, "");
+        Some(cut)
+    }
+}
+
+/// sent when the focused field is closed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TextInputEvent {
+    /// `Enter` was pressed while `owner`'s field was focused.
+    Submitted { owner: &'static str, text: String },
+    /// `Escape` was pressed while `owner`'s field was focused.
+    Cancelled { owner: &'static str },
+}
+
+/// the field that's taking keyboard input, if any.
+#[derive(Clone, Debug, Default)]
+pub struct TextInput {
+    focused: Option<(&'static str, TextField)>,
+    /// where the focused field's cursor was last drawn, in UI units, which is
+    /// where the IME's candidate window goes.
+    cursor_spot: Option<[f32; 2]>,
+}
+
+impl TextInput {
+    /// gives `field` the keyboard, taking it away from whichever field had it
+    /// before. `owner` says who the field belongs to, and is sent back along
+    /// with the field's [`TextInputEvent`] once it's closed.
+    pub fn focus(&mut self, owner: &'static str, field: TextField) {
+        self.focused = Some((owner, field));
+        self.cursor_spot = None;
+    }
+
+    pub fn is_focused(&self, owner: &str) -> bool {
+        self.field(owner).is_some()
+    }
+
+    pub fn is_any_focused(&self) -> bool {
+        self.focused.is_some()
+    }
+
+    /// `owner`'s field, if it's the one that's focused.
+    pub fn field(&self, owner: &str) -> Option<&TextField> {
+        match &self.focused {
+            Some((focused, field)) if *focused == owner => Some(field),
+            _ => None,
+        }
+    }
+
+    /// closes `owner`'s field, if it's focused, without sending anything.
+    pub fn close(&mut self, owner: &str) {
+        if self.is_focused(owner) {
+            self.focused = None;
+        }
+    }
+
+    /// draws `owner`'s field, if it's focused, with its bottom left corner at
+    /// `pos`. text that doesn't fit in `width` is scrolled so that the cursor
+    /// stays in view.
+    pub fn draw(&mut self, ui: &mut ImmediateUi, owner: &str, pos: [f32; 2], width: f32) {
+        let field = match self.field(owner) {
+            Some(field) => field,
+            None => return,
+        };
+        let [left, bottom] = pos;
+        let top = bottom + FIELD_HEIGHT;
+        ui.rect([left, bottom], [left + width, top], [0.05, 0.05, 0.05, 0.8]);
+
+        let fits = ((width - 2.0 * PADDING) / font::GLYPH_ADVANCE).max(1.0) as usize;
+        let char_idx = |byte: usize| field.text[..byte].chars().count();
+        let cursor = char_idx(field.cursor);
+        let first = cursor.saturating_sub(fits);
+        let visible = |idx: usize| util::clamp(first, first + fits, idx);
+        let x = |idx: usize| left + PADDING + (visible(idx) - first) as f32 * font::GLYPH_ADVANCE;
+
+        if let Some(selection) = field.selection() {
+            let (start, end) = (x(char_idx(selection.start)), x(char_idx(selection.end)));
+            let color = [0.3, 0.45, 0.6, 0.9];
+            ui.rect([start - 1.0, bottom + 1.0], [end, top - 1.0], color);
+        }
+        let shown = field
+            .text
+            .chars()
+            .skip(first)
+            .take(fits)
+            .collect::<String>();
+        ui.text([left + PADDING, bottom + PADDING], &shown, [1.0; 4]);
+
+        // the cursor goes in the gap between glyphs
+        let cursor_x = x(cursor) - 1.0;
+        ui.rect(
+            [cursor_x, bottom + 1.0],
+            [cursor_x + 1.0, top - 1.0],
+            [1.0; 4],
+        );
+        self.cursor_spot = Some([cursor_x, bottom]);
+    }
+}
+
+/// the system clipboard, if there is one.
+struct Clipboard(Option<ClipboardContext>);
+
+impl Clipboard {
+    fn new() -> Self {
+        match ClipboardContext::new() {
+            Ok(context) => Self(Some(context)),
+            Err(err) => {
+                log::warn!("the clipboard isn't available: {}", err);
+                Self(None)
+            }
+        }
+    }
+
+    fn get(&mut self) -> Option<String> {
+        let contents = self.0.as_mut()?.get_contents();
+        contents
+            .map_err(|err| log::warn!("failed to paste: {}", err))
+            .ok()
+    }
+
+    fn set(&mut self, text: String) {
+        if let Some(context) = &mut self.0 {
+            if let Err(err) = context.set_contents(text) {
+                log::warn!("failed to copy: {}", err);
+            }
+        }
+    }
+}
+
+/// does whatever pressing `key` does to `field`, other than typing a
+/// character, which comes in separately.
+fn press_key(
+    field: &mut TextField,
+    key: VirtualKeyCode,
+    input: &InputState,
+    clipboard: &mut Clipboard,
+) {
+    let (ctrl, shift) = (input.ctrl(), input.shift());
+    let by_word = |motion, word_motion| if ctrl { word_motion } else { motion };
+    match key {
+        VirtualKeyCode::Left => field.move_cursor(by_word(Motion::Left, Motion::WordLeft), shift),
+        VirtualKeyCode::Right => {
+            field.move_cursor(by_word(Motion::Right, Motion::WordRight), shift)
+        }
+        VirtualKeyCode::Home => field.move_cursor(Motion::Home, shift),
+        VirtualKeyCode::End => field.move_cursor(Motion::End, shift),
+        VirtualKeyCode::Back => field.delete(by_word(Motion::Left, Motion::WordLeft)),
+        VirtualKeyCode::Delete => field.delete(by_word(Motion::Right, Motion::WordRight)),
+        VirtualKeyCode::A if ctrl => field.select_all(),
+        VirtualKeyCode::C if ctrl => {
+            if let Some(text) = field.selected_text() {
+                clipboard.set(text.to_owned());
+            }
+        }
+        VirtualKeyCode::X if ctrl => {
+            if let Some(text) = field.cut() {
+                clipboard.set(text);
+            }
+        }
+        VirtualKeyCode::V if ctrl => {
+            if let Some(text) = clipboard.get() {
+                field.insert(&text);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn edit_focused_field(
+    mut input: ResMut<InputState>,
+    mut text_input: ResMut<TextInput>,
+    mut clipboard: NonSendMut<Clipboard>,
+    mut raw_events: EventReader<RawInputEvent>,
+    mut events: EventWriter<TextInputEvent>,
+) {
+    for event in raw_events.iter() {
+        let event = match event {
+            RawInputEvent::Window(_, event) => event,
+            _ => continue,
+        };
+        let key = match *event {
+            WindowEvent::ReceivedCharacter(ch) => {
+                if let Some((_, field)) = &mut text_input.focused {
+                    field.insert(ch.encode_utf8(&mut [0; 4]));
+                }
+                continue;
+            }
+            WindowEvent::KeyboardInput { input: key, .. } if key.state == ElementState::Pressed => {
+                match key.virtual_keycode {
+                    Some(key) => key,
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+
+        let event = match (key, text_input.focused.take()) {
+            (VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter, Some((owner, field))) => {
+                TextInputEvent::Submitted {
+                    owner,
+                    text: field.text,
+                }
+            }
+            (VirtualKeyCode::Escape, Some((owner, _))) => TextInputEvent::Cancelled { owner },
+            (key, Some((owner, mut field))) => {
+                press_key(&mut field, key, &input, &mut clipboard);
+                text_input.focused = Some((owner, field));
+                continue;
+            }
+            (_, None) => continue,
+        };
+        events.send(event);
+    }
+
+    input.capture_keyboard(text_input.is_any_focused());
+}
+
+/// moves the IME's candidate window next to the focused field's cursor.
+fn place_ime_window(
+    display: NonSend<Rc<Display>>,
+    ui_scale: Res<UiScale>,
+    text_input: Res<TextInput>,
+    mut placed: Local<Option<[f32; 2]>>,
+) {
+    let spot = text_input.focused.as_ref().and(text_input.cursor_spot);
+    if spot == *placed {
+        return;
+    }
+    *placed = spot;
+
+    if let Some([x, y]) = spot {
+        let (width, height) = display.get_framebuffer_dimensions();
+        let pos = PhysicalPosition::new(
+            width as f32 / 2.0 + x * ui_scale.0,
+            height as f32 / 2.0 - y * ui_scale.0,
+        );
+        display.gl_window().window().set_ime_position(pos);
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub struct TextInputLabel;
+
+#[derive(Debug, Default)]
+pub struct TextInputPlugin {}
+
+impl Plugin for TextInputPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<TextInput>();
+        app.insert_non_send_resource(Clipboard::new());
+        app.add_event::<TextInputEvent>();
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            edit_focused_field
+                .system()
+                .label(TextInputLabel)
+                .after(InputLabel),
+        );
+        app.add_system_to_stage(CoreStage::PostUpdate, place_ime_window.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_move_select_and_delete() {
+        let mut field = TextField::new("hello wörld  foo");
+        field.move_cursor(Motion::WordLeft, false);
+        assert_eq!(&field.text()[field.cursor()..], "foo");
+        field.move_cursor(Motion::WordLeft, true);
+        assert_eq!(field.selected_text(), Some("wörld  "));
+
+        // moving without selecting leaves from the side it's moving towards
+        field.move_cursor(Motion::Right, false);
+        assert_eq!(field.selection(), None);
+        assert_eq!(&field.text()[field.cursor()..], "foo");
+
+        field.move_cursor(Motion::Left, false);
+        field.move_cursor(Motion::Left, false);
+        field.delete(Motion::WordLeft);
+        assert_eq!(field.text(), "hello   foo");
+        field.delete(Motion::WordRight);
+        assert_eq!(field.text(), "hello ");
+
+        field.move_cursor(Motion::Home, false);
+        field.move_cursor(Motion::End, true);
+        assert_eq!(field.cut().as_deref(), Some("hello "));
+        assert_eq!((field.text(), field.cursor()), ("", 0));
+        assert_eq!(field.cut(), None);
+    }
+
+    #[test]
+    fn inserted_text_is_cleaned_up_and_kept_short() {
+        let mut field = TextField::new("a long sign").with_max_chars(6);
+        assert_eq!(field.text(), "a long");
+
+        field.move_cursor(Motion::Home, false);
+        field.insert("x\ty");
+        assert_eq!(field.text(), "a long");
+        field.move_cursor(Motion::End, true);
+        field.insert("é\ty\u{8}\nz");
+        assert_eq!(field.text(), "é y z");
+        field.insert("!!");
+        assert_eq!(field.text(), "é y z!");
+
+        field.select_all();
+        field.insert("é");
+        assert_eq!((field.text(), field.cursor()), ("é", 2));
+    }
+}
@@ -0,0 +1,142 @@
+//! texture packs, which replace some or all of the block textures.
+//!
+//! a texture pack is a directory in `resources/texturepacks`, with a `blocks`
+//! directory laid out just like `resources/textures/blocks`. textures are
+//! looked for in the selected pack first, and any that the pack doesn't have
+//! come from the enabled content packs, and then from the base textures.
+//!
+//! block textures can be reloaded while the game is running, either to switch
+//! packs or to pick up edited images. the images are loaded on a background
+//! thread, and the renderer swaps them in once they're all ready, so the game
+//! keeps running with the old textures in the meantime. the first textures are
+//! loaded the same way, while the loading screen is up.
+
+use super::{
+    loader::{self, BlockTextures, TextureLoadError},
+    settings::SettingsFile,
+};
+use notcraft_common::{
+    assets::{AssetLoadProgress, TEXTURES},
+    content_pack::ContentPacks,
+    prelude::*,
+    task::{PoolKind, TaskPools},
+    util::ChannelPair,
+    world::registry::BlockRegistry,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+pub const BASE_TEXTURE_DIR: &str = "resources/textures/blocks";
+pub const TEXTURE_PACK_DIR: &str = "resources/texturepacks";
+
+/// the directories that block textures are looked for in, most preferred
+/// first.
+pub fn texture_layers(pack: Option<&str>, content_packs: &ContentPacks) -> Vec<PathBuf> {
+    let mut layers = vec![];
+    if let Some(pack) = pack {
+        layers.push(Path::new(TEXTURE_PACK_DIR).join(pack).join("blocks"));
+    }
+    // later content packs win, and the base textures come last
+    let content = content_packs.layers(Path::new(BASE_TEXTURE_DIR), "textures/blocks");
+    layers.extend(content.into_iter().rev().map(|(_, dir)| dir));
+    layers
+}
+
+/// the names of every texture pack that's installed, in alphabetical order.
+pub fn available_packs() -> Result<Vec<String>> {
+    let dir = Path::new(TEXTURE_PACK_DIR);
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut packs = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            packs.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    packs.sort();
+    Ok(packs)
+}
+
+/// asks for the block textures to be loaded again, from `pack` if there is
+/// one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReloadTextures {
+    pub pack: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct StreamedTextures {
+    pub pack: Option<String>,
+    pub textures: Result<BlockTextures, TextureLoadError>,
+}
+
+#[derive(Debug, Default)]
+pub struct TextureStreamer {
+    channel: ChannelPair<(u64, StreamedTextures)>,
+    /// counts up for every load that's started, so that a slow load can't
+    /// replace textures from a load that was started after it.
+    started: u64,
+    finished: u64,
+}
+
+impl TextureStreamer {
+    /// the textures from the latest load that's finished since the last time
+    /// this was called.
+    pub fn take_finished(&mut self) -> Option<StreamedTextures> {
+        let mut latest = None;
+        for (generation, streamed) in self.channel.rx.try_iter() {
+            if generation > self.finished {
+                self.finished = generation;
+                latest = Some(streamed);
+            }
+        }
+        latest
+    }
+}
+
+/// asks for the block textures from the selected texture pack to be loaded
+/// when the game starts, and again if they failed and the player wants to try
+/// again.
+pub fn load_textures(
+    settings: Res<SettingsFile>,
+    mut progress: ResMut<AssetLoadProgress>,
+    mut reload_events: EventWriter<ReloadTextures>,
+) {
+    if progress.start(TEXTURES) {
+        let pack = settings.settings().texture_pack.clone();
+        reload_events.send(ReloadTextures { pack });
+    }
+}
+
+pub fn start_texture_loads(
+    registry: Res<Arc<BlockRegistry>>,
+    content_packs: Res<ContentPacks>,
+    pools: Res<TaskPools>,
+    mut streamer: ResMut<TextureStreamer>,
+    mut reload_events: EventReader<ReloadTextures>,
+) {
+    // only the last request matters, since it would replace all the others
+    // anyways.
+    let pack = match reload_events.iter().last() {
+        Some(reload) => reload.pack.clone(),
+        None => return,
+    };
+
+    streamer.started += 1;
+    let generation = streamer.started;
+    let sender = streamer.channel.sender();
+    let registry = Arc::clone(&registry);
+    let layers = texture_layers(pack.as_deref(), &content_packs);
+    pools.spawn(PoolKind::Io, move || {
+        let textures = loader::load_block_textures(&layers, &registry);
+        let streamed = StreamedTextures { pack, textures };
+        if sender.send((generation, streamed)).is_err() {
+            log::debug!("dropping loaded block textures, since the renderer has shut down");
+        }
+    });
+}
@@ -0,0 +1,113 @@
+//! shows [`Notify`] events as toasts stacked in the top right corner of the
+//! screen, which fade out once they've been up for long enough.
+
+use crate::client::{
+    render::{font, renderer::ImmediateUi},
+    window::UiScale,
+};
+use glium::Display;
+use notcraft_common::{
+    notification::{Notify, Severity},
+    prelude::*,
+};
+use std::{
+    collections::VecDeque,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// the most toasts that are shown at once. the oldest ones are dropped to make
+/// room for new ones.
+const MAX_TOASTS: usize = 5;
+/// how long toasts take to fade out at the end of their duration.
+const FADE_DURATION: Duration = Duration::from_millis(500);
+
+const MARGIN: f32 = 4.0;
+const PADDING: f32 = 3.0;
+const STRIPE_WIDTH: f32 = 2.0;
+const TOAST_HEIGHT: f32 = font::GLYPH_HEIGHT + 2.0 * PADDING;
+
+fn severity_color(severity: Severity) -> [f32; 3] {
+    match severity {
+        Severity::Info => [0.4, 0.6, 0.9],
+        Severity::Warning => [0.9, 0.75, 0.3],
+        Severity::Error => [0.9, 0.3, 0.3],
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Toast {
+    notify: Notify,
+    shown_at: Instant,
+}
+
+impl Toast {
+    /// how visible the toast is, from 1 while it's fully shown to 0 once it's
+    /// faded out completely.
+    fn opacity(&self, now: Instant) -> f32 {
+        let remaining = self.notify.duration.saturating_sub(now - self.shown_at);
+        f32::min(1.0, remaining.as_secs_f32() / FADE_DURATION.as_secs_f32())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Toasts {
+    /// newest first.
+    toasts: VecDeque<Toast>,
+}
+
+fn collect_toasts(mut notify_events: EventReader<Notify>, mut toasts: ResMut<Toasts>) {
+    let now = Instant::now();
+    toasts.toasts.retain(|toast| toast.opacity(now) > 0.0);
+
+    for notify in notify_events.iter() {
+        toasts.toasts.push_front(Toast {
+            notify: notify.clone(),
+            shown_at: now,
+        });
+    }
+    toasts.toasts.truncate(MAX_TOASTS);
+}
+
+fn draw_toasts(
+    toasts: Res<Toasts>,
+    display: NonSend<Rc<Display>>,
+    ui_scale: Res<UiScale>,
+    mut ui: ResMut<ImmediateUi>,
+) {
+    let (width, height) = display.get_framebuffer_dimensions();
+    let right = width as f32 / ui_scale.0 / 2.0 - MARGIN;
+    let mut top = height as f32 / ui_scale.0 / 2.0 - MARGIN;
+
+    let now = Instant::now();
+    for toast in toasts.toasts.iter() {
+        let opacity = toast.opacity(now);
+        let text_width = font::text_width(&toast.notify.message);
+        let left = right - text_width - 2.0 * PADDING - STRIPE_WIDTH;
+        let bottom = top - TOAST_HEIGHT;
+
+        let background = [0.05, 0.05, 0.05, 0.8 * opacity];
+        ui.rect([left, bottom], [right, top], background);
+        let [r, g, b] = severity_color(toast.notify.severity);
+        let stripe = [left + STRIPE_WIDTH, top];
+        ui.rect([left, bottom], stripe, [r, g, b, opacity]);
+
+        let text_pos = [left + STRIPE_WIDTH + PADDING, bottom + PADDING];
+        ui.text(text_pos, &toast.notify.message, [1.0, 1.0, 1.0, opacity]);
+
+        top = bottom - MARGIN / 2.0;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ToastPlugin {}
+
+impl Plugin for ToastPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Toasts>();
+        app.add_system(collect_toasts.system());
+        // drawn after everything else in the UI, so that toasts show up on top
+        // of menus.
+        app.add_system_to_stage(CoreStage::PostUpdate, draw_toasts.system());
+    }
+}
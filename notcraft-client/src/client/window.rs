@@ -0,0 +1,227 @@
+//! the game window: whether it's fullscreen, how big it is, and how big the UI
+//! is drawn inside of it.
+//!
+//! [`WindowSettings`] is both the live state of the window and what gets saved
+//! to the settings file. changing it applies the change to the window and
+//! saves it.
+
+use super::{
+    camera::Camera,
+    input::{InputState, RawInputEvent},
+    render::render_scale::RenderScaleSettings,
+    settings::SettingsFile,
+};
+use glium::{
+    glutin::{
+        dpi::PhysicalSize,
+        event::{VirtualKeyCode, WindowEvent},
+        event_loop::EventLoop,
+        window::{Fullscreen, WindowBuilder},
+        ContextBuilder,
+    },
+    Display,
+};
+use notcraft_common::prelude::*;
+use std::rc::Rc;
+
+/// how many pixels tall the screen has to be for each UI unit to be drawn one
+/// pixel bigger, when the UI isn't scaled by hand.
+const PIXELS_PER_UI_STEP: f32 = 270.0;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    /// borderless fullscreen on whichever monitor the window is on.
+    pub fullscreen: bool,
+    /// the size of the window when it isn't fullscreen, in pixels.
+    pub resolution: [u32; 2],
+    /// this only takes effect when the game starts, since the graphics context
+    /// has to be recreated to change it.
+    pub vsync: bool,
+    /// multiplies how big the UI would otherwise be drawn. useful for HiDPI
+    /// screens, where the UI would otherwise come out tiny.
+    pub ui_scale: f32,
+    /// the most frames that are drawn each second, or `None` to draw them as
+    /// fast as possible (or as fast as vsync allows).
+    pub max_fps: Option<u32>,
+    /// the most frames that are drawn each second while the window is in the
+    /// background or minimized.
+    pub background_fps: u32,
+    /// how big the world is drawn compared to the window.
+    pub render_scale: RenderScaleSettings,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            fullscreen: false,
+            resolution: [1280, 720],
+            vsync: true,
+            ui_scale: 1.0,
+            max_fps: None,
+            background_fps: 15,
+            render_scale: Default::default(),
+        }
+    }
+}
+
+/// how many pixels each UI unit takes up on screen. this is always a whole
+/// number, so that UI textures stay crisp.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UiScale(pub f32);
+
+impl UiScale {
+    fn new(settings: &WindowSettings, (_, height): (u32, u32)) -> Self {
+        let automatic = f32::max(1.0, (height as f32 / PIXELS_PER_UI_STEP).floor());
+        Self(f32::max(1.0, (automatic * settings.ui_scale).floor()))
+    }
+}
+
+/// sent when the window changes size, including when it goes in and out of
+/// fullscreen. minimizing the window doesn't count.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WindowResized {
+    pub width: u32,
+    pub height: u32,
+}
+
+fn read_window_events(
+    mut raw_events: EventReader<RawInputEvent>,
+    mut resized_events: EventWriter<WindowResized>,
+) {
+    for event in raw_events.iter() {
+        if let &RawInputEvent::Window(_, WindowEvent::Resized(size)) = event {
+            if size.width > 0 && size.height > 0 {
+                resized_events.send(WindowResized {
+                    width: size.width,
+                    height: size.height,
+                });
+            }
+        }
+    }
+}
+
+fn handle_resize(
+    mut resized_events: EventReader<WindowResized>,
+    mut settings: ResMut<WindowSettings>,
+    mut ui_scale: ResMut<UiScale>,
+    cameras: Query<&mut Camera>,
+) {
+    let WindowResized { width, height } = match resized_events.iter().last() {
+        Some(&event) => event,
+        None => return,
+    };
+
+    cameras.for_each_mut(|mut camera| {
+        camera.projection.set_aspect(width as f32 / height as f32);
+    });
+    *ui_scale = UiScale::new(&settings, (width, height));
+
+    // remember the size that the window was dragged to, but not the size of
+    // the monitor it was made fullscreen on.
+    if !settings.fullscreen && settings.resolution != [width, height] {
+        settings.resolution = [width, height];
+    }
+}
+
+fn toggle_fullscreen(input: Res<InputState>, mut settings: ResMut<WindowSettings>) {
+    if input.key(VirtualKeyCode::F11).is_rising() {
+        settings.fullscreen = !settings.fullscreen;
+    }
+}
+
+/// brings the window in line with its settings whenever they change, and saves
+/// them.
+fn apply_window_settings(
+    display: NonSend<Rc<Display>>,
+    settings: Res<WindowSettings>,
+    mut applied: Local<Option<WindowSettings>>,
+    mut ui_scale: ResMut<UiScale>,
+    mut settings_file: ResMut<SettingsFile>,
+) {
+    let previous = match applied.replace(settings.clone()) {
+        // the window was created with these settings already
+        None => return,
+        Some(previous) if previous == *settings => return,
+        Some(previous) => previous,
+    };
+
+    let gl_window = display.gl_window();
+    let window = gl_window.window();
+    if previous.fullscreen != settings.fullscreen {
+        let fullscreen = match settings.fullscreen {
+            true => Some(Fullscreen::Borderless(window.current_monitor())),
+            false => None,
+        };
+        window.set_fullscreen(fullscreen);
+    }
+
+    let [width, height] = settings.resolution;
+    let size = PhysicalSize::new(width, height);
+    if !settings.fullscreen && window.inner_size() != size {
+        window.set_inner_size(size);
+    }
+
+    if previous.ui_scale != settings.ui_scale {
+        *ui_scale = UiScale::new(&settings, display.get_framebuffer_dimensions());
+    }
+
+    let window_settings = settings.clone();
+    if let Err(err) = settings_file.update(|file| file.window = window_settings) {
+        log::error!("failed to save window settings: {}", err);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WindowingPlugin {}
+
+impl Plugin for WindowingPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let settings = app
+            .world()
+            .get_resource::<SettingsFile>()
+            .expect("`WindowingPlugin` added before `SettingsFile` was inserted!")
+            .settings()
+            .window
+            .clone();
+
+        let [width, height] = settings.resolution;
+        let event_loop = EventLoop::new();
+        let mut window = WindowBuilder::new()
+            .with_title("Notcraft™")
+            .with_inner_size(PhysicalSize::new(width, height));
+        if settings.fullscreen {
+            window = window.with_fullscreen(Some(Fullscreen::Borderless(None)));
+        }
+        let graphics_context = ContextBuilder::new()
+            .with_depth_buffer(24)
+            .with_vsync(settings.vsync);
+        let display = Rc::new(Display::new(window, graphics_context, &event_loop).unwrap());
+
+        app.insert_resource(UiScale::new(
+            &settings,
+            display.get_framebuffer_dimensions(),
+        ));
+        app.insert_resource(settings);
+        app.insert_non_send_resource(event_loop);
+        app.insert_non_send_resource(display);
+
+        app.add_event::<WindowResized>();
+        app.add_system(read_window_events.system().label(WindowEventsLabel));
+        app.add_system(
+            handle_resize
+                .system()
+                .label(WindowLabel)
+                .after(WindowEventsLabel),
+        );
+        app.add_system(toggle_fullscreen.system().label(WindowLabel));
+        app.add_system(apply_window_settings.system().after(WindowLabel));
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+struct WindowEventsLabel;
+
+/// systems that change the window settings.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+struct WindowLabel;
@@ -7,44 +7,88 @@ pub mod client;
 pub mod total_float;
 
 use crate::client::{
-    camera::{ActiveCamera, Camera},
+    camera::{ActiveCamera, Camera, CameraPlugin, FovModifiers},
+    container::ContainerUiPlugin,
+    crafting::{CraftingUi, CraftingUiPlugin},
+    free_camera::{CameraTransition, FreeCamera},
     input::{keys, DigitalInput, InputPlugin, InputState, RawInputEvent},
     render::{
-        mesher::{ChunkMesherPlugin, MesherMode},
-        renderer::{add_debug_box, DebugBox, RenderPlugin},
+        mesher::{generation::LightingType, ChunkMesherPlugin, MesherMode},
+        renderer::{
+            add_debug_box, ActiveHealthBar, AntiAliasing, DebugBox, DynamicLight, HdrSettings,
+            RenderPlugin, ShadowSettings, Tonemapper,
+        },
     },
 };
 use audio_pool::{load_audio, RandomizedAudioPools};
-use bevy_app::{AppExit, Events};
+use bevy_app::{AppExit, Events, ManualEventReader};
 use bevy_core::CorePlugin;
+use bevy_ecs::system::SystemParam;
 use client::{
     audio::{
         ActiveAudioListener, AudioEvent, AudioListener, AudioPlugin, AudioState,
         ParameterizedSource,
     },
-    render::renderer::{immediate_draw_box_edges, ImmediateLines, LineCanvas, RenderStage},
+    frame_limiter::{frame_rate_cap, FrameLimiter, WindowActivity, SPIN_DURATION},
+    replay::{InputRecorder, InputReplay, ReplayMode, ReplayPlugin, RECORDING_TIME_STEP},
+    render::{
+        render_scale::RenderScalePlugin,
+        renderer::{immediate_draw_box_edges, ImmediateLines, LineCanvas, RenderStage},
+    },
+    settings::SettingsFile,
+    window::{WindowSettings, WindowingPlugin},
 };
 use glium::{
     glutin::{
         event::{ButtonId, Event, ModifiersState, VirtualKeyCode, WindowEvent},
         event_loop::{ControlFlow, EventLoop},
-        window::WindowBuilder,
-        ContextBuilder,
     },
     Display,
 };
-use nalgebra::{point, Point3, UnitQuaternion, Vector2, Vector3};
+use nalgebra::{point, Point3, Vector3};
 use notcraft_common::{
     aabb::Aabb,
+    assets::{self, AssetLoadProgress},
+    clock::{GameTime, GameTimePlugin, TimeStep},
+    console::ConsolePlugin,
+    content_pack::{ContentPackPlugin, ContentPacks, CONTENT_PACK_DIR},
+    crafting::CraftingPlugin,
+    explosion::{self, ExplosionPlugin},
+    debug::recording,
+    game_mode::{GameMode, GameModePlugin},
+    game_state::{playing, GameState},
+    health::{DeathEvent, Health, HealthPlugin},
+    inventory::{Inventory, ItemStack},
+    item_drop::{ItemDropEvent, ItemDropPlugin},
+    lang::{self, tr},
+    mob::MobPlugin,
+    movement::{MovementApplication, MovementCommands, MovementInput, MovementPlugin},
+    net::remote_player::RemotePlayerPlugin,
+    notification::{NotificationPlugin, Notify},
+    pathfinding::PathfindingPlugin,
     physics::{AabbCollider, CollisionPlugin, PhysicsPlugin, RigidBody},
     prelude::*,
+    profiler::{ProfileSystemExt, ProfilerPlugin},
+    saved_components::SavedComponents,
+    scripting::{ScriptHost, ScriptingPlugin},
+    targeting::{CurrentTarget, TargetingConfig},
+    task::{PoolSize, TaskPoolPlugin, TaskPools},
+    teleport::{TeleportEvent, TeleportPlugin},
+    tool,
     transform::Transform,
-    try_system,
+    stats::{StatsPlugin, StatsTracked},
+    weather::WeatherPlugin,
     world::{
         self,
+        block_entity::BlockEntity,
         chunk::ChunkAccess,
-        registry::{BlockId, AIR_BLOCK},
-        trace_ray, BlockPos, DynamicChunkLoader, Ray3, RaycastHit, WorldPlugin,
+        lighting::BLOCK_LIGHT_MASK,
+        persistence::{SaveEvent, SavedPlayer, WorldPersistence},
+        pregen::{self, MAX_PREGEN_RADIUS},
+        registry::{load_registry, BlockId, BlockRegistry, AIR_BLOCK},
+        repro,
+        spawn::WorldSpawn,
+        BlockPos, DynamicChunkLoader, Ray3, RaycastHit, WorldPlugin,
     },
     Axis, Side,
 };
@@ -54,80 +98,275 @@ use rand::{
 };
 use std::{
     collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
     rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 use structopt::StructOpt;
+use total_float::TotalFloat;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct PlayerController {
     player: Entity,
+    flying: bool,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum CameraControllerMode {
     Follow(Entity),
     Static,
+    /// flying around on its own, see [`client::free_camera`].
+    Free,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct CameraController {
     camera: Entity,
     mode: CameraControllerMode,
+    /// where the current mode puts the camera. the camera only lags behind
+    /// this while it's transitioning from one mode to another.
+    view: Transform,
+    transition: Option<CameraTransition>,
 }
 
 fn camera_controller(
+    time: Res<GameTime>,
     input: Res<InputState>,
     mut camera_controller: ResMut<CameraController>,
     player_controller: ResMut<PlayerController>,
-    // world: &mut SubWorld,
+    mut free_camera: ResMut<FreeCamera>,
     mut transform_query: Query<&mut Transform>,
 ) {
-    let mut update_camera_transform =
-        |camera_controller: &mut CameraController, entity| match transform_query
-            .get_mut(entity)
-            .ok()
-            .as_deref()
-            .copied()
-        {
-            None => camera_controller.mode = CameraControllerMode::Static,
-            Some(player_transform) => {
-                let mut camera_transform =
-                    transform_query.get_mut(camera_controller.camera).unwrap();
-                *camera_transform = player_transform.translated(&nalgebra::vector![0.0, 0.5, 0.0]);
-            }
-        };
+    let current = match transform_query.get_mut(camera_controller.camera) {
+        Ok(transform) => *transform,
+        Err(_) => return,
+    };
+
+    let pressed = |key| {
+        input
+            .key(key)
+            .require_modifiers(ModifiersState::CTRL | ModifiersState::SHIFT)
+            .is_rising()
+    };
+    let mode = if pressed(VirtualKeyCode::S) {
+        Some(CameraControllerMode::Static)
+    } else if pressed(VirtualKeyCode::F) {
+        Some(CameraControllerMode::Follow(player_controller.player))
+    } else if pressed(VirtualKeyCode::D) {
+        Some(CameraControllerMode::Free)
+    } else {
+        None
+    };
+
+    // the new mode starts out wherever the camera is now, which is all that
+    // the static and free cameras need. following snaps the view back to the
+    // player, and the transition eases the camera over to it.
+    if let Some(mode) = mode.filter(|&mode| mode != camera_controller.mode) {
+        log::info!("camera mode: {:?}", mode);
+        camera_controller.mode = mode;
+        camera_controller.view = current;
+        camera_controller.transition = Some(CameraTransition::new(current));
+        free_camera.stop();
+    }
 
-    match camera_controller.mode {
-        CameraControllerMode::Follow(entity) => {
-            update_camera_transform(&mut camera_controller, entity)
+    if let CameraControllerMode::Follow(entity) = camera_controller.mode {
+        match transform_query.get_mut(entity) {
+            Ok(player) => camera_controller.view = player.translated(&vector![0.0, 0.5, 0.0]),
+            Err(_) => camera_controller.mode = CameraControllerMode::Static,
         }
-        CameraControllerMode::Static => {}
     }
 
-    if input
-        .key(VirtualKeyCode::S)
-        .require_modifiers(ModifiersState::CTRL | ModifiersState::SHIFT)
-        .is_rising()
-    {
-        update_camera_transform(&mut camera_controller, player_controller.player);
-        camera_controller.mode = CameraControllerMode::Static;
+    let controller = &mut *camera_controller;
+    let view = controller.view;
+    let transitioning = match controller.transition.as_mut() {
+        Some(transition) => transition.step(time.delta_seconds(), &view),
+        None => None,
+    };
+    if transitioning.is_none() {
+        controller.transition = None;
+    }
+    if let Ok(mut transform) = transform_query.get_mut(controller.camera) {
+        *transform = transitioning.unwrap_or(view);
     }
+}
 
-    if input
-        .key(VirtualKeyCode::F)
-        .require_modifiers(ModifiersState::CTRL | ModifiersState::SHIFT)
-        .is_rising()
-    {
-        camera_controller.mode = CameraControllerMode::Follow(player_controller.player);
+/// flies the camera around while it's in [`CameraControllerMode::Free`].
+fn free_camera_controller(
+    time: Res<GameTime>,
+    input: Res<InputState>,
+    mut camera_controller: ResMut<CameraController>,
+    mut free_camera: ResMut<FreeCamera>,
+    mut access: ResMut<ChunkAccess>,
+) {
+    if camera_controller.mode != CameraControllerMode::Free {
+        return;
     }
+
+    free_camera.scroll(input.scroll_delta());
+    let axis = |positive, negative| {
+        let positive = input.key(positive).is_pressed() as i32;
+        (positive - input.key(negative).is_pressed() as i32) as f32
+    };
+    let direction = vector![
+        axis(keys::RIGHT, keys::LEFT),
+        axis(keys::UP, keys::DOWN),
+        axis(keys::BACKWARD, keys::FORWARD)
+    ];
+    let view = &mut camera_controller.view;
+    free_camera.fly(&mut access, view, direction, time.delta_seconds());
 }
 
-#[derive(Copy, Clone, Debug)]
+/// widens the camera's field of view while the player sprints, narrows it
+/// while the zoom key is held, and sees as far as chunks are loaded around the
+/// player.
+fn camera_fov(
+    input: Res<InputState>,
+    state: Res<GameState>,
+    camera_controller: Res<CameraController>,
+    player_controller: Res<PlayerController>,
+    players: Query<(&MovementCommands, &DynamicChunkLoader)>,
+    mut cameras: Query<&mut Camera>,
+) {
+    let mut camera = match cameras.get_mut(camera_controller.camera) {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let player = players.get(player_controller.player).ok();
+
+    let following = matches!(camera_controller.mode, CameraControllerMode::Follow(_));
+    let sprinting = player
+        .and_then(|(commands, _)| commands.latest())
+        .is_some_and(|command| following && command.input.sprint);
+    let zoom_key = input
+        .key(keys::ZOOM)
+        .require_modifiers(ModifiersState::empty());
+    camera.fov_modifiers = FovModifiers {
+        sprinting,
+        zooming: *state == GameState::Playing && zoom_key.is_pressed(),
+    };
+    camera.render_distance = player.map(|(_, loader)| loader.load_radius);
+}
+
+#[derive(Clone, Debug)]
 pub struct TerrainManipulator {
     start_pos: Option<BlockPos>,
     start_button: Option<ButtonId>,
+    repeat: ButtonRepeat,
+    fill_mode: AreaFillMode,
     // TODO: certainly not this!!
-    block_name: &'static str,
+    block_name: String,
+    /// the container that was right-clicked this frame, if any.
+    clicked_container: Option<BlockPos>,
+    /// the sign that was right-clicked this frame, if any.
+    clicked_sign: Option<BlockPos>,
+    /// the block being dug out in survival, and when digging it started.
+    digging: Option<(BlockPos, Instant)>,
+    /// when blocks can be broken or placed again, after breaking or placing a
+    /// block with a cooldown.
+    next_break: Option<Instant>,
+    next_place: Option<Instant>,
+}
+
+/// the strategy used to fill (or clear) a selected area.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum AreaFillMode {
+    /// every block within the selection.
+    Solid,
+    /// only the blocks on the faces of the selection.
+    Hollow,
+    /// the faces of the selection, excluding its top and bottom.
+    Walls,
+    /// only the blocks along the edges of the selection.
+    Outline,
+    /// a straight line of blocks between the two selected blocks.
+    Line,
+}
+
+impl AreaFillMode {
+    fn name(self) -> &'static str {
+        match self {
+            AreaFillMode::Solid => "solid",
+            AreaFillMode::Hollow => "hollow",
+            AreaFillMode::Walls => "walls",
+            AreaFillMode::Outline => "outline",
+            AreaFillMode::Line => "line",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            AreaFillMode::Solid => AreaFillMode::Hollow,
+            AreaFillMode::Hollow => AreaFillMode::Walls,
+            AreaFillMode::Walls => AreaFillMode::Outline,
+            AreaFillMode::Outline => AreaFillMode::Line,
+            AreaFillMode::Line => AreaFillMode::Solid,
+        }
+    }
+}
+
+/// tunables for how the player interacts with the world. how far they can
+/// reach is part of the [`TargetingConfig`] instead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameplayConfig {
+    /// how long a mouse button has to be held before its action starts
+    /// repeating.
+    pub repeat_delay: Duration,
+    /// the time between repeated actions while a mouse button is held.
+    pub repeat_interval: Duration,
+}
+
+impl Default for GameplayConfig {
+    fn default() -> Self {
+        Self {
+            repeat_delay: Duration::from_millis(300),
+            repeat_interval: Duration::from_millis(100),
+        }
+    }
+}
+
+/// tracks a held mouse button so that its action can be repeated without
+/// having to click over and over again.
+#[derive(Copy, Clone, Debug, Default)]
+struct ButtonRepeat {
+    button: Option<ButtonId>,
+    next_fire: Option<Instant>,
+}
+
+impl ButtonRepeat {
+    /// returns true when `button` was just pressed, and then once per repeat
+    /// interval after the initial delay has passed while it stays held.
+    fn fire(
+        &mut self,
+        input: &InputState,
+        button: ButtonId,
+        config: &GameplayConfig,
+        now: Instant,
+    ) -> bool {
+        let key = input.key(DigitalInput::Button(button));
+        if key.is_rising() {
+            self.button = Some(button);
+            self.next_fire = Some(now + config.repeat_delay);
+            return true;
+        }
+
+        if self.button != Some(button) {
+            return false;
+        }
+
+        if !key.is_pressed() {
+            *self = Self::default();
+            return false;
+        }
+
+        match self.next_fire {
+            Some(next) if now >= next => {
+                self.next_fire = Some(now + config.repeat_interval);
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
 fn make_ray(transform: &Transform, reference: &Vector3<f32>) -> Ray3<f32> {
@@ -156,6 +395,36 @@ fn iter_blocks_in(a: BlockPos, b: BlockPos, mut func: impl FnMut(BlockPos)) {
     }
 }
 
+fn iter_fill_blocks(mode: AreaFillMode, a: BlockPos, b: BlockPos, mut func: impl FnMut(BlockPos)) {
+    if mode == AreaFillMode::Line {
+        world::trace_line(a, b, func);
+        return;
+    }
+
+    let xmin = i32::min(a.x, b.x);
+    let ymin = i32::min(a.y, b.y);
+    let zmin = i32::min(a.z, b.z);
+    let xmax = i32::max(a.x, b.x);
+    let ymax = i32::max(a.y, b.y);
+    let zmax = i32::max(a.z, b.z);
+    iter_blocks_in(a, b, |pos| {
+        let on_x = pos.x == xmin || pos.x == xmax;
+        let on_y = pos.y == ymin || pos.y == ymax;
+        let on_z = pos.z == zmin || pos.z == zmax;
+        let include = match mode {
+            AreaFillMode::Solid => true,
+            AreaFillMode::Hollow => on_x || on_y || on_z,
+            AreaFillMode::Walls => on_x || on_z,
+            // edges are where at least two of the selection's faces meet
+            AreaFillMode::Outline => on_x as u8 + on_y as u8 + on_z as u8 >= 2,
+            AreaFillMode::Line => unreachable!(),
+        };
+        if include {
+            func(pos);
+        }
+    });
+}
+
 fn box_enclosing(a: BlockPos, b: BlockPos) -> Aabb {
     let xmin = i32::min(a.x, b.x);
     let ymin = i32::min(a.y, b.y);
@@ -179,11 +448,12 @@ fn terrain_manipulation_area(
         if start_button == 1 {
             {
                 let mut canvas = ctx.lines.start_default();
-                draw_selection_box(&mut canvas, start_pos, hit.pos, [1.0, 0.2, 0.2, 0.8]);
+                let color = [1.0, 0.2, 0.2, 0.8];
+                draw_fill_selection(&mut canvas, ctx.manip.fill_mode, start_pos, hit.pos, color);
             }
 
             if input.key(DigitalInput::Button(1)).is_falling() {
-                iter_blocks_in(start_pos, hit.pos, |pos| {
+                iter_fill_blocks(ctx.manip.fill_mode, start_pos, hit.pos, |pos| {
                     ctx.set_block(pos, AIR_BLOCK);
                 });
                 ctx.manip.start_pos = None;
@@ -204,12 +474,13 @@ fn terrain_manipulation_area(
 
             {
                 let mut canvas = ctx.lines.start_default();
-                draw_selection_box(&mut canvas, start_pos, end_pos, [0.2, 0.2, 1.0, 0.8]);
+                let color = [0.2, 0.2, 1.0, 0.8];
+                draw_fill_selection(&mut canvas, ctx.manip.fill_mode, start_pos, end_pos, color);
             }
 
             if input.key(DigitalInput::Button(3)).is_falling() {
-                let id = ctx.access.registry().lookup(ctx.manip.block_name);
-                iter_blocks_in(start_pos, end_pos, |pos| {
+                let id = ctx.held_block(hit.side.unwrap_or(Side::Top));
+                iter_fill_blocks(ctx.manip.fill_mode, start_pos, end_pos, |pos| {
                     ctx.set_block(pos, id);
                 });
                 ctx.manip.start_pos = None;
@@ -338,8 +609,8 @@ fn terrain_manipulation_build_to_me(
     hit: &RaycastHit,
     ctx: &mut TerrainManipulationContext,
 ) {
-    let id = ctx.access.registry().lookup(ctx.manip.block_name);
     if let Some(side) = hit.side {
+        let id = ctx.held_block(side);
         let offset = side.normal::<i32>();
         let start_pos = BlockPos {
             x: hit.pos.x + offset.x,
@@ -385,8 +656,40 @@ fn terrain_manipulation_single(
         draw_selection_box(&mut canvas, hit.pos, hit.pos, [1.0, 0.2, 0.2, 0.8]);
     }
 
-    if input.key(DigitalInput::Button(1)).is_rising() {
-        ctx.set_block(hit.pos, AIR_BLOCK);
+    // right-clicking a container opens it instead of placing a block against it
+    let right_click = input.key(DigitalInput::Button(3)).is_rising();
+    if right_click && matches!(ctx.access.block_entity(hit.pos), Some(BlockEntity::Chest(_))) {
+        ctx.manip.clicked_container = Some(hit.pos);
+        return;
+    }
+    // ...and right-clicking a sign edits what's written on it
+    if right_click && matches!(ctx.access.block_entity(hit.pos), Some(BlockEntity::Sign(_))) {
+        ctx.manip.clicked_sign = Some(hit.pos);
+        return;
+    }
+    // ...and so does right-clicking a block that does something, like a door,
+    // or that a script gave something to do
+    let scripted = right_click && ctx.scripts.interact(ctx.access, hit.pos);
+    if scripted || (right_click && world::behavior::interact(ctx.access, hit.pos)) {
+        return;
+    }
+    // ...or lighting an explosive while holding something that can light it
+    let held = ctx.access.registry().lookup(&ctx.manip.block_name);
+    let igniter = ctx.access.registry().get(held).igniter();
+    if right_click && igniter && explosion::ignite(ctx.access, hit.pos) {
+        return;
+    }
+
+    let (config, now) = (ctx.config, ctx.now);
+    let cooled_down = |next: Option<Instant>| !matches!(next, Some(next) if now < next);
+    if !cooled_down(ctx.manip.next_break) {
+        ctx.manip.digging = None;
+    } else if ctx.game_mode.instant_break() {
+        if ctx.manip.repeat.fire(input, 1, config, now) {
+            ctx.break_block(hit.pos, true);
+        }
+    } else {
+        dig(input, hit.pos, ctx);
     }
 
     if let Some(side) = hit.side {
@@ -402,13 +705,58 @@ fn terrain_manipulation_single(
             draw_selection_box(&mut canvas, offset, offset, [0.2, 0.2, 1.0, 0.8]);
         }
 
-        if input.key(DigitalInput::Button(3)).is_rising() {
-            let id = ctx.access.registry().lookup(ctx.manip.block_name);
-            ctx.set_block(offset, id);
+        let place = ctx.manip.repeat.fire(input, 3, config, now);
+        if place && cooled_down(ctx.manip.next_place) {
+            let id = ctx.held_block(side);
+            if ctx.set_block(offset, id) {
+                let cooldown = ctx.access.registry().get(id).place_cooldown();
+                ctx.manip.next_place = Some(now + Duration::from_secs_f32(cooldown));
+            }
         }
     }
 }
 
+/// digs at the block at `pos` for as long as the break button is held, breaking
+/// it once it's been dug at for long enough.
+fn dig(input: &InputState, pos: BlockPos, ctx: &mut TerrainManipulationContext) {
+    if !input.key(DigitalInput::Button(1)).is_pressed() {
+        ctx.manip.digging = None;
+        return;
+    }
+
+    // looking at a different block starts over
+    let start = match ctx.manip.digging {
+        Some((digging, start)) if digging == pos => start,
+        _ => ctx.now,
+    };
+    let elapsed = (ctx.now - start).as_secs_f32();
+
+    let registry = Arc::clone(ctx.access.registry());
+    let block = registry.get(ctx.access.block(pos).unwrap_or(AIR_BLOCK));
+    let held = registry.get(registry.lookup(&ctx.manip.block_name)).tool();
+    let time = tool::break_time(block.hardness(), block.required_tool(), held);
+    let progress = elapsed / time.seconds;
+    if progress >= 1.0 || time.seconds == 0.0 {
+        ctx.break_block(pos, time.drops);
+        ctx.manip.digging = None;
+        return;
+    }
+    ctx.manip.digging = Some((pos, start));
+
+    // a box that shrinks in towards the middle of the block as it's dug out
+    let inset = 0.5 * progress;
+    let origin = pos.origin();
+    let mut canvas = ctx.lines.start_default();
+    canvas.color([1.0, 0.8, 0.2, 0.8]).width(2.0);
+    immediate_draw_box_edges(
+        &mut canvas,
+        &Aabb {
+            min: point![origin.x + inset, origin.y + inset, origin.z + inset],
+            max: point![origin.x + 1.0 - inset, origin.y + 1.0 - inset, origin.z + 1.0 - inset],
+        },
+    );
+}
+
 fn draw_selection_box(canvas: &mut LineCanvas, start: BlockPos, end: BlockPos, color: [f32; 4]) {
     let aabb = box_enclosing(start, end);
 
@@ -473,38 +821,264 @@ fn draw_selection_box(canvas: &mut LineCanvas, start: BlockPos, end: BlockPos, c
     }
 }
 
+fn draw_fill_selection(
+    canvas: &mut LineCanvas,
+    mode: AreaFillMode,
+    start: BlockPos,
+    end: BlockPos,
+    color: [f32; 4],
+) {
+    match mode {
+        // a box enclosing a diagonal line isn't very helpful for seeing which blocks will
+        // actually be affected, so we outline each block individually.
+        AreaFillMode::Line => world::trace_line(start, end, |pos| {
+            draw_selection_box(canvas, pos, pos, color);
+        }),
+        _ => draw_selection_box(canvas, start, end, color),
+    }
+}
+
 struct TerrainManipulationContext<'a> {
     access: &'a mut ChunkAccess,
     manip: &'a mut TerrainManipulator,
     transform: &'a Transform,
     // collider: &'a AabbCollider,
+    config: &'a GameplayConfig,
+    now: Instant,
+    game_mode: GameMode,
+    scripts: &'a ScriptHost,
+    /// where placed blocks come from, when the game mode uses them up.
+    inventory: Option<&'a mut Inventory>,
     broken_blocks: &'a mut HashMap<BlockId, HashSet<BlockPos>>,
+    /// broken blocks that don't end up in the inventory, because they weren't
+    /// broken with the tool they need.
+    undropped: &'a mut HashSet<BlockPos>,
+    placed_blocks: &'a mut HashMap<BlockId, HashSet<BlockPos>>,
     lines: &'a mut ImmediateLines,
+    /// items dropped by broken containers.
+    drops: &'a mut Vec<ItemDropEvent>,
 }
 
 impl<'a> TerrainManipulationContext<'a> {
-    fn set_block(&mut self, pos: BlockPos, id: BlockId) {
+    /// the block that gets placed against the `clicked` side of another block,
+    /// turned to face the right way if it can be.
+    fn held_block(&self, clicked: Side) -> BlockId {
+        let registry = self.access.registry();
+        let id = registry.lookup(&self.manip.block_name);
+        let yaw = self.transform.rotation.yaw;
+        registry.get(id).placed(clicked, yaw)
+    }
+
+    /// breaks the block at `pos`, starting its break cooldown. it's only
+    /// added to the inventory if it `drops`.
+    fn break_block(&mut self, pos: BlockPos, drops: bool) {
+        let prev = self.access.block(pos).unwrap_or(AIR_BLOCK);
+        if !self.set_block(pos, AIR_BLOCK) {
+            return;
+        }
+        if !drops {
+            self.undropped.insert(pos);
+        }
+        let cooldown = self.access.registry().get(prev).break_cooldown();
+        self.manip.next_break = Some(self.now + Duration::from_secs_f32(cooldown));
+    }
+
+    /// returns whether the block at `pos` was changed to `id`.
+    fn set_block(&mut self, pos: BlockPos, id: BlockId) -> bool {
         if let Some(prev) = self.access.block(pos) {
+            // tools are only for holding
+            if self.access.registry().get(id).tool().is_some() {
+                return false;
+            }
+            if id != AIR_BLOCK && id != prev && self.game_mode.consumes_blocks() {
+                let name = self.access.registry().name(id);
+                let taken = match &mut self.inventory {
+                    Some(inventory) => inventory.remove(name, 1),
+                    None => false,
+                };
+                if !taken {
+                    return false;
+                }
+            }
             if id == AIR_BLOCK && id != prev {
                 self.broken_blocks.entry(prev).or_default().insert(pos);
             }
+            if id != AIR_BLOCK && id != prev {
+                self.placed_blocks.entry(id).or_default().insert(pos);
+            }
+            if id != prev {
+                if let Some(BlockEntity::Chest(items)) = self.access.block_entity(pos) {
+                    let middle = Point3::from(pos.origin()) + vector![0.5, 0.5, 0.5];
+                    for (item, count) in items.iter() {
+                        let stack = ItemStack {
+                            item: item.into(),
+                            count,
+                        };
+                        self.drops.push(ItemDropEvent { pos: middle, stack });
+                    }
+                }
+            }
             // TODO: prevent placing blocks that would collide with any entity colliders
             self.access.set_block(pos, id);
+            return id != prev;
         }
+        false
     }
 }
 
-fn terrain_manipulation(
+#[derive(SystemParam)]
+pub struct PlayerInventory<'a> {
+    player_controller: Res<'a, PlayerController>,
+    inventories: Query<'a, &'static mut Inventory>,
+    game_modes: Query<'a, &'static GameMode>,
+    /// broken blocks are dropped instead of going straight into the
+    /// inventory, and picked up from there.
+    drop_events: EventWriter<'a, ItemDropEvent>,
+}
+
+impl<'a> PlayerInventory<'a> {
+    fn get(&mut self) -> Option<Mut<'_, Inventory>> {
+        self.inventories.get_mut(self.player_controller.player).ok()
+    }
+
+    /// the game mode the player is in, which decides whether placing blocks
+    /// uses them up.
+    fn game_mode(&self) -> GameMode {
+        let game_mode = self.game_modes.get(self.player_controller.player);
+        game_mode.copied().unwrap_or_default()
+    }
+}
+
+/// the world, along with the scripts that can give blocks something to do when
+/// they're clicked.
+#[derive(SystemParam)]
+pub struct ScriptedWorld<'a> {
+    access: ResMut<'a, ChunkAccess>,
+    scripts: Res<'a, ScriptHost>,
+}
+
+/// everything that decides how the player reaches out and changes the world.
+#[derive(SystemParam)]
+pub struct InteractionConfig<'a> {
+    gameplay: Res<'a, GameplayConfig>,
+    target: Res<'a, CurrentTarget>,
+}
+
+#[derive(SystemParam)]
+pub struct BlockSounds<'a> {
+    events: EventWriter<'a, AudioEvent>,
+    pools: Res<'a, RandomizedAudioPools>,
+}
+
+fn switch_manipulator_modes(
     input: Res<InputState>,
+    query: Query<&mut TerrainManipulator>,
+    mut notify_events: EventWriter<Notify>,
+) {
+    query.for_each_mut(|mut manip| {
+        if input.key(VirtualKeyCode::Q).is_rising() {
+            manip.block_name = match &*manip.block_name {
+                "debug_glow_block" => "stone",
+                "stone" => "ladder",
+                "ladder" => "sign",
+                "sign" => "chest",
+                "chest" => "door",
+                "door" => "trapdoor",
+                "trapdoor" => "wire",
+                "wire" => "lever",
+                "lever" => "lamp",
+                "lamp" => "tnt",
+                "tnt" => "flint",
+                "flint" => "polished_stone",
+                "polished_stone" => "ice",
+                "ice" => "slime",
+                "slime" => "soul_sand",
+                "soul_sand" => "log",
+                "log" => "furnace",
+                "furnace" => "wooden_pickaxe",
+                "wooden_pickaxe" => "stone_pickaxe",
+                _ => "debug_glow_block",
+            }
+            .into();
+
+            log::info!("switched block to {}", manip.block_name);
+            let message = tr!("notify.block_switched", manip.block_name);
+            notify_events.send(Notify::info(message));
+        }
+
+        if input.key(VirtualKeyCode::R).is_rising() {
+            manip.fill_mode = manip.fill_mode.next();
+            log::info!("switched area fill mode to {:?}", manip.fill_mode);
+            let message = tr!("notify.fill_mode_switched", manip.fill_mode.name());
+            notify_events.send(Notify::info(message));
+        }
+    });
+}
+
+/// middle-clicking a block switches to placing that kind of block, as long as
+/// the player has some of it to place.
+fn pick_target_block(
+    input: Res<InputState>,
+    target: Res<CurrentTarget>,
+    mut access: ResMut<ChunkAccess>,
+    mut player_inventory: PlayerInventory,
+    query: Query<&mut TerrainManipulator>,
+    mut notify_events: EventWriter<Notify>,
+) {
+    if !input.key(DigitalInput::Button(2)).is_rising() {
+        return;
+    }
+    let id = match target.0.and_then(|hit| access.block(hit.pos)) {
+        Some(id) => id,
+        None => return,
+    };
+
+    // every state of a block has the block's name, so picking e.g. an open
+    // door picks the door itself
+    let registry = Arc::clone(access.registry());
+    let block = registry.get(id);
+    if player_inventory.game_mode().consumes_blocks() {
+        let inventory = player_inventory.get();
+        if inventory.map_or(0, |inventory| inventory.count(block.name())) == 0 {
+            let message = tr!("notify.pick_missing", block.display_name());
+            notify_events.send(Notify::info(message));
+            return;
+        }
+    }
+
+    query.for_each_mut(|mut manip| manip.block_name = block.name().into());
+    log::info!("picked {}", block.name());
+    let message = tr!("notify.block_picked", block.display_name());
+    notify_events.send(Notify::info(message));
+}
+
+/// traces what the player is looking at, once a frame, for everything that
+/// wants to know.
+fn update_current_target(
+    targeting: Res<TargetingConfig>,
     mut access: ResMut<ChunkAccess>,
+    mut current_target: ResMut<CurrentTarget>,
+    query: Query<&Transform, With<TerrainManipulator>>,
+) {
+    let ray = query
+        .iter()
+        .next()
+        .map(|transform| make_ray(transform, &-Vector3::z()));
+    current_target.0 = ray.and_then(|ray| targeting.target(&mut access, ray));
+}
+
+fn terrain_manipulation(
+    input: Res<InputState>,
+    config: InteractionConfig,
+    scripted_world: ScriptedWorld,
     mut query: Query<(
         &Transform,
         // &AabbCollider,
         &mut TerrainManipulator,
     )>,
     mut lines: ResMut<ImmediateLines>,
-    mut audio_events: EventWriter<AudioEvent>,
-    audio_pools: Res<RandomizedAudioPools>,
+    mut block_sounds: BlockSounds,
+    mut player_inventory: PlayerInventory,
 ) {
     // transform: &Transform,
     // // collider: &AabbCollider,
@@ -519,27 +1093,41 @@ fn terrain_manipulation(
     // button 2 - middle click
     // button 3 - right click
 
+    let ScriptedWorld {
+        mut access,
+        scripts,
+    } = scripted_world;
+    let now = Instant::now();
     let mut broken_blocks = HashMap::default();
+    let mut undropped = HashSet::default();
+    let mut placed_blocks = HashMap::default();
+    let mut drops = vec![];
+    let game_mode = player_inventory.game_mode();
+    let mut inventory = player_inventory.get();
     query.for_each_mut(|(transform, mut manip)| {
-        if input.key(VirtualKeyCode::Q).is_rising() {
-            manip.block_name = match manip.block_name {
-                "debug_glow_block" => "stone",
-                _ => "debug_glow_block",
-            };
-
-            log::info!("switched block to {}", manip.block_name);
-        }
-
-        if let Some(hit) = trace_ray(&mut access, make_ray(transform, &-Vector3::z()), 100.0) {
+        manip.clicked_container = None;
+        manip.clicked_sign = None;
+        if let Some(hit) = config.target.0 {
             let mut ctx = TerrainManipulationContext {
                 access: &mut access,
                 manip: &mut manip,
                 transform,
+                config: &config.gameplay,
+                now,
+                game_mode,
+                scripts: &scripts,
+                inventory: inventory.as_deref_mut(),
                 broken_blocks: &mut broken_blocks,
+                undropped: &mut undropped,
+                placed_blocks: &mut placed_blocks,
                 lines: &mut lines,
+                drops: &mut drops,
             };
 
-            if input.key(VirtualKeyCode::E).is_rising() {
+            // the tools for changing lots of blocks at once are only for
+            // creative mode
+            let instant = ctx.game_mode.instant_break();
+            if instant && input.key(VirtualKeyCode::E).is_rising() {
                 for x in hit.pos.x - 10..hit.pos.x + 10 {
                     for y in hit.pos.y - 10..hit.pos.y + 10 {
                         for z in hit.pos.z - 10..hit.pos.z + 10 {
@@ -554,7 +1142,9 @@ fn terrain_manipulation(
                 }
             }
 
-            if ctx.manip.start_pos.is_some() || (input.ctrl() && input.shift()) {
+            if !instant {
+                terrain_manipulation_single(&input, &hit, &mut ctx);
+            } else if ctx.manip.start_pos.is_some() || (input.ctrl() && input.shift()) {
                 terrain_manipulation_area(&input, &hit, &mut ctx);
             } else if ctx.manip.start_pos.is_none() && input.ctrl() {
                 terrain_manipulation_build_to_me(&input, &hit, &mut ctx);
@@ -564,101 +1154,163 @@ fn terrain_manipulation(
         }
     });
 
+    // broken blocks drop themselves, so that they can be picked up and used
+    // for crafting.
+    let drop_events = &mut player_inventory.drop_events;
+    for (&id, positions) in broken_blocks.iter() {
+        let name = access.registry().name(id);
+        for pos in positions.iter().filter(|pos| !undropped.contains(pos)) {
+            drop_events.send(ItemDropEvent {
+                pos: Point3::from(pos.origin()) + vector![0.5, 0.5, 0.5],
+                stack: ItemStack {
+                    item: name.into(),
+                    count: 1,
+                },
+            });
+        }
+    }
+    drop_events.send_batch(drops.into_iter());
+
     if let Some(transform) = query.iter_mut().next().map(|(t, _)| t) {
-        // how many sounds of the same type can be playing at once
-        const SOUND_TYPE_LIMIT: usize = 3;
-        const MAX_AMPLITUDE: f32 = 6.0;
-
-        #[derive(Debug)]
-        struct SoundEntry {
-            amplitude: f32,
-            count: usize,
-        }
-
-        let mut sounds = HashMap::new();
-        let mut near = None;
-        let mut far = None;
-        let mut near_dist = f32::MAX;
-        let mut far_dist = f32::MIN;
-        for (&id, positions) in broken_blocks.iter() {
-            let block_name = format!("blocks/break/{}", access.registry().name(id));
-            if let Some(sound_id) = audio_pools.id(&block_name) {
-                assert!(!positions.is_empty());
-                let count = positions.len().min(SOUND_TYPE_LIMIT);
-                let amplitude = (positions.len() as f32) / (count as f32);
-
-                sounds.insert(sound_id, SoundEntry { amplitude, count });
-
-                for pos in positions.iter() {
-                    let dx = transform.pos().x - pos.x as f32 + 0.5;
-                    let dy = transform.pos().y - pos.y as f32 + 0.5;
-                    let dz = transform.pos().z - pos.z as f32 + 0.5;
-                    let dist_sq = dx * dx + dy * dy + dz * dz;
-
-                    if dist_sq < near_dist {
-                        near_dist = dist_sq;
-                        near = Some(pos);
-                    }
+        let registry = Arc::clone(access.registry());
+        let listener = transform.pos();
+        play_block_sounds(&mut block_sounds, &registry, listener, &broken_blocks, "break");
+        play_block_sounds(&mut block_sounds, &registry, listener, &placed_blocks, "place");
+    }
+}
 
-                    if dist_sq > far_dist {
-                        far_dist = dist_sq;
-                        far = Some(pos);
-                    }
-                }
-            }
+/// plays the `kind` sound of every block in `blocks`. blocks in the same sound
+/// group share a few sounds spread out between them, which are louder the more
+/// blocks there are, rather than every block getting its own.
+fn play_block_sounds(
+    block_sounds: &mut BlockSounds,
+    registry: &BlockRegistry,
+    listener: Point3<f32>,
+    blocks: &HashMap<BlockId, HashSet<BlockPos>>,
+    kind: &str,
+) {
+    // how many sounds of the same type can be playing at once
+    const SOUND_TYPE_LIMIT: usize = 3;
+    const MAX_AMPLITUDE: f32 = 6.0;
+
+    let mut groups = HashMap::<_, Vec<BlockPos>>::new();
+    for (&id, positions) in blocks.iter() {
+        if let Some(sound_id) = block_sounds.pools.block_sound(registry, id, kind) {
+            groups.entry(sound_id).or_default().extend(positions);
         }
+    }
 
-        let mut rng = rand::thread_rng();
-        for (id, entry) in sounds.into_iter() {
-            let start = Point3::from(near.unwrap().origin());
-            let end = Point3::from(far.unwrap().origin());
+    let mut rng = rand::thread_rng();
+    for (id, positions) in groups.into_iter() {
+        assert!(!positions.is_empty());
+        let count = positions.len().min(SOUND_TYPE_LIMIT);
+        let amplitude = (positions.len() as f32) / (count as f32);
 
-            let step = match entry.count {
-                0 => unreachable!(),
-                1 => vector![0.0, 0.0, 0.0], // could be anything
-                n => (end - start) / (n as f32 - 1.0),
-            };
+        let dist_sq = |pos: &&BlockPos| {
+            let center = Point3::from(pos.origin()) + vector![0.5, 0.5, 0.5];
+            TotalFloat((center - listener).norm_squared())
+        };
+        let near = positions.iter().min_by_key(dist_sq).unwrap();
+        let far = positions.iter().max_by_key(dist_sq).unwrap();
 
-            let mut pos = start;
-            for _ in 0..entry.count {
-                pos += step;
-
-                audio_pools.select(&mut rng, id, |id, mut params| {
-                    // 1.0 / (1.0 + f32::exp(-));
-                    let curved = entry.amplitude.sqrt().min(MAX_AMPLITUDE);
-                    params.min_amplitude *= curved;
-                    params.max_amplitude *= curved;
-                    let source = ParameterizedSource::from_sample(id).with_parameters(params);
-                    audio_events.send(AudioEvent::SpawnSpatial(pos, source));
-                });
-            }
+        let start = Point3::from(near.origin());
+        let end = Point3::from(far.origin());
+
+        let step = match count {
+            0 => unreachable!(),
+            1 => vector![0.0, 0.0, 0.0], // could be anything
+            n => (end - start) / (n as f32 - 1.0),
+        };
+
+        let mut pos = start;
+        for _ in 0..count {
+            pos += step;
+
+            block_sounds.pools.select(&mut rng, id, |id, mut params| {
+                // 1.0 / (1.0 + f32::exp(-));
+                let curved = amplitude.sqrt().min(MAX_AMPLITUDE);
+                params.min_amplitude *= curved;
+                params.max_amplitude *= curved;
+                let source = ParameterizedSource::from_sample(id).with_parameters(params);
+                let event = AudioEvent::SpawnSpatial(pos, source);
+                block_sounds.events.send(event);
+            });
         }
     }
 }
 
+/// how far the light given off by a held block reaches, in blocks.
+const HELD_LIGHT_RADIUS: f32 = 12.0;
+
+/// holding a block that gives off light makes it light up the area around
+/// whoever is holding it.
+fn light_held_blocks(
+    mut cmd: Commands,
+    access: Res<ChunkAccess>,
+    query: Query<(Entity, &TerrainManipulator, Option<&DynamicLight>)>,
+) {
+    query.for_each(|(entity, manip, current)| {
+        let id = access.registry().lookup(&manip.block_name);
+        let block_light = access.registry().get(id).block_light();
+        if block_light.iter().all(|&channel| channel == 0) {
+            if current.is_some() {
+                cmd.entity(entity).remove::<DynamicLight>();
+            }
+            return;
+        }
+
+        let light = DynamicLight {
+            color: block_light.map(|channel| channel as f32 / BLOCK_LIGHT_MASK as f32),
+            radius: HELD_LIGHT_RADIUS,
+        };
+        if current != Some(&light) {
+            cmd.entity(entity).insert(light);
+        }
+    });
+}
+
 fn player_look_first_person(
     input: Res<InputState>,
+    settings_file: Res<SettingsFile>,
     player_controller: ResMut<PlayerController>,
+    mut camera_controller: ResMut<CameraController>,
+    cameras: Query<&Camera>,
     mut query: Query<&mut Transform>,
 ) {
     use std::f32::consts::PI;
 
-    let pitch_delta = input.cursor_delta().y.to_radians();
-    let yaw_delta = input.cursor_delta().x.to_radians();
+    // zooming in slows looking around down, so the view doesn't jump around
+    let sensitivity = cameras.get(camera_controller.camera).map_or(1.0, |camera| {
+        camera.look_sensitivity(&settings_file.settings().camera)
+    });
+    let pitch_delta = sensitivity * input.cursor_delta().y.to_radians();
+    let yaw_delta = sensitivity * input.cursor_delta().x.to_radians();
 
-    if let Some(mut transform) = query.get_mut(player_controller.player).ok() {
+    let look = |transform: &mut Transform| {
         transform.rotation.yaw -= yaw_delta;
         transform.rotation.pitch -= pitch_delta;
         transform.rotation.pitch = util::clamp(-PI / 2.0, PI / 2.0, transform.rotation.pitch);
+    };
+
+    // the free camera looks around on its own, leaving the player as they were
+    match camera_controller.mode {
+        CameraControllerMode::Free => look(&mut camera_controller.view),
+        _ => {
+            if let Ok(mut transform) = query.get_mut(player_controller.player) {
+                look(&mut transform)
+            }
+        }
     }
 }
 
 fn player_controller(
-    time: Res<Time>,
+    mut cmd: Commands,
+    time: Res<GameTime>,
     input: Res<InputState>,
-    player_controller: ResMut<PlayerController>,
+    mut player_controller: ResMut<PlayerController>,
     camera_controller: Res<CameraController>,
-    mut player_query: Query<(&mut Transform /* &mut RigidBody, &AabbCollider */,)>,
+    mut player_query: Query<(&Transform, Option<&RigidBody>, &mut MovementCommands)>,
+    game_modes: Query<&GameMode>,
 ) {
     if input
         .key(VirtualKeyCode::C)
@@ -670,117 +1322,240 @@ fn player_controller(
         input.hide_cursor(!grabbed);
     }
 
-    if matches!(camera_controller.mode, CameraControllerMode::Static) {
+    if !matches!(camera_controller.mode, CameraControllerMode::Follow(_)) {
         return;
     }
 
-    if let Some((mut transform /* mut rigidbody, collider */,)) =
-        player_query.get_mut(player_controller.player).ok()
-    {
-        let mut vert_speed = 0.2;
-        let mut horiz_speed = 0.2;
+    // flying is done by moving the player around directly, so the player doesn't
+    // get a rigidbody while flying, which also keeps physics and terrain
+    // collision from getting in the way.
+    let game_mode = game_modes.get(player_controller.player).copied();
+    let can_fly = game_mode.unwrap_or_default().allows_flight();
+    if input.key(VirtualKeyCode::V).is_rising() && (can_fly || player_controller.flying) {
+        player_controller.flying = !player_controller.flying;
+        let mut player = cmd.entity(player_controller.player);
+        match player_controller.flying {
+            true => player.remove::<RigidBody>(),
+            false => player.insert(RigidBody::default()),
+        };
+        log::info!("flying: {}", player_controller.flying);
+    }
 
-        if input.key(VirtualKeyCode::LControl).is_pressed() {
-            horiz_speed *= 10.0;
-            vert_speed *= 10.0;
-        }
+    let movement = MovementInput {
+        forward: input.key(keys::FORWARD).is_pressed(),
+        backward: input.key(keys::BACKWARD).is_pressed(),
+        left: input.key(keys::LEFT).is_pressed(),
+        right: input.key(keys::RIGHT).is_pressed(),
+        up: input.key(keys::UP).is_pressed(),
+        down: input.key(keys::DOWN).is_pressed(),
+        sprint: input.key(VirtualKeyCode::LControl).is_pressed(),
+    };
+
+    // the rigidbody is added and removed at the end of the frame, so whether the
+    // player has one says how this frame's movement works, not `flying`.
+    let player = player_controller.player;
+    if let Ok((transform, rigidbody, mut commands)) = player_query.get_mut(player) {
+        let yaw = transform.rotation.yaw;
+        commands.issue(time.delta_seconds(), yaw, rigidbody.is_none(), movement);
+    }
+}
 
-        if input.key(keys::FORWARD).is_pressed() {
-            let offset = transform_project_xz(&transform, nalgebra::vector![0.0, -horiz_speed]);
-            transform.translate_global(offset);
-        }
-        if input.key(keys::BACKWARD).is_pressed() {
-            let offset = transform_project_xz(&transform, nalgebra::vector![0.0, horiz_speed]);
-            transform.translate_global(offset);
-        }
-        if input.key(keys::RIGHT).is_pressed() {
-            let offset = transform_project_xz(&transform, nalgebra::vector![horiz_speed, 0.0]);
-            transform.translate_global(offset);
-        }
-        if input.key(keys::LEFT).is_pressed() {
-            let offset = transform_project_xz(&transform, nalgebra::vector![-horiz_speed, 0.0]);
-            transform.translate_global(offset);
-        }
-        if input.key(keys::UP).is_pressed() {
-            transform.translate_global(vector![0.0, vert_speed, 0.0]);
+fn respawn_player(
+    player_controller: Res<PlayerController>,
+    spawn: Res<WorldSpawn>,
+    mut death_events: EventReader<DeathEvent>,
+    mut teleport_events: EventWriter<TeleportEvent>,
+    mut query: Query<&mut Health>,
+) {
+    for event in death_events.iter() {
+        if event.entity != player_controller.player {
+            continue;
         }
-        if input.key(keys::DOWN).is_pressed() {
-            transform.translate_global(vector![0.0, -vert_speed, 0.0]);
+
+        log::info!("player died ({:?}), respawning", event.source);
+        if let Ok(mut health) = query.get_mut(event.entity) {
+            health.reset();
+            teleport_events.send(TeleportEvent {
+                entity: event.entity,
+                target: player_spawn_translation(&spawn).into(),
+            });
         }
+    }
+}
 
-        /*
-        let mut vert_acceleration = 9.0;
-        let mut horiz_acceleration = 70.0;
+fn return_to_spawn(
+    input: Res<InputState>,
+    player_controller: Res<PlayerController>,
+    spawn: Res<WorldSpawn>,
+    mut teleport_events: EventWriter<TeleportEvent>,
+) {
+    if input.key(VirtualKeyCode::H).is_rising() {
+        teleport_events.send(TeleportEvent {
+            entity: player_controller.player,
+            target: player_spawn_translation(&spawn).into(),
+        });
+    }
+}
 
-        if collider.on_ground {
-            horiz_acceleration *= 0.85;
-        }
+/// keeps the player standing on the spawn point until it's settled, so that the
+/// terrain around each candidate gets loaded in, and so the player doesn't get
+/// to wander off before they've spawned properly.
+fn hold_player_at_spawn(
+    player_controller: Res<PlayerController>,
+    spawn: Res<WorldSpawn>,
+    persistence: Res<WorldPersistence>,
+    mut spawned: Local<bool>,
+    mut query: Query<(&mut Transform, Option<&mut RigidBody>)>,
+    mut teleport_events: EventWriter<TeleportEvent>,
+) {
+    if *spawned {
+        return;
+    }
 
-        if input.key(VirtualKeyCode::LControl).is_pressed() {
-            horiz_acceleration *= 5.5;
-            vert_acceleration *= 3.5;
-        }
+    // a player that was saved picks up where they left off instead, which the
+    // teleport takes care of loading the terrain for.
+    let saved = persistence.metadata().player.as_ref();
+    if let Some(position) = saved.and_then(|saved| saved.position) {
+        teleport_events.send(TeleportEvent {
+            entity: player_controller.player,
+            target: player_translation_at(position.into()).into(),
+        });
+        *spawned = true;
+        return;
+    }
 
-        if input.key(keys::FORWARD).is_pressed() {
-            rigidbody.acceleration +=
-                transform_project_xz(&mut transform, nalgebra::vector![0.0, -horiz_acceleration]);
-        }
-        if input.key(keys::BACKWARD).is_pressed() {
-            rigidbody.acceleration +=
-                transform_project_xz(&mut transform, nalgebra::vector![0.0, horiz_acceleration]);
-        }
-        if input.key(keys::RIGHT).is_pressed() {
-            rigidbody.acceleration +=
-                transform_project_xz(&mut transform, nalgebra::vector![horiz_acceleration, 0.0]);
-        }
-        if input.key(keys::LEFT).is_pressed() {
-            rigidbody.acceleration +=
-                transform_project_xz(&mut transform, nalgebra::vector![-horiz_acceleration, 0.0]);
-        }
-        if input.key(keys::UP).is_pressed() {
-            if collider.in_liquid {
-                rigidbody.acceleration.y += 60.0;
-            } else if collider.on_ground {
-                rigidbody.velocity.y = vert_acceleration;
-            }
+    if let Ok((mut transform, rigidbody)) = query.get_mut(player_controller.player) {
+        transform.translation.vector = player_spawn_translation(&spawn);
+        // don't let the player build up speed while they're stuck here, or else
+        // they'd go splat as soon as they're let go.
+        if let Some(mut rigidbody) = rigidbody {
+            rigidbody.velocity = vector![0.0, 0.0, 0.0];
         }
+        *spawned = spawn.is_settled();
+    }
+}
+
+/// lets the player know when the save has blocks in it that aren't in the
+/// block registry anymore, since those get swapped out for another block.
+fn report_missing_blocks(
+    persistence: Res<WorldPersistence>,
+    registry: Res<Arc<BlockRegistry>>,
+    mut notify_events: EventWriter<Notify>,
+) {
+    let remap = persistence.block_remap();
+    if !remap.missing().is_empty() {
+        notify_events.send(Notify::warning(tr!(
+            "notify.missing_blocks",
+            remap.missing().join(", "),
+            registry.name(remap.fallback())
+        )));
+    }
+}
 
-        // 0.96 with horiz_acceleration=30.0 is good for flight or slippery surfaces or
-        // such rigidbody.velocity.x *= 0.96;
-        // rigidbody.velocity.z *= 0.96;
+#[derive(Default)]
+struct PlayerSaveEvents(ManualEventReader<SaveEvent>);
 
-        let horiz_drag = 0.1;
-        rigidbody.velocity.x *= util::lerp(1.0 - horiz_drag, 0.0, time.delta_seconds());
-        rigidbody.velocity.z *= util::lerp(1.0 - horiz_drag, 0.0, time.delta_seconds());
+/// saves the player whenever everything is asked to be saved, like when the
+/// game is closing. this needs the whole world, since any of the player's
+/// components might be a saved one.
+fn save_player(world: &mut World) {
+    let requested = world.resource_scope(|world, mut reader: Mut<PlayerSaveEvents>| {
+        let save_events = world.get_resource::<Events<SaveEvent>>().unwrap();
+        reader.0.iter(save_events).count() > 0
+    });
+    if !requested {
+        return;
+    }
+
+    let player = match world.get_resource::<PlayerController>() {
+        Some(player_controller) => player_controller.player,
+        None => return,
+    };
+    let entity = match world.get_entity(player) {
+        Some(entity) => entity,
+        None => return,
+    };
+
+    let persistence = world.get_resource::<WorldPersistence>().unwrap();
+    let previous = persistence.metadata().player.as_ref();
+    let saved_components = world.get_resource::<SavedComponents>().unwrap();
+    let mut components =
+        match saved_components.save(&entity, previous.map(|saved| &saved.components)) {
+            Ok(components) => components,
+            Err(err) => {
+                log::error!("failed to save the player: {}", err);
+                return;
+            }
+        };
 
-        if collider.in_liquid {
-            rigidbody.velocity.y *= util::lerp(0.96, 0.0, time.delta_seconds());
+    // dead players are left to respawn next time, with their health back
+    let alive = entity
+        .get::<Health>()
+        .is_some_and(|health| !health.is_dead());
+    let position = match entity.get::<Transform>() {
+        Some(transform) if alive => {
+            let feet = transform.translation.vector - vector![0.0, PLAYER_DIMENSIONS.y / 2.0, 0.0];
+            Some(feet.into())
         }
-        */
+        _ => {
+            components.remove::<Health>();
+            None
+        }
+    };
+
+    let player = SavedPlayer {
+        position,
+        components,
+    };
+    let mut persistence = world.get_resource_mut::<WorldPersistence>().unwrap();
+    if let Err(err) = persistence.update_metadata(|meta| meta.player = Some(player)) {
+        log::error!("failed to save the player: {}", err);
     }
 }
 
-fn transform_project_xz(transform: &Transform, translation: Vector2<f32>) -> Vector3<f32> {
-    // remove all components of the rotation except for the rotation in the XZ plane
-    let lateral_rotation = UnitQuaternion::from_euler_angles(0.0, transform.rotation.yaw, 0.0);
-    let local_translation = nalgebra::vector![translation.x, 0.0, translation.y];
-    lateral_rotation * local_translation
+/// where the player's transform needs to be for its feet to be at `feet`. the
+/// transform is at the center of the player's collider, so it needs to be
+/// lifted up a bit.
+fn player_translation_at(feet: Point3<f32>) -> Vector3<f32> {
+    feet.coords + nalgebra::vector![0.0, PLAYER_DIMENSIONS.y / 2.0, 0.0]
+}
+
+fn player_spawn_translation(spawn: &WorldSpawn) -> Vector3<f32> {
+    let pos = spawn.pos();
+    let [x, y, z] = [pos.x as f32, pos.y as f32, pos.z as f32];
+    player_translation_at(nalgebra::point![x + 0.5, y, z + 0.5])
 }
 
-fn setup_player(mut cmd: Commands) {
+const PLAYER_MAX_HEALTH: f32 = 20.0;
+const PLAYER_DIMENSIONS: Vector3<f32> = nalgebra::vector![0.7, 1.7, 0.7];
+
+fn setup_player(
+    mut cmd: Commands,
+    spawn: Res<WorldSpawn>,
+    persistence: Res<WorldPersistence>,
+    saved_components: Res<SavedComponents>,
+) {
     let player = cmd
         .spawn()
-        .insert(Transform::default().translated(&nalgebra::vector![0.0, 20.0, 0.0]))
-        // .insert(AabbCollider::new(Aabb::with_dimensions(nalgebra::vector![
-        //     0.7, 1.7, 0.7
-        // ])))
-        // .insert(RigidBody::default())
+        .insert(Transform::default().translated(&player_spawn_translation(&spawn)))
+        .insert(AabbCollider::new(Aabb::with_dimensions(PLAYER_DIMENSIONS)))
+        .insert(RigidBody::default())
+        .insert(MovementCommands::default())
+        .insert(Health::new(PLAYER_MAX_HEALTH))
+        .insert(Inventory::default())
+        .insert(StatsTracked::default())
+        .insert(GameMode::default())
         .insert(DynamicChunkLoader {
             load_radius: 7,
             unload_radius: 8,
         })
         .id();
 
+    // saved components replace the defaults the player was just given
+    if let Some(saved) = &persistence.metadata().player {
+        saved_components.load(&saved.components, &mut cmd.entity(player));
+    }
+
     let camera = cmd
         .spawn()
         .insert(Camera::default())
@@ -789,35 +1564,85 @@ fn setup_player(mut cmd: Commands) {
         .insert(TerrainManipulator {
             start_pos: None,
             start_button: None,
-            block_name: "debug_glow_block",
+            repeat: ButtonRepeat::default(),
+            fill_mode: AreaFillMode::Solid,
+            block_name: "debug_glow_block".into(),
+            clicked_container: None,
+            clicked_sign: None,
+            digging: None,
+            next_break: None,
+            next_place: None,
         })
         .id();
 
     cmd.insert_resource(ActiveCamera(Some(camera)));
+    cmd.insert_resource(ActiveHealthBar(Some(player)));
+    cmd.insert_resource(CraftingUi::for_entity(player));
     cmd.insert_resource(ActiveAudioListener(Some(camera)));
     cmd.insert_resource(CameraController {
         mode: CameraControllerMode::Follow(player),
         camera,
+        view: Transform::default(),
+        transition: None,
+    });
+    cmd.insert_resource(PlayerController {
+        player,
+        flying: false,
     });
-    cmd.insert_resource(PlayerController { player });
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+#[derive(Clone, Debug, PartialEq, Default)]
 pub struct DefaultPlugins {
     seed: Option<u64>,
+    worldgen: Option<String>,
+    save_dir: Option<PathBuf>,
+    missing_block: Option<String>,
+    shadows: ShadowSettings,
+    hdr: HdrSettings,
+    anti_aliasing: AntiAliasing,
+}
+
+impl DefaultPlugins {
+    fn world_plugin(&self) -> WorldPlugin {
+        let mut world_plugin = WorldPlugin::default();
+        if let Some(seed) = self.seed {
+            world_plugin = world_plugin.with_seed(seed);
+        }
+        if let Some(worldgen) = &self.worldgen {
+            world_plugin = world_plugin.with_preset(worldgen);
+        }
+        if let Some(save_dir) = &self.save_dir {
+            world_plugin = world_plugin.with_save_dir(save_dir);
+        }
+        if let Some(missing_block) = &self.missing_block {
+            world_plugin = world_plugin.with_missing_block(missing_block);
+        }
+        world_plugin
+    }
 }
 
 impl PluginGroup for DefaultPlugins {
     fn build(&mut self, group: &mut bevy_app::PluginGroupBuilder) {
         group.add(CorePlugin);
+        group.add(client::loading::LoadingScreenPlugin::default());
+        group.add(GameTimePlugin::default());
+        group.add(NotificationPlugin::default());
+        group.add(ContentPackPlugin::default());
         group.add(WindowingPlugin::default());
         group.add(InputPlugin::default());
-        let mut world_plugin = WorldPlugin::default();
-        if let Some(seed) = self.seed {
-            world_plugin = world_plugin.with_seed(seed);
-        }
-        group.add(world_plugin);
-        group.add(RenderPlugin::default());
+        group.add(client::text_input::TextInputPlugin::default());
+        group.add(CameraPlugin::default());
+        group.add(self.world_plugin());
+        group.add(WeatherPlugin::default());
+        group.add(StatsPlugin::default());
+        group.add(ScriptingPlugin::default());
+        group.add(
+            RenderPlugin::default()
+                .with_shadows(self.shadows)
+                .with_hdr(self.hdr)
+                .with_anti_aliasing(self.anti_aliasing),
+        );
+        group.add(RenderScalePlugin::default());
         group.add(AudioPlugin::default());
 
         #[cfg(feature = "hot-reload")]
@@ -825,19 +1650,44 @@ impl PluginGroup for DefaultPlugins {
     }
 }
 
-#[derive(Debug, Default)]
-pub struct WindowingPlugin {}
+/// how long to wait for background work to finish when the game is closing.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
-impl Plugin for WindowingPlugin {
-    fn build(&self, app: &mut AppBuilder) {
-        let event_loop = EventLoop::new();
-        let window = WindowBuilder::new().with_title("Notcraft™");
-        let graphics_context = ContextBuilder::new().with_depth_buffer(24).with_vsync(true);
-        let display = Rc::new(Display::new(window, graphics_context, &event_loop).unwrap());
+/// gets everything in order before the game exits: background work is stopped,
+/// whatever it already finished is picked up, and everything is saved.
+fn shut_down(app: &mut App) {
+    log::info!("shutting down");
+    if let Some(pools) = app.world.get_resource::<TaskPools>() {
+        if !pools.shut_down(SHUTDOWN_TIMEOUT) {
+            log::warn!("gave up waiting for background tasks to finish");
+        }
+    }
 
-        app.insert_non_send_resource(event_loop);
-        app.insert_non_send_resource(display);
+    // one last update drains anything the background tasks sent back, and lets
+    // everything that saves on a `SaveEvent` do so.
+    if let Some(mut save_events) = app.world.get_resource_mut::<Events<SaveEvent>>() {
+        save_events.send(SaveEvent);
     }
+    app.update();
+    client::replay::finish_recording(&mut app.world);
+
+    if let Some(persistence) = app.world.get_resource::<WorldPersistence>() {
+        if let Err(err) = persistence.flush() {
+            log::error!("failed to save world metadata: {}", err);
+        }
+        // the io pool was shut down before the last update, so the sections it
+        // saved are still waiting to be written
+        if let Err(err) = persistence.flush_sections() {
+            log::error!("failed to save chunks: {}", err);
+        }
+    }
+    log::logger().flush();
+}
+
+fn current_frame_rate_cap(world: &World, activity: WindowActivity) -> Option<u32> {
+    let settings = world.get_resource::<WindowSettings>()?;
+    let step = world.get_resource::<TimeStep>().copied().unwrap_or_default();
+    frame_rate_cap(settings, activity, step)
 }
 
 fn glutin_runner(mut app: App) {
@@ -846,12 +1696,21 @@ fn glutin_runner(mut app: App) {
     let event_loop = app.world.remove_non_send::<EventLoop<()>>().unwrap();
     let display = Rc::clone(app.world.get_non_send_resource::<Rc<Display>>().unwrap());
 
+    let mut limiter = FrameLimiter::new(Instant::now());
+    let mut activity = WindowActivity::default();
+
+    // events can still come in after asking the event loop to exit, which
+    // shouldn't run the game any further.
+    let mut exiting = false;
     event_loop.run(move |event, _target, cf| match event {
+        _ if exiting => {}
+
         Event::WindowEvent {
             event: WindowEvent::CloseRequested,
             ..
         } => {
-            // TODO: move close handling code somewhere else mayhaps
+            shut_down(&mut app);
+            exiting = true;
             *cf = ControlFlow::Exit;
         }
 
@@ -863,6 +1722,15 @@ fn glutin_runner(mut app: App) {
         }
 
         Event::WindowEvent { window_id, event } => {
+            match event {
+                WindowEvent::Focused(focused) => activity.focused = focused,
+                // some platforms shrink the window down to nothing when it's minimized
+                WindowEvent::Resized(size) => {
+                    activity.minimized = size.width == 0 || size.height == 0
+                }
+                _ => {}
+            }
+
             if let Some(mut events) = app.world.get_resource_mut::<Events<RawInputEvent>>() {
                 if let Some(event) = event.to_static() {
                     events.send(RawInputEvent::Window(window_id, event));
@@ -870,11 +1738,28 @@ fn glutin_runner(mut app: App) {
             }
         }
 
-        Event::MainEventsCleared => display.gl_window().window().request_redraw(),
+        Event::MainEventsCleared => {
+            let fps = current_frame_rate_cap(&app.world, activity);
+
+            let remaining = limiter.remaining(Instant::now(), fps);
+            if remaining > SPIN_DURATION {
+                // sleep through most of the wait, while still handling events
+                *cf = ControlFlow::WaitUntil(Instant::now() + remaining - SPIN_DURATION);
+            } else {
+                limiter.spin(fps);
+                *cf = ControlFlow::Poll;
+                display.gl_window().window().request_redraw();
+            }
+        }
         Event::RedrawRequested(id) if id == display.gl_window().window().id() => {
+            let fps = current_frame_rate_cap(&app.world, activity);
+            limiter.start_frame(Instant::now(), fps);
+
             app.update();
             let mut app_exit_events = app.world.get_resource_mut::<Events<AppExit>>().unwrap();
             if app_exit_events.drain().last().is_some() {
+                shut_down(&mut app);
+                exiting = true;
                 *cf = ControlFlow::Exit;
             }
         }
@@ -888,11 +1773,124 @@ pub struct RunOptions {
     #[structopt(default_value = "simple", long)]
     pub mesher_mode: MesherMode,
 
+    /// whether light is blended smoothly across block faces, or is flat
+    #[structopt(default_value = "smooth", long)]
+    pub lighting: LightingType,
+
+    /// how many shadow maps the sun's shadows are split between. more cascades
+    /// keep shadows sharp further away. zero turns shadows off
+    #[structopt(default_value = "3", long)]
+    pub shadow_cascades: usize,
+
+    /// the width and height of each shadow map, in texels
+    #[structopt(default_value = "2048", long)]
+    pub shadow_resolution: u32,
+
+    /// how the HDR image is brought into the range the screen can show, either
+    /// `aces` or `reinhard`
+    #[structopt(default_value = "aces", long)]
+    pub tonemapper: Tonemapper,
+
+    /// how much brighter the image is made before it's tonemapped
+    #[structopt(default_value = "1.0", long)]
+    pub exposure: f32,
+
+    /// how strongly bright things glow. zero turns bloom off
+    #[structopt(default_value = "0.6", long)]
+    pub bloom: f32,
+
+    /// how jagged edges are smoothed out: `off`, `fxaa`, `msaa2`, `msaa4`, or
+    /// `msaa8`. this can be changed while the game is running with the
+    /// `/antialiasing` command
+    #[structopt(default_value = "fxaa", long)]
+    pub anti_aliasing: AntiAliasing,
+
+    /// mesh chunks on the main thread, spending at most this many milliseconds
+    /// per frame doing so, instead of using the mesher thread pool
+    #[structopt(long)]
+    pub mesh_budget_ms: Option<f32>,
+
+    /// keep up to this many section meshes around after they're made, so that
+    /// sections which are unloaded and then loaded again unchanged don't have
+    /// to be meshed again
+    #[structopt(long)]
+    pub mesh_cache_size: Option<usize>,
+
     #[structopt(long)]
     pub seed: Option<u64>,
 
+    /// the worldgen preset from `resources/worldgen` to generate new worlds
+    /// with, like `superflat`, `amplified`, or `islands`. saved worlds keep
+    /// the one they were made with unless this is given
+    #[structopt(long)]
+    pub worldgen: Option<String>,
+
+    /// the directory to save the world in. worlds aren't saved at all if this
+    /// isn't given
+    #[structopt(long)]
+    pub save_dir: Option<PathBuf>,
+
+    /// the block to load saved blocks as when they've been removed from the
+    /// block registry. defaults to air
+    #[structopt(long)]
+    pub missing_block: Option<String>,
+
+    /// where settings like the window size are kept between runs
+    #[structopt(default_value = "settings.ron", long)]
+    pub settings_file: PathBuf,
+
+    /// where log files are written, when the settings say to write them
+    #[structopt(default_value = "logs", long)]
+    pub log_dir: PathBuf,
+
+    /// how far away, in blocks, the player can break and place blocks
+    #[structopt(long)]
+    pub reach: Option<f32>,
+
     #[structopt(long, short = "D")]
     pub enable_debug_events: Option<Vec<String>>,
+
+    /// record every enabled debug event to this file
+    #[structopt(long)]
+    pub record_debug_events: Option<PathBuf>,
+
+    /// print a summary of a file made with `--record-debug-events`, and exit
+    /// without starting the game
+    #[structopt(long)]
+    pub inspect_debug_log: Option<PathBuf>,
+
+    /// load a capsule from `crash-dumps` into a world made of only the sections
+    /// in it, write the block updates it was made for again, and exit without
+    /// starting the game
+    #[structopt(long)]
+    pub load_crash_dump: Option<PathBuf>,
+
+    /// generate and save every column up to this many chunks away from the
+    /// world's spawn point, like `/pregen` does, and exit without starting the
+    /// game
+    #[structopt(long, requires = "save-dir")]
+    pub pregen: Option<i32>,
+
+    /// overrides the number of threads used by a thread pool, like
+    /// `--pool-size mesher=2`
+    #[structopt(long)]
+    pub pool_size: Vec<PoolSize>,
+
+    /// write frame timings to this file in the chrome tracing format
+    #[structopt(long)]
+    pub trace_file: Option<PathBuf>,
+
+    /// record keyboard and mouse input to this file, along with the world seed
+    /// and where the player started, so that it can be played back with
+    /// `--replay-input`. the game moves forward by a fixed amount every frame
+    /// while recording
+    #[structopt(long, conflicts_with_all = &["save-dir", "worldgen", "replay-input"])]
+    pub record_input: Option<PathBuf>,
+
+    /// play back a file made with `--record-input` instead of taking input from
+    /// the keyboard and mouse, in the same world it was recorded in
+    #[structopt(long, conflicts_with_all = &["save-dir", "seed", "worldgen"])]
+    pub replay_input: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
@@ -945,18 +1943,95 @@ impl<T> WeightedList<T> {
     }
 }
 
-fn load_sounds(mut cmd: Commands, mut state: ResMut<AudioState>) -> Result<()> {
-    let pools = load_audio("resources/audio/manifest.ron", &mut *state)?;
-    cmd.insert_resource(pools);
+fn load_sounds(
+    mut cmd: Commands,
+    mut state: ResMut<AudioState>,
+    mut packs: ResMut<ContentPacks>,
+    mut progress: ResMut<AssetLoadProgress>,
+) {
+    if !progress.start(assets::SOUNDS) {
+        return;
+    }
+
+    match load_audio("resources/audio/manifest.ron", &mut *state, &mut packs) {
+        Ok(pools) => {
+            cmd.insert_resource(pools);
+            progress.finish(assets::SOUNDS);
+        }
+        Err(err) => progress.fail(assets::SOUNDS, err),
+    }
+}
 
+/// loads a capsule that was saved when a chunk invariant failed, and writes its
+/// updates again, so that the panic can be caught somewhere it's easy to look
+/// at. blocks from content packs are loaded as air.
+fn replay_capsule(path: &Path) -> Result<()> {
+    let registry = load_registry("resources/blocks.json")?;
+    let capsule = repro::load_capsule(path, &registry)?;
+    print!("{}", capsule);
+
+    println!("writing the updates again...");
+    let rebuild = capsule.replay();
+    println!(
+        "the updates were written without panicking, and {} sections would be remeshed",
+        rebuild.len()
+    );
+    Ok(())
+}
+
+/// generates and saves the area around a world's spawn point without starting
+/// the game, printing its progress as it goes.
+fn pregenerate_world(
+    world_plugin: &WorldPlugin,
+    mut packs: ContentPacks,
+    pool_sizes: &[PoolSize],
+    radius: i32,
+) -> Result<()> {
+    if !(0..=MAX_PREGEN_RADIUS).contains(&radius) {
+        bail!(tr!("command.pregen.bad_radius", MAX_PREGEN_RADIUS));
+    }
+
+    let world = world_plugin.open(&mut packs)?;
+    let pools = TaskPools::new(pool_sizes)?;
+    let center = world.spawn.pos().section_and_offset().0.column();
+    let (mut columns, mut reported) = (0, 0);
+    pregen::pregenerate_saved(&world.generator, &pools, center, radius, |done, total| {
+        columns = total;
+        let quarters = 4 * done / total;
+        if quarters > reported {
+            reported = quarters;
+            println!("{}", tr!("notify.pregen_progress", 25 * quarters, total));
+        }
+    })?;
+    println!("{}", tr!("notify.pregen_done", columns));
     Ok(())
 }
 
 fn main() {
-    env_logger::init();
+    client::logging::init();
+    client::crash::install_panic_hook();
 
     let options = RunOptions::from_args();
 
+    if let Some(path) = options.inspect_debug_log {
+        match recording::read_log(&path) {
+            Ok(events) => print!("{}", recording::summarize_log(&events)),
+            Err(err) => eprintln!("failed to read debug log '{}': {}", path.display(), err),
+        }
+        return;
+    }
+
+    if let Some(path) = options.load_crash_dump {
+        if let Err(err) = replay_capsule(&path) {
+            eprintln!("failed to load crash dump '{}': {}", path.display(), err);
+        }
+        return;
+    }
+
+    let mesh_budget = options
+        .mesh_budget_ms
+        .map(|ms| Duration::from_secs_f32(ms / 1000.0));
+
     if let Some(enabled) = options
         .enable_debug_events
         .map(|names| names.into_iter().collect::<HashSet<_>>())
@@ -971,26 +2046,225 @@ fn main() {
         client::debug::events::enumerate(enabled.as_ref());
     }
 
+    if let Some(path) = &options.record_debug_events {
+        match recording::start_recording(path) {
+            Ok(()) => println!("recording debug events to '{}'", path.display()),
+            Err(err) => log::error!("failed to start recording debug events: {}", err),
+        }
+    }
+
+    let mut seed = options.seed;
+    let mut time_step = TimeStep::RealTime;
+    let mut replay_mode = ReplayMode::Off;
+    if let Some(path) = &options.record_input {
+        match InputRecorder::create(path) {
+            Ok(recorder) => {
+                println!("recording input to '{}'", path.display());
+                time_step = TimeStep::Fixed(RECORDING_TIME_STEP);
+                replay_mode = ReplayMode::Record(recorder);
+            }
+            Err(err) => log::error!("failed to start recording input: {}", err),
+        }
+    }
+    if let Some(path) = &options.replay_input {
+        match client::replay::read_recording(path) {
+            Ok(recording) => {
+                seed = Some(recording.header.seed);
+                time_step = TimeStep::Fixed(recording.header.step);
+                replay_mode = ReplayMode::Replay(InputReplay::new(recording));
+            }
+            Err(err) => {
+                eprintln!("failed to read input recording '{}': {}", path.display(), err);
+                return;
+            }
+        }
+    }
+
+    // a broken settings file is left alone instead of being overwritten with
+    // the defaults, so that it can be fixed by hand.
+    let settings_file = match SettingsFile::open(&options.settings_file) {
+        Ok(settings_file) => settings_file,
+        Err(err) => {
+            log::error!(
+                "failed to read settings from '{}', using the defaults: {}",
+                options.settings_file.display(),
+                err
+            );
+            SettingsFile::unsaved()
+        }
+    };
+
+    client::logging::configure(&settings_file.settings().logging, &options.log_dir);
+
+    let language_dir = Path::new(lang::LANGUAGE_DIR);
+    let language = &settings_file.settings().language;
+    if let Err(err) = lang::load_language(language_dir, language) {
+        log::error!("failed to load language '{}': {}", language, err);
+        if let Err(err) = lang::load_language(language_dir, lang::FALLBACK_LANGUAGE) {
+            log::error!("failed to load fallback language: {}", err);
+        }
+    }
+
+    let content_packs = ContentPacks::open(
+        CONTENT_PACK_DIR,
+        &settings_file.settings().content_packs,
+    );
+
+    let default_plugins = DefaultPlugins {
+        seed,
+        worldgen: options.worldgen,
+        save_dir: options.save_dir,
+        missing_block: options.missing_block,
+        shadows: ShadowSettings {
+            cascades: options.shadow_cascades,
+            resolution: options.shadow_resolution,
+        },
+        hdr: HdrSettings {
+            tonemapper: options.tonemapper,
+            exposure: options.exposure,
+            bloom_strength: options.bloom,
+        },
+        anti_aliasing: options.anti_aliasing,
+    };
+
+    if let Some(radius) = options.pregen {
+        let world_plugin = default_plugins.world_plugin();
+        let pool_sizes = &options.pool_size;
+        if let Err(err) = pregenerate_world(&world_plugin, content_packs, pool_sizes, radius) {
+            eprintln!("failed to pregenerate the world: {}", err);
+        }
+        return;
+    }
+
+    let mut targeting_config = TargetingConfig::default();
+    if let Some(reach) = options.reach {
+        targeting_config.reach = reach;
+    }
+
+    let item_drop_config = settings_file.settings().item_drops;
+
     App::build()
-        .add_plugins(DefaultPlugins { seed: options.seed })
-        .add_plugin(ChunkMesherPlugin::default().with_mode(options.mesher_mode))
+        .insert_resource(GameplayConfig::default())
+        .insert_resource(targeting_config)
+        .insert_resource(item_drop_config)
+        .insert_resource(settings_file)
+        .insert_resource(content_packs)
+        .insert_resource(time_step)
+        .insert_resource(replay_mode)
+        .add_plugin(TaskPoolPlugin::default().with_sizes(&options.pool_size))
+        .add_plugin(ProfilerPlugin::default().with_trace_file(options.trace_file))
+        .add_plugins(default_plugins)
+        .add_plugin(
+            ChunkMesherPlugin::default()
+                .with_mode(options.mesher_mode)
+                .with_lighting(options.lighting)
+                .with_cooperative_budget(mesh_budget)
+                .with_cache_capacity(options.mesh_cache_size),
+        )
         .add_plugin(PhysicsPlugin::default())
+        .add_plugin(MovementPlugin::default())
         .add_plugin(CollisionPlugin::default())
+        .add_plugin(HealthPlugin::default())
+        .add_plugin(GameModePlugin::default())
+        .add_plugin(TeleportPlugin::default())
+        .add_plugin(ExplosionPlugin::default())
+        .add_plugin(ItemDropPlugin::default())
+        .add_plugin(CraftingPlugin::default())
+        .add_plugin(CraftingUiPlugin::default())
+        .add_plugin(ContainerUiPlugin::default())
+        .add_plugin(client::debug::DebugOverlayPlugin::default())
+        .add_plugin(client::inspector::ChunkInspectorPlugin::default())
+        .add_plugin(PathfindingPlugin::default())
+        .add_plugin(MobPlugin::default())
+        .add_plugin(client::mob::MobRenderPlugin::default())
+        .add_plugin(RemotePlayerPlugin::default())
+        .add_plugin(client::remote_player::RemotePlayerRenderPlugin::default())
+        .add_plugin(client::sign::SignRenderPlugin::default())
+        .add_plugin(client::explosion::ExplosionEffectsPlugin::default())
+        .add_plugin(client::item_drop::ItemDropRenderPlugin::default())
+        .add_plugin(client::particles::BlockParticlePlugin::default())
+        .add_plugin(client::footsteps::FootstepPlugin::default())
+        .add_plugin(ConsolePlugin::default())
+        .add_plugin(client::console::ConsoleUiPlugin::default())
+        .add_plugin(client::commands::CommandsPlugin::default())
+        .add_plugin(client::menu::MenuPlugin::default())
+        .add_plugin(client::minimap::MinimapPlugin::default())
+        .add_plugin(client::compass::CompassPlugin::default())
+        .add_plugin(client::block_tooltip::BlockTooltipPlugin::default())
+        .add_plugin(client::toast::ToastPlugin::default())
+        .add_plugin(client::screenshot::ScreenshotPlugin::default())
+        .add_plugin(ReplayPlugin::default())
         .add_startup_system(setup_player.system())
-        .add_startup_system(try_system!(load_sounds))
+        // nothing makes any sounds until they're loaded
+        .init_resource::<RandomizedAudioPools>()
+        .add_system(load_sounds.system())
+        .add_startup_system(report_missing_blocks.system())
         .add_system(
             player_look_first_person
                 .system()
-                .label(PlayerControllerUpdate),
+                .profiled()
+                .label(PlayerControllerUpdate)
+                .with_run_criteria(playing.system()),
+        )
+        .add_system(
+            player_controller
+                .system()
+                .profiled()
+                .label(PlayerControllerUpdate)
+                .before(MovementApplication)
+                .with_run_criteria(playing.system()),
+        )
+        .init_resource::<FreeCamera>()
+        .add_system(
+            free_camera_controller
+                .system()
+                .label(PlayerControllerUpdate)
+                .with_run_criteria(playing.system()),
         )
-        .add_system(player_controller.system().label(PlayerControllerUpdate))
         .add_system(
             camera_controller
                 .system()
                 .label(CameraControllerUpdate)
                 .after(PlayerControllerUpdate),
         )
-        .add_system(terrain_manipulation.system().after(CameraControllerUpdate))
+        .add_system(camera_fov.system().after(CameraControllerUpdate))
+        .add_system(
+            switch_manipulator_modes
+                .system()
+                .label(SwitchManipulatorModes)
+                .with_run_criteria(playing.system()),
+        )
+        .init_resource::<CurrentTarget>()
+        .add_system(
+            pick_target_block
+                .system()
+                .after(CurrentTargetUpdate)
+                .before(SwitchManipulatorModes)
+                .with_run_criteria(playing.system()),
+        )
+        .add_system(
+            update_current_target
+                .system()
+                .label(CurrentTargetUpdate)
+                .after(PlayerControllerUpdate)
+                .with_run_criteria(playing.system()),
+        )
+        .add_system(
+            terrain_manipulation
+                .system()
+                .profiled()
+                .after(CameraControllerUpdate)
+                .after(CurrentTargetUpdate)
+                .label(TerrainManipulation)
+                .after(SwitchManipulatorModes)
+                .with_run_criteria(playing.system()),
+        )
+        .add_system(light_held_blocks.system())
+        .add_system(respawn_player.system().before(PlayerControllerUpdate))
+        .add_system(hold_player_at_spawn.system().after(PlayerControllerUpdate))
+        .init_resource::<PlayerSaveEvents>()
+        .add_system(save_player.exclusive_system())
+        .add_system(return_to_spawn.system().with_run_criteria(playing.system()))
         .add_system_to_stage(
             RenderStage::PreRender,
             client::debug::debug_event_handler.system(),
@@ -1008,3 +2282,12 @@ pub struct PlayerControllerUpdate;
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
 pub struct CameraControllerUpdate;
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub struct SwitchManipulatorModes;
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub struct TerrainManipulation;
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub struct CurrentTargetUpdate;
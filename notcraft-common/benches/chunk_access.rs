@@ -0,0 +1,142 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use notcraft_common::{
+    aabb::Aabb,
+    world::{
+        chunk::{ArrayChunk, Chunk, ChunkAccess, ChunkData, ChunkSection, CHUNK_LENGTH},
+        generation::SurfaceHeightmap,
+        registry::load_registry,
+        BlockPos, ChunkPos, VoxelWorld,
+    },
+};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use std::sync::Arc;
+
+// the benchmark world is `WORLD_SECTIONS` sections wide along each axis.
+const WORLD_SECTIONS: i32 = 4;
+// the scanned region is 64 blocks wide along each axis, and straddles section
+// borders so that every access path has to deal with more than one section.
+const REGION_MIN: i32 = 16;
+const REGION_MAX: i32 = 79;
+
+fn make_world() -> Arc<VoxelWorld> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../resources/blocks.json");
+    let registry = load_registry(path).unwrap();
+    let stone = registry.lookup("stone");
+    let dirt = registry.lookup("dirt");
+
+    let world = VoxelWorld::new(&registry);
+    for x in 0..WORLD_SECTIONS {
+        for z in 0..WORLD_SECTIONS {
+            let pos = ChunkPos { x, z };
+            let chunk = Chunk::initialize(pos, SurfaceHeightmap::flat(0));
+            for y in 0..WORLD_SECTIONS {
+                // a checkerboard, so no section ends up homogeneous
+                let mut blocks = ArrayChunk::homogeneous(stone);
+                for dx in 0..CHUNK_LENGTH {
+                    for dy in 0..CHUNK_LENGTH {
+                        for dz in ((dx + dy) % 2..CHUNK_LENGTH).step_by(2) {
+                            blocks[[dx, dy, dz]] = dirt;
+                        }
+                    }
+                }
+                let data = ChunkData::Array(blocks);
+                let section = ChunkSection::initialize(pos.section(y), data, &registry);
+                chunk.sections_mut().insert(y, Arc::new(section));
+            }
+            world.insert_chunk(Arc::new(chunk));
+        }
+    }
+
+    world
+}
+
+fn bench_region_scan(c: &mut Criterion) {
+    let world = make_world();
+    let mut group = c.benchmark_group("region_scan");
+
+    group.bench_function("per_block", |b| {
+        b.iter(|| {
+            let mut access = ChunkAccess::new(&world);
+            let mut count = 0;
+            for x in REGION_MIN..=REGION_MAX {
+                for z in REGION_MIN..=REGION_MAX {
+                    for y in REGION_MIN..=REGION_MAX {
+                        count += access.block(BlockPos { x, y, z }).is_some() as usize;
+                    }
+                }
+            }
+            black_box(count)
+        })
+    });
+
+    group.bench_function("snapshot_region", |b| {
+        b.iter(|| {
+            let mut access = ChunkAccess::new(&world);
+            let min = BlockPos::new([REGION_MIN; 3]);
+            let max = BlockPos::new([REGION_MAX; 3]);
+            let region = access.snapshot_region(min, max);
+            black_box(region.iter().count())
+        })
+    });
+
+    group.bench_function("iter_blocks_in", |b| {
+        b.iter(|| {
+            let mut access = ChunkAccess::new(&world);
+            let min = nalgebra::point![REGION_MIN as f32, REGION_MIN as f32, REGION_MIN as f32];
+            let aabb = Aabb {
+                min,
+                max: min + nalgebra::vector![64.0, 64.0, 64.0],
+            };
+            black_box(access.iter_blocks_in(&aabb).count())
+        })
+    });
+
+    group.finish();
+}
+
+// how many blocks each access pattern reads.
+const ACCESS_COUNT: usize = 4096;
+
+fn bench_access_patterns(c: &mut Criterion) {
+    let world = make_world();
+    let mut group = c.benchmark_group("access_patterns");
+
+    // reads that jump around between sections, like many entities each
+    // looking at the blocks around themselves.
+    let mut rng = SmallRng::seed_from_u64(0);
+    let random = (0..ACCESS_COUNT)
+        .map(|_| BlockPos {
+            x: rng.gen_range(REGION_MIN, REGION_MAX + 1),
+            y: rng.gen_range(REGION_MIN, REGION_MAX + 1),
+            z: rng.gen_range(REGION_MIN, REGION_MAX + 1),
+        })
+        .collect::<Vec<_>>();
+
+    // the same number of reads, one after another along a single line of
+    // blocks, like when filling in or scanning an area.
+    let sequential = (0..ACCESS_COUNT as i32)
+        .map(|i| BlockPos {
+            x: REGION_MIN + i / 64,
+            y: REGION_MIN + i % 64,
+            z: REGION_MIN,
+        })
+        .collect::<Vec<_>>();
+
+    for (name, positions) in [("random", &random), ("sequential", &sequential)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut access = ChunkAccess::new(&world);
+                let mut count = 0;
+                for &pos in positions.iter() {
+                    count += access.block(pos).is_some() as usize;
+                }
+                black_box(count)
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_region_scan, bench_access_patterns);
+criterion_main!(benches);
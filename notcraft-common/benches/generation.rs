@@ -0,0 +1,138 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use notcraft_common::{
+    net::compression::CompressedSection,
+    world::{
+        chunk::{ChunkData, ChunkSectionPos, CompactedChunkSection, CHUNK_LENGTH, CHUNK_LENGTH_3},
+        generation::{
+            preset::WorldGenPreset, ChunkGenerator, SurfaceHeighmapCache, SurfaceHeightmap,
+        },
+        registry::{load_registry, BlockId},
+        ChunkPos,
+    },
+};
+
+const SEED: u64 = 1;
+// a column with a fair amount of height variation across it for this seed.
+const HILLS_COLUMN: ChunkPos = ChunkPos { x: 3, z: -2 };
+
+/// the sections that each benchmark runs on, from cheapest to most expensive
+/// to generate: one that's entirely above the surface, one with a perfectly
+/// flat surface running through it, one with the real surface running through
+/// it, and one so far underground that caves are carved out of every block.
+fn terrains() -> Vec<(&'static str, ChunkSectionPos, SurfaceHeightmap)> {
+    let section = |column: ChunkPos, y: i32| column.section(y);
+    let origin = ChunkPos { x: 0, z: 0 };
+
+    let hills = SurfaceHeighmapCache::default().surface_heights(
+        SEED,
+        &WorldGenPreset::default(),
+        HILLS_COLUMN,
+    );
+    let hills_section = section(HILLS_COLUMN, hills.min().div_euclid(CHUNK_LENGTH as i32));
+
+    vec![
+        (
+            "above_surface",
+            section(origin, 0),
+            SurfaceHeightmap::flat(-64),
+        ),
+        (
+            "flat_surface",
+            section(origin, 0),
+            SurfaceHeightmap::flat(16),
+        ),
+        ("hills", hills_section, hills),
+        (
+            "underground",
+            section(origin, 0),
+            SurfaceHeightmap::flat(256),
+        ),
+    ]
+}
+
+fn make_generator() -> ChunkGenerator {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../resources/blocks.json");
+    ChunkGenerator::new_default(&load_registry(path).unwrap())
+}
+
+fn bench_generation(c: &mut Criterion) {
+    let generator = make_generator();
+    let mut group = c.benchmark_group("generation");
+    group.throughput(Throughput::Elements(CHUNK_LENGTH_3 as u64));
+
+    for (name, pos, heights) in terrains() {
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(generator.make_chunk(SEED, pos, &heights)))
+        });
+    }
+
+    // a new cache every time, so that the heights are actually generated
+    // instead of being looked up.
+    let preset = WorldGenPreset::default();
+    group.bench_function("surface_heights", |b| {
+        b.iter_batched(
+            SurfaceHeighmapCache::default,
+            |cache| black_box(cache.surface_heights(SEED, &preset, HILLS_COLUMN)),
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn bench_compaction(c: &mut Criterion) {
+    let generator = make_generator();
+    let sections: Vec<(&str, ChunkData<BlockId>)> = terrains()
+        .into_iter()
+        .map(|(name, pos, heights)| (name, generator.make_chunk(SEED, pos, &heights)))
+        .collect();
+
+    let mut group = c.benchmark_group("compaction");
+    group.throughput(Throughput::Elements(CHUNK_LENGTH_3 as u64));
+
+    for (name, data) in sections.iter() {
+        group.bench_function(format!("compact/{}", name), |b| {
+            b.iter(|| black_box(CompactedChunkSection::compact(data)))
+        });
+
+        let compacted = CompactedChunkSection::compact(data);
+        group.bench_function(format!("decompact/{}", name), |b| {
+            b.iter(|| black_box(compacted.decompact()))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_wire(c: &mut Criterion) {
+    let generator = make_generator();
+    let mut group = c.benchmark_group("wire");
+    group.throughput(Throughput::Elements(CHUNK_LENGTH_3 as u64));
+
+    for (name, pos, heights) in terrains() {
+        let data = generator.make_chunk(SEED, pos, &heights);
+        let compressed = CompressedSection::compress(&data);
+
+        // how big the section is when it's actually sent is more interesting
+        // than how long it takes to squeeze it down. sending every block as a
+        // `u16` is what the section would cost without any compression.
+        println!(
+            "wire/{}: {} bytes per section ({} uncompressed)",
+            name,
+            compressed.as_bytes().len(),
+            CHUNK_LENGTH_3 * 2,
+        );
+
+        group.bench_function(format!("compress/{}", name), |b| {
+            b.iter(|| black_box(CompressedSection::compress(&data)))
+        });
+        group.bench_function(format!("decompress/{}", name), |b| {
+            b.iter(|| black_box(compressed.decompress().unwrap()))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_generation, bench_compaction, bench_wire);
+criterion_main!(benches);
@@ -0,0 +1,164 @@
+//! keeping track of the assets that have to be loaded before the game can
+//! start, like block textures and sounds.
+//!
+//! each kind of asset has a loader that reports how it's doing through the
+//! [`AssetLoadProgress`] resource, and the client shows a loading screen until
+//! all of them are done. the world doesn't start generating until then either,
+//! which systems opt into by using [`assets_ready`] as their run criteria.
+
+use crate::prelude::*;
+use bevy_ecs::schedule::ShouldRun;
+
+pub const REGISTRY: &str = "registry";
+pub const SHADERS: &str = "shaders";
+pub const TEXTURES: &str = "textures";
+pub const SOUNDS: &str = "sounds";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssetState {
+    /// waiting for its loader to start on it, or to start on it again after
+    /// it failed.
+    Pending,
+    Loading {
+        done: usize,
+        total: usize,
+    },
+    Loaded,
+    Failed(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AssetLoadProgress {
+    /// every asset that's been registered, in the order they were registered.
+    assets: Vec<(&'static str, AssetState)>,
+}
+
+impl AssetLoadProgress {
+    fn entry(&mut self, name: &'static str) -> &mut AssetState {
+        let idx = match self.assets.iter().position(|&(other, _)| other == name) {
+            Some(idx) => idx,
+            None => {
+                self.assets.push((name, AssetState::Pending));
+                self.assets.len() - 1
+            }
+        };
+        &mut self.assets[idx].1
+    }
+
+    /// adds an asset that has to be loaded before the game can start.
+    pub fn register(&mut self, name: &'static str) {
+        self.entry(name);
+    }
+
+    pub fn state(&self, name: &str) -> Option<&AssetState> {
+        self.assets
+            .iter()
+            .find(|&&(other, _)| other == name)
+            .map(|(_, state)| state)
+    }
+
+    /// marks `name` as being loaded if its loader should start on it, which
+    /// loaders check for every frame.
+    pub fn start(&mut self, name: &'static str) -> bool {
+        let state = self.entry(name);
+        if *state != AssetState::Pending {
+            return false;
+        }
+        *state = AssetState::Loading { done: 0, total: 1 };
+        true
+    }
+
+    pub fn report(&mut self, name: &'static str, done: usize, total: usize) {
+        *self.entry(name) = AssetState::Loading { done, total };
+    }
+
+    pub fn finish(&mut self, name: &'static str) {
+        *self.entry(name) = AssetState::Loaded;
+    }
+
+    pub fn fail<E: std::fmt::Display>(&mut self, name: &'static str, err: E) {
+        log::error!("failed to load {}: {}", name, err);
+        *self.entry(name) = AssetState::Failed(err.to_string());
+    }
+
+    /// puts every asset that failed back in line to be loaded again.
+    pub fn retry_failed(&mut self) {
+        for (_, state) in self.assets.iter_mut() {
+            if let AssetState::Failed(_) = state {
+                *state = AssetState::Pending;
+            }
+        }
+    }
+
+    pub fn assets(&self) -> impl Iterator<Item = (&'static str, &AssetState)> + '_ {
+        self.assets.iter().map(|(name, state)| (*name, state))
+    }
+
+    /// every asset that failed to load, along with why.
+    pub fn failures(&self) -> impl Iterator<Item = (&'static str, &str)> + '_ {
+        self.assets().filter_map(|(name, state)| match state {
+            AssetState::Failed(err) => Some((name, err.as_str())),
+            _ => None,
+        })
+    }
+
+    /// how much of the loading is done, from 0 to 1. every asset counts the
+    /// same, no matter how big it is.
+    pub fn fraction(&self) -> f32 {
+        if self.assets.is_empty() {
+            return 1.0;
+        }
+        let done = self.assets().map(|(_, state)| match *state {
+            AssetState::Loaded => 1.0,
+            AssetState::Loading { done, total } if total > 0 => done as f32 / total as f32,
+            _ => 0.0,
+        });
+        done.sum::<f32>() / self.assets.len() as f32
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.assets().all(|(_, state)| *state == AssetState::Loaded)
+    }
+}
+
+/// run criteria for systems that have to wait for every asset to be loaded.
+/// apps without an [`AssetLoadProgress`] never have to wait.
+pub fn assets_ready(progress: Option<Res<AssetLoadProgress>>) -> ShouldRun {
+    match progress.as_deref() {
+        Some(progress) if !progress.is_ready() => ShouldRun::No,
+        _ => ShouldRun::Yes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_assets_can_be_retried() {
+        let mut progress = AssetLoadProgress::default();
+        progress.register(TEXTURES);
+        progress.register(SOUNDS);
+        assert_eq!(progress.fraction(), 0.0);
+
+        assert!(progress.start(TEXTURES));
+        assert!(!progress.start(TEXTURES));
+        progress.report(TEXTURES, 1, 4);
+        assert_eq!(progress.fraction(), 0.125);
+        progress.finish(TEXTURES);
+
+        assert!(progress.start(SOUNDS));
+        progress.fail(SOUNDS, "no manifest");
+        assert_eq!(progress.fraction(), 0.5);
+        let failures = progress.failures().collect::<Vec<_>>();
+        assert_eq!(failures, [(SOUNDS, "no manifest")]);
+        assert!(!progress.is_ready());
+
+        progress.retry_failed();
+        assert_eq!(progress.state(SOUNDS), Some(&AssetState::Pending));
+        assert!(progress.start(SOUNDS));
+        progress.finish(SOUNDS);
+        assert!(progress.is_ready());
+        assert_eq!(progress.fraction(), 1.0);
+    }
+}
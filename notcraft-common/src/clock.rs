@@ -0,0 +1,62 @@
+//! how much time passes in the game each frame.
+//!
+//! normally, this is just how long the last frame took, the same as bevy's
+//! `Time`. recording input or replaying it steps the game forward by a fixed
+//! amount every frame instead, so that a replay plays out exactly like the game
+//! that was recorded, no matter how fast either of them ran. anything that
+//! affects gameplay should use [`GameTime`] instead of `Time`.
+
+use crate::prelude::*;
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimeStep {
+    /// every frame moves the game forward by however long the last frame took.
+    #[default]
+    RealTime,
+    /// every frame moves the game forward by exactly this much.
+    Fixed(Duration),
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct GameTime {
+    delta: Duration,
+    elapsed: Duration,
+}
+
+impl GameTime {
+    /// how much time passed in the game this frame.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// how much time has passed in the game since it started.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+fn advance_game_time(time: Res<Time>, step: Res<TimeStep>, mut game_time: ResMut<GameTime>) {
+    let delta = match *step {
+        TimeStep::RealTime => time.delta(),
+        TimeStep::Fixed(step) => step,
+    };
+    game_time.delta = delta;
+    game_time.elapsed += delta;
+}
+
+#[derive(Debug, Default)]
+pub struct GameTimePlugin {}
+
+impl Plugin for GameTimePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<TimeStep>();
+        app.init_resource::<GameTime>();
+        // bevy's time system is exclusive, so it always runs before this does
+        app.add_system_to_stage(CoreStage::First, advance_game_time.system());
+    }
+}
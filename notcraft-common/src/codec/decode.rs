@@ -1,45 +1,293 @@
-// fn read_one_byte<R: Read>(reader: &mut R) -> Result<u8> {
-//     let mut buf = [0];
-//     reader.read_exact(&mut buf)?;
-//     Ok(buf[0])
-// }
-
-// fn decode_u8<R: Read>(reader: &mut R) -> Result<u8> {
-//     let mut cur = 0;
-//     let mut shift = 0;
-
-//     loop {
-//         let octet = read_one_byte(reader)?;
-//         cur |= ((octet & 0x7f) as u8) << shift;
-//         shift += 7;
-
-//         if octet & 0x80 == 0 {
-//             break;
-//         }
-//     }
-
-//     Ok(cur)
-// }
-
-// fn decode_i8<R: Read>(reader: &mut R) -> Result<i8> {
-//     let mut cur = 0;
-//     let mut shift = 0;
-
-//     loop {
-//         let octet = read_one_byte(reader)?;
-//         match octet & 0x80 != 0 {
-//             true => {
-//                 cur |= ((octet & 0x7f) as i8) << shift;
-//                 shift += 7;
-//             }
-//             false => {
-//                 let sign = octet & 0x40 != 0;
-//                 cur |= ((octet & 0x3f) as i8) << shift;
-//                 cur *= [1, -1][sign as usize];
-//                 break;
-//             }
-//         }
-//     }
-
-//     Ok(cur)
-// }
+use super::{ListKind, NodeKind};
+use crate::prelude::*;
+use std::io::Read;
+
+/// a decoded node of any kind. there are no typed decoders yet, so this is
+/// what everything decodes to for now.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    Map(Vec<(String, Node)>),
+    List(Vec<Node>),
+    Raw(Vec<u8>),
+    String(String),
+    Bool(bool),
+    Unsigned(u128),
+    Signed(i128),
+    Float32(f32),
+    Float64(f64),
+}
+
+impl Node {
+    pub fn get(&self, key: &str) -> Option<&Node> {
+        match self {
+            Node::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Node::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_unsigned(&self) -> Option<u128> {
+        match *self {
+            Node::Unsigned(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_signed(&self) -> Option<i128> {
+        match *self {
+            Node::Signed(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match *self {
+            Node::Float32(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Node::Float64(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Node::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Node]> {
+        match self {
+            Node::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&[(String, Node)]> {
+        match self {
+            Node::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    pub fn as_raw(&self) -> Option<&[u8]> {
+        match self {
+            Node::Raw(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+}
+
+fn read_one_byte<R: Read>(reader: &mut R) -> Result<u8> {
+    let mut buf = [0];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn decode_unsigned<R: Read>(reader: &mut R) -> Result<u128> {
+    let mut cur = 0u128;
+    let mut shift = 0;
+
+    loop {
+        let octet = read_one_byte(reader)?;
+        if shift >= 128 {
+            bail!("unsigned varint is too long");
+        }
+        cur |= ((octet & 0x7f) as u128) << shift;
+        shift += 7;
+
+        if octet & 0x80 == 0 {
+            break;
+        }
+    }
+
+    Ok(cur)
+}
+
+fn decode_signed<R: Read>(reader: &mut R) -> Result<i128> {
+    let mut cur = 0u128;
+    let mut shift = 0;
+
+    loop {
+        let octet = read_one_byte(reader)?;
+        if shift >= 128 {
+            bail!("signed varint is too long");
+        }
+
+        // the final octet has a clear continuation bit, and holds the sign in
+        // the next bit down, leaving 6 bits for the magnitude.
+        match octet & 0x80 != 0 {
+            true => {
+                cur |= ((octet & 0x7f) as u128) << shift;
+                shift += 7;
+            }
+            false => {
+                cur |= ((octet & 0x3f) as u128) << shift;
+                let magnitude = cur as i128;
+                return Ok(match octet & 0x40 != 0 {
+                    true => -magnitude,
+                    false => magnitude,
+                });
+            }
+        }
+    }
+}
+
+fn decode_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let len = decode_unsigned(reader)? as usize;
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn decode_string<R: Read>(reader: &mut R) -> Result<String> {
+    Ok(String::from_utf8(decode_bytes(reader)?)?)
+}
+
+fn decode_kind<R: Read>(reader: &mut R) -> Result<NodeKind> {
+    Ok(match read_one_byte(reader)? {
+        0 => NodeKind::Node,
+        1 => NodeKind::Map,
+        2 => NodeKind::List,
+        3 => NodeKind::Raw,
+        4 => NodeKind::String,
+        5 => NodeKind::Bool,
+        6 => NodeKind::UnsignedVarInt,
+        7 => NodeKind::SignedVarInt,
+        8 => NodeKind::Float32,
+        9 => NodeKind::Float64,
+        other => bail!("unknown node kind {}", other),
+    })
+}
+
+fn decode_map<R: Read>(reader: &mut R) -> Result<Vec<(String, Node)>> {
+    // see `mapNode` in module-level documentation for format specification. keys
+    // are never empty, so an empty key marks the end of the map.
+    let mut entries = Vec::new();
+    loop {
+        let key = decode_string(reader)?;
+        if key.is_empty() {
+            break Ok(entries);
+        }
+        let kind = decode_kind(reader)?;
+        entries.push((key, decode_with_kind(reader, kind)?));
+    }
+}
+
+fn decode_list<R: Read>(reader: &mut R) -> Result<Vec<Node>> {
+    let list_kind = match read_one_byte(reader)? {
+        0 => ListKind::Verbatim,
+        1 => ListKind::RunLength,
+        other => bail!("unknown list kind {}", other),
+    };
+
+    let mut items = Vec::new();
+    match list_kind {
+        // see `verbatimListNode` in module-level documentation for format specification
+        ListKind::Verbatim => {
+            let len = decode_unsigned(reader)? as usize;
+            let kind = decode_kind(reader)?;
+            for _ in 0..len {
+                items.push(decode_with_kind(reader, kind)?);
+            }
+        }
+        // see `rleListNode` in module-level documentation for format specification
+        ListKind::RunLength => {
+            let kind = decode_kind(reader)?;
+            loop {
+                let run_len = decode_unsigned(reader)? as usize;
+                if run_len == 0 {
+                    break;
+                }
+                let item = decode_with_kind(reader, kind)?;
+                items.extend(std::iter::repeat_n(item, run_len));
+            }
+        }
+    }
+
+    Ok(items)
+}
+
+fn decode_with_kind<R: Read>(reader: &mut R, kind: NodeKind) -> Result<Node> {
+    Ok(match kind {
+        NodeKind::Node => decode_node(reader)?,
+        NodeKind::Map => Node::Map(decode_map(reader)?),
+        NodeKind::List => Node::List(decode_list(reader)?),
+        NodeKind::Raw => Node::Raw(decode_bytes(reader)?),
+        NodeKind::String => Node::String(decode_string(reader)?),
+        NodeKind::Bool => Node::Bool(read_one_byte(reader)? != 0),
+        NodeKind::UnsignedVarInt => Node::Unsigned(decode_unsigned(reader)?),
+        NodeKind::SignedVarInt => Node::Signed(decode_signed(reader)?),
+        NodeKind::Float32 => {
+            let mut buf = [0; 4];
+            reader.read_exact(&mut buf)?;
+            Node::Float32(f32::from_be_bytes(buf))
+        }
+        NodeKind::Float64 => {
+            let mut buf = [0; 8];
+            reader.read_exact(&mut buf)?;
+            Node::Float64(f64::from_be_bytes(buf))
+        }
+    })
+}
+
+/// decodes a node along with the kind that precedes it, like the ones written
+/// by [`super::encode::encode_node`].
+pub fn decode_node<R: Read>(reader: &mut R) -> Result<Node> {
+    let kind = decode_kind(reader)?;
+    decode_with_kind(reader, kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::encode::encode_node;
+
+    fn roundtrip<T: crate::codec::encode::Encode<Vec<u8>>>(item: &T) -> Node {
+        let mut buf = Vec::new();
+        encode_node(&mut buf, item).unwrap();
+        decode_node(&mut buf.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_varint_roundtrip() {
+        for value in [0u64, 1, 0x7f, 0x80, 300, u32::MAX as u64, u64::MAX] {
+            assert_eq!(roundtrip(&value), Node::Unsigned(value as u128));
+        }
+        for value in [0i32, 1, -1, 63, -64, 300, -300, i32::MAX, i32::MIN + 1] {
+            assert_eq!(roundtrip(&value), Node::Signed(value as i128));
+        }
+    }
+
+    #[test]
+    fn test_map_roundtrip() {
+        struct Thing;
+        impl crate::codec::encode::Encode<Vec<u8>> for Thing {
+            const KIND: NodeKind = NodeKind::Map;
+
+            fn encode(&self, encoder: crate::codec::encode::Encoder<Vec<u8>>) -> Result<()> {
+                encoder.encode_map(|mut map| {
+                    map.entry("name").encode(&String::from("thing"))?;
+                    map.entry("pos").encode(&-1234i32)?;
+                    Ok(())
+                })
+            }
+        }
+
+        let node = roundtrip(&Thing);
+        assert_eq!(node.get("name").and_then(Node::as_str), Some("thing"));
+        assert_eq!(node.get("pos").and_then(Node::as_signed), Some(-1234));
+        assert_eq!(node.get("missing"), None);
+    }
+}
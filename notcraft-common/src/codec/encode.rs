@@ -36,7 +36,7 @@ impl<'w, W: Write> MapEncoderEntry<'w, '_, '_, W> {
     }
 
     // see `mapNode` in module-level documentation for format specification
-    pub fn encode<T: Encode<W>>(mut self, item: &T) -> Result<()> {
+    pub fn encode<T: Encode<W> + ?Sized>(mut self, item: &T) -> Result<()> {
         self.encode_header(<T as Encode<W>>::KIND)?;
         encode(self.encoder.writer, item)?;
         Ok(())
@@ -89,7 +89,7 @@ pub struct Encoder<'w, W> {
 }
 
 impl<'w, W: Write> Encoder<'w, W> {
-    pub fn encode<T: Encode<W>>(self, item: &T) -> Result<()> {
+    pub fn encode<T: Encode<W> + ?Sized>(self, item: &T) -> Result<()> {
         encode(self.writer, item)
     }
 
@@ -133,6 +133,13 @@ fn encode<W, T: Encode<W> + ?Sized>(writer: &mut W, item: &T) -> Result<()> {
     T::encode(item, Encoder { writer })
 }
 
+/// encodes a node preceded by its kind, so that it can be decoded without
+/// knowing what kind of node to expect ahead of time.
+pub fn encode_node<W: Write, T: Encode<W>>(writer: &mut W, item: &T) -> Result<()> {
+    encode_base(writer, &FixedInt(<T as Encode<W>>::KIND as u8))?;
+    encode(writer, item)
+}
+
 fn encode_verbatim_list<W, I>(writer: &mut W, mut iter: I) -> Result<()>
 where
     I: ExactSizeIterator,
@@ -239,6 +246,10 @@ pub struct Verbatim<T>(pub T);
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
 pub struct RunLength<T>(pub T);
 
+/// a sequence of bytes that's written out as-is.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub struct Raw<T>(pub T);
+
 impl<W: std::io::Write, T: Encode<W>> Encode<W> for Verbatim<Vec<T>> {
     const KIND: NodeKind = NodeKind::List;
 
@@ -287,6 +298,25 @@ impl<W: Write> Encode<W> for str {
     }
 }
 
+impl<W: Write> Encode<W> for String {
+    const KIND: NodeKind = NodeKind::String;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        <str as Encode<W>>::encode(self, encoder)
+    }
+}
+
+impl<W: Write> Encode<W> for Raw<&'_ [u8]> {
+    const KIND: NodeKind = NodeKind::Raw;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        // see `byteSequence` in module-level documentation for format specification
+        <_ as BaseEncode<W>>::encode(&VarInt(self.0.len()), encoder.writer)?;
+        encoder.writer.write_all(self.0)?;
+        Ok(())
+    }
+}
+
 impl<W: Write> BaseEncode<W> for bool {
     fn encode(&self, writer: &mut W) -> Result<()> {
         writer.write_all(&[*self as u8])?;
@@ -367,10 +397,11 @@ macro_rules! impl_numeric_encode {
         $(impl<W: Write> BaseEncode<W> for VarInt<$type> {
             fn encode(&self, writer: &mut W) -> Result<()> {
                 let VarInt(value) = *self;
-                let (sign, unsigned) = match value >= 0 {
-                    true => (0, value as u8),
-                    false => (0x40, value.abs() as u8),
+                let sign = match value >= 0 {
+                    true => 0,
+                    false => 0x40,
                 };
+                let unsigned = value.unsigned_abs();
 
                 let mut cur = unsigned;
                 while cur > 0x3f {
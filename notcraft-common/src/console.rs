@@ -0,0 +1,160 @@
+//! a bare-bones command console.
+//!
+//! commands are read line by line from stdin, meaning they can be typed into
+//! the terminal that the game was started from, and from [`ConsoleLine`]
+//! events, which is how the client's in-game console sends them. each line
+//! becomes a [`ConsoleCommand`] event, which is then handled by whichever
+//! systems care about it.
+
+use std::{io::BufRead, str::FromStr};
+
+use crate::{lang::tr, prelude::*, util::ChannelPair};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ConsoleCommand {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl ConsoleCommand {
+    /// parses a line like `/locate water 64`. the leading slash is optional.
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        let line = line.strip_prefix('/').unwrap_or(line);
+        let mut words = line.split_whitespace().map(String::from);
+        Some(Self {
+            name: words.next()?,
+            args: words.collect(),
+        })
+    }
+
+    pub fn is(&self, name: &str) -> bool {
+        self.name == name
+    }
+
+    pub fn arg<T>(&self, idx: usize) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        match self.args.get(idx) {
+            Some(arg) => arg
+                .parse()
+                .map_err(|err| anyhow!(tr!("console.bad_argument", arg, err))),
+            None => bail!(tr!("console.missing_argument", idx + 1)),
+        }
+    }
+
+    /// like [`ConsoleCommand::arg`], but gives back `None` if the argument
+    /// wasn't passed at all.
+    pub fn optional_arg<T>(&self, idx: usize) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        match idx < self.args.len() {
+            true => self.arg(idx).map(Some),
+            false => Ok(None),
+        }
+    }
+}
+
+/// a line typed into the console from somewhere other than stdin.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConsoleLine(pub String);
+
+/// the commands that something has said it will handle, along with how to use
+/// them.
+#[derive(Clone, Debug, Default)]
+pub struct ConsoleCommands {
+    commands: Vec<(String, String)>,
+}
+
+impl ConsoleCommands {
+    pub fn usage(&self, name: &str) -> Option<&str> {
+        self.commands
+            .iter()
+            .find(|(command, _)| command == name)
+            .map(|(_, usage)| &**usage)
+    }
+}
+
+/// lets the console know about a command, so that it shows up in `/help` and
+/// doesn't get reported as unknown.
+pub fn add_console_command<N, U>(app: &mut AppBuilder, name: N, usage: U)
+where
+    N: Into<String>,
+    U: Into<String>,
+{
+    let world = app.world_mut();
+    if !world.contains_resource::<ConsoleCommands>() {
+        world.insert_resource(ConsoleCommands::default());
+    }
+    let mut commands = world.get_resource_mut::<ConsoleCommands>().unwrap();
+    commands.commands.push((name.into(), usage.into()));
+}
+
+struct ConsoleInput {
+    lines: ChannelPair<String>,
+}
+
+fn spawn_stdin_reader(lines: crossbeam_channel::Sender<String>) {
+    let result = std::thread::Builder::new()
+        .name("console".into())
+        .spawn(move || {
+            let stdin = std::io::stdin();
+            for line in stdin.lock().lines().map_while(Result::ok) {
+                if lines.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+    if let Err(err) = result {
+        log::error!("failed to start console thread: {}", err);
+    }
+}
+
+fn emit_console_commands(
+    input: Res<ConsoleInput>,
+    known: Res<ConsoleCommands>,
+    mut typed: EventReader<ConsoleLine>,
+    mut events: EventWriter<ConsoleCommand>,
+) {
+    let typed = typed.iter().map(|ConsoleLine(line)| line.clone());
+    for line in typed.chain(input.lines.rx.try_iter()) {
+        let command = match ConsoleCommand::parse(&line) {
+            Some(command) => command,
+            None => continue,
+        };
+
+        if command.is("help") {
+            for (_, usage) in known.commands.iter() {
+                println!("{}", usage);
+            }
+        } else if known.usage(&command.name).is_none() {
+            println!("{}", tr!("console.unknown_command", command.name));
+        } else {
+            events.send(command);
+        }
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub struct ConsoleLabel;
+
+#[derive(Debug, Default)]
+pub struct ConsolePlugin {}
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let lines = ChannelPair::default();
+        spawn_stdin_reader(lines.sender());
+
+        app.insert_resource(ConsoleInput { lines });
+        app.add_event::<ConsoleLine>();
+        app.add_event::<ConsoleCommand>();
+        add_console_command(app, "help", "/help");
+        app.add_system(emit_console_commands.system().label(ConsoleLabel));
+    }
+}
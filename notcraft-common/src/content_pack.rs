@@ -0,0 +1,296 @@
+//! content packs, which add to or change the game's blocks, textures, sounds,
+//! and recipes, without touching the base resources.
+//!
+//! a content pack is a directory in `resources/packs`, laid out just like
+//! `resources` itself. it can have any of:
+//!
+//! - a `blocks.json`, with blocks, texture pools, and animations in the same
+//!   format as `resources/blocks.json`.
+//! - a `textures/blocks` directory, with the textures its blocks use.
+//! - an `audio/manifest.ron`, along with the sounds it uses in `audio`.
+//! - a `recipes.ron`.
+//!
+//! packs are only loaded when they're enabled, in the order they were enabled
+//! in. anything a pack defines with the same name as something from the base
+//! resources or from an earlier pack replaces it, and each of those is kept as
+//! a [`PackConflict`], so that packs stepping on each other's toes don't go
+//! unnoticed.
+
+use crate::{lang::tr, notification::Notify, prelude::*};
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+pub const CONTENT_PACK_DIR: &str = "resources/packs";
+
+/// what the base resources are called when they're part of a conflict.
+pub const BASE_PACK: &str = "base";
+
+/// something that was defined more than once.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackConflict {
+    /// what kind of thing was defined more than once, like `block` or `recipe`.
+    pub kind: &'static str,
+    pub name: String,
+    /// the pack whose definition was replaced, which is [`BASE_PACK`] for the
+    /// base resources.
+    pub replaced: String,
+    pub by: String,
+}
+
+impl fmt::Display for PackConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} '{}' from '{}' was replaced by '{}'",
+            self.kind, self.name, self.replaced, self.by
+        )
+    }
+}
+
+/// the content packs that are enabled, and what they've been caught
+/// conflicting over so far.
+#[derive(Clone, Debug, Default)]
+pub struct ContentPacks {
+    dir: PathBuf,
+    /// in the order they're loaded, so later packs win conflicts.
+    enabled: Vec<String>,
+    /// enabled packs that aren't installed.
+    missing: Vec<String>,
+    conflicts: Vec<PackConflict>,
+}
+
+impl ContentPacks {
+    /// the packs named in `enabled` that are installed in `dir`, in the order
+    /// they're listed.
+    pub fn open<P: AsRef<Path>>(dir: P, enabled: &[String]) -> Self {
+        let dir = dir.as_ref();
+        let mut packs = Self {
+            dir: dir.into(),
+            ..Default::default()
+        };
+
+        for name in enabled {
+            if packs.enabled.contains(name) {
+                log::warn!("content pack '{}' is enabled more than once", name);
+            } else if dir.join(name).is_dir() {
+                log::info!("enabled content pack '{}'", name);
+                packs.enabled.push(name.clone());
+            } else {
+                log::error!("content pack '{}' isn't installed in {:?}", name, dir);
+                packs.missing.push(name.clone());
+            }
+        }
+
+        packs
+    }
+
+    pub fn enabled(&self) -> &[String] {
+        &self.enabled
+    }
+
+    pub fn missing(&self) -> &[String] {
+        &self.missing
+    }
+
+    pub fn conflicts(&self) -> &[PackConflict] {
+        &self.conflicts
+    }
+
+    /// `base`, followed by `path` in each enabled pack that has it, along with
+    /// the name of the pack each one comes from.
+    pub fn layers(&self, base: &Path, path: &str) -> Vec<(String, PathBuf)> {
+        let mut layers = vec![(BASE_PACK.to_string(), base.to_path_buf())];
+        for name in self.enabled.iter() {
+            let path = self.dir.join(name).join(path);
+            if path.exists() {
+                layers.push((name.clone(), path));
+            }
+        }
+        layers
+    }
+
+    /// combines the named definitions from each layer, as given by
+    /// [`ContentPacks::layers`]. a definition that shows up again in a later
+    /// layer replaces the earlier one, but stays where the earlier one was,
+    /// and the replacement is recorded as a conflict.
+    pub fn merge<T, I>(&mut self, kind: &'static str, layers: I) -> Vec<(String, T)>
+    where
+        I: IntoIterator<Item = (String, Vec<(String, T)>)>,
+    {
+        let mut merged = Vec::<(String, T)>::new();
+        let mut owners = HashMap::<String, (usize, String)>::new();
+
+        for (pack, definitions) in layers {
+            for (name, definition) in definitions {
+                match owners.get_mut(&name) {
+                    Some((idx, owner)) => {
+                        self.record(kind, &name, owner, &pack);
+                        merged[*idx].1 = definition;
+                        *owner = pack.clone();
+                    }
+                    None => {
+                        owners.insert(name.clone(), (merged.len(), pack.clone()));
+                        merged.push((name, definition));
+                    }
+                }
+            }
+        }
+
+        merged
+    }
+
+    /// records that each of `packs` has its own copy of `name`, where the last
+    /// one is the copy that's used.
+    pub fn record_shadowed(&mut self, kind: &'static str, name: &str, packs: &[&str]) {
+        for pair in packs.windows(2) {
+            self.record(kind, name, pair[0], pair[1]);
+        }
+    }
+
+    fn record(&mut self, kind: &'static str, name: &str, replaced: &str, by: &str) {
+        let conflict = PackConflict {
+            kind,
+            name: name.into(),
+            replaced: replaced.into(),
+            by: by.into(),
+        };
+        log::warn!("content pack conflict: {}", conflict);
+        self.conflicts.push(conflict);
+    }
+}
+
+/// the names of every content pack that's installed in `dir`, in alphabetical
+/// order.
+pub fn available_packs<P: AsRef<Path>>(dir: P) -> Result<Vec<String>> {
+    let dir = dir.as_ref();
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut packs = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            packs.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    packs.sort();
+    Ok(packs)
+}
+
+/// runs `func` with the app's content packs, or with no packs at all when
+/// there's no [`ContentPacks`] resource.
+pub fn with_content_packs<R, F>(app: &mut AppBuilder, func: F) -> R
+where
+    F: FnOnce(&mut ContentPacks) -> R,
+{
+    match app.world_mut().get_resource_mut::<ContentPacks>() {
+        Some(mut packs) => func(&mut packs),
+        None => func(&mut ContentPacks::default()),
+    }
+}
+
+// conflicts can turn up whenever something is loaded, so this keeps an eye out
+// for new ones instead of only checking once at startup.
+fn report_pack_problems(
+    packs: Res<ContentPacks>,
+    mut reported: Local<Option<usize>>,
+    mut notify_events: EventWriter<Notify>,
+) {
+    if reported.is_none() {
+        for name in packs.missing() {
+            notify_events.send(Notify::error(tr!("notify.pack_missing", name)));
+        }
+    }
+
+    let reported = reported.get_or_insert(0);
+    let new_conflicts = packs.conflicts().len() - *reported;
+    if new_conflicts > 0 {
+        notify_events.send(Notify::warning(tr!("notify.pack_conflicts", new_conflicts)));
+        *reported = packs.conflicts().len();
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ContentPackPlugin {}
+
+impl Plugin for ContentPackPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ContentPacks>();
+        app.add_system(report_pack_problems.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{crafting::RecipeRegistry, world::registry::load_registry_with_packs};
+
+    fn layer(pack: &str, names: &[(&str, u32)]) -> (String, Vec<(String, u32)>) {
+        let names = names.iter().map(|&(name, value)| (name.to_string(), value));
+        (pack.to_string(), names.collect())
+    }
+
+    #[test]
+    fn later_packs_replace_definitions_in_place() {
+        let mut packs = ContentPacks::default();
+        let merged = packs.merge(
+            "block",
+            vec![
+                layer(BASE_PACK, &[("air", 0), ("stone", 1), ("dirt", 2)]),
+                layer("shiny", &[("stone", 10), ("gem", 11)]),
+                layer("shinier", &[("stone", 20)]),
+            ],
+        );
+
+        let expected = [("air", 0), ("stone", 20), ("dirt", 2), ("gem", 11)];
+        let expected = expected.map(|(name, value)| (name.to_string(), value));
+        assert_eq!(merged, expected);
+
+        let conflicts = packs
+            .conflicts()
+            .iter()
+            .map(|conflict| (&*conflict.replaced, &*conflict.by))
+            .collect::<Vec<_>>();
+        assert_eq!(conflicts, [(BASE_PACK, "shiny"), ("shiny", "shinier")]);
+    }
+
+    #[test]
+    fn example_pack_adds_to_the_base_resources() {
+        let resources = concat!(env!("CARGO_MANIFEST_DIR"), "/../resources");
+        let enabled = ["example".to_string()];
+        let mut packs = ContentPacks::open(format!("{}/packs", resources), &enabled);
+
+        let blocks = format!("{}/blocks.json", resources);
+        let registry = load_registry_with_packs(blocks, &mut packs).unwrap();
+        let obsidian = registry.get(registry.lookup("reinforced_obsidian"));
+        assert_eq!(obsidian.display_name(), "Reinforced Obsidian");
+        assert_eq!(obsidian.pack(), "example");
+        assert_eq!(registry.get(registry.lookup("stone")).pack(), BASE_PACK);
+
+        let recipes = format!("{}/recipes.ron", resources);
+        let recipes = RecipeRegistry::load_with_packs(recipes, &mut packs).unwrap();
+        assert!(recipes
+            .iter()
+            .any(|(_, recipe)| recipe.result.item == "reinforced_obsidian"));
+
+        assert_eq!(packs.conflicts(), []);
+    }
+
+    #[test]
+    fn missing_packs_are_skipped() {
+        let dir = std::env::temp_dir().join(format!("notcraft-packs-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("real")).unwrap();
+
+        let enabled = ["real", "imaginary", "real"].map(String::from);
+        let packs = ContentPacks::open(&dir, &enabled);
+        assert_eq!(packs.enabled(), ["real"]);
+        assert_eq!(packs.missing(), ["imaginary"]);
+        assert_eq!(available_packs(&dir).unwrap(), ["real"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+//! data-driven crafting recipes.
+//!
+//! recipes are loaded from `resources/recipes.ron` into the [`RecipeRegistry`]
+//! resource, along with the `recipes.ron` of every enabled content pack.
+//! everything else can add its own recipes to the registry at startup, too.
+
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    content_pack::{with_content_packs, ContentPacks, BASE_PACK},
+    inventory::{Inventory, ItemStack},
+    prelude::*,
+    saved_components::register_saved_component,
+    world::registry::BlockRegistry,
+};
+
+/// a recipe that only cares about which ingredients are present, and not how
+/// they're arranged.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Recipe {
+    pub name: String,
+    pub ingredients: Vec<ItemStack>,
+    pub result: ItemStack,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct RecipeId(usize);
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct RecipeRegistry {
+    recipes: Vec<Recipe>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+struct RecipeManifest(Vec<Recipe>);
+
+impl RecipeRegistry {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::load_with_packs(path, &mut ContentPacks::default())
+    }
+
+    /// loads the recipes in `path`, along with each enabled content pack's
+    /// `recipes.ron`. a pack's recipe replaces any recipe with the same name.
+    pub fn load_with_packs<P: AsRef<Path>>(path: P, packs: &mut ContentPacks) -> Result<Self> {
+        let mut layers = vec![];
+        for (pack, path) in packs.layers(path.as_ref(), "recipes.ron") {
+            let manifest = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| Ok(ron::from_str::<RecipeManifest>(&text)?));
+            match manifest {
+                Ok(manifest) => {
+                    let named = manifest
+                        .0
+                        .into_iter()
+                        .map(|recipe| (recipe.name.clone(), recipe));
+                    layers.push((pack, named.collect()));
+                }
+                Err(err) if pack == BASE_PACK => return Err(err),
+                Err(err) => log::error!(
+                    "failed to read recipes from content pack '{}': {}",
+                    pack,
+                    err
+                ),
+            }
+        }
+
+        let mut registry = Self::default();
+        for (_, recipe) in packs.merge("recipe", layers) {
+            registry.register(recipe);
+        }
+
+        Ok(registry)
+    }
+
+    pub fn register(&mut self, recipe: Recipe) -> RecipeId {
+        let id = RecipeId(self.recipes.len());
+        self.recipes.push(recipe);
+        id
+    }
+
+    pub fn get(&self, id: RecipeId) -> &Recipe {
+        &self.recipes[id.0]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (RecipeId, &Recipe)> {
+        self.recipes
+            .iter()
+            .enumerate()
+            .map(|(idx, recipe)| (RecipeId(idx), recipe))
+    }
+
+    pub fn len(&self) -> usize {
+        self.recipes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recipes.is_empty()
+    }
+
+    pub fn can_craft(&self, inventory: &Inventory, id: RecipeId) -> bool {
+        inventory.contains_all(&self.get(id).ingredients)
+    }
+
+    /// swaps the recipe's ingredients for its result. returns false and leaves
+    /// the inventory untouched if the inventory doesn't have everything the
+    /// recipe needs.
+    pub fn craft(&self, inventory: &mut Inventory, id: RecipeId) -> bool {
+        if !self.can_craft(inventory, id) {
+            return false;
+        }
+
+        let recipe = self.get(id);
+        for ingredient in recipe.ingredients.iter() {
+            inventory.remove(&ingredient.item, ingredient.count);
+        }
+        inventory.add(&recipe.result.item, recipe.result.count);
+
+        true
+    }
+}
+
+/// asks for `entity` to craft a recipe using the items in its [`Inventory`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CraftEvent {
+    pub entity: Entity,
+    pub recipe: RecipeId,
+}
+
+pub fn handle_craft_events(
+    recipes: Res<RecipeRegistry>,
+    mut craft_events: EventReader<CraftEvent>,
+    mut query: Query<&mut Inventory>,
+) {
+    for event in craft_events.iter() {
+        if let Ok(mut inventory) = query.get_mut(event.entity) {
+            let recipe = recipes.get(event.recipe);
+            if recipes.craft(&mut inventory, event.recipe) {
+                log::info!("crafted {}", recipe.name);
+            } else {
+                log::info!("missing ingredients for {}", recipe.name);
+            }
+        }
+    }
+}
+
+// all items are blocks right now, so a recipe that mentions something that
+// isn't a block is almost certainly a typo.
+fn validate_recipes(registry: &BlockRegistry, recipes: &RecipeRegistry) {
+    for (_, recipe) in recipes.iter() {
+        let items = recipe.ingredients.iter().chain(Some(&recipe.result));
+        for stack in items {
+            if registry.try_lookup(&stack.item).is_none() {
+                let (name, item) = (&recipe.name, &stack.item);
+                log::warn!("recipe '{name}' uses unknown item '{item}'");
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CraftingPlugin {
+    recipes_path: Option<PathBuf>,
+}
+
+impl CraftingPlugin {
+    pub fn with_recipes_path<P: AsRef<Path>>(mut self, path: &P) -> Self {
+        self.recipes_path = Some(path.as_ref().into());
+        self
+    }
+}
+
+impl Plugin for CraftingPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let path = self
+            .recipes_path
+            .clone()
+            .unwrap_or_else(|| "resources/recipes.ron".into());
+
+        let recipes =
+            with_content_packs(app, |packs| RecipeRegistry::load_with_packs(&path, packs));
+        let recipes = match recipes {
+            Ok(recipes) => recipes,
+            Err(err) => {
+                log::error!("failed to load recipes from '{}': {}", path.display(), err);
+                RecipeRegistry::default()
+            }
+        };
+
+        if let Some(registry) = app.world().get_resource::<Arc<BlockRegistry>>() {
+            validate_recipes(registry, &recipes);
+        }
+
+        app.insert_resource(recipes);
+        register_saved_component::<Inventory>(app);
+        app.add_event::<CraftEvent>();
+        app.add_system(handle_craft_events.system());
+    }
+}
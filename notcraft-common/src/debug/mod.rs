@@ -1,6 +1,8 @@
+pub mod recording;
+
 #[cfg(feature = "debug")]
 mod inner {
-    use super::DebugEvent;
+    use super::{recording, DebugEvent};
     use crate::util::ChannelPair;
     use std::{
         any::{Any, TypeId},
@@ -66,6 +68,7 @@ mod inner {
 
     pub fn send_debug_event<E: DebugEvent>(event: E) {
         if let Some(channel) = DEBUG_EVENTS.pin().get(&TypeId::of::<E>()) {
+            recording::record_event(E::name(), event.summarize());
             let tx = &channel.downcast_ref::<DebugChannel<E>>().unwrap().inner.tx;
             tx.send(event).unwrap();
         }
@@ -86,6 +89,7 @@ mod inner {
         for value in DEBUG_EVENTS.pin().values() {
             value.clear();
         }
+        recording::flush_recording();
     }
 }
 
@@ -113,7 +117,7 @@ mod inner {
 
 pub use inner::*;
 
-pub trait DebugEvent: Send + Sync + 'static {
+pub trait DebugEvent: recording::Summarize + Send + Sync + 'static {
     fn name() -> &'static str;
 }
 
@@ -0,0 +1,355 @@
+//! recording debug events to a file, and making sense of them afterwards.
+//!
+//! a debug log starts with a big-endian `u64` format version, and is followed
+//! by one map node per recorded event, each preceded by its node kind. see the
+//! [`crate::codec`] module for details on how nodes are encoded.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+    time::Duration,
+};
+
+use crate::{
+    codec::{
+        decode::{decode_node, Node},
+        encode::{Encode, Encoder},
+        NodeKind,
+    },
+    prelude::*,
+};
+
+pub const LOG_FORMAT_VERSION: u64 = 1;
+
+/// a section that is meshed at least this many times within
+/// [`REMESH_STORM_WINDOW`] is considered to be in a remesh storm.
+pub const REMESH_STORM_THRESHOLD: usize = 4;
+pub const REMESH_STORM_WINDOW: Duration = Duration::from_secs(1);
+
+/// the parts of a debug event that get recorded.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct EventSummary {
+    pub kind: &'static str,
+    /// the chunk or chunk section that the event happened to. chunk columns
+    /// use a y coordinate of 0.
+    pub pos: [i32; 3],
+}
+
+pub trait Summarize {
+    fn summarize(&self) -> EventSummary;
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct RecordedEvent {
+    /// the time since recording started.
+    pub time: Duration,
+    /// the name of the debug event stream, like `world-load`.
+    pub stream: String,
+    pub kind: String,
+    pub pos: [i32; 3],
+}
+
+impl<W: Write> Encode<W> for RecordedEvent {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode_map(|mut map| {
+            map.entry("time").encode(&(self.time.as_micros() as u64))?;
+            map.entry("stream").encode(&self.stream)?;
+            map.entry("kind").encode(&self.kind)?;
+            map.entry("x").encode(&self.pos[0])?;
+            map.entry("y").encode(&self.pos[1])?;
+            map.entry("z").encode(&self.pos[2])?;
+            Ok(())
+        })
+    }
+}
+
+impl RecordedEvent {
+    fn from_node(node: &Node) -> Result<Self> {
+        let missing = |key| anyhow!("missing field '{}'", key);
+        let field = |key| node.get(key).ok_or_else(|| missing(key));
+        let string = |key| match field(key)?.as_str() {
+            Some(value) => Ok(value.to_owned()),
+            None => bail!("field '{}' is not a string", key),
+        };
+        let coord = |key| match field(key)?.as_signed() {
+            Some(value) => Ok(value as i32),
+            None => bail!("field '{}' is not a signed integer", key),
+        };
+
+        let time = match field("time")?.as_unsigned() {
+            Some(micros) => Duration::from_micros(micros as u64),
+            None => bail!("field 'time' is not an unsigned integer"),
+        };
+
+        Ok(Self {
+            time,
+            stream: string("stream")?,
+            kind: string("kind")?,
+            pos: [coord("x")?, coord("y")?, coord("z")?],
+        })
+    }
+}
+
+#[cfg(feature = "debug")]
+mod recorder {
+    use super::{EventSummary, RecordedEvent, LOG_FORMAT_VERSION};
+    use crate::{codec::encode::encode_node, prelude::*};
+    use parking_lot::Mutex;
+    use std::{
+        fs::File,
+        io::{BufWriter, Write},
+        path::Path,
+        time::Instant,
+    };
+
+    struct Recorder {
+        start: Instant,
+        out: BufWriter<File>,
+    }
+
+    lazy_static::lazy_static! {
+        static ref RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+    }
+
+    /// starts writing every enabled debug event to a new log at `path`.
+    pub fn start_recording(path: &Path) -> Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&LOG_FORMAT_VERSION.to_be_bytes())?;
+
+        *RECORDER.lock() = Some(Recorder {
+            start: Instant::now(),
+            out,
+        });
+        Ok(())
+    }
+
+    pub(crate) fn record_event(stream: &'static str, summary: EventSummary) {
+        let mut recorder = RECORDER.lock();
+        if let Some(inner) = recorder.as_mut() {
+            let event = RecordedEvent {
+                time: inner.start.elapsed(),
+                stream: stream.into(),
+                kind: summary.kind.into(),
+                pos: summary.pos,
+            };
+
+            if let Err(err) = encode_node(&mut inner.out, &event) {
+                log::error!("failed to record debug event, stopping recording: {}", err);
+                *recorder = None;
+            }
+        }
+    }
+
+    pub(crate) fn flush_recording() {
+        let mut recorder = RECORDER.lock();
+        if let Some(inner) = recorder.as_mut() {
+            if let Err(err) = inner.out.flush() {
+                log::error!("failed to flush debug log, stopping recording: {}", err);
+                *recorder = None;
+            }
+        }
+    }
+}
+
+// debug events are never sent without the debug feature, so there would be
+// nothing to record.
+#[cfg(not(feature = "debug"))]
+mod recorder {
+    use crate::prelude::*;
+    use std::path::Path;
+
+    pub fn start_recording(_path: &Path) -> Result<()> {
+        bail!("debug events can only be recorded with the debug feature enabled")
+    }
+}
+
+pub use recorder::*;
+
+pub fn read_log(path: &Path) -> Result<Vec<RecordedEvent>> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut version = [0; 8];
+    reader.read_exact(&mut version)?;
+    let version = u64::from_be_bytes(version);
+    if version != LOG_FORMAT_VERSION {
+        bail!("unsupported debug log version {}", version);
+    }
+
+    let mut events = Vec::new();
+    // the log might have been cut off partway through an event if the game
+    // didn't exit cleanly, so we keep everything that made it.
+    while !reader.fill_buf()?.is_empty() {
+        match decode_node(&mut reader).and_then(|node| RecordedEvent::from_node(&node)) {
+            Ok(event) => events.push(event),
+            Err(err) => {
+                let count = events.len();
+                log::warn!("stopped reading debug log after {} events: {}", count, err);
+                break;
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct RemeshStorm {
+    pub pos: [i32; 3],
+    pub start: Duration,
+    /// how many times the section was meshed within [`REMESH_STORM_WINDOW`]
+    /// of `start`.
+    pub meshes: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct Rate {
+    pub total: usize,
+    pub average_per_second: f32,
+    pub peak_per_second: usize,
+}
+
+impl Rate {
+    fn from_times<'a, I>(times: I, duration: Duration) -> Self
+    where
+        I: Iterator<Item = &'a Duration>,
+    {
+        let mut buckets = HashMap::<u64, usize>::new();
+        for time in times {
+            *buckets.entry(time.as_secs()).or_default() += 1;
+        }
+
+        let total = buckets.values().sum::<usize>();
+        Self {
+            total,
+            average_per_second: total as f32 / duration.as_secs_f32().max(1.0),
+            peak_per_second: buckets.values().copied().max().unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct LogSummary {
+    pub duration: Duration,
+    /// the number of events of each kind, keyed by stream and then kind.
+    pub counts: BTreeMap<(String, String), usize>,
+    pub chunk_loads: Rate,
+    pub section_loads: Rate,
+    pub meshes: Rate,
+    pub remesh_storms: Vec<RemeshStorm>,
+}
+
+fn find_remesh_storms(pos: [i32; 3], times: &[Duration], storms: &mut Vec<RemeshStorm>) {
+    let mut start = 0;
+    while start < times.len() {
+        let window_end = times[start] + REMESH_STORM_WINDOW;
+        let end = start + times[start..].partition_point(|&time| time <= window_end);
+
+        if end - start >= REMESH_STORM_THRESHOLD {
+            storms.push(RemeshStorm {
+                pos,
+                start: times[start],
+                meshes: end - start,
+            });
+            start = end;
+        } else {
+            start += 1;
+        }
+    }
+}
+
+fn times_of<'a>(
+    events: &'a [RecordedEvent],
+    stream: &'a str,
+    kind: &'a str,
+) -> impl Iterator<Item = &'a Duration> + 'a {
+    events
+        .iter()
+        .filter(move |event| event.stream == stream && event.kind == kind)
+        .map(|event| &event.time)
+}
+
+pub fn summarize_log(events: &[RecordedEvent]) -> LogSummary {
+    let end = events.iter().map(|event| event.time).max();
+    let duration = end.unwrap_or_default();
+    let rate_of = |stream, kind| Rate::from_times(times_of(events, stream, kind), duration);
+
+    let mut counts = BTreeMap::new();
+    for event in events {
+        let key = (event.stream.clone(), event.kind.clone());
+        *counts.entry(key).or_default() += 1;
+    }
+
+    let mut mesh_times = HashMap::<_, Vec<_>>::new();
+    for event in events {
+        if event.stream == "mesher" && event.kind == "meshed" {
+            mesh_times.entry(event.pos).or_default().push(event.time);
+        }
+    }
+
+    let mut remesh_storms = Vec::new();
+    for (pos, mut times) in mesh_times {
+        times.sort();
+        find_remesh_storms(pos, &times, &mut remesh_storms);
+    }
+    remesh_storms.sort_by_key(|storm| (std::cmp::Reverse(storm.meshes), storm.start));
+
+    LogSummary {
+        duration,
+        counts,
+        chunk_loads: rate_of("world-load", "loaded"),
+        section_loads: rate_of("world-load", "loaded_section"),
+        meshes: rate_of("mesher", "meshed"),
+        remesh_storms,
+    }
+}
+
+impl fmt::Display for LogSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "session length: {:.1}s", self.duration.as_secs_f32())?;
+
+        writeln!(f, "events:")?;
+        for ((stream, kind), count) in self.counts.iter() {
+            writeln!(f, "  {}/{}: {}", stream, kind, count)?;
+        }
+
+        let rates = [
+            ("chunks loaded", &self.chunk_loads),
+            ("sections loaded", &self.section_loads),
+            ("sections meshed", &self.meshes),
+        ];
+        for (name, rate) in rates {
+            writeln!(
+                f,
+                "{}: {} total, {:.1}/s on average, {}/s at peak",
+                name, rate.total, rate.average_per_second, rate.peak_per_second
+            )?;
+        }
+
+        writeln!(
+            f,
+            "remesh storms (sections meshed {}+ times within {:?}): {}",
+            REMESH_STORM_THRESHOLD,
+            REMESH_STORM_WINDOW,
+            self.remesh_storms.len()
+        )?;
+        for storm in self.remesh_storms.iter().take(10) {
+            let [x, y, z] = storm.pos;
+            writeln!(
+                f,
+                "  section ({}, {}, {}) meshed {} times starting at {:.1}s",
+                x,
+                y,
+                z,
+                storm.meshes,
+                storm.start.as_secs_f32()
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+//! explosions, which blow craters into the terrain and throw entities away
+//! from them.
+//!
+//! anything can set off an explosion by sending an [`ExplosionEvent`]. each
+//! block inside of the blast gets a chance to be destroyed, which is lower the
+//! further it is from the center and the harder it is, and blocks at least as
+//! hard as the explosion is powerful never break at all. every block that's
+//! destroyed is queued in the same [`ChunkAccess`], so the whole crater is
+//! written to the world at once.
+//!
+//! explosive blocks, like TNT, are lit by switching them to their lit state,
+//! either by a player, a circuit, or another explosion. lit explosives are
+//! replaced by a [`PrimedExplosive`] entity, which falls like anything else
+//! and explodes once its fuse runs out.
+
+use crate::{
+    aabb::Aabb,
+    clock::GameTime,
+    game_state::playing,
+    physics::{AabbCollider, PhysicsDisabled, RigidBody},
+    prelude::*,
+    transform::Transform,
+    world::{
+        chunk::ChunkAccess,
+        circuit::SIGNAL_ON_STATE,
+        registry::{BlockId, AIR_BLOCK},
+        BlockPos, BlockUpdateEvent,
+    },
+};
+use nalgebra::{Point3, Vector3};
+use rand::Rng;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// explosions can't reach any further than this, so that a single one can't
+/// try to destroy an unbounded number of blocks.
+pub const MAX_EXPLOSION_RADIUS: f32 = 16.0;
+
+/// how much speed, in blocks per second, an entity right at the center of an
+/// explosion gets per point of power.
+const KNOCKBACK_PER_POWER: f32 = 4.0;
+
+/// the state explosive blocks are switched to when they're lit. it's the same
+/// as a circuit's on state, so that powering an explosive lights it.
+pub const LIT_STATE: &str = SIGNAL_ON_STATE;
+
+/// the length of a primed explosive's sides, in blocks. it's a little smaller
+/// than a block so that it can fall down a one block wide hole.
+pub const PRIMED_EXPLOSIVE_SIZE: f32 = 0.98;
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExplosiveProperties {
+    /// how many seconds it takes to explode after being lit.
+    pub fuse: f32,
+    pub radius: f32,
+    pub power: f32,
+}
+
+/// an explosive that's been lit, and is waiting for its fuse to run out.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PrimedExplosive {
+    /// the block that was lit, which is what this looks like.
+    pub block: BlockId,
+    pub explosive: ExplosiveProperties,
+    /// how many seconds are left before it explodes.
+    pub remaining: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ExplosionEvent {
+    pub center: Point3<f32>,
+    /// how far, in blocks, the explosion reaches.
+    pub radius: f32,
+    /// how strong the explosion is at its center. blocks with a hardness at
+    /// least this high are never destroyed.
+    pub power: f32,
+}
+
+impl ExplosionEvent {
+    /// how much of the explosion's power reaches `pos`, from 1 at the center
+    /// down to 0 at its edge.
+    fn falloff(&self, pos: Point3<f32>) -> f32 {
+        let radius = self.radius.min(MAX_EXPLOSION_RADIUS);
+        f32::max(0.0, 1.0 - (pos - self.center).norm() / radius)
+    }
+
+    /// the chance that a block with `hardness` at `pos` is destroyed.
+    pub fn destroy_chance(&self, pos: Point3<f32>, hardness: f32) -> f32 {
+        let strength = self.power * self.falloff(pos);
+        match strength > hardness {
+            true => 1.0 - hardness / strength,
+            false => 0.0,
+        }
+    }
+
+    /// the change in velocity of an entity at `pos`.
+    pub fn knockback(&self, pos: Point3<f32>) -> Vector3<f32> {
+        let falloff = self.falloff(pos);
+        if falloff <= 0.0 {
+            return Vector3::zeros();
+        }
+
+        // things right at the center get thrown straight up, and everything
+        // else gets a bit of lift so that it comes off the ground.
+        let away = pos - self.center;
+        let direction = match away.try_normalize(1e-4) {
+            Some(direction) => (direction + vector![0.0, 0.25, 0.0]).normalize(),
+            None => Vector3::y(),
+        };
+        direction * self.power * falloff * KNOCKBACK_PER_POWER
+    }
+}
+
+/// lights the explosive block at `pos`. returns whether there was anything
+/// there to light.
+pub fn ignite(access: &mut ChunkAccess, pos: BlockPos) -> bool {
+    let registry = Arc::clone(access.registry());
+    let id = match access.block(pos) {
+        Some(id) => id,
+        None => return false,
+    };
+    let block = registry.get(id);
+    if block.explosive().is_none() {
+        return false;
+    }
+    match block.state(LIT_STATE) {
+        Some(lit) if lit != id => access.set_block(pos, lit),
+        _ => {}
+    }
+    true
+}
+
+/// queues up the removal of every block the explosion destroys, and returns
+/// where they were. explosives caught in the blast are lit instead, so that
+/// they go off in a chain.
+pub fn explode_blocks<R: Rng>(
+    access: &mut ChunkAccess,
+    explosion: &ExplosionEvent,
+    rng: &mut R,
+) -> Vec<BlockPos> {
+    let registry = Arc::clone(access.registry());
+    let radius = explosion.radius.min(MAX_EXPLOSION_RADIUS).ceil() as i32;
+    let center = BlockPos {
+        x: explosion.center.x.floor() as i32,
+        y: explosion.center.y.floor() as i32,
+        z: explosion.center.z.floor() as i32,
+    };
+
+    let mut destroyed = vec![];
+    for x in -radius..=radius {
+        for y in -radius..=radius {
+            for z in -radius..=radius {
+                let pos = center.offset([x, y, z]);
+                let id = match access.block(pos) {
+                    Some(id) if id != AIR_BLOCK => id,
+                    _ => continue,
+                };
+
+                let middle = Point3::from(pos.origin()) + vector![0.5, 0.5, 0.5];
+                let chance = explosion.destroy_chance(middle, registry.get(id).hardness());
+                if chance > 0.0 && rng.gen::<f32>() < chance && !ignite(access, pos) {
+                    destroyed.push(pos);
+                }
+            }
+        }
+    }
+
+    for &pos in destroyed.iter() {
+        access.set_block(pos, AIR_BLOCK);
+    }
+    destroyed
+}
+
+fn apply_explosions(
+    mut access: ResMut<ChunkAccess>,
+    mut explosion_events: EventReader<ExplosionEvent>,
+    bodies: Query<(&Transform, &mut RigidBody), Without<PhysicsDisabled>>,
+) {
+    let mut rng = rand::thread_rng();
+    for explosion in explosion_events.iter() {
+        let destroyed = explode_blocks(&mut access, explosion, &mut rng);
+        log::debug!(
+            "explosion at {:?} destroyed {} blocks",
+            explosion.center,
+            destroyed.len()
+        );
+
+        bodies.for_each_mut(|(transform, mut rigidbody)| {
+            rigidbody.velocity += explosion.knockback(transform.pos());
+        });
+    }
+}
+
+/// replaces explosive blocks that were just lit with primed explosives.
+fn prime_explosives(
+    mut cmd: Commands,
+    mut access: ResMut<ChunkAccess>,
+    mut block_update_events: EventReader<BlockUpdateEvent>,
+) {
+    let registry = Arc::clone(access.registry());
+    for update in block_update_events.iter() {
+        let block = registry.get(update.new_id);
+        let explosive = match block.explosive() {
+            Some(explosive) if block.state_name() == Some(LIT_STATE) => explosive,
+            _ => continue,
+        };
+        // the event might be stale if the block changed again since
+        if access.block(update.pos) != Some(update.new_id) {
+            continue;
+        }
+        access.set_block(update.pos, AIR_BLOCK);
+
+        let inset = (1.0 - PRIMED_EXPLOSIVE_SIZE) / 2.0;
+        let pos = Point3::from(update.pos.origin()).coords + vector![inset, 0.0, inset];
+        cmd.spawn()
+            .insert(PrimedExplosive {
+                block: update.new_id,
+                explosive,
+                remaining: explosive.fuse,
+            })
+            .insert(Transform::default().translated(&pos))
+            .insert(RigidBody::default())
+            .insert(AabbCollider::new(Aabb {
+                min: point![0.0, 0.0, 0.0],
+                max: Point3::from(Vector3::repeat(PRIMED_EXPLOSIVE_SIZE)),
+            }));
+    }
+}
+
+fn burn_fuses(
+    mut cmd: Commands,
+    time: Res<GameTime>,
+    mut explosion_events: EventWriter<ExplosionEvent>,
+    query: Query<(Entity, &Transform, &mut PrimedExplosive)>,
+) {
+    query.for_each_mut(|(entity, transform, mut primed)| {
+        primed.remaining -= time.delta_seconds();
+        if primed.remaining > 0.0 {
+            return;
+        }
+
+        cmd.entity(entity).despawn();
+        explosion_events.send(ExplosionEvent {
+            center: transform.pos() + Vector3::repeat(PRIMED_EXPLOSIVE_SIZE / 2.0),
+            radius: primed.explosive.radius,
+            power: primed.explosive.power,
+        });
+    });
+}
+
+#[derive(Debug, Default)]
+pub struct ExplosionPlugin {}
+
+impl Plugin for ExplosionPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<ExplosionEvent>();
+        app.add_system(apply_explosions.system());
+        app.add_system(prime_explosives.system());
+        app.add_system(burn_fuses.system().with_run_criteria(playing.system()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        chunk::{self, ArrayChunk},
+        test_util::{section_world, test_registry},
+    };
+    use rand::{rngs::SmallRng, SeedableRng};
+    use std::collections::HashMap;
+
+    #[test]
+    fn explosions_leave_hard_blocks_standing() {
+        let registry = test_registry();
+        let (dirt, obsidian) = (registry.lookup("dirt"), registry.lookup("obsidian"));
+
+        // a solid cube of dirt with a column of obsidian running through it
+        let mut blocks = ArrayChunk::homogeneous(dirt);
+        for y in 0..32 {
+            blocks[[16, y, 18]] = obsidian;
+        }
+
+        let world = section_world(&registry, blocks);
+
+        let explosion = ExplosionEvent {
+            center: point![16.5, 16.5, 16.5],
+            radius: 4.0,
+            power: 4.0,
+        };
+        let mut access = ChunkAccess::new(&world);
+        let destroyed = explode_blocks(&mut access, &explosion, &mut SmallRng::seed_from_u64(7));
+        chunk::write_all_chunk_updates(&mut access, &mut HashMap::new(), &mut HashMap::new());
+
+        let block = |x, y, z| BlockPos { x, y, z };
+        assert!(destroyed.len() > 20);
+        for pos in destroyed {
+            let middle = Point3::from(pos.origin()) + vector![0.5, 0.5, 0.5];
+            assert!((middle - explosion.center).norm() < explosion.radius);
+            assert_eq!(access.block(pos), Some(AIR_BLOCK));
+        }
+        for y in 12..=20 {
+            assert_eq!(access.block(block(16, y, 18)), Some(obsidian));
+        }
+
+        let knockback = explosion.knockback(point![18.5, 16.5, 16.5]);
+        assert!(knockback.x > 0.0 && knockback.y > 0.0);
+        assert_eq!(
+            explosion.knockback(point![30.0, 16.5, 16.5]),
+            Vector3::zeros()
+        );
+    }
+
+    #[test]
+    fn explosions_light_nearby_explosives() {
+        let registry = test_registry();
+        let (tnt, stone) = (registry.lookup("tnt"), registry.lookup("stone"));
+        let lit = registry.try_lookup_state("tnt", LIT_STATE).unwrap();
+
+        // one TNT block right next to the blast, and one well out of its reach
+        let mut blocks = ArrayChunk::homogeneous(AIR_BLOCK);
+        blocks[[18, 16, 16]] = tnt;
+        blocks[[28, 16, 16]] = tnt;
+        blocks[[16, 15, 16]] = stone;
+
+        let world = section_world(&registry, blocks);
+
+        let explosion = ExplosionEvent {
+            center: point![16.5, 16.5, 16.5],
+            radius: 4.0,
+            power: 4.0,
+        };
+        let mut access = ChunkAccess::new(&world);
+        let block = |x, y, z| BlockPos { x, y, z };
+        assert!(!ignite(&mut access, block(16, 15, 16)));
+        let destroyed = explode_blocks(&mut access, &explosion, &mut SmallRng::seed_from_u64(7));
+        chunk::write_all_chunk_updates(&mut access, &mut HashMap::new(), &mut HashMap::new());
+
+        assert!(!destroyed.contains(&block(18, 16, 16)));
+        assert_eq!(access.block(block(18, 16, 16)), Some(lit));
+        assert_eq!(access.block(block(28, 16, 16)), Some(tnt));
+        assert!(registry.get(lit).explosive().is_some());
+    }
+}
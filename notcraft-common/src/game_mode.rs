@@ -0,0 +1,93 @@
+//! the rules a player plays by. creative players can fly, break blocks
+//! instantly, place as many blocks as they like, and don't take fall damage.
+//! survival players have to dig blocks out over time, can only place blocks
+//! they have in their inventory, and stay on the ground.
+//!
+//! a player's game mode is a component on their entity, so that each player
+//! can have their own, and it's saved along with the rest of the player.
+
+use crate::{
+    codec::{
+        decode::Node,
+        encode::{Encode, Encoder},
+        NodeKind,
+    },
+    prelude::*,
+    saved_components::{register_saved_component, SavedComponent},
+};
+use std::{io::Write, str::FromStr};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GameMode {
+    #[default]
+    Creative,
+    Survival,
+}
+
+impl GameMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Creative => "creative",
+            GameMode::Survival => "survival",
+        }
+    }
+
+    /// whether blocks break as soon as they're hit, instead of taking longer
+    /// the harder they are.
+    pub fn instant_break(self) -> bool {
+        self == GameMode::Creative
+    }
+
+    /// whether placing a block takes one out of the player's inventory.
+    pub fn consumes_blocks(self) -> bool {
+        self == GameMode::Survival
+    }
+
+    pub fn allows_flight(self) -> bool {
+        self == GameMode::Creative
+    }
+
+    pub fn takes_fall_damage(self) -> bool {
+        self == GameMode::Survival
+    }
+}
+
+impl FromStr for GameMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "creative" => Self::Creative,
+            "survival" => Self::Survival,
+            other => bail!("unknown game mode '{}'", other),
+        })
+    }
+}
+
+impl<W: Write> Encode<W> for GameMode {
+    const KIND: NodeKind = NodeKind::String;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode(self.name())
+    }
+}
+
+impl SavedComponent for GameMode {
+    const NAME: &'static str = "game_mode";
+
+    fn from_node(node: &Node) -> Result<Self> {
+        match node.as_str() {
+            Some(name) => name.parse(),
+            None => bail!("game mode is not a string"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GameModePlugin {}
+
+impl Plugin for GameModePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        register_saved_component::<GameMode>(app);
+    }
+}
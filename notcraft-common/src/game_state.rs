@@ -0,0 +1,29 @@
+//! which part of the game is running: the loading screen, the main menu, the
+//! game itself, or the pause menu.
+//!
+//! the world keeps loading no matter what state the game is in, but anything
+//! that moves the game along, like physics, mobs, and the weather, only runs
+//! while the game is being played. systems opt into this by using [`playing`]
+//! as their run criteria.
+
+use crate::prelude::*;
+use bevy_ecs::schedule::ShouldRun;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum GameState {
+    /// assets are still being loaded, see [`crate::assets`].
+    Loading,
+    MainMenu,
+    #[default]
+    Playing,
+    Paused,
+}
+
+/// run criteria for systems that should stop while the game isn't being
+/// played. apps without a [`GameState`] are always being played.
+pub fn playing(state: Option<Res<GameState>>) -> ShouldRun {
+    match state.as_deref() {
+        None | Some(GameState::Playing) => ShouldRun::Yes,
+        Some(_) => ShouldRun::No,
+    }
+}
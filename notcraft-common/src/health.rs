@@ -0,0 +1,199 @@
+use crate::{
+    clock::GameTime,
+    codec::{
+        decode::Node,
+        encode::{Encode, Encoder},
+        NodeKind,
+    },
+    game_mode::GameMode,
+    game_state::playing,
+    physics::{AabbCollider, CollisionResolution, PhysicsDisabled, RigidBody, GRAVITY},
+    prelude::*,
+    saved_components::{register_saved_component, SavedComponent},
+    transform::Transform,
+    world::{fluid, registry::BlockId},
+};
+
+/// entities below this height take void damage.
+pub const VOID_LEVEL: f32 = -512.0;
+/// damage dealt per second to entities that have fallen into the void.
+pub const VOID_DAMAGE_PER_SECOND: f32 = 8.0;
+
+/// entities can fall this many blocks before taking any damage. every block
+/// fallen past this deals one point of damage.
+pub const SAFE_FALL_DISTANCE: f32 = 3.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.max;
+    }
+}
+
+impl<W: std::io::Write> Encode<W> for Health {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode_map(|mut map| {
+            map.entry("current").encode(&self.current)?;
+            map.entry("max").encode(&self.max)?;
+            Ok(())
+        })
+    }
+}
+
+impl SavedComponent for Health {
+    const NAME: &'static str = "health";
+
+    fn from_node(node: &Node) -> Result<Self> {
+        let current = node.get("current").and_then(Node::as_f32);
+        let max = node.get("max").and_then(Node::as_f32);
+        match (current, max) {
+            (Some(current), Some(max)) => Ok(Health {
+                current: f32::min(current, max),
+                max,
+            }),
+            _ => bail!("health is missing its current or max health"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DamageSource {
+    /// hitting the ground too fast.
+    Fall,
+    /// falling below [`VOID_LEVEL`].
+    Void,
+    /// standing in a fluid that deals damage, like lava.
+    Fluid(BlockId),
+    /// being attacked by another entity. nothing deals this kind of damage
+    /// yet.
+    Mob(Entity),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DamageEvent {
+    pub entity: Entity,
+    pub source: DamageSource,
+    pub amount: f32,
+}
+
+/// sent once when an entity's health reaches zero. it's up to whoever owns the
+/// entity to decide what to do with it, be it respawning or despawning it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub source: DamageSource,
+}
+
+pub fn apply_damage(
+    mut damage_events: EventReader<DamageEvent>,
+    mut death_events: EventWriter<DeathEvent>,
+    mut query: Query<&mut Health>,
+) {
+    for event in damage_events.iter() {
+        let mut health = match query.get_mut(event.entity) {
+            Ok(health) => health,
+            Err(_) => continue,
+        };
+
+        // already dead entities can't die again
+        if health.is_dead() {
+            continue;
+        }
+
+        health.current = f32::max(0.0, health.current - event.amount);
+        if health.is_dead() {
+            death_events.send(DeathEvent {
+                entity: event.entity,
+                source: event.source,
+            });
+        }
+    }
+}
+
+pub fn fall_damage(
+    // colliders that physics isn't moving around (like flying players) keep
+    // whatever state they had when they stopped, so they're left out here.
+    // entities without any health just have their damage ignored.
+    query: Query<(Entity, &AabbCollider, &RigidBody, Option<&GameMode>), Without<PhysicsDisabled>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    query.for_each(|(entity, collider, _, game_mode)| {
+        // landing in water or on something bouncy breaks your fall
+        if !collider.on_ground || collider.in_liquid || collider.ground.bounce > 0.0 {
+            return;
+        }
+        if game_mode.is_some_and(|mode| !mode.takes_fall_damage()) {
+            return;
+        }
+
+        // the height we would have had to fall from to be moving this fast
+        let speed = f32::max(0.0, -collider.impact_velocity.y);
+        let fall_distance = speed * speed / (2.0 * GRAVITY);
+
+        let damage = (fall_distance - SAFE_FALL_DISTANCE).floor();
+        if damage > 0.0 {
+            damage_events.send(DamageEvent {
+                entity,
+                source: DamageSource::Fall,
+                amount: damage,
+            });
+        }
+    });
+}
+
+pub fn void_damage(
+    time: Res<GameTime>,
+    query: Query<(Entity, &Transform), With<Health>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    query.for_each(|(entity, transform)| {
+        if transform.pos().y < VOID_LEVEL {
+            damage_events.send(DamageEvent {
+                entity,
+                source: DamageSource::Void,
+                amount: VOID_DAMAGE_PER_SECOND * time.delta_seconds(),
+            });
+        }
+    });
+}
+
+#[derive(Debug, Default)]
+pub struct HealthPlugin {}
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<DamageEvent>();
+        app.add_event::<DeathEvent>();
+        register_saved_component::<Health>(app);
+
+        app.add_system(void_damage.system().with_run_criteria(playing.system()));
+        app.add_system(
+            fluid::fluid_contact_damage
+                .system()
+                .with_run_criteria(playing.system()),
+        );
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            fall_damage
+                .system()
+                .after(CollisionResolution)
+                .with_run_criteria(playing.system()),
+        );
+        app.add_system_to_stage(CoreStage::Last, apply_damage.system());
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{
+    codec::{
+        decode::Node,
+        encode::{Encode, Encoder},
+        NodeKind,
+    },
+    prelude::*,
+    saved_components::SavedComponent,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// some number of a single kind of item. items are referred to by name, which
+/// for now is always the name of a block.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub struct ItemStack {
+    pub item: String,
+    pub count: u32,
+}
+
+/// an unordered bag of items.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Inventory {
+    items: BTreeMap<String, u32>,
+}
+
+impl Inventory {
+    pub fn count(&self, item: &str) -> u32 {
+        self.items.get(item).copied().unwrap_or(0)
+    }
+
+    pub fn add(&mut self, item: &str, count: u32) {
+        if count > 0 {
+            *self.items.entry(item.into()).or_default() += count;
+        }
+    }
+
+    /// removes `count` of `item`, returning false and leaving the inventory
+    /// untouched if there weren't enough.
+    pub fn remove(&mut self, item: &str, count: u32) -> bool {
+        let current = self.count(item);
+        if current < count {
+            return false;
+        }
+
+        if current == count {
+            self.items.remove(item);
+        } else {
+            self.items.insert(item.into(), current - count);
+        }
+        true
+    }
+
+    pub fn contains_all(&self, stacks: &[ItemStack]) -> bool {
+        stacks
+            .iter()
+            .all(|stack| self.count(&stack.item) >= stack.count)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.items.iter().map(|(item, &count)| (&**item, count))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// moves everything in `other` into this inventory.
+    pub fn merge(&mut self, other: Inventory) {
+        for (item, count) in other.items {
+            self.add(&item, count);
+        }
+    }
+
+    pub fn from_node(node: &Node) -> Result<Self> {
+        let list = match node.as_list() {
+            Some(list) => list,
+            None => bail!("inventory is not a list"),
+        };
+
+        let mut inventory = Self::default();
+        for stack in list {
+            let item = stack.get("item").and_then(Node::as_str);
+            let count = stack.get("count").and_then(Node::as_unsigned);
+            match (item, count.and_then(|count| u32::try_from(count).ok())) {
+                (Some(item), Some(count)) => inventory.add(item, count),
+                _ => bail!("inventory has a malformed item stack"),
+            }
+        }
+        Ok(inventory)
+    }
+}
+
+struct EncodedStack<'a>(&'a str, u32);
+
+impl<W: std::io::Write> Encode<W> for EncodedStack<'_> {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode_map(|mut map| {
+            map.entry("item").encode(self.0)?;
+            map.entry("count").encode(&self.1)?;
+            Ok(())
+        })
+    }
+}
+
+impl<W: std::io::Write> Encode<W> for Inventory {
+    const KIND: NodeKind = NodeKind::List;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        let stacks = self
+            .items
+            .iter()
+            .map(|(item, &count)| EncodedStack(item, count));
+        encoder.encode_verbatim_list(stacks)
+    }
+}
+
+impl SavedComponent for Inventory {
+    const NAME: &'static str = "inventory";
+
+    fn from_node(node: &Node) -> Result<Self> {
+        Inventory::from_node(node)
+    }
+}
@@ -0,0 +1,244 @@
+//! items lying around in the world, like the blocks left behind when
+//! something is broken.
+//!
+//! anything can drop items by sending an [`ItemDropEvent`]. dropped items fall
+//! like anything else, and are picked up by anything with an inventory that
+//! walks up to them. so that breaking a big area doesn't leave thousands of
+//! them lying around, drops of the same item that are close together merge
+//! into one stack, drops that nobody picks up despawn after a while, and once
+//! there are too many, the oldest ones are removed to make room.
+//!
+//! dropped items aren't saved with the world.
+
+use crate::{
+    aabb::Aabb,
+    clock::GameTime,
+    game_state::playing,
+    inventory::{Inventory, ItemStack},
+    physics::{AabbCollider, RigidBody},
+    prelude::*,
+    transform::Transform,
+};
+use nalgebra::{Point3, Vector3};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// the length of a dropped item's sides, in blocks.
+pub const ITEM_DROP_SIZE: f32 = 0.25;
+
+/// how far, in blocks, something can be from a drop and still pick it up.
+const PICKUP_DISTANCE: f32 = 1.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ItemDropConfig {
+    /// how many seconds a drop lies around for before it despawns.
+    pub despawn_seconds: f32,
+    /// the most drops there can be at once. the oldest drops despawn early to
+    /// stay under this.
+    pub max_drops: usize,
+    /// drops of the same item closer together than this many blocks merge.
+    pub merge_distance: f32,
+}
+
+impl Default for ItemDropConfig {
+    fn default() -> Self {
+        Self {
+            despawn_seconds: 300.0,
+            max_drops: 256,
+            merge_distance: 1.0,
+        }
+    }
+}
+
+/// drops `stack` with its middle at `pos`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemDropEvent {
+    pub pos: Point3<f32>,
+    pub stack: ItemStack,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DroppedItem {
+    pub stack: ItemStack,
+    /// how long it's been lying around, in seconds.
+    pub age: f32,
+}
+
+/// works out which drops merge into which, given where each drop is and what
+/// it's made of. each drop merges into the first drop before it of the same
+/// item that's within `distance`, and drops that other drops merge into stay
+/// where they are. the result has the index of the drop each drop merges
+/// into, or `None` for drops that stay.
+pub fn plan_merges(drops: &[(Point3<f32>, &str)], distance: f32) -> Vec<Option<usize>> {
+    let mut merges = vec![None; drops.len()];
+    if distance <= 0.0 {
+        return merges;
+    }
+
+    // drops that stay, by which `distance` sized cell they're in, so that only
+    // the cells around a drop need to be looked through
+    let cell = |pos: Point3<f32>| pos.coords.map(|axis| (axis / distance).floor() as i32);
+    let mut staying = HashMap::<Vector3<i32>, Vec<usize>>::new();
+    for (idx, &(pos, item)) in drops.iter().enumerate() {
+        let center = cell(pos);
+        let mut target = None;
+        for offset in (0..27).map(|n| vector![n % 3 - 1, n / 3 % 3 - 1, n / 9 - 1]) {
+            let nearby = staying.get(&(center + offset)).into_iter().flatten();
+            let mut nearby = nearby.filter(|&&other| {
+                let (other_pos, other_item) = drops[other];
+                other_item == item && (other_pos - pos).norm() <= distance
+            });
+            if let Some(&other) = nearby.next() {
+                target = Some(target.map_or(other, |target: usize| target.min(other)));
+            }
+        }
+
+        match target {
+            Some(target) => merges[idx] = Some(target),
+            None => staying.entry(center).or_default().push(idx),
+        }
+    }
+    merges
+}
+
+/// spawns this frame's drops, merging the ones that are close together first.
+fn spawn_item_drops(
+    mut cmd: Commands,
+    config: Res<ItemDropConfig>,
+    mut drop_events: EventReader<ItemDropEvent>,
+) {
+    let mut events = drop_events.iter().cloned().collect::<Vec<_>>();
+    if events.is_empty() {
+        return;
+    }
+
+    let drops = events.iter().map(|event| (event.pos, &*event.stack.item));
+    let merges = plan_merges(&drops.collect::<Vec<_>>(), config.merge_distance);
+    for (idx, merge) in merges.iter().enumerate() {
+        if let Some(target) = *merge {
+            events[target].stack.count += events[idx].stack.count;
+        }
+    }
+
+    for (merge, event) in merges.iter().zip(events) {
+        if merge.is_some() {
+            continue;
+        }
+        let pos = event.pos - Vector3::repeat(ITEM_DROP_SIZE / 2.0);
+        cmd.spawn()
+            .insert(DroppedItem {
+                stack: event.stack,
+                age: 0.0,
+            })
+            .insert(Transform::default().translated(&pos.coords))
+            .insert(RigidBody::default())
+            .insert(AabbCollider::new(Aabb {
+                min: point![0.0, 0.0, 0.0],
+                max: Point3::from(Vector3::repeat(ITEM_DROP_SIZE)),
+            }));
+    }
+}
+
+/// ages drops, despawns the ones that are too old or that there isn't room
+/// for, merges the ones that have come together, and lets anything with an
+/// inventory pick up the ones it's close to.
+fn update_item_drops(
+    mut cmd: Commands,
+    time: Res<GameTime>,
+    config: Res<ItemDropConfig>,
+    mut drops: Query<(Entity, &Transform, &mut DroppedItem)>,
+    collectors: Query<(&Transform, &AabbCollider, &mut Inventory)>,
+) {
+    let mut removed = HashSet::new();
+    let mut live = vec![];
+    drops.for_each_mut(|(entity, _, mut dropped)| {
+        dropped.age += time.delta_seconds();
+        live.push((entity, dropped.age));
+    });
+    // oldest first, so that drops merge into older drops and the oldest are
+    // the first to go when there are too many
+    live.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    let over = live.len().saturating_sub(config.max_drops);
+    for (idx, &(entity, age)) in live.iter().enumerate() {
+        if idx < over || age >= config.despawn_seconds {
+            cmd.entity(entity).despawn();
+            removed.insert(entity);
+        }
+    }
+    live.retain(|(entity, _)| !removed.contains(entity));
+
+    let middle = |transform: &Transform| transform.pos() + Vector3::repeat(ITEM_DROP_SIZE / 2.0);
+    let mut stacks = vec![];
+    for &(entity, _) in live.iter() {
+        let (_, transform, dropped) = drops.get_mut(entity).unwrap();
+        stacks.push((middle(transform), dropped.stack.clone()));
+    }
+    let items = stacks.iter().map(|(pos, stack)| (*pos, &*stack.item));
+    let merges = plan_merges(&items.collect::<Vec<_>>(), config.merge_distance);
+    for (idx, merge) in merges.iter().enumerate() {
+        let target = match *merge {
+            Some(target) => target,
+            None => continue,
+        };
+        let (entity, age) = live[idx];
+        stacks[target].1.count += stacks[idx].1.count;
+        let (_, _, mut dropped) = drops.get_mut(live[target].0).unwrap();
+        dropped.stack.count = stacks[target].1.count;
+        // the merged stack lasts as long as the newest drop in it would have
+        dropped.age = dropped.age.min(age);
+        cmd.entity(entity).despawn();
+        removed.insert(entity);
+    }
+
+    collectors.for_each_mut(|(transform, collider, mut inventory)| {
+        let reach = collider.aabb.transformed(transform);
+        let reach = reach.inflate(PICKUP_DISTANCE);
+        for (&(entity, _), (pos, stack)) in live.iter().zip(stacks.iter()) {
+            if !removed.contains(&entity) && reach.contains(pos) {
+                inventory.add(&stack.item, stack.count);
+                cmd.entity(entity).despawn();
+                removed.insert(entity);
+            }
+        }
+    });
+}
+
+#[derive(Debug, Default)]
+pub struct ItemDropPlugin {}
+
+impl Plugin for ItemDropPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<ItemDropEvent>();
+        app.init_resource::<ItemDropConfig>();
+        app.add_system(spawn_item_drops.system());
+        app.add_system(
+            update_item_drops
+                .system()
+                .with_run_criteria(playing.system()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearby_drops_of_the_same_item_merge() {
+        let drops = [
+            (point![0.0, 0.0, 0.0], "stone"),
+            (point![0.5, 0.0, 0.0], "stone"),
+            (point![0.5, 0.0, 0.0], "dirt"),
+            // close to the second drop, but that one merges into the first
+            (point![1.2, 0.0, 0.0], "stone"),
+            (point![5.0, 0.0, 0.0], "stone"),
+            (point![-0.9, 0.0, 0.0], "stone"),
+        ];
+        assert_eq!(
+            plan_merges(&drops, 1.0),
+            [None, Some(0), None, None, None, Some(0)]
+        );
+        assert_eq!(plan_merges(&drops, 0.0), [None; 6]);
+    }
+}
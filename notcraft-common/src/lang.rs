@@ -0,0 +1,154 @@
+//! translations for the text that the game shows to players.
+//!
+//! each language lives in `resources/lang/<code>.ron`, which maps keys to
+//! translated text. text can have `{}` placeholders in it, which are filled in
+//! with the arguments passed to [`tr!`], in order.
+//!
+//! English is always loaded alongside the current language, and is used for
+//! any keys that the current language is missing.
+
+use crate::prelude::*;
+use parking_lot::{Mutex, RwLock};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    path::Path,
+};
+
+/// where the game's language files are kept.
+pub const LANGUAGE_DIR: &str = "resources/lang";
+
+/// the language that every key must have a translation in.
+pub const FALLBACK_LANGUAGE: &str = "en";
+
+#[derive(Clone, Debug, Default)]
+struct Translations {
+    code: String,
+    strings: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+lazy_static::lazy_static! {
+    static ref TRANSLATIONS: RwLock<Translations> = Default::default();
+    /// keys that have already been warned about, so that text drawn every
+    /// frame doesn't flood the log.
+    static ref WARNED_KEYS: Mutex<HashSet<String>> = Default::default();
+}
+
+fn read_language(dir: &Path, code: &str) -> Result<HashMap<String, String>> {
+    let path = dir.join(code).with_extension("ron");
+    match std::fs::read_to_string(&path) {
+        Ok(text) => Ok(ron::from_str(&text)?),
+        Err(err) => bail!("failed to read '{}': {}", path.display(), err),
+    }
+}
+
+/// switches to the language with the given code, loading its translations
+/// from `dir`. the old language stays in place if anything goes wrong.
+pub fn load_language(dir: &Path, code: &str) -> Result<()> {
+    let fallback = read_language(dir, FALLBACK_LANGUAGE)?;
+    let strings = match code {
+        FALLBACK_LANGUAGE => fallback.clone(),
+        _ => read_language(dir, code)?,
+    };
+
+    *TRANSLATIONS.write() = Translations {
+        code: code.into(),
+        strings,
+        fallback,
+    };
+    WARNED_KEYS.lock().clear();
+    log::info!("switched language to '{}'", code);
+    Ok(())
+}
+
+/// the code of the language that's currently loaded, if any.
+pub fn current_language() -> Option<String> {
+    let translations = TRANSLATIONS.read();
+    match translations.code.is_empty() {
+        true => None,
+        false => Some(translations.code.clone()),
+    }
+}
+
+fn warn_once(key: &str, message: std::fmt::Arguments) {
+    if WARNED_KEYS.lock().insert(key.into()) {
+        log::warn!("{}", message);
+    }
+}
+
+fn fill_placeholders(template: &str, args: &[&dyn Display]) -> String {
+    let mut args = args.iter();
+    let mut pieces = template.split("{}");
+    let mut out = pieces.next().unwrap_or_default().to_string();
+    for piece in pieces {
+        match args.next() {
+            Some(arg) => out.push_str(&arg.to_string()),
+            None => out.push_str("{}"),
+        }
+        out.push_str(piece);
+    }
+    out
+}
+
+/// looks up the text for `key` in the current language, see [`tr!`].
+pub fn translate(key: &str, args: &[&dyn Display]) -> String {
+    let translations = TRANSLATIONS.read();
+    let template = match translations.strings.get(key) {
+        Some(template) => template,
+        None => match translations.fallback.get(key) {
+            Some(template) => {
+                let code = &translations.code;
+                warn_once(key, format_args!("no '{}' translation for '{}'", code, key));
+                template
+            }
+            None => {
+                warn_once(key, format_args!("unknown translation key '{}'", key));
+                key
+            }
+        },
+    };
+
+    fill_placeholders(template, args)
+}
+
+/// translates a key into the current language, filling in any `{}`
+/// placeholders with the rest of the arguments, like
+/// `tr!("command.weather.set", kind.name())`.
+#[macro_export]
+macro_rules! tr {
+    ($key:expr) => {
+        $crate::lang::translate($key, &[])
+    };
+    ($key:expr, $($arg:expr),+ $(,)?) => {
+        $crate::lang::translate($key, &[$(&$arg as &dyn ::std::fmt::Display),+])
+    };
+}
+
+pub use tr;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_placeholders_in_order() {
+        let filled = fill_placeholders("{} blocks from {}", &[&12, &"spawn"]);
+        assert_eq!(filled, "12 blocks from spawn");
+    }
+
+    #[test]
+    fn leaves_extra_placeholders_alone() {
+        assert_eq!(fill_placeholders("{} and {}", &[&1]), "1 and {}");
+        assert_eq!(fill_placeholders("nothing here", &[&1]), "nothing here");
+    }
+
+    #[test]
+    fn english_translations_parse() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join(LANGUAGE_DIR);
+        let strings = read_language(&dir, FALLBACK_LANGUAGE).unwrap();
+        assert!(strings.contains_key("console.unknown_command"));
+    }
+}
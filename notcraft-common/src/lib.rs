@@ -4,11 +4,36 @@ use serde::{Deserialize, Serialize};
 use std::ops::Neg;
 
 pub mod aabb;
+pub mod assets;
+pub mod clock;
 pub mod codec;
+pub mod console;
+pub mod content_pack;
+pub mod crafting;
+pub mod explosion;
+pub mod game_mode;
+pub mod game_state;
+pub mod health;
+pub mod inventory;
+pub mod item_drop;
+pub mod lang;
+pub mod mob;
+pub mod movement;
 pub mod net;
+pub mod notification;
+pub mod pathfinding;
 pub mod physics;
+pub mod profiler;
+pub mod saved_components;
+pub mod scripting;
+pub mod stats;
+pub mod targeting;
+pub mod task;
+pub mod teleport;
+pub mod tool;
 pub mod transform;
 pub mod util;
+pub mod weather;
 pub mod world;
 
 pub mod debug;
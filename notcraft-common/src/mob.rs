@@ -0,0 +1,419 @@
+//! a very basic mob subsystem.
+//!
+//! mobs are spawned on the surface of the terrain around chunk loaders, wander
+//! around aimlessly, and are despawned once they get too far away from every
+//! chunk loader.
+
+use nalgebra::{point, vector, Point3, Vector2, Vector3};
+use rand::Rng;
+use std::time::Duration;
+
+use crate::{
+    aabb::Aabb,
+    clock::GameTime,
+    game_state::playing,
+    health::{DeathEvent, Health},
+    pathfinding::PathFollower,
+    physics::{AabbCollider, RigidBody},
+    prelude::*,
+    transform::Transform,
+    world::{chunk::ChunkAccess, trace_ray, BlockPos, DynamicChunkLoader, Ray3, WorldPos},
+};
+
+// enough to clear a single block with our gravity
+const JUMP_VELOCITY: f32 = 8.5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MobSettings {
+    /// the maximum number of mobs that may exist at once.
+    pub max_mobs: usize,
+    /// mobs spawn at least this many blocks away from a chunk loader...
+    pub min_spawn_distance: f32,
+    /// ...but no further than this.
+    pub max_spawn_distance: f32,
+    /// mobs further than this from every chunk loader are despawned.
+    pub despawn_distance: f32,
+    /// how often a spawn is attempted.
+    pub spawn_interval: Duration,
+    pub dimensions: Vector3<f32>,
+    /// horizontal speed, in blocks per second, of a wandering mob.
+    pub walk_speed: f32,
+    /// mobs won't walk off of ledges that are taller than this.
+    pub max_drop: f32,
+    pub max_health: f32,
+    /// how far away, in blocks, a travelling mob may pick its destination.
+    pub travel_distance: i32,
+}
+
+impl Default for MobSettings {
+    fn default() -> Self {
+        Self {
+            max_mobs: 16,
+            min_spawn_distance: 16.0,
+            max_spawn_distance: 64.0,
+            despawn_distance: 128.0,
+            spawn_interval: Duration::from_secs(2),
+            dimensions: vector![0.8, 0.8, 0.8],
+            walk_speed: 2.0,
+            max_drop: 3.0,
+            max_health: 10.0,
+            travel_distance: 12,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MobState {
+    /// standing still for `remaining` more seconds.
+    Idle { remaining: f32 },
+    /// walking in `direction` for `remaining` more seconds.
+    Wander {
+        direction: Vector2<f32>,
+        remaining: f32,
+    },
+    /// following the path in the mob's [`PathFollower`], giving up after
+    /// `remaining` more seconds.
+    Travel { remaining: f32 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mob {
+    pub state: MobState,
+}
+
+impl Default for Mob {
+    fn default() -> Self {
+        Self {
+            state: MobState::Idle { remaining: 1.0 },
+        }
+    }
+}
+
+fn random_idle<R: Rng + ?Sized>(rng: &mut R) -> MobState {
+    MobState::Idle {
+        remaining: rng.gen_range(1.0, 5.0),
+    }
+}
+
+fn random_wander<R: Rng + ?Sized>(rng: &mut R) -> MobState {
+    let angle = rng.gen_range(0.0, std::f32::consts::TAU);
+    MobState::Wander {
+        direction: vector![angle.cos(), angle.sin()],
+        remaining: rng.gen_range(1.0, 4.0),
+    }
+}
+
+fn start_travelling<R: Rng + ?Sized>(
+    rng: &mut R,
+    settings: &MobSettings,
+    follower: &mut PathFollower,
+    feet: BlockPos,
+) -> MobState {
+    let dist = settings.travel_distance;
+    let dx = rng.gen_range(-dist, dist + 1);
+    let dz = rng.gen_range(-dist, dist + 1);
+    follower.set_goal(feet, feet.offset([dx, 0, dz]));
+    MobState::Travel { remaining: 20.0 }
+}
+
+/// returns true if a mob standing at `feet` could take a step in `direction`
+/// without falling further than `max_drop` blocks.
+fn is_safe_step(
+    access: &mut ChunkAccess,
+    settings: &MobSettings,
+    feet: Point3<f32>,
+    direction: Vector2<f32>,
+) -> bool {
+    // probe from just past the leading edge of the mob, starting a little above
+    // its feet so that a step up is not mistaken for a cliff.
+    let reach = 0.5 * settings.dimensions.x.max(settings.dimensions.z) + 0.5;
+    let probe = feet + reach * vector![direction.x, 0.0, direction.y] + vector![0.0, 0.5, 0.0];
+    let ray = Ray3 {
+        origin: probe,
+        direction: -Vector3::y(),
+    };
+
+    trace_ray(access, ray, settings.max_drop + 1.0).is_some()
+}
+
+fn is_obstructed_at(
+    access: &mut ChunkAccess,
+    settings: &MobSettings,
+    feet: Point3<f32>,
+    direction: Vector2<f32>,
+    height: f32,
+) -> bool {
+    let ray = Ray3 {
+        origin: feet + vector![0.0, height, 0.0],
+        direction: vector![direction.x, 0.0, direction.y],
+    };
+    let reach = 0.5 * settings.dimensions.x.max(settings.dimensions.z) + 0.5;
+
+    trace_ray(access, ray, reach).is_some()
+}
+
+/// returns true if there's a single block in front of the mob that it could
+/// jump onto.
+fn is_step_ahead(
+    access: &mut ChunkAccess,
+    settings: &MobSettings,
+    feet: Point3<f32>,
+    direction: Vector2<f32>,
+) -> bool {
+    is_obstructed_at(access, settings, feet, direction, 0.5)
+        && !is_obstructed_at(access, settings, feet, direction, 1.5)
+}
+
+/// returns true if there's a wall directly in front of the mob that's too tall
+/// to step over.
+fn is_blocked(
+    access: &mut ChunkAccess,
+    settings: &MobSettings,
+    feet: Point3<f32>,
+    direction: Vector2<f32>,
+) -> bool {
+    is_obstructed_at(access, settings, feet, direction, 1.5)
+}
+
+fn next_state<R: Rng + ?Sized>(
+    rng: &mut R,
+    access: &mut ChunkAccess,
+    settings: &MobSettings,
+    follower: &mut PathFollower,
+    state: MobState,
+    feet: Point3<f32>,
+    dt: f32,
+) -> MobState {
+    // nudge the position up a bit so that standing exactly on a block doesn't end up
+    // rounding down into it.
+    let feet_block = BlockPos::from(WorldPos::new(feet + vector![0.0, 0.1, 0.0]));
+
+    match state {
+        MobState::Idle { remaining } if remaining <= 0.0 => match rng.gen() {
+            true => random_wander(rng),
+            false => start_travelling(rng, settings, follower, feet_block),
+        },
+        MobState::Wander { remaining, .. } if remaining <= 0.0 => random_idle(rng),
+        MobState::Travel { remaining } if remaining <= 0.0 || follower.is_done() => {
+            follower.clear();
+            random_idle(rng)
+        }
+
+        MobState::Idle { remaining } => MobState::Idle {
+            remaining: remaining - dt,
+        },
+
+        MobState::Wander {
+            direction,
+            remaining,
+        } => {
+            if !is_safe_step(access, settings, feet, direction) {
+                random_idle(rng)
+            } else if is_blocked(access, settings, feet, direction) {
+                // turn around and try somewhere else
+                MobState::Wander {
+                    direction: -direction,
+                    remaining,
+                }
+            } else {
+                MobState::Wander {
+                    direction,
+                    remaining: remaining - dt,
+                }
+            }
+        }
+
+        MobState::Travel { remaining } => MobState::Travel {
+            remaining: remaining - dt,
+        },
+    }
+}
+
+/// returns the horizontal direction a travelling mob should move in, and
+/// whether it needs to jump to get there.
+fn follow_path(follower: &mut PathFollower, feet: Point3<f32>) -> (Vector2<f32>, bool) {
+    let feet_block = BlockPos::from(WorldPos::new(feet + vector![0.0, 0.1, 0.0]));
+    let next = match follower.path().and_then(|path| path.nodes.first()) {
+        Some(&next) => next,
+        // still waiting on a path
+        None => return (Vector2::zeros(), false),
+    };
+
+    let target = vector![next.x as f32 + 0.5, next.z as f32 + 0.5];
+    let delta = target - vector![feet.x, feet.z];
+    if delta.magnitude() < 0.2 && feet_block.y >= next.y {
+        follower.advance();
+    }
+
+    let direction = delta.try_normalize(1e-4).unwrap_or_else(Vector2::zeros);
+    (direction, next.y > feet_block.y)
+}
+
+pub fn update_mob_ai(
+    time: Res<GameTime>,
+    settings: Res<MobSettings>,
+    mut access: ResMut<ChunkAccess>,
+    query: Query<(
+        &mut Mob,
+        &mut PathFollower,
+        &Transform,
+        &AabbCollider,
+        &mut RigidBody,
+    )>,
+) {
+    let dt = time.delta_seconds();
+    let mut rng = rand::thread_rng();
+
+    query.for_each_mut(|(mut mob, mut follower, transform, collider, mut body)| {
+        let aabb = collider.aabb.transformed(transform);
+        let feet = point![aabb.center().x, aabb.min.y, aabb.center().z];
+
+        let state = mob.state;
+        mob.state = next_state(
+            &mut rng,
+            &mut access,
+            &settings,
+            &mut follower,
+            state,
+            feet,
+            dt,
+        );
+
+        let (direction, jump) = match mob.state {
+            MobState::Idle { .. } => (Vector2::zeros(), false),
+            MobState::Wander { direction, .. } => {
+                // hop up single-block steps
+                let jump = is_step_ahead(&mut access, &settings, feet, direction);
+                (direction, jump)
+            }
+            MobState::Travel { .. } => follow_path(&mut follower, feet),
+        };
+
+        body.velocity.x = settings.walk_speed * direction.x;
+        body.velocity.z = settings.walk_speed * direction.y;
+        if jump && collider.on_ground {
+            body.velocity.y = JUMP_VELOCITY;
+        }
+    });
+}
+
+/// finds the topmost block that a mob could stand on in the column at `(x, z)`,
+/// searching downwards from `top`.
+fn find_surface(access: &mut ChunkAccess, x: i32, z: i32, top: i32, bottom: i32) -> Option<i32> {
+    let registry = std::sync::Arc::clone(access.registry());
+    let mut air_above = 0;
+    for y in (bottom..=top).rev() {
+        let id = access.block(BlockPos { x, y, z })?;
+        let block = registry.get(id);
+        if block.collision_type().is_solid() && !block.liquid() {
+            if air_above >= 2 {
+                return Some(y);
+            }
+            air_above = 0;
+        } else if block.collision_type().is_liquid() {
+            // don't spawn mobs underwater
+            air_above = 0;
+        } else {
+            air_above += 1;
+        }
+    }
+    None
+}
+
+pub fn spawn_mobs(
+    mut cmd: Commands,
+    time: Res<GameTime>,
+    settings: Res<MobSettings>,
+    mut access: ResMut<ChunkAccess>,
+    mut since_last_spawn: Local<Duration>,
+    loaders: Query<&Transform, With<DynamicChunkLoader>>,
+    mobs: Query<&Mob>,
+) {
+    *since_last_spawn += time.delta();
+    if *since_last_spawn < settings.spawn_interval {
+        return;
+    }
+    *since_last_spawn = Duration::ZERO;
+
+    if mobs.iter().count() >= settings.max_mobs {
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+    let loaders = loaders.iter().collect::<Vec<_>>();
+    let loader = match loaders.len() {
+        0 => return,
+        len => loaders[rng.gen_range(0, len)],
+    };
+
+    let angle = rng.gen_range(0.0, std::f32::consts::TAU);
+    let distance = rng.gen_range(settings.min_spawn_distance, settings.max_spawn_distance);
+    let center = loader.pos();
+    let x = (center.x + distance * angle.cos()).floor() as i32;
+    let z = (center.z + distance * angle.sin()).floor() as i32;
+    let top = center.y as i32 + 32;
+    let bottom = center.y as i32 - 64;
+
+    if let Some(y) = find_surface(&mut access, x, z, top, bottom) {
+        let dims = settings.dimensions;
+        let pos = vector![
+            x as f32 + 0.5 - dims.x / 2.0,
+            y as f32 + 1.0,
+            z as f32 + 0.5 - dims.z / 2.0
+        ];
+
+        cmd.spawn()
+            .insert(Mob::default())
+            .insert(PathFollower::default())
+            .insert(Transform::default().translated(&pos))
+            .insert(RigidBody::default())
+            .insert(Health::new(settings.max_health))
+            .insert(AabbCollider::new(Aabb {
+                min: point![0.0, 0.0, 0.0],
+                max: Point3::from(dims),
+            }));
+    }
+}
+
+pub fn despawn_far_mobs(
+    mut cmd: Commands,
+    settings: Res<MobSettings>,
+    loaders: Query<&Transform, With<DynamicChunkLoader>>,
+    mobs: Query<(Entity, &Transform), With<Mob>>,
+) {
+    let max_dist_sq = settings.despawn_distance * settings.despawn_distance;
+    mobs.for_each(|(entity, transform)| {
+        let near_any = loaders
+            .iter()
+            .any(|loader| (loader.pos() - transform.pos()).magnitude_squared() <= max_dist_sq);
+
+        if !near_any {
+            cmd.entity(entity).despawn();
+        }
+    });
+}
+
+pub fn despawn_dead_mobs(
+    mut cmd: Commands,
+    mut death_events: EventReader<DeathEvent>,
+    mobs: Query<&Mob>,
+) {
+    for event in death_events.iter() {
+        if mobs.get(event.entity).is_ok() {
+            cmd.entity(event.entity).despawn();
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MobPlugin {}
+
+impl Plugin for MobPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<MobSettings>();
+        app.add_system(spawn_mobs.system().with_run_criteria(playing.system()));
+        app.add_system(despawn_far_mobs.system());
+        app.add_system(despawn_dead_mobs.system());
+        app.add_system(update_mob_ai.system().with_run_criteria(playing.system()));
+    }
+}
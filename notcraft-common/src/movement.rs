@@ -0,0 +1,572 @@
+//! player movement, as a stream of numbered commands.
+//!
+//! instead of pushing the player's rigidbody around directly, the player
+//! controller turns each tick's input into a [`MovementCommand`], which gets
+//! applied by [`apply_movement_commands`] like any other tick's worth of
+//! movement would be. commands are kept around after they're applied until
+//! they've been confirmed, so that once a server decides where players really
+//! are, the local player can still move right away without waiting to hear
+//! back. when the server disagrees with where we think the player is, a
+//! [`MovementCorrection`] rewinds the player to where the server says they were
+//! after one of their commands, then replays every command after that one on
+//! top of it.
+
+use crate::{
+    game_state::playing,
+    physics::{
+        do_terrain_collision_wrapper, integrate_motion, overlaps_blocks, AabbCollider,
+        PreviousCollider, RigidBody, SurfaceProperties, GRAVITY,
+    },
+    prelude::*,
+    transform::Transform,
+    util,
+    world::chunk::ChunkAccess,
+};
+use nalgebra::{vector, Point3, UnitQuaternion, Vector3};
+use std::collections::VecDeque;
+
+/// how fast the player climbs up ladders and such, in blocks per second.
+pub const CLIMB_SPEED: f32 = 4.0;
+
+/// how many applied commands are kept around waiting to be confirmed. at 60
+/// ticks per second, this is a little over 4 seconds' worth.
+pub const MAX_UNCONFIRMED_COMMANDS: usize = 256;
+
+/// which movement keys were held down during a tick.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MovementInput {
+    pub forward: bool,
+    pub backward: bool,
+    pub left: bool,
+    pub right: bool,
+    /// jumping or swimming upwards, or going up while flying.
+    pub up: bool,
+    /// going down while flying.
+    pub down: bool,
+    pub sprint: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MovementCommand {
+    /// commands from the same entity are numbered in the order they were
+    /// issued, starting from zero.
+    pub sequence: u64,
+    /// how far the game moved forward during the tick this was issued in, in
+    /// seconds.
+    pub dt: f32,
+    /// which way the entity was facing.
+    pub yaw: f32,
+    pub flying: bool,
+    pub input: MovementInput,
+}
+
+/// where an entity is, and how it's moving. this is what a server would send
+/// back about where it thinks the entity is.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MovementState {
+    pub translation: Vector3<f32>,
+    /// zero for entities without a rigidbody, like players that are flying.
+    pub velocity: Vector3<f32>,
+    pub on_ground: bool,
+    pub in_liquid: bool,
+    pub climbing: bool,
+    pub ground: SurfaceProperties,
+}
+
+impl MovementState {
+    pub fn read(
+        transform: &Transform,
+        rigidbody: Option<&RigidBody>,
+        collider: &AabbCollider,
+    ) -> Self {
+        Self {
+            translation: transform.translation.vector,
+            velocity: rigidbody.map_or(vector![0.0, 0.0, 0.0], |rigidbody| rigidbody.velocity),
+            on_ground: collider.on_ground,
+            in_liquid: collider.in_liquid,
+            climbing: collider.climbing,
+            ground: collider.ground,
+        }
+    }
+
+    fn write(
+        &self,
+        transform: &mut Transform,
+        rigidbody: Option<&mut RigidBody>,
+        collider: &mut AabbCollider,
+    ) {
+        transform.translation.vector = self.translation;
+        if let Some(rigidbody) = rigidbody {
+            rigidbody.velocity = self.velocity;
+        }
+        collider.on_ground = self.on_ground;
+        collider.in_liquid = self.in_liquid;
+        collider.climbing = self.climbing;
+        collider.ground = self.ground;
+    }
+}
+
+/// sent when the server says where an entity really was right after it
+/// applied one of that entity's commands.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MovementCorrection {
+    pub entity: Entity,
+    /// the last command that the server applied.
+    pub sequence: u64,
+    pub state: MovementState,
+}
+
+/// the movement commands for a single entity.
+#[derive(Clone, Debug, Default)]
+pub struct MovementCommands {
+    next_sequence: u64,
+    /// the newest command that the server has confirmed.
+    confirmed: Option<u64>,
+    /// commands that have been issued, but not applied yet.
+    queued: VecDeque<MovementCommand>,
+    /// commands that have been applied, but not confirmed yet, oldest first.
+    unconfirmed: VecDeque<MovementCommand>,
+    /// the newest command that was issued, even if it's been confirmed since.
+    latest: Option<MovementCommand>,
+}
+
+impl MovementCommands {
+    /// queues up a command to be applied this tick, returning its sequence
+    /// number.
+    pub fn issue(&mut self, dt: f32, yaw: f32, flying: bool, input: MovementInput) -> u64 {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        let command = MovementCommand {
+            sequence,
+            dt,
+            yaw,
+            flying,
+            input,
+        };
+        self.queued.push_back(command);
+        self.latest = Some(command);
+        sequence
+    }
+
+    /// the newest command that was issued, which says what the entity is
+    /// trying to do right now, like whether it's sprinting.
+    pub fn latest(&self) -> Option<&MovementCommand> {
+        self.latest.as_ref()
+    }
+
+    /// commands that have been applied, but that the server hasn't confirmed
+    /// yet. these are what a client would send to the server.
+    pub fn unconfirmed(&self) -> impl Iterator<Item = &MovementCommand> + '_ {
+        self.unconfirmed.iter()
+    }
+
+    /// takes the next command that should be applied, and holds onto it until
+    /// it's confirmed.
+    fn next_queued(&mut self) -> Option<MovementCommand> {
+        let command = self.queued.pop_front()?;
+        if self.unconfirmed.len() >= MAX_UNCONFIRMED_COMMANDS {
+            self.unconfirmed.pop_front();
+        }
+        self.unconfirmed.push_back(command);
+        Some(command)
+    }
+
+    /// rewinds to `state`, which is where the server says the entity was right
+    /// after applying command `sequence`, and replays every command since then
+    /// on top of it. returns where that puts the entity now, or `None` if a
+    /// newer correction was already applied, since packets can arrive out of
+    /// order.
+    pub fn correct(
+        &mut self,
+        access: &mut ChunkAccess,
+        collider: &AabbCollider,
+        sequence: u64,
+        mut state: MovementState,
+    ) -> Option<MovementState> {
+        if self
+            .confirmed
+            .is_some_and(|confirmed| sequence <= confirmed)
+        {
+            return None;
+        }
+
+        self.confirmed = Some(sequence);
+        while self
+            .unconfirmed
+            .front()
+            .is_some_and(|command| command.sequence <= sequence)
+        {
+            self.unconfirmed.pop_front();
+        }
+
+        for command in self.unconfirmed.iter() {
+            simulate(access, collider, &mut state, command);
+        }
+        Some(state)
+    }
+}
+
+/// turns a movement along the XZ plane relative to where the entity is facing
+/// into a movement in world space.
+fn project_xz(yaw: f32, x: f32, z: f32) -> Vector3<f32> {
+    UnitQuaternion::from_euler_angles(0.0, yaw, 0.0) * vector![x, 0.0, z]
+}
+
+/// flying moves the entity around directly, without any physics.
+fn fly(command: &MovementCommand, translation: &mut Vector3<f32>) {
+    let input = &command.input;
+    let mut vert_speed = 0.2;
+    let mut horiz_speed = 0.2;
+
+    if input.sprint {
+        horiz_speed *= 10.0;
+        vert_speed *= 10.0;
+    }
+
+    if input.forward {
+        *translation += project_xz(command.yaw, 0.0, -horiz_speed);
+    }
+    if input.backward {
+        *translation += project_xz(command.yaw, 0.0, horiz_speed);
+    }
+    if input.right {
+        *translation += project_xz(command.yaw, horiz_speed, 0.0);
+    }
+    if input.left {
+        *translation += project_xz(command.yaw, -horiz_speed, 0.0);
+    }
+    if input.up {
+        translation.y += vert_speed;
+    }
+    if input.down {
+        translation.y -= vert_speed;
+    }
+}
+
+fn walk(command: &MovementCommand, collider: &AabbCollider, rigidbody: &mut RigidBody) {
+    let input = &command.input;
+    let mut vert_acceleration = 9.0;
+    let mut horiz_acceleration = 70.0;
+
+    // slippery ground is harder to get going on, but also to stop on, since
+    // there's less drag holding the entity back too.
+    let mut grip = 1.0;
+    if collider.on_ground {
+        let ground = collider.ground;
+        grip = ground.friction;
+        horiz_acceleration *= 0.85 * ground.friction * ground.speed_multiplier;
+    }
+
+    if input.sprint {
+        horiz_acceleration *= 5.5;
+        vert_acceleration *= 3.5;
+    }
+
+    if input.forward {
+        rigidbody.acceleration += project_xz(command.yaw, 0.0, -horiz_acceleration);
+    }
+    if input.backward {
+        rigidbody.acceleration += project_xz(command.yaw, 0.0, horiz_acceleration);
+    }
+    if input.right {
+        rigidbody.acceleration += project_xz(command.yaw, horiz_acceleration, 0.0);
+    }
+    if input.left {
+        rigidbody.acceleration += project_xz(command.yaw, -horiz_acceleration, 0.0);
+    }
+
+    // on a ladder, moving forwards or jumping climbs up, and letting go of both
+    // slides back down, which the physics keeps from getting too fast.
+    if collider.climbing && (input.forward || input.up) {
+        rigidbody.velocity.y = CLIMB_SPEED;
+    } else if input.up {
+        if collider.in_liquid {
+            rigidbody.acceleration.y += 60.0;
+        } else if collider.on_ground {
+            rigidbody.velocity.y = vert_acceleration;
+        }
+    }
+
+    let horiz_drag = 0.1 * grip;
+    rigidbody.velocity.x *= util::lerp(1.0 - horiz_drag, 0.0, command.dt * grip);
+    rigidbody.velocity.z *= util::lerp(1.0 - horiz_drag, 0.0, command.dt * grip);
+
+    if collider.in_liquid {
+        rigidbody.velocity.y *= util::lerp(0.96, 0.0, command.dt);
+    }
+}
+
+/// runs a whole tick of movement for an entity with the given collider,
+/// outside of the ECS. this has to line up with what applying a command and
+/// then running physics and collision does, or replayed commands will end up
+/// somewhere different than they did the first time around.
+pub fn simulate(
+    access: &mut ChunkAccess,
+    collider: &AabbCollider,
+    state: &mut MovementState,
+    command: &MovementCommand,
+) {
+    let mut transform = Transform::to(Point3::from(state.translation));
+    let mut collider = AabbCollider {
+        on_ground: state.on_ground,
+        in_liquid: state.in_liquid,
+        climbing: state.climbing,
+        ground: state.ground,
+        ..*collider
+    };
+
+    if command.flying {
+        fly(command, &mut transform.translation.vector);
+        state.velocity = vector![0.0, 0.0, 0.0];
+    } else {
+        let previous = PreviousCollider {
+            aabb_world: collider.aabb.transformed(&transform),
+        };
+        let mut rigidbody = RigidBody {
+            velocity: state.velocity,
+            ..Default::default()
+        };
+
+        walk(command, &collider, &mut rigidbody);
+        rigidbody.acceleration.y -= GRAVITY;
+        integrate_motion(
+            &mut rigidbody,
+            &mut transform,
+            collider.climbing,
+            command.dt,
+        );
+        do_terrain_collision_wrapper(
+            access,
+            &mut collider,
+            &previous,
+            &mut rigidbody,
+            &mut transform,
+        );
+        state.velocity = rigidbody.velocity;
+    }
+
+    let aabb = collider.aabb.transformed(&transform);
+    if let Some(climbing) = overlaps_blocks(access, &aabb, |block| block.climbable()) {
+        collider.climbing = climbing;
+    }
+
+    state.translation = transform.translation.vector;
+    state.on_ground = collider.on_ground;
+    state.in_liquid = collider.in_liquid;
+    state.climbing = collider.climbing;
+    state.ground = collider.ground;
+}
+
+pub fn apply_movement_commands(
+    query: Query<(
+        &mut MovementCommands,
+        &mut Transform,
+        Option<&mut RigidBody>,
+        &AabbCollider,
+    )>,
+) {
+    query.for_each_mut(|(mut commands, mut transform, mut rigidbody, collider)| {
+        while let Some(command) = commands.next_queued() {
+            match rigidbody.as_deref_mut() {
+                Some(rigidbody) if !command.flying => walk(&command, collider, rigidbody),
+                _ => fly(&command, &mut transform.translation.vector),
+            }
+        }
+    });
+}
+
+type CorrectedQuery<'a> = (
+    &'a mut MovementCommands,
+    &'a mut Transform,
+    Option<&'a mut RigidBody>,
+    &'a mut AabbCollider,
+    Option<&'a mut PreviousCollider>,
+);
+
+pub fn reconcile_movement(
+    mut access: ResMut<ChunkAccess>,
+    mut corrections: EventReader<MovementCorrection>,
+    mut query: Query<CorrectedQuery>,
+) {
+    for correction in corrections.iter() {
+        let (mut commands, mut transform, mut rigidbody, mut collider, previous) =
+            match query.get_mut(correction.entity) {
+                Ok(entity) => entity,
+                Err(_) => continue,
+            };
+
+        let corrected = commands.correct(
+            &mut access,
+            &collider,
+            correction.sequence,
+            correction.state,
+        );
+        if let Some(state) = corrected {
+            state.write(&mut transform, rigidbody.as_deref_mut(), &mut collider);
+            // otherwise, collision would see the entity as having moved all the way
+            // from where it was before the correction.
+            if let Some(mut previous) = previous {
+                previous.aabb_world = collider.aabb.transformed(&transform);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub struct MovementApplication;
+
+#[derive(Debug, Default)]
+pub struct MovementPlugin {}
+
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<MovementCorrection>();
+        app.add_system(
+            reconcile_movement
+                .system()
+                .before(MovementApplication)
+                .with_run_criteria(playing.system()),
+        );
+        app.add_system(
+            apply_movement_commands
+                .system()
+                .label(MovementApplication)
+                .with_run_criteria(playing.system()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aabb::Aabb,
+        world::{
+            test_util::{flat_world, test_registry},
+            VoxelWorld,
+        },
+    };
+    use std::sync::Arc;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    // `floor` below y=0, and air above it.
+    fn make_world(floor: &str) -> Arc<VoxelWorld> {
+        let registry = test_registry();
+        flat_world(&registry, 1, registry.lookup(floor))
+    }
+
+    fn input(tick: usize) -> MovementInput {
+        MovementInput {
+            forward: true,
+            right: tick % 20 < 5,
+            up: [0, 15, 30, 45].contains(&tick),
+            sprint: tick > 30,
+            ..Default::default()
+        }
+    }
+
+    /// where an entity dropped at `height` above a floor made of `floor` is
+    /// after each tick of following `input`.
+    fn walk_on<F>(floor: &str, height: f32, ticks: usize, input: F) -> Vec<MovementState>
+    where
+        F: Fn(usize) -> MovementInput,
+    {
+        let world = make_world(floor);
+        let mut access = ChunkAccess::new(&world);
+        let collider = AabbCollider::new(Aabb::with_dimensions(vector![0.8, 1.8, 0.8]));
+        let transform = Transform::to(vector![0.5, 0.9 + height, 0.5].into());
+        let mut state = MovementState::read(&transform, None, &collider);
+
+        let mut commands = MovementCommands::default();
+        let mut states = vec![];
+        for tick in 0..ticks {
+            commands.issue(DT, 0.0, false, input(tick));
+            let command = commands.next_queued().unwrap();
+            simulate(&mut access, &collider, &mut state, &command);
+            states.push(state);
+        }
+        states
+    }
+
+    #[test]
+    fn slippery_ground_keeps_entities_sliding() {
+        // walk forwards for a bit, then let go and see how far they slide
+        let coast = |floor| {
+            let states = walk_on(floor, 0.0, 120, |tick| MovementInput {
+                forward: tick < 60,
+                ..Default::default()
+            });
+            (states[119].translation - states[59].translation).magnitude()
+        };
+        assert!(coast("ice") > 2.0 * coast("stone"));
+    }
+
+    #[test]
+    fn slow_ground_slows_walking() {
+        let walked = |floor| {
+            let states = walk_on(floor, 0.0, 60, |_| MovementInput {
+                forward: true,
+                ..Default::default()
+            });
+            (states[59].translation - states[0].translation).magnitude()
+        };
+        assert!(walked("soul_sand") < 0.6 * walked("stone"));
+    }
+
+    #[test]
+    fn bouncy_ground_bounces_falling_entities() {
+        let bounced = |floor| {
+            let states = walk_on(floor, 5.0, 120, |_| MovementInput::default());
+            let landed = states.iter().position(|state| state.on_ground).unwrap();
+            states[landed..].iter().any(|state| state.velocity.y > 0.0)
+        };
+        assert!(bounced("slime"));
+        assert!(!bounced("stone"));
+    }
+
+    #[test]
+    fn corrections_replay_unconfirmed_commands() {
+        let world = make_world("stone");
+        let mut access = ChunkAccess::new(&world);
+        let collider = AabbCollider::new(Aabb::with_dimensions(vector![0.8, 1.8, 0.8]));
+        let start = MovementState::read(
+            &Transform::to(vector![0.5, 0.9, 0.5].into()),
+            None,
+            &collider,
+        );
+
+        // predict where each command takes the player
+        let mut commands = MovementCommands::default();
+        let mut state = start;
+        let mut predicted = vec![];
+        for tick in 0..60 {
+            commands.issue(DT, 0.3, false, input(tick));
+            let command = commands.next_queued().unwrap();
+            simulate(&mut access, &collider, &mut state, &command);
+            predicted.push(state);
+        }
+        assert!(predicted.iter().any(|state| !state.on_ground));
+        assert!(state.on_ground);
+
+        // the server agreeing with us changes nothing
+        let corrected = commands.correct(&mut access, &collider, 10, predicted[10]);
+        assert_eq!(corrected, Some(predicted[59]));
+        assert_eq!(commands.unconfirmed().count(), 49);
+
+        // being pushed to the side carries through the rest of the commands,
+        // since the ground is flat
+        let mut pushed = predicted[20];
+        pushed.translation.x += 1.0;
+        let corrected = commands
+            .correct(&mut access, &collider, 20, pushed)
+            .unwrap();
+        assert_eq!(commands.unconfirmed().next().unwrap().sequence, 21);
+        assert!((corrected.translation.x - predicted[59].translation.x - 1.0).abs() < 1e-3);
+        assert!((corrected.translation.y - predicted[59].translation.y).abs() < 1e-3);
+
+        // older corrections that show up late are ignored
+        assert_eq!(commands.correct(&mut access, &collider, 15, start), None);
+        assert_eq!(commands.unconfirmed().count(), 39);
+    }
+}
@@ -0,0 +1,331 @@
+//! squeezing chunk sections down before they're sent over the network.
+//!
+//! a section full of a single block is sent as just that block. anything else
+//! is turned into a palette of the blocks it uses, and a list of indices into
+//! that palette, packed into as few bits as it takes to tell the palette
+//! entries apart. terrain tends to be made of big runs of the same few blocks,
+//! so that packed list is then compressed with LZ4, which is good at squashing
+//! repeats and cheap to undo on the client.
+//!
+//! a compressed section starts with a tag byte: [`HOMOGENEOUS_TAG`] is
+//! followed by a little-endian `u16` block ID, and [`PALETTED_TAG`] is followed
+//! by an LZ4 block with its uncompressed size in front, as a little-endian
+//! `u32`. that block holds a little-endian `u16` palette length, the palette's
+//! block IDs as `u16`s, the number of bits per index as a `u8`, and then the
+//! indices packed into little-endian `u64`s, lowest bits first. indices never
+//! straddle two words, so that unpacking them doesn't need to look at more than
+//! one word at a time.
+//!
+//! the uncompressed size comes from a peer or a file, so it isn't trusted: one
+//! bigger than any section could need is rejected before anything is allocated
+//! for it.
+
+use crate::{
+    prelude::*,
+    world::{
+        chunk::{ArrayChunk, ChunkData, CHUNK_LENGTH_3},
+        persistence::BlockIdRemap,
+        registry::BlockId,
+    },
+};
+use std::collections::HashMap;
+
+pub const HOMOGENEOUS_TAG: u8 = 0;
+pub const PALETTED_TAG: u8 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressedSection {
+    bytes: Box<[u8]>,
+}
+
+fn wire_id(id: BlockId) -> u16 {
+    // there are nowhere near this many kinds of blocks
+    u16::try_from(id.0).expect("block ID too big to send over the network")
+}
+
+/// how many bits it takes to tell `palette_len` different entries apart.
+fn bits_per_index(palette_len: usize) -> usize {
+    (usize::BITS - (palette_len - 1).leading_zeros()).max(1) as usize
+}
+
+fn packed_words(bits: usize) -> usize {
+    let per_word = 64 / bits;
+    CHUNK_LENGTH_3.div_ceil(per_word)
+}
+
+/// the size of a paletted body with the longest palette and widest indices
+/// there could be.
+fn max_paletted_body_len() -> usize {
+    2 + 2 * u16::MAX as usize + 1 + 8 * packed_words(16)
+}
+
+/// reads through a decompressed paletted body, complaining instead of panicking
+/// when it's too short.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        if self.0.len() < N {
+            bail!("compressed section ended early");
+        }
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(head.try_into().unwrap())
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+}
+
+impl CompressedSection {
+    pub fn compress(data: &ChunkData<BlockId>) -> Self {
+        let blocks = match data {
+            &ChunkData::Homogeneous(id) => return Self::homogeneous(id),
+            ChunkData::Array(data) => data.as_slice(),
+        };
+
+        let mut palette = vec![];
+        let mut palette_indices = HashMap::new();
+        let indices = blocks.iter().map(|&id| {
+            *palette_indices.entry(id).or_insert_with(|| {
+                palette.push(id);
+                palette.len() - 1
+            })
+        });
+        let indices = indices.collect::<Vec<_>>();
+
+        // sections that were filled in block by block can still end up all the
+        // same block
+        if palette.len() == 1 {
+            return Self::homogeneous(palette[0]);
+        }
+
+        let bits = bits_per_index(palette.len());
+        let per_word = 64 / bits;
+        let mut body = Vec::with_capacity(3 + 2 * palette.len() + 8 * packed_words(bits));
+        body.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+        for &id in palette.iter() {
+            body.extend_from_slice(&wire_id(id).to_le_bytes());
+        }
+        body.push(bits as u8);
+        for chunk in indices.chunks(per_word) {
+            let word = chunk.iter().enumerate().fold(0u64, |word, (i, &index)| {
+                word | (index as u64) << (i * bits)
+            });
+            body.extend_from_slice(&word.to_le_bytes());
+        }
+
+        let mut bytes = vec![PALETTED_TAG];
+        bytes.extend(lz4_flex::compress_prepend_size(&body));
+        Self::from_bytes(bytes)
+    }
+
+    /// compresses a section with its blocks turned into saved block IDs, to be
+    /// read back with [`CompressedSection::decompress_remapped`].
+    pub fn compress_remapped(data: &ChunkData<BlockId>, remap: &BlockIdRemap) -> Self {
+        let saved = match data {
+            &ChunkData::Homogeneous(id) => ChunkData::Homogeneous(remap.to_saved(id)),
+            ChunkData::Array(blocks) => {
+                let saved = blocks.as_slice().iter().map(|&id| remap.to_saved(id));
+                ChunkData::from_elements(saved.collect()).unwrap()
+            }
+        };
+        Self::compress(&saved)
+    }
+
+    fn homogeneous(id: BlockId) -> Self {
+        let [lo, hi] = wire_id(id).to_le_bytes();
+        Self::from_bytes(vec![HOMOGENEOUS_TAG, lo, hi])
+    }
+
+    pub fn decompress(&self) -> Result<ChunkData<BlockId>> {
+        self.decompress_with(|id| Ok(BlockId(id as usize)))
+    }
+
+    /// decompresses a section that was compressed with saved block IDs, turning
+    /// them back into the registry's IDs.
+    pub fn decompress_remapped(&self, remap: &BlockIdRemap) -> Result<ChunkData<BlockId>> {
+        self.decompress_with(|id| remap.from_saved(BlockId(id as usize)))
+    }
+
+    fn decompress_with<F>(&self, mut map_id: F) -> Result<ChunkData<BlockId>>
+    where
+        F: FnMut(u16) -> Result<BlockId>,
+    {
+        let (&tag, rest) = match self.bytes.split_first() {
+            Some(split) => split,
+            None => bail!("compressed section was empty"),
+        };
+
+        match tag {
+            HOMOGENEOUS_TAG => {
+                let mut reader = Reader(rest);
+                let id = map_id(reader.u16()?)?;
+                if !reader.0.is_empty() {
+                    bail!("homogeneous section had extra data after it");
+                }
+                Ok(ChunkData::Homogeneous(id))
+            }
+            PALETTED_TAG => {
+                let mut reader = Reader(rest);
+                let size = u32::from_le_bytes(reader.take()?) as usize;
+                if size > max_paletted_body_len() {
+                    bail!("paletted section claimed to be {} bytes long", size);
+                }
+                let body = lz4_flex::decompress(reader.0, size)?;
+                Self::read_paletted(&mut Reader(&body), map_id)
+            }
+            _ => bail!("unknown compressed section tag {}", tag),
+        }
+    }
+
+    fn read_paletted<F>(reader: &mut Reader, mut map_id: F) -> Result<ChunkData<BlockId>>
+    where
+        F: FnMut(u16) -> Result<BlockId>,
+    {
+        let palette_len = reader.u16()? as usize;
+        if palette_len < 2 {
+            bail!("paletted section had only {} palette entries", palette_len);
+        }
+        let palette = (0..palette_len)
+            .map(|_| map_id(reader.u16()?))
+            .collect::<Result<Vec<_>>>()?;
+
+        let [bits] = reader.take()?;
+        let bits = bits as usize;
+        if bits != bits_per_index(palette_len) {
+            bail!(
+                "paletted section used {} bits for {} entries",
+                bits,
+                palette_len
+            );
+        }
+
+        let per_word = 64 / bits;
+        let mask = (1u64 << bits) - 1;
+        let mut blocks = Vec::with_capacity(CHUNK_LENGTH_3);
+        for _ in 0..packed_words(bits) {
+            let word = u64::from_le_bytes(reader.take()?);
+            let remaining = usize::min(per_word, CHUNK_LENGTH_3 - blocks.len());
+            for i in 0..remaining {
+                let index = (word >> (i * bits) & mask) as usize;
+                match palette.get(index) {
+                    Some(&id) => blocks.push(id),
+                    None => bail!("palette index {} out of range", index),
+                }
+            }
+        }
+
+        if !reader.0.is_empty() {
+            bail!("paletted section had extra data after it");
+        }
+        Ok(ChunkData::Array(ArrayChunk::try_from(
+            blocks.into_boxed_slice(),
+        )?))
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes: bytes.into_boxed_slice(),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{registry::AIR_BLOCK, test_util::test_registry};
+
+    fn round_trip(data: ChunkData<BlockId>) -> usize {
+        let compressed = CompressedSection::compress(&data);
+        let decompressed = compressed.decompress().unwrap();
+        let blocks = |data: &ChunkData<BlockId>| {
+            (0..CHUNK_LENGTH_3)
+                .map(|i| data.get([i / 1024, i / 32 % 32, i % 32]))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(blocks(&decompressed), blocks(&data));
+        compressed.as_bytes().len()
+    }
+
+    #[test]
+    fn sections_survive_compression() {
+        assert_eq!(round_trip(ChunkData::Homogeneous(BlockId(7))), 3);
+
+        // filled in one block at a time, but still all air
+        let air = ArrayChunk::homogeneous(AIR_BLOCK);
+        assert_eq!(round_trip(ChunkData::Array(air.clone())), 3);
+        assert!(matches!(
+            CompressedSection::compress(&ChunkData::Array(air)).decompress(),
+            Ok(ChunkData::Homogeneous(AIR_BLOCK))
+        ));
+
+        // layers of a few different blocks, like terrain
+        let mut layered = ArrayChunk::homogeneous(AIR_BLOCK);
+        for i in 0..CHUNK_LENGTH_3 {
+            let [x, y, z] = [i / 1024, i / 32 % 32, i % 32];
+            layered[[x, y, z]] = BlockId(y / 5 + usize::from(x == z));
+        }
+        assert!(round_trip(ChunkData::Array(layered)) < 512);
+
+        // every index has a different block in it, which takes the most bits
+        let mut noisy = ArrayChunk::homogeneous(AIR_BLOCK);
+        for i in 0..CHUNK_LENGTH_3 {
+            noisy[[i / 1024, i / 32 % 32, i % 32]] = BlockId(i * 7919 % 3001);
+        }
+        round_trip(ChunkData::Array(noisy));
+    }
+
+    #[test]
+    fn saved_sections_are_remapped() {
+        let registry = test_registry();
+        let stone = registry.lookup("stone");
+
+        // a save where stone and air swapped places
+        let mut table = vec!["stone".to_string(), "air".to_string()];
+        let remap = BlockIdRemap::new(&mut table, &registry, AIR_BLOCK);
+
+        let mut layered = ArrayChunk::homogeneous(BlockId(1));
+        layered[[3, 4, 5]] = BlockId(0);
+        let compressed = CompressedSection::compress(&ChunkData::Array(layered));
+        let data = compressed.decompress_remapped(&remap).unwrap();
+        assert_eq!(data.get([3, 4, 5]), stone);
+        assert_eq!(data.get([0, 0, 0]), AIR_BLOCK);
+
+        let homogeneous = CompressedSection::compress(&ChunkData::Homogeneous(BlockId(0)));
+        assert!(matches!(
+            homogeneous.decompress_remapped(&remap),
+            Ok(ChunkData::Homogeneous(id)) if id == stone
+        ));
+
+        // and registry IDs come back out as themselves after a round trip
+        let mut mixed = ArrayChunk::homogeneous(stone);
+        mixed[[5, 4, 3]] = AIR_BLOCK;
+        let compressed = CompressedSection::compress_remapped(&ChunkData::Array(mixed), &remap);
+        assert_eq!(compressed.decompress().unwrap().get([0, 0, 0]), BlockId(0));
+        let data = compressed.decompress_remapped(&remap).unwrap();
+        assert_eq!(data.get([5, 4, 3]), AIR_BLOCK);
+        assert_eq!(data.get([0, 0, 0]), stone);
+    }
+
+    #[test]
+    fn garbled_sections_are_rejected() {
+        let mut layered = ArrayChunk::homogeneous(AIR_BLOCK);
+        layered[[3, 4, 5]] = BlockId(2);
+        let good = CompressedSection::compress(&ChunkData::Array(layered));
+
+        let mut truncated = good.as_bytes().to_vec();
+        truncated.truncate(truncated.len() - 2);
+        // claims to unpack to 4 GiB, which shouldn't be allocated
+        let oversized = vec![PALETTED_TAG, 0xff, 0xff, 0xff, 0xff, 0];
+        let garbled = [vec![], vec![9, 0, 0], vec![HOMOGENEOUS_TAG, 1]];
+        for broken in garbled.into_iter().chain([truncated, oversized]) {
+            assert!(CompressedSection::from_bytes(broken).decompress().is_err());
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! keeping track of which chunks each client cares about, so that the server
+//! only tells clients about changes to chunks they actually have loaded.
+//!
+//! a client's area of interest follows their chunk loader around: chunks
+//! within its load radius are sent when the client gets near them, and are
+//! only forgotten about once the client goes past its unload radius, the same
+//! way the loader itself decides what to load and unload.
+
+use super::packet::ServerToClientPlayPacket;
+use crate::world::{chunk::ChunkSectionPos, BlockUpdateEvent, ChunkPos, DynamicChunkLoader};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ClientId(pub u64);
+
+/// how a client's area of interest changed after it moved.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InterestChange {
+    /// chunks that the client has to be sent in full.
+    pub entered: Vec<ChunkPos>,
+    /// chunks that the client won't hear about anymore, and can unload.
+    pub left: Vec<ChunkPos>,
+}
+
+#[derive(Debug, Default)]
+pub struct InterestTracker {
+    clients: HashMap<ClientId, HashSet<ChunkPos>>,
+    watchers: HashMap<ChunkPos, HashSet<ClientId>>,
+}
+
+fn within(center: ChunkPos, radius: usize, pos: ChunkPos) -> bool {
+    let radius = radius as i32;
+    i32::abs(center.x - pos.x) <= radius && i32::abs(center.z - pos.z) <= radius
+}
+
+impl InterestTracker {
+    /// moves a client's area of interest to be centered on `center`, which is
+    /// the chunk their loader is in.
+    pub fn update_client(
+        &mut self,
+        client: ClientId,
+        center: ChunkPos,
+        loader: &DynamicChunkLoader,
+    ) -> InterestChange {
+        let watching = self.clients.entry(client).or_default();
+        let mut change = InterestChange::default();
+
+        watching.retain(|&pos| {
+            let keep = within(center, loader.unload_radius, pos);
+            if !keep {
+                change.left.push(pos);
+            }
+            keep
+        });
+
+        let radius = loader.load_radius as i32;
+        for x in center.x - radius..=center.x + radius {
+            for z in center.z - radius..=center.z + radius {
+                if watching.insert(ChunkPos { x, z }) {
+                    change.entered.push(ChunkPos { x, z });
+                }
+            }
+        }
+
+        for pos in change.left.iter() {
+            if let Some(watchers) = self.watchers.get_mut(pos) {
+                watchers.remove(&client);
+                if watchers.is_empty() {
+                    self.watchers.remove(pos);
+                }
+            }
+        }
+        for &pos in change.entered.iter() {
+            self.watchers.entry(pos).or_default().insert(client);
+        }
+
+        change.entered.sort();
+        change.left.sort();
+        change
+    }
+
+    /// forgets about a client, like when they disconnect.
+    pub fn remove_client(&mut self, client: ClientId) {
+        for pos in self.clients.remove(&client).into_iter().flatten() {
+            if let Some(watchers) = self.watchers.get_mut(&pos) {
+                watchers.remove(&client);
+                if watchers.is_empty() {
+                    self.watchers.remove(&pos);
+                }
+            }
+        }
+    }
+
+    pub fn is_watching(&self, client: ClientId, pos: ChunkPos) -> bool {
+        self.clients
+            .get(&client)
+            .is_some_and(|watching| watching.contains(&pos))
+    }
+
+    pub fn watchers(&self, pos: ChunkPos) -> impl Iterator<Item = ClientId> + '_ {
+        self.watchers.get(&pos).into_iter().flatten().copied()
+    }
+
+    /// sorts a tick's worth of block updates into the packets that each client
+    /// needs to hear about them. clients that aren't watching any of the
+    /// updated chunks are left out.
+    pub fn route_block_updates(
+        &self,
+        updates: &[BlockUpdateEvent],
+    ) -> HashMap<ClientId, Vec<ServerToClientPlayPacket>> {
+        let mut changes = HashMap::<_, Vec<_>>::new();
+        for update in updates.iter() {
+            let chunk = ChunkSectionPos::from(update.pos).column();
+            for client in self.watchers(chunk) {
+                changes
+                    .entry(client)
+                    .or_default()
+                    .push((update.pos, update.new_id));
+            }
+        }
+
+        changes
+            .into_iter()
+            .map(|(client, changes)| (client, ServerToClientPlayPacket::block_deltas(changes)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{registry::AIR_BLOCK, BlockPos};
+
+    #[test]
+    fn interest_follows_loaders() {
+        let loader = DynamicChunkLoader {
+            load_radius: 1,
+            unload_radius: 2,
+        };
+        let (alice, bob) = (ClientId(1), ClientId(2));
+        let mut tracker = InterestTracker::default();
+
+        let change = tracker.update_client(alice, ChunkPos { x: 0, z: 0 }, &loader);
+        assert_eq!(change.entered.len(), 9);
+        assert!(change.left.is_empty());
+        tracker.update_client(bob, ChunkPos { x: 10, z: 0 }, &loader);
+
+        // moving over by one only sends the new edge, and nothing is dropped
+        // until it's past the unload radius
+        let change = tracker.update_client(alice, ChunkPos { x: 1, z: 0 }, &loader);
+        assert_eq!(
+            change.entered,
+            (-1..=1).map(|z| ChunkPos { x: 2, z }).collect::<Vec<_>>()
+        );
+        assert!(change.left.is_empty());
+        let change = tracker.update_client(alice, ChunkPos { x: 2, z: 0 }, &loader);
+        assert_eq!(
+            change.left,
+            (-1..=1).map(|z| ChunkPos { x: -1, z }).collect::<Vec<_>>()
+        );
+        assert!(tracker.is_watching(alice, ChunkPos { x: 0, z: 1 }));
+        assert!(!tracker.is_watching(alice, ChunkPos { x: -1, z: 1 }));
+
+        let block = |x, z| BlockUpdateEvent {
+            pos: BlockPos { x, y: 5, z },
+            old_id: AIR_BLOCK,
+            new_id: AIR_BLOCK,
+        };
+        let routed = tracker.route_block_updates(&[block(64, 0), block(330, 0), block(-20, 0)]);
+        assert_eq!(routed[&alice].len(), 1);
+        assert_eq!(routed[&bob].len(), 1);
+        assert_eq!(routed.len(), 2);
+
+        tracker.remove_client(alice);
+        assert_eq!(tracker.watchers(ChunkPos { x: 2, z: 0 }).count(), 0);
+        assert_eq!(
+            tracker
+                .watchers(ChunkPos { x: 10, z: 0 })
+                .collect::<Vec<_>>(),
+            [bob]
+        );
+    }
+}
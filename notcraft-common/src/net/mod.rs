@@ -1 +1,5 @@
-// pub mod packet;
+pub mod compression;
+pub mod interest;
+pub mod packet;
+pub mod remote_player;
+pub mod validation;
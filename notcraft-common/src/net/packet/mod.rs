@@ -1,3 +1,12 @@
+use super::{compression::CompressedSection, interest::ClientId};
+use crate::world::{
+    chunk::{index_to_block, ChunkAccess, ChunkSectionIndex, ChunkSectionPos, CHUNK_LENGTH},
+    registry::BlockId,
+    BlockPos,
+};
+use nalgebra::Point3;
+use std::collections::BTreeMap;
+
 #[derive(Clone, Debug)]
 pub enum ClientToServerLoginPacket {
     Login { username: String },
@@ -26,9 +35,215 @@ pub enum ClientToServerPlayPacket {
         id: BlockId,
         position: BlockPos,
     },
+    /// a chat message, or a command if it starts with a `/`.
+    Chat {
+        message: String,
+    },
 }
 
 #[derive(Clone, Debug)]
 pub enum ServerToClientPlayPacket {
-    ChunkData { data: Box<[BlockId]> },
+    /// a whole section, sent when a client first gets near it. see
+    /// [`CompressedSection`] for how it's squeezed down.
+    ChunkData {
+        section: ChunkSectionPos,
+        data: CompressedSection,
+    },
+    BlockChanged {
+        pos: BlockPos,
+        id: BlockId,
+    },
+    /// a bunch of blocks in the same section changed at once, like from an
+    /// explosion. each change is a packed index into the section (see
+    /// [`pack_section_index`]) along with what the block changed to.
+    SectionDelta {
+        section: ChunkSectionPos,
+        changes: Vec<(u16, BlockId)>,
+    },
+    /// another player came within range of this client.
+    PlayerJoined {
+        client: ClientId,
+        name: String,
+        position: Point3<f32>,
+        pitch: f32,
+        yaw: f32,
+    },
+    /// another player moved. see [`ServerToClientPlayPacket::relay_transform`].
+    PlayerTransform {
+        client: ClientId,
+        position: Point3<f32>,
+        pitch: f32,
+        yaw: f32,
+    },
+    PlayerLeft {
+        client: ClientId,
+    },
+}
+
+// every index into a section has to fit in a `u16`.
+const _: () = assert!(CHUNK_LENGTH * CHUNK_LENGTH * CHUNK_LENGTH <= u16::MAX as usize + 1);
+
+pub fn pack_section_index([x, y, z]: ChunkSectionIndex) -> u16 {
+    (CHUNK_LENGTH * CHUNK_LENGTH * x + CHUNK_LENGTH * z + y) as u16
+}
+
+/// returns `None` if `index` is past the end of a section, which can only
+/// happen if the packet it came from was garbled.
+pub fn unpack_section_index(index: u16) -> Option<ChunkSectionIndex> {
+    let index = index as usize;
+    let plane = CHUNK_LENGTH * CHUNK_LENGTH;
+    if index >= plane * CHUNK_LENGTH {
+        return None;
+    }
+    let (x, zy) = (index / plane, index % plane);
+    Some([x, zy % CHUNK_LENGTH, zy / CHUNK_LENGTH])
+}
+
+impl ServerToClientPlayPacket {
+    /// passes a player's `UpdateTransform` along to everyone else who can see
+    /// them.
+    pub fn relay_transform(client: ClientId, packet: &ClientToServerPlayPacket) -> Option<Self> {
+        match *packet {
+            ClientToServerPlayPacket::UpdateTransform {
+                position,
+                pitch,
+                yaw,
+            } => Some(Self::PlayerTransform {
+                client,
+                position,
+                pitch,
+                yaw,
+            }),
+            _ => None,
+        }
+    }
+
+    /// packs a batch of block changes into as few packets as possible: one per
+    /// section that changed. when the same block is changed more than once,
+    /// only the last change is kept.
+    pub fn block_deltas<I>(changes: I) -> Vec<Self>
+    where
+        I: IntoIterator<Item = (BlockPos, BlockId)>,
+    {
+        let mut sections = BTreeMap::<_, BTreeMap<_, _>>::new();
+        for (pos, id) in changes {
+            let (section, index) = pos.section_and_offset();
+            sections
+                .entry(section)
+                .or_default()
+                .insert(pack_section_index(index), id);
+        }
+
+        let packets = sections
+            .into_iter()
+            .map(|(section, changes)| match changes.iter().next() {
+                Some((&index, &id)) if changes.len() == 1 => Self::BlockChanged {
+                    pos: index_to_block(section, unpack_section_index(index).unwrap()),
+                    id,
+                },
+                _ => Self::SectionDelta {
+                    section,
+                    changes: changes.into_iter().collect(),
+                },
+            });
+        packets.collect()
+    }
+
+    /// queues up this packet's block changes on `access`, returning how many
+    /// there were. like any other edit, only the sections around the changed
+    /// blocks get remeshed once the changes are written. changes to sections
+    /// that aren't loaded are dropped, since the server sends the whole chunk
+    /// when it's loaded anyways.
+    pub fn apply_block_changes(&self, access: &mut ChunkAccess) -> usize {
+        let mut applied = 0;
+        let mut apply = |pos: BlockPos, id| {
+            if access.section(pos.into()).is_some() {
+                access.set_block(pos, id);
+                applied += 1;
+            }
+        };
+
+        match self {
+            Self::BlockChanged { pos, id } => apply(*pos, *id),
+            Self::SectionDelta { section, changes } => {
+                for &(index, id) in changes {
+                    match unpack_section_index(index) {
+                        Some(index) => apply(index_to_block(*section, index), id),
+                        None => log::warn!("block change in {:?} had a bad index", section),
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        chunk,
+        registry::AIR_BLOCK,
+        test_util::{flat_world, test_registry},
+        ChunkPos,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn section_indices_round_trip() {
+        for index in [[0, 0, 0], [31, 0, 0], [0, 31, 0], [0, 0, 31], [3, 17, 29]] {
+            assert_eq!(unpack_section_index(pack_section_index(index)), Some(index));
+        }
+        assert_eq!(unpack_section_index(u16::MAX), None);
+    }
+
+    #[test]
+    fn deltas_only_remesh_the_sections_they_touch() {
+        let registry = test_registry();
+        let stone = registry.lookup("stone");
+
+        // two sections out from the origin, with stone in the bottom two layers
+        let world = flat_world(&registry, 2, stone);
+
+        let middle = |y| BlockPos { x: 16, y, z: 16 };
+        let packets = ServerToClientPlayPacket::block_deltas([
+            (middle(-48), AIR_BLOCK),
+            (middle(-47), AIR_BLOCK),
+            (middle(-47), stone),
+            (middle(16), stone),
+            // way above anything that's loaded
+            (middle(500), stone),
+        ]);
+        assert_eq!(packets.len(), 3);
+        assert!(
+            matches!(&packets[0], ServerToClientPlayPacket::SectionDelta { changes, .. } if changes.len() == 2)
+        );
+        assert!(
+            matches!(packets[1], ServerToClientPlayPacket::BlockChanged { pos, .. } if pos == middle(16))
+        );
+
+        let mut access = ChunkAccess::new(&world);
+        let applied = packets
+            .iter()
+            .map(|packet| packet.apply_block_changes(&mut access))
+            .collect::<Vec<_>>();
+        assert_eq!(applied, [2, 1, 0]);
+
+        let mut rebuild = chunk::DirtySections::new();
+        chunk::write_all_chunk_updates(&mut access, &mut rebuild, &mut HashMap::default());
+        assert_eq!(access.block(middle(-48)), Some(AIR_BLOCK));
+        assert_eq!(access.block(middle(-47)), Some(stone));
+        assert_eq!(access.block(middle(16)), Some(stone));
+
+        // both edits are in the middle of their sections, and the stone at -47
+        // didn't actually change anything. the stone at 16 shades everything
+        // below it though, down to the top of the ground in section -1.
+        let column = ChunkPos { x: 0, z: 0 };
+        let mut rebuilt = rebuild.into_keys().collect::<Vec<_>>();
+        rebuilt.sort();
+        let expected = [-2, -1, 0].map(|y| column.section(y));
+        assert_eq!(rebuilt, expected);
+    }
 }
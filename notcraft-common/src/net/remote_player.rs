@@ -0,0 +1,273 @@
+//! other players in the world, as far as a client can tell from what the server
+//! sends it.
+//!
+//! transform updates only arrive every so often, and never at exactly the same
+//! rate, so remote players are drawn slightly in the past: each one keeps the
+//! last few transforms it was sent, and is placed somewhere between the two
+//! that surround [`INTERPOLATION_DELAY`] ago. as long as updates keep coming in
+//! more often than that, remote players move smoothly, even if some of the
+//! updates come in late.
+
+use super::{interest::ClientId, packet::ServerToClientPlayPacket};
+use crate::{
+    prelude::*,
+    transform::{EulerAngles, Transform},
+    util,
+};
+use nalgebra::Point3;
+use std::{
+    collections::{HashMap, VecDeque},
+    f32::consts::PI,
+    time::Duration,
+};
+
+/// how far in the past remote players are shown.
+pub const INTERPOLATION_DELAY: Duration = Duration::from_millis(100);
+/// snapshots past this many are dropped, oldest first. the server would have to
+/// be sending transforms way faster than anyone could see for this to matter.
+const MAX_SNAPSHOTS: usize = 32;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TransformSnapshot {
+    /// when the snapshot was received, in seconds since startup.
+    pub time: f64,
+    pub position: Point3<f32>,
+    pub pitch: f32,
+    pub yaw: f32,
+}
+
+/// turns from `a` to `b` the short way around.
+fn lerp_angle(a: f32, b: f32, t: f32) -> f32 {
+    let delta = util::modulo(b - a + PI, 2.0 * PI) - PI;
+    a + delta * t
+}
+
+impl TransformSnapshot {
+    fn lerp(&self, other: &Self, time: f64) -> Self {
+        let span = other.time - self.time;
+        let t = match span > 0.0 {
+            true => ((time - self.time) / span).clamp(0.0, 1.0) as f32,
+            false => 1.0,
+        };
+        Self {
+            time,
+            position: util::lerp_point(self.position, other.position, t),
+            pitch: util::lerp(self.pitch, other.pitch, t),
+            yaw: lerp_angle(self.yaw, other.yaw, t),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InterpolatedTransform {
+    /// oldest first.
+    snapshots: VecDeque<TransformSnapshot>,
+}
+
+impl InterpolatedTransform {
+    pub fn push(&mut self, snapshot: TransformSnapshot) {
+        // packets can't arrive out of order, but snapshots from the same frame
+        // can, and the latest one is what counts.
+        if self
+            .snapshots
+            .back()
+            .is_some_and(|last| last.time > snapshot.time)
+        {
+            return;
+        }
+        self.snapshots.push_back(snapshot);
+        if self.snapshots.len() > MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// where the player was at `time`. times before the oldest snapshot or
+    /// after the newest one are clamped to them, since guessing where a player
+    /// is going usually looks worse than having them stop for a moment.
+    pub fn sample(&mut self, time: f64) -> Option<TransformSnapshot> {
+        // anything before the pair of snapshots surrounding `time` won't be
+        // needed again.
+        while self.snapshots.get(1).is_some_and(|next| next.time <= time) {
+            self.snapshots.pop_front();
+        }
+
+        let first = self.snapshots.front()?;
+        Some(match self.snapshots.get(1) {
+            Some(next) if time > first.time => first.lerp(next, time),
+            _ => TransformSnapshot { time, ..*first },
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemotePlayer {
+    pub client: ClientId,
+    pub name: String,
+    /// players' bodies only turn side to side, but their heads look up and
+    /// down too.
+    pub pitch: f32,
+}
+
+/// the entity for every remote player this client knows about.
+#[derive(Clone, Debug, Default)]
+pub struct RemotePlayers {
+    entities: HashMap<ClientId, Entity>,
+}
+
+impl RemotePlayers {
+    pub fn get(&self, client: ClientId) -> Option<Entity> {
+        self.entities.get(&client).copied()
+    }
+}
+
+fn handle_player_packets(
+    mut cmd: Commands,
+    time: Res<Time>,
+    mut players: ResMut<RemotePlayers>,
+    mut packets: EventReader<ServerToClientPlayPacket>,
+    mut query: Query<&mut InterpolatedTransform>,
+) {
+    let now = time.seconds_since_startup();
+    for packet in packets.iter() {
+        match packet {
+            ServerToClientPlayPacket::PlayerJoined {
+                client,
+                name,
+                position,
+                pitch,
+                yaw,
+            } => {
+                let snapshot = TransformSnapshot {
+                    time: now,
+                    position: *position,
+                    pitch: *pitch,
+                    yaw: *yaw,
+                };
+                let mut transform = InterpolatedTransform::default();
+                transform.push(snapshot);
+
+                if let Some(old) = players.entities.remove(client) {
+                    log::warn!("{:?} joined twice, replacing their old entity", client);
+                    cmd.entity(old).despawn();
+                }
+
+                let entity = cmd
+                    .spawn()
+                    .insert(Transform::to(*position))
+                    .insert(transform)
+                    .insert(RemotePlayer {
+                        client: *client,
+                        name: name.clone(),
+                        pitch: *pitch,
+                    })
+                    .id();
+                players.entities.insert(*client, entity);
+            }
+
+            ServerToClientPlayPacket::PlayerTransform {
+                client,
+                position,
+                pitch,
+                yaw,
+            } => {
+                let entity = players.get(*client);
+                match entity.and_then(|entity| query.get_mut(entity).ok()) {
+                    Some(mut transform) => transform.push(TransformSnapshot {
+                        time: now,
+                        position: *position,
+                        pitch: *pitch,
+                        yaw: *yaw,
+                    }),
+                    // the entity might have just been spawned this frame
+                    None if entity.is_some() => {}
+                    None => log::warn!("got a transform for unknown player {:?}", client),
+                }
+            }
+
+            ServerToClientPlayPacket::PlayerLeft { client } => {
+                if let Some(entity) = players.entities.remove(client) {
+                    cmd.entity(entity).despawn();
+                }
+            }
+
+            _ => {}
+        }
+    }
+}
+
+fn interpolate_remote_players(
+    time: Res<Time>,
+    query: Query<(
+        &mut Transform,
+        &mut RemotePlayer,
+        &mut InterpolatedTransform,
+    )>,
+) {
+    let time = time.seconds_since_startup() - INTERPOLATION_DELAY.as_secs_f64();
+    query.for_each_mut(|(mut transform, mut player, mut interpolated)| {
+        if let Some(snapshot) = interpolated.sample(time) {
+            transform.translation.vector = snapshot.position.coords;
+            transform.rotation = EulerAngles::new(0.0, snapshot.yaw, 0.0);
+            player.pitch = snapshot.pitch;
+        }
+    });
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub struct RemotePlayerInterpolation;
+
+#[derive(Debug, Default)]
+pub struct RemotePlayerPlugin {}
+
+impl Plugin for RemotePlayerPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<ServerToClientPlayPacket>();
+        app.init_resource::<RemotePlayers>();
+        app.add_system(
+            handle_player_packets
+                .system()
+                .before(RemotePlayerInterpolation),
+        );
+        app.add_system(
+            interpolate_remote_players
+                .system()
+                .label(RemotePlayerInterpolation),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(time: f64, x: f32, yaw: f32) -> TransformSnapshot {
+        TransformSnapshot {
+            time,
+            position: point![x, 0.0, 0.0],
+            pitch: 0.0,
+            yaw,
+        }
+    }
+
+    #[test]
+    fn remote_players_move_between_snapshots() {
+        let mut transform = InterpolatedTransform::default();
+        assert_eq!(transform.sample(1.0), None);
+
+        transform.push(snapshot(1.0, 0.0, 3.0));
+        transform.push(snapshot(1.5, 10.0, -3.0));
+        transform.push(snapshot(2.0, 20.0, -3.0));
+
+        // before the first snapshot, the player just waits there
+        assert_eq!(transform.sample(0.5).unwrap().position.x, 0.0);
+
+        let halfway = transform.sample(1.25).unwrap();
+        assert!((halfway.position.x - 5.0).abs() < 1e-4);
+        // turning from 3 to -3 radians goes the short way, across PI
+        assert!(halfway.yaw > 3.0 && halfway.yaw < PI + 0.2);
+
+        // past the newest snapshot, players stop instead of overshooting
+        assert_eq!(transform.sample(3.0).unwrap().position.x, 20.0);
+        assert_eq!(transform.snapshots.len(), 1);
+    }
+}
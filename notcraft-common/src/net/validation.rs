@@ -0,0 +1,382 @@
+//! sanity checks for what clients send the server.
+//!
+//! clients can't be trusted to follow the rules, so every packet that changes
+//! the world goes through a [`PacketValidator`] before the server acts on it.
+//! block edits have to be within reach of where the server thinks the player
+//! is, edits and chat are rate limited, and players can't move faster than
+//! anyone could legitimately go. packets that break the rules are dropped, or
+//! in the case of movement, clamped to what the player could have actually
+//! done.
+//!
+//! every broken rule counts against the client, and a client that keeps
+//! breaking them gets kicked. violations are forgiven over time, so that
+//! the occasional lag spike doesn't get anyone kicked.
+
+use super::{interest::ClientId, packet::ClientToServerPlayPacket};
+use crate::world::BlockPos;
+use nalgebra::{point, Point3};
+use std::{collections::HashMap, fmt, time::Duration};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationSettings {
+    /// how far, in blocks, a player can break or place blocks from.
+    pub reach: f32,
+    /// how fast, in blocks per second, a player can move. this has to be fast
+    /// enough for sprinting while flying.
+    pub max_speed: f32,
+    /// how much further than `max_speed` would allow a player can move in a
+    /// single update without it counting as a violation, since updates don't
+    /// arrive at exactly the rate they were sent.
+    pub movement_slack: f32,
+    pub block_edits: RateLimit,
+    pub chat: RateLimit,
+    /// clients are kicked once they've racked up more than this many
+    /// violations...
+    pub max_violations: u32,
+    /// ...but one of them is forgiven every this often.
+    pub violation_decay: Duration,
+}
+
+impl Default for ValidationSettings {
+    fn default() -> Self {
+        Self {
+            reach: 8.0,
+            max_speed: 128.0,
+            movement_slack: 2.0,
+            block_edits: RateLimit {
+                per_second: 20.0,
+                burst: 40.0,
+            },
+            chat: RateLimit {
+                per_second: 1.0,
+                burst: 5.0,
+            },
+            max_violations: 10,
+            violation_decay: Duration::from_secs(5),
+        }
+    }
+}
+
+/// a token bucket: each action takes a token, and tokens refill at
+/// `per_second`, up to `burst` of them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    pub per_second: f32,
+    pub burst: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Bucket {
+    tokens: f32,
+    refilled_at: Duration,
+}
+
+impl Bucket {
+    fn full(limit: &RateLimit, now: Duration) -> Self {
+        Self {
+            tokens: limit.burst,
+            refilled_at: now,
+        }
+    }
+
+    fn take(&mut self, limit: &RateLimit, now: Duration) -> bool {
+        let elapsed = now.saturating_sub(self.refilled_at).as_secs_f32();
+        self.tokens = f32::min(limit.burst, self.tokens + elapsed * limit.per_second);
+        self.refilled_at = now;
+
+        let allowed = self.tokens >= 1.0;
+        if allowed {
+            self.tokens -= 1.0;
+        }
+        allowed
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Violation {
+    OutOfReach { pos: BlockPos, distance: f32 },
+    TooFast { speed: f32 },
+    TooManyEdits,
+    TooManyMessages,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfReach { pos, distance } => {
+                write!(f, "edited {:?}, which is {:.1} blocks away", pos, distance)
+            }
+            Self::TooFast { speed } => write!(f, "moved at {:.1} blocks per second", speed),
+            Self::TooManyEdits => write!(f, "edited blocks too quickly"),
+            Self::TooManyMessages => write!(f, "sent messages too quickly"),
+        }
+    }
+}
+
+/// what the server should do with a packet.
+#[derive(Clone, Debug)]
+pub enum Verdict {
+    Accept(ClientToServerPlayPacket),
+    /// the packet broke the rules, but can be fixed up into one that doesn't.
+    /// the client should be told, since it disagrees with the server now.
+    Corrected {
+        packet: ClientToServerPlayPacket,
+        violation: Violation,
+    },
+    /// the packet should be dropped.
+    Reject(Violation),
+    /// the client has broken the rules too many times, and should be
+    /// disconnected. it's been forgotten about already.
+    Kick(Violation),
+}
+
+#[derive(Clone, Debug)]
+struct ClientState {
+    /// where the server thinks the player is, which only changes when the
+    /// player sends a transform that passes validation.
+    position: Point3<f32>,
+    moved_at: Duration,
+    block_edits: Bucket,
+    chat: Bucket,
+    violations: u32,
+    last_forgiven: Duration,
+}
+
+fn block_center(pos: BlockPos) -> Point3<f32> {
+    point![pos.x as f32, pos.y as f32, pos.z as f32] + nalgebra::vector![0.5, 0.5, 0.5]
+}
+
+#[derive(Debug, Default)]
+pub struct PacketValidator {
+    settings: ValidationSettings,
+    clients: HashMap<ClientId, ClientState>,
+}
+
+impl PacketValidator {
+    pub fn new(settings: ValidationSettings) -> Self {
+        Self {
+            settings,
+            clients: Default::default(),
+        }
+    }
+
+    /// starts validating a client that just logged in at `position`.
+    pub fn add_client(&mut self, client: ClientId, position: Point3<f32>, now: Duration) {
+        let state = ClientState {
+            position,
+            moved_at: now,
+            block_edits: Bucket::full(&self.settings.block_edits, now),
+            chat: Bucket::full(&self.settings.chat, now),
+            violations: 0,
+            last_forgiven: now,
+        };
+        self.clients.insert(client, state);
+    }
+
+    pub fn remove_client(&mut self, client: ClientId) {
+        self.clients.remove(&client);
+    }
+
+    /// where the server thinks `client` is.
+    pub fn position(&self, client: ClientId) -> Option<Point3<f32>> {
+        self.clients.get(&client).map(|state| state.position)
+    }
+
+    /// checks a packet that `client` sent at `now`. returns `None` for clients
+    /// that aren't being validated, like ones that haven't logged in yet or
+    /// were just kicked, whose packets shouldn't be handled at all.
+    pub fn validate(
+        &mut self,
+        client: ClientId,
+        packet: ClientToServerPlayPacket,
+        now: Duration,
+    ) -> Option<Verdict> {
+        let settings = &self.settings;
+        let state = self.clients.get_mut(&client)?;
+
+        let violation = match packet {
+            ClientToServerPlayPacket::UpdateTransform {
+                position,
+                pitch,
+                yaw,
+            } => {
+                let elapsed = now.saturating_sub(state.moved_at).as_secs_f32();
+                let allowed = settings.max_speed * elapsed + settings.movement_slack;
+                let delta = position - state.position;
+                let distance = delta.norm();
+                state.moved_at = now;
+
+                if distance <= allowed {
+                    state.position = position;
+                    return Some(Verdict::Accept(packet));
+                }
+
+                // move the player as far as they could have gone towards where
+                // they said they were
+                state.position += delta * (allowed / distance);
+                let speed = distance / f32::max(elapsed, f32::EPSILON);
+                let corrected = ClientToServerPlayPacket::UpdateTransform {
+                    position: state.position,
+                    pitch,
+                    yaw,
+                };
+                let violation = Violation::TooFast { speed };
+                return Some(self.record(client, violation, Some(corrected), now));
+            }
+
+            ClientToServerPlayPacket::BreakBlock { position }
+            | ClientToServerPlayPacket::PlaceBlock { position, .. } => {
+                let distance = (block_center(position) - state.position).norm();
+                if distance > settings.reach {
+                    Violation::OutOfReach {
+                        pos: position,
+                        distance,
+                    }
+                } else if !state.block_edits.take(&settings.block_edits, now) {
+                    Violation::TooManyEdits
+                } else {
+                    return Some(Verdict::Accept(packet));
+                }
+            }
+
+            ClientToServerPlayPacket::Chat { .. } => {
+                if !state.chat.take(&settings.chat, now) {
+                    Violation::TooManyMessages
+                } else {
+                    return Some(Verdict::Accept(packet));
+                }
+            }
+        };
+
+        Some(self.record(client, violation, None, now))
+    }
+
+    fn record(
+        &mut self,
+        client: ClientId,
+        violation: Violation,
+        corrected: Option<ClientToServerPlayPacket>,
+        now: Duration,
+    ) -> Verdict {
+        let settings = &self.settings;
+        let state = self.clients.get_mut(&client).unwrap();
+
+        if !settings.violation_decay.is_zero() {
+            while state.violations > 0 && now >= state.last_forgiven + settings.violation_decay {
+                state.violations -= 1;
+                state.last_forgiven += settings.violation_decay;
+            }
+        }
+        if state.violations == 0 {
+            state.last_forgiven = now;
+        }
+        state.violations += 1;
+
+        if state.violations > settings.max_violations {
+            log::warn!("kicking {:?}: {}", client, violation);
+            self.clients.remove(&client);
+            return Verdict::Kick(violation);
+        }
+
+        log::debug!(
+            "{:?} {} ({} violations)",
+            client,
+            violation,
+            state.violations
+        );
+        match corrected {
+            Some(packet) => Verdict::Corrected { packet, violation },
+            None => Verdict::Reject(violation),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: f32) -> Duration {
+        Duration::from_secs_f32(secs)
+    }
+
+    #[test]
+    fn cheaters_get_corrected_and_kicked() {
+        let client = ClientId(1);
+        let mut validator = PacketValidator::new(ValidationSettings {
+            max_speed: 10.0,
+            movement_slack: 0.0,
+            max_violations: 3,
+            ..Default::default()
+        });
+        validator.add_client(client, point![0.5, 0.5, 0.5], secs(0.0));
+        let mut validate = |packet, now| validator.validate(client, packet, secs(now)).unwrap();
+
+        let move_to = |x| ClientToServerPlayPacket::UpdateTransform {
+            position: point![x, 0.5, 0.5],
+            pitch: 0.0,
+            yaw: 0.0,
+        };
+        assert!(matches!(validate(move_to(5.0), 1.0), Verdict::Accept(..)));
+        // only one block could have been covered in a tenth of a second
+        match validate(move_to(15.0), 1.1) {
+            Verdict::Corrected {
+                packet: ClientToServerPlayPacket::UpdateTransform { position, .. },
+                ..
+            } => assert!((position.x - 6.0).abs() < 1e-4),
+            other => panic!("expected a correction, got {:?}", other),
+        }
+
+        // reach is measured from where the server thinks the player is, which
+        // is at x = 6 now
+        let block = |x| ClientToServerPlayPacket::BreakBlock {
+            position: BlockPos { x, y: 0, z: 0 },
+        };
+        assert!(matches!(validate(block(10), 1.2), Verdict::Accept(..)));
+        assert!(matches!(
+            validate(block(20), 1.3),
+            Verdict::Reject(Violation::OutOfReach { .. })
+        ));
+
+        // a long time later, both violations have been forgiven
+        assert!(matches!(
+            validate(block(20), 60.0),
+            Verdict::Reject(Violation::OutOfReach { .. })
+        ));
+        assert!(matches!(validate(block(20), 60.0), Verdict::Reject(..)));
+        assert!(matches!(validate(block(20), 60.0), Verdict::Reject(..)));
+        assert!(matches!(validate(block(20), 60.0), Verdict::Kick(..)));
+        assert!(validator.validate(client, block(6), secs(61.0)).is_none());
+    }
+
+    #[test]
+    fn edits_and_chat_are_rate_limited() {
+        let client = ClientId(1);
+        let mut validator = PacketValidator::default();
+        validator.add_client(client, point![0.5, 0.5, 0.5], secs(0.0));
+        let mut validate = |packet, now| validator.validate(client, packet, secs(now)).unwrap();
+
+        let chat = || ClientToServerPlayPacket::Chat {
+            message: "/help".into(),
+        };
+        for _ in 0..5 {
+            assert!(matches!(validate(chat(), 1.0), Verdict::Accept(..)));
+        }
+        assert!(matches!(
+            validate(chat(), 1.0),
+            Verdict::Reject(Violation::TooManyMessages)
+        ));
+        assert!(matches!(validate(chat(), 2.0), Verdict::Accept(..)));
+
+        let edit = || ClientToServerPlayPacket::PlaceBlock {
+            id: crate::world::registry::AIR_BLOCK,
+            position: BlockPos { x: 1, y: 1, z: 1 },
+        };
+        for _ in 0..40 {
+            assert!(matches!(validate(edit(), 3.0), Verdict::Accept(..)));
+        }
+        assert!(matches!(
+            validate(edit(), 3.0),
+            Verdict::Reject(Violation::TooManyEdits)
+        ));
+    }
+}
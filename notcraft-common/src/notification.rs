@@ -0,0 +1,71 @@
+//! short messages for the player, like "switched block to stone" or "world
+//! saved".
+//!
+//! anything can send a [`Notify`] event, and the client shows each one as a
+//! toast in the corner of the screen for a little while. messages should
+//! already be translated, see [`crate::lang`].
+
+use crate::prelude::*;
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// how long notifications stay up when they don't ask for a specific
+    /// duration. the more important a notification, the longer it stays up.
+    pub fn default_duration(self) -> Duration {
+        match self {
+            Severity::Info => Duration::from_secs(3),
+            Severity::Warning => Duration::from_secs(5),
+            Severity::Error => Duration::from_secs(8),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Notify {
+    pub message: String,
+    pub severity: Severity,
+    pub duration: Duration,
+}
+
+impl Notify {
+    pub fn new<S: Into<String>>(severity: Severity, message: S) -> Self {
+        Self {
+            message: message.into(),
+            severity,
+            duration: severity.default_duration(),
+        }
+    }
+
+    pub fn info<S: Into<String>>(message: S) -> Self {
+        Self::new(Severity::Info, message)
+    }
+
+    pub fn warning<S: Into<String>>(message: S) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn error<S: Into<String>>(message: S) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct NotificationPlugin {}
+
+impl Plugin for NotificationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<Notify>();
+    }
+}
@@ -0,0 +1,604 @@
+//! grid-based A* pathfinding over voxel terrain.
+//!
+//! paths are made up of the block positions that an entity's feet pass
+//! through. a position is walkable if the block below it is solid and the two
+//! blocks starting at it are empty. searches run on the rayon thread pool
+//! against their own [`ChunkAccess`], so requesting a path never blocks the
+//! frame; results are picked up by [`receive_paths`] some frames later.
+
+use crossbeam_channel::{Receiver, Sender};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    prelude::*,
+    task::{PoolKind, TaskPools},
+    world::{
+        chunk::ChunkAccess,
+        registry::{BlockRegistry, CollisionType},
+        BlockPos, BlockUpdateEvent, VoxelWorld,
+    },
+};
+
+const HORIZONTAL_DIRECTIONS: [[i32; 3]; 4] = [[1, 0, 0], [-1, 0, 0], [0, 0, 1], [0, 0, -1]];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathfindingSettings {
+    /// the maximum number of nodes a single search may expand before giving up
+    /// and settling for the closest position it found.
+    pub max_nodes: usize,
+    /// the furthest an entity is willing to drop down in a single step.
+    pub max_drop: i32,
+    /// cost of moving to a horizontally adjacent block on the same level.
+    pub step_cost: u32,
+    /// cost of jumping up onto a horizontally adjacent block.
+    pub jump_cost: u32,
+    /// additional cost for each block dropped down.
+    pub drop_cost: u32,
+}
+
+impl Default for PathfindingSettings {
+    fn default() -> Self {
+        // note that the search heuristic is the manhattan distance scaled by
+        // `step_cost`, so none of the vertical costs should be less than that, or the
+        // heuristic will overestimate and we'll find worse paths.
+        Self {
+            max_nodes: 4096,
+            max_drop: 3,
+            step_cost: 10,
+            jump_cost: 20,
+            drop_cost: 10,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Path {
+    /// the positions of the entity's feet along the path, starting with the
+    /// position just after the start.
+    pub nodes: Vec<BlockPos>,
+    /// every block whose change could make this path untraversable.
+    footprint: HashSet<BlockPos>,
+}
+
+impl Path {
+    fn new(nodes: Vec<BlockPos>) -> Self {
+        let mut footprint = HashSet::with_capacity(3 * nodes.len());
+        for &node in nodes.iter() {
+            footprint.insert(node.offset([0, -1, 0]));
+            footprint.insert(node);
+            footprint.insert(node.offset([0, 1, 0]));
+        }
+        Self { nodes, footprint }
+    }
+
+    /// returns true if a change to the block at `pos` could invalidate this
+    /// path.
+    pub fn passes_through(&self, pos: BlockPos) -> bool {
+        self.footprint.contains(&pos)
+    }
+
+    /// the last position in the path, which may not be the requested goal if
+    /// the goal was unreachable.
+    pub fn destination(&self) -> Option<BlockPos> {
+        self.nodes.last().copied()
+    }
+}
+
+fn is_passable(registry: &BlockRegistry, access: &mut ChunkAccess, pos: BlockPos) -> Option<bool> {
+    let block = registry.get(access.block(pos)?);
+    Some(block.collision_type() == CollisionType::None && !block.liquid())
+}
+
+fn is_walkable(registry: &BlockRegistry, access: &mut ChunkAccess, pos: BlockPos) -> bool {
+    let below = match access.block(pos.offset([0, -1, 0])) {
+        Some(id) => registry.get(id),
+        None => return false,
+    };
+
+    below.collision_type().is_solid()
+        && is_passable(registry, access, pos) == Some(true)
+        && is_passable(registry, access, pos.offset([0, 1, 0])) == Some(true)
+}
+
+fn heuristic(settings: &PathfindingSettings, a: BlockPos, b: BlockPos) -> u32 {
+    let dist = (a.x - b.x).abs() + (a.y - b.y).abs() + (a.z - b.z).abs();
+    settings.step_cost * dist as u32
+}
+
+struct SearchContext<'a> {
+    settings: &'a PathfindingSettings,
+    registry: Arc<BlockRegistry>,
+    access: ChunkAccess,
+}
+
+impl<'a> SearchContext<'a> {
+    fn passable(&mut self, pos: BlockPos) -> bool {
+        is_passable(&self.registry, &mut self.access, pos) == Some(true)
+    }
+
+    fn walkable(&mut self, pos: BlockPos) -> bool {
+        is_walkable(&self.registry, &mut self.access, pos)
+    }
+
+    fn neighbors(&mut self, pos: BlockPos, out: &mut Vec<(BlockPos, u32)>) {
+        out.clear();
+
+        for dir in HORIZONTAL_DIRECTIONS {
+            let side = pos.offset(dir);
+
+            // walk straight across
+            if self.walkable(side) {
+                out.push((side, self.settings.step_cost));
+                continue;
+            }
+
+            // jump up a single block, which needs some headroom above us
+            let up = side.offset([0, 1, 0]);
+            if self.walkable(up) && self.passable(pos.offset([0, 2, 0])) {
+                out.push((up, self.settings.jump_cost));
+                continue;
+            }
+
+            // step off of a ledge
+            if !self.passable(side) || !self.passable(side.offset([0, 1, 0])) {
+                continue;
+            }
+            for drop in 1..=self.settings.max_drop {
+                let down = side.offset([0, -drop, 0]);
+                if self.walkable(down) {
+                    let cost = self.settings.step_cost + drop as u32 * self.settings.drop_cost;
+                    out.push((down, cost));
+                    break;
+                }
+                if !self.passable(down) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct OpenNode {
+    estimated_cost: u32,
+    pos: BlockPos,
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed, so that the binary heap pops the cheapest node first.
+        other
+            .estimated_cost
+            .cmp(&self.estimated_cost)
+            .then_with(|| self.pos.cmp(&other.pos))
+    }
+}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// finds a path from `start` to `goal`. if the goal can't be reached, the path
+/// leads to the reachable position closest to it instead. returns [`None`] if
+/// no progress towards the goal can be made at all.
+pub fn find_path(
+    world: &Arc<VoxelWorld>,
+    settings: &PathfindingSettings,
+    start: BlockPos,
+    goal: BlockPos,
+) -> Option<Path> {
+    let mut ctx = SearchContext {
+        settings,
+        registry: Arc::clone(&world.registry),
+        access: ChunkAccess::new(world),
+    };
+
+    let mut open = BinaryHeap::new();
+    let mut came_from = HashMap::new();
+    let mut costs = HashMap::new();
+    let mut neighbors = Vec::new();
+
+    let mut closest = (heuristic(settings, start, goal), start);
+    open.push(OpenNode {
+        estimated_cost: closest.0,
+        pos: start,
+    });
+    costs.insert(start, 0);
+
+    let mut expanded = 0;
+    while let Some(OpenNode { pos, .. }) = open.pop() {
+        let remaining = heuristic(settings, pos, goal);
+        if remaining < closest.0 {
+            closest = (remaining, pos);
+        }
+        if pos == goal || expanded >= settings.max_nodes {
+            break;
+        }
+        expanded += 1;
+
+        let cost = costs[&pos];
+        ctx.neighbors(pos, &mut neighbors);
+        for &(neighbor, step_cost) in neighbors.iter() {
+            let new_cost = cost + step_cost;
+            if matches!(costs.get(&neighbor), Some(&old) if old <= new_cost) {
+                continue;
+            }
+
+            costs.insert(neighbor, new_cost);
+            came_from.insert(neighbor, pos);
+            open.push(OpenNode {
+                estimated_cost: new_cost + heuristic(settings, neighbor, goal),
+                pos: neighbor,
+            });
+        }
+    }
+
+    let (_, end) = closest;
+    if end == start {
+        return None;
+    }
+
+    let mut nodes = vec![end];
+    let mut current = end;
+    while let Some(&prev) = came_from.get(&current) {
+        if prev == start {
+            break;
+        }
+        nodes.push(prev);
+        current = prev;
+    }
+    nodes.reverse();
+
+    Some(Path::new(nodes))
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PathRequestId(u64);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletedPath {
+    pub id: PathRequestId,
+    pub path: Option<Path>,
+}
+
+/// hands out path searches to the thread pool and collects their results.
+#[derive(Debug)]
+pub struct Pathfinder {
+    next_id: u64,
+    completed_tx: Sender<CompletedPath>,
+    completed_rx: Receiver<CompletedPath>,
+}
+
+impl Default for Pathfinder {
+    fn default() -> Self {
+        let (completed_tx, completed_rx) = crossbeam_channel::unbounded();
+        Self {
+            next_id: 0,
+            completed_tx,
+            completed_rx,
+        }
+    }
+}
+
+impl Pathfinder {
+    pub fn request(
+        &mut self,
+        pools: &TaskPools,
+        world: &Arc<VoxelWorld>,
+        settings: &PathfindingSettings,
+        start: BlockPos,
+        goal: BlockPos,
+    ) -> PathRequestId {
+        let id = PathRequestId(self.next_id);
+        self.next_id += 1;
+
+        let world = Arc::clone(world);
+        let settings = settings.clone();
+        let sender = self.completed_tx.clone();
+        pools.spawn(PoolKind::General, move || {
+            let path = find_path(&world, &settings, start, goal);
+            // the receiver only goes away when the whole app does.
+            let _ = sender.send(CompletedPath { id, path });
+        });
+
+        id
+    }
+
+    pub fn completed(&self) -> impl Iterator<Item = CompletedPath> + '_ {
+        self.completed_rx.try_iter()
+    }
+}
+
+/// entities with this component are given a path towards `goal`, which is
+/// recomputed whenever the terrain along it changes.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct PathFollower {
+    goal: Option<BlockPos>,
+    start: Option<BlockPos>,
+    path: Option<Path>,
+    pending: Option<PathRequestId>,
+    failed: bool,
+}
+
+impl PathFollower {
+    /// requests a new path from `start` to `goal`, throwing away any previous
+    /// path.
+    pub fn set_goal(&mut self, start: BlockPos, goal: BlockPos) {
+        self.goal = Some(goal);
+        self.start = Some(start);
+        self.path = None;
+        self.pending = None;
+        self.failed = false;
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn goal(&self) -> Option<BlockPos> {
+        self.goal
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref()
+    }
+
+    /// true while waiting on a search to finish.
+    pub fn is_searching(&self) -> bool {
+        self.goal.is_some() && self.path.is_none() && !self.failed
+    }
+
+    /// true if the last search couldn't make any progress towards the goal.
+    pub fn has_failed(&self) -> bool {
+        self.failed
+    }
+
+    /// true if the follower has either reached the end of its path, or has no
+    /// way of getting any closer to its goal.
+    pub fn is_done(&self) -> bool {
+        self.failed || matches!(&self.path, Some(path) if path.nodes.is_empty())
+    }
+
+    /// removes the first node of the path, once the follower has reached it.
+    pub fn advance(&mut self) {
+        if let Some(path) = &mut self.path {
+            if !path.nodes.is_empty() {
+                path.nodes.remove(0);
+            }
+        }
+    }
+}
+
+pub fn dispatch_path_requests(
+    mut pathfinder: ResMut<Pathfinder>,
+    settings: Res<PathfindingSettings>,
+    pools: Res<TaskPools>,
+    world: Res<Arc<VoxelWorld>>,
+    query: Query<&mut PathFollower>,
+) {
+    query.for_each_mut(|mut follower| {
+        if follower.pending.is_some() || !follower.is_searching() {
+            return;
+        }
+        if let (Some(start), Some(goal)) = (follower.start, follower.goal) {
+            let id = pathfinder.request(&pools, &world, &settings, start, goal);
+            follower.pending = Some(id);
+        }
+    });
+}
+
+pub fn receive_paths(pathfinder: Res<Pathfinder>, query: Query<&mut PathFollower>) {
+    let completed = pathfinder.completed().collect::<Vec<_>>();
+    if completed.is_empty() {
+        return;
+    }
+
+    let completed = completed
+        .into_iter()
+        .map(|completed| (completed.id, completed.path))
+        .collect::<HashMap<_, _>>();
+
+    query.for_each_mut(|mut follower| {
+        let id = match follower.pending {
+            Some(id) if completed.contains_key(&id) => id,
+            // either nothing was requested, or the request was superseded by a newer one.
+            _ => return,
+        };
+
+        follower.pending = None;
+        match &completed[&id] {
+            Some(path) => follower.path = Some(path.clone()),
+            None => follower.failed = true,
+        }
+    });
+}
+
+/// throws away paths that go through blocks that were changed, so that they
+/// get recomputed from wherever the follower currently is.
+pub fn invalidate_paths(
+    mut block_updates: EventReader<BlockUpdateEvent>,
+    query: Query<&mut PathFollower>,
+) {
+    let updated = block_updates
+        .iter()
+        .map(|update| update.pos)
+        .collect::<Vec<_>>();
+    if updated.is_empty() {
+        return;
+    }
+
+    query.for_each_mut(|mut follower| {
+        let invalidated = match &follower.path {
+            Some(path) => updated.iter().any(|&pos| path.passes_through(pos)),
+            None => false,
+        };
+
+        if invalidated {
+            let restart = follower.path.as_ref().and_then(|path| path.nodes.first());
+            let restart = restart.copied();
+            follower.path = None;
+            follower.pending = None;
+            follower.failed = false;
+            if let Some(restart) = restart {
+                follower.start = Some(restart);
+            }
+        }
+    });
+}
+
+#[derive(Debug, Default)]
+pub struct PathfindingPlugin {}
+
+impl Plugin for PathfindingPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Pathfinder>();
+        app.init_resource::<PathfindingSettings>();
+        app.add_system(invalidate_paths.system().before(PathfindingLabel));
+        app.add_system(receive_paths.system().before(PathfindingLabel));
+        app.add_system(dispatch_path_requests.system().label(PathfindingLabel));
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub struct PathfindingLabel;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        chunk::ArrayChunk,
+        registry::AIR_BLOCK,
+        test_util::{section_world, test_registry},
+    };
+
+    // the top of the floor, which runs across the whole section.
+    const FLOOR: i32 = 4;
+
+    /// a flat stone floor, with stone pillars `(x, z, height)` standing on it.
+    fn make_world(pillars: &[(i32, i32, i32)]) -> Arc<VoxelWorld> {
+        let registry = test_registry();
+        let stone = registry.lookup("stone");
+        let mut blocks = ArrayChunk::homogeneous(AIR_BLOCK);
+        for x in 0..32 {
+            for z in 0..32 {
+                blocks[[x, FLOOR as usize, z]] = stone;
+            }
+        }
+        for &(x, z, height) in pillars {
+            for y in FLOOR + 1..=FLOOR + height {
+                blocks[[x, y, z].map(|n| n as usize)] = stone;
+            }
+        }
+        section_world(&registry, blocks)
+    }
+
+    /// where an entity standing on the floor at `x`, `z` has its feet.
+    fn on_floor(x: i32, z: i32) -> BlockPos {
+        BlockPos { x, y: FLOOR + 1, z }
+    }
+
+    fn wall(x: i32, zs: std::ops::RangeInclusive<i32>, height: i32) -> Vec<(i32, i32, i32)> {
+        zs.map(|z| (x, z, height)).collect()
+    }
+
+    #[test]
+    fn walks_straight_to_the_goal() {
+        let world = make_world(&[]);
+        let settings = PathfindingSettings::default();
+        let path = find_path(&world, &settings, on_floor(2, 2), on_floor(8, 2)).unwrap();
+        assert_eq!(
+            path.nodes,
+            (3..=8).map(|x| on_floor(x, 2)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn walks_around_walls() {
+        let world = make_world(&wall(5, 0..=10, 2));
+        let settings = PathfindingSettings::default();
+        let path = find_path(&world, &settings, on_floor(2, 2), on_floor(8, 2)).unwrap();
+
+        assert_eq!(path.destination(), Some(on_floor(8, 2)));
+        assert!(path.nodes.iter().all(|node| node.y == FLOOR + 1));
+        assert!(path.nodes.iter().all(|node| node.x != 5 || node.z > 10));
+    }
+
+    #[test]
+    fn climbs_single_blocks_but_not_walls() {
+        let settings = PathfindingSettings::default();
+        let step = BlockPos {
+            x: 8,
+            y: FLOOR + 2,
+            z: 2,
+        };
+        let path = find_path(&make_world(&[(8, 2, 1)]), &settings, on_floor(2, 2), step);
+        assert_eq!(path.unwrap().destination(), Some(step));
+
+        // the goal can't be reached, so the path gets as close as it can
+        let ledge = BlockPos {
+            x: 8,
+            y: FLOOR + 3,
+            z: 2,
+        };
+        let path = find_path(&make_world(&[(8, 2, 2)]), &settings, on_floor(2, 2), ledge);
+        let end = path.unwrap().destination().unwrap();
+        assert_eq!(end.y, FLOOR + 1);
+        assert_eq!(heuristic(&settings, end, ledge), 3 * settings.step_cost);
+    }
+
+    #[test]
+    fn drops_no_further_than_allowed() {
+        let settings = PathfindingSettings {
+            max_drop: 3,
+            ..Default::default()
+        };
+        for (height, reachable) in [(3, true), (4, false)] {
+            let world = make_world(&[(8, 8, height)]);
+            let top = BlockPos {
+                x: 8,
+                y: FLOOR + height + 1,
+                z: 8,
+            };
+            let path = find_path(&world, &settings, top, on_floor(12, 8));
+            assert_eq!(path.is_some(), reachable, "drop of {}", height);
+        }
+    }
+
+    #[test]
+    fn no_path_when_boxed_in() {
+        let world = make_world(&[(5, 6, 2), (7, 6, 2), (6, 5, 2), (6, 7, 2)]);
+        let settings = PathfindingSettings::default();
+        assert_eq!(
+            find_path(&world, &settings, on_floor(6, 6), on_floor(20, 6)),
+            None
+        );
+    }
+
+    #[test]
+    fn gives_up_after_the_node_budget() {
+        let world = make_world(&[]);
+        let goal = on_floor(30, 30);
+        let settings = PathfindingSettings {
+            max_nodes: 5,
+            ..Default::default()
+        };
+        let path = find_path(&world, &settings, on_floor(1, 1), goal).unwrap();
+        let end = path.destination().unwrap();
+        assert_ne!(end, goal);
+        assert!(heuristic(&settings, end, goal) < heuristic(&settings, on_floor(1, 1), goal));
+
+        let path = find_path(
+            &world,
+            &PathfindingSettings::default(),
+            on_floor(1, 1),
+            goal,
+        );
+        assert_eq!(path.unwrap().destination(), Some(goal));
+    }
+}
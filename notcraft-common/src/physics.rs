@@ -1,13 +1,16 @@
 use crate::prelude::*;
 use nalgebra::{vector, Vector3};
+use serde::Deserialize;
 use std::{ops::RangeInclusive, sync::Arc};
 
 use super::{
     aabb::Aabb,
+    clock::GameTime,
+    game_state::playing,
     transform::Transform,
     world::{
         chunk::ChunkAccess,
-        registry::{BlockRegistry, CollisionType},
+        registry::{BlockRegistry, RegistryRef, MAX_COLLISION_OVERHANG},
         BlockPos,
     },
 };
@@ -20,11 +23,70 @@ pub struct RigidBody {
     pub velocity: Vector3<f32>,
 }
 
+/// entities with this component are left alone by physics and collision
+/// resolution, like while they're waiting to be teleported.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct PhysicsDisabled;
+
+/// downwards acceleration applied to every rigidbody, in blocks per second
+/// squared.
+pub const GRAVITY: f32 = 27.0;
+
+/// the fastest that an entity can slide down a climbable block, in blocks per
+/// second.
+pub const MAX_CLIMBING_DESCENT_SPEED: f32 = 3.0;
+
+/// entities landing slower than this on a bouncy block stay put instead of
+/// bouncing, so that they don't jitter up and down forever.
+pub const MIN_BOUNCE_SPEED: f32 = 2.0;
+
+/// how entities move around while standing on a block.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[serde(default)]
+pub struct SurfaceProperties {
+    /// how much grip the block has, from 0 to 1. less grip means that
+    /// entities speed up and slow down more slowly, like on ice.
+    pub friction: f32,
+    /// how much of the speed an entity lands with is sent back upwards, from
+    /// 0 to 1. entities that land on a bouncy block don't take fall damage.
+    pub bounce: f32,
+    /// how fast entities walk on this block, compared to normal ground.
+    pub speed_multiplier: f32,
+}
+
+impl Default for SurfaceProperties {
+    fn default() -> Self {
+        Self {
+            friction: 1.0,
+            bounce: 0.0,
+            speed_multiplier: 1.0,
+        }
+    }
+}
+
+impl SurfaceProperties {
+    pub fn is_valid(&self) -> bool {
+        self.friction > 0.0
+            && self.friction <= 1.0
+            && (0.0..=1.0).contains(&self.bounce)
+            && self.speed_multiplier > 0.0
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct AabbCollider {
     pub aabb: Aabb,
     pub on_ground: bool,
     pub in_liquid: bool,
+    /// whether the collider overlaps a climbable block, like a ladder.
+    pub climbing: bool,
+    /// the velocity that was cancelled out by terrain collisions this frame.
+    /// components are zero along axes that didn't collide with anything.
+    pub impact_velocity: Vector3<f32>,
+    /// the surface of the block the collider is standing on, which is just the
+    /// default surface when it isn't on the ground.
+    pub ground: SurfaceProperties,
 }
 
 impl AabbCollider {
@@ -33,6 +95,9 @@ impl AabbCollider {
             aabb,
             on_ground: false,
             in_liquid: false,
+            climbing: false,
+            impact_velocity: vector![0.0, 0.0, 0.0],
+            ground: SurfaceProperties::default(),
         }
     }
 }
@@ -68,230 +133,174 @@ impl<'a> CollisionContext<'a> {
     }
 }
 
-fn does_block_collide(ctx: &mut CollisionContext, block_pos: BlockPos) -> Option<bool> {
-    let prev_intersects = util::block_aabb(block_pos).intersects(&Aabb {
-        min: ctx.previous.min.map(f32::floor),
-        max: ctx.previous.max.map(f32::ceil),
-    });
+// collisions shallower than this are ignored, so that an entity resting right
+// on top of a block (give or take some float error) isn't considered to be
+// colliding with it.
+const COLLISION_EPSILON: f32 = 1e-5;
+// entities that were already this deep inside of a block's shape before moving
+// aren't pushed out of it, since that would usually mean getting stuck
+// jittering around inside of it instead.
+const EMBEDDED_DEPTH: f32 = 0.01;
+// the most times we go back over the blocks around an entity looking for edges
+// it clipped into.
+const MAX_EDGE_PASSES: usize = 3;
+
+/// how far `a` and `b` overlap along the axis where they overlap the least,
+/// which is negative if they don't overlap at all.
+fn overlap_depth(a: &Aabb, b: &Aabb) -> f32 {
+    (0..3)
+        .map(|i| f32::min(a.max[i], b.max[i]) - f32::max(a.min[i], b.min[i]))
+        .fold(f32::INFINITY, f32::min)
+}
 
-    match ctx
-        .registry
-        .get(ctx.access.block(block_pos)?)
-        .collision_type()
-    {
-        CollisionType::Solid => Some(!prev_intersects),
-        _ => Some(false),
-    }
+/// whether moving into `shape` from `previous` to `current` counts as a
+/// collision.
+fn is_new_collision(shape: &Aabb, previous: &Aabb, current: &Aabb) -> bool {
+    overlap_depth(shape, current) > COLLISION_EPSILON
+        && overlap_depth(shape, previous) <= EMBEDDED_DEPTH
 }
 
-fn detect_terrain_collisions(ctx: &mut CollisionContext) -> Option<Vector3<f32>> {
-    let delta = ctx.current.center() - ctx.previous.center();
-
-    let mut resolution = vector![0.0, 0.0, 0.0];
-
-    // let proj_x = ctx.previous.translated(delta.x * Vector3::x());
-    // let proj_y = ctx.previous.translated(delta.y * Vector3::y());
-    // let proj_z = ctx.previous.translated(delta.z * Vector3::z());
-
-    // add_debug_box(DebugBox {
-    //     bounds: proj_x,
-    //     rgba: [1.0, 0.0, 0.0, 0.6],
-    //     kind: DebugBoxKind::Dashed,
-    // });
-
-    // add_debug_box(DebugBox {
-    //     bounds: proj_y,
-    //     rgba: [0.0, 1.0, 0.0, 0.6],
-    //     kind: DebugBoxKind::Dashed,
-    // });
-
-    // add_debug_box(DebugBox {
-    //     bounds: proj_z,
-    //     rgba: [0.0, 0.0, 1.0, 0.6],
-    //     kind: DebugBoxKind::Dashed,
-    // });
-
-    {
-        let x = match delta.x < 0.0 {
-            true => ctx.current.min.x.floor() as i32,
-            false => make_collision_bound(ctx.current.max.x),
-        };
-        for y in make_collision_range(ctx.previous.min.y, ctx.previous.max.y) {
-            for z in make_collision_range(ctx.previous.min.z, ctx.previous.max.z) {
-                let block_pos = BlockPos { x, y, z };
-                if does_block_collide(ctx, block_pos)? {
-                    // add_debug_box(DebugBox {
-                    //     bounds: util::block_aabb(block_pos).inflate(0.003),
-                    //     rgba: [1.0, 0.2, 0.2, 0.6],
-                    //     kind: DebugBoxKind::Solid,
-                    // });
-                    resolution.x = match delta.x < 0.0 {
-                        true => x as f32 + 1.0 - ctx.current.min.x,
-                        false => x as f32 - ctx.current.max.x,
-                    };
-                }
-            }
-        }
+/// how far `aabb` has to move along `axis` to get back out of `shape`, going
+/// against the direction it was moving in.
+fn push_out(shape: &Aabb, aabb: &Aabb, axis: usize, delta: f32) -> f32 {
+    match delta < 0.0 {
+        true => shape.max[axis] - aabb.min[axis],
+        false => shape.min[axis] - aabb.max[axis],
     }
+}
 
-    {
-        let y = match delta.y < 0.0 {
-            true => ctx.current.min.y.floor() as i32,
-            false => make_collision_bound(ctx.current.max.y),
-        };
-        for x in make_collision_range(ctx.previous.min.x, ctx.previous.max.x) {
-            for z in make_collision_range(ctx.previous.min.z, ctx.previous.max.z) {
-                let block_pos = BlockPos { x, y, z };
-                if does_block_collide(ctx, block_pos)? {
-                    // add_debug_box(DebugBox {
-                    //     bounds: util::block_aabb(block_pos).inflate(0.003),
-                    //     rgba: [0.2, 1.0, 0.2, 0.6],
-                    //     kind: DebugBoxKind::Solid,
-                    // });
-                    resolution.y = match delta.y < 0.0 {
-                        true => y as f32 + 1.0 - ctx.current.min.y,
-                        false => y as f32 - ctx.current.max.y,
-                    };
+/// calls `func` with the world-space collision shape of every block that could
+/// be touching `aabb`, along with the block it belongs to.
+fn for_each_block_shape<F>(ctx: &mut CollisionContext, aabb: &Aabb, mut func: F) -> Option<()>
+where
+    F: FnMut(&Aabb, &RegistryRef),
+{
+    // shapes can stick out of the top of their block, so we have to look a bit
+    // further down than the aabb itself reaches.
+    let bottom = (aabb.min.y - MAX_COLLISION_OVERHANG).floor() as i32;
+    for x in make_collision_range(aabb.min.x, aabb.max.x) {
+        for y in bottom..=make_collision_bound(aabb.max.y) {
+            for z in make_collision_range(aabb.min.z, aabb.max.z) {
+                let id = ctx.access.block(BlockPos { x, y, z })?;
+                let offset = vector![x as f32, y as f32, z as f32];
+                let block = ctx.registry.get(id);
+                for shape in block.collision_shape() {
+                    func(&shape.translated(offset), &block);
                 }
             }
         }
     }
 
-    {
-        let z = match delta.z < 0.0 {
-            true => ctx.current.min.z.floor() as i32,
-            false => make_collision_bound(ctx.current.max.z),
-        };
-        for x in make_collision_range(ctx.previous.min.x, ctx.previous.max.x) {
-            for y in make_collision_range(ctx.previous.min.y, ctx.previous.max.y) {
-                let block_pos = BlockPos { x, y, z };
-                if does_block_collide(ctx, block_pos)? {
-                    // add_debug_box(DebugBox {
-                    //     bounds: util::block_aabb(block_pos).inflate(0.003),
-                    //     rgba: [0.2, 0.2, 1.0, 0.6],
-                    //     kind: DebugBoxKind::Solid,
-                    // });
-                    resolution.z = match delta.z < 0.0 {
-                        true => z as f32 + 1.0 - ctx.current.min.z,
-                        false => z as f32 - ctx.current.max.z,
-                    };
-                }
-            }
+    Some(())
+}
+
+fn detect_terrain_collisions(ctx: &mut CollisionContext) -> Option<Vector3<f32>> {
+    let previous = ctx.previous;
+    let current = ctx.current;
+    let delta = current.center() - previous.center();
+
+    let mut resolution: Vector3<f32> = vector![0.0, 0.0, 0.0];
+
+    // each axis is swept on its own, so that running into a wall along one axis
+    // still lets you slide along it on the others.
+    for axis in 0..3 {
+        if delta[axis] == 0.0 {
+            continue;
         }
-    }
 
-    // movement in the XZ plane but no collisions there mean we have to check for
-    // "the" corner case, where, since no collisions are detected when moving
-    // directly into an edge, that movement is allowed, and you end up clipping into
-    // the block. in the next frame, that collision is dectected, and the player is
-    // ejected from the block. this leads to a sort of stuck jittering.
-    //
-    // to mitigate this, we:
-    //     A) check the corner we're moving into for potential collisions
-    //     B) choose an axis to act like we slid on
-    let dotx = delta.normalize().dot(&Vector3::x()).abs();
-    let doty = delta.normalize().dot(&Vector3::y()).abs();
-    let dotz = delta.normalize().dot(&Vector3::z()).abs();
-
-    let yz = doty > dotx || dotz > dotx;
-    let xz = dotx > doty || dotz > doty;
-    let xy = dotx > dotz || doty > dotz;
-
-    if xz && delta.x != 0.0 && delta.z != 0.0 && resolution.x == 0.0 && resolution.z == 0.0 {
-        let x = match delta.x > 0.0 {
-            true => make_collision_bound(ctx.current.max.x),
-            false => ctx.current.min.x.floor() as i32,
-        };
-        let z = match delta.z > 0.0 {
-            true => make_collision_bound(ctx.current.max.z),
-            false => ctx.current.min.z.floor() as i32,
-        };
-        for y in make_collision_range(ctx.previous.min.y, ctx.previous.max.y) {
-            let block_pos = BlockPos { x, y, z };
-            if does_block_collide(ctx, block_pos)? {
-                if dotx > dotz {
-                    resolution.z = match delta.z < 0.0 {
-                        true => z as f32 + 1.0 - ctx.current.min.z,
-                        false => z as f32 - ctx.current.max.z,
-                    };
-                } else {
-                    resolution.x = match delta.x < 0.0 {
-                        true => x as f32 + 1.0 - ctx.current.min.x,
-                        false => x as f32 - ctx.current.max.x,
-                    };
+        let mut moved = previous;
+        moved.min[axis] += delta[axis];
+        moved.max[axis] += delta[axis];
+
+        let push = &mut resolution[axis];
+        for_each_block_shape(ctx, &moved, |shape, _| {
+            if is_new_collision(shape, &previous, &moved) {
+                let shape_push = push_out(shape, &moved, axis, delta[axis]);
+                if shape_push.abs() > push.abs() {
+                    *push = shape_push;
                 }
             }
-        }
+        })?;
     }
 
-    if yz && delta.y != 0.0 && delta.z != 0.0 && resolution.y == 0.0 && resolution.z == 0.0 {
-        let y = match delta.y > 0.0 {
-            true => make_collision_bound(ctx.current.max.y),
-            false => ctx.current.min.y.floor() as i32,
-        };
-        let z = match delta.z > 0.0 {
-            true => make_collision_bound(ctx.current.max.z),
-            false => ctx.current.min.z.floor() as i32,
-        };
-        for x in make_collision_range(ctx.previous.min.x, ctx.previous.max.x) {
-            let block_pos = BlockPos { x, y, z };
-            if does_block_collide(ctx, block_pos)? {
-                if doty > dotz {
-                    resolution.z = match delta.z < 0.0 {
-                        true => z as f32 + 1.0 - ctx.current.min.z,
-                        false => z as f32 - ctx.current.max.z,
-                    };
-                } else {
-                    resolution.y = match delta.y < 0.0 {
-                        true => y as f32 + 1.0 - ctx.current.min.y,
-                        false => y as f32 - ctx.current.max.y,
-                    };
-                }
+    // moving diagonally into an edge doesn't collide along any one axis on its
+    // own, so the combined movement would clip into the block. in that case, we
+    // push back out along whichever axis takes the least movement, which acts
+    // like we slid along that side. an axis can't push back any further than
+    // we moved along it, or we'd end up somewhere we never were. pushing out of
+    // one block can still push us into the block next to it, so this repeats
+    // until there's nothing left to push out of.
+    let mut resolved = current.translated(resolution);
+    for _ in 0..MAX_EDGE_PASSES {
+        let mut pushed = false;
+        let area = resolved;
+        for_each_block_shape(ctx, &area, |shape, _| {
+            if !is_new_collision(shape, &previous, &resolved) {
+                return;
             }
-        }
-    }
 
-    if xy && delta.x != 0.0 && delta.y != 0.0 && resolution.x == 0.0 && resolution.y == 0.0 {
-        let x = match delta.x > 0.0 {
-            true => make_collision_bound(ctx.current.max.x),
-            false => ctx.current.min.x.floor() as i32,
-        };
-        let y = match delta.y > 0.0 {
-            true => make_collision_bound(ctx.current.max.y),
-            false => ctx.current.min.y.floor() as i32,
-        };
-        for z in make_collision_range(ctx.previous.min.z, ctx.previous.max.z) {
-            let block_pos = BlockPos { x, y, z };
-            if does_block_collide(ctx, block_pos)? {
-                if dotx > doty {
-                    resolution.y = match delta.y < 0.0 {
-                        true => y as f32 + 1.0 - ctx.current.min.y,
-                        false => y as f32 - ctx.current.max.y,
-                    };
-                } else {
-                    resolution.x = match delta.x < 0.0 {
-                        true => x as f32 + 1.0 - ctx.current.min.x,
-                        false => x as f32 - ctx.current.max.x,
-                    };
-                }
+            let smallest_push = (0..3)
+                .filter(|&axis| delta[axis] != 0.0)
+                .map(|axis| (axis, push_out(shape, &resolved, axis, delta[axis])))
+                .filter(|&(axis, push)| push.abs() <= delta[axis].abs() + EMBEDDED_DEPTH)
+                .min_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()));
+
+            if let Some((axis, push)) = smallest_push {
+                resolution[axis] += push;
+                resolved.min[axis] += push;
+                resolved.max[axis] += push;
+                pushed = true;
             }
+        })?;
+
+        if !pushed {
+            break;
         }
     }
 
     Some(resolution)
 }
 
-fn detect_liquid_collisions(access: &mut ChunkAccess, prev: &Aabb) -> Option<bool> {
+// how far below an entity's feet we look for the block it's standing on.
+const GROUND_PROBE_DEPTH: f32 = 0.05;
+
+/// the surface of the block that `aabb` is standing on. when it's standing on
+/// more than one block, the one that's under the most of it wins.
+fn ground_surface(ctx: &mut CollisionContext, aabb: &Aabb) -> Option<SurfaceProperties> {
+    let mut probe = *aabb;
+    probe.max.y = aabb.min.y;
+    probe.min.y = aabb.min.y - GROUND_PROBE_DEPTH;
+
+    let mut surface = SurfaceProperties::default();
+    let mut largest_area = 0.0;
+    for_each_block_shape(ctx, &probe, |shape, block| {
+        if shape.max.y < probe.min.y || shape.max.y > aabb.min.y + EMBEDDED_DEPTH {
+            return;
+        }
+
+        let width = f32::min(shape.max.x, aabb.max.x) - f32::max(shape.min.x, aabb.min.x);
+        let depth = f32::min(shape.max.z, aabb.max.z) - f32::max(shape.min.z, aabb.min.z);
+        if width > 0.0 && depth > 0.0 && width * depth > largest_area {
+            largest_area = width * depth;
+            surface = block.surface();
+        }
+    })?;
+
+    Some(surface)
+}
+
+/// checks whether `aabb` overlaps any block that `predicate` accepts. returns
+/// `None` if any of the blocks it overlaps aren't loaded.
+pub fn overlaps_blocks<F>(access: &mut ChunkAccess, aabb: &Aabb, mut predicate: F) -> Option<bool>
+where
+    F: FnMut(RegistryRef) -> bool,
+{
     let registry = Arc::clone(access.registry());
-    for x in make_collision_range(prev.min.x, prev.max.x) {
-        for y in make_collision_range(prev.min.y, prev.max.y) {
-            for z in make_collision_range(prev.min.z, prev.max.z) {
+    for x in make_collision_range(aabb.min.x, aabb.max.x) {
+        for y in make_collision_range(aabb.min.y, aabb.max.y) {
+            for z in make_collision_range(aabb.min.z, aabb.max.z) {
                 let block_pos = BlockPos { x, y, z };
-                if registry
-                    .get(access.block(block_pos)?)
-                    .collision_type()
-                    .is_liquid()
-                {
+                if predicate(registry.get(access.block(block_pos)?)) {
                     return Some(true);
                 }
             }
@@ -301,6 +310,37 @@ fn detect_liquid_collisions(access: &mut ChunkAccess, prev: &Aabb) -> Option<boo
     Some(false)
 }
 
+/// moves `aabb` by `delta`, stopping at the first blocks it runs into, and
+/// returns how far it actually got. it slides along whatever it runs into, the
+/// same way colliders do. returns `None` if any of the blocks in the way aren't
+/// loaded.
+pub fn sweep_aabb(
+    access: &mut ChunkAccess,
+    aabb: &Aabb,
+    delta: Vector3<f32>,
+) -> Option<Vector3<f32>> {
+    const MAX_SWEEP_STEPS: usize = 32;
+    const MAX_STEP_DISTANCE: f32 = 0.5;
+
+    // stepping through keeps fast movement from skipping over thin walls
+    let desired_num_steps = (delta.amax() / MAX_STEP_DISTANCE) as usize;
+    let num_steps = usize::max(1, usize::min(desired_num_steps, MAX_SWEEP_STEPS));
+    let step = delta / num_steps as f32;
+
+    let mut moved = vector![0.0, 0.0, 0.0];
+    for _ in 0..num_steps {
+        let previous = aabb.translated(moved);
+        let mut ctx = CollisionContext::new(access, previous.translated(step), previous);
+        let resolution = detect_terrain_collisions(&mut ctx)?;
+        moved += step + resolution;
+        if resolution.magnitude_squared() > 0.0 {
+            break;
+        }
+    }
+
+    Some(moved)
+}
+
 fn do_terrain_collision(
     access: &mut ChunkAccess,
     collider: &mut AabbCollider,
@@ -315,7 +355,10 @@ fn do_terrain_collision(
         transform.translation.vector + (original_aabb.center() - target_aabb.center());
     let end_pos = transform.translation.vector;
 
-    collider.in_liquid = detect_liquid_collisions(access, &original_aabb)?;
+    collider.impact_velocity = vector![0.0, 0.0, 0.0];
+    collider.in_liquid = overlaps_blocks(access, &original_aabb, |block| {
+        block.collision_type().is_liquid()
+    })?;
 
     // we set the entity's position back to the previous position, and then step
     // through in increments. if there are no collisions, we usually reach the final
@@ -360,14 +403,17 @@ fn do_terrain_collision(
 
         if resolution.magnitude_squared() > 0.0 {
             if resolution.x != 0.0 {
+                collider.impact_velocity.x = rigidbody.velocity.x;
                 rigidbody.velocity.x = 0.0;
                 rigidbody.acceleration.x = 0.0;
             }
             if resolution.y != 0.0 {
+                collider.impact_velocity.y = rigidbody.velocity.y;
                 rigidbody.velocity.y = 0.0;
                 rigidbody.acceleration.y = 0.0;
             }
             if resolution.z != 0.0 {
+                collider.impact_velocity.z = rigidbody.velocity.z;
                 rigidbody.velocity.z = 0.0;
                 rigidbody.acceleration.z = 0.0;
             }
@@ -379,10 +425,23 @@ fn do_terrain_collision(
         }
     }
 
+    collider.ground = SurfaceProperties::default();
+    if collider.on_ground {
+        let aabb = collider.aabb.transformed(transform);
+        let mut ctx = CollisionContext::new(access, aabb, aabb);
+        collider.ground = ground_surface(&mut ctx, &aabb)?;
+
+        // bouncy blocks send whatever landed on them back up again
+        let bounce_speed = -collider.impact_velocity.y * collider.ground.bounce;
+        if bounce_speed >= MIN_BOUNCE_SPEED {
+            rigidbody.velocity.y = bounce_speed;
+        }
+    }
+
     Some(())
 }
 
-fn do_terrain_collision_wrapper(
+pub(crate) fn do_terrain_collision_wrapper(
     access: &mut ChunkAccess,
     collider: &mut AabbCollider,
     prev_collider: &PreviousCollider,
@@ -399,7 +458,7 @@ fn do_terrain_collision_wrapper(
 }
 
 pub struct PreviousCollider {
-    aabb_world: Aabb,
+    pub(crate) aabb_world: Aabb,
 }
 
 pub fn fix_previous_colliders(
@@ -422,12 +481,15 @@ pub fn update_previous_colliders(query: Query<(&AabbCollider, &Transform, &mut P
 // should happen after most code that deals with transforms happens.
 pub fn terrain_collision(
     mut access: ResMut<ChunkAccess>,
-    query: Query<(
-        &mut AabbCollider,
-        &PreviousCollider,
-        &mut RigidBody,
-        &mut Transform,
-    )>,
+    query: Query<
+        (
+            &mut AabbCollider,
+            &PreviousCollider,
+            &mut RigidBody,
+            &mut Transform,
+        ),
+        Without<PhysicsDisabled>,
+    >,
 ) {
     query.for_each_mut(
         |(mut collider, previous_collider, mut rigidbody, mut transform)| {
@@ -454,25 +516,58 @@ pub fn terrain_collision(
     );
 }
 
-pub fn apply_gravity(query: Query<&mut RigidBody>) {
+/// keeps track of which colliders are inside of climbable blocks, after
+/// they've been moved out of the terrain.
+pub fn detect_climbing(
+    mut access: ResMut<ChunkAccess>,
+    query: Query<(&mut AabbCollider, &Transform), Without<PhysicsDisabled>>,
+) {
+    query.for_each_mut(|(mut collider, transform)| {
+        let aabb = collider.aabb.transformed(transform);
+        // colliders in unloaded terrain don't move, so whatever they were doing
+        // before is still accurate.
+        if let Some(climbing) = overlaps_blocks(&mut access, &aabb, |block| block.climbable()) {
+            collider.climbing = climbing;
+        }
+    });
+}
+
+pub fn apply_gravity(query: Query<&mut RigidBody, Without<PhysicsDisabled>>) {
     query.for_each_mut(|mut rigidbody| {
-        rigidbody.acceleration.y -= 27.0;
+        rigidbody.acceleration.y -= GRAVITY;
+    });
+}
+
+pub fn apply_rigidbody_motion(
+    time: Res<GameTime>,
+    query: Query<(&mut RigidBody, &mut Transform, Option<&AabbCollider>), Without<PhysicsDisabled>>,
+) {
+    query.for_each_mut(|(mut rigidbody, mut transform, collider)| {
+        let climbing = collider.is_some_and(|collider| collider.climbing);
+        integrate_motion(&mut rigidbody, &mut transform, climbing, time.delta_seconds());
     });
 }
 
-pub fn apply_rigidbody_motion(time: Res<Time>, query: Query<(&mut RigidBody, &mut Transform)>) {
-    query.for_each_mut(|(mut rigidbody, mut transform)| {
-        let dt = time.delta_seconds();
+pub(crate) fn integrate_motion(
+    rigidbody: &mut RigidBody,
+    transform: &mut Transform,
+    climbing: bool,
+    dt: f32,
+) {
+    let a = rigidbody.acceleration;
+    rigidbody.acceleration = vector![0.0, 0.0, 0.0];
 
-        let a = rigidbody.acceleration;
-        rigidbody.acceleration = vector![0.0, 0.0, 0.0];
+    let dv = a * dt;
+    rigidbody.velocity += dv;
 
-        let dv = a * dt;
-        rigidbody.velocity += dv;
+    // climbing entities hold on instead of falling, so gravity only slides
+    // them down slowly.
+    if climbing {
+        rigidbody.velocity.y = f32::max(rigidbody.velocity.y, -MAX_CLIMBING_DESCENT_SPEED);
+    }
 
-        let dp = rigidbody.velocity * dt;
-        transform.translation.vector += dp;
-    });
+    let dp = rigidbody.velocity * dt;
+    transform.translation.vector += dp;
 }
 
 #[derive(Debug, Default)]
@@ -480,10 +575,13 @@ pub struct PhysicsPlugin {}
 
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system(apply_gravity.system());
+        app.add_system(apply_gravity.system().with_run_criteria(playing.system()));
         app.add_system_to_stage(
             CoreStage::PostUpdate,
-            apply_rigidbody_motion.system().label(MotionApplication),
+            apply_rigidbody_motion
+                .system()
+                .label(MotionApplication)
+                .with_run_criteria(playing.system()),
         );
     }
 }
@@ -498,10 +596,23 @@ impl Plugin for CollisionPlugin {
             terrain_collision
                 .system()
                 .label(CollisionResolution)
-                .after(MotionApplication),
+                .after(MotionApplication)
+                .with_run_criteria(playing.system()),
+        );
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            detect_climbing
+                .system()
+                .after(CollisionResolution)
+                .with_run_criteria(playing.system()),
         );
         app.add_system_to_stage(CoreStage::PreUpdate, fix_previous_colliders.system());
-        app.add_system_to_stage(CoreStage::PreUpdate, update_previous_colliders.system());
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            update_previous_colliders
+                .system()
+                .with_run_criteria(playing.system()),
+        );
     }
 }
 
@@ -510,3 +621,155 @@ pub struct MotionApplication;
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
 pub struct CollisionResolution;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        chunk::ArrayChunk,
+        registry::AIR_BLOCK,
+        test_util::{section_world, test_registry},
+        trace_ray, Ray3, VoxelWorld,
+    };
+    use nalgebra::{point, Point3};
+    use proptest::prelude::*;
+
+    // the random blocks fill a cube this many blocks wide, which starts at
+    // `REGION_MIN` along each axis. everything else in the section is air, and
+    // anything outside of the section is unloaded.
+    const REGION_LENGTH: usize = 8;
+    const REGION_MIN: f32 = 12.0;
+
+    fn make_world(solid: &[bool]) -> Arc<VoxelWorld> {
+        let registry = test_registry();
+        let stone = registry.lookup("stone");
+
+        let mut blocks = ArrayChunk::homogeneous(AIR_BLOCK);
+        for (i, _) in solid.iter().enumerate().filter(|(_, &solid)| solid) {
+            let offset = [i / 64, i / 8 % 8, i % 8];
+            blocks[offset.map(|n| REGION_MIN as usize + n)] = stone;
+        }
+
+        section_world(&registry, blocks)
+    }
+
+    fn solid_blocks() -> impl Strategy<Value = Vec<bool>> {
+        prop::collection::vec(prop::bool::weighted(0.3), REGION_LENGTH.pow(3))
+    }
+
+    // points a little past the edges of the region, so that things can start
+    // out in the open air around it.
+    fn point_near_region() -> impl Strategy<Value = Point3<f32>> {
+        let range = REGION_MIN - 2.0..REGION_MIN + REGION_LENGTH as f32 + 2.0;
+        (range.clone(), range.clone(), range).prop_map(|(x, y, z)| point![x, y, z])
+    }
+
+    fn vector_within(extent: f32) -> impl Strategy<Value = Vector3<f32>> {
+        let range = -extent..extent;
+        (range.clone(), range.clone(), range).prop_map(|(x, y, z)| vector![x, y, z])
+    }
+
+    /// how deep `aabb` is inside of the deepest solid block it overlaps.
+    fn deepest_overlap(access: &mut ChunkAccess, aabb: &Aabb) -> f32 {
+        let registry = Arc::clone(access.registry());
+        let mut deepest = f32::NEG_INFINITY;
+        for x in make_collision_range(aabb.min.x, aabb.max.x) {
+            for y in make_collision_range(aabb.min.y, aabb.max.y) {
+                for z in make_collision_range(aabb.min.z, aabb.max.z) {
+                    let id = access.block(BlockPos { x, y, z }).unwrap();
+                    let offset = vector![x as f32, y as f32, z as f32];
+                    for shape in registry.get(id).collision_shape() {
+                        deepest = f32::max(deepest, overlap_depth(&shape.translated(offset), aabb));
+                    }
+                }
+            }
+        }
+        deepest
+    }
+
+    proptest! {
+        #[test]
+        fn swept_colliders_never_end_inside_blocks(
+            solid in solid_blocks(),
+            start in point_near_region(),
+            dimensions in (0.2f32..0.9, 0.2f32..1.8, 0.2f32..0.9),
+            delta in vector_within(3.0),
+        ) {
+            let world = make_world(&solid);
+            let mut access = ChunkAccess::new(&world);
+
+            let (width, height, depth) = dimensions;
+            let mut collider = AabbCollider::new(Aabb::with_dimensions(vector![width, height, depth]));
+            let mut transform = Transform::to(start);
+            let previous = PreviousCollider {
+                aabb_world: collider.aabb.transformed(&transform),
+            };
+            prop_assume!(deepest_overlap(&mut access, &previous.aabb_world) <= 0.0);
+
+            transform.translation.vector += delta;
+            let mut rigidbody = RigidBody::default();
+            let resolved = do_terrain_collision(&mut access, &mut collider, &previous, &mut rigidbody, &mut transform);
+            prop_assert!(resolved.is_some());
+
+            let end = collider.aabb.transformed(&transform);
+            let depth = deepest_overlap(&mut access, &end);
+            prop_assert!(depth <= COLLISION_EPSILON, "ended {} deep inside of a block at {:?}", depth, end);
+        }
+
+        #[test]
+        fn swept_aabbs_never_end_inside_blocks(
+            solid in solid_blocks(),
+            start in point_near_region(),
+            size in 0.1f32..0.9,
+            delta in vector_within(3.0),
+        ) {
+            let world = make_world(&solid);
+            let mut access = ChunkAccess::new(&world);
+
+            let aabb = Aabb::with_dimensions(vector![size, size, size]).translated(start.coords);
+            prop_assume!(deepest_overlap(&mut access, &aabb) <= 0.0);
+
+            let moved = sweep_aabb(&mut access, &aabb, delta);
+            prop_assert!(moved.is_some());
+            let moved = moved.unwrap();
+            prop_assert!(moved.amax() <= delta.amax() + 1e-4, "moved {} when asked to move {}", moved, delta);
+
+            let end = aabb.translated(moved);
+            let depth = deepest_overlap(&mut access, &end);
+            prop_assert!(depth <= COLLISION_EPSILON, "ended {} deep inside of a block at {:?}", depth, end);
+        }
+
+        #[test]
+        fn raycasts_only_hit_solid_blocks_from_the_side_facing_the_ray(
+            solid in solid_blocks(),
+            origin in point_near_region(),
+            direction in vector_within(1.0),
+        ) {
+            prop_assume!(direction.magnitude() > 0.01);
+            let world = make_world(&solid);
+            let mut access = ChunkAccess::new(&world);
+
+            let ray = Ray3 { origin, direction };
+            let hit = match trace_ray(&mut access, ray, 16.0) {
+                Some(hit) => hit,
+                None => return Ok(()),
+            };
+
+            let id = access.block(hit.pos).unwrap();
+            prop_assert!(access.registry().get(id).collision_type().is_solid(), "hit a non-solid block at {:?}", hit.pos);
+
+            let center = Point3::from(hit.pos.origin()) + vector![0.5, 0.5, 0.5];
+            match hit.side {
+                // only the block the ray starts in can be hit without going
+                // through one of its sides.
+                None => prop_assert!(util::block_aabb(hit.pos).contains(&origin)),
+                Some(side) => {
+                    let normal = side.normal::<f32>();
+                    prop_assert!(normal.dot(&direction) < 0.0, "{:?} faces away from the ray", side);
+                    // the origin is on the outside of the face that was hit.
+                    prop_assert!(normal.dot(&(origin - center)) >= 0.5 - 1e-4, "{:?} of {:?} faces away from {}", side, hit.pos, origin);
+                }
+            }
+        }
+    }
+}
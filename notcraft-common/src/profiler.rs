@@ -0,0 +1,483 @@
+//! a small frame profiler.
+//!
+//! work is measured in "spans", which are either created with
+//! [`profile_scope!`], or by wrapping a system with
+//! [`ProfileSystemExt::profiled`]. the [`ProfilerPlugin`] additionally records
+//! a span for each stage of the schedule, and gathers everything that was
+//! recorded during a frame into a [`FrameProfile`] at the end of the frame.
+//!
+//! spans are only recorded while the profiler is recording, which is the case
+//! while something has asked for it with [`Profiler::set_recording`], or when
+//! a trace file was requested. trace files use the chrome tracing format, and
+//! can be opened with `chrome://tracing` or <https://ui.perfetto.dev>.
+
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use bevy_ecs::{
+    archetype::{Archetype, ArchetypeComponentId},
+    component::ComponentId,
+    query::Access,
+    schedule::StageLabel,
+    system::{System, SystemId},
+};
+use parking_lot::Mutex;
+
+use crate::prelude::*;
+
+/// the number of frames kept around in [`Profiler::history`].
+pub const FRAME_HISTORY_LEN: usize = 120;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SpanRecord {
+    pub name: &'static str,
+    /// the span this span was started inside of. spans started outside of any
+    /// other span on the same thread are parented to the current stage.
+    pub parent: Option<&'static str>,
+    pub thread: u64,
+    /// the time since the profiler was created that this span started at.
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+struct Recorder {
+    recording: AtomicBool,
+    epoch: Instant,
+    next_thread: AtomicU64,
+    spans: Mutex<Vec<SpanRecord>>,
+    stage: Mutex<Option<(&'static str, Instant)>>,
+    new_threads: Mutex<Vec<(u64, String)>>,
+}
+
+lazy_static::lazy_static! {
+    static ref RECORDER: Recorder = Recorder {
+        recording: AtomicBool::new(false),
+        epoch: Instant::now(),
+        next_thread: AtomicU64::new(0),
+        spans: Default::default(),
+        stage: Default::default(),
+        new_threads: Default::default(),
+    };
+}
+
+struct ThreadState {
+    id: u64,
+    open: RefCell<Vec<&'static str>>,
+}
+
+impl ThreadState {
+    fn new() -> Self {
+        let id = RECORDER.next_thread.fetch_add(1, Ordering::Relaxed);
+        let current = std::thread::current();
+        let name = current.name().unwrap_or("unnamed").to_owned();
+        RECORDER.new_threads.lock().push((id, name));
+
+        Self {
+            id,
+            open: Default::default(),
+        }
+    }
+}
+
+thread_local! {
+    static THREAD: ThreadState = ThreadState::new();
+}
+
+fn is_recording() -> bool {
+    RECORDER.recording.load(Ordering::Relaxed)
+}
+
+fn record_span(name: &'static str, parent: Option<&'static str>, start: Instant) {
+    let duration = start.elapsed();
+    let thread = THREAD.with(|thread| thread.id);
+    RECORDER.spans.lock().push(SpanRecord {
+        name,
+        parent,
+        thread,
+        start: start.saturating_duration_since(RECORDER.epoch),
+        duration,
+    });
+}
+
+/// records a span from when it's created until it's dropped. usually created
+/// through [`profile_scope!`].
+pub struct SpanGuard {
+    name: &'static str,
+    parent: Option<&'static str>,
+    start: Option<Instant>,
+}
+
+impl SpanGuard {
+    pub fn new(name: &'static str) -> Self {
+        if !is_recording() {
+            return Self {
+                name,
+                parent: None,
+                start: None,
+            };
+        }
+
+        let parent = THREAD.with(|thread| {
+            let mut open = thread.open.borrow_mut();
+            let parent = open.last().copied();
+            open.push(name);
+            parent
+        });
+        let parent = parent.or_else(|| RECORDER.stage.lock().map(|(stage, _)| stage));
+
+        Self {
+            name,
+            parent,
+            start: Some(Instant::now()),
+        }
+    }
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            THREAD.with(|thread| thread.open.borrow_mut().pop());
+            record_span(self.name, self.parent, start);
+        }
+    }
+}
+
+/// measures the rest of the enclosing scope as a span with the given name.
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        let _profile_span = $crate::profiler::SpanGuard::new($name);
+    };
+}
+
+pub use profile_scope;
+
+fn begin_stage(name: &'static str) {
+    if is_recording() {
+        *RECORDER.stage.lock() = Some((name, Instant::now()));
+    }
+}
+
+fn end_stage() {
+    if let Some((name, start)) = RECORDER.stage.lock().take() {
+        record_span(name, Some(FRAME_SPAN), start);
+    }
+}
+
+const FRAME_SPAN: &str = "frame";
+
+/// a system that records a span every time it runs. see
+/// [`ProfileSystemExt::profiled`].
+pub struct Profiled<S> {
+    inner: S,
+    name: &'static str,
+}
+
+impl<S: System> System for Profiled<S> {
+    type In = S::In;
+    type Out = S::Out;
+
+    fn name(&self) -> Cow<'static, str> {
+        self.inner.name()
+    }
+
+    fn id(&self) -> SystemId {
+        self.inner.id()
+    }
+
+    fn new_archetype(&mut self, archetype: &Archetype) {
+        self.inner.new_archetype(archetype);
+    }
+
+    fn component_access(&self) -> &Access<ComponentId> {
+        self.inner.component_access()
+    }
+
+    fn archetype_component_access(&self) -> &Access<ArchetypeComponentId> {
+        self.inner.archetype_component_access()
+    }
+
+    fn is_send(&self) -> bool {
+        self.inner.is_send()
+    }
+
+    unsafe fn run_unsafe(&mut self, input: Self::In, world: &World) -> Self::Out {
+        let _span = SpanGuard::new(self.name);
+        self.inner.run_unsafe(input, world)
+    }
+
+    fn apply_buffers(&mut self, world: &mut World) {
+        self.inner.apply_buffers(world);
+    }
+
+    fn initialize(&mut self, world: &mut World) {
+        self.inner.initialize(world);
+    }
+
+    fn check_change_tick(&mut self, change_tick: u32) {
+        self.inner.check_change_tick(change_tick);
+    }
+}
+
+// system names are full paths, which are a bit much to look at. chained
+// systems (like the ones made by `try_system!`) are named after the first system
+// in the chain.
+fn short_system_name(name: &str) -> &str {
+    let name = match name.strip_prefix("Chain(") {
+        Some(rest) => rest.split(", ").next().unwrap_or(rest),
+        None => name,
+    };
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+pub trait ProfileSystemExt: System + Sized {
+    /// records a span named after this system every time it runs.
+    fn profiled(self) -> Profiled<Self> {
+        // systems are only ever created once, so leaking the name here is fine.
+        let name = short_system_name(&self.name()).to_owned();
+        let name = Box::leak(name.into_boxed_str());
+        Profiled { inner: self, name }
+    }
+}
+
+impl<S: System> ProfileSystemExt for S {}
+
+/// every span that was recorded during a single frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameProfile {
+    pub duration: Duration,
+    pub spans: Vec<SpanRecord>,
+}
+
+impl FrameProfile {
+    /// the total time spent in each span with the given parent, longest first.
+    pub fn children(&self, parent: &str) -> Vec<(&'static str, Duration)> {
+        let mut totals = HashMap::<_, Duration>::new();
+        for span in self.spans.iter().filter(|span| span.parent == Some(parent)) {
+            *totals.entry(span.name).or_default() += span.duration;
+        }
+
+        let mut totals = totals.into_iter().collect::<Vec<_>>();
+        totals.sort_by_key(|&(_, duration)| std::cmp::Reverse(duration));
+        totals
+    }
+
+    /// the time spent in each stage, in the order the stages ran.
+    pub fn stages(&self) -> Vec<(&'static str, Duration)> {
+        let mut stages = self
+            .spans
+            .iter()
+            .filter(|span| span.parent == Some(FRAME_SPAN))
+            .collect::<Vec<_>>();
+        stages.sort_by_key(|span| span.start);
+        stages
+            .iter()
+            .map(|span| (span.name, span.duration))
+            .collect()
+    }
+}
+
+struct TraceWriter {
+    out: BufWriter<File>,
+    wrote_any: bool,
+}
+
+impl TraceWriter {
+    fn create(path: &Path) -> Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        // the closing bracket is optional in the chrome tracing format, which is
+        // handy for us because we don't have a good place to write it.
+        writeln!(out, "[")?;
+        Ok(Self {
+            out,
+            wrote_any: false,
+        })
+    }
+
+    fn write_event(&mut self, event: serde_json::Value) -> Result<()> {
+        if self.wrote_any {
+            writeln!(self.out, ",")?;
+        }
+        self.wrote_any = true;
+        serde_json::to_writer(&mut self.out, &event)?;
+        Ok(())
+    }
+
+    fn write_frame(&mut self, new_threads: &[(u64, String)], frame: &FrameProfile) -> Result<()> {
+        for (id, name) in new_threads {
+            self.write_event(serde_json::json!({
+                "name": "thread_name",
+                "ph": "M",
+                "pid": 0,
+                "tid": id,
+                "args": { "name": name },
+            }))?;
+        }
+
+        for span in frame.spans.iter() {
+            self.write_event(serde_json::json!({
+                "name": span.name,
+                "cat": span.parent.unwrap_or(""),
+                "ph": "X",
+                "pid": 0,
+                "tid": span.thread,
+                "ts": span.start.as_secs_f64() * 1e6,
+                "dur": span.duration.as_secs_f64() * 1e6,
+            }))?;
+        }
+
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+pub struct Profiler {
+    requested: bool,
+    frame_start: Option<Instant>,
+    history: VecDeque<FrameProfile>,
+    trace: Option<TraceWriter>,
+}
+
+impl std::fmt::Debug for Profiler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Profiler")
+            .field("requested", &self.requested)
+            .field("history", &self.history.len())
+            .field("tracing", &self.trace.is_some())
+            .finish()
+    }
+}
+
+impl Profiler {
+    fn new(trace_path: Option<&Path>) -> Self {
+        let trace = trace_path.and_then(|path| match TraceWriter::create(path) {
+            Ok(trace) => {
+                log::info!("writing frame traces to '{}'", path.display());
+                Some(trace)
+            }
+            Err(err) => {
+                log::error!("failed to create trace file '{}': {}", path.display(), err);
+                None
+            }
+        });
+
+        let profiler = Self {
+            requested: false,
+            frame_start: None,
+            history: VecDeque::with_capacity(FRAME_HISTORY_LEN),
+            trace,
+        };
+        profiler.update_recording();
+        profiler
+    }
+
+    fn update_recording(&self) {
+        let recording = self.requested || self.trace.is_some();
+        RECORDER.recording.store(recording, Ordering::Relaxed);
+    }
+
+    /// starts or stops recording spans. recording is always on while writing
+    /// a trace file.
+    pub fn set_recording(&mut self, recording: bool) {
+        self.requested = recording;
+        self.update_recording();
+    }
+
+    /// the most recent frames, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &FrameProfile> + '_ {
+        self.history.iter()
+    }
+
+    pub fn last_frame(&self) -> Option<&FrameProfile> {
+        self.history.back()
+    }
+
+    fn begin_frame(&mut self) {
+        // throw away anything left over from frames that we weren't recording
+        RECORDER.spans.lock().clear();
+        self.frame_start = is_recording().then(Instant::now);
+    }
+
+    fn finish_frame(&mut self) {
+        end_stage();
+        let start = match self.frame_start.take() {
+            Some(start) => start,
+            None => return,
+        };
+        record_span(FRAME_SPAN, None, start);
+
+        let frame = FrameProfile {
+            duration: start.elapsed(),
+            spans: std::mem::take(&mut *RECORDER.spans.lock()),
+        };
+
+        if let Some(trace) = self.trace.as_mut() {
+            let new_threads = std::mem::take(&mut *RECORDER.new_threads.lock());
+            if let Err(err) = trace.write_frame(&new_threads, &frame) {
+                log::error!("failed to write frame trace, no longer tracing: {}", err);
+                self.trace = None;
+                self.update_recording();
+            }
+        }
+
+        if self.history.len() == FRAME_HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(frame);
+    }
+}
+
+/// records a span for the stage with the given label every time it runs.
+pub fn profile_stage<L>(app: &mut AppBuilder, label: L, name: &'static str)
+where
+    L: StageLabel + Clone,
+{
+    let begin = move |_: &mut World| begin_stage(name);
+    let end = |_: &mut World| end_stage();
+    app.add_system_to_stage(label.clone(), begin.exclusive_system().at_start());
+    app.add_system_to_stage(label, end.exclusive_system().at_end());
+}
+
+fn begin_frame(world: &mut World) {
+    world.get_resource_mut::<Profiler>().unwrap().begin_frame();
+}
+
+fn finish_frame(world: &mut World) {
+    world.get_resource_mut::<Profiler>().unwrap().finish_frame();
+}
+
+#[derive(Debug, Default)]
+pub struct ProfilerPlugin {
+    trace_path: Option<PathBuf>,
+}
+
+impl ProfilerPlugin {
+    /// writes every recorded frame to a chrome tracing file at `path`.
+    pub fn with_trace_file<P: Into<PathBuf>>(mut self, path: Option<P>) -> Self {
+        self.trace_path = path.map(Into::into);
+        self
+    }
+}
+
+impl Plugin for ProfilerPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(Profiler::new(self.trace_path.as_deref()));
+
+        // the frame needs to begin before the first stage, and end after the last
+        app.add_system_to_stage(CoreStage::First, begin_frame.exclusive_system().at_start());
+        profile_stage(app, CoreStage::First, "first");
+        profile_stage(app, CoreStage::PreUpdate, "pre_update");
+        profile_stage(app, CoreStage::Update, "update");
+        profile_stage(app, CoreStage::PostUpdate, "post_update");
+        profile_stage(app, CoreStage::Last, "last");
+        app.add_system_to_stage(CoreStage::Last, finish_frame.exclusive_system().at_end());
+    }
+}
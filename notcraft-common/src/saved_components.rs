@@ -0,0 +1,264 @@
+//! saving the components on entities.
+//!
+//! components that should stick around between runs of the game implement
+//! [`SavedComponent`], and are registered with [`register_saved_component`]
+//! when their plugin is built. saving an entity goes through every registered
+//! component that it has, so that new kinds of saved components don't need the
+//! saving code to know about them.
+//!
+//! an entity is saved as a map from component names to raw nodes, each holding
+//! one encoded component. components in a save that nothing registered, like
+//! ones from a plugin that isn't loaded anymore, are kept as those opaque
+//! blobs, and written back out untouched the next time the entity is saved.
+
+use crate::{
+    codec::{
+        decode::{decode_node, Node},
+        encode::{encode_node, Encode, Encoder, Raw},
+        NodeKind,
+    },
+    prelude::*,
+};
+use bevy_ecs::{component::Component, system::EntityCommands, world::EntityRef};
+use std::io::Write;
+
+/// a component that can be saved.
+pub trait SavedComponent: Component + Encode<Vec<u8>> + Sized {
+    /// what the component is saved under. changing this makes old saves treat
+    /// the component as one they don't know about.
+    const NAME: &'static str;
+
+    fn from_node(node: &Node) -> Result<Self>;
+}
+
+/// the saved components of a single entity.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SavedEntity {
+    /// each component's name, and the component encoded as a node.
+    components: Vec<(String, Vec<u8>)>,
+}
+
+impl SavedEntity {
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.components.iter().map(|(name, _)| &**name)
+    }
+
+    /// adds `component`, replacing the saved component with the same name if
+    /// there is one.
+    pub fn insert<C: SavedComponent>(&mut self, component: &C) -> Result<()> {
+        let mut bytes = vec![];
+        encode_node(&mut bytes, component)?;
+        self.insert_raw(C::NAME, bytes);
+        Ok(())
+    }
+
+    pub fn remove<C: SavedComponent>(&mut self) {
+        self.components.retain(|(name, _)| name != C::NAME);
+    }
+
+    /// the saved component with `C`'s name, if there is one.
+    pub fn get<C: SavedComponent>(&self) -> Result<Option<C>> {
+        match self.components.iter().find(|(name, _)| name == C::NAME) {
+            Some((_, bytes)) => Ok(Some(C::from_node(&decode_node(&mut &bytes[..])?)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn insert_raw(&mut self, name: &str, bytes: Vec<u8>) {
+        match self.components.iter_mut().find(|(other, _)| other == name) {
+            Some((_, old)) => *old = bytes,
+            None => self.components.push((name.into(), bytes)),
+        }
+    }
+
+    pub fn from_node(node: &Node) -> Result<Self> {
+        let entries = match node.as_map() {
+            Some(entries) => entries,
+            None => bail!("saved entity is not a map"),
+        };
+
+        let mut saved = Self::default();
+        for (name, component) in entries {
+            match component.as_raw() {
+                Some(bytes) => saved.insert_raw(name, bytes.to_vec()),
+                None => bail!("saved component '{}' is not a raw node", name),
+            }
+        }
+        Ok(saved)
+    }
+}
+
+impl<W: Write> Encode<W> for SavedEntity {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode_map(|mut map| {
+            for (name, bytes) in self.components.iter() {
+                map.entry(name).encode(&Raw(&bytes[..]))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+struct ComponentEntry {
+    name: &'static str,
+    save: fn(&EntityRef, &mut SavedEntity) -> Result<()>,
+    load: fn(&SavedEntity, &mut EntityCommands) -> Result<()>,
+}
+
+fn save_component<C: SavedComponent>(entity: &EntityRef, saved: &mut SavedEntity) -> Result<()> {
+    match entity.get::<C>() {
+        Some(component) => saved.insert(component),
+        None => Ok(()),
+    }
+}
+
+fn load_component<C: SavedComponent>(
+    saved: &SavedEntity,
+    entity: &mut EntityCommands,
+) -> Result<()> {
+    if let Some(component) = saved.get::<C>()? {
+        entity.insert(component);
+    }
+    Ok(())
+}
+
+/// every component that gets saved.
+#[derive(Default)]
+pub struct SavedComponents {
+    entries: Vec<ComponentEntry>,
+}
+
+impl SavedComponents {
+    pub fn register<C: SavedComponent>(&mut self) {
+        if self.is_registered(C::NAME) {
+            log::warn!("saved component '{}' was registered twice", C::NAME);
+            return;
+        }
+
+        self.entries.push(ComponentEntry {
+            name: C::NAME,
+            save: save_component::<C>,
+            load: load_component::<C>,
+        });
+    }
+
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.entries.iter().any(|entry| entry.name == name)
+    }
+
+    /// saves every registered component that `entity` has. components in
+    /// `previous` that aren't registered are carried over, so that saving an
+    /// entity doesn't lose what it was loaded with.
+    pub fn save(&self, entity: &EntityRef, previous: Option<&SavedEntity>) -> Result<SavedEntity> {
+        let mut saved = SavedEntity::default();
+        if let Some(previous) = previous {
+            for (name, bytes) in previous.components.iter() {
+                if !self.is_registered(name) {
+                    saved.insert_raw(name, bytes.clone());
+                }
+            }
+        }
+
+        for entry in self.entries.iter() {
+            (entry.save)(entity, &mut saved)?;
+        }
+        Ok(saved)
+    }
+
+    /// inserts every registered component in `saved` into `entity`. components
+    /// that fail to load are skipped, so that one broken component doesn't
+    /// take the rest of the entity down with it.
+    pub fn load(&self, saved: &SavedEntity, entity: &mut EntityCommands) {
+        for entry in self.entries.iter() {
+            if let Err(err) = (entry.load)(saved, entity) {
+                log::error!("failed to load saved component '{}': {}", entry.name, err);
+            }
+        }
+        for name in saved.names().filter(|name| !self.is_registered(name)) {
+            log::debug!("keeping unknown saved component '{}' as-is", name);
+        }
+    }
+}
+
+/// makes `C` get saved along with the rest of the entity it's on.
+pub fn register_saved_component<C: SavedComponent>(app: &mut AppBuilder) {
+    let world = app.world_mut();
+    if !world.contains_resource::<SavedComponents>() {
+        world.insert_resource(SavedComponents::default());
+    }
+    let mut components = world.get_resource_mut::<SavedComponents>().unwrap();
+    components.register::<C>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::encode::encode_node;
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct Counter(u64);
+
+    impl<W: Write> Encode<W> for Counter {
+        const KIND: NodeKind = NodeKind::UnsignedVarInt;
+
+        fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+            encoder.encode(&self.0)
+        }
+    }
+
+    impl SavedComponent for Counter {
+        const NAME: &'static str = "counter";
+
+        fn from_node(node: &Node) -> Result<Self> {
+            match node.as_unsigned() {
+                Some(count) => Ok(Counter(count as u64)),
+                None => bail!("counter is not an unsigned integer"),
+            }
+        }
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct Label;
+
+    fn saved_entity(world: &mut World, components: &SavedComponents) -> SavedEntity {
+        let entity = world.spawn().insert(Counter(7)).insert(Label).id();
+        components.save(&world.entity(entity), None).unwrap()
+    }
+
+    #[test]
+    fn registered_components_are_saved() {
+        let mut components = SavedComponents::default();
+        components.register::<Counter>();
+
+        let saved = saved_entity(&mut World::default(), &components);
+        assert_eq!(saved.names().collect::<Vec<_>>(), ["counter"]);
+        assert_eq!(saved.get::<Counter>().unwrap(), Some(Counter(7)));
+    }
+
+    #[test]
+    fn unknown_components_are_kept() {
+        let mut old_components = SavedComponents::default();
+        old_components.register::<Counter>();
+        let saved = saved_entity(&mut World::default(), &old_components);
+
+        let mut bytes = vec![];
+        encode_node(&mut bytes, &saved).unwrap();
+        let loaded = SavedEntity::from_node(&decode_node(&mut &bytes[..]).unwrap()).unwrap();
+        assert_eq!(loaded, saved);
+
+        // whatever used to save counters isn't around anymore, but the counter
+        // should still be there after the entity is saved again
+        let mut world = World::default();
+        let entity = world.spawn().insert(Label).id();
+        let resaved = SavedComponents::default()
+            .save(&world.entity(entity), Some(&loaded))
+            .unwrap();
+        assert_eq!(resaved.get::<Counter>().unwrap(), Some(Counter(7)));
+    }
+}
@@ -0,0 +1,565 @@
+//! scripts for trying out new behavior without recompiling the game.
+//!
+//! scripts are [Rhai](https://rhai.rs) files in `resources/scripts/`, which are
+//! each run once when the game starts, in order of their file names. while
+//! they run, they can register callbacks with these functions:
+//!
+//! - `register_command(name, usage, callback)` adds a console command. the
+//!   event has the command's `args` as an array of strings, and a string
+//!   returned from the callback is printed to the console.
+//! - `register_block_behavior(block, callback)` runs the callback when a block
+//!   with that name is right-clicked. the event has the block's `x`, `y`, and
+//!   `z`. returning `false` lets the click carry on as if the block didn't do
+//!   anything, so that a block can still be placed against it.
+//! - `on_block_update(callback)` runs the callback for every block that
+//!   changes, whatever changed it. the event has the block's `x`, `y`, and
+//!   `z`, and the `old` and `new` block names.
+//! - `on_chunk_load(callback)` runs the callback whenever a chunk column is
+//!   loaded. the event has the column's `x` and `z`.
+//!
+//! every callback is called as `callback(world, event)`. the world lets
+//! scripts look at and change blocks by name, with `world.block(x, y, z)`,
+//! `world.block_state(x, y, z)`, `world.set_block(x, y, z, name)`,
+//! `world.set_block_state(x, y, z, name, state)`, and `world.has_block(name)`.
+//! blocks that aren't loaded read as `()`. a world is only good for as long as
+//! the callback it was passed to is running.
+//!
+//! scripts can't reach anything besides what's listed here: there's no access
+//! to files, and a script that runs for too long is stopped partway through.
+
+use crate::{
+    console::{add_console_command, ConsoleCommand, ConsoleLabel},
+    lang::tr,
+    notification::Notify,
+    prelude::*,
+    world::{chunk::ChunkAccess, BlockPos, BlockUpdateEvent, WorldEvent},
+};
+use parking_lot::Mutex;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, FnPtr, Map, AST};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// how many operations a script gets to run each time it's called before it's
+/// stopped, so that an endless loop doesn't hang the game.
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_CALL_LEVELS: usize = 64;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// a script's view of the world, which is handed to every callback.
+#[derive(Clone)]
+pub struct ScriptWorld {
+    /// taken back out once the callback returns, so that scripts that hang on
+    /// to the world can't use it later.
+    access: Arc<Mutex<Option<ChunkAccess>>>,
+}
+
+impl ScriptWorld {
+    fn with<R>(&self, func: impl FnOnce(&mut ChunkAccess) -> ScriptResult<R>) -> ScriptResult<R> {
+        match self.access.lock().as_mut() {
+            Some(access) => func(access),
+            None => Err("the world can only be used while a callback is running".into()),
+        }
+    }
+
+    fn block(&mut self, x: i64, y: i64, z: i64) -> ScriptResult<Dynamic> {
+        self.with(|access| {
+            Ok(match access.block(script_pos(x, y, z)) {
+                Some(id) => access.registry().name(id).into(),
+                None => Dynamic::UNIT,
+            })
+        })
+    }
+
+    fn block_state(&mut self, x: i64, y: i64, z: i64) -> ScriptResult<Dynamic> {
+        self.with(|access| {
+            let id = access.block(script_pos(x, y, z));
+            let registry = access.registry();
+            let state = id.and_then(|id| registry.get(id).state_name());
+            Ok(state.map_or(Dynamic::UNIT, |state| state.into()))
+        })
+    }
+
+    fn set_block(&mut self, x: i64, y: i64, z: i64, name: &str) -> ScriptResult<()> {
+        self.with(|access| match access.registry().try_lookup(name) {
+            Some(id) => {
+                access.set_block(script_pos(x, y, z), id);
+                Ok(())
+            }
+            None => Err(format!("unknown block '{}'", name).into()),
+        })
+    }
+
+    fn set_block_state(
+        &mut self,
+        x: i64,
+        y: i64,
+        z: i64,
+        name: &str,
+        state: &str,
+    ) -> ScriptResult<()> {
+        self.with(
+            |access| match access.registry().try_lookup_state(name, state) {
+                Some(id) => {
+                    access.set_block(script_pos(x, y, z), id);
+                    Ok(())
+                }
+                None => Err(format!("unknown block state '{}' of '{}'", state, name).into()),
+            },
+        )
+    }
+
+    fn has_block(&mut self, name: &str) -> ScriptResult<bool> {
+        self.with(|access| Ok(access.registry().try_lookup(name).is_some()))
+    }
+}
+
+fn script_pos(x: i64, y: i64, z: i64) -> BlockPos {
+    BlockPos::new([x as i32, y as i32, z as i32])
+}
+
+/// lends `access` to scripts for as long as `func` runs.
+fn with_script_world<R>(access: &mut ChunkAccess, func: impl FnOnce(&ScriptWorld) -> R) -> R {
+    let empty = ChunkAccess::new(&access.world);
+    let lent = std::mem::replace(access, empty);
+    let world = ScriptWorld {
+        access: Arc::new(Mutex::new(Some(lent))),
+    };
+
+    let result = func(&world);
+    *access = world
+        .access
+        .lock()
+        .take()
+        .expect("chunk access was taken while lent to scripts");
+    result
+}
+
+#[derive(Clone, Debug)]
+struct Callback {
+    /// index into [`ScriptHost::scripts`] of the script that registered this.
+    script: usize,
+    func: FnPtr,
+}
+
+/// what scripts registered while they were being run.
+#[derive(Debug, Default)]
+struct Registrations {
+    /// the script that's running right now.
+    script: usize,
+    commands: Vec<(String, String, Callback)>,
+    behaviors: Vec<(String, Callback)>,
+    block_updates: Vec<Callback>,
+    chunk_loads: Vec<Callback>,
+}
+
+impl Registrations {
+    fn callback(&self, func: FnPtr) -> Callback {
+        Callback {
+            script: self.script,
+            func,
+        }
+    }
+}
+
+struct Script {
+    name: String,
+    ast: AST,
+}
+
+/// every script that was loaded, and what they registered.
+pub struct ScriptHost {
+    engine: Engine,
+    scripts: Vec<Script>,
+    commands: HashMap<String, (String, Callback)>,
+    behaviors: HashMap<String, Callback>,
+    block_updates: Vec<Callback>,
+    chunk_loads: Vec<Callback>,
+    /// scripts that couldn't be loaded, and why.
+    load_errors: Vec<(String, String)>,
+}
+
+fn make_engine(registrations: &Arc<Mutex<Registrations>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.on_debug(|text, source, pos| {
+        log::debug!("{} ({}): {}", source.unwrap_or("script"), pos, text);
+    });
+
+    engine
+        .register_type_with_name::<ScriptWorld>("World")
+        .register_fn("block", ScriptWorld::block)
+        .register_fn("block_state", ScriptWorld::block_state)
+        .register_fn("set_block", ScriptWorld::set_block)
+        .register_fn("set_block_state", ScriptWorld::set_block_state)
+        .register_fn("has_block", ScriptWorld::has_block);
+
+    let regs = Arc::clone(registrations);
+    engine.register_fn(
+        "register_command",
+        move |name: &str, usage: &str, func: FnPtr| {
+            let mut regs = regs.lock();
+            let callback = regs.callback(func);
+            regs.commands.push((name.into(), usage.into(), callback));
+        },
+    );
+    let regs = Arc::clone(registrations);
+    engine.register_fn(
+        "register_block_behavior",
+        move |block: &str, func: FnPtr| {
+            let mut regs = regs.lock();
+            let callback = regs.callback(func);
+            regs.behaviors.push((block.into(), callback));
+        },
+    );
+    let regs = Arc::clone(registrations);
+    engine.register_fn("on_block_update", move |func: FnPtr| {
+        let mut regs = regs.lock();
+        let callback = regs.callback(func);
+        regs.block_updates.push(callback);
+    });
+    let regs = Arc::clone(registrations);
+    engine.register_fn("on_chunk_load", move |func: FnPtr| {
+        let mut regs = regs.lock();
+        let callback = regs.callback(func);
+        regs.chunk_loads.push(callback);
+    });
+
+    engine
+}
+
+impl ScriptHost {
+    /// runs every `.rhai` file in `dir`. scripts that fail to run are skipped,
+    /// and listed in [`ScriptHost::load_errors`]. there being no directory at
+    /// all just means there are no scripts.
+    pub fn load_dir(dir: &Path) -> Result<Self> {
+        let mut paths = vec![];
+        if dir.exists() {
+            for entry in std::fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.extension().is_some_and(|ext| ext == "rhai") {
+                    paths.push(path);
+                }
+            }
+        }
+        paths.sort();
+
+        let mut sources = vec![];
+        for path in paths {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            sources.push((name, std::fs::read_to_string(&path)?));
+        }
+        Ok(Self::from_sources(sources))
+    }
+
+    /// runs each script, given as its name and source code.
+    pub fn from_sources<I>(sources: I) -> Self
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let registrations = Arc::new(Mutex::new(Registrations::default()));
+        let engine = make_engine(&registrations);
+
+        let mut scripts = vec![];
+        let mut load_errors = vec![];
+        for (name, source) in sources {
+            registrations.lock().script = scripts.len();
+            let result = engine
+                .compile(&source)
+                .map_err(|err| err.to_string())
+                .and_then(|ast| match engine.run_ast(&ast) {
+                    Ok(()) => Ok(ast),
+                    Err(err) => Err(err.to_string()),
+                });
+
+            match result {
+                Ok(ast) => {
+                    log::info!("loaded script '{}'", name);
+                    scripts.push(Script { name, ast });
+                }
+                Err(err) => {
+                    log::error!("failed to load script '{}': {}", name, err);
+                    // anything the script registered before failing goes too
+                    let failed = scripts.len();
+                    let mut regs = registrations.lock();
+                    regs.commands.retain(|(_, _, cb)| cb.script != failed);
+                    regs.behaviors.retain(|(_, cb)| cb.script != failed);
+                    regs.block_updates.retain(|cb| cb.script != failed);
+                    regs.chunk_loads.retain(|cb| cb.script != failed);
+                    load_errors.push((name, err));
+                }
+            }
+        }
+
+        let regs = std::mem::take(&mut *registrations.lock());
+        Self {
+            engine,
+            scripts,
+            commands: regs
+                .commands
+                .into_iter()
+                .map(|(name, usage, callback)| (name, (usage, callback)))
+                .collect(),
+            behaviors: regs.behaviors.into_iter().collect(),
+            block_updates: regs.block_updates,
+            chunk_loads: regs.chunk_loads,
+            load_errors,
+        }
+    }
+
+    /// the name and usage of every command that scripts registered.
+    pub fn commands(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.commands
+            .iter()
+            .map(|(name, (usage, _))| (&**name, &**usage))
+    }
+
+    pub fn load_errors(&self) -> &[(String, String)] {
+        &self.load_errors
+    }
+
+    fn call(&self, callback: &Callback, world: &ScriptWorld, event: Map) -> Option<Dynamic> {
+        let script = &self.scripts[callback.script];
+        let args = (world.clone(), event);
+        match callback
+            .func
+            .call::<Dynamic>(&self.engine, &script.ast, args)
+        {
+            Ok(result) => Some(result),
+            Err(err) => {
+                log::error!("script '{}' failed: {}", script.name, err);
+                None
+            }
+        }
+    }
+
+    /// runs the script behavior of the block at `pos`, if it has one. returns
+    /// whether the block did anything, like [`crate::world::behavior::interact`].
+    pub fn interact(&self, access: &mut ChunkAccess, pos: BlockPos) -> bool {
+        let callback = match access.block(pos) {
+            Some(id) => self.behaviors.get(access.registry().name(id)),
+            None => None,
+        };
+        let callback = match callback {
+            Some(callback) => callback,
+            None => return false,
+        };
+
+        let event = position_event(pos);
+        let result = with_script_world(access, |world| self.call(callback, world, event));
+        // a behavior that failed still swallows the click
+        result.is_none_or(|result| result.as_bool().unwrap_or(true))
+    }
+
+    /// runs a command that a script registered. returns `false` if no script
+    /// registered a command with that name.
+    pub fn run_command(&self, access: &mut ChunkAccess, command: &ConsoleCommand) -> bool {
+        let (_, callback) = match self.commands.get(&command.name) {
+            Some(command) => command,
+            None => return false,
+        };
+
+        let mut event = Map::new();
+        let args = command.args.iter().cloned().map(Dynamic::from);
+        event.insert("args".into(), args.collect::<Array>().into());
+        let result = with_script_world(access, |world| self.call(callback, world, event));
+        if let Some(text) = result.and_then(|result| result.into_string().ok()) {
+            println!("{}", text);
+        }
+        true
+    }
+}
+
+fn position_event(pos: BlockPos) -> Map {
+    let mut event = Map::new();
+    event.insert("x".into(), (pos.x as i64).into());
+    event.insert("y".into(), (pos.y as i64).into());
+    event.insert("z".into(), (pos.z as i64).into());
+    event
+}
+
+fn dispatch_block_updates(
+    host: Res<ScriptHost>,
+    mut access: ResMut<ChunkAccess>,
+    mut block_update_events: EventReader<BlockUpdateEvent>,
+) {
+    if host.block_updates.is_empty() {
+        return;
+    }
+
+    let registry = Arc::clone(access.registry());
+    let events = block_update_events.iter().collect::<Vec<_>>();
+    with_script_world(&mut access, |world| {
+        for event in events {
+            let mut args = position_event(event.pos);
+            args.insert("old".into(), registry.name(event.old_id).into());
+            args.insert("new".into(), registry.name(event.new_id).into());
+            for callback in host.block_updates.iter() {
+                host.call(callback, world, args.clone());
+            }
+        }
+    });
+}
+
+fn dispatch_chunk_loads(
+    host: Res<ScriptHost>,
+    mut access: ResMut<ChunkAccess>,
+    mut world_events: EventReader<WorldEvent>,
+) {
+    if host.chunk_loads.is_empty() {
+        return;
+    }
+
+    let loaded = world_events
+        .iter()
+        .filter_map(|event| match event {
+            WorldEvent::Loaded(chunk) => Some(chunk.pos()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    with_script_world(&mut access, |world| {
+        for pos in loaded {
+            let mut args = Map::new();
+            args.insert("x".into(), (pos.x as i64).into());
+            args.insert("z".into(), (pos.z as i64).into());
+            for callback in host.chunk_loads.iter() {
+                host.call(callback, world, args.clone());
+            }
+        }
+    });
+}
+
+fn script_commands(
+    host: Res<ScriptHost>,
+    mut access: ResMut<ChunkAccess>,
+    mut commands: EventReader<ConsoleCommand>,
+) {
+    for command in commands.iter() {
+        host.run_command(&mut access, command);
+    }
+}
+
+fn report_load_errors(host: Res<ScriptHost>, mut notify_events: EventWriter<Notify>) {
+    for (name, err) in host.load_errors() {
+        notify_events.send(Notify::error(tr!("notify.script_failed", name, err)));
+    }
+}
+
+#[derive(Debug)]
+pub struct ScriptingPlugin {
+    script_dir: PathBuf,
+}
+
+impl Default for ScriptingPlugin {
+    fn default() -> Self {
+        Self {
+            script_dir: "resources/scripts".into(),
+        }
+    }
+}
+
+impl ScriptingPlugin {
+    pub fn with_script_dir<P: AsRef<Path>>(mut self, path: &P) -> Self {
+        self.script_dir = path.as_ref().into();
+        self
+    }
+}
+
+impl Plugin for ScriptingPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let host = match ScriptHost::load_dir(&self.script_dir) {
+            Ok(host) => host,
+            Err(err) => {
+                log::error!("failed to read scripts in {:?}: {}", self.script_dir, err);
+                ScriptHost::from_sources(vec![])
+            }
+        };
+
+        let commands = host
+            .commands()
+            .map(|(name, usage)| (name.to_string(), usage.to_string()))
+            .collect::<Vec<_>>();
+        for (name, usage) in commands {
+            add_console_command(app, name, usage);
+        }
+
+        app.insert_resource(host);
+        app.add_startup_system(report_load_errors.system());
+        app.add_system(dispatch_block_updates.system());
+        app.add_system(dispatch_chunk_loads.system());
+        app.add_system(script_commands.system().after(ConsoleLabel));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(source: &str) -> ScriptHost {
+        ScriptHost::from_sources(vec![("test".to_string(), source.to_string())])
+    }
+
+    #[test]
+    fn scripts_register_callbacks() {
+        let host = host(
+            r#"
+            register_command("hello", "/hello <name>", |world, event| "hi " + event.args[0]);
+            register_block_behavior("lamp", |world, event| true);
+            on_block_update(|world, event| ());
+            on_chunk_load(|world, event| ());
+            "#,
+        );
+        assert!(host.load_errors().is_empty());
+        assert_eq!(
+            host.commands().collect::<Vec<_>>(),
+            [("hello", "/hello <name>")]
+        );
+        assert!(host.behaviors.contains_key("lamp"));
+        assert_eq!((host.block_updates.len(), host.chunk_loads.len()), (1, 1));
+    }
+
+    #[test]
+    fn broken_scripts_register_nothing() {
+        let host = ScriptHost::from_sources(vec![
+            (
+                "broken".to_string(),
+                r#"register_command("a", "/a", |w, e| ()); this is not rhai"#.to_string(),
+            ),
+            (
+                "thrower".to_string(),
+                r#"on_chunk_load(|w, e| ()); throw "oops";"#.to_string(),
+            ),
+            (
+                "fine".to_string(),
+                r#"on_chunk_load(|w, e| ());"#.to_string(),
+            ),
+        ]);
+        let failed = host
+            .load_errors()
+            .iter()
+            .map(|(name, _)| &**name)
+            .collect::<Vec<_>>();
+        assert_eq!(failed, ["broken", "thrower"]);
+        assert_eq!(host.commands().count(), 0);
+        assert_eq!(host.chunk_loads.len(), 1);
+        assert_eq!(host.scripts[host.chunk_loads[0].script].name, "fine");
+    }
+
+    #[test]
+    fn bundled_scripts_load() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../resources/scripts");
+        let host = ScriptHost::load_dir(dir.as_ref()).unwrap();
+        assert_eq!(host.load_errors(), []);
+        assert!(host.commands().any(|(name, _)| name == "blockat"));
+    }
+
+    #[test]
+    fn endless_scripts_are_stopped() {
+        let host = host("loop {}");
+        assert_eq!(host.load_errors().len(), 1);
+    }
+}
@@ -0,0 +1,313 @@
+//! counting up what's happened in a world, like how many blocks have been
+//! broken, how far the player has walked, and how long the world has been
+//! played for.
+//!
+//! block counts come straight from [`BlockUpdateEvent`]s, so they notice blocks
+//! changing no matter what changed them, be it the player, an explosion, or a
+//! console command. the rest is only counted for entities with a
+//! [`StatsTracked`] component, which is usually just the player.
+
+use crate::{
+    clock::GameTime,
+    codec::{
+        decode::Node,
+        encode::{Encode, Encoder},
+        NodeKind,
+    },
+    game_state::playing,
+    health::DeathEvent,
+    lang::tr,
+    math::*,
+    notification::Notify,
+    prelude::*,
+    transform::Transform,
+    world::{
+        persistence::{SaveEvent, WorldPersistence},
+        registry::{BlockId, BlockRegistry, AIR_BLOCK},
+        BlockUpdateEvent,
+    },
+};
+use std::{collections::BTreeMap, io::Write, sync::Arc};
+
+/// how often the stats are written out to the save, in seconds. they're also
+/// saved whenever everything else is.
+const STATS_SAVE_INTERVAL: f32 = 60.0;
+
+/// moving further than this in a single frame is a teleport rather than a
+/// trip, and doesn't count towards the distance traveled.
+const MAX_TRAVEL_PER_FRAME: f32 = 16.0;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorldStats {
+    /// how many of each block have been broken, by block name.
+    pub blocks_broken: BTreeMap<String, u64>,
+    /// how many of each block have been placed, by block name.
+    pub blocks_placed: BTreeMap<String, u64>,
+    /// in blocks.
+    pub distance_traveled: f64,
+    pub deaths: u64,
+    /// in seconds. time spent paused doesn't count.
+    pub time_played: f64,
+}
+
+impl WorldStats {
+    /// counts a block changing from `old_id` into `new_id`. fluids flowing
+    /// around and blocks switching between their own states don't count as
+    /// breaking or placing anything.
+    pub fn record_block_change(
+        &mut self,
+        registry: &BlockRegistry,
+        old_id: BlockId,
+        new_id: BlockId,
+    ) {
+        if registry.get(old_id).same_block(new_id) {
+            return;
+        }
+
+        let counts = |id| id != AIR_BLOCK && !registry.get(id).liquid();
+        if counts(old_id) {
+            let name = registry.name(old_id).into();
+            *self.blocks_broken.entry(name).or_default() += 1;
+        }
+        if counts(new_id) {
+            let name = registry.name(new_id).into();
+            *self.blocks_placed.entry(name).or_default() += 1;
+        }
+    }
+
+    pub fn total_broken(&self) -> u64 {
+        self.blocks_broken.values().sum()
+    }
+
+    pub fn total_placed(&self) -> u64 {
+        self.blocks_placed.values().sum()
+    }
+
+    pub fn from_node(node: &Node) -> Result<Self> {
+        let counts = |key| match node.get(key).map(Node::as_map) {
+            Some(Some(entries)) => entries
+                .iter()
+                .map(|(name, count)| match count.as_unsigned() {
+                    Some(count) => Ok((name.clone(), count as u64)),
+                    None => bail!("field '{}.{}' is not an unsigned integer", key, name),
+                })
+                .collect(),
+            Some(None) => bail!("field '{}' is not a map", key),
+            None => Ok(BTreeMap::new()),
+        };
+        let float = |key| match node.get(key).map(Node::as_f64) {
+            Some(Some(value)) => Ok(value),
+            Some(None) => bail!("field '{}' is not a float", key),
+            None => Ok(0.0),
+        };
+
+        let deaths = match node.get("deaths").map(Node::as_unsigned) {
+            Some(Some(deaths)) => deaths as u64,
+            Some(None) => bail!("field 'deaths' is not an unsigned integer"),
+            None => 0,
+        };
+
+        Ok(Self {
+            blocks_broken: counts("blocks_broken")?,
+            blocks_placed: counts("blocks_placed")?,
+            distance_traveled: float("distance_traveled")?,
+            deaths,
+            time_played: float("time_played")?,
+        })
+    }
+}
+
+impl<W: Write> Encode<W> for WorldStats {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode_map(|mut map| {
+            for (key, counts) in [
+                ("blocks_broken", &self.blocks_broken),
+                ("blocks_placed", &self.blocks_placed),
+            ] {
+                map.entry(key).encode_map(|mut map| {
+                    for (name, count) in counts {
+                        map.entry(name).encode(count)?;
+                    }
+                    Ok(())
+                })?;
+            }
+            map.entry("distance_traveled")
+                .encode(&self.distance_traveled)?;
+            map.entry("deaths").encode(&self.deaths)?;
+            map.entry("time_played").encode(&self.time_played)?;
+            Ok(())
+        })
+    }
+}
+
+/// formats a number of seconds like `1h 2m 3s`, leaving off the larger units
+/// when they'd be zero.
+pub fn format_duration(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    match (hours, minutes) {
+        (0, 0) => format!("{}s", seconds),
+        (0, _) => format!("{}m {}s", minutes, seconds),
+        _ => format!("{}h {}m {}s", hours, minutes, seconds),
+    }
+}
+
+/// marks an entity whose travels and deaths are counted in the [`WorldStats`].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct StatsTracked {
+    last_pos: Option<Point3<f32>>,
+}
+
+fn count_block_changes(
+    registry: Res<Arc<BlockRegistry>>,
+    mut stats: ResMut<WorldStats>,
+    mut block_update_events: EventReader<BlockUpdateEvent>,
+) {
+    for event in block_update_events.iter() {
+        stats.record_block_change(&registry, event.old_id, event.new_id);
+    }
+}
+
+fn count_deaths(
+    query: Query<(), With<StatsTracked>>,
+    mut stats: ResMut<WorldStats>,
+    mut death_events: EventReader<DeathEvent>,
+) {
+    for event in death_events.iter() {
+        if query.get(event.entity).is_ok() {
+            stats.deaths += 1;
+        }
+    }
+}
+
+fn count_distance(
+    mut stats: ResMut<WorldStats>,
+    query: Query<(&Transform, &mut StatsTracked), Changed<Transform>>,
+) {
+    query.for_each_mut(|(transform, mut tracked)| {
+        let pos = transform.pos();
+        if let Some(last_pos) = tracked.last_pos {
+            let distance = nalgebra::distance(&last_pos, &pos);
+            if distance <= MAX_TRAVEL_PER_FRAME {
+                stats.distance_traveled += distance as f64;
+            }
+        }
+        tracked.last_pos = Some(pos);
+    });
+}
+
+fn count_time_played(time: Res<GameTime>, mut stats: ResMut<WorldStats>) {
+    stats.time_played += time.delta_seconds() as f64;
+}
+
+fn save_stats(
+    time: Res<GameTime>,
+    stats: Res<WorldStats>,
+    mut since_save: Local<f32>,
+    mut persistence: ResMut<WorldPersistence>,
+    mut save_events: EventReader<SaveEvent>,
+    mut notify_events: EventWriter<Notify>,
+) {
+    *since_save += time.delta_seconds();
+    let requested = save_events.iter().count() > 0;
+    if !requested && *since_save < STATS_SAVE_INTERVAL {
+        return;
+    }
+
+    *since_save = 0.0;
+    if let Err(err) = persistence.update_metadata(|meta| meta.stats = stats.clone()) {
+        log::error!("failed to save stats: {}", err);
+        notify_events.send(Notify::error(tr!("notify.save_failed", err)));
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+struct StatsLabel;
+
+#[derive(Debug, Default)]
+pub struct StatsPlugin {}
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let saved = app
+            .world()
+            .get_resource::<WorldPersistence>()
+            .expect(
+                "`StatsPlugin` added before `WorldPlugin`! (no `WorldPersistence` resource exists)",
+            )
+            .metadata()
+            .stats
+            .clone();
+        app.insert_resource(saved);
+
+        app.add_system(count_block_changes.system().label(StatsLabel));
+        app.add_system(count_deaths.system().label(StatsLabel));
+        app.add_system(
+            count_distance
+                .system()
+                .label(StatsLabel)
+                .with_run_criteria(playing.system()),
+        );
+        app.add_system(
+            count_time_played
+                .system()
+                .label(StatsLabel)
+                .with_run_criteria(playing.system()),
+        );
+        app.add_system(save_stats.system().after(StatsLabel));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codec::{decode::decode_node, encode::encode_node},
+        world::test_util::test_registry,
+    };
+
+    #[test]
+    fn block_changes_are_counted_by_name() {
+        let registry = test_registry();
+        let stone = registry.lookup("stone");
+        let water = registry.lookup("water");
+
+        let mut stats = WorldStats::default();
+        stats.record_block_change(&registry, AIR_BLOCK, stone);
+        stats.record_block_change(&registry, stone, AIR_BLOCK);
+        stats.record_block_change(&registry, AIR_BLOCK, stone);
+        // fluids spreading around isn't anybody placing anything
+        stats.record_block_change(&registry, AIR_BLOCK, water);
+        stats.record_block_change(&registry, stone, water);
+
+        assert_eq!(stats.blocks_placed.get("stone"), Some(&2));
+        assert_eq!(stats.blocks_broken.get("stone"), Some(&2));
+        assert_eq!((stats.total_placed(), stats.total_broken()), (2, 2));
+    }
+
+    #[test]
+    fn stats_survive_encoding() {
+        let mut stats = WorldStats {
+            distance_traveled: 1234.5,
+            deaths: 3,
+            time_played: 60.25,
+            ..Default::default()
+        };
+        stats.blocks_broken.insert("stone".into(), 12);
+        stats.blocks_placed.insert("dirt".into(), 4);
+
+        let mut bytes = vec![];
+        encode_node(&mut bytes, &stats).unwrap();
+        let node = decode_node(&mut &bytes[..]).unwrap();
+        assert_eq!(WorldStats::from_node(&node).unwrap(), stats);
+    }
+
+    #[test]
+    fn durations_leave_off_empty_units() {
+        assert_eq!(format_duration(5.9), "5s");
+        assert_eq!(format_duration(125.0), "2m 5s");
+        assert_eq!(format_duration(3600.0 * 26.0 + 61.0), "26h 1m 1s");
+    }
+}
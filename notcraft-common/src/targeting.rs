@@ -0,0 +1,108 @@
+//! what the player can point at and interact with.
+//!
+//! everything that picks out the block the player is looking at goes through
+//! the [`TargetingConfig`] resource, so that how far they can reach and which
+//! blocks get in the way are the same everywhere, and can be changed in one
+//! place while the game is running. the block the player is looking at is
+//! traced once a frame into [`CurrentTarget`], which anything else that needs
+//! it can read instead of tracing it again.
+
+use crate::world::{chunk::ChunkAccess, trace_ray_filtered, Ray3, RayFilter, RaycastHit};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TargetingConfig {
+    /// the furthest away, in blocks, that a block can be targeted from.
+    pub reach: f32,
+    /// whether fluids can be targeted, instead of being looked through.
+    pub fluids: bool,
+    /// whether detail blocks that can be walked through, like grass and
+    /// ladders, are looked through instead of being targeted.
+    pub through_detail_blocks: bool,
+}
+
+impl Default for TargetingConfig {
+    fn default() -> Self {
+        Self {
+            reach: 100.0,
+            fluids: false,
+            through_detail_blocks: true,
+        }
+    }
+}
+
+impl TargetingConfig {
+    pub fn ray_filter(&self) -> RayFilter {
+        RayFilter {
+            fluids: self.fluids,
+            detail_blocks: !self.through_detail_blocks,
+        }
+    }
+
+    /// the block that `ray` is pointing at, if there's one in reach.
+    pub fn target(&self, access: &mut ChunkAccess, ray: Ray3<f32>) -> Option<RaycastHit> {
+        trace_ray_filtered(access, ray, self.reach, self.ray_filter())
+    }
+}
+
+/// the block the player is looking at this frame, if there's one in reach.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CurrentTarget(pub Option<RaycastHit>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        chunk::ArrayChunk,
+        registry::AIR_BLOCK,
+        test_util::{section_world, test_registry},
+        VoxelWorld,
+    };
+    use nalgebra::{point, vector};
+    use std::sync::Arc;
+
+    // a column of grass, water, and stone, from top to bottom.
+    fn make_world() -> Arc<VoxelWorld> {
+        let registry = test_registry();
+
+        let mut blocks = ArrayChunk::homogeneous(AIR_BLOCK);
+        blocks[[4, 6, 4]] = registry.lookup("detail_grass");
+        blocks[[4, 5, 4]] = registry.lookup("water");
+        blocks[[4, 4, 4]] = registry.lookup("stone");
+
+        section_world(&registry, blocks)
+    }
+
+    #[test]
+    fn targeting_skips_the_blocks_it_looks_through() {
+        let world = make_world();
+        let mut access = ChunkAccess::new(&world);
+        let ray = Ray3 {
+            origin: point![4.5, 10.5, 4.5],
+            direction: vector![0.0, -1.0, 0.0],
+        };
+        let target = |access: &mut ChunkAccess, config: TargetingConfig| {
+            config.target(access, ray).map(|hit| hit.pos.y)
+        };
+
+        let config = TargetingConfig::default();
+        assert_eq!(target(&mut access, config), Some(4));
+
+        let fluids = TargetingConfig {
+            fluids: true,
+            ..config
+        };
+        assert_eq!(target(&mut access, fluids), Some(5));
+
+        let detail = TargetingConfig {
+            through_detail_blocks: false,
+            ..config
+        };
+        assert_eq!(target(&mut access, detail), Some(6));
+
+        let short = TargetingConfig {
+            reach: 3.0,
+            ..config
+        };
+        assert_eq!(target(&mut access, short), None);
+    }
+}
@@ -0,0 +1,278 @@
+//! thread pools for background work.
+//!
+//! rather than every subsystem throwing work onto the global rayon pool (or
+//! building its own pool), work is split up by kind into a few named pools, so
+//! that one kind of work can't starve another, and so that pool sizes can be
+//! tuned for the machine we're running on.
+
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::prelude::*;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PoolKind {
+    /// terrain generation.
+    Generator,
+    /// chunk meshing.
+    Mesher,
+    /// reading and writing files.
+    Io,
+    /// anything else that needs to happen off the main thread, like
+    /// pathfinding.
+    General,
+}
+
+impl PoolKind {
+    pub const ALL: [PoolKind; 4] = [
+        PoolKind::Generator,
+        PoolKind::Mesher,
+        PoolKind::Io,
+        PoolKind::General,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PoolKind::Generator => "generator",
+            PoolKind::Mesher => "mesher",
+            PoolKind::Io => "io",
+            PoolKind::General => "general",
+        }
+    }
+
+    fn default_threads(&self, cores: usize) -> usize {
+        let threads = match self {
+            PoolKind::Generator => cores / 2,
+            PoolKind::Mesher => cores / 4,
+            PoolKind::Io => 2,
+            PoolKind::General => cores / 4,
+        };
+        usize::max(1, threads)
+    }
+}
+
+impl FromStr for PoolKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match PoolKind::ALL.iter().find(|kind| kind.name() == s) {
+            Some(&kind) => Ok(kind),
+            None => bail!("unknown thread pool '{}'", s),
+        }
+    }
+}
+
+/// an override for the number of threads in a pool, written as
+/// `<pool>=<threads>`, like `mesher=2`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PoolSize {
+    pub kind: PoolKind,
+    pub threads: usize,
+}
+
+impl FromStr for PoolSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, threads) = match s.split_once('=') {
+            Some(it) => it,
+            None => bail!("expected '<pool>=<threads>', got '{}'", s),
+        };
+
+        let threads = threads.parse()?;
+        if threads == 0 {
+            bail!("thread pool '{}' needs at least one thread", kind);
+        }
+
+        Ok(Self {
+            kind: kind.parse()?,
+            threads,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct PoolMetrics {
+    queued: AtomicUsize,
+    running: AtomicUsize,
+    busy_nanos: AtomicU64,
+}
+
+/// a point-in-time view of what a pool is up to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PoolStats {
+    pub threads: usize,
+    /// tasks that have been spawned, but haven't started running yet.
+    pub queued: usize,
+    /// tasks that are currently running.
+    pub running: usize,
+    /// the total amount of time spent running tasks, summed across all threads
+    /// in the pool.
+    pub busy_time: Duration,
+}
+
+pub struct TaskPool {
+    kind: PoolKind,
+    pool: ThreadPool,
+    metrics: Arc<PoolMetrics>,
+    /// shared between every pool. once it's set, tasks that haven't started yet
+    /// are skipped.
+    stopping: Arc<AtomicBool>,
+}
+
+impl std::fmt::Debug for TaskPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TaskPool")
+            .field("kind", &self.kind)
+            .field("threads", &self.pool.current_num_threads())
+            .field("metrics", &self.metrics)
+            .finish()
+    }
+}
+
+impl TaskPool {
+    fn new(kind: PoolKind, threads: usize, stopping: Arc<AtomicBool>) -> Result<Self> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(move |idx| format!("{}-{}", kind.name(), idx))
+            .build()?;
+
+        Ok(Self {
+            kind,
+            pool,
+            metrics: Default::default(),
+            stopping,
+        })
+    }
+
+    pub fn kind(&self) -> PoolKind {
+        self.kind
+    }
+
+    pub fn spawn<F>(&self, func: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let metrics = Arc::clone(&self.metrics);
+        let stopping = Arc::clone(&self.stopping);
+        metrics.queued.fetch_add(1, Ordering::Relaxed);
+
+        self.pool.spawn(move || {
+            metrics.queued.fetch_sub(1, Ordering::Relaxed);
+            if stopping.load(Ordering::Relaxed) {
+                return;
+            }
+            metrics.running.fetch_add(1, Ordering::Relaxed);
+
+            let start = Instant::now();
+            func();
+            let elapsed = start.elapsed().as_nanos() as u64;
+
+            metrics.busy_nanos.fetch_add(elapsed, Ordering::Relaxed);
+            metrics.running.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            threads: self.pool.current_num_threads(),
+            queued: self.metrics.queued.load(Ordering::Relaxed),
+            running: self.metrics.running.load(Ordering::Relaxed),
+            busy_time: Duration::from_nanos(self.metrics.busy_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// the thread pools shared between every subsystem that needs to do work in
+/// the background.
+#[derive(Debug)]
+pub struct TaskPools {
+    pools: Vec<TaskPool>,
+    stopping: Arc<AtomicBool>,
+}
+
+impl TaskPools {
+    pub fn new(sizes: &[PoolSize]) -> Result<Self> {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let stopping = Arc::new(AtomicBool::new(false));
+
+        let pools = PoolKind::ALL
+            .iter()
+            .map(|&kind| {
+                let threads = match sizes.iter().rev().find(|size| size.kind == kind) {
+                    Some(size) => size.threads,
+                    None => kind.default_threads(cores),
+                };
+                log::info!("starting '{}' pool with {} threads", kind.name(), threads);
+                TaskPool::new(kind, threads, Arc::clone(&stopping))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { pools, stopping })
+    }
+
+    pub fn get(&self, kind: PoolKind) -> &TaskPool {
+        // pools are created in the same order as `PoolKind::ALL`
+        &self.pools[PoolKind::ALL.iter().position(|&k| k == kind).unwrap()]
+    }
+
+    pub fn spawn<F>(&self, kind: PoolKind, func: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.get(kind).spawn(func);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TaskPool> + '_ {
+        self.pools.iter()
+    }
+
+    /// stops every pool from starting any more tasks, and waits for the ones
+    /// that are already running to finish, giving up after `timeout`. returns
+    /// whether everything finished in time.
+    ///
+    /// tasks can still be spawned afterwards, but they'll be skipped.
+    pub fn shut_down(&self, timeout: Duration) -> bool {
+        self.stopping.store(true, Ordering::Relaxed);
+
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            let idle = self.iter().all(|pool| {
+                let stats = pool.stats();
+                stats.queued == 0 && stats.running == 0
+            });
+            if idle {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        false
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TaskPoolPlugin {
+    sizes: Vec<PoolSize>,
+}
+
+impl TaskPoolPlugin {
+    pub fn with_sizes(mut self, sizes: &[PoolSize]) -> Self {
+        self.sizes.extend_from_slice(sizes);
+        self
+    }
+}
+
+impl Plugin for TaskPoolPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let pools = TaskPools::new(&self.sizes).expect("failed to create thread pools");
+        app.insert_resource(pools);
+    }
+}
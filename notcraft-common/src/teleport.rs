@@ -0,0 +1,157 @@
+//! moving entities long distances without dropping them into unloaded terrain.
+//!
+//! when an entity is teleported, a chunk loader is put down at the
+//! destination, and the entity is held in place with its physics disabled
+//! until the terrain it's going to land in has loaded. only then is it moved.
+
+use crate::{
+    physics::{PhysicsDisabled, PreviousCollider, RigidBody},
+    prelude::*,
+    transform::Transform,
+    world::{DynamicChunkLoader, VoxelWorld, WorldPos},
+};
+use nalgebra::Point3;
+use std::sync::Arc;
+
+/// the loader put down at teleport destinations for entities that don't load
+/// chunks themselves.
+const DEFAULT_ANCHOR_LOADER: DynamicChunkLoader = DynamicChunkLoader {
+    load_radius: 1,
+    unload_radius: 2,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TeleportEvent {
+    pub entity: Entity,
+    pub target: Point3<f32>,
+}
+
+/// an entity that's waiting on its destination to load.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Teleporting {
+    pub target: Point3<f32>,
+    /// the chunk loader keeping the destination loaded.
+    anchor: Entity,
+    /// whether the entity has been moved already. the anchor sticks around for
+    /// one frame after the move, so that the entity's own chunk loader has a
+    /// chance to take over the destination before the anchor lets go of it.
+    arrived: bool,
+}
+
+/// keeps a teleport destination loaded.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct TeleportAnchor {
+    entity: Entity,
+}
+
+fn start_teleports(
+    mut cmd: Commands,
+    mut teleport_events: EventReader<TeleportEvent>,
+    query: Query<(Option<&DynamicChunkLoader>, Option<&Teleporting>)>,
+) {
+    for event in teleport_events.iter() {
+        let (loader, teleporting) = match query.get(event.entity) {
+            Ok(it) => it,
+            Err(_) => continue,
+        };
+
+        // a newer teleport replaces any that haven't finished yet
+        if let Some(teleporting) = teleporting {
+            cmd.entity(teleporting.anchor).despawn();
+        }
+
+        // using the same loader as the entity means that when it arrives, its
+        // own loader covers exactly the area that the anchor already loaded.
+        let anchor = cmd
+            .spawn()
+            .insert(Transform::default().translated(&event.target.coords))
+            .insert(loader.copied().unwrap_or(DEFAULT_ANCHOR_LOADER))
+            .insert(TeleportAnchor {
+                entity: event.entity,
+            })
+            .id();
+
+        cmd.entity(event.entity)
+            .insert(PhysicsDisabled)
+            .insert(Teleporting {
+                target: event.target,
+                anchor,
+                arrived: false,
+            });
+    }
+}
+
+fn finish_teleports(
+    mut cmd: Commands,
+    world: Res<Arc<VoxelWorld>>,
+    query: Query<(
+        Entity,
+        &mut Teleporting,
+        &mut Transform,
+        Option<&mut RigidBody>,
+    )>,
+) {
+    query.for_each_mut(|(entity, mut teleporting, mut transform, rigidbody)| {
+        if teleporting.arrived {
+            cmd.entity(teleporting.anchor).despawn();
+            cmd.entity(entity)
+                .remove::<Teleporting>()
+                .remove::<PhysicsDisabled>();
+            return;
+        }
+
+        // the section the entity ends up in, and the one below it, so there's
+        // something to stand on even if it lands right on a section boundary.
+        let target = WorldPos::new(teleporting.target.coords);
+        let below = target.offset([0.0, -1.0, 0.0]);
+        if !world.is_section_loaded(target.into()) || !world.is_section_loaded(below.into()) {
+            return;
+        }
+
+        transform.translation.vector = teleporting.target.coords;
+        if let Some(mut rigidbody) = rigidbody {
+            rigidbody.velocity = vector![0.0, 0.0, 0.0];
+        }
+        // otherwise, collision detection would see the entity sweep all the way
+        // from where it started.
+        cmd.entity(entity).remove::<PreviousCollider>();
+        teleporting.arrived = true;
+    });
+}
+
+/// gets rid of anchors whose entity has since been despawned.
+fn remove_orphaned_anchors(
+    mut cmd: Commands,
+    anchors: Query<(Entity, &TeleportAnchor)>,
+    teleporting: Query<&Teleporting>,
+) {
+    anchors.for_each(|(anchor, &TeleportAnchor { entity })| {
+        if teleporting.get(entity).is_err() {
+            cmd.entity(anchor).despawn();
+        }
+    });
+}
+
+#[derive(Debug, Default)]
+pub struct TeleportPlugin {}
+
+impl Plugin for TeleportPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<TeleportEvent>();
+
+        // these run before `Update`, because chunk loaders only notice removed
+        // anchors if they're despawned in an earlier stage.
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            start_teleports.system().label(TeleportLabel),
+        );
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            finish_teleports.system().after(TeleportLabel),
+        );
+        app.add_system_to_stage(CoreStage::PreUpdate, remove_orphaned_anchors.system());
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+struct TeleportLabel;
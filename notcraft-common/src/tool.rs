@@ -0,0 +1,122 @@
+//! tools, and how long blocks take to break with them.
+//!
+//! blocks can say that they need a class of tool of at least some tier to be
+//! broken properly, like stone needing a pickaxe. they can still be broken
+//! with anything else, but it takes a lot longer and they don't drop anything.
+//! holding a tool of the class a block needs also breaks it faster, by however
+//! fast the tool is.
+//!
+//! until there are proper items, tools are blocks with a `tool` property,
+//! which can be held but never placed.
+
+use serde::Deserialize;
+
+/// how long it takes to break a block by hand, per point of hardness.
+pub const SECONDS_PER_HARDNESS: f32 = 1.0;
+
+/// how many times longer a block takes to break without the tool it needs.
+pub const WRONG_TOOL_SLOWDOWN: f32 = 3.0;
+
+/// the tool that a block needs to be broken properly.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ToolRequirement {
+    pub class: String,
+    /// the lowest tier of tool that's good enough.
+    pub tier: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ToolProperties {
+    /// what kind of tool this is, like `pickaxe`, which is matched against the
+    /// class that blocks need.
+    pub class: String,
+    pub tier: u8,
+    /// how many times faster this breaks blocks that need its class.
+    pub speed: f32,
+}
+
+impl ToolProperties {
+    pub fn is_valid(&self) -> bool {
+        self.speed > 0.0
+    }
+
+    pub fn satisfies(&self, requirement: &ToolRequirement) -> bool {
+        self.class == requirement.class && self.tier >= requirement.tier
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BreakTime {
+    pub seconds: f32,
+    /// whether the block drops anything once it's broken.
+    pub drops: bool,
+}
+
+/// how long a block with `hardness` takes to break while holding `held`, and
+/// whether that's good enough to get anything out of it.
+pub fn break_time(
+    hardness: f32,
+    requirement: Option<&ToolRequirement>,
+    held: Option<&ToolProperties>,
+) -> BreakTime {
+    let mut seconds = hardness * SECONDS_PER_HARDNESS;
+    let mut drops = true;
+    if let Some(requirement) = requirement {
+        if let Some(held) = held.filter(|held| held.class == requirement.class) {
+            seconds /= held.speed;
+        }
+        drops = matches!(held, Some(held) if held.satisfies(requirement));
+        if !drops {
+            seconds *= WRONG_TOOL_SLOWDOWN;
+        }
+    }
+    BreakTime { seconds, drops }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(seconds: f32, drops: bool) -> BreakTime {
+        BreakTime { seconds, drops }
+    }
+
+    fn pickaxe(tier: u8) -> ToolProperties {
+        ToolProperties {
+            class: "pickaxe".into(),
+            tier,
+            speed: 2.0,
+        }
+    }
+
+    #[test]
+    fn tools_speed_up_and_gate_breaking() {
+        let requirement = ToolRequirement {
+            class: "pickaxe".into(),
+            tier: 2,
+        };
+
+        // blocks that don't need anything break the same with anything
+        let dirt = break_time(1.0, None, Some(&pickaxe(5)));
+        assert_eq!(dirt, time(1.0, true));
+
+        let by_hand = break_time(1.0, Some(&requirement), None);
+        assert_eq!(by_hand, time(3.0, false));
+
+        // the right class of tool is faster, but too low of a tier doesn't drop
+        let too_low = break_time(1.0, Some(&requirement), Some(&pickaxe(1)));
+        assert_eq!(too_low, time(1.5, false));
+
+        let good = break_time(1.0, Some(&requirement), Some(&pickaxe(2)));
+        assert_eq!(good, time(0.5, true));
+
+        let shovel = ToolProperties {
+            class: "shovel".into(),
+            ..pickaxe(9)
+        };
+        let wrong = break_time(1.0, Some(&requirement), Some(&shovel));
+        assert_eq!(wrong, time(3.0, false));
+    }
+}
@@ -1,6 +1,6 @@
-use crate::{aabb::Aabb, world::BlockPos};
-use bevy_app::{AppExit, EventWriter};
-use bevy_ecs::prelude::In;
+use crate::{aabb::Aabb, notification::Notify, world::BlockPos};
+use bevy_app::{AppExit, EventWriter, Events};
+use bevy_ecs::prelude::{In, Local, ResMut};
 use nalgebra::{point, vector, Point3, Vector3};
 use std::{cmp::Ordering, fmt::Display};
 
@@ -156,14 +156,22 @@ pub fn block_aabb(block: BlockPos) -> Aabb {
     }
 }
 
-pub fn handle_error_internal<T, E>(In(res): In<Result<T, E>>, mut exit: EventWriter<AppExit>)
-where
+/// the error is also shown to the player, when something is around to show
+/// [`Notify`] events.
+pub fn handle_error_internal<T, E>(
+    In(res): In<Result<T, E>>,
+    notify_events: Option<ResMut<Events<Notify>>>,
+    mut exit: EventWriter<AppExit>,
+) where
     E: Display,
 {
     match res {
         Ok(_) => {}
         Err(err) => {
             log::error!("{}", err);
+            if let Some(mut notify_events) = notify_events {
+                notify_events.send(Notify::error(err.to_string()));
+            }
             exit.send(AppExit);
         }
     }
@@ -178,3 +186,43 @@ macro_rules! try_system {
 }
 
 pub use try_system;
+
+/// like [`handle_error_internal`], but keeps the game running. the same error
+/// happening over and over is only reported once, so that a system failing
+/// every frame doesn't flood the log.
+pub fn handle_recoverable_error_internal<T, E>(
+    In(res): In<Result<T, E>>,
+    mut last_error: Local<Option<String>>,
+    notify_events: Option<ResMut<Events<Notify>>>,
+) where
+    E: Display,
+{
+    match res {
+        Ok(_) => *last_error = None,
+        Err(err) => {
+            let message = err.to_string();
+            if last_error.as_ref() == Some(&message) {
+                return;
+            }
+
+            log::error!("{}", message);
+            if let Some(mut notify_events) = notify_events {
+                notify_events.send(Notify::error(message.clone()));
+            }
+            *last_error = Some(message);
+        }
+    }
+}
+
+/// like [`try_system!`], but errors only skip the rest of the system for this
+/// run instead of exiting the game. useful for systems that run every frame,
+/// where giving up on one frame is better than giving up on the game.
+#[macro_export]
+macro_rules! recoverable_system {
+    ($sys:expr) => {
+        $sys.system()
+            .chain($crate::util::handle_recoverable_error_internal.system())
+    };
+}
+
+pub use recoverable_system;
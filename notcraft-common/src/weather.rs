@@ -0,0 +1,262 @@
+//! the world's weather, which drifts between clear skies and precipitation
+//! over time.
+//!
+//! weather is only simulated here. how it looks is up to the client, and other
+//! systems can react to it changing through [`WeatherChangedEvent`].
+
+use crate::{
+    clock::GameTime,
+    game_state::playing,
+    lang::tr,
+    notification::Notify,
+    prelude::*,
+    world::persistence::{SaveEvent, WorldPersistence},
+};
+use rand::Rng;
+use std::str::FromStr;
+
+/// how long it takes to fade from one kind of weather to the next, in seconds.
+pub const WEATHER_TRANSITION_SECONDS: f32 = 20.0;
+
+/// how often the weather is written out to the save, in seconds. it's also
+/// saved whenever it changes.
+const WEATHER_SAVE_INTERVAL: f32 = 30.0;
+
+/// the chance that precipitation falls as snow instead of rain.
+const SNOW_CHANCE: f64 = 0.3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WeatherKind {
+    Clear,
+    Rain,
+    Snow,
+}
+
+impl WeatherKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            WeatherKind::Clear => "clear",
+            WeatherKind::Rain => "rain",
+            WeatherKind::Snow => "snow",
+        }
+    }
+
+    pub fn is_precipitation(self) -> bool {
+        self != WeatherKind::Clear
+    }
+
+    /// picks how long this kind of weather lasts, in seconds.
+    fn random_duration(self) -> f32 {
+        let (min, max) = match self {
+            WeatherKind::Clear => (600.0, 1200.0),
+            WeatherKind::Rain | WeatherKind::Snow => (180.0, 480.0),
+        };
+        rand::thread_rng().gen_range(min, max)
+    }
+}
+
+impl FromStr for WeatherKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "clear" => Self::Clear,
+            "rain" => Self::Rain,
+            "snow" => Self::Snow,
+            other => bail!("unknown weather '{}'", other),
+        })
+    }
+}
+
+/// the weather as it's stored in a save.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SavedWeather {
+    pub kind: WeatherKind,
+    /// how much longer the weather lasts, in seconds.
+    pub remaining: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Weather {
+    current: WeatherKind,
+    previous: WeatherKind,
+    /// how far along the fade from `previous` to `current` is, from 0 to 1.
+    transition: f32,
+    /// seconds until the weather changes on its own.
+    remaining: f32,
+}
+
+impl Weather {
+    fn from_saved(saved: SavedWeather) -> Self {
+        Self {
+            current: saved.kind,
+            previous: saved.kind,
+            transition: 1.0,
+            remaining: saved.remaining,
+        }
+    }
+
+    fn saved(&self) -> SavedWeather {
+        SavedWeather {
+            kind: self.current,
+            remaining: self.remaining,
+        }
+    }
+
+    /// the weather that's fading in, or that's already fully in place.
+    pub fn current(&self) -> WeatherKind {
+        self.current
+    }
+
+    /// how strongly `kind` is showing right now, from 0 to 1. during a
+    /// transition, both the outgoing and incoming weather show partially.
+    pub fn strength(&self, kind: WeatherKind) -> f32 {
+        let mut strength = 0.0;
+        if self.current == kind {
+            strength += self.transition;
+        }
+        if self.previous == kind {
+            strength += 1.0 - self.transition;
+        }
+        strength
+    }
+
+    /// how much of any kind of precipitation is falling, from 0 to 1.
+    pub fn precipitation(&self) -> f32 {
+        1.0 - self.strength(WeatherKind::Clear)
+    }
+
+    /// starts fading into `kind`, which then lasts for `duration` seconds.
+    pub fn set(&mut self, kind: WeatherKind, duration: f32) {
+        if kind != self.current {
+            self.previous = self.current;
+            self.transition = 0.0;
+        }
+        self.current = kind;
+        self.remaining = duration;
+    }
+
+    /// starts fading into `kind`, which then lasts for a random amount of
+    /// time.
+    pub fn set_random_duration(&mut self, kind: WeatherKind) {
+        self.set(kind, kind.random_duration());
+    }
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Self::from_saved(SavedWeather {
+            kind: WeatherKind::Clear,
+            remaining: WeatherKind::Clear.random_duration(),
+        })
+    }
+}
+
+/// sent whenever the weather starts changing, including changes made by hand.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WeatherChangedEvent {
+    pub previous: WeatherKind,
+    pub current: WeatherKind,
+}
+
+fn pick_next_weather(current: WeatherKind) -> WeatherKind {
+    let mut rng = rand::thread_rng();
+    match current {
+        WeatherKind::Clear if rng.gen_bool(SNOW_CHANCE) => WeatherKind::Snow,
+        WeatherKind::Clear => WeatherKind::Rain,
+        WeatherKind::Rain | WeatherKind::Snow => WeatherKind::Clear,
+    }
+}
+
+fn advance_weather(time: Res<GameTime>, mut weather: ResMut<Weather>) {
+    let dt = time.delta_seconds();
+    weather.transition = f32::min(1.0, weather.transition + dt / WEATHER_TRANSITION_SECONDS);
+    weather.remaining -= dt;
+
+    if weather.remaining <= 0.0 {
+        let next = pick_next_weather(weather.current);
+        weather.set_random_duration(next);
+    }
+}
+
+fn notify_weather_changes(
+    weather: Res<Weather>,
+    mut last_kind: Local<Option<WeatherKind>>,
+    mut events: EventWriter<WeatherChangedEvent>,
+) {
+    let current = weather.current();
+    match *last_kind {
+        Some(previous) if previous != current => {
+            log::info!(
+                "weather changing from {} to {}",
+                previous.name(),
+                current.name()
+            );
+            events.send(WeatherChangedEvent { previous, current });
+        }
+        _ => {}
+    }
+    *last_kind = Some(current);
+}
+
+fn save_weather(
+    time: Res<GameTime>,
+    weather: Res<Weather>,
+    mut since_save: Local<f32>,
+    mut last_saved: Local<Option<WeatherKind>>,
+    mut persistence: ResMut<WorldPersistence>,
+    mut save_events: EventReader<SaveEvent>,
+    mut notify_events: EventWriter<Notify>,
+) {
+    *since_save += time.delta_seconds();
+    let changed = *last_saved != Some(weather.current());
+    let requested = save_events.iter().count() > 0;
+    if !changed && !requested && *since_save < WEATHER_SAVE_INTERVAL {
+        return;
+    }
+
+    *since_save = 0.0;
+    *last_saved = Some(weather.current());
+    let saved = weather.saved();
+    let result = persistence.update_metadata(|meta| meta.weather = Some(saved));
+    match result {
+        Err(err) => {
+            log::error!("failed to save weather: {}", err);
+            notify_events.send(Notify::error(tr!("notify.save_failed", err)));
+        }
+        // only say something when the save was asked for, rather than every
+        // time the weather gets saved in the background
+        Ok(()) if requested && persistence.save_dir().is_some() => {
+            notify_events.send(Notify::info(tr!("notify.world_saved")));
+        }
+        Ok(()) => {}
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WeatherPlugin {}
+
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let saved = app
+            .world()
+            .get_resource::<WorldPersistence>()
+            .expect("`WeatherPlugin` added before `WorldPlugin`! (no `WorldPersistence` resource exists)")
+            .metadata()
+            .weather;
+        app.insert_resource(saved.map(Weather::from_saved).unwrap_or_default());
+
+        app.add_event::<WeatherChangedEvent>();
+        app.add_system(
+            advance_weather
+                .system()
+                .label(WeatherLabel)
+                .with_run_criteria(playing.system()),
+        );
+        app.add_system(notify_weather_changes.system().after(WeatherLabel));
+        app.add_system(save_weather.system().after(WeatherLabel));
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+struct WeatherLabel;
@@ -0,0 +1,104 @@
+//! what blocks do when players interact with them.
+//!
+//! which behavior a block has is set by its `interaction` property in the
+//! registry. most behaviors switch the block between its states, which the
+//! registry gives each their own block ID, so changing a block's state is just
+//! a matter of setting the block to a different ID.
+
+use super::{chunk::ChunkAccess, BlockPos};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// how far up and down a column of blocks toggles along with the one that was
+/// clicked, so that one door can't toggle a whole tower of them.
+const MAX_COLUMN_REACH: i32 = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlockInteraction {
+    /// moves the block on to its next state, like opening a trapdoor.
+    Toggle,
+    /// like `Toggle`, but also toggles the same block stacked directly above
+    /// and below it, as long as they're in the same state. doors are made of
+    /// two blocks, and both halves should open together.
+    ToggleColumn,
+}
+
+/// right-clicks the block at `pos`. returns whether the block did anything, in
+/// which case nothing else should happen because of the click, like placing a
+/// block against it.
+pub fn interact(access: &mut ChunkAccess, pos: BlockPos) -> bool {
+    let registry = Arc::clone(access.registry());
+    let id = match access.block(pos) {
+        Some(id) => id,
+        None => return false,
+    };
+    let block = registry.get(id);
+    let next = block.next_state();
+
+    match block.interaction() {
+        None => return false,
+        Some(BlockInteraction::Toggle) => access.set_block(pos, next),
+        Some(BlockInteraction::ToggleColumn) => {
+            access.set_block(pos, next);
+            for direction in [1, -1] {
+                for offset in 1..=MAX_COLUMN_REACH {
+                    let pos = pos.offset([0, direction * offset, 0]);
+                    if access.block(pos) != Some(id) {
+                        break;
+                    }
+                    access.set_block(pos, next);
+                }
+            }
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        chunk::{self, ArrayChunk},
+        registry::AIR_BLOCK,
+        test_util::{section_world, test_registry},
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn doors_open_together() {
+        let registry = test_registry();
+        let closed = registry.lookup("door");
+        let open = registry.try_lookup_state("door", "open").unwrap();
+        assert_eq!(registry.get(closed).state_name(), Some("closed"));
+        assert_eq!(registry.get(open).next_state(), closed);
+        assert!(registry.get(open).same_block(closed));
+        assert_ne!(
+            registry.get(closed).collision_shape(),
+            registry.get(open).collision_shape()
+        );
+
+        // a two block tall door, with an open door on top that shouldn't be
+        // toggled along with it
+        let mut blocks = ArrayChunk::homogeneous(AIR_BLOCK);
+        blocks[[4, 4, 4]] = closed;
+        blocks[[4, 5, 4]] = closed;
+        blocks[[4, 6, 4]] = open;
+        blocks[[8, 4, 4]] = registry.lookup("stone");
+
+        let world = section_world(&registry, blocks);
+
+        let mut access = ChunkAccess::new(&world);
+        let block = |x, y, z| BlockPos { x, y, z };
+        assert!(interact(&mut access, block(4, 5, 4)));
+        assert!(!interact(&mut access, block(8, 4, 4)));
+        assert!(!interact(&mut access, block(0, 0, 0)));
+        chunk::write_all_chunk_updates(&mut access, &mut HashMap::new(), &mut HashMap::new());
+
+        assert_eq!(access.block(block(4, 4, 4)), Some(open));
+        assert_eq!(access.block(block(4, 5, 4)), Some(open));
+        assert_eq!(access.block(block(4, 6, 4)), Some(open));
+        assert_eq!(access.block(block(8, 4, 4)), Some(registry.lookup("stone")));
+    }
+}
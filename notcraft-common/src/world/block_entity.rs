@@ -0,0 +1,216 @@
+//! extra data attached to individual blocks, for the few kinds of blocks that
+//! need more than just their ID, like the text written on a sign or the items
+//! kept in a chest.
+//!
+//! which kind of block entity a block has is set by its `block-entity`
+//! property in the registry. block entities are kept alongside the rest of a
+//! section's data, in a sparse map from positions in the section to whatever
+//! is stored there. a block gets a fresh block entity whenever it's placed,
+//! and loses it when it's replaced, so block entities always match the block
+//! they're attached to.
+
+use super::chunk::{ChunkSectionIndex, CHUNK_LENGTH};
+use crate::{
+    codec::{
+        decode::Node,
+        encode::{Encode, Encoder},
+        NodeKind,
+    },
+    inventory::Inventory,
+    prelude::*,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// signs can't hold any more characters than this.
+pub const MAX_SIGN_TEXT_LENGTH: usize = 24;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlockEntityKind {
+    Sign,
+    Chest,
+}
+
+impl BlockEntityKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sign => "sign",
+            Self::Chest => "chest",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlockEntity {
+    Sign(SignData),
+    Chest(Inventory),
+}
+
+impl BlockEntity {
+    /// what a block with this kind of block entity starts out with when it's
+    /// placed.
+    pub fn new(kind: BlockEntityKind) -> Self {
+        match kind {
+            BlockEntityKind::Sign => Self::Sign(SignData::default()),
+            BlockEntityKind::Chest => Self::Chest(Inventory::default()),
+        }
+    }
+
+    pub fn kind(&self) -> BlockEntityKind {
+        match self {
+            Self::Sign(_) => BlockEntityKind::Sign,
+            Self::Chest(_) => BlockEntityKind::Chest,
+        }
+    }
+
+    fn from_node(node: &Node) -> Result<Self> {
+        match node.get("kind").and_then(Node::as_str) {
+            Some("sign") => match node.get("text").and_then(Node::as_str) {
+                Some(text) => Ok(Self::Sign(SignData::new(text)?)),
+                None => bail!("sign is missing its text"),
+            },
+            Some("chest") => match node.get("items") {
+                Some(items) => Ok(Self::Chest(Inventory::from_node(items)?)),
+                None => bail!("chest is missing its items"),
+            },
+            Some(other) => bail!("unknown block entity kind '{}'", other),
+            None => bail!("block entity is missing its kind"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SignData {
+    text: String,
+}
+
+impl SignData {
+    pub fn new(text: &str) -> Result<Self> {
+        let len = text.chars().count();
+        if len > MAX_SIGN_TEXT_LENGTH {
+            bail!(
+                "sign text is {} characters long, but signs only fit {}",
+                len,
+                MAX_SIGN_TEXT_LENGTH
+            );
+        }
+        Ok(Self { text: text.into() })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// every block entity in a single section.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockEntities {
+    entities: BTreeMap<ChunkSectionIndex, BlockEntity>,
+}
+
+impl BlockEntities {
+    pub fn get(&self, index: ChunkSectionIndex) -> Option<&BlockEntity> {
+        self.entities.get(&index)
+    }
+
+    pub fn insert(&mut self, index: ChunkSectionIndex, entity: BlockEntity) {
+        self.entities.insert(index, entity);
+    }
+
+    pub fn remove(&mut self, index: ChunkSectionIndex) -> Option<BlockEntity> {
+        self.entities.remove(&index)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ChunkSectionIndex, &BlockEntity)> + '_ {
+        self.entities.iter().map(|(&index, entity)| (index, entity))
+    }
+
+    pub fn from_node(node: &Node) -> Result<Self> {
+        let list = match node.as_list() {
+            Some(list) => list,
+            None => bail!("block entities are not a list"),
+        };
+
+        let mut entities = BTreeMap::new();
+        for entry in list {
+            let coord = |key| match entry.get(key).and_then(Node::as_unsigned) {
+                Some(value) if value < CHUNK_LENGTH as u128 => Ok(value as usize),
+                _ => bail!("block entity has a bad '{}' coordinate", key),
+            };
+            let index = [coord("x")?, coord("y")?, coord("z")?];
+            if entities
+                .insert(index, BlockEntity::from_node(entry)?)
+                .is_some()
+            {
+                bail!("more than one block entity at {:?}", index);
+            }
+        }
+
+        Ok(Self { entities })
+    }
+}
+
+struct PlacedBlockEntity<'a>(ChunkSectionIndex, &'a BlockEntity);
+
+impl<W: std::io::Write> Encode<W> for PlacedBlockEntity<'_> {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        let PlacedBlockEntity([x, y, z], entity) = self;
+        encoder.encode_map(|mut map| {
+            map.entry("x").encode(x)?;
+            map.entry("y").encode(y)?;
+            map.entry("z").encode(z)?;
+            map.entry("kind").encode(entity.kind().name())?;
+            match entity {
+                BlockEntity::Sign(sign) => map.entry("text").encode(sign.text())?,
+                BlockEntity::Chest(items) => map.entry("items").encode(items)?,
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<W: std::io::Write> Encode<W> for BlockEntities {
+    const KIND: NodeKind = NodeKind::List;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        let placed = self
+            .entities
+            .iter()
+            .map(|(&index, entity)| PlacedBlockEntity(index, entity));
+        encoder.encode_verbatim_list(placed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{decode::decode_node, encode::encode_node};
+
+    #[test]
+    fn block_entities_round_trip() {
+        let mut entities = BlockEntities::default();
+        let sign = |text| BlockEntity::Sign(SignData::new(text).unwrap());
+        entities.insert([0, 0, 0], sign("hello"));
+        entities.insert([31, 4, 17], sign(""));
+        entities.insert([2, 31, 9], sign("fresh water ->"));
+        let mut items = Inventory::default();
+        items.add("stone", 12);
+        items.add("ladder", 1);
+        entities.insert([5, 5, 5], BlockEntity::Chest(items));
+        entities.insert([5, 6, 5], BlockEntity::new(BlockEntityKind::Chest));
+
+        let mut bytes = vec![];
+        encode_node(&mut bytes, &entities).unwrap();
+        let decoded = decode_node(&mut &bytes[..]).unwrap();
+        assert_eq!(BlockEntities::from_node(&decoded).unwrap(), entities);
+
+        assert!(SignData::new(&"a".repeat(MAX_SIGN_TEXT_LENGTH + 1)).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{
+    aabb::Aabb,
     codec::{
         encode::{Encode, Encoder},
         NodeKind,
@@ -6,6 +7,7 @@ use crate::{
     debug::send_debug_event,
     prelude::*,
     world::{
+        block_entity::{BlockEntities, BlockEntity},
         lighting::{propagate_block_light, propagate_sky_light, LightUpdateQueues},
         registry::BlockId,
     },
@@ -14,7 +16,7 @@ use crate::{
 use nalgebra::Point3;
 use parking_lot::Mutex;
 use std::{
-    collections::{hash_map::Entry, HashMap, HashSet},
+    collections::{hash_map::Entry, HashMap},
     ops::{Index, IndexMut},
     sync::{
         atomic::{AtomicBool, Ordering as AtomicOrdering},
@@ -27,6 +29,7 @@ use super::{
     lighting::{LightValue, SkyLightColumns, FULL_SKY_LIGHT},
     orphan::{Orphan, OrphanSnapshot, OrphanWriter},
     registry::BlockRegistry,
+    repro::WriteScope,
     BlockPos, ChunkPos, VoxelWorld,
 };
 
@@ -42,6 +45,7 @@ pub struct ChunkSectionInner {
     pos: ChunkSectionPos,
     block_data: ChunkData<BlockId>,
     light_data: ChunkData<LightValue>,
+    block_entities: BlockEntities,
 }
 
 #[derive(Clone)]
@@ -66,6 +70,10 @@ impl ChunkSectionSnapshot {
         &self.inner.light_data
     }
 
+    pub fn block_entities(&self) -> &BlockEntities {
+        &self.inner.block_entities
+    }
+
     /// See [`OrphanSnapshot::is_orphaned`]
     pub fn is_orphaned(&self) -> bool {
         self.inner.is_orphaned()
@@ -101,6 +109,14 @@ impl ChunkSectionSnapshotMut {
         &mut self.inner.light_data
     }
 
+    pub fn block_entities(&self) -> &BlockEntities {
+        &self.inner.block_entities
+    }
+
+    pub fn block_entities_mut(&mut self) -> &mut BlockEntities {
+        &mut self.inner.block_entities
+    }
+
     pub fn was_cloned(&self) -> bool {
         OrphanWriter::was_cloned(&self.inner)
     }
@@ -178,20 +194,19 @@ impl Chunk {
             })
     }
 
-    pub fn unload_section(&self, y: i32) -> Arc<ChunkSection> {
-        match self.sections.orphan_readers().remove(&y) {
-            Some(section) => {
-                self.unloaded_modified_sections
-                    .orphan_readers()
-                    .insert(y, Arc::clone(&section));
-                section
-            }
-            None => panic!(
-                "tried to unload section y{y} from chunk x{x} z{z}, which was not loaded!",
-                x = self.pos.x,
-                z = self.pos.z
-            ),
-        }
+    /// sections of this chunk that were unloaded while the chunk itself stayed
+    /// loaded.
+    pub fn unloaded_sections(&self) -> OrphanSnapshot<HashMap<i32, Arc<ChunkSection>>> {
+        self.unloaded_modified_sections.snapshot()
+    }
+
+    /// unloads a section, returning `None` if it wasn't loaded.
+    pub fn unload_section(&self, y: i32) -> Option<Arc<ChunkSection>> {
+        let section = self.sections.orphan_readers().remove(&y)?;
+        self.unloaded_modified_sections
+            .orphan_readers()
+            .insert(y, Arc::clone(&section));
+        Some(section)
     }
 
     pub fn is_empty(&self) -> bool {
@@ -245,6 +260,7 @@ impl ChunkSection {
             pos,
             block_data,
             light_data,
+            block_entities: Default::default(),
         });
 
         Self {
@@ -264,6 +280,7 @@ impl ChunkSection {
             pos,
             block_data,
             light_data,
+            block_entities: Default::default(),
         });
 
         Self {
@@ -282,9 +299,21 @@ impl ChunkSection {
         ChunkSectionSnapshot::new(self.inner.snapshot())
     }
 
+    /// like [`ChunkSection::snapshot`], but gives up instead of waiting for the
+    /// section to finish being written to.
+    pub fn try_snapshot(&self) -> Option<ChunkSectionSnapshot> {
+        self.inner.try_snapshot().map(ChunkSectionSnapshot::new)
+    }
+
     pub fn needs_persistence(&self) -> bool {
         self.needs_persistence.load(AtomicOrdering::Relaxed)
     }
+
+    /// clears the flag that says the section changed since it was last saved,
+    /// returning whether it was set.
+    pub fn take_needs_persistence(&self) -> bool {
+        self.needs_persistence.swap(false, AtomicOrdering::Relaxed)
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
@@ -299,12 +328,90 @@ pub struct ChunkSectionUpdate {
     pub id: BlockId,
 }
 
+/// the blocks of a section whose part of the section's mesh might have changed,
+/// as an inclusive box of offsets into the section. that's every block that
+/// changed, along with the blocks right next to them, since meshing looks at
+/// neighboring blocks for culling, AO, and smooth lighting.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct DirtyRegion {
+    pub min: ChunkSectionIndex,
+    pub max: ChunkSectionIndex,
+}
+
+impl DirtyRegion {
+    /// every block in the section.
+    pub const ALL: DirtyRegion = DirtyRegion {
+        min: [0; 3],
+        max: [CHUNK_LENGTH - 1; 3],
+    };
+
+    /// the part of `section` that can see the block at `pos`, if any of it
+    /// can.
+    fn around(section: ChunkSectionPos, pos: BlockPos) -> Option<Self> {
+        let origin = section.origin();
+        let offset = [pos.x - origin.x, pos.y - origin.y, pos.z - origin.z];
+        let min = offset.map(|n| i32::max(n - 1, 0));
+        let max = offset.map(|n| i32::min(n + 1, CHUNK_LENGTH as i32 - 1));
+        let touches = (0..3).all(|axis| min[axis] <= max[axis]);
+        touches.then(|| DirtyRegion {
+            min: min.map(|n| n as usize),
+            max: max.map(|n| n as usize),
+        })
+    }
+
+    /// grows the region to cover `other` too.
+    pub fn include(&mut self, other: DirtyRegion) {
+        for axis in 0..3 {
+            self.min[axis] = usize::min(self.min[axis], other.min[axis]);
+            self.max[axis] = usize::max(self.max[axis], other.max[axis]);
+        }
+    }
+
+    pub fn contains(&self, index: ChunkSectionIndex) -> bool {
+        (0..3).all(|axis| (self.min[axis]..=self.max[axis]).contains(&index[axis]))
+    }
+}
+
+/// the sections whose meshes need to be rebuilt, along with the part of each
+/// of them that changed.
+pub type DirtySections = HashMap<ChunkSectionPos, DirtyRegion>;
+
 struct ChunkUpdateContext<'a> {
-    pub rebuild: &'a mut HashSet<ChunkSectionPos>,
+    pub rebuild: &'a mut DirtySections,
     pub block_updates: &'a mut HashMap<BlockPos, BlockUpdate>,
     pub light_queues: &'a mut LightUpdateQueues,
     pub registry: &'a BlockRegistry,
     pub chunk: ChunkPos,
+    /// blocks in the section being written that were actually changed to
+    /// something else, and what they were changed to.
+    pub replaced: Vec<(ChunkSectionIndex, BlockId)>,
+}
+
+fn mark_dirty(rebuild: &mut DirtySections, section: ChunkSectionPos, region: DirtyRegion) {
+    rebuild
+        .entry(section)
+        .and_modify(|dirty| dirty.include(region))
+        .or_insert(region);
+}
+
+/// flags every section whose mesh can depend on the block at `pos`. that's the
+/// section it's in, along with any neighbors within a block of it, since
+/// meshing looks past the edges of a section for culling, AO, and smooth
+/// lighting. blocks on the edges and corners of a section touch the diagonal
+/// neighbors too, but neighbors only get the layer of blocks along their
+/// border marked as dirty.
+fn flag_for_rebuild(rebuild: &mut DirtySections, pos: BlockPos) {
+    let (section, _) = pos.section_and_offset();
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            for dz in -1..=1 {
+                let neighbor = section.offset([dx, dy, dz]);
+                if let Some(region) = DirtyRegion::around(neighbor, pos) {
+                    mark_dirty(rebuild, neighbor, region);
+                }
+            }
+        }
+    }
 }
 
 fn write_section_updates_array(
@@ -313,18 +420,8 @@ fn write_section_updates_array(
     y: i32,
     updates: &[ChunkSectionUpdate],
 ) {
-    const MAX_AXIS_INDEX: usize = CHUNK_LENGTH - 1;
-
     let pos = ctx.chunk.section(y);
 
-    let mut c = false;
-    let mut nx = false;
-    let mut px = false;
-    let mut ny = false;
-    let mut py = false;
-    let mut nz = false;
-    let mut pz = false;
-
     for update in updates.iter() {
         let slot = &mut data[update.index];
 
@@ -334,41 +431,11 @@ fn write_section_updates_array(
                     old_id: *slot,
                     new_id: update.id,
                 });
-            c = true;
-            nx |= update.index[0] == 0;
-            px |= update.index[0] == MAX_AXIS_INDEX;
-            ny |= update.index[1] == 0;
-            py |= update.index[1] == MAX_AXIS_INDEX;
-            nz |= update.index[2] == 0;
-            pz |= update.index[2] == MAX_AXIS_INDEX;
+            flag_for_rebuild(ctx.rebuild, index_to_block(pos, update.index));
+            ctx.replaced.push((update.index, update.id));
             *slot = update.id;
         }
     }
-
-    if c {
-        ctx.rebuild.insert(pos);
-    }
-
-    if nx {
-        ctx.rebuild.insert(pos.offset([-1, 0, 0]));
-    }
-    if px {
-        ctx.rebuild.insert(pos.offset([1, 0, 0]));
-    }
-
-    if ny {
-        ctx.rebuild.insert(pos.offset([0, -1, 0]));
-    }
-    if py {
-        ctx.rebuild.insert(pos.offset([0, 1, 0]));
-    }
-
-    if nz {
-        ctx.rebuild.insert(pos.offset([0, 0, -1]));
-    }
-    if pz {
-        ctx.rebuild.insert(pos.offset([0, 0, 1]));
-    }
 }
 
 fn write_section_block_updates(
@@ -404,6 +471,9 @@ fn write_chunk_updates_to_section(
     ctx: &mut ChunkUpdateContext,
 ) {
     assert!(!updates.is_empty());
+    // if anything in here panics, the panic hook saves this write so that it
+    // can be looked at later. see `super::repro`.
+    let capsule = WriteScope::enter(&access.world, chunk.pos(), updates);
     let _updating = chunk
         .updating
         .try_lock()
@@ -413,12 +483,19 @@ fn write_chunk_updates_to_section(
 
     let registry = Arc::clone(access.registry());
 
-    write_section_block_updates(
-        access.section(chunk.pos()).unwrap().blocks_mut(),
-        ctx,
-        chunk.pos().y,
-        updates,
-    );
+    let section = access.section(chunk.pos()).unwrap();
+    capsule.keep_section(section.blocks(), section.light());
+    write_section_block_updates(section.blocks_mut(), ctx, chunk.pos().y, updates);
+
+    // replaced blocks lose whatever was attached to them, and new blocks start
+    // out with a fresh block entity.
+    for (index, id) in ctx.replaced.drain(..) {
+        let entities = section.block_entities_mut();
+        match registry.get(id).block_entity() {
+            Some(kind) => entities.insert(index, BlockEntity::new(kind)),
+            None => drop(entities.remove(index)),
+        }
+    }
 
     #[cfg(feature = "debug")]
     match access.section(chunk.pos()).unwrap().was_cloned() {
@@ -440,7 +517,7 @@ fn write_chunk_updates_to_chunk(
     chunk: &Chunk,
     updates: &HashMap<i32, Vec<ChunkSectionUpdate>>,
     access: &mut MutableChunkAccess,
-    rebuild: &mut HashSet<ChunkSectionPos>,
+    rebuild: &mut DirtySections,
     block_updates: &mut HashMap<BlockPos, BlockUpdate>,
 ) {
     assert!(!updates.is_empty());
@@ -454,6 +531,7 @@ fn write_chunk_updates_to_chunk(
         light_queues: &mut light_queues,
         registry: &registry,
         chunk: chunk.pos(),
+        replaced: vec![],
     };
 
     for (&y, updates) in updates.iter() {
@@ -465,6 +543,12 @@ fn write_chunk_updates_to_chunk(
 
     let mut updated_sky_columns: HashMap<[usize; 2], HashMap<i32, bool>> = HashMap::default();
     for (&pos, update) in ctx.block_updates.iter() {
+        // block updates are collected for every chunk written in this batch, so we
+        // have to skip the ones that belong to other chunks' columns.
+        if ChunkSectionPos::from(pos).column() != ctx.chunk {
+            continue;
+        }
+
         let old_solid = !ctx.registry.get(update.old_id).light_transmissible();
         let new_solid = !ctx.registry.get(update.new_id).light_transmissible();
         if old_solid != new_solid {
@@ -504,9 +588,27 @@ fn write_chunk_updates_to_chunk(
     propagate_sky_light(&mut ctx.light_queues, access);
 }
 
+fn write_block_entity(access: &mut MutableChunkAccess, pos: BlockPos, entity: BlockEntity) {
+    let (section_pos, index) = pos.section_and_offset();
+    let registry = Arc::clone(access.registry());
+    let (section, writer) = match (access.world.section(section_pos), access.section(section_pos)) {
+        (Some(section), Some(writer)) => (section, writer),
+        _ => return log::warn!("dropped block entity in unloaded section {:?}", section_pos),
+    };
+
+    let block = registry.get(writer.blocks().get(index));
+    if block.block_entity() != Some(entity.kind()) {
+        let kind = entity.kind().name();
+        return log::warn!("dropped {} block entity for {} at {:?}", kind, block.name(), pos);
+    }
+
+    writer.block_entities_mut().insert(index, entity);
+    section.needs_persistence.store(true, AtomicOrdering::Relaxed);
+}
+
 pub(crate) fn write_all_chunk_updates(
     access: &mut ChunkAccess,
-    rebuild: &mut HashSet<ChunkSectionPos>,
+    rebuild: &mut DirtySections,
     block_updates: &mut HashMap<BlockPos, BlockUpdate>,
 ) {
     #[cfg(feature = "debug")]
@@ -537,7 +639,69 @@ pub(crate) fn write_all_chunk_updates(
         access.free_update_queues.extend(updates.into_values());
     }
 
-    rebuild.extend(mut_access.rebuild);
+    // block entities are written after blocks, so that a block and its block
+    // entity can be set at the same time.
+    for (pos, entity) in access.block_entity_updates.drain(..) {
+        write_block_entity(&mut mut_access, pos, entity);
+    }
+
+    for (pos, region) in mut_access.rebuild {
+        mark_dirty(rebuild, pos, region);
+    }
+}
+
+/// throws away all the light in the section at `pos`, and works it out again
+/// from the blocks in it and the light around it. light that spread out of the
+/// section is redone too, so that stale light can't leak back in from its
+/// neighbors. returns false if the section isn't loaded.
+pub(crate) fn relight_section(
+    access: &mut ChunkAccess,
+    pos: ChunkSectionPos,
+    rebuild: &mut DirtySections,
+) -> bool {
+    let (chunk, section) = match access.world.chunk(pos.column()) {
+        Some(chunk) => match chunk.section(pos.y) {
+            Some(section) => (chunk, section),
+            None => return false,
+        },
+        None => return false,
+    };
+
+    // same as with block updates, our own snapshots would force an orphan
+    access.sections.clear();
+    let mut mut_access = MutableChunkAccess::new(&access.world);
+    let _updating = section
+        .updating
+        .try_lock()
+        .expect("chunk section update not exclusive");
+    let persist = &section.needs_persistence;
+    persist.store(true, AtomicOrdering::Relaxed);
+
+    let registry = Arc::clone(access.registry());
+    let sky_nodes = chunk.sky_light();
+    let mut queues = LightUpdateQueues::default();
+    for x in 0..CHUNK_LENGTH {
+        for z in 0..CHUNK_LENGTH {
+            let top = sky_nodes.node(x, z).top();
+            for y in 0..CHUNK_LENGTH {
+                let block_pos = index_to_block(pos, [x, y, z]);
+                let block = registry.get(mut_access.block(block_pos).unwrap());
+                let sky = match block.light_transmissible() && block_pos.y >= top {
+                    true => 15,
+                    false => 0,
+                };
+                queues.queue_relight(&mut mut_access, block_pos, sky, block.block_light());
+            }
+        }
+    }
+
+    propagate_block_light(&mut queues, &mut mut_access);
+    propagate_sky_light(&mut queues, &mut mut_access);
+
+    for (pos, region) in mut_access.rebuild {
+        mark_dirty(rebuild, pos, region);
+    }
+    true
 }
 
 // TODO: maybe think about splitting this into a read half and a write half, so
@@ -549,6 +713,7 @@ pub struct ChunkAccess {
 
     free_update_queues: Vec<Vec<ChunkSectionUpdate>>,
     chunk_updates: HashMap<ChunkPos, HashMap<i32, Vec<ChunkSectionUpdate>>>,
+    block_entity_updates: Vec<(BlockPos, BlockEntity)>,
 }
 
 impl ChunkAccess {
@@ -558,6 +723,7 @@ impl ChunkAccess {
             sections: Default::default(),
             free_update_queues: Default::default(),
             chunk_updates: Default::default(),
+            block_entity_updates: Default::default(),
         }
     }
 
@@ -584,6 +750,21 @@ impl ChunkAccess {
         Some(self.section(section_pos)?.light().get(chunk_index))
     }
 
+    /// the block entity attached to the block at `pos`, if it has one.
+    pub fn block_entity(&mut self, pos: BlockPos) -> Option<&BlockEntity> {
+        let (section_pos, chunk_index) = pos.section_and_offset();
+        self.section(section_pos)?
+            .block_entities()
+            .get(chunk_index)
+    }
+
+    /// replaces the block entity at `pos` once updates are written. it's
+    /// dropped if the block there by then doesn't have that kind of block
+    /// entity.
+    pub fn set_block_entity(&mut self, pos: BlockPos, entity: BlockEntity) {
+        self.block_entity_updates.push((pos, entity));
+    }
+
     // TODO: what do we do about updates of chunk sections that don't exist in the
     // world??
     pub fn set_block(&mut self, pos: BlockPos, id: BlockId) {
@@ -599,10 +780,125 @@ impl ChunkAccess {
             id,
         });
     }
+
+    /// copies every block between `min` and `max` (inclusive) into one
+    /// contiguous buffer, looking up each section that the region touches only
+    /// once. this is much cheaper than calling [`ChunkAccess::block`] for each
+    /// block when scanning over a large area.
+    pub fn snapshot_region(&mut self, min: BlockPos, max: BlockPos) -> RegionSnapshot {
+        let region_min = [min.x, min.y, min.z];
+        let region_max = [max.x, max.y, max.z];
+        let size = [0, 1, 2].map(|i| i32::max(0, region_max[i] - region_min[i] + 1) as usize);
+
+        let mut blocks = vec![None; size[0] * size[1] * size[2]];
+        if blocks.is_empty() {
+            return RegionSnapshot { min, size, blocks };
+        }
+
+        let min_section = ChunkSectionPos::from(min);
+        let max_section = ChunkSectionPos::from(max);
+        for sx in min_section.x..=max_section.x {
+            for sy in min_section.y..=max_section.y {
+                for sz in min_section.z..=max_section.z {
+                    let section_pos = ChunkSectionPos::new([sx, sy, sz]);
+                    let section = match self.section(section_pos) {
+                        Some(section) => section,
+                        None => continue,
+                    };
+
+                    // the part of the region that this section covers
+                    let origin = section_pos.origin();
+                    let origin = [origin.x, origin.y, origin.z];
+                    let last = CHUNK_LENGTH as i32 - 1;
+                    let lo = [0, 1, 2].map(|i| i32::max(region_min[i], origin[i]));
+                    let hi = [0, 1, 2].map(|i| i32::min(region_max[i], origin[i] + last));
+
+                    for x in lo[0]..=hi[0] {
+                        for z in lo[2]..=hi[2] {
+                            for y in lo[1]..=hi[1] {
+                                let local = [x - origin[0], y - origin[1], z - origin[2]];
+                                let id = section.blocks().get(local.map(|n| n as usize));
+                                let offset = [x - min.x, y - min.y, z - min.z];
+                                blocks[region_index(size, offset.map(|n| n as usize))] = Some(id);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        RegionSnapshot { min, size, blocks }
+    }
+
+    /// every loaded block whose volume overlaps `aabb`.
+    pub fn iter_blocks_in(&mut self, aabb: &Aabb) -> impl Iterator<Item = (BlockPos, BlockId)> {
+        let min = aabb.min.map(|n| n.floor() as i32);
+        let max = aabb.max.map(|n| n.ceil() as i32 - 1);
+        let min = BlockPos::new([min.x, min.y, min.z]);
+        let max = BlockPos::new([max.x, max.y, max.z]);
+        self.snapshot_region(min, max).into_blocks()
+    }
+}
+
+// regions are laid out the same way as chunk sections, with y varying fastest.
+fn region_index(size: [usize; 3], [x, y, z]: [usize; 3]) -> usize {
+    (x * size[2] + z) * size[1] + y
+}
+
+/// a copy of the blocks in a box-shaped region of the world, made by
+/// [`ChunkAccess::snapshot_region`].
+#[derive(Clone, Debug)]
+pub struct RegionSnapshot {
+    min: BlockPos,
+    size: [usize; 3],
+    // `None` for blocks in sections that weren't loaded
+    blocks: Vec<Option<BlockId>>,
+}
+
+impl RegionSnapshot {
+    pub fn min(&self) -> BlockPos {
+        self.min
+    }
+
+    pub fn size(&self) -> [usize; 3] {
+        self.size
+    }
+
+    /// the block at `pos`, or `None` if it's outside of the region or wasn't
+    /// loaded.
+    pub fn block(&self, pos: BlockPos) -> Option<BlockId> {
+        let offset = [pos.x - self.min.x, pos.y - self.min.y, pos.z - self.min.z];
+        let in_bounds = (0..3).all(|i| offset[i] >= 0 && (offset[i] as usize) < self.size[i]);
+        match in_bounds {
+            true => self.blocks[region_index(self.size, offset.map(|n| n as usize))],
+            false => None,
+        }
+    }
+
+    fn pos_of(&self, index: usize) -> BlockPos {
+        let [_, size_y, size_z] = self.size;
+        let x = index / (size_y * size_z);
+        let z = (index / size_y) % size_z;
+        let y = index % size_y;
+        self.min.offset([x as i32, y as i32, z as i32])
+    }
+
+    /// every loaded block in the region, along with its position.
+    pub fn iter(&self) -> impl Iterator<Item = (BlockPos, BlockId)> + '_ {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(index, id)| Some((self.pos_of(index), (*id)?)))
+    }
+
+    pub fn into_blocks(self) -> impl Iterator<Item = (BlockPos, BlockId)> {
+        (0..self.blocks.len())
+            .filter_map(move |index| Some((self.pos_of(index), self.blocks[index]?)))
+    }
 }
 
 pub struct MutableChunkAccess {
-    rebuild: HashSet<ChunkSectionPos>,
+    rebuild: DirtySections,
     world: Arc<VoxelWorld>,
     writers: HashMap<ChunkSectionPos, ChunkSectionSnapshotMut>,
 }
@@ -643,7 +939,7 @@ impl MutableChunkAccess {
         let prev = block_data.get(chunk_index);
         if id != prev {
             block_data.set(chunk_index, id);
-            self.rebuild.insert(pos.into());
+            flag_for_rebuild(&mut self.rebuild, pos);
         }
         Some(())
     }
@@ -661,22 +957,22 @@ impl MutableChunkAccess {
 
         let prev = light_data.get(chunk_index);
         if light != prev.sky() {
-            light_data.set(chunk_index, LightValue::pack(light, prev.block()));
-            self.rebuild.insert(pos.into());
+            light_data.set(chunk_index, prev.with_sky(light));
+            flag_for_rebuild(&mut self.rebuild, pos);
         }
 
         Some(())
     }
 
     #[must_use]
-    pub fn set_block_light(&mut self, pos: BlockPos, light: u16) -> Option<()> {
+    pub fn set_block_light(&mut self, pos: BlockPos, channel: usize, light: u16) -> Option<()> {
         let (section_pos, chunk_index) = pos.section_and_offset();
         let light_data = self.section(section_pos)?.light_mut();
 
         let prev = light_data.get(chunk_index);
-        if light != prev.block() {
-            light_data.set(chunk_index, LightValue::pack(prev.sky(), light));
-            self.rebuild.insert(pos.into());
+        if light != prev.block_channel(channel) {
+            light_data.set(chunk_index, prev.with_block_channel(channel, light));
+            flag_for_rebuild(&mut self.rebuild, pos);
         }
 
         Some(())
@@ -708,6 +1004,22 @@ impl<T: Copy + Eq> ChunkData<T> {
             ChunkData::Array(data) => data[index] = new_value,
         }
     }
+
+    /// chunk data holding every element of a section, in XZY order like
+    /// [`ArrayChunk::as_slice`]. it's kept homogeneous when every element is the
+    /// same. `None` if there isn't exactly one element per block.
+    pub fn from_elements(elements: Vec<T>) -> Option<Self> {
+        if elements.len() != CHUNK_LENGTH_3 {
+            return None;
+        }
+        let first = elements[0];
+        Some(match elements.iter().all(|&element| element == first) {
+            true => ChunkData::Homogeneous(first),
+            false => ChunkData::Array(ArrayChunk {
+                data: elements.into_boxed_slice(),
+            }),
+        })
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
@@ -724,6 +1036,13 @@ impl<T: Copy> ArrayChunk<T> {
     }
 }
 
+impl<T> ArrayChunk<T> {
+    /// every element of the section, in XZY order.
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+}
+
 pub fn is_in_chunk_bounds(x: usize, y: usize, z: usize) -> bool {
     x < CHUNK_LENGTH && y < CHUNK_LENGTH && z < CHUNK_LENGTH
 }
@@ -891,6 +1210,16 @@ impl CompactedChunkSection {
         }
     }
 
+    /// how many runs of the same block the section was squashed down to.
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// roughly how much memory this section takes up.
+    pub fn size_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.runs.len() * std::mem::size_of::<(usize, BlockId)>()
+    }
+
     pub fn decompact(&self) -> ChunkData<BlockId> {
         match self.runs.len() {
             1 => ChunkData::Homogeneous(self.runs[0].1),
@@ -993,7 +1322,10 @@ impl<W: std::io::Write> Encode<W> for ChunkSection {
             encoder.entry("pos").encode(&snapshot.pos())?;
             encoder.entry("blocks").encode(&snapshot.blocks())?;
             encoder.entry("light").encode(&snapshot.light())?;
-            todo!()
+            encoder
+                .entry("block-entities")
+                .encode(snapshot.block_entities())?;
+            Ok(())
         })
     }
 }
@@ -1022,3 +1354,289 @@ impl<W: std::io::Write> Encode<W> for ChunkSectionPos {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codec::{
+            decode::{decode_node, Node},
+            encode::encode_node,
+        },
+        world::{
+            registry::AIR_BLOCK,
+            test_util::{flat_world, test_registry},
+        },
+    };
+    use std::collections::HashSet;
+
+    // the world is this many sections out from the center section along each
+    // axis, and edits are kept close enough to the center that light never
+    // spreads out of the world.
+    const WORLD_RADIUS: i32 = 1;
+    const MIN_BLOCK: i32 = -WORLD_RADIUS * CHUNK_LENGTH as i32;
+    const MAX_BLOCK: i32 = (WORLD_RADIUS + 1) * CHUNK_LENGTH as i32;
+
+    /// a world made of a flat stone floor with nothing but air above it, where
+    /// the top of the floor lines up with the bottom of the center section.
+    fn make_world() -> Arc<VoxelWorld> {
+        let registry = test_registry();
+        flat_world(&registry, WORLD_RADIUS, registry.lookup("stone"))
+    }
+
+    fn light_at(world: &VoxelWorld, pos: BlockPos) -> LightValue {
+        let (section, index) = pos.section_and_offset();
+        world
+            .section(section)
+            .unwrap()
+            .snapshot()
+            .light()
+            .get(index)
+    }
+
+    fn light_snapshot(world: &VoxelWorld) -> HashMap<ChunkSectionPos, ChunkData<LightValue>> {
+        let mut snapshot = HashMap::new();
+        for x in -WORLD_RADIUS..=WORLD_RADIUS {
+            for y in -WORLD_RADIUS..=WORLD_RADIUS {
+                for z in -WORLD_RADIUS..=WORLD_RADIUS {
+                    let pos = ChunkSectionPos { x, y, z };
+                    let light = world.section(pos).unwrap().snapshot().light().clone();
+                    snapshot.insert(pos, light);
+                }
+            }
+        }
+        snapshot
+    }
+
+    fn blocks_near(pos: BlockPos) -> HashSet<BlockPos> {
+        let mut blocks = HashSet::new();
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    blocks.insert(pos.offset([dx, dy, dz]));
+                }
+            }
+        }
+        blocks
+    }
+
+    /// makes every edit at once, and then checks that every block that could
+    /// see any of the blocks or light that changed is in the dirty region of
+    /// the section it's in.
+    fn edit(world: &Arc<VoxelWorld>, edits: &[(BlockPos, BlockId)]) -> DirtySections {
+        let before = light_snapshot(world);
+
+        let mut access = ChunkAccess::new(world);
+        for &(pos, id) in edits {
+            access.set_block(pos, id);
+        }
+        let mut rebuild = DirtySections::new();
+        write_all_chunk_updates(&mut access, &mut rebuild, &mut HashMap::default());
+
+        let mut expected = HashSet::new();
+        for &(pos, _) in edits {
+            expected.extend(blocks_near(pos));
+        }
+        for (&section, light) in light_snapshot(world).iter() {
+            let old_light = &before[&section];
+            for x in 0..CHUNK_LENGTH {
+                for y in 0..CHUNK_LENGTH {
+                    for z in 0..CHUNK_LENGTH {
+                        if light.get([x, y, z]) != old_light.get([x, y, z]) {
+                            expected.extend(blocks_near(index_to_block(section, [x, y, z])));
+                        }
+                    }
+                }
+            }
+        }
+
+        let missing = expected
+            .into_iter()
+            .filter(|&pos| {
+                let (section, offset) = pos.section_and_offset();
+                let dirty = rebuild.get(&section);
+                !dirty.is_some_and(|dirty| dirty.contains(offset))
+            })
+            .collect::<Vec<_>>();
+        assert!(
+            missing.is_empty(),
+            "{:?} weren't flagged to be remeshed",
+            missing
+        );
+        rebuild
+    }
+
+    /// light spreads out by one level per block, so blocks next to each other
+    /// that light can get through are never more than one level apart. this
+    /// only checks blocks on either side of section borders, since that's where
+    /// light is the most likely to go wrong.
+    fn check_light_across_borders(world: &Arc<VoxelWorld>) {
+        let mut access = ChunkAccess::new(world);
+        let registry = Arc::clone(access.registry());
+        let mut transmissible = |pos| {
+            registry
+                .get(access.block(pos).unwrap())
+                .light_transmissible()
+        };
+
+        for axis in 0..3 {
+            for border in (MIN_BLOCK + CHUNK_LENGTH as i32..MAX_BLOCK).step_by(CHUNK_LENGTH) {
+                for u in MIN_BLOCK..MAX_BLOCK {
+                    for v in MIN_BLOCK..MAX_BLOCK {
+                        let mut coords = [0; 3];
+                        coords[axis] = border;
+                        coords[(axis + 1) % 3] = u;
+                        coords[(axis + 2) % 3] = v;
+                        let after = BlockPos::new(coords);
+                        coords[axis] -= 1;
+                        let before = BlockPos::new(coords);
+
+                        if !transmissible(before) || !transmissible(after) {
+                            continue;
+                        }
+
+                        let (a, b) = (light_at(world, before), light_at(world, after));
+                        let channels_a = [a.sky(), a.block()[0], a.block()[1], a.block()[2]];
+                        let channels_b = [b.sky(), b.block()[0], b.block()[1], b.block()[2]];
+                        for (a, b) in channels_a.into_iter().zip(channels_b) {
+                            assert!(
+                                a.abs_diff(b) <= 1,
+                                "light jumps from {:?} at {:?} to {:?} at {:?}",
+                                a,
+                                before,
+                                b,
+                                after
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn edits_at_section_corners_and_edges() {
+        let world = make_world();
+        let registry = Arc::clone(&world.registry);
+        let stone = registry.lookup("stone");
+        let glow = registry.lookup("debug_glow_block");
+        let last = CHUNK_LENGTH as i32 - 1;
+
+        // lights in the corners of the center section, and one sitting on an
+        // edge of it.
+        for pos in [[0, 0, 0], [last, last, last], [last, 10, 0]] {
+            edit(&world, &[(BlockPos::new(pos), glow)]);
+            check_light_across_borders(&world);
+        }
+
+        // a roof over the corner where four columns meet, which shades the
+        // ground under it.
+        let mut roof = vec![];
+        for x in last - 7..=last + 8 {
+            for z in last - 7..=last + 8 {
+                roof.push((BlockPos::new([x, last, z]), stone));
+            }
+        }
+        edit(&world, &roof);
+        check_light_across_borders(&world);
+
+        // digging into the floor on an edge between two sections below, and
+        // taking the lights back out.
+        edit(&world, &[(BlockPos::new([0, -1, 5]), AIR_BLOCK)]);
+        check_light_across_borders(&world);
+        for pos in [[0, 0, 0], [last, last, last], [last, 10, 0]] {
+            edit(&world, &[(BlockPos::new(pos), AIR_BLOCK)]);
+            check_light_across_borders(&world);
+        }
+    }
+    #[test]
+    fn one_block_edits_only_dirty_the_blocks_around_them() {
+        let world = make_world();
+        let dirt = world.registry.lookup("dirt");
+        let below = ChunkSectionPos { x: 0, y: -1, z: 0 };
+        let above = ChunkSectionPos { x: 0, y: 0, z: 0 };
+
+        // swapping one opaque block for another doesn't change any light, so
+        // only the blocks right around it need remeshing.
+        let rebuild = edit(&world, &[(BlockPos::new([16, -10, 16]), dirt)]);
+        let expected = DirtyRegion {
+            min: [15, 21, 15],
+            max: [17, 23, 17],
+        };
+        assert_eq!(rebuild, [(below, expected)].into_iter().collect());
+
+        // the section above only sees the block through its bottom layer.
+        let rebuild = edit(&world, &[(BlockPos::new([16, -1, 16]), dirt)]);
+        let expected_below = DirtyRegion {
+            min: [15, 30, 15],
+            max: [17, 31, 17],
+        };
+        let expected_above = DirtyRegion {
+            min: [15, 0, 15],
+            max: [17, 0, 17],
+        };
+        let expected = [(below, expected_below), (above, expected_above)];
+        assert_eq!(rebuild, expected.into_iter().collect());
+    }
+
+    #[test]
+    fn relighting_a_section_fixes_stale_light() {
+        let world = make_world();
+        let glow = world.registry.lookup("debug_glow_block");
+        edit(&world, &[(BlockPos::new([5, 5, 5]), glow)]);
+        let center = ChunkSectionPos { x: 0, y: 0, z: 0 };
+        let mut access = ChunkAccess::new(&world);
+        let mut rebuild = DirtySections::new();
+        assert!(relight_section(&mut access, center, &mut rebuild));
+        let expected = light_snapshot(&world);
+
+        // sky light that's too dark, block light with nothing making it, and
+        // light missing right next to the glow block
+        let mut access = MutableChunkAccess::new(&world);
+        let stale = [
+            ([10, 10, 10], None, 3),
+            ([20, 20, 20], Some(0), 15),
+            ([6, 5, 5], Some(1), 0),
+        ];
+        for (pos, channel, light) in stale {
+            let pos = BlockPos::new(pos);
+            match channel {
+                Some(channel) => access.set_block_light(pos, channel, light).unwrap(),
+                None => access.set_sky_light(pos, light).unwrap(),
+            }
+        }
+        drop(access);
+        assert_ne!(light_snapshot(&world), expected);
+
+        let mut access = ChunkAccess::new(&world);
+        let mut rebuild = DirtySections::new();
+        assert!(relight_section(&mut access, center, &mut rebuild));
+        assert_eq!(light_snapshot(&world), expected);
+        assert!(rebuild.contains_key(&center));
+
+        let unloaded = ChunkSectionPos { x: 5, y: 0, z: 0 };
+        assert!(!relight_section(&mut access, unloaded, &mut rebuild));
+    }
+
+    #[test]
+    fn sections_can_be_dumped_and_read_back() {
+        let world = make_world();
+        let dirt = world.registry.lookup("dirt");
+        edit(&world, &[(BlockPos::new([3, -1, 5]), dirt)]);
+        let pos = ChunkSectionPos { x: 0, y: -1, z: 0 };
+        let section = world.section(pos).unwrap();
+
+        let mut bytes = vec![];
+        encode_node(&mut bytes, &*section).unwrap();
+        let node = decode_node(&mut &bytes[..]).unwrap();
+
+        let y = node.get("pos").and_then(|pos| pos.get("y"));
+        assert_eq!(y.and_then(Node::as_signed), Some(-1));
+        let blocks = node.get("blocks").and_then(Node::as_list).unwrap();
+        assert_eq!(blocks.len(), CHUNK_LENGTH_3);
+        let index = CHUNK_LENGTH_2 * 3 + CHUNK_LENGTH * 5 + 31;
+        assert_eq!(blocks[index].as_unsigned(), Some(dirt.0 as u128));
+        assert!(node.get("light").is_some());
+        assert!(node.get("block-entities").is_some());
+    }
+}
@@ -0,0 +1,301 @@
+//! a minimal circuit system: levers turn wires on, and wires turn lamps on
+//! (or light TNT).
+//!
+//! signals are just on or off, and which one a circuit block has is stored as
+//! its state, so every circuit block has an `off` and an `on` state. like
+//! fluids, circuits update in scheduled "circuit ticks": whenever a block
+//! changes, the circuit blocks next to it are scheduled to update on the next
+//! tick. a wire updating recomputes its whole network of connected wires at
+//! once, which is on if any of its wires touch a source that's on. since the
+//! network's state only depends on its sources, and not on what the wires
+//! were before, wires can't keep each other on forever in a loop.
+
+use super::{
+    chunk::ChunkAccess,
+    registry::{BlockId, BlockRegistry},
+    BlockPos, BlockUpdateEvent,
+};
+use crate::{clock::GameTime, prelude::*};
+use serde::Deserialize;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+
+/// the amount of time a single circuit tick represents.
+pub const CIRCUIT_TICK_DURATION: Duration = Duration::from_millis(50);
+
+/// the names of the states every circuit block needs.
+pub const SIGNAL_OFF_STATE: &str = "off";
+pub const SIGNAL_ON_STATE: &str = "on";
+
+/// wire networks with more wire than this never turn on. this keeps a single
+/// update from walking an unbounded amount of wire, and since it doesn't
+/// matter where in the network an update starts, every update agrees that the
+/// network is off.
+const MAX_NETWORK_SIZE: usize = 256;
+
+/// the most circuit blocks that are updated in a single tick. anything else
+/// that's scheduled waits for the next one.
+const MAX_UPDATES_PER_TICK: usize = 512;
+
+const NEIGHBORS: [[i32; 3]; 6] = [
+    [1, 0, 0],
+    [-1, 0, 0],
+    [0, 1, 0],
+    [0, -1, 0],
+    [0, 0, 1],
+    [0, 0, -1],
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CircuitComponent {
+    /// carries a signal between the other components. wires connect to the
+    /// wires on all six sides of them.
+    Wire,
+    /// turns the wires and lamps next to it on while it's on, like a lever.
+    Source,
+    /// is on while any wire or source next to it is on.
+    Lamp,
+    /// like `Lamp`, but for blocks that do something once they're turned on,
+    /// like TNT, which is lit.
+    Trigger,
+}
+
+#[derive(Debug, Default)]
+pub struct CircuitSimulation {
+    accumulated: Duration,
+    /// positions that need a circuit tick, in the order they were scheduled.
+    pending: VecDeque<BlockPos>,
+    /// everything in `pending`, so nothing gets scheduled twice.
+    scheduled: HashSet<BlockPos>,
+}
+
+impl CircuitSimulation {
+    pub fn schedule(&mut self, pos: BlockPos) {
+        if self.scheduled.insert(pos) {
+            self.pending.push_back(pos);
+        }
+    }
+
+    /// schedules every circuit block that could be affected by the block at
+    /// `pos` changing.
+    pub fn block_changed(&mut self, access: &mut ChunkAccess, pos: BlockPos) {
+        let registry = Arc::clone(access.registry());
+        let nearby = std::iter::once(pos).chain(NEIGHBORS.map(|offset| pos.offset(offset)));
+        for pos in nearby {
+            match access.block(pos) {
+                Some(id) if registry.get(id).circuit().is_some() => self.schedule(pos),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn is_on(registry: &BlockRegistry, id: BlockId) -> bool {
+    registry.get(id).state_name() == Some(SIGNAL_ON_STATE)
+}
+
+/// switches the circuit block at `pos` to its on or off state.
+fn set_signal(access: &mut ChunkAccess, registry: &BlockRegistry, pos: BlockPos, on: bool) {
+    let id = match access.block(pos) {
+        Some(id) => id,
+        None => return,
+    };
+    let state = match on {
+        true => SIGNAL_ON_STATE,
+        false => SIGNAL_OFF_STATE,
+    };
+    match registry.get(id).state(state) {
+        Some(target) if target != id => access.set_block(pos, target),
+        _ => {}
+    }
+}
+
+fn update_wire(
+    access: &mut ChunkAccess,
+    registry: &BlockRegistry,
+    start: BlockPos,
+    updated: &mut HashSet<BlockPos>,
+) {
+    let mut network = vec![start];
+    let mut seen = HashSet::from([start]);
+    let mut powered = false;
+    let mut overloaded = false;
+
+    let mut next = 0;
+    while next < network.len() && !overloaded {
+        let pos = network[next];
+        next += 1;
+
+        for offset in NEIGHBORS {
+            let neighbor = pos.offset(offset);
+            let id = match access.block(neighbor) {
+                Some(id) => id,
+                None => continue,
+            };
+            match registry.get(id).circuit() {
+                Some(CircuitComponent::Source) => powered |= is_on(registry, id),
+                Some(CircuitComponent::Wire) if seen.insert(neighbor) => {
+                    match network.len() < MAX_NETWORK_SIZE {
+                        true => network.push(neighbor),
+                        false => overloaded = true,
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for pos in network {
+        set_signal(access, registry, pos, powered && !overloaded);
+        updated.insert(pos);
+    }
+}
+
+fn update_lamp(access: &mut ChunkAccess, registry: &BlockRegistry, pos: BlockPos) {
+    let powered = NEIGHBORS.iter().any(|&offset| {
+        let id = match access.block(pos.offset(offset)) {
+            Some(id) => id,
+            None => return false,
+        };
+        let gives_signal = matches!(
+            registry.get(id).circuit(),
+            Some(CircuitComponent::Wire | CircuitComponent::Source)
+        );
+        gives_signal && is_on(registry, id)
+    });
+    set_signal(access, registry, pos, powered);
+}
+
+/// updates the circuit blocks that were scheduled, up to
+/// `MAX_UPDATES_PER_TICK` of them.
+pub fn tick_circuits(access: &mut ChunkAccess, sim: &mut CircuitSimulation) {
+    let registry = Arc::clone(access.registry());
+    // wires that were already updated as part of another wire's network
+    let mut updated = HashSet::new();
+
+    for _ in 0..MAX_UPDATES_PER_TICK {
+        let pos = match sim.pending.pop_front() {
+            Some(pos) => pos,
+            None => break,
+        };
+        sim.scheduled.remove(&pos);
+
+        let id = match access.block(pos) {
+            Some(id) => id,
+            None => continue,
+        };
+        match registry.get(id).circuit() {
+            Some(CircuitComponent::Wire) if !updated.contains(&pos) => {
+                update_wire(access, &registry, pos, &mut updated)
+            }
+            Some(CircuitComponent::Lamp | CircuitComponent::Trigger) => {
+                update_lamp(access, &registry, pos)
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn schedule_circuit_updates(
+    mut access: ResMut<ChunkAccess>,
+    mut sim: ResMut<CircuitSimulation>,
+    mut block_update_events: EventReader<BlockUpdateEvent>,
+) {
+    for update in block_update_events.iter() {
+        sim.block_changed(&mut access, update.pos);
+    }
+}
+
+pub fn simulate_circuits(
+    time: Res<GameTime>,
+    mut access: ResMut<ChunkAccess>,
+    mut sim: ResMut<CircuitSimulation>,
+) {
+    sim.accumulated += time.delta();
+    if sim.accumulated < CIRCUIT_TICK_DURATION {
+        return;
+    }
+
+    // a tick's changes aren't seen until they've been written to the world, so
+    // there's never anything for a second tick in the same frame to do.
+    sim.accumulated = Duration::ZERO;
+    tick_circuits(&mut access, &mut sim);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        behavior::interact,
+        chunk::{self, ArrayChunk},
+        registry::AIR_BLOCK,
+        test_util::{section_world, test_registry},
+    };
+    use std::collections::HashMap;
+
+    /// writes out everything that's been changed, and keeps ticking until the
+    /// circuits stop changing.
+    fn settle(access: &mut ChunkAccess, sim: &mut CircuitSimulation) {
+        for _ in 0..16 {
+            let mut updates = HashMap::new();
+            chunk::write_all_chunk_updates(access, &mut HashMap::new(), &mut updates);
+            if updates.is_empty() {
+                return;
+            }
+            for &pos in updates.keys() {
+                sim.block_changed(access, pos);
+            }
+            tick_circuits(access, sim);
+        }
+        panic!("circuits never settled");
+    }
+
+    #[test]
+    fn levers_light_lamps() {
+        let registry = test_registry();
+        let state = |name, state| registry.try_lookup_state(name, state).unwrap();
+
+        // a lever, a wire that runs along and then up, and a lamp at the end.
+        // the lamp is in the middle of the section so that its light doesn't
+        // spill out into unloaded sections.
+        let mut blocks = ArrayChunk::homogeneous(AIR_BLOCK);
+        blocks[[12, 14, 16]] = registry.lookup("lever");
+        for x in 13..=16 {
+            blocks[[x, 14, 16]] = registry.lookup("wire");
+        }
+        blocks[[16, 15, 16]] = registry.lookup("wire");
+        blocks[[16, 16, 16]] = registry.lookup("lamp");
+
+        let world = section_world(&registry, blocks);
+
+        let mut access = ChunkAccess::new(&world);
+        let mut sim = CircuitSimulation::default();
+        let block = |x, y, z| BlockPos { x, y, z };
+        let lamp = block(16, 16, 16);
+
+        assert!(interact(&mut access, block(12, 14, 16)));
+        settle(&mut access, &mut sim);
+        assert_eq!(access.block(block(14, 14, 16)), Some(state("wire", "on")));
+        assert_eq!(access.block(lamp), Some(state("lamp", "on")));
+        assert!(registry.get(state("lamp", "on")).block_light() != [0; 3]);
+
+        // cutting the wire turns everything after the cut off
+        access.set_block(block(14, 14, 16), AIR_BLOCK);
+        settle(&mut access, &mut sim);
+        assert_eq!(access.block(block(13, 14, 16)), Some(state("wire", "on")));
+        assert_eq!(access.block(block(16, 15, 16)), Some(state("wire", "off")));
+        assert_eq!(access.block(lamp), Some(state("lamp", "off")));
+
+        access.set_block(block(14, 14, 16), registry.lookup("wire"));
+        settle(&mut access, &mut sim);
+        assert_eq!(access.block(lamp), Some(state("lamp", "on")));
+        assert!(interact(&mut access, block(12, 14, 16)));
+        settle(&mut access, &mut sim);
+        assert_eq!(access.block(block(13, 14, 16)), Some(state("wire", "off")));
+        assert_eq!(access.block(lamp), Some(state("lamp", "off")));
+    }
+}
@@ -0,0 +1,282 @@
+//! simple cellular fluid simulation.
+//!
+//! fluids spread by way of scheduled "fluid ticks". whenever a block changes,
+//! any fluid blocks next to it are scheduled to be ticked after their fluid's
+//! spread delay, where they may flow downwards, spread outwards, or react with
+//! neighboring blocks. blocks placed by the simulation are flowing blocks, and
+//! carry a level that limits how far they can spread from their source. the
+//! level is part of the block's state, so that it's kept when the block is
+//! unloaded or saved.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::Duration,
+};
+
+use super::{
+    chunk::ChunkAccess,
+    registry::{BlockId, BlockRegistry, CollisionType, FluidProperties},
+    BlockPos, BlockUpdateEvent,
+};
+use crate::{
+    aabb::Aabb,
+    clock::GameTime,
+    health::{DamageEvent, DamageSource, Health},
+    physics::AabbCollider,
+    prelude::*,
+    transform::Transform,
+};
+
+/// the state of a fluid block that's a source, which is its first state.
+pub const SOURCE_STATE: &str = "source";
+
+/// the name of the state of a flowing fluid block that can spread `level` more
+/// blocks.
+pub fn flowing_state_name(level: u8) -> String {
+    format!("level_{}", level)
+}
+
+/// the amount of time a single fluid tick represents.
+pub const FLUID_TICK_DURATION: Duration = Duration::from_millis(50);
+
+// don't let a long frame turn into a huge pile of work all at once.
+const MAX_TICKS_PER_FRAME: usize = 4;
+
+const NEIGHBORS: [[i32; 3]; 6] = [
+    [1, 0, 0],
+    [-1, 0, 0],
+    [0, 1, 0],
+    [0, -1, 0],
+    [0, 0, 1],
+    [0, 0, -1],
+];
+
+const HORIZONTAL_NEIGHBORS: [[i32; 3]; 4] = [[1, 0, 0], [-1, 0, 0], [0, 0, 1], [0, 0, -1]];
+
+#[derive(Debug, Default)]
+pub struct FluidSimulation {
+    tick: u64,
+    accumulated: Duration,
+    /// positions that need a fluid tick, keyed by the tick they're due on.
+    scheduled: BTreeMap<u64, HashSet<BlockPos>>,
+}
+
+impl FluidSimulation {
+    pub fn schedule(&mut self, pos: BlockPos, delay: u32) {
+        // always wait at least one tick, so that fluids placed by this tick's updates
+        // don't get processed in the same tick.
+        let due = self.tick + u64::max(1, delay as u64);
+        self.scheduled.entry(due).or_default().insert(pos);
+    }
+}
+
+fn can_flow_into(registry: &BlockRegistry, id: BlockId) -> bool {
+    let block = registry.get(id);
+    block.collision_type() == CollisionType::None && !block.liquid()
+}
+
+pub fn schedule_fluid_updates(
+    mut access: ResMut<ChunkAccess>,
+    mut sim: ResMut<FluidSimulation>,
+    mut block_update_events: EventReader<BlockUpdateEvent>,
+) {
+    let registry = std::sync::Arc::clone(access.registry());
+    for update in block_update_events.iter() {
+        let mut schedule_if_fluid = |pos: BlockPos, id: BlockId| {
+            if let Some(fluid) = registry.get(id).fluid() {
+                sim.schedule(pos, fluid.spread_delay);
+            }
+        };
+
+        schedule_if_fluid(update.pos, update.new_id);
+        for offset in NEIGHBORS {
+            let pos = update.pos.offset(offset);
+            if let Some(id) = access.block(pos) {
+                schedule_if_fluid(pos, id);
+            }
+        }
+    }
+}
+
+fn react(
+    access: &mut ChunkAccess,
+    pos: BlockPos,
+    id: BlockId,
+    fluid: &FluidProperties,
+) -> Option<BlockId> {
+    let registry = std::sync::Arc::clone(access.registry());
+    for offset in NEIGHBORS {
+        let neighbor = match access.block(pos.offset(offset)) {
+            Some(id) => registry.name(id),
+            None => continue,
+        };
+
+        if let Some(reaction) = fluid.reactions.iter().find(|r| r.touching == neighbor) {
+            let result = match registry.get(id).is_fluid_source() {
+                true => &reaction.source_result,
+                false => &reaction.flowing_result,
+            };
+
+            match registry.try_lookup(result) {
+                Some(id) => return Some(id),
+                None => log::warn!("fluid reaction produced unknown block '{}'", result),
+            }
+        }
+    }
+
+    None
+}
+
+fn tick_fluid(access: &mut ChunkAccess, pos: BlockPos) {
+    let registry = std::sync::Arc::clone(access.registry());
+    let id = match access.block(pos) {
+        Some(id) => id,
+        None => return,
+    };
+    let fluid = match registry.get(id).fluid() {
+        Some(fluid) => fluid,
+        None => return,
+    };
+
+    if let Some(result) = react(access, pos, id, fluid) {
+        access.set_block(pos, result);
+        return;
+    }
+
+    // fluids always prefer to flow downwards, and falling fluid is refilled to its
+    // full level so that it can spread out again once it lands.
+    let below = pos.offset([0, -1, 0]);
+    match access.block(below) {
+        Some(below_id) if can_flow_into(&registry, below_id) => {
+            let level = fluid.spread_distance.saturating_sub(1);
+            access.set_block(below, registry.get(id).flowing(level));
+            return;
+        }
+        // don't spread out over unloaded terrain, we'll get another chance when it
+        // gets loaded and something around us changes.
+        None => return,
+        _ => {}
+    }
+
+    let level = registry.get(id).fluid_level().unwrap_or_default();
+    if level <= 1 {
+        return;
+    }
+    let flowing = registry.get(id).flowing(level - 1);
+
+    for offset in HORIZONTAL_NEIGHBORS {
+        let neighbor = pos.offset(offset);
+        if let Some(neighbor_id) = access.block(neighbor) {
+            if can_flow_into(&registry, neighbor_id) {
+                access.set_block(neighbor, flowing);
+            }
+        }
+    }
+}
+
+// TODO: flowing fluid should recede when the source that fed it is removed.
+pub fn simulate_fluids(
+    time: Res<GameTime>,
+    mut access: ResMut<ChunkAccess>,
+    mut sim: ResMut<FluidSimulation>,
+) {
+    sim.accumulated += time.delta();
+
+    let mut ticks = 0;
+    while sim.accumulated >= FLUID_TICK_DURATION && ticks < MAX_TICKS_PER_FRAME {
+        sim.accumulated -= FLUID_TICK_DURATION;
+        sim.tick += 1;
+        ticks += 1;
+
+        // updates that were due on ticks we skipped are caught up on now,
+        // rather than falling further and further behind.
+        let tick = sim.tick;
+        while let Some((&due, _)) = sim.scheduled.first_key_value() {
+            if due > tick {
+                break;
+            }
+            for pos in sim.scheduled.remove(&due).unwrap() {
+                tick_fluid(&mut access, pos);
+            }
+        }
+    }
+
+    // if we fell behind, drop the extra time instead of trying to catch up forever.
+    if ticks == MAX_TICKS_PER_FRAME {
+        sim.accumulated = Duration::ZERO;
+    }
+}
+
+fn most_damaging_fluid(access: &mut ChunkAccess, aabb: &Aabb) -> Option<(BlockId, f32)> {
+    let registry = std::sync::Arc::clone(access.registry());
+    let mut worst: Option<(BlockId, f32)> = None;
+
+    for x in aabb.min.x.floor() as i32..aabb.max.x.ceil() as i32 {
+        for y in aabb.min.y.floor() as i32..aabb.max.y.ceil() as i32 {
+            for z in aabb.min.z.floor() as i32..aabb.max.z.ceil() as i32 {
+                let id = access.block(BlockPos { x, y, z })?;
+                let damage = match registry.get(id).fluid() {
+                    Some(fluid) if fluid.submerged_damage > 0.0 => fluid.submerged_damage,
+                    _ => continue,
+                };
+                match worst {
+                    Some((_, worst)) if worst >= damage => {}
+                    _ => worst = Some((id, damage)),
+                }
+            }
+        }
+    }
+
+    worst
+}
+
+/// damages entities for every frame that their collider overlaps a fluid that
+/// deals damage.
+pub fn fluid_contact_damage(
+    time: Res<GameTime>,
+    mut access: ResMut<ChunkAccess>,
+    query: Query<(Entity, &Transform, &AabbCollider), With<Health>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    query.for_each(|(entity, transform, collider)| {
+        let aabb = collider.aabb.transformed(transform);
+        if let Some((fluid, damage)) = most_damaging_fluid(&mut access, &aabb) {
+            damage_events.send(DamageEvent {
+                entity,
+                source: DamageSource::Fluid(fluid),
+                amount: damage * time.delta_seconds(),
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::{
+        persistence::{lookup_saved_block, saved_block_name},
+        test_util::test_registry,
+    };
+
+    #[test]
+    fn flowing_levels_are_saved_with_the_block() {
+        let registry = test_registry();
+        let water = registry.get(registry.lookup("water"));
+        let spread = water.fluid().unwrap().spread_distance;
+        assert!(water.is_fluid_source());
+        assert_eq!(water.fluid_level(), Some(spread));
+        assert_eq!(water.flowing(spread), registry.lookup("water"));
+
+        for level in 0..spread {
+            let saved = saved_block_name(&registry, water.flowing(level));
+            let loaded = registry.get(lookup_saved_block(&registry, &saved).unwrap());
+            assert!(!loaded.is_fluid_source());
+            assert_eq!(loaded.fluid_level(), Some(level));
+        }
+
+        // saves from before fluids had states only have the block's name
+        assert_eq!(
+            lookup_saved_block(&registry, "water"),
+            Some(registry.lookup("water"))
+        );
+    }
+}
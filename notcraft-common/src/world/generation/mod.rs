@@ -1,4 +1,8 @@
-use self::spline::Spline;
+use self::{
+    preset::{IslandSettings, WorldGenPreset},
+    rng::FeatureRng,
+    spline::{Spline, SplinePoint},
+};
 
 use super::{
     chunk::ChunkData,
@@ -14,7 +18,7 @@ use crate::{
     world::chunk::{CHUNK_LENGTH, CHUNK_LENGTH_2, CHUNK_LENGTH_3},
 };
 use noise::{Fbm, MultiFractal, NoiseFn, OpenSimplex, Perlin};
-use rand::{rngs::SmallRng, FromEntropy, Rng, SeedableRng};
+use rand::Rng;
 use std::{
     collections::hash_map::DefaultHasher,
     sync::{
@@ -24,6 +28,8 @@ use std::{
     time::{Duration, Instant},
 };
 
+pub mod preset;
+pub mod rng;
 pub mod spline;
 
 #[derive(Clone, Debug)]
@@ -35,9 +41,29 @@ pub struct SurfaceHeightmap {
 }
 
 impl SurfaceHeightmap {
+    /// a heightmap where the surface is at the same height everywhere.
+    pub fn flat(height: i32) -> Self {
+        Self {
+            min: height,
+            max: height,
+            timestamp: Default::default(),
+            data: vec![height; CHUNK_LENGTH_2].into(),
+        }
+    }
+
     pub fn data(&self) -> &Arc<[i32]> {
         &self.data
     }
+
+    /// the height of the lowest point on the surface.
+    pub fn min(&self) -> i32 {
+        self.min
+    }
+
+    /// the height of the highest point on the surface.
+    pub fn max(&self) -> i32 {
+        self.max
+    }
 }
 
 #[derive(Debug)]
@@ -59,7 +85,7 @@ fn generate_surface_heights(
     cache: &SurfaceHeighmapCache,
     seed: u64,
     pos: ChunkPos,
-    shaping_curve: &Spline,
+    preset: &WorldGenPreset,
 ) -> SurfaceHeightmap {
     // let mix_noise = NoiseSampler::seeded(seed, Perlin::new()).with_scale(0.0001);
     // let rolling_noise = NoiseSampler::seeded(seed,
@@ -74,7 +100,8 @@ fn generate_surface_heights(
     // let warp_noise_z = NoiseSampler::seeded(seed, Perlin::new())
     //     .with_offset([0.5, 0.0])
     //     .with_scale(0.003);
-    let noise = NoiseSamplerN::seeded(seed, Fbm::new().set_octaves(4)).with_scale(0.004);
+    let noise = Fbm::new().set_octaves(preset.surface_octaves);
+    let noise = NoiseSamplerN::seeded(seed, noise).with_scale(preset.surface_scale);
 
     let mut min = i32::MAX;
     let mut max = i32::MIN;
@@ -94,7 +121,7 @@ fn generate_surface_heights(
             // let mountain = 800.0 * (mountainous_noise_unwarped.sample(x, z) * 0.5 + 0.5);
             // let rolling = 100.0 * rolling_noise.sample(x, z);
             // let result = rolling + mix_noise.sample(x, z) * (warped + mountain);
-            let result = shaping_curve.sample(noise.sample([x, z]));
+            let result = preset.shaping_curve.sample(noise.sample([x, z]));
 
             // let result = 100.0 * f32::sin(x / 30.0) * f32::cos(z / 30.0);
 
@@ -119,16 +146,16 @@ impl SurfaceHeighmapCache {
     pub fn surface_heights(
         &self,
         seed: u64,
-        shaping_curve: &Spline,
+        preset: &WorldGenPreset,
         pos: ChunkPos,
     ) -> SurfaceHeightmap {
         if let Some(cached) = self.heightmaps.pin().get(&pos) {
             cached.timestamp.store(self.timestamp(), Ordering::SeqCst);
             return SurfaceHeightmap::clone(cached);
         } else {
-            let surface_heights = generate_surface_heights(self, seed, pos, shaping_curve);
+            let surface_heights = generate_surface_heights(self, seed, pos, preset);
             self.heightmaps.pin().insert(pos, surface_heights);
-            self.surface_heights(seed, shaping_curve, pos)
+            self.surface_heights(seed, preset, pos)
         }
     }
 
@@ -143,6 +170,7 @@ impl SurfaceHeighmapCache {
     }
 }
 
+#[derive(Debug)]
 struct NoiseSamplerN<F, const D: usize> {
     noise: F,
     offset: [f32; D],
@@ -208,6 +236,62 @@ where
     }
 }
 
+/// how hot and how wet a column of the world is, each from 0 to 1. this is
+/// what blocks like grass are tinted by.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct Climate {
+    pub temperature: f32,
+    pub humidity: f32,
+}
+
+/// picks the climate of each column. there aren't any biomes yet, so the
+/// climate is just smooth noise that changes over a few hundred blocks.
+#[derive(Debug)]
+pub struct ClimateSampler {
+    temperature: NoiseSamplerN<OpenSimplex, 2>,
+    humidity: NoiseSamplerN<OpenSimplex, 2>,
+}
+
+impl ClimateSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            temperature: NoiseSamplerN::seeded(seed + 5, OpenSimplex::new()).with_scale(0.002),
+            humidity: NoiseSamplerN::seeded(seed + 6, OpenSimplex::new()).with_scale(0.002),
+        }
+    }
+
+    pub fn sample(&self, x: f32, z: f32) -> Climate {
+        let pos = [x, z];
+        let remap = |n: f32| (0.5 * n + 0.5).clamp(0.0, 1.0);
+        Climate {
+            temperature: remap(self.temperature.sample(pos)),
+            humidity: remap(self.humidity.sample(pos)),
+        }
+    }
+}
+
+/// maps the raw surface noise to how high the surface is, which is what gives
+/// the world its mix of oceans, plains, and mountains.
+pub fn default_shaping_curve() -> Spline {
+    Spline::default()
+        .with_point(SplinePoint {
+            start: -1.0,
+            height: -10.0,
+        })
+        .with_point(SplinePoint {
+            start: 0.2,
+            height: 20.0,
+        })
+        .with_point(SplinePoint {
+            start: 0.6,
+            height: 40.0,
+        })
+        .with_point(SplinePoint {
+            start: 1.0,
+            height: 100.0,
+        })
+}
+
 #[derive(Debug)]
 pub struct ChunkGenerator {
     stone_id: BlockId,
@@ -216,10 +300,15 @@ pub struct ChunkGenerator {
     water_id: BlockId,
     sand_id: BlockId,
     detail_grass_id: BlockId,
+    preset: WorldGenPreset,
 }
 
 impl ChunkGenerator {
     pub fn new_default(registry: &BlockRegistry) -> Self {
+        Self::new(registry, WorldGenPreset::default())
+    }
+
+    pub fn new(registry: &BlockRegistry, preset: WorldGenPreset) -> Self {
         Self {
             stone_id: registry.lookup("stone"),
             dirt_id: registry.lookup("dirt"),
@@ -227,23 +316,39 @@ impl ChunkGenerator {
             water_id: registry.lookup("water"),
             sand_id: registry.lookup("sand"),
             detail_grass_id: registry.lookup("detail_grass"),
+            preset,
         }
     }
 
     fn pick_block<F: NoiseFn<[f64; 3]>>(
         &self,
-        rng: &mut SmallRng,
+        rng: &mut FeatureRng,
         open_noise: &NoiseSamplerN<F, 3>,
         stringy_noise: &NoiseSamplerN<F, 3>,
         pos: BlockPos,
         surface: i32,
+        bottom: i32,
     ) -> BlockId {
+        // anywhere that isn't in the ground is open, and is flooded below the
+        // sea level. some of the open blocks sitting right on the ground get a
+        // bit of grass.
+        if pos.y >= surface || pos.y < bottom {
+            return match self.preset.sea_level {
+                Some(sea_level) if pos.y < sea_level => self.water_id,
+                _ if pos.y == surface && bottom < surface && rng.gen_bool(1.0 / 3.0) => {
+                    self.detail_grass_id
+                }
+                _ => AIR_BLOCK,
+            };
+        }
+
+        let caves = &self.preset.caves;
         let distance = pos.y - surface;
-        if distance < -20 && {
+        if caves.enabled && distance < -caves.min_depth && {
             let d1 = open_noise.sample_block(pos);
             let d2 = stringy_noise.sample_block(pos);
 
-            d1.abs() < 0.05 && d2.abs() < 0.05
+            d1.abs() < caves.width && d2.abs() < caves.width
 
             // let density = stringy_noise.sample_block(pos);
             // let stringy_bias = util::clamp(
@@ -258,10 +363,8 @@ impl ChunkGenerator {
             // stringy_bias); density.abs() < 0.02
         } {
             AIR_BLOCK
-        } else if distance < 0 {
-            self.stone_id
         } else {
-            AIR_BLOCK
+            self.stone_id
         }
         // if distance < -4 {
         //     self.stone_id
@@ -296,13 +399,18 @@ impl ChunkGenerator {
         let base_y = pos.origin().y;
         let base_z = pos.origin().z;
 
-        let stringy_noise = NoiseSamplerN::seeded(seed, OpenSimplex::new()).with_scale(0.015);
-        let open_noise = NoiseSamplerN::seeded(seed + 3, OpenSimplex::new()).with_scale(0.015);
+        let cave_scale = self.preset.caves.scale;
+        let stringy_noise = NoiseSamplerN::seeded(seed, OpenSimplex::new()).with_scale(cave_scale);
+        let open_noise = NoiseSamplerN::seeded(seed + 3, OpenSimplex::new()).with_scale(cave_scale);
+        let islands = self.preset.islands.map(|islands| {
+            let noise = NoiseSamplerN::seeded(seed + 7, OpenSimplex::new());
+            (islands, noise.with_scale(islands.scale))
+        });
 
-        let seed = make_chunk_section_seed(seed, pos);
-        let mut rng = SmallRng::seed_from_u64(seed);
+        let mut rng = FeatureRng::for_section(seed, pos, "detail_grass");
 
-        if base_y > heights.max {
+        let flooded = matches!(self.preset.sea_level, Some(level) if base_y < level);
+        if base_y > heights.max && !flooded {
             // if pos.y < 1 {
             //     return ChunkData::Homogeneous(self.water_id);
             // } else {
@@ -317,6 +425,13 @@ impl ChunkGenerator {
         for x in 0..CHUNK_LENGTH {
             for z in 0..CHUNK_LENGTH {
                 let surface_height = heights.data[CHUNK_LENGTH * x + z];
+                let bottom = match &islands {
+                    Some((islands, noise)) => {
+                        let pos = [(base_x + x as i32) as f32, (base_z + z as i32) as f32];
+                        island_bottom(islands, noise.sample(pos), surface_height)
+                    }
+                    None => i32::MIN,
+                };
                 chunk_data.extend((0..CHUNK_LENGTH).map(|y| {
                     self.pick_block(
                         &mut rng,
@@ -328,6 +443,7 @@ impl ChunkGenerator {
                             z: base_z + z as i32,
                         },
                         surface_height,
+                        bottom,
                     )
                 }));
             }
@@ -338,6 +454,17 @@ impl ChunkGenerator {
     }
 }
 
+/// the lowest block of an island, given the island noise in its column. the
+/// open sky between islands has no ground at all, so its bottom is its surface.
+fn island_bottom(islands: &IslandSettings, noise: f32, surface: i32) -> i32 {
+    // 0 at the edges of islands, and close to 1 in the middle of the biggest
+    let inside = 1.0 - (0.5 * noise + 0.5) / islands.coverage;
+    if inside <= 0.0 {
+        return surface;
+    }
+    surface - f32::max(1.0, (islands.depth * inside.sqrt()).ceil()) as i32
+}
+
 impl<W: std::io::Write> Encode<W> for SurfaceHeightmap {
     const KIND: NodeKind = NodeKind::List;
 
@@ -346,17 +473,197 @@ impl<W: std::io::Write> Encode<W> for SurfaceHeightmap {
     }
 }
 
-fn make_chunk_section_seed(world_seed: u64, chunk: ChunkSectionPos) -> u64 {
-    let mut rng = SmallRng::seed_from_u64(world_seed);
-    let x = rng.gen::<u64>() ^ SmallRng::seed_from_u64(chunk.x as u64).gen::<u64>();
-    let y = rng.gen::<u64>() ^ SmallRng::seed_from_u64(chunk.x as u64).gen::<u64>();
-    let z = rng.gen::<u64>() ^ SmallRng::seed_from_u64(chunk.z as u64).gen::<u64>();
-    x ^ y ^ z
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::test_util::test_registry;
+    use std::{collections::BTreeMap, path::Path};
+
+    // set this environment variable to overwrite the snapshots with whatever the
+    // generator makes now, when it was changed on purpose.
+    const BLESS_VAR: &str = "NOTCRAFT_BLESS";
+    const SNAPSHOT_PATH: &str = "src/world/generation/snapshots.ron";
+
+    const SEEDS: [u64; 3] = [0, 1, 0xdead_beef];
+    const COLUMNS: [ChunkPos; 3] = [
+        ChunkPos { x: 0, z: 0 },
+        ChunkPos { x: 1, z: 0 },
+        ChunkPos { x: -3, z: 7 },
+    ];
+    // deep enough that caves are carved out everywhere, whatever the surface
+    // looks like.
+    const CAVE_SECTION_Y: i32 = -2;
+
+    /// 64-bit FNV-1a, which unlike the standard library's hasher is
+    /// guaranteed to give the same hash everywhere, forever.
+    struct Fnv(u64);
+
+    impl Fnv {
+        fn new() -> Self {
+            Self(0xcbf2_9ce4_8422_2325)
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for &byte in bytes {
+                self.0 ^= byte as u64;
+                self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+    }
+
+    fn hash_heights(heights: &SurfaceHeightmap) -> String {
+        let mut hasher = Fnv::new();
+        for height in heights.data().iter() {
+            hasher.write(&height.to_le_bytes());
+        }
+        format!("{:016x}", hasher.0)
+    }
+
+    // blocks are hashed by name, so that adding blocks to the registry doesn't
+    // count as a change.
+    fn hash_blocks(registry: &BlockRegistry, data: &ChunkData<BlockId>) -> String {
+        let mut hasher = Fnv::new();
+        for x in 0..CHUNK_LENGTH {
+            for y in 0..CHUNK_LENGTH {
+                for z in 0..CHUNK_LENGTH {
+                    hasher.write(registry.name(data.get([x, y, z])).as_bytes());
+                    hasher.write(&[0]);
+                }
+            }
+        }
+        format!("{:016x}", hasher.0)
+    }
 
-fn make_chunk_seed(world_seed: u64, chunk: ChunkPos) -> u64 {
-    let mut rng = SmallRng::seed_from_u64(world_seed);
-    let x = rng.gen::<u64>() ^ SmallRng::seed_from_u64(chunk.x as u64).gen::<u64>();
-    let z = rng.gen::<u64>() ^ SmallRng::seed_from_u64(chunk.z as u64).gen::<u64>();
-    x ^ z
+    fn generate_snapshots() -> BTreeMap<String, String> {
+        let registry = test_registry();
+        let generator = ChunkGenerator::new_default(&registry);
+        let preset = WorldGenPreset::default();
+
+        let mut snapshots = BTreeMap::new();
+        for seed in SEEDS {
+            let cache = SurfaceHeighmapCache::default();
+            for column in COLUMNS {
+                let heights = cache.surface_heights(seed, &preset, column);
+                let key = format!("seed {} column {} {}", seed, column.x, column.z);
+                snapshots.insert(key, hash_heights(&heights));
+
+                let surface_y = heights.min().div_euclid(CHUNK_LENGTH as i32);
+                for y in [surface_y, CAVE_SECTION_Y] {
+                    let pos = column.section(y);
+                    let data = generator.make_chunk(seed, pos, &heights);
+                    let key = format!("seed {} section {} {} {}", seed, pos.x, pos.y, pos.z);
+                    snapshots.insert(key, hash_blocks(&registry, &data));
+                }
+            }
+        }
+        snapshots
+    }
+
+    // saved chunks are mixed in with freshly generated ones, so if the generator
+    // changes, the two won't line up at their borders anymore.
+    #[test]
+    fn generation_matches_snapshots() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(SNAPSHOT_PATH);
+        let actual = generate_snapshots();
+
+        if std::env::var_os(BLESS_VAR).is_some() {
+            let config = ron::ser::PrettyConfig::new();
+            std::fs::write(&path, ron::ser::to_string_pretty(&actual, config).unwrap()).unwrap();
+            return;
+        }
+
+        let expected: BTreeMap<String, String> =
+            ron::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let changed = expected
+            .keys()
+            .chain(actual.keys())
+            .filter(|key| expected.get(*key) != actual.get(*key))
+            .collect::<std::collections::BTreeSet<_>>();
+
+        assert!(
+            changed.is_empty(),
+            "world generation changed for {:?}. if this was on purpose, run the tests again \
+             with {}=1 to update the snapshots.",
+            changed,
+            BLESS_VAR
+        );
+    }
+
+    #[test]
+    fn generation_order_doesnt_matter() {
+        let registry = test_registry();
+        let preset = WorldGenPreset::default();
+
+        let generate = |sections: &[ChunkSectionPos]| {
+            let generator = ChunkGenerator::new_default(&registry);
+            let cache = SurfaceHeighmapCache::default();
+            let mut generated = BTreeMap::new();
+            for &pos in sections {
+                let heights = cache.surface_heights(1, &preset, pos.column());
+                let data = generator.make_chunk(1, pos, &heights);
+                generated.insert((pos.x, pos.y, pos.z), data);
+            }
+            generated
+        };
+
+        // every section the surface of each column passes through, so that
+        // there's grass scattered around
+        let cache = SurfaceHeighmapCache::default();
+        let mut sections = vec![];
+        for column in COLUMNS {
+            let heights = cache.surface_heights(1, &preset, column);
+            let min = heights.min().div_euclid(CHUNK_LENGTH as i32);
+            let max = heights.max().div_euclid(CHUNK_LENGTH as i32);
+            sections.extend((min..=max).map(|y| column.section(y)));
+        }
+
+        let forwards = generate(&sections);
+        sections.reverse();
+        let backwards = generate(&sections);
+        assert!(forwards == backwards);
+
+        let detail_grass = registry.lookup("detail_grass");
+        let has_grass = |data: &ChunkData<BlockId>| {
+            let mut blocks = (0..CHUNK_LENGTH_3).map(|i| [i / 1024, i / 32 % 32, i % 32]);
+            blocks.any(|pos| data.get(pos) == detail_grass)
+        };
+        assert!(forwards.values().any(has_grass));
+    }
+
+    #[test]
+    fn islands_float_over_nothing() {
+        let registry = test_registry();
+        let preset = WorldGenPreset {
+            shaping_curve: Spline::default().with_point(SplinePoint {
+                start: 0.0,
+                height: 80.0,
+            }),
+            islands: Some(IslandSettings::default()),
+            ..Default::default()
+        };
+        let generator = ChunkGenerator::new(&registry, preset.clone());
+        let cache = SurfaceHeighmapCache::default();
+
+        let open = |data: &ChunkData<BlockId>, x, z| {
+            (0..CHUNK_LENGTH).all(|y| data.get([x, y, z]) == AIR_BLOCK)
+        };
+        let [mut ground, mut sky] = [0, 0];
+        for column in (-2..2).flat_map(|x| (-2..2).map(move |z| ChunkPos { x, z })) {
+            let heights = cache.surface_heights(1, &preset, column);
+            // the surface is at 80, and islands are at most 24 blocks deep, so
+            // there's nothing at all below 56.
+            let below = generator.make_chunk(1, column.section(0), &heights);
+            let surface = generator.make_chunk(1, column.section(2), &heights);
+            for x in 0..CHUNK_LENGTH {
+                for z in 0..CHUNK_LENGTH {
+                    assert!(open(&below, x, z));
+                    match open(&surface, x, z) {
+                        true => sky += 1,
+                        false => ground += 1,
+                    }
+                }
+            }
+        }
+        assert!(ground > 0 && sky > 0, "{} ground, {} sky", ground, sky);
+    }
 }
@@ -0,0 +1,177 @@
+//! named sets of world generation settings, like `superflat` or `amplified`.
+//!
+//! presets are found at `resources/worldgen/<name>.ron`. a world's preset is
+//! picked when it's first made, and its name is kept in the save, so that the
+//! rest of the world keeps being generated the same way. any field a preset
+//! leaves out keeps the value the default terrain uses.
+
+use super::{
+    default_shaping_curve,
+    spline::{Spline, SplinePoint},
+};
+use crate::prelude::*;
+use serde::Deserialize;
+use std::path::Path;
+
+pub const PRESET_DIR: &str = "resources/worldgen";
+pub const DEFAULT_PRESET: &str = "default";
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct WorldGenPreset {
+    /// maps the surface noise, which goes from -1 to 1, to how high the surface
+    /// is.
+    #[serde(deserialize_with = "deserialize_spline")]
+    pub shaping_curve: Spline,
+    /// how quickly the surface noise changes from block to block. smaller
+    /// values spread hills and valleys out further.
+    pub surface_scale: f32,
+    /// how many layers of finer and finer detail the surface noise has.
+    pub surface_octaves: usize,
+    /// open space below this height is filled with water. there's no water at
+    /// all without one.
+    pub sea_level: Option<i32>,
+    pub caves: CaveSettings,
+    /// breaks the surface up into islands floating over nothing.
+    pub islands: Option<IslandSettings>,
+}
+
+impl Default for WorldGenPreset {
+    fn default() -> Self {
+        Self {
+            shaping_curve: default_shaping_curve(),
+            surface_scale: 0.004,
+            surface_octaves: 4,
+            sea_level: None,
+            caves: CaveSettings::default(),
+            islands: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CaveSettings {
+    pub enabled: bool,
+    /// how far below the surface caves start, in blocks.
+    pub min_depth: i32,
+    /// how quickly the cave noise changes from block to block. smaller values
+    /// make longer, straighter tunnels.
+    pub scale: f32,
+    /// how wide tunnels are. this is compared against noise, so it isn't in
+    /// blocks, but bigger is wider.
+    pub width: f32,
+}
+
+impl Default for CaveSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_depth: 20,
+            scale: 0.015,
+            width: 0.05,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct IslandSettings {
+    /// how quickly the noise that picks where islands are changes. smaller
+    /// values make bigger islands, further apart.
+    pub scale: f32,
+    /// roughly how much of the world is covered by islands, from 0 to 1.
+    pub coverage: f32,
+    /// how far an island reaches down below its surface in the middle, in
+    /// blocks. islands get thinner towards their edges.
+    pub depth: f32,
+}
+
+impl Default for IslandSettings {
+    fn default() -> Self {
+        Self {
+            scale: 0.01,
+            coverage: 0.3,
+            depth: 24.0,
+        }
+    }
+}
+
+fn deserialize_spline<'de, D>(deserializer: D) -> Result<Spline, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let points = Vec::<SplinePoint>::deserialize(deserializer)?;
+    Ok(points
+        .into_iter()
+        .fold(Spline::default(), Spline::with_point))
+}
+
+impl WorldGenPreset {
+    fn validate(&self) -> Result<()> {
+        if self.shaping_curve.is_empty() {
+            bail!("the shaping curve needs at least one point");
+        }
+        if self.surface_scale <= 0.0 || self.caves.scale <= 0.0 {
+            bail!("noise scales have to be more than 0");
+        }
+        if self.surface_octaves == 0 {
+            bail!("the surface noise needs at least one octave");
+        }
+        if let Some(islands) = &self.islands {
+            if islands.scale <= 0.0 || !(0.0..=1.0).contains(&islands.coverage) {
+                bail!("islands need a scale more than 0, and a coverage from 0 to 1");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// loads the preset called `name` from `dir`.
+pub fn load_preset(dir: &Path, name: &str) -> Result<WorldGenPreset> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        bail!("'{}' isn't a valid preset name", name);
+    }
+    let path = dir.join(format!("{}.ron", name));
+    let preset: WorldGenPreset = ron::from_str(&std::fs::read_to_string(path)?)?;
+    preset.validate()?;
+    Ok(preset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset_dir() -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join(PRESET_DIR)
+    }
+
+    // the default preset is what worlds made before presets existed use, so it
+    // has to keep generating the same terrain they did.
+    #[test]
+    fn bundled_presets_load() {
+        let default = load_preset(&preset_dir(), DEFAULT_PRESET).unwrap();
+        assert_eq!(default, WorldGenPreset::default());
+
+        for name in ["superflat", "amplified", "islands"] {
+            load_preset(&preset_dir(), name).unwrap();
+        }
+        assert!(load_preset(&preset_dir(), "../default").is_err());
+    }
+
+    #[test]
+    fn left_out_fields_keep_their_defaults() {
+        let preset: WorldGenPreset =
+            ron::from_str("(shaping_curve: [(start: 0.0, height: 5.0)], caves: (width: 0.1))")
+                .unwrap();
+        assert_eq!(preset.shaping_curve.sample(0.7), 5.0);
+        assert_eq!(
+            preset.surface_scale,
+            WorldGenPreset::default().surface_scale
+        );
+        assert_eq!(preset.caves.width, 0.1);
+        assert_eq!(preset.caves.min_depth, CaveSettings::default().min_depth);
+    }
+}
@@ -0,0 +1,165 @@
+//! randomness for world generation that only depends on *what* is being
+//! generated, never on *when*.
+//!
+//! seeds are derived in a hierarchy: the world seed picks a seed for each
+//! chunk, and each chunk's seed picks a seed for each named feature in it, like
+//! `"trees"`. so, two features never share random numbers, adding a feature
+//! doesn't change what the others do, and a chunk looks the same whichever
+//! order it and its neighbors happen to be generated in.
+//!
+//! everything here is written out by hand instead of using `rand`'s generators
+//! or the standard library's hasher, neither of which promise to give the same
+//! numbers between versions. saved chunks are mixed in with freshly generated
+//! ones, so that would show up as seams.
+
+use crate::world::{ChunkPos, ChunkSectionPos};
+use rand::{Error, RngCore};
+
+/// the fractional part of the golden ratio, which spreads out consecutive
+/// numbers nicely.
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// splitmix64's finalizer, which scrambles every bit of `x` into every bit of
+/// the result.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// 64-bit FNV-1a.
+fn hash_name(name: &str) -> u64 {
+    name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// a seed somewhere in the hierarchy.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GenerationSeed(u64);
+
+impl GenerationSeed {
+    pub fn world(seed: u64) -> Self {
+        Self(mix(seed))
+    }
+
+    /// the seed of something below this one, picked out by `key`. the same
+    /// key always gives the same seed, and different keys give unrelated
+    /// ones.
+    pub fn child(self, key: u64) -> Self {
+        Self(mix(self.0 ^ mix(key.wrapping_add(GOLDEN_GAMMA))))
+    }
+
+    pub fn chunk(self, pos: ChunkPos) -> Self {
+        self.child(pos.x as u32 as u64).child(pos.z as u32 as u64)
+    }
+
+    /// a section's seed is below its column's, so that features spanning a
+    /// whole column and features in a single section can both be seeded.
+    pub fn section(self, pos: ChunkSectionPos) -> Self {
+        self.chunk(pos.column()).child(pos.y as u32 as u64)
+    }
+
+    pub fn feature(self, name: &str) -> Self {
+        self.child(hash_name(name))
+    }
+
+    pub fn value(self) -> u64 {
+        self.0
+    }
+}
+
+/// random numbers for a single feature of a single chunk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeatureRng {
+    state: u64,
+}
+
+impl FeatureRng {
+    pub fn new(seed: GenerationSeed) -> Self {
+        Self { state: seed.0 }
+    }
+
+    /// random numbers for `feature` in the column at `pos`, in the world with
+    /// seed `world_seed`.
+    pub fn for_chunk(world_seed: u64, pos: ChunkPos, feature: &str) -> Self {
+        Self::new(
+            GenerationSeed::world(world_seed)
+                .chunk(pos)
+                .feature(feature),
+        )
+    }
+
+    /// random numbers for `feature` in the section at `pos`, in the world with
+    /// seed `world_seed`.
+    pub fn for_section(world_seed: u64, pos: ChunkSectionPos, feature: &str) -> Self {
+        Self::new(
+            GenerationSeed::world(world_seed)
+                .section(pos)
+                .feature(feature),
+        )
+    }
+}
+
+// splitmix64
+impl RngCore for FeatureRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        mix(self.state)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNKS: [ChunkPos; 5] = [
+        ChunkPos { x: 0, z: 0 },
+        ChunkPos { x: 1, z: 0 },
+        ChunkPos { x: 0, z: 1 },
+        ChunkPos { x: -1, z: 0 },
+        ChunkPos { x: 12, z: -7 },
+    ];
+
+    #[test]
+    fn seeds_features_and_chunks_are_independent() {
+        let mut seen = std::collections::HashSet::new();
+        for seed in [0, 1, 7] {
+            for &pos in CHUNKS.iter() {
+                for feature in ["trees", "ores", "grass"] {
+                    let first = FeatureRng::for_chunk(seed, pos, feature).next_u64();
+                    assert!(seen.insert(first), "{} {:?} {}", seed, pos, feature);
+                }
+                for y in -1..=1 {
+                    let first = FeatureRng::for_section(seed, pos.section(y), "trees").next_u64();
+                    assert!(seen.insert(first), "{} {:?} {}", seed, pos, y);
+                }
+            }
+        }
+    }
+
+    // these numbers must never change, or newly generated chunks won't match up
+    // with the ones saved before.
+    #[test]
+    fn numbers_are_stable() {
+        let mut rng = FeatureRng::for_chunk(0xdead_beef, ChunkPos { x: -3, z: 7 }, "trees");
+        assert_eq!(rng.next_u64(), 0x36a3_b758_0e8f_88c4);
+        assert_eq!(rng.next_u64(), 0x4d30_182f_69f7_c957);
+    }
+}
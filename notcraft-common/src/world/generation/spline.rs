@@ -1,11 +1,12 @@
 use crate::util;
+use serde::Deserialize;
 
 #[derive(Clone, Debug, PartialEq, Default)]
 pub struct Spline {
     points: Vec<SplinePoint>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Deserialize)]
 pub struct SplinePoint {
     pub start: f32,
     pub height: f32,
@@ -23,6 +24,10 @@ impl Spline {
         self
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
     pub fn sample(&self, value: f32) -> f32 {
         match self
             .points
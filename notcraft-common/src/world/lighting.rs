@@ -30,22 +30,40 @@ impl<W: std::io::Write> Encode<W> for LightValue {
 }
 
 pub const SKY_LIGHT_BITS: u16 = 4;
+/// the number of bits in each color channel of block light.
 pub const BLOCK_LIGHT_BITS: u16 = 4;
+/// block light is split up into red, green, and blue channels, which each
+/// spread through the world on their own.
+pub const BLOCK_LIGHT_CHANNELS: usize = 3;
 
-pub const SKY_LIGHT_MASK: u16 = ((1 << SKY_LIGHT_BITS) - 1) << BLOCK_LIGHT_BITS;
+const SKY_LIGHT_SHIFT: u16 = BLOCK_LIGHT_BITS * BLOCK_LIGHT_CHANNELS as u16;
+
+pub const SKY_LIGHT_MASK: u16 = ((1 << SKY_LIGHT_BITS) - 1) << SKY_LIGHT_SHIFT;
+/// the mask for a single channel of block light, once it's been shifted down.
 pub const BLOCK_LIGHT_MASK: u16 = (1 << BLOCK_LIGHT_BITS) - 1;
 
-pub const FULL_SKY_LIGHT: LightValue = LightValue::pack(15, 0);
+pub const FULL_SKY_LIGHT: LightValue = LightValue::pack(15, [0; BLOCK_LIGHT_CHANNELS]);
+
+/// the red, green, and blue channels of block light.
+pub type BlockLight = [u16; BLOCK_LIGHT_CHANNELS];
+
+const fn block_channel_shift(channel: usize) -> u16 {
+    BLOCK_LIGHT_BITS * (BLOCK_LIGHT_CHANNELS - 1 - channel) as u16
+}
 
+// light values are laid out like `SSSS RRRR GGGG BBBB`
 impl LightValue {
     pub const fn new(value: u16) -> Self {
         Self(value)
     }
 
-    pub const fn pack(sky: u16, block: u16) -> Self {
-        let mut val = 0;
-        val |= block & BLOCK_LIGHT_MASK;
-        val |= (sky << BLOCK_LIGHT_BITS) & SKY_LIGHT_MASK;
+    pub const fn pack(sky: u16, block: BlockLight) -> Self {
+        let mut val = (sky << SKY_LIGHT_SHIFT) & SKY_LIGHT_MASK;
+        let mut channel = 0;
+        while channel < BLOCK_LIGHT_CHANNELS {
+            val |= (block[channel] & BLOCK_LIGHT_MASK) << block_channel_shift(channel);
+            channel += 1;
+        }
         Self(val)
     }
 
@@ -54,25 +72,67 @@ impl LightValue {
     }
 
     pub const fn sky(self) -> u16 {
-        self.0 >> BLOCK_LIGHT_BITS
+        self.0 >> SKY_LIGHT_SHIFT
     }
 
-    pub const fn block(self) -> u16 {
-        self.0 & BLOCK_LIGHT_MASK
+    pub const fn block_channel(self, channel: usize) -> u16 {
+        (self.0 >> block_channel_shift(channel)) & BLOCK_LIGHT_MASK
     }
 
-    pub const fn intensity(self) -> u16 {
-        if self.sky() > self.block() {
-            self.sky()
-        } else {
-            self.block()
-        }
+    pub const fn block(self) -> BlockLight {
+        [
+            self.block_channel(0),
+            self.block_channel(1),
+            self.block_channel(2),
+        ]
+    }
+
+    pub const fn with_sky(self, sky: u16) -> Self {
+        Self(self.0 & !SKY_LIGHT_MASK | ((sky << SKY_LIGHT_SHIFT) & SKY_LIGHT_MASK))
+    }
+
+    pub const fn with_block_channel(self, channel: usize, light: u16) -> Self {
+        let shift = block_channel_shift(channel);
+        Self(self.0 & !(BLOCK_LIGHT_MASK << shift) | ((light & BLOCK_LIGHT_MASK) << shift))
+    }
+
+    /// the brightest of the block light channels.
+    pub fn block_intensity(self) -> u16 {
+        self.block().into_iter().max().unwrap_or(0)
+    }
+
+    pub fn intensity(self) -> u16 {
+        u16::max(self.sky(), self.block_intensity())
     }
 
     pub fn combine_max(self, other: LightValue) -> LightValue {
-        let block = u16::max(self.0 & BLOCK_LIGHT_MASK, other.0 & BLOCK_LIGHT_MASK);
-        let sky = u16::max(self.0 & SKY_LIGHT_MASK, other.0 & SKY_LIGHT_MASK);
-        LightValue(sky | block)
+        let [r0, g0, b0] = self.block();
+        let [r1, g1, b1] = other.block();
+        let block = [u16::max(r0, r1), u16::max(g0, g1), u16::max(b0, b1)];
+        LightValue::pack(u16::max(self.sky(), other.sky()), block)
+    }
+
+    /// averages the sky light and each channel of block light in `values`
+    /// separately, rounding to the nearest light level. averaging nothing at
+    /// all gives no light.
+    pub fn average<I>(values: I) -> LightValue
+    where
+        I: IntoIterator<Item = LightValue>,
+    {
+        let (mut sky, mut block, mut count) = (0, [0; BLOCK_LIGHT_CHANNELS], 0);
+        for value in values {
+            sky += value.sky();
+            for (total, channel) in block.iter_mut().zip(value.block()) {
+                *total += channel;
+            }
+            count += 1;
+        }
+
+        let average = |total| (total + count / 2) / count;
+        match count {
+            0 => LightValue::default(),
+            _ => LightValue::pack(average(sky), block.map(average)),
+        }
     }
 }
 
@@ -86,8 +146,9 @@ impl LightValue {
 // SoA lighting code: https://github.com/RegrowthStudios/SoACode-Public/blob/develop/SoA/VoxelLightEngine.cpp
 #[derive(Debug, Default)]
 pub struct LightUpdateQueues {
-    block_removal: VecDeque<(BlockPos, u16)>,
-    block_update: VecDeque<(BlockPos, u16)>,
+    // block light has a separate set of queues for each channel
+    block_removal: [VecDeque<(BlockPos, u16)>; BLOCK_LIGHT_CHANNELS],
+    block_update: [VecDeque<(BlockPos, u16)>; BLOCK_LIGHT_CHANNELS],
     sky_removal: VecDeque<(BlockPos, u16)>,
     sky_update: VecDeque<(BlockPos, u16)>,
     visited: HashSet<BlockPos>,
@@ -96,7 +157,7 @@ pub struct LightUpdateQueues {
 impl LightUpdateQueues {
     pub fn queue_blocklight_updates<I>(&mut self, access: &mut MutableChunkAccess, iter: I)
     where
-        I: Iterator<Item = (BlockPos, u16)>,
+        I: Iterator<Item = (BlockPos, BlockLight)>,
     {
         for (pos, new_light) in iter {
             if !self.visited.insert(pos) {
@@ -104,23 +165,63 @@ impl LightUpdateQueues {
             }
 
             let prev_light = access.light(pos).unwrap();
-
             let id = access.block(pos).unwrap();
-            if access.registry().get(id).light_transmissible() {
+            let transmissible = access.registry().get(id).light_transmissible();
+
+            if transmissible {
                 self.sky_removal.push_back((pos, prev_light.sky()));
-                self.block_removal.push_back((pos, prev_light.block()));
             }
 
-            match new_light.cmp(&prev_light.block()) {
-                Ordering::Equal => {}
-                Ordering::Less => self.block_removal.push_back((pos, prev_light.block())),
-                Ordering::Greater => self.block_update.push_back((pos, new_light)),
+            for (channel, new) in new_light.into_iter().enumerate() {
+                let prev = prev_light.block_channel(channel);
+                let removal = &mut self.block_removal[channel];
+                if transmissible {
+                    removal.push_back((pos, prev));
+                }
+
+                match new.cmp(&prev) {
+                    Ordering::Equal => {}
+                    Ordering::Less => removal.push_back((pos, prev)),
+                    Ordering::Greater => self.block_update[channel].push_back((pos, new)),
+                }
             }
         }
 
         self.visited.clear();
     }
 
+    /// throws away the light at `pos`, along with any light that spread out
+    /// from it, and starts it over from the `sky` and `block` light that it
+    /// gets on its own.
+    pub fn queue_relight(
+        &mut self,
+        access: &mut MutableChunkAccess,
+        pos: BlockPos,
+        sky: u16,
+        block: BlockLight,
+    ) {
+        let prev = match access.light(pos) {
+            Some(prev) => prev,
+            None => return,
+        };
+
+        if prev.sky() > 0 {
+            self.sky_removal.push_back((pos, prev.sky()));
+        }
+        if sky > 0 {
+            self.sky_update.push_back((pos, sky));
+        }
+        for (channel, new) in block.into_iter().enumerate() {
+            let prev = prev.block_channel(channel);
+            if prev > 0 {
+                self.block_removal[channel].push_back((pos, prev));
+            }
+            if new > 0 {
+                self.block_update[channel].push_back((pos, new));
+            }
+        }
+    }
+
     pub fn queue_skylight_updates(
         &mut self,
         access: &mut MutableChunkAccess,
@@ -151,11 +252,24 @@ pub(crate) fn propagate_block_light(
     queues: &mut LightUpdateQueues,
     access: &mut MutableChunkAccess,
 ) {
-    for &(pos, _) in queues.block_removal.iter() {
-        access.set_block_light(pos, 0).unwrap();
+    for channel in 0..BLOCK_LIGHT_CHANNELS {
+        propagate_block_light_channel(queues, access, channel);
+    }
+}
+
+fn propagate_block_light_channel(
+    queues: &mut LightUpdateQueues,
+    access: &mut MutableChunkAccess,
+    channel: usize,
+) {
+    let removal = &mut queues.block_removal[channel];
+    let update = &mut queues.block_update[channel];
+
+    for &(pos, _) in removal.iter() {
+        access.set_block_light(pos, channel, 0).unwrap();
     }
 
-    while let Some((pos, light)) = queues.block_removal.pop_front() {
+    while let Some((pos, light)) = removal.pop_front() {
         let dirs = [
             pos.offset([1, 0, 0]),
             pos.offset([-1, 0, 0]),
@@ -166,22 +280,26 @@ pub(crate) fn propagate_block_light(
         ];
 
         for dir in dirs.into_iter() {
-            let neighbor_light = access.light(dir).unwrap().block();
+            // light doesn't spread into sections that aren't loaded
+            let neighbor_light = match access.light(dir) {
+                Some(light) => light.block_channel(channel),
+                None => continue,
+            };
 
             if neighbor_light > 0 && neighbor_light < light {
-                access.set_block_light(dir, 0).unwrap();
-                queues.block_removal.push_back((dir, light - 1));
+                access.set_block_light(dir, channel, 0).unwrap();
+                removal.push_back((dir, light - 1));
             } else if neighbor_light > 0 {
-                queues.block_update.push_back((dir, 0));
+                update.push_back((dir, 0));
             }
         }
     }
 
-    while let Some((pos, queue_light)) = queues.block_update.pop_front() {
-        let current_light = access.light(pos).unwrap().block();
+    while let Some((pos, queue_light)) = update.pop_front() {
+        let current_light = access.light(pos).unwrap().block_channel(channel);
         let queue_light = u16::max(queue_light, current_light);
         if queue_light != current_light {
-            access.set_block_light(pos, queue_light).unwrap();
+            access.set_block_light(pos, channel, queue_light).unwrap();
         }
 
         if queue_light == 0 {
@@ -198,15 +316,18 @@ pub(crate) fn propagate_block_light(
         ];
 
         for dir in dirs.into_iter() {
-            let neighbor_light = access.light(dir).unwrap().block();
+            let neighbor_light = match access.light(dir) {
+                Some(light) => light.block_channel(channel),
+                None => continue,
+            };
             let new_light = u16::max(queue_light - 1, neighbor_light);
 
             let id = access.block(dir).unwrap();
             let neighbor_transmissible = access.registry().get(id).light_transmissible();
 
             if new_light != neighbor_light && neighbor_transmissible {
-                access.set_block_light(dir, new_light).unwrap();
-                queues.block_update.push_back((dir, new_light));
+                access.set_block_light(dir, channel, new_light).unwrap();
+                update.push_back((dir, new_light));
             }
         }
     }
@@ -228,7 +349,10 @@ pub(crate) fn propagate_sky_light(queues: &mut LightUpdateQueues, access: &mut M
         ];
 
         for dir in dirs.into_iter() {
-            let neighbor_light = access.light(dir).unwrap().sky();
+            let neighbor_light = match access.light(dir) {
+                Some(light) => light.sky(),
+                None => continue,
+            };
 
             if neighbor_light > 0 && neighbor_light < light {
                 access.set_sky_light(dir, 0).unwrap();
@@ -260,7 +384,10 @@ pub(crate) fn propagate_sky_light(queues: &mut LightUpdateQueues, access: &mut M
         ];
 
         for dir in dirs.into_iter() {
-            let neighbor_light = access.light(dir).unwrap().sky();
+            let neighbor_light = match access.light(dir) {
+                Some(light) => light.sky(),
+                None => continue,
+            };
             let new_light = u16::max(queue_light - 1, neighbor_light);
 
             let id = access.block(dir).unwrap();
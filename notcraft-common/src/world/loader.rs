@@ -0,0 +1,262 @@
+//! what chunk loaders load beyond a plain square around them.
+//!
+//! loaders load a little further ahead in the direction they're moving, so
+//! that chunks are ready by the time they get there. and since walking back
+//! and forth over the same ground would otherwise load and unload the same
+//! chunks over and over, loaders hold on to chunks for longer while that's
+//! been happening a lot, and go back to their usual unload radius once it
+//! stops.
+//!
+//! sections that blocks have been changed in are loaded along with their
+//! column no matter how far above or below a loader they are, see
+//! [`ModifiedSections`].
+
+use super::{
+    chunk::{ChunkSectionPos, CHUNK_LENGTH},
+    ChunkPos,
+};
+use nalgebra::{Point3, Vector3};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// how many seconds of movement ahead loaders load chunks for.
+const LOOKAHEAD_SECONDS: f32 = 2.0;
+/// how quickly a loader's velocity catches up to how it's actually moving.
+const VELOCITY_RATE: f32 = 4.0;
+/// how long loads and unloads count towards churn, in seconds.
+const CHURN_WINDOW: f64 = 60.0;
+/// how many chunks have to be loaded again soon after being unloaded before
+/// loaders keep chunks loaded one more chunk further out.
+const RELOADS_PER_EXTRA_RADIUS: usize = 8;
+/// the furthest past their unload radius that churn can make loaders keep
+/// chunks loaded.
+pub const MAX_EXTRA_UNLOAD_RADIUS: usize = 4;
+
+/// how fast a loader has been moving recently.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LoaderMotion {
+    last_pos: Point3<f32>,
+    last_time: f64,
+    velocity: Vector3<f32>,
+}
+
+impl LoaderMotion {
+    pub fn new(pos: Point3<f32>, now: f64) -> Self {
+        Self {
+            last_pos: pos,
+            last_time: now,
+            velocity: Vector3::zeros(),
+        }
+    }
+
+    /// takes into account that the loader is at `pos` at time `now`, in
+    /// seconds.
+    pub fn update(&mut self, pos: Point3<f32>, now: f64) {
+        let dt = (now - self.last_time) as f32;
+        if dt <= 0.0 {
+            return;
+        }
+        let velocity = (pos - self.last_pos) / dt;
+        self.velocity += (velocity - self.velocity) * (1.0 - f32::exp(-VELOCITY_RATE * dt));
+        self.last_pos = pos;
+        self.last_time = now;
+    }
+
+    pub fn velocity(&self) -> Vector3<f32> {
+        self.velocity
+    }
+
+    /// how many chunks further along the x and z axes the loader should load,
+    /// going by where it'll be soon. it's never more than half of
+    /// `load_radius`, so that things like teleporting don't load half the
+    /// world.
+    pub fn lead(&self, load_radius: usize) -> [i32; 2] {
+        let max_lead = (load_radius / 2) as f32;
+        let ahead = self.velocity * LOOKAHEAD_SECONDS / CHUNK_LENGTH as f32;
+        [ahead.x, ahead.z].map(|axis| axis.round().clamp(-max_lead, max_lead) as i32)
+    }
+}
+
+/// how many chunks have been loaded and unloaded recently.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkChurn {
+    loads: VecDeque<f64>,
+    unloads: VecDeque<f64>,
+    /// loads of chunks that had been unloaded not long before.
+    reloads: VecDeque<f64>,
+    unloaded_at: HashMap<ChunkPos, f64>,
+}
+
+impl ChunkChurn {
+    pub fn record_load(&mut self, pos: ChunkPos, now: f64) {
+        self.loads.push_back(now);
+        if let Some(unloaded) = self.unloaded_at.remove(&pos) {
+            if now - unloaded < CHURN_WINDOW {
+                self.reloads.push_back(now);
+            }
+        }
+    }
+
+    pub fn record_unload(&mut self, pos: ChunkPos, now: f64) {
+        self.unloads.push_back(now);
+        self.unloaded_at.insert(pos, now);
+    }
+
+    /// forgets about anything that happened too long before `now`.
+    pub fn expire(&mut self, now: f64) {
+        for times in [&mut self.loads, &mut self.unloads, &mut self.reloads] {
+            while matches!(times.front(), Some(&time) if now - time >= CHURN_WINDOW) {
+                times.pop_front();
+            }
+        }
+        self.unloaded_at
+            .retain(|_, &mut time| now - time < CHURN_WINDOW);
+    }
+
+    pub fn stats(&self) -> ChunkLoadStats {
+        ChunkLoadStats {
+            loads_per_minute: self.loads.len(),
+            unloads_per_minute: self.unloads.len(),
+            extra_unload_radius: self.extra_unload_radius(),
+        }
+    }
+
+    /// how much further than usual loaders keep chunks loaded for.
+    pub fn extra_unload_radius(&self) -> usize {
+        usize::min(
+            MAX_EXTRA_UNLOAD_RADIUS,
+            self.reloads.len() / RELOADS_PER_EXTRA_RADIUS,
+        )
+    }
+}
+
+/// the sections of each column that have had blocks changed in them since they
+/// were generated. loaders only load sections within their load radius of
+/// them vertically, plus the ones the surface passes through, so without this,
+/// something like a tall tower would be cut off when its column is loaded
+/// again from further down. these are kept in the world's metadata, so they
+/// still get loaded after the game is restarted.
+#[derive(Clone, Debug, Default)]
+pub struct ModifiedSections {
+    columns: HashMap<ChunkPos, HashSet<i32>>,
+}
+
+impl ModifiedSections {
+    pub fn from_sections<I>(sections: I) -> Self
+    where
+        I: IntoIterator<Item = ChunkSectionPos>,
+    {
+        let mut modified = Self::default();
+        for pos in sections {
+            modified.insert(pos);
+        }
+        modified
+    }
+
+    /// returns false if `pos` was already known to be modified.
+    pub fn insert(&mut self, pos: ChunkSectionPos) -> bool {
+        self.columns.entry(pos.column()).or_default().insert(pos.y)
+    }
+
+    pub fn contains(&self, pos: ChunkSectionPos) -> bool {
+        self.columns
+            .get(&pos.column())
+            .is_some_and(|ys| ys.contains(&pos.y))
+    }
+
+    /// every modified section, sorted so that saving them again without any
+    /// changes doesn't reorder them.
+    pub fn sections(&self) -> Vec<ChunkSectionPos> {
+        let mut sections = self
+            .columns
+            .iter()
+            .flat_map(|(column, ys)| ys.iter().map(move |&y| column.section(y)))
+            .collect::<Vec<_>>();
+        sections.sort_unstable();
+        sections
+    }
+
+    /// the heights of the sections in `column` that have to be loaded so that
+    /// every modified section can be meshed, which needs the sections all
+    /// around it to be loaded too.
+    pub fn pinned(&self, column: ChunkPos) -> HashSet<i32> {
+        let mut pinned = HashSet::new();
+        for dx in -1..=1 {
+            for dz in -1..=1 {
+                let neighbor = ChunkPos {
+                    x: column.x + dx,
+                    z: column.z + dz,
+                };
+                for &y in self.columns.get(&neighbor).into_iter().flatten() {
+                    pinned.extend(y - 1..=y + 1);
+                }
+            }
+        }
+        pinned
+    }
+}
+
+/// how much chunk loading has been going on over the last minute, for the
+/// debug overlay.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChunkLoadStats {
+    pub loads_per_minute: usize,
+    pub unloads_per_minute: usize,
+    pub extra_unload_radius: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loaders_lead_in_the_direction_they_move() {
+        let mut motion = LoaderMotion::new(Point3::origin(), 0.0);
+        for step in 1..=60 {
+            let time = step as f64 / 20.0;
+            motion.update(Point3::new(time as f32 * 48.0, 0.0, 0.0), time);
+        }
+        // 48 blocks a second for 2 seconds is 3 chunks ahead
+        assert_eq!(motion.lead(8), [3, 0]);
+        // but never more than half the load radius
+        assert_eq!(motion.lead(2), [1, 0]);
+    }
+
+    #[test]
+    fn modified_sections_pin_the_sections_around_them() {
+        let mut modified = ModifiedSections::default();
+        assert!(modified.insert(ChunkSectionPos { x: 0, y: 20, z: 0 }));
+        assert!(!modified.insert(ChunkSectionPos { x: 0, y: 20, z: 0 }));
+
+        let sorted = |column| {
+            let mut pinned = modified.pinned(column).into_iter().collect::<Vec<_>>();
+            pinned.sort_unstable();
+            pinned
+        };
+        assert_eq!(sorted(ChunkPos { x: 0, z: 0 }), [19, 20, 21]);
+        assert_eq!(sorted(ChunkPos { x: -1, z: 1 }), [19, 20, 21]);
+        assert!(sorted(ChunkPos { x: 2, z: 0 }).is_empty());
+    }
+
+    #[test]
+    fn reloading_chunks_widens_the_unload_radius_for_a_while() {
+        let mut churn = ChunkChurn::default();
+        let chunks = (0..16).map(|x| ChunkPos { x, z: 0 }).collect::<Vec<_>>();
+        for &pos in chunks.iter() {
+            churn.record_load(pos, 0.0);
+            churn.record_unload(pos, 1.0);
+        }
+        // chunks that haven't been seen before don't count
+        assert_eq!(churn.extra_unload_radius(), 0);
+
+        for &pos in chunks.iter() {
+            churn.record_load(pos, 2.0);
+        }
+        churn.expire(2.0);
+        let stats = churn.stats();
+        assert_eq!((stats.loads_per_minute, stats.unloads_per_minute), (32, 16));
+        assert_eq!(stats.extra_unload_radius, 2);
+
+        churn.expire(62.0);
+        assert_eq!(churn.stats(), ChunkLoadStats::default());
+    }
+}
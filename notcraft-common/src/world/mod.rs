@@ -6,7 +6,7 @@ use std::{
     borrow::Borrow,
     collections::{BTreeMap, HashMap, HashSet, VecDeque},
     hash::Hash,
-    ops::{Index, IndexMut},
+    ops::{Index, IndexMut, RangeInclusive},
     path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -17,26 +17,67 @@ use std::{
 
 pub use self::chunk::ArrayChunk;
 use self::{
-    chunk::{Chunk, ChunkAccess, ChunkSection, ChunkSectionPos, CompactedChunkSection},
-    generation::spline::{Spline, SplinePoint},
-    persistence::{update_persistence, WorldPersistence},
-    registry::{load_registry, BlockId, BlockRegistry, CollisionType, AIR_BLOCK},
+    chunk::{
+        Chunk, ChunkAccess, ChunkSection, ChunkSectionPos, CompactedChunkSection, DirtyRegion,
+        DirtySections,
+    },
+    circuit::CircuitSimulation,
+    fluid::FluidSimulation,
+    generation::preset::{load_preset, WorldGenPreset, DEFAULT_PRESET, PRESET_DIR},
+    loader::{ChunkChurn, ChunkLoadStats, LoaderMotion, ModifiedSections},
+    persistence::{update_persistence, SaveEvent, SectionStore, WorldPersistence},
+    pregen::PregenQueue,
+    registry::{
+        load_registry_with_packs, BlockId, BlockMeshType, BlockRegistry, CollisionType,
+        RegistryRef, AIR_BLOCK,
+    },
+    relight::RelightQueue,
+    spawn::WorldSpawn,
+    unload_cache::UnloadedSectionCache,
 };
 use crate::{
-    aabb::Aabb, debug::send_debug_event, prelude::*, transform::Transform, util::ChannelPair,
-    world::chunk::CHUNK_LENGTH, Axis, Side,
+    aabb::Aabb,
+    assets::{assets_ready, AssetLoadProgress, REGISTRY},
+    content_pack::{with_content_packs, ContentPacks},
+    debug::send_debug_event,
+    game_state::playing,
+    prelude::*,
+    profile_scope,
+    profiler::ProfileSystemExt,
+    task::{PoolKind, TaskPools},
+    transform::Transform,
+    util::ChannelPair,
+    world::chunk::CHUNK_LENGTH,
+    Axis, Side,
 };
 
+pub mod behavior;
+pub mod block_entity;
 pub mod chunk;
+pub mod circuit;
+pub mod fluid;
 pub mod generation;
 pub mod lighting;
+pub mod loader;
+pub mod orientation;
 pub mod orphan;
 pub mod persistence;
+pub mod pregen;
+pub mod query;
 pub mod registry;
+pub mod relight;
+pub mod repro;
+pub mod spawn;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod unload_cache;
 
 pub mod debug {
     use super::{chunk::ChunkSectionPos, ChunkPos};
-    use crate::debug_events;
+    use crate::{
+        debug::recording::{EventSummary, Summarize},
+        debug_events,
+    };
 
     pub enum WorldLoadEvent {
         Loaded(ChunkPos),
@@ -52,6 +93,39 @@ pub mod debug {
         Orphaned(ChunkSectionPos),
     }
 
+    fn column(kind: &'static str, pos: ChunkPos) -> EventSummary {
+        let pos = [pos.x, 0, pos.z];
+        EventSummary { kind, pos }
+    }
+
+    fn section(kind: &'static str, pos: ChunkSectionPos) -> EventSummary {
+        let pos = [pos.x, pos.y, pos.z];
+        EventSummary { kind, pos }
+    }
+
+    impl Summarize for WorldLoadEvent {
+        fn summarize(&self) -> EventSummary {
+            match *self {
+                WorldLoadEvent::Loaded(pos) => column("loaded", pos),
+                WorldLoadEvent::Unloaded(pos) => column("unloaded", pos),
+                WorldLoadEvent::Modified(pos) => column("modified", pos),
+                WorldLoadEvent::LoadedSection(pos) => section("loaded_section", pos),
+                WorldLoadEvent::UnloadedSection(pos) => section("unloaded_section", pos),
+                WorldLoadEvent::ModifiedSection(pos) => section("modified_section", pos),
+            }
+        }
+    }
+
+    impl Summarize for WorldAccessEvent {
+        fn summarize(&self) -> EventSummary {
+            match *self {
+                WorldAccessEvent::Read(pos) => section("read", pos),
+                WorldAccessEvent::Written(pos) => section("written", pos),
+                WorldAccessEvent::Orphaned(pos) => section("orphaned", pos),
+            }
+        }
+    }
+
     debug_events! {
         events,
         WorldLoadEvent => "world-load",
@@ -250,48 +324,54 @@ pub struct VoxelWorld {
     compacted_columns: ConcurrentHashMap<ChunkPos, CompactedChunkSection>,
 }
 
-struct WorldGenerator {
-    // pool: ThreadPool,
+pub struct WorldGenerator {
     seed: u64,
-    shaping_curve: Spline,
+    preset: WorldGenPreset,
     generator: Arc<generation::ChunkGenerator>,
     surface_cache: Arc<generation::SurfaceHeighmapCache>,
+    climate: Arc<generation::ClimateSampler>,
+    unload_cache: Mutex<UnloadedSectionCache>,
+    /// where sections are saved, for worlds that are saved anywhere.
+    store: Option<SectionStore>,
     finished_chunks: ChannelPair<Arc<Chunk>>,
     finished_sections: ChannelPair<Arc<ChunkSection>>,
 }
 
 impl WorldGenerator {
-    pub fn new(registry: &BlockRegistry, seed: u64) -> Self {
-        // TODO: make configurable
-        // let pool = ThreadPoolBuilder::new().build().unwrap();
-        let generator = Arc::new(generation::ChunkGenerator::new_default(&registry));
+    pub fn new(
+        registry: &BlockRegistry,
+        seed: u64,
+        preset: WorldGenPreset,
+        store: Option<SectionStore>,
+    ) -> Self {
+        let generator = Arc::new(generation::ChunkGenerator::new(registry, preset.clone()));
 
         Self {
-            // pool,
             seed,
-            shaping_curve: Spline::default()
-                .with_point(SplinePoint {
-                    start: -1.0,
-                    height: -10.0,
-                })
-                .with_point(SplinePoint {
-                    start: 0.2,
-                    height: 20.0,
-                })
-                .with_point(SplinePoint {
-                    start: 0.6,
-                    height: 40.0,
-                })
-                .with_point(SplinePoint {
-                    start: 1.0,
-                    height: 100.0,
-                }),
+            preset,
             generator,
             surface_cache: Default::default(),
+            climate: Arc::new(generation::ClimateSampler::new(seed)),
+            unload_cache: Default::default(),
+            store,
             finished_chunks: Default::default(),
             finished_sections: Default::default(),
         }
     }
+
+    /// the height of the first air block above the generated surface.
+    pub fn surface_height(&self, x: i32, z: i32) -> i32 {
+        let pos = BlockPos { x, y: 0, z };
+        let (section, [dx, _, dz]) = pos.section_and_offset();
+        let heights = self
+            .surface_cache
+            .surface_heights(self.seed, &self.preset, section.column());
+        heights.data()[CHUNK_LENGTH * dx + dz]
+    }
+
+    pub fn climate(&self) -> &Arc<generation::ClimateSampler> {
+        &self.climate
+    }
 }
 
 #[derive(Clone)]
@@ -301,7 +381,9 @@ pub enum WorldEvent {
 
     LoadedSection(Arc<ChunkSection>),
     UnloadedSection(Arc<ChunkSection>),
-    ModifiedSection(Arc<ChunkSection>),
+    /// a section that had blocks or light change, along with the part of it
+    /// that needs to be remeshed.
+    ModifiedSection(Arc<ChunkSection>, DirtyRegion),
 }
 
 impl VoxelWorld {
@@ -330,12 +412,22 @@ impl VoxelWorld {
     pub fn section(&self, pos: ChunkSectionPos) -> Option<Arc<ChunkSection>> {
         Some(self.chunk(pos.column())?.section(pos.y)?)
     }
+
+    /// puts a chunk into the world directly, bypassing generation and load
+    /// events entirely. this is meant for building worlds by hand, like in
+    /// tests and benchmarks.
+    pub fn insert_chunk(&self, chunk: Arc<Chunk>) {
+        self.chunks.pin().insert(chunk.pos(), chunk);
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct WorldPlugin {
     registry_path: Option<PathBuf>,
+    save_dir: Option<PathBuf>,
     seed: Option<u64>,
+    preset: Option<String>,
+    missing_block: Option<String>,
 }
 
 impl WorldPlugin {
@@ -348,40 +440,207 @@ impl WorldPlugin {
         self.seed = Some(seed);
         self
     }
+
+    /// the name of the worldgen preset in `resources/worldgen` that new worlds
+    /// are generated with. worlds that were already saved keep the one they
+    /// were made with unless this is set.
+    pub fn with_preset(mut self, name: &str) -> Self {
+        self.preset = Some(name.into());
+        self
+    }
+
+    pub fn with_save_dir<P: AsRef<Path>>(mut self, path: &P) -> Self {
+        self.save_dir = Some(path.as_ref().into());
+        self
+    }
+
+    /// the block that saved blocks which aren't in the registry anymore are
+    /// loaded as. defaults to air.
+    pub fn with_missing_block(mut self, name: &str) -> Self {
+        self.missing_block = Some(name.into());
+        self
+    }
+
+    /// loads the block registry, opens the save, and sets up the generator the
+    /// same way adding the plugin does, for things that need a world without
+    /// running the game.
+    pub fn open(&self, packs: &mut ContentPacks) -> Result<OpenedWorld> {
+        let registry_path = self
+            .registry_path
+            .clone()
+            .unwrap_or_else(|| "resources/blocks.json".into());
+        let registry = load_registry_with_packs(registry_path, packs)?;
+
+        let missing_block = match &self.missing_block {
+            Some(name) => registry.try_lookup(name).unwrap_or_else(|| {
+                log::error!("missing block '{}' is not in the block registry", name);
+                AIR_BLOCK
+            }),
+            None => AIR_BLOCK,
+        };
+        let mut persistence = match &self.save_dir {
+            Some(dir) => WorldPersistence::open(dir, &registry, missing_block)?,
+            None => WorldPersistence::new(&registry),
+        };
+
+        let saved_seed = persistence.metadata().seed;
+        if let (Some(seed), Some(saved_seed)) = (self.seed, saved_seed) {
+            if seed != saved_seed {
+                log::warn!("overriding saved world seed {} with {}", saved_seed, seed);
+            }
+        }
+        let seed = self
+            .seed
+            .or(saved_seed)
+            .unwrap_or_else(|| rand::thread_rng().gen());
+
+        let saved_preset = persistence.metadata().worldgen.clone();
+        if let (Some(preset), Some(saved_preset)) = (&self.preset, &saved_preset) {
+            if preset != saved_preset {
+                log::warn!(
+                    "overriding saved worldgen preset '{}' with '{}'",
+                    saved_preset,
+                    preset
+                );
+            }
+        }
+        let new_world = saved_preset.is_none();
+        let preset_name = self
+            .preset
+            .clone()
+            .or(saved_preset)
+            .unwrap_or_else(|| DEFAULT_PRESET.into());
+        // the save records the preset that was really used, so that it keeps
+        // generating the same way even if the broken one is fixed later. only
+        // new worlds fall back like this, since the rest of a saved world would
+        // no longer match the parts that were already generated.
+        let (preset_name, preset) = match load_preset(Path::new(PRESET_DIR), &preset_name) {
+            Ok(preset) => (preset_name, preset),
+            Err(err) if !new_world => {
+                bail!("failed to load worldgen preset '{}': {}", preset_name, err)
+            }
+            Err(err) => {
+                log::error!("failed to load worldgen preset '{}': {}", preset_name, err);
+                (DEFAULT_PRESET.into(), WorldGenPreset::default())
+            }
+        };
+
+        persistence.update_metadata(|meta| {
+            meta.seed = Some(seed);
+            meta.worldgen = Some(preset_name);
+        })?;
+
+        let store = persistence.sections().cloned();
+        let generator = Arc::new(WorldGenerator::new(&registry, seed, preset, store));
+        let spawn = match persistence.metadata().spawn {
+            Some(pos) => WorldSpawn::settled(pos),
+            None => WorldSpawn::unsettled(&generator),
+        };
+
+        Ok(OpenedWorld {
+            registry,
+            persistence,
+            generator,
+            spawn,
+        })
+    }
+}
+
+/// everything [`WorldPlugin::open`] sets up.
+pub struct OpenedWorld {
+    pub registry: Arc<BlockRegistry>,
+    pub persistence: WorldPersistence,
+    pub generator: Arc<WorldGenerator>,
+    pub spawn: WorldSpawn,
 }
 
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        let registry = load_registry(
-            self.registry_path
-                .clone()
-                .unwrap_or_else(|| "resources/blocks.json".into()),
-        )
-        .unwrap();
+        let OpenedWorld {
+            registry,
+            persistence,
+            generator,
+            spawn,
+        } = with_content_packs(app, |packs| self.open(packs)).unwrap();
+        // everything else that gets loaded depends on the registry, so it's
+        // loaded before anything else, rather than while the game is running.
+        if let Some(mut progress) = app.world_mut().get_resource_mut::<AssetLoadProgress>() {
+            progress.finish(REGISTRY);
+        }
 
         let world = VoxelWorld::new(&registry);
         app.insert_resource(ChunkAccess::new(&world));
         app.insert_resource(world);
 
-        app.insert_resource(Arc::new(WorldGenerator::new(
-            &registry,
-            self.seed.unwrap_or_else(|| rand::thread_rng().gen()),
-        )));
+        app.insert_resource(spawn);
+        app.insert_resource(generator);
         app.insert_resource(registry);
 
+        let modified = &persistence.metadata().modified_sections;
+        app.insert_resource(ModifiedSections::from_sections(modified.iter().copied()));
         app.insert_resource(LoadQueue::default());
-        app.insert_resource(WorldPersistence::new());
+        app.insert_resource(persistence);
+        app.insert_resource(FluidSimulation::default());
+        app.insert_resource(CircuitSimulation::default());
+        app.init_resource::<RelightQueue>();
+        app.init_resource::<PregenQueue>();
+        app.init_resource::<ChunkLoadStats>();
 
         app.add_event::<WorldEvent>();
+        app.add_event::<SaveEvent>();
         app.add_event::<BlockUpdateEvent>();
         app.add_event::<Handleable<ChunkLoadEvent>>();
         app.add_event::<Handleable<ChunkSectionLoadEvent>>();
         app.add_event::<Handleable<ChunkUnloadEvent>>();
         app.add_event::<Handleable<ChunkSectionUnloadEvent>>();
 
-        app.add_system(load_chunks.system());
-        app.add_system(remove_unrooted_blocks.system());
-        app.add_system(emit_load_events.system().label(WorldLabel("load_events")));
+        // nothing gets generated until everything is loaded, so that the
+        // game can't start before there's anything to show it with.
+        app.add_system(
+            load_chunks
+                .system()
+                .profiled()
+                .label(WorldLabel("load_chunks"))
+                .with_run_criteria(assets_ready.system()),
+        );
+        app.add_system(
+            save_modified_sections
+                .system()
+                .after(WorldLabel("load_chunks")),
+        );
+        app.add_system(spawn::settle_spawn.system().label(WorldLabel("spawn")));
+        app.add_system(spawn::save_spawn.system().after(WorldLabel("spawn")));
+        app.add_system(remove_unrooted_blocks.system().profiled());
+        app.add_system(
+            fluid::schedule_fluid_updates
+                .system()
+                .before(WorldLabel("fluids")),
+        );
+        app.add_system(
+            fluid::simulate_fluids
+                .system()
+                .profiled()
+                .label(WorldLabel("fluids"))
+                .with_run_criteria(playing.system()),
+        );
+        app.add_system(
+            circuit::schedule_circuit_updates
+                .system()
+                .before(WorldLabel("circuits")),
+        );
+        app.add_system(
+            circuit::simulate_circuits
+                .system()
+                .profiled()
+                .label(WorldLabel("circuits"))
+                .with_run_criteria(playing.system()),
+        );
+        app.add_system(
+            emit_load_events
+                .system()
+                .profiled()
+                .label(WorldLabel("load_events")),
+        );
         app.add_system(
             update_persistence
                 .system()
@@ -391,6 +650,7 @@ impl Plugin for WorldPlugin {
         app.add_system(
             generate_world
                 .system()
+                .profiled()
                 .label(WorldLabel("generate"))
                 .after(WorldLabel("persistence"))
                 .after(WorldLabel("load_events")),
@@ -398,11 +658,35 @@ impl Plugin for WorldPlugin {
         app.add_system(
             world_unload_handler
                 .system()
+                .profiled()
                 .label(WorldLabel("unload"))
                 .after(WorldLabel("persistence"))
                 .after(WorldLabel("load_events")),
         );
-        app.add_system_to_stage(CoreStage::PostUpdate, apply_chunk_updates.system());
+        // after generation, so that it can tell whether loading chunks for
+        // players is keeping the generator busy.
+        app.add_system(
+            pregen::pregenerate_chunks
+                .system()
+                .profiled()
+                .after(WorldLabel("generate")),
+        );
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            apply_chunk_updates
+                .system()
+                .profiled()
+                .label(WorldLabel("apply_updates")),
+        );
+        // relighting reads the world after this frame's edits are in, so that
+        // it doesn't light blocks that are about to change.
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            relight::relight_sections
+                .system()
+                .profiled()
+                .after(WorldLabel("apply_updates")),
+        );
     }
 }
 
@@ -434,6 +718,91 @@ impl LoadQueue {
         let events = &mut self.inner.write().events;
         events.push_back(LoadEvent::UnloadSection(pos));
     }
+
+    /// tells the queue where a loader is and which way it's facing, so that
+    /// pending loads can be ordered by how much they matter to it.
+    pub fn set_focus(&self, entity: Entity, pos: Point3<f32>, forward: Vector3<f32>) {
+        let mut queues = self.inner.write();
+        let focus = LoadFocus { pos, forward };
+        let previous = queues.focuses.insert(entity, focus);
+        queues.needs_reprioritize |= match previous {
+            Some(previous) => previous.differs_from(&focus),
+            None => true,
+        };
+    }
+
+    pub fn remove_focus(&self, entity: Entity) {
+        let mut queues = self.inner.write();
+        queues.focuses.remove(&entity);
+        queues.needs_reprioritize = true;
+    }
+
+    /// whether any chunks or sections are still waiting to be loaded.
+    pub fn has_pending_loads(&self) -> bool {
+        let queues = self.inner.read();
+        let loads = queues
+            .events
+            .iter()
+            .any(|event| matches!(event, LoadEvent::Load(_) | LoadEvent::LoadSection(_)));
+        loads || !queues.load.is_empty() || !queues.load_sections.is_empty()
+    }
+}
+
+/// where a loader is, and which way it's facing.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct LoadFocus {
+    pos: Point3<f32>,
+    forward: Vector3<f32>,
+}
+
+// loads that are in front of a loader are treated as if they were this much
+// closer, in terms of squared distance.
+const IN_VIEW_WEIGHT: f32 = 0.25;
+// the cosine of the half-angle of the cone that counts as "in front of" a
+// loader. there's no camera down here, so this stands in for its frustum.
+const VIEW_CONE_COS: f32 = 0.5;
+
+impl LoadFocus {
+    // whether the focus moved or turned enough to be worth re-sorting the
+    // pending loads over.
+    fn differs_from(&self, other: &LoadFocus) -> bool {
+        let moved = BlockPos::from(WorldPos::new(self.pos.coords))
+            != BlockPos::from(WorldPos::new(other.pos.coords));
+        moved || self.forward.dot(&other.forward) < 0.95
+    }
+
+    fn weighted_distance_sq(&self, offset: Vector3<f32>) -> f32 {
+        let distance_sq = offset.norm_squared();
+        // a zero offset makes a NaN here, which is fine, since it compares as
+        // not being in view and the distance is zero anyways.
+        match offset.normalize().dot(&self.forward) > VIEW_CONE_COS {
+            true => IN_VIEW_WEIGHT * distance_sq,
+            false => distance_sq,
+        }
+    }
+}
+
+fn chunk_load_priority(focuses: &HashMap<Entity, LoadFocus>, pos: ChunkPos) -> f32 {
+    let len = CHUNK_LENGTH as f32;
+    let center_x = len * (pos.x as f32 + 0.5);
+    let center_z = len * (pos.z as f32 + 0.5);
+    focuses
+        .values()
+        .map(|focus| {
+            // columns span the whole world vertically, so only the horizontal
+            // distance matters.
+            let offset = vector![center_x - focus.pos.x, 0.0, center_z - focus.pos.z];
+            focus.weighted_distance_sq(offset)
+        })
+        .fold(f32::INFINITY, f32::min)
+}
+
+fn section_load_priority(focuses: &HashMap<Entity, LoadFocus>, pos: ChunkSectionPos) -> f32 {
+    let center = chunk_section_aabb(pos).center();
+    focuses
+        .values()
+        .map(|focus| focus.weighted_distance_sq(center - focus.pos))
+        .fold(f32::INFINITY, f32::min)
 }
 
 // `btree.pop_front()` isnt stable yet :(
@@ -495,9 +864,35 @@ impl<T: Hash + Eq> DedupQueue<T> {
             .map(|id| self.queue.remove(&id).unwrap())
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
     pub fn pop_iter(&mut self) -> impl Iterator<Item = T> + '_ {
         std::iter::from_fn(|| self.pop_front())
     }
+
+    /// reorders the queue so that elements with the lowest priority values are
+    /// popped first. elements with equal priorities keep their relative order.
+    pub fn prioritize_by<F>(&mut self, mut priority: F)
+    where
+        T: Copy,
+        F: FnMut(T) -> f32,
+    {
+        let mut values = self
+            .queue
+            .values()
+            .map(|&value| (priority(value), value))
+            .collect::<Vec<_>>();
+        values.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        self.queue.clear();
+        self.dedup_map.clear();
+        self.head = 0;
+        for (_, value) in values {
+            self.push_back(value);
+        }
+    }
 }
 
 #[derive(Default)]
@@ -508,6 +903,9 @@ struct MutableLoadQueue {
     unload_sections: DedupQueue<ChunkSectionPos>,
 
     events: VecDeque<LoadEvent>,
+
+    focuses: HashMap<Entity, LoadFocus>,
+    needs_reprioritize: bool,
 }
 
 fn process_load_events(world: &VoxelWorld, queues: &mut MutableLoadQueue) {
@@ -518,7 +916,7 @@ fn process_load_events(world: &VoxelWorld, queues: &mut MutableLoadQueue) {
             LoadEvent::Load(pos) => {
                 queues.unload.remove(&pos);
                 if !world.is_loaded(pos) {
-                    queues.load.push_back(pos);
+                    queues.needs_reprioritize |= queues.load.push_back(pos);
                 }
             }
             LoadEvent::Unload(pos) => {
@@ -534,7 +932,7 @@ fn process_load_events(world: &VoxelWorld, queues: &mut MutableLoadQueue) {
                 );
                 queues.unload_sections.remove(&pos);
                 if !world.is_section_loaded(pos) {
-                    queues.load_sections.push_back(pos);
+                    queues.needs_reprioritize |= queues.load_sections.push_back(pos);
                 }
             }
             LoadEvent::UnloadSection(pos) => {
@@ -549,14 +947,20 @@ fn process_load_events(world: &VoxelWorld, queues: &mut MutableLoadQueue) {
             }
         }
     }
+
+    if std::mem::take(&mut queues.needs_reprioritize) {
+        let focuses = &queues.focuses;
+        let load = &mut queues.load;
+        let load_sections = &mut queues.load_sections;
+        load.prioritize_by(|pos| chunk_load_priority(focuses, pos));
+        load_sections.prioritize_by(|pos| section_load_priority(focuses, pos));
+    }
 }
 
 fn run_chunk_generation_task(generator: Arc<WorldGenerator>, pos: ChunkPos) {
-    let heights = generator.surface_cache.surface_heights(
-        generator.seed,
-        &generator.shaping_curve,
-        pos.into(),
-    );
+    let heights = generator
+        .surface_cache
+        .surface_heights(generator.seed, &generator.preset, pos);
     let chunk = Chunk::initialize(pos, heights);
 
     let _ = generator.finished_chunks.tx.send(Arc::new(chunk));
@@ -569,17 +973,40 @@ fn run_chunk_section_generation_task(
     registry: Arc<BlockRegistry>,
 ) {
     let pos = chunk.pos().section(pos);
-    let chunk_data = generator.generator.make_chunk(
-        generator.seed,
-        // &generator.shaping_curve,
-        pos,
-        &chunk.heights(),
-    );
+    let saved = generator.store.as_ref().and_then(|store| {
+        store.load(pos).unwrap_or_else(|err| {
+            log::error!(
+                "failed to load saved section {:?}, generating it again: {}",
+                pos,
+                err
+            );
+            None
+        })
+    });
+    let chunk_data = match saved {
+        Some(data) => data,
+        None => generator.generator.make_chunk(
+            generator.seed,
+            // &generator.shaping_curve,
+            pos,
+            &chunk.heights(),
+        ),
+    };
     let chunk = ChunkSection::initialize(pos, chunk_data, &registry);
 
     let _ = generator.finished_sections.tx.send(Arc::new(chunk));
 }
 
+fn run_chunk_section_restore_task(
+    compacted: CompactedChunkSection,
+    pos: ChunkSectionPos,
+    generator: Arc<WorldGenerator>,
+    registry: Arc<BlockRegistry>,
+) {
+    let section = ChunkSection::initialize(pos, compacted.decompact(), &registry);
+    let _ = generator.finished_sections.tx.send(Arc::new(section));
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct BlockUpdateEvent {
     pub pos: BlockPos,
@@ -587,25 +1014,33 @@ pub struct BlockUpdateEvent {
     pub new_id: BlockId,
 }
 
+/// lets everything that cares know which parts of which sections changed.
+fn send_modified_sections(
+    world: &VoxelWorld,
+    rebuild: &DirtySections,
+    chunk_events: &mut EventWriter<WorldEvent>,
+) {
+    for (&pos, &region) in rebuild.iter() {
+        if let Some(chunk) = world.section(pos) {
+            chunk_events.send(WorldEvent::ModifiedSection(chunk, region));
+            send_debug_event(debug::WorldLoadEvent::ModifiedSection(pos));
+        }
+    }
+}
+
 fn apply_chunk_updates(
     world: Res<Arc<VoxelWorld>>,
     mut access: ResMut<ChunkAccess>,
     mut chunk_events: EventWriter<WorldEvent>,
     mut block_update_events: EventWriter<BlockUpdateEvent>,
 ) {
-    let mut rebuild_set = HashSet::new();
+    let mut rebuild = DirtySections::new();
     let mut block_updates = HashMap::default();
 
     // TODO: think about what section updates might do to the chunk's data, like
     // updating heightmaps and such
-    chunk::write_all_chunk_updates(&mut access, &mut rebuild_set, &mut block_updates);
-
-    for &pos in rebuild_set.iter() {
-        if let Some(chunk) = world.section(pos) {
-            chunk_events.send(WorldEvent::ModifiedSection(chunk));
-            send_debug_event(debug::WorldLoadEvent::ModifiedSection(pos));
-        }
-    }
+    chunk::write_all_chunk_updates(&mut access, &mut rebuild, &mut block_updates);
+    send_modified_sections(&world, &rebuild, &mut chunk_events);
 
     block_update_events.send_batch(block_updates.iter().map(|(&k, &v)| BlockUpdateEvent {
         pos: k,
@@ -676,12 +1111,24 @@ fn emit_load_events(
     // TODO: it might be nice if the load and unload rates were configurable
 
     for pos in queues.load.pop_iter().take(1) {
-        assert!(world.chunk(pos).is_none());
+        if world.chunk(pos).is_some() {
+            log::warn!(
+                "chunk {:?} was queued for loading, but it's already loaded",
+                pos
+            );
+            continue;
+        }
         chunk_load_events.send(Handleable::new(ChunkLoadEvent(pos)));
     }
 
     for pos in queues.load_sections.pop_iter().take(8) {
-        assert!(world.section(pos).is_none());
+        if world.section(pos).is_some() {
+            log::warn!(
+                "section {:?} was queued for loading, but it's already loaded",
+                pos
+            );
+            continue;
+        }
         section_load_events.send(Handleable::new(ChunkSectionLoadEvent(pos)));
     }
 
@@ -696,23 +1143,47 @@ fn emit_load_events(
 
 fn world_unload_handler(
     world: Res<Arc<VoxelWorld>>,
+    generator: Res<Arc<WorldGenerator>>,
+    persistence: Res<WorldPersistence>,
+    pools: Res<TaskPools>,
     mut chunk_events: EventWriter<WorldEvent>,
     mut load_events: LoadEvents,
 ) {
     for event in load_events.section_unload.iter() {
         if let Some(ChunkSectionUnloadEvent(pos)) = event.handle() {
-            if let Some(chunk) = world.chunk(pos.column()) {
-                let section = chunk.unload_section(pos.y);
-
-                send_debug_event(debug::WorldLoadEvent::UnloadedSection(pos));
-                chunk_events.send(WorldEvent::UnloadedSection(section));
+            let chunk = match world.chunk(pos.column()) {
+                Some(chunk) => chunk,
+                None => continue,
+            };
+            match chunk.unload_section(pos.y) {
+                Some(section) => {
+                    send_debug_event(debug::WorldLoadEvent::UnloadedSection(pos));
+                    chunk_events.send(WorldEvent::UnloadedSection(section));
+                }
+                None => log::warn!("tried unloading section {:?}, which wasn't loaded", pos),
             }
         }
     }
 
     for event in load_events.chunk_unload.iter() {
         if let Some(ChunkUnloadEvent(pos)) = event.handle() {
-            let chunk = Arc::clone(world.chunks.pin().remove(&pos).unwrap());
+            let chunk = match world.chunks.pin().remove(&pos) {
+                Some(chunk) => Arc::clone(chunk),
+                None => {
+                    log::warn!("tried unloading chunk {:?}, which wasn't loaded", pos);
+                    continue;
+                }
+            };
+
+            persistence.save_chunk(&chunk, &pools);
+
+            let mut unload_cache = generator.unload_cache.lock();
+            let sections = chunk.sections();
+            let unloaded_sections = chunk.unloaded_sections();
+            for section in sections.values().chain(unloaded_sections.values()) {
+                let compacted = CompactedChunkSection::compact(section.snapshot().blocks());
+                unload_cache.insert(section.pos(), compacted);
+            }
 
             for section in chunk.sections().values() {
                 send_debug_event(debug::WorldLoadEvent::UnloadedSection(section.pos()));
@@ -730,6 +1201,7 @@ fn generate_world(
     registry: Res<Arc<BlockRegistry>>,
     load_queue: Res<LoadQueue>,
     generator: Res<Arc<WorldGenerator>>,
+    pools: Res<TaskPools>,
     mut chunk_events: EventWriter<WorldEvent>,
     mut load_events: LoadEvents,
 ) {
@@ -749,7 +1221,8 @@ fn generate_world(
             // TODO: assert that we arent loading already-loaded chunks
 
             let generator_ref = Arc::clone(&generator);
-            rayon::spawn(move || {
+            pools.spawn(PoolKind::Generator, move || {
+                profile_scope!("generate_chunk");
                 run_chunk_generation_task(generator_ref, pos);
             });
         }
@@ -758,36 +1231,47 @@ fn generate_world(
     for event in load_events.section_load.iter() {
         if let Some(ChunkSectionLoadEvent(pos)) = event.handle() {
             // TODO: assert that we arent loading already-loaded chunks
-            if !world.is_loaded(pos.column()) {
-                log::error!(
-                    "tried loading section {pos:?} for unloaded chunk {column:?}, skipping",
-                    column = pos.column()
-                );
+            let chunk = match world.chunk(pos.column()) {
+                Some(chunk) => chunk,
+                None => {
+                    log::error!(
+                        "tried loading section {pos:?} for unloaded chunk {column:?}, skipping",
+                        column = pos.column()
+                    );
+                    continue;
+                }
+            };
+
+            if let Some(section) = chunk.try_load_section(pos.y) {
+                let _ = generator.finished_sections.tx.send(section);
                 continue;
             }
 
-            let chunk = world.chunk(pos.column()).unwrap();
-            match chunk.try_load_section(pos.y) {
-                Some(section) => generator.finished_sections.tx.send(section).unwrap(),
-                None => {
-                    let generator_ref = Arc::clone(&generator);
-                    let registry_ref = Arc::clone(&registry);
-                    rayon::spawn(move || {
-                        run_chunk_section_generation_task(
-                            chunk,
-                            pos.y,
-                            generator_ref,
-                            registry_ref,
-                        );
-                    });
-                }
+            let generator_ref = Arc::clone(&generator);
+            let registry_ref = Arc::clone(&registry);
+            // sections of recently unloaded chunks can skip generation entirely
+            match generator.unload_cache.lock().take(pos) {
+                Some(compacted) => pools.spawn(PoolKind::Generator, move || {
+                    profile_scope!("restore_section");
+                    run_chunk_section_restore_task(compacted, pos, generator_ref, registry_ref);
+                }),
+                None => pools.spawn(PoolKind::Generator, move || {
+                    profile_scope!("generate_section");
+                    run_chunk_section_generation_task(chunk, pos.y, generator_ref, registry_ref);
+                }),
             }
         }
     }
 
     for chunk in generator.finished_chunks.rx.try_iter() {
         // FIXME: drop generated chunks that have since been unloaded
-        assert!(!world.chunks.pin().contains_key(&chunk.pos()));
+        if world.chunks.pin().contains_key(&chunk.pos()) {
+            log::warn!(
+                "dropping generated chunk {:?}, which was already loaded",
+                chunk.pos()
+            );
+            continue;
+        }
         world.chunks.pin().insert(chunk.pos(), Arc::clone(&chunk));
 
         send_debug_event(debug::WorldLoadEvent::Loaded(chunk.pos()));
@@ -797,7 +1281,14 @@ fn generate_world(
     for section in generator.finished_sections.rx.try_iter() {
         if let Some(chunk) = world.chunk(section.pos().column()) {
             // FIXME: drop generated chunks that have since been unloaded
-            assert!(!chunk.is_loaded(section.pos().y));
+            if chunk.is_loaded(section.pos().y) {
+                let pos = section.pos();
+                log::warn!(
+                    "dropping generated section {:?}, which was already loaded",
+                    pos
+                );
+                continue;
+            }
             chunk
                 .sections_mut()
                 .insert(section.pos().y, Arc::clone(&section));
@@ -848,6 +1339,84 @@ pub struct ChunkLoaderContext {
     prev_loaders: HashMap<Entity, (DynamicChunkLoader, ChunkSectionPos)>,
     loaded_chunk_set: HashSet<ChunkPos>,
     waiting_sections: HashMap<ChunkPos, HashSet<i32>>,
+    /// sections of loaded columns that are far enough from the surface that
+    /// they aren't loaded until a loader gets close to them vertically.
+    deferred_sections: HashMap<ChunkPos, HashSet<i32>>,
+    motion: HashMap<Entity, LoaderMotion>,
+    churn: ChunkChurn,
+    /// when the current update is happening, in seconds.
+    now: f64,
+}
+
+// how many sections above and below the surface of a column are loaded right
+// away. there needs to be at least one, since sections can't be meshed
+// without all their neighbors loaded.
+const SURFACE_BAND_PADDING: i32 = 1;
+// how close (in sections) a loader needs to get vertically to a deferred
+// section before it's loaded.
+const DEFERRED_SECTION_RADIUS: i32 = 2;
+
+/// the range of section heights that the surface of a column passes through.
+fn surface_band(heights: &generation::SurfaceHeightmap) -> RangeInclusive<i32> {
+    let bottom = crate::util::floor_div(heights.min(), CHUNK_LENGTH as i32);
+    let top = crate::util::floor_div(heights.max(), CHUNK_LENGTH as i32);
+    bottom - SURFACE_BAND_PADDING..=top + SURFACE_BAND_PADDING
+}
+
+fn loader_near_section(ctx: &ChunkLoaderContext, pos: ChunkSectionPos) -> bool {
+    ctx.prev_loaders.values().any(|&(loader, loader_pos)| {
+        let radius = loader.load_radius as i32;
+        i32::abs(loader_pos.x - pos.x) <= radius
+            && i32::abs(loader_pos.z - pos.z) <= radius
+            && i32::abs(loader_pos.y - pos.y) <= DEFERRED_SECTION_RADIUS
+    })
+}
+
+fn load_waiting_sections(
+    ctx: &mut ChunkLoaderContext,
+    modified: &ModifiedSections,
+    load_queue: &LoadQueue,
+    chunk: &Chunk,
+) {
+    let mut waiting = match ctx.waiting_sections.remove(&chunk.pos()) {
+        Some(waiting) => waiting,
+        None => return,
+    };
+    let pinned = modified.pinned(chunk.pos());
+    waiting.extend(pinned.iter().copied());
+
+    // sections that the surface passes through are the ones that are actually
+    // visible, while the ones far above or below it are usually all air or all
+    // stone, so they can wait until someone actually goes there. sections that
+    // have been built in are visible no matter where they are.
+    let band = surface_band(&chunk.heights());
+    for y in waiting {
+        let pos = chunk.pos().section(y);
+        if band.contains(&y) || pinned.contains(&y) || loader_near_section(ctx, pos) {
+            load_queue.load_section(pos);
+        } else {
+            ctx.deferred_sections
+                .entry(chunk.pos())
+                .or_default()
+                .insert(y);
+        }
+    }
+}
+
+fn load_deferred_sections(ctx: &mut ChunkLoaderContext, load_queue: &LoadQueue) {
+    let mut deferred = std::mem::take(&mut ctx.deferred_sections);
+    for (&chunk, ys) in deferred.iter_mut() {
+        ys.retain(|&y| {
+            let pos = chunk.section(y);
+            let load = loader_near_section(ctx, pos);
+            if load {
+                load_queue.load_section(pos);
+            }
+            !load
+        });
+    }
+    deferred.retain(|_, ys| !ys.is_empty());
+    ctx.deferred_sections = deferred;
 }
 
 fn recheck_loaded_chunk_sections(
@@ -871,9 +1440,18 @@ fn recheck_loaded_chunks(ctx: &mut ChunkLoaderContext, load_queue: &LoadQueue) {
     // let mut should_be_loaded_sections = HashSet::new();
     // let mut should_keep_loaded_sections = HashSet::new();
 
-    for &(loader, pos) in ctx.prev_loaders.values() {
-        for x in pos.x - loader.load_radius as i32..=pos.x + loader.load_radius as i32 {
-            for z in pos.z - loader.load_radius as i32..=pos.z + loader.load_radius as i32 {
+    for (entity, &(loader, pos)) in ctx.prev_loaders.iter() {
+        let radius = loader.load_radius as i32;
+        let [lead_x, lead_z] = match ctx.motion.get(entity) {
+            Some(motion) => motion.lead(loader.load_radius),
+            None => [0, 0],
+        };
+        // the square around the loader, stretched out in the direction it's
+        // heading
+        let xs = pos.x - radius + lead_x.min(0)..=pos.x + radius + lead_x.max(0);
+        let zs = pos.z - radius + lead_z.min(0)..=pos.z + radius + lead_z.max(0);
+        for x in xs {
+            for z in zs.clone() {
                 let chunk_pos = ChunkPos { x, z };
                 should_be_loaded.insert(chunk_pos);
                 for y in pos.y - loader.load_radius as i32..=pos.y + loader.load_radius as i32 {
@@ -883,14 +1461,20 @@ fn recheck_loaded_chunks(ctx: &mut ChunkLoaderContext, load_queue: &LoadQueue) {
         }
     }
 
+    // chunks that keep getting loaded again right after they're unloaded are
+    // held on to for longer
+    let extra = ctx.churn.extra_unload_radius() as i32;
     for &(loader, pos) in ctx.prev_loaders.values() {
-        for x in pos.x - loader.unload_radius as i32..=pos.x + loader.unload_radius as i32 {
-            for z in pos.z - loader.unload_radius as i32..=pos.z + loader.unload_radius as i32 {
+        let radius = loader.unload_radius as i32 + extra;
+        for x in pos.x - radius..=pos.x + radius {
+            for z in pos.z - radius..=pos.z + radius {
                 let chunk_pos = ChunkPos { x, z };
                 should_keep_loaded.insert(chunk_pos);
             }
         }
     }
+    // chunks loaded ahead of a loader can be past its unload radius
+    should_keep_loaded.extend(should_be_loaded.iter().copied());
 
     let to_unload: Vec<_> = ctx
         .loaded_chunk_set
@@ -898,28 +1482,33 @@ fn recheck_loaded_chunks(ctx: &mut ChunkLoaderContext, load_queue: &LoadQueue) {
         .copied()
         .collect();
 
-    let mut to_load: Vec<_> = should_be_loaded
+    // no need to sort these, the load queue orders pending loads by their
+    // distance to the nearest loader.
+    let to_load: Vec<_> = should_be_loaded
         .difference(&ctx.loaded_chunk_set)
         .copied()
         .collect();
 
-    // TODO: sort by distance to closest loader
-    to_load.sort_unstable_by_key(|pos| (pos.x, pos.z));
-
     for pos in to_load {
         load_queue.load(pos);
         ctx.loaded_chunk_set.insert(pos);
+        ctx.churn.record_load(pos, ctx.now);
     }
 
     for pos in to_unload {
         load_queue.unload(pos);
+        ctx.churn.record_unload(pos, ctx.now);
         ctx.loaded_chunk_set.remove(&pos);
         ctx.waiting_sections.remove(&pos);
+        ctx.deferred_sections.remove(&pos);
     }
+
+    load_deferred_sections(ctx, load_queue);
 }
 
 fn remove_loader(ctx: &mut ChunkLoaderContext, load_queue: &LoadQueue, entity: Entity) {
     ctx.prev_loaders.remove(&entity);
+    ctx.motion.remove(&entity);
     recheck_loaded_chunks(ctx, load_queue);
 }
 
@@ -931,9 +1520,11 @@ fn update_loader(
     pos: ChunkSectionPos,
 ) {
     if let Some(&(_, previous_pos)) = ctx.prev_loaders.get(&entity) {
+        ctx.prev_loaders.get_mut(&entity).unwrap().1 = pos;
         if previous_pos.column() != pos.column() {
-            ctx.prev_loaders.get_mut(&entity).unwrap().1 = pos;
             recheck_loaded_chunks(ctx, load_queue);
+        } else if previous_pos.y != pos.y {
+            load_deferred_sections(ctx, load_queue);
         }
     } else {
         ctx.prev_loaders.insert(entity, (*loader, pos));
@@ -941,33 +1532,125 @@ fn update_loader(
     }
 }
 
+/// newly modified sections pin the sections around them in every loaded column,
+/// see [`ModifiedSections`].
+fn load_pinned_sections(
+    ctx: &mut ChunkLoaderContext,
+    modified_sections: &mut ResMut<ModifiedSections>,
+    world: &VoxelWorld,
+    load_queue: &LoadQueue,
+    modified: ChunkSectionPos,
+) {
+    // checked first, so that the set only counts as changed (and gets saved
+    // again) when there's actually something new in it
+    if modified_sections.contains(modified) {
+        return;
+    }
+    modified_sections.insert(modified);
+
+    for dx in -1..=1 {
+        for dz in -1..=1 {
+            let column = ChunkPos {
+                x: modified.x + dx,
+                z: modified.z + dz,
+            };
+            // columns that aren't loaded pick these up once they are
+            if !ctx.loaded_chunk_set.contains(&column) || !world.is_loaded(column) {
+                continue;
+            }
+            for y in modified.y - 1..=modified.y + 1 {
+                if let Some(deferred) = ctx.deferred_sections.get_mut(&column) {
+                    deferred.remove(&y);
+                }
+                load_queue.load_section(column.section(y));
+            }
+        }
+    }
+}
+
+#[derive(SystemParam)]
+pub struct ChunkLoaderEvents<'a> {
+    removed: RemovedComponents<'a, DynamicChunkLoader>,
+    chunk_events: EventReader<'a, WorldEvent>,
+    block_updates: EventReader<'a, BlockUpdateEvent>,
+}
+
+/// the resources that [`load_chunks`] keeps up to date for everything else.
+#[derive(SystemParam)]
+pub struct ChunkLoaderResources<'a> {
+    stats: ResMut<'a, ChunkLoadStats>,
+    modified: ResMut<'a, ModifiedSections>,
+}
+
 pub fn load_chunks(
     mut ctx: Local<ChunkLoaderContext>,
+    time: Res<Time>,
+    mut resources: ChunkLoaderResources,
+    world: Res<Arc<VoxelWorld>>,
     load_queue: Res<LoadQueue>,
     query: Query<(Entity, &DynamicChunkLoader, &Transform), Changed<Transform>>,
-    removed: RemovedComponents<DynamicChunkLoader>,
-    mut chunk_events: EventReader<WorldEvent>,
+    mut events: ChunkLoaderEvents,
 ) {
-    removed
-        .iter()
-        .for_each(|entity| remove_loader(&mut ctx, &load_queue, entity));
+    let now = time.seconds_since_startup();
+    ctx.now = now;
+    ctx.churn.expire(now);
+
+    events.removed.iter().for_each(|entity| {
+        load_queue.remove_focus(entity);
+        remove_loader(&mut ctx, &load_queue, entity);
+    });
 
     query.for_each(|(entity, loader, transform)| {
+        let forward = transform.rotation.to_quaternion() * -Vector3::z();
+        load_queue.set_focus(entity, transform.pos(), forward);
+        ctx.motion
+            .entry(entity)
+            .and_modify(|motion| motion.update(transform.pos(), now))
+            .or_insert_with(|| LoaderMotion::new(transform.pos(), now));
+
         let pos = WorldPos::new(transform.translation.vector).into();
         update_loader(&mut *ctx, &load_queue, entity, loader, pos);
     });
 
-    for event in chunk_events.iter() {
-        match event {
-            WorldEvent::Loaded(chunk) => {
-                if let Some(waiting) = ctx.waiting_sections.remove(&chunk.pos()) {
-                    for &waiting in waiting.iter() {
-                        let pos = chunk.pos().section(waiting);
-                        load_queue.load_section(pos);
-                    }
-                }
-            }
-            _ => {}
+    for event in events.chunk_events.iter() {
+        if let WorldEvent::Loaded(chunk) = event {
+            load_waiting_sections(&mut ctx, &resources.modified, &load_queue, chunk);
+        }
+    }
+
+    for update in events.block_updates.iter() {
+        let section = ChunkSectionPos::from(update.pos);
+        load_pinned_sections(
+            &mut ctx,
+            &mut resources.modified,
+            &world,
+            &load_queue,
+            section,
+        );
+    }
+
+    let churn = ctx.churn.stats();
+    if *resources.stats != churn {
+        *resources.stats = churn;
+    }
+}
+
+/// writes the modified sections out to the save whenever a new one shows up.
+/// this happens right away rather than along with the sections themselves, so
+/// that a section can't be saved without being loaded again later.
+fn save_modified_sections(
+    modified: Res<ModifiedSections>,
+    mut persistence: ResMut<WorldPersistence>,
+) {
+    if !modified.is_changed() {
+        return;
+    }
+
+    let sections = modified.sections();
+    if persistence.metadata().modified_sections != sections {
+        let result = persistence.update_metadata(|meta| meta.modified_sections = sections);
+        if let Err(err) = result {
+            log::error!("failed to save modified sections: {}", err);
         }
     }
 }
@@ -985,14 +1668,51 @@ pub struct Ray3<T: Scalar> {
     pub origin: Point3<T>,
 }
 
+/// which blocks a ray can hit besides solid ones. the blocks it lets through
+/// don't have a collision shape, so they're hit anywhere inside of them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct RayFilter {
+    pub fluids: bool,
+    /// blocks that are drawn, but that entities can walk through, like grass
+    /// and ladders.
+    pub detail_blocks: bool,
+}
+
+impl RayFilter {
+    fn hits_whole_block(&self, block: &RegistryRef) -> bool {
+        match block.collision_type() {
+            CollisionType::Solid => false,
+            CollisionType::Liquid => self.fluids,
+            CollisionType::None => self.detail_blocks && block.mesh_type() != BlockMeshType::None,
+        }
+    }
+}
+
 #[must_use]
 pub fn trace_ray(cache: &mut ChunkAccess, ray: Ray3<f32>, radius: f32) -> Option<RaycastHit> {
+    trace_ray_filtered(cache, ray, radius, RayFilter::default())
+}
+
+/// like [`trace_ray`], but also hitting the kinds of non-solid blocks that
+/// `filter` asks for.
+#[must_use]
+pub fn trace_ray_filtered(
+    cache: &mut ChunkAccess,
+    ray: Ray3<f32>,
+    radius: f32,
+    filter: RayFilter,
+) -> Option<RaycastHit> {
+    let whole_block = [Aabb {
+        min: Point3::origin(),
+        max: Point3::new(1.0, 1.0, 1.0),
+    }];
     let start_block = BlockPos {
         x: ray.origin.x.floor() as i32,
         y: ray.origin.y.floor() as i32,
         z: ray.origin.z.floor() as i32,
     };
-    trace_ray_generic(ray, |pos| {
+    let mut hit_side = None;
+    let hit = trace_ray_generic(ray, |pos| {
         if block_distance_sq(start_block, pos) > radius * radius {
             return RaycastStep::Exit;
         }
@@ -1000,13 +1720,116 @@ pub fn trace_ray(cache: &mut ChunkAccess, ray: Ray3<f32>, radius: f32) -> Option
             None => return RaycastStep::Exit,
             Some(id) => id,
         };
-        match cache.registry().get(id).collision_type() {
-            CollisionType::Solid => RaycastStep::Hit,
-            _ => RaycastStep::Continue,
+
+        // only the part of a shape that's inside of its own block can be hit,
+        // since the ray has already gone past the block below it.
+        let block_bounds = util::block_aabb(pos);
+        let offset = block_bounds.min.coords;
+        let block = cache.registry().get(id);
+        let shape = match filter.hits_whole_block(&block) {
+            true => &whole_block[..],
+            false => block.collision_shape(),
+        };
+        let entry = shape
+            .iter()
+            .filter_map(|shape| {
+                let shape = shape.translated(offset);
+                let clipped = Aabb {
+                    min: shape.min,
+                    max: shape.max.inf(&block_bounds.max),
+                };
+                ray_aabb_entry(&ray, &clipped)
+            })
+            .min_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        match entry {
+            Some((_, side)) => {
+                hit_side = side;
+                RaycastStep::Hit
+            }
+            None => RaycastStep::Continue,
         }
+    })?;
+
+    Some(RaycastHit {
+        pos: hit.pos,
+        side: hit_side,
     })
 }
 
+/// how far along `ray` it enters `aabb`, and through which side. the side is
+/// `None` if the ray starts out inside of the aabb.
+fn ray_aabb_entry(ray: &Ray3<f32>, aabb: &Aabb) -> Option<(f32, Option<Side>)> {
+    let mut t_enter = f32::NEG_INFINITY;
+    let mut t_exit = f32::INFINITY;
+    let mut enter_axis = None;
+
+    for axis in [Axis::X, Axis::Y, Axis::Z] {
+        let i = axis as usize;
+        let (origin, direction) = (ray.origin[i], ray.direction[i]);
+        if direction == 0.0 {
+            if origin < aabb.min[i] || origin > aabb.max[i] {
+                return None;
+            }
+            continue;
+        }
+
+        let t0 = (aabb.min[i] - origin) / direction;
+        let t1 = (aabb.max[i] - origin) / direction;
+        if f32::min(t0, t1) > t_enter {
+            t_enter = f32::min(t0, t1);
+            enter_axis = Some(axis);
+        }
+        t_exit = f32::min(t_exit, f32::max(t0, t1));
+    }
+
+    if t_enter > t_exit || t_exit < 0.0 {
+        return None;
+    }
+    if t_enter < 0.0 {
+        return Some((0.0, None));
+    }
+
+    let side = enter_axis.map(|axis| {
+        let positive = ray.direction[axis as usize] > 0.0;
+        match axis {
+            Axis::X if positive => Side::Left,
+            Axis::X => Side::Right,
+            Axis::Y if positive => Side::Bottom,
+            Axis::Y => Side::Top,
+            Axis::Z if positive => Side::Back,
+            Axis::Z => Side::Front,
+        }
+    });
+    Some((t_enter, side))
+}
+
+/// visits every block along the straight line between the centers of `start`
+/// and `end`, including both endpoints.
+pub fn trace_line<F>(start: BlockPos, end: BlockPos, mut func: F)
+where
+    F: FnMut(BlockPos),
+{
+    let origin = Point3::from(start.origin()) + vector![0.5, 0.5, 0.5];
+    let target = Point3::from(end.origin()) + vector![0.5, 0.5, 0.5];
+    let ray = Ray3 {
+        direction: target - origin,
+        origin,
+    };
+
+    // a 6-connected line between two blocks visits exactly this many blocks, so
+    // we use it as an upper bound in case float error makes us miss `end`.
+    let mut remaining = (end.x - start.x).abs() + (end.y - start.y).abs() + (end.z - start.z).abs();
+    let _ = trace_ray_generic(ray, |pos| {
+        func(pos);
+        if pos == end || remaining == 0 {
+            return RaycastStep::Exit;
+        }
+        remaining -= 1;
+        RaycastStep::Continue
+    });
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct RaycastHit {
     pub pos: BlockPos,
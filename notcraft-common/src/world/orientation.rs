@@ -0,0 +1,167 @@
+//! blocks that can be placed facing different ways, like logs lying on their
+//! side or furnaces turned towards whoever placed them.
+//!
+//! each way an orientable block can face is one of its states, named after the
+//! side that it faces, so orientations are stored in the world like any other
+//! block state. the block's textures are described as if it were facing the
+//! first of its [`Orientable::facings`], and get turned along with it.
+
+use crate::Side;
+use nalgebra::{matrix, Matrix3, Vector3};
+use serde::Deserialize;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Orientable {
+    /// the block's top points away from the face it was placed against, like
+    /// a log.
+    Pillar,
+    /// the block's front turns towards whoever placed it, like a furnace.
+    Horizontal,
+}
+
+impl Orientable {
+    /// every way a block like this can face, starting with the way it's
+    /// described in the registry.
+    pub fn facings(self) -> &'static [Side] {
+        match self {
+            Orientable::Pillar => &[Side::Top, Side::Right, Side::Front],
+            Orientable::Horizontal => &[Side::Front, Side::Right, Side::Back, Side::Left],
+        }
+    }
+
+    /// which way a block placed against the `clicked` side of another block
+    /// faces, when whoever placed it was looking along `yaw`.
+    pub fn placement(self, clicked: Side, yaw: f32) -> Side {
+        match self {
+            // pillars don't care which way along their axis they point
+            Orientable::Pillar => match clicked {
+                Side::Top | Side::Bottom => Side::Top,
+                Side::Right | Side::Left => Side::Right,
+                Side::Front | Side::Back => Side::Front,
+            },
+            // the player looks along -Z when their yaw is 0, so the block has
+            // to face the other way to face them.
+            Orientable::Horizontal => {
+                let (x, z) = (yaw.sin(), yaw.cos());
+                match (x.abs() > z.abs(), x > 0.0, z > 0.0) {
+                    (true, true, _) => Side::Right,
+                    (true, false, _) => Side::Left,
+                    (false, _, true) => Side::Front,
+                    (false, _, false) => Side::Back,
+                }
+            }
+        }
+    }
+}
+
+/// which way a particular orientable block is facing.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Orientation {
+    pub kind: Orientable,
+    pub facing: Side,
+}
+
+impl Orientation {
+    /// turns the block's own directions into directions in the world.
+    fn rotation(&self) -> Matrix3<i32> {
+        match (self.kind, self.facing) {
+            (Orientable::Pillar, Side::Right | Side::Left) => matrix![
+                0, 1, 0;
+                -1, 0, 0;
+                0, 0, 1;
+            ],
+            (Orientable::Pillar, Side::Front | Side::Back) => matrix![
+                1, 0, 0;
+                0, 0, -1;
+                0, 1, 0;
+            ],
+            (Orientable::Horizontal, Side::Right) => matrix![
+                0, 0, 1;
+                0, 1, 0;
+                -1, 0, 0;
+            ],
+            (Orientable::Horizontal, Side::Back) => matrix![
+                -1, 0, 0;
+                0, 1, 0;
+                0, 0, -1;
+            ],
+            (Orientable::Horizontal, Side::Left) => matrix![
+                0, 0, -1;
+                0, 1, 0;
+                1, 0, 0;
+            ],
+            _ => Matrix3::identity(),
+        }
+    }
+
+    /// where a direction relative to the block ends up in the world.
+    pub fn to_world(&self, dir: Vector3<i32>) -> Vector3<i32> {
+        self.rotation() * dir
+    }
+
+    /// the side of the block, as it's described in the registry, that ends up
+    /// on `side` in the world.
+    pub fn model_side(&self, side: Side) -> Side {
+        // rotations are orthogonal, so the transpose undoes them
+        side_of(self.rotation().transpose() * side.normal::<i32>())
+    }
+}
+
+/// the name of the state that an orientable block is in when it faces `side`.
+pub fn facing_name(side: Side) -> &'static str {
+    match side {
+        Side::Top => "top",
+        Side::Bottom => "bottom",
+        Side::Right => "right",
+        Side::Left => "left",
+        Side::Front => "front",
+        Side::Back => "back",
+    }
+}
+
+fn side_of(normal: Vector3<i32>) -> Side {
+    match [normal.x, normal.y, normal.z] {
+        [1, 0, 0] => Side::Right,
+        [-1, 0, 0] => Side::Left,
+        [0, 1, 0] => Side::Top,
+        [0, -1, 0] => Side::Bottom,
+        [0, 0, 1] => Side::Front,
+        [0, 0, -1] => Side::Back,
+        _ => unreachable!("{:?} isn't the normal of a side", normal),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orientations_turn_the_block_to_face_their_side() {
+        for kind in [Orientable::Pillar, Orientable::Horizontal] {
+            let front = kind.facings()[0];
+            for &facing in kind.facings() {
+                let orientation = Orientation { kind, facing };
+                assert_eq!(orientation.to_world(front.normal()), facing.normal());
+                assert_eq!(orientation.model_side(facing), front);
+                assert_eq!(orientation.rotation().cast::<f32>().determinant(), 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn placement_follows_the_clicked_side_and_yaw() {
+        use std::f32::consts::FRAC_PI_2;
+        let pillar = Orientable::Pillar;
+        assert_eq!(pillar.placement(Side::Bottom, 0.0), Side::Top);
+        assert_eq!(pillar.placement(Side::Left, 0.0), Side::Right);
+        assert_eq!(pillar.placement(Side::Back, 0.0), Side::Front);
+
+        // looking along -Z, so the front should face +Z
+        let horizontal = Orientable::Horizontal;
+        assert_eq!(horizontal.placement(Side::Top, 0.0), Side::Front);
+        assert_eq!(horizontal.placement(Side::Top, FRAC_PI_2), Side::Right);
+        assert_eq!(horizontal.placement(Side::Right, -FRAC_PI_2), Side::Left);
+        assert_eq!(horizontal.placement(Side::Top, 2.0 * FRAC_PI_2), Side::Back);
+    }
+}
@@ -1,28 +1,931 @@
-use super::{chunk::Chunk, LoadEvents};
-use crate::prelude::*;
-use std::sync::Arc;
+//! saving worlds to disk.
+//!
+//! a save is a directory, which holds a `world.meta` file for the things that
+//! aren't tied to any particular chunk, like the seed, worldgen preset, spawn
+//! point, weather, where the player was when the game was closed, the world's
+//! [stats](crate::stats), and which sections have been
+//! [modified](super::loader::ModifiedSections). the player's components are saved through the
+//! [saved component registry](crate::saved_components). the
+//! metadata file starts with a big-endian `u64` format version, followed by a
+//! single map node. see the [`crate::codec`] module for details on how nodes
+//! are encoded.
+//!
+//! block IDs are handed out in the order blocks appear in `blocks.json`, so
+//! adding or moving a block there shifts the IDs of everything after it. so
+//! that saved chunks don't turn into the wrong blocks when that happens, saves
+//! don't use the registry's IDs directly. instead, the metadata keeps a table
+//! of block names, and a block is saved as its position in that table. the
+//! table only ever grows, and a [`BlockIdRemap`] built from it when the save is
+//! opened translates between the two kinds of IDs.
+//!
+//! chunks are saved a section at a time, each in its own file in the save's
+//! `sections` directory, named after the section's position. a section file
+//! starts with a big-endian `u64` format version, followed by the section's
+//! blocks, compressed the same way as sections sent over the network (see
+//! [`crate::net::compression`]), but with saved block IDs. sections are saved
+//! when their chunk is unloaded and whenever the world is saved, but only if
+//! they changed since they were last saved, since the rest can be generated or
+//! loaded again just the same. loading a section looks for it there before
+//! generating it. pregenerated sections are only saved where nothing has been
+//! saved yet, so they can't replace a section that was unloaded while they were
+//! being generated.
+//!
+//! sections are compressed and written out on the io pool, so that saving doesn't
+//! hold up the frame. sections waiting to be written are kept around until they
+//! are, and loading looks at those first. when the game closes, whatever is
+//! still waiting is written out right away instead, since background tasks
+//! that haven't started by then never will.
+
+use super::{
+    chunk::{Chunk, ChunkData, ChunkSectionPos, ChunkSectionSnapshot},
+    registry::{BlockId, BlockRegistry, AIR_BLOCK},
+    BlockPos, VoxelWorld,
+};
+use crate::{
+    codec::{
+        decode::{decode_node, Node},
+        encode::{encode_node, Encode, Encoder},
+        NodeKind,
+    },
+    game_mode::GameMode,
+    lang::tr,
+    net::compression::CompressedSection,
+    notification::Notify,
+    prelude::*,
+    profile_scope,
+    saved_components::SavedEntity,
+    stats::WorldStats,
+    task::{PoolKind, TaskPools},
+    util::ChannelPair,
+    weather::SavedWeather,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+pub const METADATA_FORMAT_VERSION: u64 = 1;
+pub const METADATA_FILE_NAME: &str = "world.meta";
+pub const SECTION_FORMAT_VERSION: u64 = 1;
+pub const SECTION_DIR_NAME: &str = "sections";
+
+/// asks for everything that's only saved every so often to be saved right away,
+/// like before the game quits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct SaveEvent;
+
+/// the parts of the player that stick around between runs of the game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SavedPlayer {
+    /// where the player's feet were. `None` when they were dead, so that they
+    /// respawn instead.
+    pub position: Option<[f32; 3]>,
+    pub components: SavedEntity,
+}
 
 pub struct RegionPos {
     pub x: i32,
     pub z: i32,
 }
 
+/// everything about a world that isn't stored in its chunks.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorldMetadata {
+    pub seed: Option<u64>,
+    /// the name of the [worldgen preset](super::generation::preset) the world
+    /// was made with.
+    pub worldgen: Option<String>,
+    /// where players spawn. this is only saved once a spot has been checked
+    /// against the real terrain, see [`super::spawn::WorldSpawn`].
+    pub spawn: Option<BlockPos>,
+    pub weather: Option<SavedWeather>,
+    /// `None` when the player hasn't been saved yet.
+    pub player: Option<SavedPlayer>,
+    pub stats: WorldStats,
+    /// the name of the block saved under each saved block ID. see
+    /// [`BlockIdRemap`].
+    pub block_ids: Vec<String>,
+    /// every section that's had blocks changed in it, so that the loader knows
+    /// to load them again. see [`super::loader::ModifiedSections`].
+    pub modified_sections: Vec<ChunkSectionPos>,
+}
+
+impl<W: Write> Encode<W> for WorldMetadata {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode_map(|mut map| {
+            if let Some(seed) = self.seed {
+                map.entry("seed").encode(&seed)?;
+            }
+            if let Some(worldgen) = &self.worldgen {
+                map.entry("worldgen").encode(worldgen)?;
+            }
+            if let Some(spawn) = self.spawn {
+                map.entry("spawn_x").encode(&spawn.x)?;
+                map.entry("spawn_y").encode(&spawn.y)?;
+                map.entry("spawn_z").encode(&spawn.z)?;
+            }
+            if let Some(weather) = self.weather {
+                let kind = weather.kind.name().to_string();
+                map.entry("weather").encode(&kind)?;
+                map.entry("weather_remaining").encode(&weather.remaining)?;
+            }
+            if let Some(player) = &self.player {
+                if let Some([x, y, z]) = player.position {
+                    map.entry("player_x").encode(&x)?;
+                    map.entry("player_y").encode(&y)?;
+                    map.entry("player_z").encode(&z)?;
+                }
+                map.entry("player_components").encode(&player.components)?;
+            }
+            map.entry("stats").encode(&self.stats)?;
+            if !self.block_ids.is_empty() {
+                map.entry("block_ids")
+                    .encode_verbatim_list(self.block_ids.iter())?;
+            }
+            if !self.modified_sections.is_empty() {
+                let coords = self
+                    .modified_sections
+                    .iter()
+                    .flat_map(|pos| [pos.x, pos.y, pos.z])
+                    .collect::<Vec<_>>();
+                map.entry("modified_sections")
+                    .encode_verbatim_list(coords.iter())?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl WorldMetadata {
+    fn from_node(node: &Node) -> Result<Self> {
+        let coord = |key| match node.get(key).map(Node::as_signed) {
+            Some(Some(value)) => Ok(Some(value as i32)),
+            Some(None) => bail!("field '{}' is not a signed integer", key),
+            None => Ok(None),
+        };
+
+        let seed = match node.get("seed").map(Node::as_unsigned) {
+            Some(Some(seed)) => Some(seed as u64),
+            Some(None) => bail!("field 'seed' is not an unsigned integer"),
+            None => None,
+        };
+        let worldgen = match node.get("worldgen").map(Node::as_str) {
+            Some(Some(name)) => Some(name.to_string()),
+            Some(None) => bail!("field 'worldgen' is not a string"),
+            None => None,
+        };
+
+        let spawn = match (coord("spawn_x")?, coord("spawn_y")?, coord("spawn_z")?) {
+            (Some(x), Some(y), Some(z)) => Some(BlockPos { x, y, z }),
+            _ => None,
+        };
+
+        let weather_kind = match node.get("weather").map(Node::as_str) {
+            Some(Some(name)) => Some(name.parse()?),
+            Some(None) => bail!("field 'weather' is not a string"),
+            None => None,
+        };
+        let weather_remaining = match node.get("weather_remaining").map(Node::as_f32) {
+            Some(Some(remaining)) => Some(remaining),
+            Some(None) => bail!("field 'weather_remaining' is not a float"),
+            None => None,
+        };
+        let weather = match (weather_kind, weather_remaining) {
+            (Some(kind), Some(remaining)) => Some(SavedWeather { kind, remaining }),
+            _ => None,
+        };
+
+        let float = |key| match node.get(key).map(Node::as_f32) {
+            Some(Some(value)) => Ok(Some(value)),
+            Some(None) => bail!("field '{}' is not a float", key),
+            None => Ok(None),
+        };
+        let position = match (float("player_x")?, float("player_y")?, float("player_z")?) {
+            (Some(x), Some(y), Some(z)) => Some([x, y, z]),
+            _ => None,
+        };
+        let mut components = match node.get("player_components") {
+            Some(components) => Some(SavedEntity::from_node(components)?),
+            None => None,
+        };
+
+        // older saves kept the game mode on its own, before it was saved with
+        // the rest of the player. their health isn't carried over, since they
+        // didn't save the player's max health along with it.
+        match node.get("game_mode").map(Node::as_str) {
+            Some(Some(name)) => components
+                .get_or_insert_with(SavedEntity::default)
+                .insert(&name.parse::<GameMode>()?)?,
+            Some(None) => bail!("field 'game_mode' is not a string"),
+            None => {}
+        }
+
+        let player = match (position, components) {
+            (None, None) => None,
+            (position, components) => Some(SavedPlayer {
+                position,
+                components: components.unwrap_or_default(),
+            }),
+        };
+
+        let stats = match node.get("stats") {
+            Some(stats) => WorldStats::from_node(stats)?,
+            None => WorldStats::default(),
+        };
+
+        let block_ids = match node.get("block_ids").map(Node::as_list) {
+            Some(Some(names)) => names
+                .iter()
+                .map(|name| match name.as_str() {
+                    Some(name) => Ok(name.to_string()),
+                    None => bail!("field 'block_ids' has an entry that is not a string"),
+                })
+                .collect::<Result<_>>()?,
+            Some(None) => bail!("field 'block_ids' is not a list"),
+            None => vec![],
+        };
+
+        // saved as a flat list of x, y, z triples
+        let modified_sections = match node.get("modified_sections").map(Node::as_list) {
+            Some(Some(coords)) if coords.len() % 3 == 0 => coords
+                .iter()
+                .map(|coord| match coord.as_signed() {
+                    Some(coord) => Ok(coord as i32),
+                    None => bail!("field 'modified_sections' has a non-integer coordinate"),
+                })
+                .collect::<Result<Vec<_>>>()?
+                .chunks_exact(3)
+                .map(|pos| ChunkSectionPos {
+                    x: pos[0],
+                    y: pos[1],
+                    z: pos[2],
+                })
+                .collect(),
+            Some(Some(_)) => bail!("field 'modified_sections' is not a list of positions"),
+            Some(None) => bail!("field 'modified_sections' is not a list"),
+            None => vec![],
+        };
+
+        Ok(Self {
+            seed,
+            worldgen,
+            spawn,
+            weather,
+            player,
+            stats,
+            block_ids,
+            modified_sections,
+        })
+    }
+}
+
+/// the name a block is saved under: just its name, or `name:state` for blocks
+/// that have states.
+pub(crate) fn saved_block_name(registry: &BlockRegistry, id: BlockId) -> String {
+    let block = registry.get(id);
+    match block.state_name() {
+        Some(state) => format!("{}:{}", block.name(), state),
+        None => block.name().to_string(),
+    }
+}
+
+pub(crate) fn lookup_saved_block(registry: &BlockRegistry, name: &str) -> Option<BlockId> {
+    match name.split_once(':') {
+        Some((name, state)) => registry.try_lookup_state(name, state),
+        None => registry.try_lookup(name),
+    }
+}
+
+/// translates between the block IDs in the registry and the ones a save uses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockIdRemap {
+    /// the registry ID of each saved ID.
+    from_saved: Vec<BlockId>,
+    /// the saved ID of each registry ID.
+    to_saved: Vec<BlockId>,
+    /// the names of saved blocks that aren't in the registry anymore.
+    missing: Vec<String>,
+    /// what saved blocks that aren't in the registry anymore are loaded as.
+    fallback: BlockId,
+}
+
+impl BlockIdRemap {
+    /// matches up the blocks in a save's `table` with the ones in `registry`,
+    /// adding any blocks the table doesn't have yet to the end of it.
+    pub fn new(table: &mut Vec<String>, registry: &BlockRegistry, fallback: BlockId) -> Self {
+        let mut missing = vec![];
+        let mut from_saved = table
+            .iter()
+            .map(|name| match lookup_saved_block(registry, name) {
+                Some(id) => id,
+                None => {
+                    missing.push(name.clone());
+                    fallback
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut saved_ids = table
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| (name.clone(), BlockId(idx)))
+            .collect::<HashMap<_, _>>();
+        let to_saved = registry
+            .ids()
+            .map(|id| {
+                let name = saved_block_name(registry, id);
+                *saved_ids.entry(name.clone()).or_insert_with(|| {
+                    table.push(name);
+                    from_saved.push(id);
+                    BlockId(from_saved.len() - 1)
+                })
+            })
+            .collect();
+
+        Self {
+            from_saved,
+            to_saved,
+            missing,
+            fallback,
+        }
+    }
+
+    /// the registry ID of a block that was saved as `saved`. blocks that have
+    /// been removed from the registry turn into the fallback block.
+    pub fn from_saved(&self, saved: BlockId) -> Result<BlockId> {
+        match self.from_saved.get(saved.0) {
+            Some(&id) => Ok(id),
+            None => bail!("saved block ID {} is not in the block ID table", saved.0),
+        }
+    }
+
+    /// the ID `id` should be saved as.
+    pub fn to_saved(&self, id: BlockId) -> BlockId {
+        self.to_saved[id.0]
+    }
+
+    /// the names of saved blocks that aren't in the registry anymore.
+    pub fn missing(&self) -> &[String] {
+        &self.missing
+    }
+
+    pub fn fallback(&self) -> BlockId {
+        self.fallback
+    }
+}
+
+/// reads and writes the sections in a save's `sections` directory. it's cheap
+/// to clone, so that background tasks can have their own.
+#[derive(Clone)]
+pub struct SectionStore {
+    dir: PathBuf,
+    block_remap: Arc<BlockIdRemap>,
+    // the newest snapshot of each section that's waiting to be written out in
+    // the background, along with its version.
+    pending: Arc<Mutex<HashMap<ChunkSectionPos, (u64, ChunkSectionSnapshot)>>>,
+    // the version of each section that was last put in place. it's held while
+    // a finished section file is put in place, so that the chunk unloader and
+    // the pregenerator never swap files around at the same time, and so that a
+    // section written out late can't replace a newer one.
+    written: Arc<Mutex<HashMap<ChunkSectionPos, u64>>>,
+    // numbers every write, for versions and for temporary files, so that two
+    // writers never share one.
+    next_write: Arc<AtomicU64>,
+}
+
+impl std::fmt::Debug for SectionStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SectionStore")
+            .field("dir", &self.dir)
+            .field("pending", &self.pending.lock().len())
+            .finish()
+    }
+}
+
+impl SectionStore {
+    fn path(&self, pos: ChunkSectionPos) -> PathBuf {
+        self.dir
+            .join(format!("{}.{}.{}.section", pos.x, pos.y, pos.z))
+    }
+
+    /// whether `pos` has been saved before, or is waiting to be.
+    pub fn contains(&self, pos: ChunkSectionPos) -> bool {
+        self.pending.lock().contains_key(&pos) || self.path(pos).exists()
+    }
+
+    /// writes `blocks` to a new temporary file, returning its path. like the
+    /// metadata, sections are written somewhere else first so that a crash
+    /// partway through doesn't leave half of one behind.
+    fn write_temp(&self, pos: ChunkSectionPos, blocks: &ChunkData<BlockId>) -> Result<PathBuf> {
+        let compressed = CompressedSection::compress_remapped(blocks, &self.block_remap);
+
+        let id = self.next_write.fetch_add(1, Ordering::Relaxed);
+        let name = format!("{}.{}.{}.{}.section.tmp", pos.x, pos.y, pos.z, id);
+        let temp_path = self.dir.join(name);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+
+        let mut out = BufWriter::new(file);
+        let written = out
+            .write_all(&SECTION_FORMAT_VERSION.to_be_bytes())
+            .and_then(|_| out.write_all(compressed.as_bytes()))
+            .and_then(|_| out.flush());
+        if let Err(err) = written {
+            drop(out);
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(err.into());
+        }
+        Ok(temp_path)
+    }
+
+    /// puts the section file at `temp_path` in place, unless a newer version
+    /// of the section beat it there.
+    fn put_in_place(&self, pos: ChunkSectionPos, version: u64, temp_path: &Path) -> Result<()> {
+        let mut written = self.written.lock();
+        let latest = written.entry(pos).or_default();
+        if *latest >= version {
+            std::fs::remove_file(temp_path)?;
+            return Ok(());
+        }
+        std::fs::rename(temp_path, self.path(pos))?;
+        *latest = version;
+        Ok(())
+    }
+
+    /// saves `blocks` as the section at `pos` right away, replacing whatever was
+    /// saved there before.
+    pub fn save(&self, pos: ChunkSectionPos, blocks: &ChunkData<BlockId>) -> Result<()> {
+        let version = self.next_write.fetch_add(1, Ordering::Relaxed);
+        let temp_path = self.write_temp(pos, blocks)?;
+        self.put_in_place(pos, version, &temp_path)
+    }
+
+    /// queues `section` to be saved on the io pool. if it's queued again
+    /// before that happens, only the newest snapshot is written. errors are
+    /// sent to `failed`.
+    pub fn save_in_background(
+        &self,
+        pools: &TaskPools,
+        section: ChunkSectionSnapshot,
+        failed: crossbeam_channel::Sender<(ChunkSectionPos, anyhow::Error)>,
+    ) {
+        let pos = section.pos();
+        let version = self.next_write.fetch_add(1, Ordering::Relaxed);
+        self.pending.lock().insert(pos, (version, section));
+
+        let store = self.clone();
+        pools.spawn(PoolKind::Io, move || {
+            profile_scope!("save_section");
+            if let Err(err) = store.write_pending(pos) {
+                let _ = failed.send((pos, err));
+            }
+        });
+    }
+
+    /// writes out the section at `pos` if it's still waiting to be saved.
+    fn write_pending(&self, pos: ChunkSectionPos) -> Result<()> {
+        let (version, section) = match self.pending.lock().get(&pos) {
+            Some((version, section)) => (*version, section.clone()),
+            None => return Ok(()),
+        };
+
+        let temp_path = self.write_temp(pos, section.blocks())?;
+        self.put_in_place(pos, version, &temp_path)?;
+
+        // it stays waiting until it's in place, so that loading it in the
+        // meantime doesn't find an older version
+        let mut pending = self.pending.lock();
+        if matches!(pending.get(&pos), Some(&(latest, _)) if latest == version) {
+            pending.remove(&pos);
+        }
+        Ok(())
+    }
+
+    /// writes out every section that's waiting to be saved on this thread,
+    /// instead of waiting on the io pool.
+    pub fn flush(&self) -> Result<()> {
+        let waiting = self.pending.lock().keys().copied().collect::<Vec<_>>();
+        let mut result = Ok(());
+        for pos in waiting {
+            if let Err(err) = self.write_pending(pos) {
+                log::error!("failed to save section {:?}: {}", pos, err);
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    /// saves `blocks` as the section at `pos` only if nothing was saved there
+    /// yet, returning whether it was. freshly generated sections are saved
+    /// this way, so that they never replace a section that was unloaded while
+    /// they were being generated, which might have been built in.
+    pub fn save_new(&self, pos: ChunkSectionPos, blocks: &ChunkData<BlockId>) -> Result<bool> {
+        let temp_path = self.write_temp(pos, blocks)?;
+        let _written = self.written.lock();
+        // unlike renaming, linking fails instead of replacing a file that's
+        // already there.
+        let linked = std::fs::hard_link(&temp_path, self.path(pos));
+        std::fs::remove_file(&temp_path)?;
+        match linked {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// the blocks that were saved for `pos`, or `None` if it was never saved.
+    pub fn load(&self, pos: ChunkSectionPos) -> Result<Option<ChunkData<BlockId>>> {
+        if let Some((_, section)) = self.pending.lock().get(&pos) {
+            return Ok(Some(section.blocks().clone()));
+        }
+
+        let bytes = match std::fs::read(self.path(pos)) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        if bytes.len() < 8 {
+            bail!("section file ended before its format version");
+        }
+        let (version, rest) = bytes.split_at(8);
+        let version = u64::from_be_bytes(version.try_into().unwrap());
+        if version != SECTION_FORMAT_VERSION {
+            bail!("unsupported section format version {}", version);
+        }
+
+        let compressed = CompressedSection::from_bytes(rest.to_vec());
+        Ok(Some(compressed.decompress_remapped(&self.block_remap)?))
+    }
+}
+
 pub struct WorldPersistence {
     // loaded_in_region: HashMap<RegionPos, HashSet<>>,
+    /// `None` for worlds that aren't saved anywhere.
+    save_dir: Option<PathBuf>,
+    metadata: WorldMetadata,
+    block_remap: BlockIdRemap,
+    sections: Option<SectionStore>,
+    failed_saves: ChannelPair<(ChunkSectionPos, anyhow::Error)>,
 }
 
 impl WorldPersistence {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(registry: &BlockRegistry) -> Self {
+        let mut metadata = WorldMetadata::default();
+        let block_remap = BlockIdRemap::new(&mut metadata.block_ids, registry, AIR_BLOCK);
+        Self {
+            save_dir: None,
+            metadata,
+            block_remap,
+            sections: None,
+            failed_saves: Default::default(),
+        }
+    }
+
+    /// opens the save in `dir`, creating it if it doesn't exist yet. blocks in
+    /// the save that aren't in `registry` anymore are loaded as `fallback`.
+    pub fn open(dir: &Path, registry: &BlockRegistry, fallback: BlockId) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+
+        let metadata_path = dir.join(METADATA_FILE_NAME);
+        let mut metadata = match metadata_path.exists() {
+            true => read_metadata(&metadata_path)?,
+            false => WorldMetadata::default(),
+        };
+
+        let saved_blocks = metadata.block_ids.len();
+        let block_remap = BlockIdRemap::new(&mut metadata.block_ids, registry, fallback);
+        for name in block_remap.missing() {
+            log::error!(
+                "saved block '{}' is not in the block registry anymore, loading it as '{}'",
+                name,
+                registry.name(fallback)
+            );
+        }
+
+        let section_dir = dir.join(SECTION_DIR_NAME);
+        std::fs::create_dir_all(&section_dir)?;
+        let sections = SectionStore {
+            dir: section_dir,
+            block_remap: Arc::new(block_remap.clone()),
+            pending: Default::default(),
+            written: Default::default(),
+            next_write: Arc::new(AtomicU64::new(1)),
+        };
+
+        let persistence = Self {
+            save_dir: Some(dir.into()),
+            metadata,
+            block_remap,
+            sections: Some(sections),
+            failed_saves: Default::default(),
+        };
+        // blocks that were just added to the table need to be written down
+        // before any chunks that use them are saved.
+        if persistence.metadata.block_ids.len() != saved_blocks {
+            persistence.flush()?;
+        }
+        Ok(persistence)
+    }
+
+    pub fn save_dir(&self) -> Option<&Path> {
+        self.save_dir.as_deref()
+    }
+
+    pub fn metadata(&self) -> &WorldMetadata {
+        &self.metadata
+    }
+
+    pub fn block_remap(&self) -> &BlockIdRemap {
+        &self.block_remap
+    }
+
+    /// where sections are saved. `None` for worlds that aren't saved anywhere.
+    pub fn sections(&self) -> Option<&SectionStore> {
+        self.sections.as_ref()
+    }
+
+    /// changes the world's metadata and writes it out to the save right away,
+    /// if there is one.
+    pub fn update_metadata<F>(&mut self, func: F) -> Result<()>
+    where
+        F: FnOnce(&mut WorldMetadata),
+    {
+        func(&mut self.metadata);
+        self.flush()
+    }
+
+    /// writes the metadata out again, in case an earlier write didn't make it.
+    pub fn flush(&self) -> Result<()> {
+        match &self.save_dir {
+            Some(dir) => write_metadata(&dir.join(METADATA_FILE_NAME), &self.metadata),
+            None => Ok(()),
+        }
+    }
+
+    /// queues every section of `chunk` that changed since it was last saved to
+    /// be written out on the io pool, including the ones that were unloaded
+    /// without the rest of the chunk.
+    pub fn save_chunk(&self, chunk: &Chunk, pools: &TaskPools) {
+        let sections = match &self.sections {
+            Some(sections) => sections,
+            None => return,
+        };
+
+        let loaded = chunk.sections();
+        let unloaded = chunk.unloaded_sections();
+        for section in loaded.values().chain(unloaded.values()) {
+            if section.take_needs_persistence() {
+                let failed = self.failed_saves.sender();
+                sections.save_in_background(pools, section.snapshot(), failed);
+            }
+        }
+    }
+
+    /// writes out every section that's still waiting to be saved, blocking
+    /// until it's done. this is for when the game is closing, since the io
+    /// pool doesn't start anything new once it's been shut down.
+    pub fn flush_sections(&self) -> Result<()> {
+        match &self.sections {
+            Some(sections) => sections.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+fn read_metadata(path: &Path) -> Result<WorldMetadata> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut version = [0; 8];
+    reader.read_exact(&mut version)?;
+    let version = u64::from_be_bytes(version);
+    if version != METADATA_FORMAT_VERSION {
+        bail!("unsupported world metadata version {}", version);
     }
 
-    pub fn save_chunk(&mut self, chunk: &Arc<Chunk>) -> Result<()> {
-        todo!()
+    WorldMetadata::from_node(&decode_node(&mut reader)?)
+}
+
+fn write_metadata(path: &Path, metadata: &WorldMetadata) -> Result<()> {
+    // write to a temporary file first, so that crashing partway through doesn't
+    // leave us with a corrupted save.
+    let temp_path = path.with_extension("meta.tmp");
+    let mut out = BufWriter::new(File::create(&temp_path)?);
+    out.write_all(&METADATA_FORMAT_VERSION.to_be_bytes())?;
+    encode_node(&mut out, metadata)?;
+    out.into_inner()?.sync_all()?;
+
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// saves every loaded chunk whenever the world is saved, since they're
+/// otherwise only saved once they're unloaded, and reports sections that
+/// couldn't be saved in the background.
+pub fn update_persistence(
+    persistence: Res<WorldPersistence>,
+    world: Res<Arc<VoxelWorld>>,
+    pools: Res<TaskPools>,
+    mut save_events: EventReader<SaveEvent>,
+    mut notify_events: EventWriter<Notify>,
+) {
+    if save_events.iter().count() > 0 {
+        for chunk in world.chunks.pin().values() {
+            persistence.save_chunk(chunk, &pools);
+        }
     }
 
-    pub fn load_chunk(&mut self) -> Result<Chunk> {
-        todo!()
+    let mut failed = None;
+    for (pos, err) in persistence.failed_saves.rx.try_iter() {
+        log::error!("failed to save section {:?}: {}", pos, err);
+        failed = Some(err);
+    }
+    // one notification is plenty, even if every section failed the same way
+    if let Some(err) = failed {
+        notify_events.send(Notify::error(tr!("notify.save_failed", err)));
     }
 }
 
-pub fn update_persistence(persistence: ResMut<WorldPersistence>, load_events: LoadEvents) {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        chunk::{self, ArrayChunk, ChunkAccess},
+        loader::ModifiedSections,
+        test_util::{flat_world, test_registry},
+        ChunkPos,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn saved_ids_follow_blocks_around_the_registry() {
+        let registry = test_registry();
+        let stone = registry.lookup("stone");
+
+        // a fresh save numbers blocks the same way the registry does
+        let mut table = vec![];
+        let remap = BlockIdRemap::new(&mut table, &registry, AIR_BLOCK);
+        assert_eq!(table.len(), registry.ids().count());
+        assert!(registry.ids().all(|id| remap.to_saved(id) == id));
+
+        // a save made when the registry was in a different order, and had a
+        // block that's since been removed
+        table.reverse();
+        table.insert(3, "removed".into());
+        let saved_stone = table.iter().position(|name| name == "stone").unwrap();
+        let len = table.len();
+        let remap = BlockIdRemap::new(&mut table, &registry, stone);
+
+        assert_eq!(table.len(), len);
+        assert_eq!(remap.from_saved(BlockId(saved_stone)).unwrap(), stone);
+        assert_eq!(remap.to_saved(stone), BlockId(saved_stone));
+        assert_eq!(remap.from_saved(BlockId(3)).unwrap(), stone);
+        assert_eq!(remap.missing(), ["removed".to_string()]);
+        assert!(remap.from_saved(BlockId(len)).is_err());
+        for id in registry.ids() {
+            assert_eq!(remap.from_saved(remap.to_saved(id)).unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn block_ids_are_saved_in_the_metadata() {
+        let mut metadata = WorldMetadata {
+            seed: Some(5),
+            worldgen: Some("amplified".into()),
+            ..Default::default()
+        };
+        BlockIdRemap::new(&mut metadata.block_ids, &test_registry(), AIR_BLOCK);
+
+        let mut bytes = vec![];
+        encode_node(&mut bytes, &metadata).unwrap();
+        let decoded = WorldMetadata::from_node(&decode_node(&mut &bytes[..]).unwrap()).unwrap();
+        assert_eq!(decoded, metadata);
+    }
+
+    #[test]
+    fn sections_are_loaded_the_way_they_were_saved() {
+        let registry = test_registry();
+        let [stone, dirt] = [registry.lookup("stone"), registry.lookup("dirt")];
+        let dir = std::env::temp_dir().join(format!("notcraft-sections-{}", std::process::id()));
+
+        let mut blocks = ArrayChunk::homogeneous(stone);
+        blocks[[3, 4, 5]] = dirt;
+        let blocks = ChunkData::Array(blocks);
+        let pos = ChunkSectionPos { x: -2, y: 1, z: 7 };
+        let persistence = WorldPersistence::open(&dir, &registry, AIR_BLOCK).unwrap();
+        let sections = persistence.sections().unwrap();
+        assert!(!sections.contains(pos));
+        assert!(sections.load(pos).unwrap().is_none());
+        sections.save(pos, &blocks).unwrap();
+        drop(persistence);
+
+        // opened again, like the next time the game starts
+        let persistence = WorldPersistence::open(&dir, &registry, AIR_BLOCK).unwrap();
+        let loaded = persistence.sections().unwrap().load(pos);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.unwrap(), Some(blocks));
+    }
+
+    #[test]
+    fn modified_sections_are_pinned_after_reopening() {
+        let registry = test_registry();
+        let dir = std::env::temp_dir().join(format!("notcraft-modified-{}", std::process::id()));
+
+        let mut modified = ModifiedSections::default();
+        modified.insert(ChunkSectionPos { x: 4, y: 30, z: -2 });
+        modified.insert(ChunkSectionPos { x: 4, y: -8, z: -2 });
+        let mut persistence = WorldPersistence::open(&dir, &registry, AIR_BLOCK).unwrap();
+        let sections = modified.sections();
+        let saved = persistence.update_metadata(|meta| meta.modified_sections = sections);
+        drop(persistence);
+
+        // opened again, the way `WorldPlugin` sets up the loader
+        let persistence = WorldPersistence::open(&dir, &registry, AIR_BLOCK);
+        std::fs::remove_dir_all(&dir).unwrap();
+        saved.unwrap();
+        let persistence = persistence.unwrap();
+        let sections = &persistence.metadata().modified_sections;
+        let reopened = ModifiedSections::from_sections(sections.iter().copied());
+
+        let mut pinned = reopened
+            .pinned(ChunkPos { x: 5, z: -2 })
+            .into_iter()
+            .collect::<Vec<_>>();
+        pinned.sort_unstable();
+        assert_eq!(pinned, [-9, -8, -7, 29, 30, 31]);
+        assert_eq!(reopened.sections(), modified.sections());
+    }
+
+    #[test]
+    fn only_changed_sections_are_saved() {
+        let registry = test_registry();
+        let [stone, dirt] = [registry.lookup("stone"), registry.lookup("dirt")];
+        let dir = std::env::temp_dir().join(format!("notcraft-changed-{}", std::process::id()));
+        let persistence = WorldPersistence::open(&dir, &registry, AIR_BLOCK).unwrap();
+        let sections = persistence.sections().unwrap();
+
+        let world = flat_world(&registry, 1, stone);
+        let mut access = ChunkAccess::new(&world);
+        access.set_block(BlockPos::new([3, -5, 3]), dirt);
+        let mut rebuild = chunk::DirtySections::new();
+        chunk::write_all_chunk_updates(&mut access, &mut rebuild, &mut HashMap::default());
+
+        // saved the same way as closing the game does, with the io pool shut
+        // down before it gets to everything
+        let chunk = world.chunk(ChunkPos { x: 0, z: 0 }).unwrap();
+        let pools = TaskPools::new(&[]).unwrap();
+        persistence.save_chunk(&chunk, &pools);
+        pools.shut_down(Duration::from_secs(5));
+        let flushed = persistence.flush_sections();
+
+        let edited = ChunkSectionPos { x: 0, y: -1, z: 0 };
+        let untouched = [0, 1].map(|y| ChunkSectionPos { x: 0, y, z: 0 });
+        let files = std::fs::read_dir(dir.join(SECTION_DIR_NAME)).unwrap();
+        let saved = files.count();
+        let loaded = sections.load(edited);
+        let untouched_saved = untouched.map(|pos| sections.contains(pos));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        flushed.unwrap();
+        assert_eq!(saved, 1);
+        assert_eq!(untouched_saved, [false, false]);
+        let loaded = loaded.unwrap().unwrap();
+        assert_eq!(loaded.get([3, 27, 3]), dirt);
+        assert_eq!(loaded.get([0, 0, 0]), stone);
+    }
+
+    #[test]
+    fn dead_players_keep_their_components() {
+        let mut components = SavedEntity::default();
+        components.insert(&GameMode::Survival).unwrap();
+        let metadata = WorldMetadata {
+            player: Some(SavedPlayer {
+                position: None,
+                components,
+            }),
+            ..Default::default()
+        };
+
+        let mut bytes = vec![];
+        encode_node(&mut bytes, &metadata).unwrap();
+        let decoded = WorldMetadata::from_node(&decode_node(&mut &bytes[..]).unwrap()).unwrap();
+        assert_eq!(decoded, metadata);
+
+        let player = decoded.player.unwrap();
+        assert_eq!(
+            player.components.get::<GameMode>().unwrap(),
+            Some(GameMode::Survival)
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! generating an area of the world ahead of time, so that exploring it later
+//! doesn't have to wait on the generator.
+//!
+//! columns queued in the [`PregenQueue`] are handed to the generator pool a few
+//! at a time, nearest first, but only while nothing else needs loading and the
+//! pool has nothing else waiting, so chunks being loaded around players always
+//! come first. each column has the same sections generated that loading it
+//! would start with, the ones its surface passes through.
+//!
+//! finished sections are written to the save, where loading looks before
+//! generating anything. worlds that aren't saved anywhere keep them in the cache
+//! of unloaded sections instead, so they only last until the game is closed, and
+//! pregeneration stops early once the cache is nearly full, rather than pushing
+//! out the sections it made first.
+//!
+//! [`pregenerate_saved`] does the same thing without running the game, for the
+//! `--pregen` command line option.
+
+use super::{
+    chunk::CompactedChunkSection, surface_band, ChunkPos, LoadQueue, VoxelWorld, WorldGenerator,
+};
+use crate::{
+    lang::tr,
+    notification::Notify,
+    prelude::*,
+    profile_scope,
+    task::{PoolKind, TaskPools},
+    util::ChannelPair,
+};
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+};
+
+/// the furthest out from its center, in chunks, that pregeneration can reach.
+pub const MAX_PREGEN_RADIUS: i32 = 32;
+
+// pregeneration stops once the unloaded section cache is this full.
+const CACHE_FILL_LIMIT: f64 = 0.9;
+
+#[derive(Debug, Default)]
+pub struct PregenQueue {
+    pending: VecDeque<ChunkPos>,
+    queued: HashSet<ChunkPos>,
+    // columns that were handed to the generator pool and haven't finished yet.
+    running: usize,
+    // how many columns have been queued and finished since the queue was last
+    // empty, for reporting progress.
+    total: usize,
+    done: usize,
+    // how many quarters of the way through have been reported.
+    reported: usize,
+    // bumped every time pregeneration is cancelled, so that columns that were
+    // still running when it was aren't counted towards the next run.
+    run: u64,
+    finished: ChannelPair<u64>,
+}
+
+impl PregenQueue {
+    /// queues every column up to `radius` chunks away from `center`, nearest
+    /// first, skipping the ones that are already waiting. returns how many were
+    /// queued.
+    pub fn queue_around(&mut self, center: ChunkPos, radius: i32) -> usize {
+        let mut columns = vec![];
+        for x in center.x - radius..=center.x + radius {
+            for z in center.z - radius..=center.z + radius {
+                columns.push(ChunkPos { x, z });
+            }
+        }
+        let distance = |pos: &ChunkPos| {
+            let [dx, dz] = [pos.x - center.x, pos.z - center.z];
+            dx * dx + dz * dz
+        };
+        columns.sort_by_key(distance);
+
+        let before = self.pending.len();
+        for pos in columns {
+            if self.queued.insert(pos) {
+                self.pending.push_back(pos);
+            }
+        }
+        let added = self.pending.len() - before;
+        self.total += added;
+        added
+    }
+
+    /// drops every column that hasn't been started yet, returning how many
+    /// there were. columns that are already being generated still finish.
+    pub fn cancel(&mut self) -> usize {
+        let dropped = self.pending.len();
+        self.reset();
+        self.run += 1;
+        dropped
+    }
+
+    /// how many columns haven't been finished yet.
+    pub fn remaining(&self) -> usize {
+        self.pending.len() + self.running
+    }
+
+    fn reset(&mut self) {
+        self.pending.clear();
+        self.queued.clear();
+        self.running = 0;
+        self.total = 0;
+        self.done = 0;
+        self.reported = 0;
+    }
+
+    fn next(&mut self) -> Option<ChunkPos> {
+        let pos = self.pending.pop_front()?;
+        self.queued.remove(&pos);
+        Some(pos)
+    }
+
+    /// the percentage to tell the player about, if pregeneration has gotten
+    /// another quarter of the way through since it was last reported.
+    fn progress(&mut self) -> Option<usize> {
+        if self.total == 0 {
+            return None;
+        }
+        let quarters = 4 * self.done / self.total;
+        if quarters <= self.reported {
+            return None;
+        }
+        self.reported = quarters;
+        Some(25 * quarters)
+    }
+}
+
+fn pregenerate_column(generator: &WorldGenerator, column: ChunkPos) -> Result<()> {
+    let heights =
+        generator
+            .surface_cache
+            .surface_heights(generator.seed, &generator.preset, column);
+    for y in surface_band(&heights) {
+        let pos = column.section(y);
+        // sections that are already saved or cached came from chunks that were
+        // unloaded, and might have been built in since they were generated.
+        let saved = generator
+            .store
+            .as_ref()
+            .is_some_and(|store| store.contains(pos));
+        if saved || generator.unload_cache.lock().contains(pos) {
+            continue;
+        }
+        let data = generator
+            .generator
+            .make_chunk(generator.seed, pos, &heights);
+
+        if let Some(store) = &generator.store {
+            store.save_new(pos, &data)?;
+            continue;
+        }
+        let compacted = CompactedChunkSection::compact(&data);
+        let mut cache = generator.unload_cache.lock();
+        if !cache.contains(pos) {
+            cache.insert(pos, compacted);
+        }
+    }
+    Ok(())
+}
+
+fn cache_is_full(generator: &WorldGenerator) -> bool {
+    let cache = generator.unload_cache.lock();
+    cache.used_bytes() as f64 >= CACHE_FILL_LIMIT * cache.capacity_bytes() as f64
+}
+
+pub(super) fn pregenerate_chunks(
+    world: Res<Arc<VoxelWorld>>,
+    generator: Res<Arc<WorldGenerator>>,
+    load_queue: Res<LoadQueue>,
+    pools: Res<TaskPools>,
+    mut queue: ResMut<PregenQueue>,
+    mut notify_events: EventWriter<Notify>,
+) {
+    let run = queue.run;
+    let finished = queue.finished.rx.try_iter().filter(|&id| id == run).count();
+    queue.running -= finished;
+    queue.done += finished;
+    if queue.total == 0 {
+        return;
+    }
+
+    let pool = pools.get(PoolKind::Generator).stats();
+    let mut slots = match load_queue.has_pending_loads() || pool.queued > 0 {
+        true => 0,
+        false => pool.threads.saturating_sub(queue.running),
+    };
+    while slots > 0 {
+        if generator.store.is_none() && cache_is_full(&generator) {
+            let left = queue.remaining();
+            queue.cancel();
+            notify_events.send(Notify::warning(tr!("notify.pregen_cache_full", left)));
+            return;
+        }
+        let column = match queue.next() {
+            Some(column) => column,
+            None => break,
+        };
+        // loaded columns already have everything pregeneration would give them
+        if world.is_loaded(column) {
+            queue.done += 1;
+            continue;
+        }
+
+        let generator = Arc::clone(&generator);
+        let finished = queue.finished.sender();
+        pools.spawn(PoolKind::Generator, move || {
+            profile_scope!("pregenerate_column");
+            if let Err(err) = pregenerate_column(&generator, column) {
+                log::error!("failed to pregenerate column {:?}: {}", column, err);
+            }
+            let _ = finished.send(run);
+        });
+        queue.running += 1;
+        slots -= 1;
+    }
+
+    if let Some(percent) = queue.progress() {
+        let message = match queue.remaining() {
+            0 => tr!("notify.pregen_done", queue.total),
+            _ => tr!("notify.pregen_progress", percent, queue.total),
+        };
+        notify_events.send(Notify::info(message));
+    }
+    if queue.remaining() == 0 {
+        queue.reset();
+    }
+}
+
+/// generates and saves every column up to `radius` chunks from `center`,
+/// without running the game. `progress` is called with how many columns are
+/// done, and how many there are in total, each time one finishes.
+pub fn pregenerate_saved<F>(
+    generator: &Arc<WorldGenerator>,
+    pools: &TaskPools,
+    center: ChunkPos,
+    radius: i32,
+    mut progress: F,
+) -> Result<()>
+where
+    F: FnMut(usize, usize),
+{
+    if generator.store.is_none() {
+        bail!("pregenerated chunks can only be kept in a world that's saved somewhere");
+    }
+
+    let mut queue = PregenQueue::default();
+    let total = queue.queue_around(center, radius);
+    let finished = ChannelPair::default();
+    while let Some(column) = queue.next() {
+        let generator = Arc::clone(generator);
+        let finished = finished.sender();
+        pools.spawn(PoolKind::Generator, move || {
+            profile_scope!("pregenerate_column");
+            let result = pregenerate_column(&generator, column);
+            let _ = finished.send(result.map_err(|err| (column, err)));
+        });
+    }
+
+    for done in 1..=total {
+        if let Err((column, err)) = finished.rx.recv()? {
+            bail!("failed to pregenerate column {:?}: {}", column, err);
+        }
+        progress(done, total);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        chunk::{self, Chunk, ChunkAccess, ChunkData, ChunkSection, CHUNK_LENGTH_3},
+        generation::preset::WorldGenPreset,
+        persistence::WorldPersistence,
+        registry::{BlockId, AIR_BLOCK},
+        test_util::test_registry,
+        BlockPos,
+    };
+    use std::{collections::HashMap, sync::Barrier};
+
+    #[test]
+    fn columns_are_queued_nearest_first() {
+        let mut queue = PregenQueue::default();
+        let center = ChunkPos { x: 3, z: -2 };
+        assert_eq!(queue.queue_around(center, 2), 25);
+        // columns that are already waiting aren't queued twice
+        assert_eq!(queue.queue_around(ChunkPos { x: 4, z: -2 }, 2), 5);
+
+        assert_eq!(queue.next(), Some(center));
+        let rest = std::iter::from_fn(|| queue.next()).collect::<Vec<_>>();
+        assert_eq!(rest.len(), 29);
+        assert!(rest[..4].iter().all(|pos| {
+            let [dx, dz] = [pos.x - center.x, pos.z - center.z];
+            dx * dx + dz * dz == 1
+        }));
+    }
+
+    #[test]
+    fn cancelling_drops_what_was_left() {
+        let mut queue = PregenQueue::default();
+        queue.queue_around(ChunkPos { x: 0, z: 0 }, 1);
+        queue.next();
+        queue.running += 1;
+        assert_eq!(queue.remaining(), 9);
+
+        assert_eq!(queue.cancel(), 8);
+        assert_eq!(queue.remaining(), 0);
+        assert_eq!(queue.progress(), None);
+    }
+
+    #[test]
+    fn pregenerated_columns_are_saved() {
+        let registry = test_registry();
+        let dir = std::env::temp_dir().join(format!("notcraft-pregen-{}", std::process::id()));
+        let persistence = WorldPersistence::open(&dir, &registry, AIR_BLOCK).unwrap();
+        let store = persistence.sections().cloned();
+        let generator = WorldGenerator::new(&registry, 3, WorldGenPreset::default(), store);
+        let generator = Arc::new(generator);
+
+        let mut reported = vec![];
+        let pools = TaskPools::new(&[]).unwrap();
+        let center = ChunkPos { x: 1, z: -1 };
+        let result = pregenerate_saved(&generator, &pools, center, 1, |done, total| {
+            reported.push((done, total))
+        });
+        // the bottom of the surface in the corner furthest from the center
+        let column = ChunkPos { x: 2, z: -2 };
+        let heights = generator
+            .surface_cache
+            .surface_heights(3, &generator.preset, column);
+        let pos = column.section(*surface_band(&heights).start());
+        let expected = generator.generator.make_chunk(3, pos, &heights);
+        let saved = generator.store.as_ref().unwrap().load(pos);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        result.unwrap();
+        assert_eq!(reported.last(), Some(&(9, 9)));
+        // saving squashes sections made of one block down, so they're compared
+        // block by block
+        let saved = saved.unwrap().unwrap();
+        let blocks = |data: &ChunkData<BlockId>| {
+            (0..CHUNK_LENGTH_3)
+                .map(|i| data.get([i / 1024, i / 32 % 32, i % 32]))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(blocks(&saved), blocks(&expected));
+    }
+
+    #[test]
+    fn pregeneration_never_replaces_unloaded_sections() {
+        let registry = test_registry();
+        let dir = std::env::temp_dir().join(format!("notcraft-pregen-race-{}", std::process::id()));
+        let persistence = WorldPersistence::open(&dir, &registry, AIR_BLOCK).unwrap();
+        let store = persistence.sections().cloned();
+        let generator = WorldGenerator::new(&registry, 3, WorldGenPreset::default(), store);
+        let generator = Arc::new(generator);
+        let [stone, dirt] = [registry.lookup("stone"), registry.lookup("dirt")];
+        let pools = TaskPools::new(&[]).unwrap();
+
+        // a block of dirt is placed in a section made of stone, and its chunk is
+        // unloaded at the same time as its column is pregenerated. whichever
+        // finishes first, the dirt has to be what's left in the save.
+        let mut saved = vec![];
+        for x in 0..16 {
+            let column = ChunkPos { x, z: 0 };
+            let heights = generator
+                .surface_cache
+                .surface_heights(3, &generator.preset, column);
+            let pos = column.section(*surface_band(&heights).start());
+            let chunk = Chunk::initialize(column, heights);
+            let data = ChunkData::Homogeneous(stone);
+            let section = ChunkSection::initialize(pos, data, &registry);
+            chunk.sections_mut().insert(pos.y, Arc::new(section));
+            let chunk = Arc::new(chunk);
+
+            let world = VoxelWorld::new(&registry);
+            world.insert_chunk(Arc::clone(&chunk));
+            let mut access = ChunkAccess::new(&world);
+            access.set_block(BlockPos::new([pos.origin().x, pos.origin().y, 0]), dirt);
+            let mut rebuild = chunk::DirtySections::new();
+            chunk::write_all_chunk_updates(&mut access, &mut rebuild, &mut HashMap::default());
+
+            let barrier = Barrier::new(2);
+            std::thread::scope(|scope| {
+                let pregen = scope.spawn(|| {
+                    barrier.wait();
+                    pregenerate_column(&generator, column)
+                });
+                barrier.wait();
+                persistence.save_chunk(&chunk, &pools);
+                persistence.flush_sections().unwrap();
+                pregen.join().unwrap().unwrap();
+            });
+
+            let store = generator.store.as_ref().unwrap();
+            saved.push(store.load(pos));
+        }
+        pools.shut_down(std::time::Duration::from_secs(5));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        for saved in saved {
+            let saved = saved.unwrap().unwrap();
+            assert_eq!(saved.get([0, 0, 0]), dirt);
+            assert_eq!(saved.get([1, 1, 1]), stone);
+        }
+    }
+}
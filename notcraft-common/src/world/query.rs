@@ -0,0 +1,165 @@
+//! searching the world for blocks.
+//!
+//! both of the searches here work a section at a time, so that homogeneous
+//! sections (which are most of the world, like the sky or deep underground)
+//! can be accepted or skipped with a single test, rather than one test per
+//! block.
+
+use super::{
+    chunk::{ChunkAccess, ChunkData, ChunkSectionPos, CHUNK_LENGTH},
+    registry::BlockId,
+    BlockPos,
+};
+
+fn distance_sq(a: BlockPos, b: BlockPos) -> i64 {
+    let [dx, dy, dz] = [a.x - b.x, a.y - b.y, a.z - b.z].map(|n| n as i64);
+    dx * dx + dy * dy + dz * dz
+}
+
+fn section_bounds(pos: ChunkSectionPos) -> (BlockPos, BlockPos) {
+    let min = pos.origin();
+    let last = CHUNK_LENGTH as i32 - 1;
+    (min, min.offset([last, last, last]))
+}
+
+// every section whose chebyshev distance (in sections) from `center` is
+// exactly `radius`.
+fn section_shell(center: ChunkSectionPos, radius: i32) -> impl Iterator<Item = ChunkSectionPos> {
+    let range = move || -radius..=radius;
+    range().flat_map(move |dx| {
+        range().flat_map(move |dy| {
+            range()
+                .filter(move |&dz| dx.abs() == radius || dy.abs() == radius || dz.abs() == radius)
+                .map(move |dz| center.offset([dx, dy, dz]))
+        })
+    })
+}
+
+/// finds the block closest to `origin` that matches `predicate`, looking no
+/// further than `max_radius` blocks away. unloaded sections are skipped.
+pub fn find_nearest_block<F>(
+    access: &mut ChunkAccess,
+    origin: BlockPos,
+    mut predicate: F,
+    max_radius: i32,
+) -> Option<BlockPos>
+where
+    F: FnMut(BlockId) -> bool,
+{
+    let len = CHUNK_LENGTH as i32;
+    let max_distance_sq = max_radius as i64 * max_radius as i64;
+    let center = ChunkSectionPos::from(origin);
+    let mut best: Option<(i64, BlockPos)> = None;
+
+    for radius in 0..=crate::util::floor_div(max_radius, len) + 1 {
+        // every block in this shell is at least this far away from the origin,
+        // so if we already found something closer, there's no point in looking
+        // any further out.
+        let min_distance = i64::max(0, (radius as i64 - 1) * len as i64 + 1);
+        if best.is_some_and(|(best, _)| best <= min_distance * min_distance) {
+            break;
+        }
+
+        for pos in section_shell(center, radius) {
+            let section = match access.section(pos) {
+                Some(section) => section,
+                None => continue,
+            };
+
+            let mut consider = |candidate: BlockPos| {
+                let dist = distance_sq(origin, candidate);
+                if dist <= max_distance_sq && best.is_none_or(|(best, _)| dist < best) {
+                    best = Some((dist, candidate));
+                }
+            };
+
+            let (min, max) = section_bounds(pos);
+            match section.blocks() {
+                &ChunkData::Homogeneous(id) if !predicate(id) => {}
+                // every block matches, so the closest one is just the closest
+                // point in the section.
+                &ChunkData::Homogeneous(_) => consider(BlockPos {
+                    x: origin.x.clamp(min.x, max.x),
+                    y: origin.y.clamp(min.y, max.y),
+                    z: origin.z.clamp(min.z, max.z),
+                }),
+                ChunkData::Array(blocks) => {
+                    for x in 0..CHUNK_LENGTH {
+                        for z in 0..CHUNK_LENGTH {
+                            for y in 0..CHUNK_LENGTH {
+                                if predicate(blocks[[x, y, z]]) {
+                                    consider(min.offset([x as i32, y as i32, z as i32]));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    best.map(|(_, pos)| pos)
+}
+
+/// finds every loaded block between `min` and `max` (inclusive) that matches
+/// `predicate`.
+pub fn find_blocks_in_region<F>(
+    access: &mut ChunkAccess,
+    min: BlockPos,
+    max: BlockPos,
+    mut predicate: F,
+) -> Vec<BlockPos>
+where
+    F: FnMut(BlockId) -> bool,
+{
+    let mut found = Vec::new();
+    let min_section = ChunkSectionPos::from(min);
+    let max_section = ChunkSectionPos::from(max);
+
+    for sx in min_section.x..=max_section.x {
+        for sy in min_section.y..=max_section.y {
+            for sz in min_section.z..=max_section.z {
+                let pos = ChunkSectionPos::new([sx, sy, sz]);
+                let section = match access.section(pos) {
+                    Some(section) => section,
+                    None => continue,
+                };
+
+                // the part of the region that this section covers
+                let (section_min, section_max) = section_bounds(pos);
+                let lo = [
+                    i32::max(min.x, section_min.x),
+                    i32::max(min.y, section_min.y),
+                    i32::max(min.z, section_min.z),
+                ];
+                let hi = [
+                    i32::min(max.x, section_max.x),
+                    i32::min(max.y, section_max.y),
+                    i32::min(max.z, section_max.z),
+                ];
+
+                let homogeneous_match = match section.blocks() {
+                    &ChunkData::Homogeneous(id) if !predicate(id) => continue,
+                    &ChunkData::Homogeneous(_) => true,
+                    ChunkData::Array(_) => false,
+                };
+
+                for x in lo[0]..=hi[0] {
+                    for z in lo[2]..=hi[2] {
+                        for y in lo[1]..=hi[1] {
+                            let block = BlockPos { x, y, z };
+                            let local = [x - section_min.x, y - section_min.y, z - section_min.z];
+                            if homogeneous_match
+                                || predicate(section.blocks().get(local.map(|n| n as usize)))
+                            {
+                                found.push(block);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    found
+}
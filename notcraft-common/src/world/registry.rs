@@ -1,10 +1,23 @@
+use super::{
+    behavior::BlockInteraction,
+    block_entity::BlockEntityKind,
+    circuit::{CircuitComponent, SIGNAL_OFF_STATE, SIGNAL_ON_STATE},
+    fluid::{flowing_state_name, SOURCE_STATE},
+    lighting::{BlockLight, BLOCK_LIGHT_MASK},
+    orientation::{facing_name, Orientable, Orientation},
+};
 use crate::{
+    aabb::Aabb,
+    content_pack::{ContentPacks, BASE_PACK},
+    explosion::{ExplosiveProperties, LIT_STATE},
+    physics::SurfaceProperties,
     codec::{
         encode::{Encode, Encoder},
         NodeKind,
     },
     prelude::*,
-    Faces,
+    tool::{ToolProperties, ToolRequirement},
+    Faces, Side,
 };
 use serde::Deserialize;
 use std::{
@@ -16,6 +29,10 @@ use std::{
 
 pub const AIR_BLOCK: BlockId = BlockId(0);
 
+/// how many tiles a pool of connected textures has, one for every combination
+/// of a face's four neighbors.
+pub const CONNECTED_TILE_COUNT: usize = 16;
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Default)]
 pub struct TexturePoolId(usize);
 
@@ -36,6 +53,17 @@ pub struct BlockTextureReference {
     faces: Faces<Option<String>>,
 }
 
+/// how a block texture animates. the texture's image is a vertical strip of
+/// `frames` frames, top to bottom, each of which gets its own layer in the
+/// texture array, one after the other.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TextureAnimation {
+    pub frames: u32,
+    /// how many seconds each frame is shown for.
+    pub frame_time: f32,
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum CollisionType {
@@ -60,20 +88,127 @@ impl CollisionType {
     }
 }
 
+/// a box that entities collide with, in block-local coordinates, where the
+/// block itself spans from 0 to 1 along each axis. boxes can stick out of the
+/// top of their block by up to one block, like fences do, but must otherwise
+/// stay inside of it.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub struct CollisionBox {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+/// how far above its block a collision box is allowed to reach.
+pub const MAX_COLLISION_OVERHANG: f32 = 1.0;
+
+/// describes what happens when a fluid touches a particular block.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FluidReaction {
+    /// the name of the block that triggers this reaction.
+    pub touching: String,
+    /// the block that a source block of this fluid turns into.
+    pub source_result: String,
+    /// the block that a flowing block of this fluid turns into.
+    pub flowing_result: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FluidProperties {
+    /// the number of fluid ticks between each step of this fluid spreading.
+    pub spread_delay: u32,
+    /// how many blocks this fluid can flow horizontally away from a source
+    /// block.
+    pub spread_distance: u8,
+    #[serde(default)]
+    pub reactions: Vec<FluidReaction>,
+    /// the color of the fog seen by a camera inside this fluid.
+    #[serde(default)]
+    pub fog_color: Option<[f32; 3]>,
+    #[serde(default)]
+    pub fog_density: f32,
+    /// the amount of damage per second that entities submerged in this fluid
+    /// take.
+    #[serde(default)]
+    pub submerged_damage: f32,
+}
+
 #[derive(Clone, Debug, PartialEq, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct BlockProperties {
     collision_type: CollisionType,
+    /// the boxes that make up a solid block's collision shape. solid blocks
+    /// without any are full cubes.
+    #[serde(default)]
+    collision_boxes: Option<Vec<CollisionBox>>,
     #[serde(default)]
     liquid: bool,
     #[serde(default)]
     wind_sway: bool,
+    /// whether this block is tinted by the climate it's in, like grass.
+    #[serde(default)]
+    tinted: bool,
     #[serde(default)]
     block_light: u16,
+    /// the color of the light given off by this block, as red, green, and blue
+    /// from 0 to 1. defaults to white.
+    #[serde(default)]
+    light_color: Option<[f32; 3]>,
     #[serde(default)]
     light_transmissible: bool,
     #[serde(default)]
     break_when_unrooted: bool,
+    /// whether entities inside of this block can climb it, like a ladder.
+    #[serde(default)]
+    climbable: bool,
+    /// how entities move around while standing on this block.
+    #[serde(default)]
+    surface: SurfaceProperties,
+    /// how well this block holds up against explosions, and how long it takes
+    /// to break in survival.
+    #[serde(default)]
+    hardness: f32,
+    /// the tool needed to get anything out of this block when it's broken.
+    /// it can still be broken without one, just more slowly.
+    #[serde(default)]
+    required_tool: Option<ToolRequirement>,
+    /// makes this block a tool, which can be held but not placed.
+    #[serde(default)]
+    tool: Option<ToolProperties>,
+    /// how many seconds have to pass after breaking this block before the
+    /// next block can be broken.
+    #[serde(default)]
+    break_cooldown: f32,
+    /// how many seconds have to pass after placing this block before the next
+    /// block can be placed.
+    #[serde(default)]
+    place_cooldown: f32,
+    /// which set of sounds this block makes when it's broken, placed, or
+    /// walked on. blocks without one are silent.
+    #[serde(default)]
+    sound_group: Option<String>,
+    /// what kind of extra data is attached to each of these blocks, if any.
+    #[serde(default)]
+    block_entity: Option<BlockEntityKind>,
+    /// what happens when this block is right-clicked, if anything.
+    #[serde(default)]
+    interaction: Option<BlockInteraction>,
+    /// what part this block plays in circuits, if any.
+    #[serde(default)]
+    circuit: Option<CircuitComponent>,
+    /// how this block blows up once it's lit, for explosives like TNT.
+    #[serde(default)]
+    explosive: Option<ExplosiveProperties>,
+    /// whether holding this block lets players light explosives, like flint.
+    #[serde(default)]
+    igniter: bool,
+    /// whether this block can be placed facing different directions, and
+    /// which ones.
+    #[serde(default)]
+    orientable: Option<Orientable>,
+    #[serde(default)]
+    fluid: Option<FluidProperties>,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
@@ -82,6 +217,8 @@ pub enum BlockMeshType {
     None,
     FullCube,
     Cross,
+    /// the block looks like its collision boxes.
+    Boxes,
 }
 
 impl Default for BlockMeshType {
@@ -94,6 +231,13 @@ impl Default for BlockMeshType {
 #[serde(rename_all = "kebab-case")]
 pub struct BlockDescription {
     name: String,
+    /// the name players see for this block. defaults to `name`.
+    #[serde(default)]
+    display_name: Option<String>,
+    /// the content pack this block was defined in, which is filled in while
+    /// the packs are merged. `None` means the base game.
+    #[serde(skip)]
+    pack: Option<String>,
     properties: BlockProperties,
     #[serde(default)]
     mesh_type: BlockMeshType,
@@ -106,19 +250,71 @@ pub struct BlockDescription {
     /// variants change the textures for just that block face.
     #[serde(default)]
     texture_variants: Option<Vec<BlockTextureReference>>,
+
+    /// a texture pool of [`CONNECTED_TILE_COUNT`] tiles that full cube blocks
+    /// use instead of their texture variants, so that faces of the same block
+    /// next to each other look like one continuous surface. see
+    /// [`RegistryRef::connected_textures`] for which tile goes where.
+    #[serde(default)]
+    connected_textures: Option<String>,
+
+    /// the different states this block can be in, like a door being open or
+    /// closed. each state gets its own block ID, the first of which is what
+    /// the block's name refers to.
+    #[serde(default)]
+    states: Vec<BlockStateDescription>,
+}
+
+/// the ways that a block state differs from the block it's a state of.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BlockStateDescription {
+    name: String,
+    #[serde(default)]
+    collision_boxes: Option<Vec<CollisionBox>>,
+    #[serde(default)]
+    block_light: Option<u16>,
+    #[serde(default)]
+    texture_variants: Option<Vec<BlockTextureReference>>,
+}
+
+/// which state of which block a block ID is.
+#[derive(Clone, Debug, PartialEq)]
+struct BlockStateInfo {
+    name: String,
+    /// the ID of the block's first state.
+    first: BlockId,
+    index: usize,
+    count: usize,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct BlockRegistryEntry {
     name: String,
+    display_name: Option<String>,
+    pack: Option<String>,
     properties: BlockProperties,
+    /// the block's collision boxes, ready to be offset by a block position.
+    collision_shape: Vec<Aabb>,
+    /// `block_light`, split up into color channels by `light_color`.
+    block_light: BlockLight,
     mesh_type: BlockMeshType,
     textures: Option<Vec<Faces<TexturePoolId>>>,
+    connected_textures: Option<TexturePoolId>,
+    state: Option<BlockStateInfo>,
+    orientation: Option<Orientation>,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Default)]
 pub struct BlockId(pub(crate) usize);
 
+impl BlockId {
+    /// the number this block is stored as in the world.
+    pub fn raw(self) -> usize {
+        self.0
+    }
+}
+
 impl<W: std::io::Write> Encode<W> for BlockId {
     const KIND: NodeKind = NodeKind::UnsignedVarInt;
 
@@ -127,12 +323,21 @@ impl<W: std::io::Write> Encode<W> for BlockId {
     }
 }
 
-fn add_texture_to_pool(reg: &mut BlockRegistry, pool: TexturePoolId, path: &Path) -> TextureId {
+fn add_texture_to_pool(
+    reg: &mut BlockRegistry,
+    pool: TexturePoolId,
+    path: &Path,
+    animation: Option<TextureAnimation>,
+) -> TextureId {
     let pool = &mut reg.texture_pools[pool.0];
 
-    let id = TextureId(reg.texture_paths.len());
+    let id = TextureId(reg.texture_layer_count);
     reg.texture_indices.insert(path.into(), id);
     reg.texture_paths.push(path.into());
+    reg.texture_layer_count += animation.map_or(1, |animation| animation.frames as usize);
+    if let Some(animation) = animation {
+        reg.texture_animations.insert(path.into(), animation);
+    }
 
     pool.push(id);
     id
@@ -150,7 +355,86 @@ fn register_texture_pool(reg: &mut BlockRegistry, name: &str) -> TexturePoolId {
     id
 }
 
+fn make_collision_shape(desc: &BlockDescription) -> Result<Vec<Aabb>> {
+    let properties = &desc.properties;
+    let boxes = match (&properties.collision_boxes, properties.collision_type) {
+        (Some(boxes), CollisionType::Solid) => boxes,
+        (Some(_), _) => bail!("block '{}' has collision boxes, but isn't solid", desc.name),
+        (None, CollisionType::Solid) => {
+            return Ok(vec![Aabb {
+                min: [0.0; 3].into(),
+                max: [1.0; 3].into(),
+            }])
+        }
+        (None, _) => return Ok(vec![]),
+    };
+
+    let mut shape = Vec::with_capacity(boxes.len());
+    for &CollisionBox { min, max } in boxes {
+        let in_block = (0..3).all(|i| {
+            let top = match i {
+                1 => 1.0 + MAX_COLLISION_OVERHANG,
+                _ => 1.0,
+            };
+            0.0 <= min[i] && min[i] < max[i] && max[i] <= top
+        });
+        if !in_block {
+            let name = &desc.name;
+            bail!("block '{}' has a bad collision box {:?}", name, [min, max]);
+        }
+        shape.push(Aabb {
+            min: min.into(),
+            max: max.into(),
+        });
+    }
+
+    Ok(shape)
+}
+
+fn make_block_light(desc: &BlockDescription) -> Result<BlockLight> {
+    let properties = &desc.properties;
+    let max_light = BLOCK_LIGHT_MASK;
+    if properties.block_light > max_light {
+        let name = &desc.name;
+        bail!("block '{}' gives off more than {} light", name, max_light);
+    }
+
+    let color = properties.light_color.unwrap_or([1.0; 3]);
+    if !color.iter().all(|channel| (0.0..=1.0).contains(channel)) {
+        bail!("block '{}' has a bad light color {:?}", desc.name, color);
+    }
+
+    Ok(color.map(|channel| (channel * properties.block_light as f32).round() as u16))
+}
+
 fn make_entry(reg: &mut BlockRegistry, desc: BlockDescription) -> Result<BlockRegistryEntry> {
+    let collision_shape = make_collision_shape(&desc)?;
+    let block_light = make_block_light(&desc)?;
+    if desc.mesh_type == BlockMeshType::Boxes && collision_shape.is_empty() {
+        bail!("block '{}' is made of boxes, but doesn't have any", desc.name);
+    }
+    if desc.properties.hardness < 0.0 {
+        bail!("block '{}' has a negative hardness", desc.name);
+    }
+    if desc.properties.break_cooldown < 0.0 || desc.properties.place_cooldown < 0.0 {
+        bail!("block '{}' has a negative cooldown", desc.name);
+    }
+    if let Some(tool) = &desc.properties.tool {
+        if !tool.is_valid() {
+            bail!("block '{}' is a bad tool {:?}", desc.name, tool);
+        }
+    }
+    if !desc.properties.surface.is_valid() {
+        let surface = &desc.properties.surface;
+        bail!("block '{}' has a bad surface {:?}", desc.name, surface);
+    }
+    if let Some(explosive) = &desc.properties.explosive {
+        let valid = explosive.fuse >= 0.0 && explosive.radius > 0.0 && explosive.power > 0.0;
+        if !valid {
+            bail!("block '{}' has a bad explosive {:?}", desc.name, explosive);
+        }
+    }
+
     let textures = match desc.texture_variants {
         Some(variants) => {
             let mut res = Vec::with_capacity(variants.len());
@@ -169,24 +453,138 @@ fn make_entry(reg: &mut BlockRegistry, desc: BlockDescription) -> Result<BlockRe
         None => None,
     };
 
+    let connected_textures = match &desc.connected_textures {
+        Some(pool) => {
+            let id = match reg.texture_pool_indices.get(pool) {
+                Some(&id) => id,
+                None => bail!("block '{}' has unknown connected textures '{}'", desc.name, pool),
+            };
+            if reg.pool_textures(id).len() != CONNECTED_TILE_COUNT {
+                let name = &desc.name;
+                bail!("connected textures of '{}' need {} tiles", name, CONNECTED_TILE_COUNT);
+            }
+            Some(id)
+        }
+        None => None,
+    };
+
     Ok(BlockRegistryEntry {
         name: desc.name,
+        display_name: desc.display_name,
+        pack: desc.pack,
         properties: desc.properties,
+        collision_shape,
+        block_light,
         mesh_type: desc.mesh_type,
         textures,
+        connected_textures,
+        state: None,
+        orientation: None,
     })
 }
 
+/// makes an entry for every state of a block, or just the one entry for blocks
+/// without any states.
+fn make_entries(
+    reg: &mut BlockRegistry,
+    mut desc: BlockDescription,
+    first: BlockId,
+) -> Result<Vec<BlockRegistryEntry>> {
+    let mut states = std::mem::take(&mut desc.states);
+    let orientable = desc.properties.orientable;
+    if let Some(kind) = orientable {
+        if desc.mesh_type != BlockMeshType::FullCube {
+            bail!("orientable block '{}' isn't a full cube", desc.name);
+        }
+        if !states.is_empty() {
+            bail!("orientable block '{}' has states of its own", desc.name);
+        }
+        // each way the block can face is a state of its own
+        states = kind
+            .facings()
+            .iter()
+            .map(|&facing| BlockStateDescription {
+                name: facing_name(facing).into(),
+                collision_boxes: None,
+                block_light: None,
+                texture_variants: None,
+            })
+            .collect();
+    }
+    if let Some(fluid) = &desc.properties.fluid {
+        if !states.is_empty() {
+            bail!("fluid block '{}' has states of its own", desc.name);
+        }
+        // flowing fluid keeps how much further it can spread in its state, so
+        // that it's saved along with the block
+        states = std::iter::once(SOURCE_STATE.to_string())
+            .chain((0..fluid.spread_distance).map(flowing_state_name))
+            .map(|name| BlockStateDescription {
+                name,
+                collision_boxes: None,
+                block_light: None,
+                texture_variants: None,
+            })
+            .collect();
+    }
+    if desc.properties.circuit.is_some() {
+        for needed in [SIGNAL_OFF_STATE, SIGNAL_ON_STATE] {
+            if !states.iter().any(|state| state.name == needed) {
+                bail!("circuit block '{}' has no '{}' state", desc.name, needed);
+            }
+        }
+    }
+    if desc.properties.explosive.is_some() && !states.iter().any(|state| state.name == LIT_STATE) {
+        bail!("explosive block '{}' has no '{}' state", desc.name, LIT_STATE);
+    }
+    if states.is_empty() {
+        return Ok(vec![make_entry(reg, desc)?]);
+    }
+
+    let mut entries = Vec::with_capacity(states.len());
+    for (index, state) in states.iter().enumerate() {
+        if states[..index].iter().any(|other| other.name == state.name) {
+            bail!("block '{}' has two '{}' states", desc.name, state.name);
+        }
+
+        let mut desc = desc.clone();
+        if let Some(boxes) = &state.collision_boxes {
+            desc.properties.collision_boxes = Some(boxes.clone());
+        }
+        if let Some(light) = state.block_light {
+            desc.properties.block_light = light;
+        }
+        if let Some(variants) = &state.texture_variants {
+            desc.texture_variants = Some(variants.clone());
+        }
+        let mut entry = make_entry(reg, desc)?;
+        entry.state = Some(BlockStateInfo {
+            name: state.name.clone(),
+            first,
+            index,
+            count: states.len(),
+        });
+        entry.orientation = orientable.map(|kind| Orientation {
+            kind,
+            facing: kind.facings()[index],
+        });
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
 #[derive(Clone, Debug, PartialEq, Default)]
 pub struct BlockRegistry {
     name_map: HashMap<String, BlockId>,
     entries: Vec<BlockRegistryEntry>,
 
     // the order here is important: the indices will becomes layers in a texture array that holds
-    // the actual texture data.
+    // the actual texture data. animated textures take up one layer per frame.
     texture_paths: Vec<PathBuf>,
     // texture *paths* to texture ID
     texture_indices: HashMap<PathBuf, TextureId>,
+    texture_animations: HashMap<PathBuf, TextureAnimation>,
+    texture_layer_count: usize,
 
     // texture pool *names* to texture ID
     texture_pools: Vec<Vec<TextureId>>,
@@ -197,28 +595,104 @@ pub struct BlockRegistry {
 #[serde(rename_all = "kebab-case")]
 struct RegistryManifest {
     textures: HashMap<String, Vec<String>>,
+    /// texture *paths* to how they animate. textures that aren't in here are
+    /// just a single frame.
+    #[serde(default)]
+    animations: HashMap<String, TextureAnimation>,
     blocks: Vec<BlockDescription>,
 }
 
+fn read_manifest(path: &Path) -> Result<RegistryManifest> {
+    Ok(serde_json::from_reader(File::open(path)?)?)
+}
+
 pub fn load_registry<P: AsRef<Path>>(path: P) -> Result<Arc<BlockRegistry>> {
-    let manifest: RegistryManifest = serde_json::from_reader(File::open(path)?)?;
+    build_registry(read_manifest(path.as_ref())?)
+}
+
+/// loads the registry at `path` along with the `blocks.json` of every enabled
+/// content pack, which can add blocks and texture pools, or replace ones that
+/// are already there. textures are looked for in each pack's `textures/blocks`
+/// directory, and `textures/blocks` next to `path`.
+pub fn load_registry_with_packs<P: AsRef<Path>>(
+    path: P,
+    packs: &mut ContentPacks,
+) -> Result<Arc<BlockRegistry>> {
+    let path = path.as_ref();
+    let mut manifests = vec![];
+    for (pack, path) in packs.layers(path, "blocks.json") {
+        match read_manifest(&path) {
+            Ok(manifest) => manifests.push((pack, manifest)),
+            Err(err) if pack == BASE_PACK => return Err(err),
+            // a broken pack shouldn't keep the game from starting
+            Err(err) => log::error!("failed to read blocks from pack '{}': {}", pack, err),
+        }
+    }
+
+    let (mut pools, mut animations, mut blocks) = (vec![], vec![], vec![]);
+    for (pack, manifest) in manifests {
+        pools.push((pack.clone(), manifest.textures.into_iter().collect()));
+        animations.push((pack.clone(), manifest.animations.into_iter().collect()));
+        let named = |mut block: BlockDescription| {
+            block.pack = Some(pack.clone());
+            (block.name.clone(), block)
+        };
+        let named = manifest.blocks.into_iter().map(named).collect::<Vec<_>>();
+        blocks.push((pack, named));
+    }
+    let manifest = RegistryManifest {
+        textures: packs.merge("texture pool", pools).into_iter().collect(),
+        animations: packs.merge("animation", animations).into_iter().collect(),
+        blocks: packs
+            .merge("block", blocks)
+            .into_iter()
+            .map(|(_, block)| block)
+            .collect(),
+    };
+
+    // packs can also replace the textures of blocks that they don't touch
+    let texture_dir = path.with_file_name("textures").join("blocks");
+    let texture_layers = packs.layers(&texture_dir, "textures/blocks");
+    for texture in manifest.textures.values().flatten() {
+        let owners = texture_layers
+            .iter()
+            .filter(|(_, dir)| dir.join(texture).exists())
+            .map(|(pack, _)| &**pack)
+            .collect::<Vec<_>>();
+        packs.record_shadowed("texture", texture, &owners);
+    }
+
+    build_registry(manifest)
+}
+
+fn build_registry(manifest: RegistryManifest) -> Result<Arc<BlockRegistry>> {
     let mut registry = BlockRegistry::default();
 
+    for (path, animation) in &manifest.animations {
+        if !manifest.textures.values().flatten().any(|other| other == path) {
+            bail!("texture '{}' is animated, but isn't in any texture pool", path);
+        }
+        if animation.frames == 0 || animation.frame_time <= 0.0 {
+            bail!("texture '{}' has a bad animation {:?}", path, animation);
+        }
+    }
+
     let unknown_pool = register_texture_pool(&mut registry, "unknown");
-    add_texture_to_pool(&mut registry, unknown_pool, Path::new("unknown.png"));
+    add_texture_to_pool(&mut registry, unknown_pool, Path::new("unknown.png"), None);
 
     for (pool_name, paths) in manifest.textures {
         let pool = register_texture_pool(&mut registry, &pool_name);
         for path in paths {
-            add_texture_to_pool(&mut registry, pool, Path::new(&path));
+            let animation = manifest.animations.get(&path).copied();
+            add_texture_to_pool(&mut registry, pool, Path::new(&path), animation);
         }
     }
 
     for block in manifest.blocks {
-        let id = registry.entries.len();
-        registry.name_map.insert(block.name.clone(), BlockId(id));
-        let entry = make_entry(&mut registry, block)?;
-        registry.entries.push(entry);
+        let id = BlockId(registry.entries.len());
+        registry.name_map.insert(block.name.clone(), id);
+        let entries = make_entries(&mut registry, block, id)?;
+        registry.entries.extend(entries);
     }
 
     Ok(Arc::new(registry))
@@ -233,6 +707,25 @@ impl BlockRegistry {
         self.name_map[name]
     }
 
+    pub fn try_lookup(&self, name: &str) -> Option<BlockId> {
+        self.name_map.get(name).copied()
+    }
+
+    /// every block ID in the registry, including each state of blocks that
+    /// have them, in order.
+    pub fn ids(&self) -> impl Iterator<Item = BlockId> {
+        (0..self.entries.len()).map(BlockId)
+    }
+
+    /// the ID of a particular state of the block called `name`.
+    pub fn try_lookup_state(&self, name: &str, state: &str) -> Option<BlockId> {
+        let first = self.try_lookup(name)?;
+        let count = self.entries[first.0].state.as_ref()?.count;
+        (first.0..first.0 + count)
+            .map(BlockId)
+            .find(|&id| self.get(id).state_name() == Some(state))
+    }
+
     pub fn name(&self, id: BlockId) -> &str {
         &self.entries[id.0].name
     }
@@ -241,10 +734,38 @@ impl BlockRegistry {
         self.texture_paths.iter().map(|s| &**s)
     }
 
+    /// how the texture at `path` animates, if it does.
+    pub fn texture_animation(&self, path: &Path) -> Option<TextureAnimation> {
+        self.texture_animations.get(path).copied()
+    }
+
+    /// every animated texture, along with the layer of its first frame.
+    pub fn texture_animations<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (TextureId, TextureAnimation)> + 'a {
+        let mut layer = 0;
+        self.texture_paths.iter().filter_map(move |path| {
+            let id = TextureId(layer);
+            let animation = self.texture_animation(path);
+            layer += animation.map_or(1, |animation| animation.frames as usize);
+            Some((id, animation?))
+        })
+    }
+
+    /// how many layers the block texture array needs, which is more than the
+    /// number of textures when some of them are animated.
+    pub fn texture_layer_count(&self) -> usize {
+        self.texture_layer_count
+    }
+
     #[inline(always)]
     pub fn pool_textures(&self, id: TexturePoolId) -> &[TextureId] {
         &self.texture_pools[id.0]
     }
+
+    pub fn texture_pool(&self, name: &str) -> Option<TexturePoolId> {
+        self.texture_pool_indices.get(name).copied()
+    }
 }
 
 pub struct RegistryRef<'reg> {
@@ -262,11 +783,31 @@ impl<'reg> RegistryRef<'reg> {
         &self.registry.entries[self.id.0].name
     }
 
+    /// the name players see for this block, which is its registry name
+    /// unless it has a display name of its own.
+    pub fn display_name(&self) -> &'reg str {
+        let entry = &self.registry.entries[self.id.0];
+        entry.display_name.as_deref().unwrap_or(&entry.name)
+    }
+
+    /// the content pack that this block came from.
+    pub fn pack(&self) -> &'reg str {
+        let entry = &self.registry.entries[self.id.0];
+        entry.pack.as_deref().unwrap_or(BASE_PACK)
+    }
+
     #[inline(always)]
     pub fn collision_type(&self) -> CollisionType {
         self.registry.entries[self.id.0].properties.collision_type
     }
 
+    /// the boxes that entities collide with, relative to the block's origin.
+    /// this is empty for blocks that aren't solid.
+    #[inline(always)]
+    pub fn collision_shape(&self) -> &'reg [Aabb] {
+        &self.registry.entries[self.id.0].collision_shape
+    }
+
     #[inline(always)]
     pub fn liquid(&self) -> bool {
         self.registry.entries[self.id.0].properties.liquid
@@ -278,8 +819,127 @@ impl<'reg> RegistryRef<'reg> {
     }
 
     #[inline(always)]
-    pub fn block_light(&self) -> u16 {
-        self.registry.entries[self.id.0].properties.block_light
+    pub fn tinted(&self) -> bool {
+        self.registry.entries[self.id.0].properties.tinted
+    }
+
+    /// the light given off by this block, for each color channel.
+    #[inline(always)]
+    pub fn block_light(&self) -> BlockLight {
+        self.registry.entries[self.id.0].block_light
+    }
+
+    #[inline(always)]
+    pub fn climbable(&self) -> bool {
+        self.registry.entries[self.id.0].properties.climbable
+    }
+
+    #[inline(always)]
+    pub fn surface(&self) -> SurfaceProperties {
+        self.registry.entries[self.id.0].properties.surface
+    }
+
+    #[inline(always)]
+    pub fn hardness(&self) -> f32 {
+        self.registry.entries[self.id.0].properties.hardness
+    }
+
+    #[inline(always)]
+    pub fn required_tool(&self) -> Option<&'reg ToolRequirement> {
+        let properties = &self.registry.entries[self.id.0].properties;
+        properties.required_tool.as_ref()
+    }
+
+    #[inline(always)]
+    pub fn tool(&self) -> Option<&'reg ToolProperties> {
+        self.registry.entries[self.id.0].properties.tool.as_ref()
+    }
+
+    #[inline(always)]
+    pub fn break_cooldown(&self) -> f32 {
+        self.registry.entries[self.id.0].properties.break_cooldown
+    }
+
+    #[inline(always)]
+    pub fn place_cooldown(&self) -> f32 {
+        self.registry.entries[self.id.0].properties.place_cooldown
+    }
+
+    #[inline(always)]
+    pub fn sound_group(&self) -> Option<&'reg str> {
+        self.registry.entries[self.id.0].properties.sound_group.as_deref()
+    }
+
+    #[inline(always)]
+    pub fn block_entity(&self) -> Option<BlockEntityKind> {
+        self.registry.entries[self.id.0].properties.block_entity
+    }
+
+    #[inline(always)]
+    pub fn interaction(&self) -> Option<BlockInteraction> {
+        self.registry.entries[self.id.0].properties.interaction
+    }
+
+    #[inline(always)]
+    pub fn circuit(&self) -> Option<CircuitComponent> {
+        self.registry.entries[self.id.0].properties.circuit
+    }
+
+    #[inline(always)]
+    pub fn explosive(&self) -> Option<ExplosiveProperties> {
+        self.registry.entries[self.id.0].properties.explosive
+    }
+
+    #[inline(always)]
+    pub fn igniter(&self) -> bool {
+        self.registry.entries[self.id.0].properties.igniter
+    }
+
+    /// the name of the state this block is in, for blocks that have states.
+    pub fn state_name(&self) -> Option<&'reg str> {
+        let state = self.registry.entries[self.id.0].state.as_ref()?;
+        Some(&state.name)
+    }
+
+    /// the ID of this block's state called `name`, if it has one.
+    pub fn state(&self, name: &str) -> Option<BlockId> {
+        self.registry.try_lookup_state(self.name(), name)
+    }
+
+    /// which way this block is facing, for orientable blocks.
+    #[inline(always)]
+    pub fn orientation(&self) -> Option<Orientation> {
+        self.registry.entries[self.id.0].orientation
+    }
+
+    /// the state this block is in when it's placed against the `clicked` side
+    /// of another block by someone looking along `yaw`. blocks that aren't
+    /// orientable are placed as they are.
+    pub fn placed(&self, clicked: Side, yaw: f32) -> BlockId {
+        let kind = match self.registry.entries[self.id.0].properties.orientable {
+            Some(kind) => kind,
+            None => return self.id,
+        };
+        let facing = kind.placement(clicked, yaw);
+        self.state(facing_name(facing)).unwrap_or(self.id)
+    }
+
+    /// whether `other` is a state of the same block as this is.
+    pub fn same_block(&self, other: BlockId) -> bool {
+        let first = |id: BlockId| match &self.registry.entries[id.0].state {
+            Some(state) => state.first,
+            None => id,
+        };
+        first(self.id) == first(other)
+    }
+
+    /// the block's next state, wrapping around to the first one after the last.
+    /// blocks without states just stay the same.
+    pub fn next_state(&self) -> BlockId {
+        match &self.registry.entries[self.id.0].state {
+            Some(state) => BlockId(state.first.0 + (state.index + 1) % state.count),
+            None => self.id,
+        }
     }
 
     #[inline(always)]
@@ -296,6 +956,39 @@ impl<'reg> RegistryRef<'reg> {
             .break_when_unrooted
     }
 
+    #[inline(always)]
+    pub fn fluid(&self) -> Option<&'reg FluidProperties> {
+        self.registry.entries[self.id.0].properties.fluid.as_ref()
+    }
+
+    /// how many more blocks a fluid block can spread. sources can always
+    /// spread their fluid's full distance.
+    pub fn fluid_level(&self) -> Option<u8> {
+        let fluid = self.fluid()?;
+        match &self.registry.entries[self.id.0].state {
+            Some(state) if state.index > 0 => Some(state.index as u8 - 1),
+            _ => Some(fluid.spread_distance),
+        }
+    }
+
+    pub fn is_fluid_source(&self) -> bool {
+        self.fluid().is_some() && self.state_name() == Some(SOURCE_STATE)
+    }
+
+    /// the state of this fluid that can spread `level` more blocks. fluid can
+    /// never spread further than from its source, so higher levels are just
+    /// the source.
+    pub fn flowing(&self, level: u8) -> BlockId {
+        let (fluid, state) = match (self.fluid(), &self.registry.entries[self.id.0].state) {
+            (Some(fluid), Some(state)) => (fluid, state),
+            _ => return self.id,
+        };
+        match level < fluid.spread_distance {
+            true => BlockId(state.first.0 + 1 + level as usize),
+            false => state.first,
+        }
+    }
+
     #[inline(always)]
     pub fn mesh_type(&self) -> BlockMeshType {
         self.registry.entries[self.id.0].mesh_type
@@ -305,4 +998,14 @@ impl<'reg> RegistryRef<'reg> {
     pub fn block_textures(&self) -> Option<&'reg Vec<Faces<TexturePoolId>>> {
         self.registry.entries[self.id.0].textures.as_ref()
     }
+
+    /// the pool of tiles that this block's faces connect with, if it has one.
+    /// each face uses the tile whose index has a bit set for each neighbor in
+    /// the plane of the face that's the same block: bit 0 for the neighbor
+    /// towards the right of the texture, bit 1 for the top, bit 2 for the
+    /// left, and bit 3 for the bottom.
+    #[inline(always)]
+    pub fn connected_textures(&self) -> Option<TexturePoolId> {
+        self.registry.entries[self.id.0].connected_textures
+    }
 }
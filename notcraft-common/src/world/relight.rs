@@ -0,0 +1,160 @@
+//! working out the light in loaded sections again from scratch.
+//!
+//! lighting is normally only updated around blocks that change, so light that
+//! went stale, like after a block's light properties changed in the registry,
+//! sticks around until something nearby is edited. sections queued in the
+//! [`RelightQueue`] get all of their light thrown away and recomputed, a few
+//! at a time each frame so that relighting a big area doesn't stall the game.
+
+use super::{
+    chunk::{self, ChunkAccess, ChunkSectionPos, DirtySections},
+    send_modified_sections, BlockPos, ChunkPos, VoxelWorld, WorldEvent,
+};
+use crate::{lang::tr, notification::Notify, prelude::*};
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// how long relighting can take each frame. at least one section is always
+/// relit, even if that takes longer.
+const RELIGHT_BUDGET: Duration = Duration::from_millis(2);
+
+#[derive(Debug, Default)]
+pub struct RelightQueue {
+    pending: VecDeque<ChunkSectionPos>,
+    queued: HashSet<ChunkSectionPos>,
+    // how many sections have been queued and relit since the queue was last
+    // empty, for reporting progress.
+    total: usize,
+    done: usize,
+    // how many quarters of the way through have been reported.
+    reported: usize,
+}
+
+impl RelightQueue {
+    /// queues `sections` to be relit, in order, skipping the ones that are
+    /// already waiting. returns how many were queued.
+    pub fn queue<I>(&mut self, sections: I) -> usize
+    where
+        I: IntoIterator<Item = ChunkSectionPos>,
+    {
+        let before = self.pending.len();
+        for pos in sections {
+            if self.queued.insert(pos) {
+                self.pending.push_back(pos);
+            }
+        }
+        let added = self.pending.len() - before;
+        self.total += added;
+        added
+    }
+
+    /// queues every loaded section in the chunks up to `radius` chunks away
+    /// from `center`, nearest first.
+    pub fn queue_around(&mut self, world: &VoxelWorld, center: BlockPos, radius: i32) -> usize {
+        let center = ChunkSectionPos::from(center);
+        let mut sections = vec![];
+        for x in center.x - radius..=center.x + radius {
+            for z in center.z - radius..=center.z + radius {
+                if let Some(chunk) = world.chunk(ChunkPos { x, z }) {
+                    let ys = chunk.sections().keys().copied().collect::<Vec<_>>();
+                    sections.extend(ys.into_iter().map(|y| ChunkSectionPos { x, y, z }));
+                }
+            }
+        }
+
+        let distance = |pos: &ChunkSectionPos| {
+            let [dx, dy, dz] = [pos.x - center.x, pos.y - center.y, pos.z - center.z];
+            dx * dx + dy * dy + dz * dz
+        };
+        sections.sort_by_key(distance);
+        self.queue(sections)
+    }
+
+    /// how many sections are still waiting to be relit.
+    pub fn remaining(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn next(&mut self) -> Option<ChunkSectionPos> {
+        let pos = self.pending.pop_front()?;
+        self.queued.remove(&pos);
+        self.done += 1;
+        Some(pos)
+    }
+
+    /// the percentage to tell the player about, if relighting has gotten
+    /// another quarter of the way through since it was last reported.
+    fn progress(&mut self) -> Option<usize> {
+        if self.total == 0 {
+            return None;
+        }
+        let quarters = 4 * self.done / self.total;
+        if quarters <= self.reported {
+            return None;
+        }
+        self.reported = quarters;
+        Some(25 * quarters)
+    }
+}
+
+pub(super) fn relight_sections(
+    world: Res<Arc<VoxelWorld>>,
+    mut queue: ResMut<RelightQueue>,
+    mut access: ResMut<ChunkAccess>,
+    mut chunk_events: EventWriter<WorldEvent>,
+    mut notify_events: EventWriter<Notify>,
+) {
+    if queue.remaining() == 0 {
+        return;
+    }
+
+    let start = Instant::now();
+    let mut rebuild = DirtySections::new();
+    while let Some(pos) = queue.next() {
+        // sections that were unloaded since they were queued don't need it
+        chunk::relight_section(&mut access, pos, &mut rebuild);
+        if start.elapsed() >= RELIGHT_BUDGET {
+            break;
+        }
+    }
+    send_modified_sections(&world, &rebuild, &mut chunk_events);
+
+    if let Some(percent) = queue.progress() {
+        let message = match queue.remaining() {
+            0 => tr!("notify.relight_done", queue.total),
+            _ => tr!("notify.relight_progress", percent, queue.total),
+        };
+        notify_events.send(Notify::info(message));
+    }
+    if queue.remaining() == 0 {
+        *queue = RelightQueue::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(x: i32) -> ChunkSectionPos {
+        ChunkSectionPos { x, y: 0, z: 0 }
+    }
+
+    #[test]
+    fn progress_is_reported_every_quarter() {
+        let mut queue = RelightQueue::default();
+        assert_eq!(queue.queue((0..8).map(section)), 8);
+        // sections that are already waiting aren't queued twice
+        assert_eq!(queue.queue((6..10).map(section)), 2);
+        assert_eq!(queue.remaining(), 10);
+
+        let mut reported = vec![];
+        while let Some(pos) = queue.next() {
+            assert_eq!(pos, section(queue.done as i32 - 1));
+            reported.extend(queue.progress());
+        }
+        assert_eq!(reported, [25, 50, 75, 100]);
+    }
+}
@@ -0,0 +1,473 @@
+//! reproduction capsules, for chunk bugs that are too rare to catch in the act.
+//!
+//! while updates are being written to a section, the thread writing them
+//! remembers what it's doing. if a chunk invariant fails partway through, like
+//! an index landing outside of a section, the panic hook calls
+//! [`write_capsule`], which saves the section as it was before the write,
+//! whichever of its neighbors can still be read, and the updates that were
+//! being written into a file in [`CAPSULE_DIR`].
+//!
+//! [`load_capsule`] builds a minimal world out of just those sections, and
+//! [`Capsule::replay`] writes the same updates to it again, which is what the
+//! client does when it's started with `--load-crash-dump`.
+
+use super::{
+    chunk::{
+        index_to_block, write_all_chunk_updates, Chunk, ChunkAccess, ChunkData, ChunkSection,
+        ChunkSectionPos, ChunkSectionUpdate, DirtySections, CHUNK_LENGTH,
+    },
+    generation::SurfaceHeightmap,
+    lighting::LightValue,
+    persistence::{lookup_saved_block, saved_block_name},
+    registry::{BlockId, BlockRegistry, AIR_BLOCK},
+    ChunkPos, VoxelWorld,
+};
+use crate::{
+    codec::{
+        decode::{decode_node, Node},
+        encode::{encode_node, Encode, Encoder},
+        NodeKind,
+    },
+    prelude::*,
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// where capsules are saved, relative to the directory the game was started
+/// in.
+pub const CAPSULE_DIR: &str = "crash-dumps";
+
+pub const CAPSULE_FORMAT_VERSION: u64 = 1;
+
+/// a write to a section that this thread is in the middle of.
+struct SectionWrite {
+    world: Arc<VoxelWorld>,
+    pos: ChunkSectionPos,
+    updates: Vec<ChunkSectionUpdate>,
+    /// the section's blocks and light from before any of the updates were
+    /// written to it. the section itself can't be read while it's being
+    /// written to.
+    before: Option<(ChunkData<BlockId>, ChunkData<LightValue>)>,
+}
+
+thread_local! {
+    static CURRENT_WRITE: RefCell<Option<SectionWrite>> = const { RefCell::new(None) };
+}
+
+/// remembers a section write for as long as it's alive, so that a capsule can
+/// be made of it if the write panics.
+pub(crate) struct WriteScope {
+    _private: (),
+}
+
+impl WriteScope {
+    pub(crate) fn enter(
+        world: &Arc<VoxelWorld>,
+        pos: ChunkSectionPos,
+        updates: &[ChunkSectionUpdate],
+    ) -> Self {
+        let write = SectionWrite {
+            world: Arc::clone(world),
+            pos,
+            updates: updates.to_vec(),
+            before: None,
+        };
+        CURRENT_WRITE.with(|current| *current.borrow_mut() = Some(write));
+        WriteScope { _private: () }
+    }
+
+    /// keeps a copy of the section from before any updates are written to it.
+    pub(crate) fn keep_section(&self, blocks: &ChunkData<BlockId>, light: &ChunkData<LightValue>) {
+        CURRENT_WRITE.with(|current| {
+            if let Some(write) = current.borrow_mut().as_mut() {
+                write.before = Some((blocks.clone(), light.clone()));
+            }
+        });
+    }
+}
+
+impl Drop for WriteScope {
+    fn drop(&mut self) {
+        // the panic hook might have taken the write already
+        let _ = CURRENT_WRITE.try_with(|current| current.borrow_mut().take());
+    }
+}
+
+/// a section in a capsule. block entities aren't kept.
+#[derive(Clone, Debug, PartialEq)]
+struct CapsuleSection {
+    pos: ChunkSectionPos,
+    blocks: ChunkData<BlockId>,
+    light: ChunkData<LightValue>,
+}
+
+impl<W: Write> Encode<W> for CapsuleSection {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode_map(|mut map| {
+            map.entry("pos").encode(&self.pos)?;
+            map.entry("blocks").encode(&self.blocks)?;
+            map.entry("light").encode(&self.light)?;
+            Ok(())
+        })
+    }
+}
+
+struct CapsuleUpdate(ChunkSectionUpdate);
+
+impl<W: Write> Encode<W> for CapsuleUpdate {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        let [x, y, z] = self.0.index;
+        encoder.encode_map(|mut map| {
+            map.entry("x").encode(&x)?;
+            map.entry("y").encode(&y)?;
+            map.entry("z").encode(&z)?;
+            map.entry("id").encode(&self.0.id)?;
+            Ok(())
+        })
+    }
+}
+
+struct CapsuleFile<'a> {
+    write: &'a SectionWrite,
+    sections: &'a [CapsuleSection],
+    message: &'a str,
+}
+
+impl<W: Write> Encode<W> for CapsuleFile<'_> {
+    const KIND: NodeKind = NodeKind::Map;
+
+    fn encode(&self, encoder: Encoder<W>) -> Result<()> {
+        encoder.encode_map(|mut map| {
+            map.entry("version").encode(&CAPSULE_FORMAT_VERSION)?;
+            map.entry("message").encode(self.message)?;
+            map.entry("operation").encode("write-blocks")?;
+            map.entry("center").encode(&self.write.pos)?;
+            let updates = self.write.updates.iter().copied().map(CapsuleUpdate);
+            map.entry("updates").encode_verbatim_list(updates)?;
+            map.entry("sections")
+                .encode_verbatim_list(self.sections.iter())?;
+            // indexed by block ID
+            let registry = &self.write.world.registry;
+            let names = registry.ids().map(|id| saved_block_name(registry, id));
+            let names = names.collect::<Vec<_>>();
+            map.entry("block-names")
+                .encode_verbatim_list(names.iter())?;
+            Ok(())
+        })
+    }
+}
+
+/// the section being written to and its neighbors, skipping any that can't be
+/// read without waiting on a writer.
+fn capture_sections(write: &SectionWrite) -> Vec<CapsuleSection> {
+    let mut sections = vec![];
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            for dz in -1..=1 {
+                let pos = ChunkSectionPos {
+                    x: write.pos.x + dx,
+                    y: write.pos.y + dy,
+                    z: write.pos.z + dz,
+                };
+                if let (true, Some((blocks, light))) = (pos == write.pos, &write.before) {
+                    let (blocks, light) = (blocks.clone(), light.clone());
+                    sections.push(CapsuleSection { pos, blocks, light });
+                    continue;
+                }
+
+                // sections that this thread is writing to are locked until the
+                // write is done, so waiting on them would never finish.
+                let section = write.world.section(pos);
+                match section.as_deref().and_then(ChunkSection::try_snapshot) {
+                    Some(snapshot) => sections.push(CapsuleSection {
+                        pos,
+                        blocks: snapshot.blocks().clone(),
+                        light: snapshot.light().clone(),
+                    }),
+                    None if section.is_some() => {
+                        log::warn!("couldn't read section {:?} for the capsule", pos)
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+    sections
+}
+
+fn save_capsule(dir: &Path, write: &SectionWrite, message: &str) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let pos = write.pos;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let name = format!("capsule_{}_{}_{}_{}.bin", pos.x, pos.y, pos.z, timestamp);
+    let path = dir.join(name);
+
+    let sections = capture_sections(write);
+    let capsule = CapsuleFile {
+        write,
+        sections: &sections,
+        message,
+    };
+    let mut file = BufWriter::new(File::create(&path)?);
+    encode_node(&mut file, &capsule)?;
+    file.flush()?;
+    Ok(path)
+}
+
+/// saves a capsule of the section write that this thread is in the middle of,
+/// if there is one. meant to be called from a panic hook, with the panic's
+/// message.
+pub fn write_capsule(message: &str) -> Option<Result<PathBuf>> {
+    let write = CURRENT_WRITE
+        .try_with(|current| current.try_borrow_mut().ok()?.take())
+        .ok()
+        .flatten()?;
+    Some(save_capsule(Path::new(CAPSULE_DIR), &write, message))
+}
+
+/// a capsule loaded into a world of its own.
+pub struct Capsule {
+    /// a world made of only the sections in the capsule.
+    pub world: Arc<VoxelWorld>,
+    /// the section that the updates were being written to.
+    pub center: ChunkSectionPos,
+    pub updates: Vec<ChunkSectionUpdate>,
+    /// the message of the panic that the capsule was made for.
+    pub message: String,
+    /// the names of blocks in the capsule that aren't in the registry, which
+    /// were loaded as air.
+    pub missing: Vec<String>,
+}
+
+impl Capsule {
+    /// writes the capsule's updates to its world again, the same way they were
+    /// being written when the capsule was made.
+    pub fn replay(&self) -> DirtySections {
+        let mut access = ChunkAccess::new(&self.world);
+        for update in self.updates.iter() {
+            access.set_block(index_to_block(self.center, update.index), update.id);
+        }
+        let mut rebuild = DirtySections::new();
+        write_all_chunk_updates(&mut access, &mut rebuild, &mut HashMap::default());
+        rebuild
+    }
+}
+
+impl fmt::Display for Capsule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let registry = &self.world.registry;
+        let pos = self.center;
+        writeln!(f, "panicked with: {}", self.message)?;
+        writeln!(
+            f,
+            "while writing to section ({}, {}, {})",
+            pos.x, pos.y, pos.z
+        )?;
+        writeln!(f, "{} block updates:", self.updates.len())?;
+        for update in self.updates.iter() {
+            let [x, y, z] = update.index;
+            let name = saved_block_name(registry, update.id);
+            writeln!(f, "  ({}, {}, {}) -> {}", x, y, z, name)?;
+        }
+        if !self.missing.is_empty() {
+            let missing = self.missing.join(", ");
+            writeln!(f, "blocks that were loaded as air: {}", missing)?;
+        }
+        Ok(())
+    }
+}
+
+fn field<'n>(node: &'n Node, key: &str) -> Result<&'n Node> {
+    node.get(key)
+        .ok_or_else(|| anyhow!("field '{}' is missing", key))
+}
+
+fn list<'n>(node: &'n Node, key: &str) -> Result<&'n [Node]> {
+    field(node, key)?
+        .as_list()
+        .ok_or_else(|| anyhow!("field '{}' is not a list", key))
+}
+
+fn unsigned(node: &Node, key: &str) -> Result<u128> {
+    field(node, key)?
+        .as_unsigned()
+        .ok_or_else(|| anyhow!("field '{}' is not an unsigned integer", key))
+}
+
+fn decode_section_pos(node: &Node) -> Result<ChunkSectionPos> {
+    let coord = |key| match field(node, key)?.as_signed() {
+        Some(value) => Ok(value as i32),
+        None => bail!("field '{}' is not a signed integer", key),
+    };
+    Ok(ChunkSectionPos {
+        x: coord("x")?,
+        y: coord("y")?,
+        z: coord("z")?,
+    })
+}
+
+fn decode_chunk_data<T, F>(node: &Node, key: &str, mut element: F) -> Result<ChunkData<T>>
+where
+    T: Copy + Eq,
+    F: FnMut(u128) -> Result<T>,
+{
+    let elements = list(node, key)?
+        .iter()
+        .map(|value| match value.as_unsigned() {
+            Some(value) => element(value),
+            None => bail!(
+                "field '{}' has an entry that is not an unsigned integer",
+                key
+            ),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ChunkData::from_elements(elements)
+        .ok_or_else(|| anyhow!("field '{}' is not the size of a section", key))
+}
+
+/// loads a capsule made by [`write_capsule`] into a new world, with the blocks
+/// in it translated to the ones in `registry`.
+pub fn load_capsule(path: &Path, registry: &Arc<BlockRegistry>) -> Result<Capsule> {
+    let node = decode_node(&mut BufReader::new(File::open(path)?))?;
+    match unsigned(&node, "version")? {
+        version if version == CAPSULE_FORMAT_VERSION as u128 => {}
+        version => bail!("capsule format version {} is not supported", version),
+    }
+    let message = match field(&node, "message")?.as_str() {
+        Some(message) => message.to_string(),
+        None => bail!("field 'message' is not a string"),
+    };
+    let center = decode_section_pos(field(&node, "center")?)?;
+
+    let mut missing = vec![];
+    let mut ids = vec![];
+    for name in list(&node, "block-names")? {
+        let name = name
+            .as_str()
+            .ok_or_else(|| anyhow!("field 'block-names' has an entry that is not a string"))?;
+        ids.push(lookup_saved_block(registry, name).unwrap_or_else(|| {
+            missing.push(name.to_string());
+            AIR_BLOCK
+        }));
+    }
+    let block_id = |raw: u128| match ids.get(raw as usize) {
+        Some(&id) => Ok(id),
+        None => bail!("block ID {} is not in 'block-names'", raw),
+    };
+
+    let updates = list(&node, "updates")?
+        .iter()
+        .map(|update| {
+            let coord = |key| unsigned(update, key).map(|value| value as usize);
+            Ok(ChunkSectionUpdate {
+                index: [coord("x")?, coord("y")?, coord("z")?],
+                id: block_id(unsigned(update, "id")?)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut sections = vec![];
+    for section in list(&node, "sections")? {
+        let pos = decode_section_pos(field(section, "pos")?)?;
+        let blocks = decode_chunk_data(section, "blocks", block_id)?;
+        let light = decode_chunk_data(section, "light", |raw| Ok(LightValue(raw as u16)))?;
+        sections.push(ChunkSection::new(pos, blocks, light));
+    }
+
+    // nothing below the capsule is known, so it's all treated as open to the
+    // sky, which the saved light already agrees with.
+    let bottom = sections.iter().map(|section| section.pos().y).min();
+    let heights = SurfaceHeightmap::flat(bottom.unwrap_or(0) * CHUNK_LENGTH as i32);
+    let mut chunks = HashMap::<ChunkPos, Chunk>::new();
+    for section in sections {
+        let column = section.pos().column();
+        let chunk = chunks
+            .entry(column)
+            .or_insert_with(|| Chunk::initialize(column, heights.clone()));
+        chunk
+            .sections_mut()
+            .insert(section.pos().y, Arc::new(section));
+    }
+
+    let world = VoxelWorld::new(registry);
+    for chunk in chunks.into_values() {
+        world.insert_chunk(Arc::new(chunk));
+    }
+    if world.section(center).is_none() {
+        bail!("the capsule is missing the section its updates were written to");
+    }
+
+    Ok(Capsule {
+        world,
+        center,
+        updates,
+        message,
+        missing,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        test_util::{flat_world, test_registry},
+        BlockPos,
+    };
+
+    #[test]
+    fn capsules_can_be_replayed() {
+        let registry = test_registry();
+        let [stone, dirt] = [registry.lookup("stone"), registry.lookup("dirt")];
+
+        // a flat stone floor, with the top of the floor at the bottom of the
+        // section that's written to.
+        let world = flat_world(&registry, 1, stone);
+
+        assert!(write_capsule("nothing is being written").is_none());
+
+        let center = ChunkSectionPos { x: 0, y: 0, z: 0 };
+        let updates = [ChunkSectionUpdate {
+            index: [3, 0, 5],
+            id: dirt,
+        }];
+        let scope = WriteScope::enter(&world, center, &updates);
+        let before = world.section(center).unwrap().snapshot();
+        scope.keep_section(before.blocks(), before.light());
+        drop(before);
+
+        let dir = std::env::temp_dir().join(format!("notcraft-capsule-{}", std::process::id()));
+        let write = CURRENT_WRITE.with(|current| current.borrow_mut().take().unwrap());
+        let saved = save_capsule(&dir, &write, "chunk index out of bounds").unwrap();
+        drop((scope, write));
+
+        let capsule = load_capsule(&saved, &registry).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(capsule.center, center);
+        assert_eq!(capsule.updates, updates);
+        assert_eq!(capsule.message, "chunk index out of bounds");
+        assert!(capsule.missing.is_empty());
+
+        let below = capsule.world.section(ChunkSectionPos { y: -1, ..center });
+        let below = below.unwrap().snapshot();
+        assert_eq!(below.blocks(), &ChunkData::Homogeneous(stone));
+
+        let rebuild = capsule.replay();
+        assert!(rebuild.contains_key(&center));
+        let pos = BlockPos::new([3, 0, 5]);
+        let (section, index) = pos.section_and_offset();
+        let section = capsule.world.section(section).unwrap().snapshot();
+        assert_eq!(section.blocks().get(index), dirt);
+    }
+}
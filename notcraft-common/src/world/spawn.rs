@@ -0,0 +1,177 @@
+//! picking a place for players to spawn that isn't buried inside of a
+//! mountain or hanging in the air.
+
+use super::{chunk::ChunkAccess, persistence::WorldPersistence, BlockPos, WorldGenerator};
+use crate::prelude::*;
+use std::collections::VecDeque;
+
+/// how far away from the world origin, in blocks, we look for a spawn point.
+pub const SPAWN_SEARCH_RADIUS: i32 = 64;
+
+/// the largest height difference between a column and its neighbors that
+/// still counts as flat enough to spawn on.
+const MAX_SPAWN_SLOPE: i32 = 1;
+
+/// where players spawn in the world.
+///
+/// when a world doesn't have a spawn point yet, one is guessed from the
+/// surface heightmap, which doesn't know about caves or anything placed after
+/// generation. the guess is only settled once its terrain has loaded and it
+/// turned out to be safe, and until then, it might move on to the next best
+/// candidate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorldSpawn {
+    pos: BlockPos,
+    settled: bool,
+    /// columns that haven't been ruled out yet, closest first. the first one
+    /// is always `pos`.
+    candidates: VecDeque<BlockPos>,
+}
+
+impl WorldSpawn {
+    pub fn settled(pos: BlockPos) -> Self {
+        Self {
+            pos,
+            settled: true,
+            candidates: Default::default(),
+        }
+    }
+
+    /// picks a spawn point near the world origin. the spawn point isn't
+    /// settled until [`settle_spawn`] has seen the terrain around it.
+    pub(super) fn unsettled(generator: &WorldGenerator) -> Self {
+        let candidates = spawn_candidates(generator);
+        Self {
+            pos: candidates[0],
+            settled: false,
+            candidates,
+        }
+    }
+
+    /// the block that a player's feet are in when they spawn.
+    pub fn pos(&self) -> BlockPos {
+        self.pos
+    }
+
+    pub fn is_settled(&self) -> bool {
+        self.settled
+    }
+
+    /// moves the spawn point somewhere else, without checking if it's safe.
+    pub fn set(&mut self, pos: BlockPos) {
+        *self = Self::settled(pos);
+    }
+}
+
+/// checks whether a player can spawn with their feet in `pos`: they need solid
+/// ground to stand on, and two blocks of open air to stand in. returns `None`
+/// if any of the blocks involved aren't loaded.
+pub fn is_safe_spawn(access: &mut ChunkAccess, pos: BlockPos) -> Option<bool> {
+    let ground = access.block(pos.offset([0, -1, 0]))?;
+    let feet = access.block(pos)?;
+    let head = access.block(pos.offset([0, 1, 0]))?;
+
+    let registry = access.registry();
+    let is_open = |id| {
+        let block = registry.get(id);
+        !block.collision_type().is_solid() && !block.collision_type().is_liquid() && !block.liquid()
+    };
+
+    Some(registry.get(ground).collision_type().is_solid() && is_open(feet) && is_open(head))
+}
+
+/// every column near the world origin that looks like a decent place to spawn
+/// according to the surface heightmap, closest first. this is never empty.
+fn spawn_candidates(generator: &WorldGenerator) -> VecDeque<BlockPos> {
+    // heights for the search area, plus a border of one block so that we can
+    // check the slope around the edge columns.
+    let side = 2 * SPAWN_SEARCH_RADIUS + 3;
+    let min = -SPAWN_SEARCH_RADIUS - 1;
+    let mut heights = Vec::with_capacity((side * side) as usize);
+    for x in min..min + side {
+        for z in min..min + side {
+            heights.push(generator.surface_height(x, z));
+        }
+    }
+    let height = |x: i32, z: i32| heights[((x - min) * side + (z - min)) as usize];
+
+    let mut candidates = Vec::new();
+    for x in -SPAWN_SEARCH_RADIUS..=SPAWN_SEARCH_RADIUS {
+        for z in -SPAWN_SEARCH_RADIUS..=SPAWN_SEARCH_RADIUS {
+            if x * x + z * z > SPAWN_SEARCH_RADIUS * SPAWN_SEARCH_RADIUS {
+                continue;
+            }
+
+            let y = height(x, z);
+            let neighbors = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+            let flat = neighbors
+                .iter()
+                .all(|&(dx, dz)| (height(x + dx, z + dz) - y).abs() <= MAX_SPAWN_SLOPE);
+            if flat {
+                candidates.push(BlockPos { x, y, z });
+            }
+        }
+    }
+
+    if candidates.is_empty() {
+        candidates.push(BlockPos {
+            x: 0,
+            y: height(0, 0),
+            z: 0,
+        });
+    }
+
+    candidates.sort_by_key(|pos| pos.x * pos.x + pos.z * pos.z);
+    candidates.into()
+}
+
+/// checks the current spawn candidate against the real terrain once it's
+/// loaded, moving on to the next one if it isn't safe.
+pub fn settle_spawn(mut spawn: ResMut<WorldSpawn>, mut access: ResMut<ChunkAccess>) {
+    if spawn.settled {
+        return;
+    }
+
+    let spawn = &mut *spawn;
+    while let Some(&pos) = spawn.candidates.front() {
+        match is_safe_spawn(&mut access, pos) {
+            // wait for the terrain to load
+            None => return,
+            Some(true) => {
+                log::info!("settled on spawn point at {} {} {}", pos.x, pos.y, pos.z);
+                spawn.set(pos);
+                return;
+            }
+            Some(false) => {
+                spawn.candidates.pop_front();
+                if let Some(&next) = spawn.candidates.front() {
+                    spawn.pos = next;
+                }
+            }
+        }
+    }
+
+    // none of the candidates were any good, so we just make do with the last one
+    let pos = spawn.pos;
+    log::warn!(
+        "couldn't find a safe spawn point, settling for {} {} {}",
+        pos.x,
+        pos.y,
+        pos.z
+    );
+    spawn.set(pos);
+}
+
+/// writes the spawn point out to the save whenever it's settled or moved.
+pub fn save_spawn(spawn: Res<WorldSpawn>, mut persistence: ResMut<WorldPersistence>) {
+    if !spawn.is_changed() || !spawn.is_settled() {
+        return;
+    }
+
+    let pos = spawn.pos();
+    if persistence.metadata().spawn != Some(pos) {
+        if let Err(err) = persistence.update_metadata(|meta| meta.spawn = Some(pos)) {
+            log::error!("failed to save spawn point: {}", err);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! small, hand-built worlds for tests to run against.
+
+use super::{
+    chunk::{ArrayChunk, Chunk, ChunkData, ChunkSection},
+    generation::SurfaceHeightmap,
+    registry::{load_registry, BlockId, BlockRegistry, AIR_BLOCK},
+    ChunkPos, VoxelWorld,
+};
+use std::sync::Arc;
+
+/// the block registry the game ships with.
+pub fn test_registry() -> Arc<BlockRegistry> {
+    load_registry(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../resources/blocks.json"
+    ))
+    .unwrap()
+}
+
+/// a world made of sections out to `radius` sections from the origin along
+/// every axis, with `floor` below y=0 and air above it.
+pub fn flat_world(registry: &Arc<BlockRegistry>, radius: i32, floor: BlockId) -> Arc<VoxelWorld> {
+    let world = VoxelWorld::new(registry);
+    for x in -radius..=radius {
+        for z in -radius..=radius {
+            let pos = ChunkPos { x, z };
+            let chunk = Chunk::initialize(pos, SurfaceHeightmap::flat(0));
+            for y in -radius..=radius {
+                let id = if y < 0 { floor } else { AIR_BLOCK };
+                let data = ChunkData::Homogeneous(id);
+                let section = ChunkSection::initialize(pos.section(y), data, registry);
+                chunk.sections_mut().insert(y, Arc::new(section));
+            }
+            world.insert_chunk(Arc::new(chunk));
+        }
+    }
+    world
+}
+
+/// a world where only the section at the origin is loaded, made of `blocks`.
+pub fn section_world(
+    registry: &Arc<BlockRegistry>,
+    blocks: ArrayChunk<BlockId>,
+) -> Arc<VoxelWorld> {
+    let world = VoxelWorld::new(registry);
+    let pos = ChunkPos { x: 0, z: 0 };
+    let chunk = Chunk::initialize(pos, SurfaceHeightmap::flat(0));
+    let section = ChunkSection::initialize(pos.section(0), ChunkData::Array(blocks), registry);
+    chunk.sections_mut().insert(0, Arc::new(section));
+    world.insert_chunk(Arc::new(chunk));
+    world
+}
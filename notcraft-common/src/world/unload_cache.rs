@@ -0,0 +1,105 @@
+//! a bounded cache of recently unloaded chunk sections.
+//!
+//! when a chunk column gets unloaded, its sections are compacted and kept here
+//! for a while, so that wandering back and forth across the load boundary
+//! doesn't mean generating the same sections over and over again. the least
+//! recently cached sections are evicted once the cache grows past its budget.
+
+use std::collections::{BTreeMap, HashMap};
+
+use super::chunk::{ChunkSectionPos, CompactedChunkSection};
+
+/// how many bytes of compacted sections are kept around by default.
+pub const DEFAULT_UNLOAD_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug)]
+struct CacheEntry {
+    section: CompactedChunkSection,
+    bytes: usize,
+    stamp: u64,
+}
+
+#[derive(Debug)]
+pub struct UnloadedSectionCache {
+    capacity_bytes: usize,
+    used_bytes: usize,
+    next_stamp: u64,
+    entries: HashMap<ChunkSectionPos, CacheEntry>,
+    // entries ordered from least to most recently inserted
+    order: BTreeMap<u64, ChunkSectionPos>,
+}
+
+impl Default for UnloadedSectionCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_UNLOAD_CACHE_BYTES)
+    }
+}
+
+impl UnloadedSectionCache {
+    pub fn new(capacity_bytes: usize) -> Self {
+        Self {
+            capacity_bytes,
+            used_bytes: 0,
+            next_stamp: 0,
+            entries: Default::default(),
+            order: Default::default(),
+        }
+    }
+
+    pub fn insert(&mut self, pos: ChunkSectionPos, section: CompactedChunkSection) {
+        self.take(pos);
+
+        let bytes = section.size_bytes();
+        if bytes > self.capacity_bytes {
+            return;
+        }
+
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        self.used_bytes += bytes;
+        self.order.insert(stamp, pos);
+        let entry = CacheEntry {
+            section,
+            bytes,
+            stamp,
+        };
+        self.entries.insert(pos, entry);
+
+        while self.used_bytes > self.capacity_bytes {
+            match self.order.values().next().copied() {
+                Some(oldest) => drop(self.take(oldest)),
+                None => break,
+            }
+        }
+    }
+
+    /// removes a section from the cache, returning it if it was present.
+    /// sections are taken out rather than copied, since a section that is
+    /// loaded again will be put back in the cache when it next unloads.
+    pub fn take(&mut self, pos: ChunkSectionPos) -> Option<CompactedChunkSection> {
+        let entry = self.entries.remove(&pos)?;
+        self.order.remove(&entry.stamp);
+        self.used_bytes -= entry.bytes;
+        Some(entry.section)
+    }
+
+    pub fn contains(&self, pos: ChunkSectionPos) -> bool {
+        self.entries.contains_key(&pos)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    pub fn capacity_bytes(&self) -> usize {
+        self.capacity_bytes
+    }
+}
@@ -8,6 +8,16 @@
 #define FOG_COLOR mix(SKY_COLOR_BASE, SKY_COLOR_BRIGHT, 0.5)
 #define FOG_COLOR_NIGHT mix(SKY_COLOR_NIGHT_BASE, SKY_COLOR_NIGHT_BRIGHT, 0.5)
 
+// the sky and fog fade towards this while it's raining or snowing
+#define WET_FOG_COLOR RGB(128, 134, 143)
+#define WET_FOG_DENSITY 2.5
+#define WET_SKY_FACTOR 0.8
+
+// the sun's disc in the sky. the intensity is well past 1 so that it blooms.
+#define SUN_COLOR RGB(255, 241, 214)
+#define SUN_INTENSITY 8.0
+#define SUN_ANGULAR_RADIUS 0.03
+
 #define DAY_NIGHT_LENGTH 60.0
 #define _DAY_NIGHT_M1_1(time) sin(3.14159 * time / DAY_NIGHT_LENGTH)
 #define _DAY_NIGHT_0_1(time) 0.5 * _DAY_NIGHT_M1_1(time) + 0.5
@@ -0,0 +1,29 @@
+#pragma include "/fullscreen_quad.vert"
+
+#pragma shaderstage fragment
+#version 330 core
+
+uniform sampler2D source;
+
+// the axis to blur along, either (1, 0) or (0, 1). the blur is separable, so
+// doing one of each is the same as a full 2d blur.
+uniform vec2 direction;
+
+in vec2 v_texcoord;
+
+out vec4 o_color;
+
+// a 9-tap gaussian kernel, of which only the center and one side are stored.
+const float WEIGHTS[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+    vec2 step = direction / vec2(textureSize(source, 0));
+
+    vec3 color = WEIGHTS[0] * texture(source, v_texcoord).rgb;
+    for (int i = 1; i < 5; ++i) {
+        color += WEIGHTS[i] * texture(source, v_texcoord + float(i) * step).rgb;
+        color += WEIGHTS[i] * texture(source, v_texcoord - float(i) * step).rgb;
+    }
+
+    o_color = vec4(color, 1.0);
+}
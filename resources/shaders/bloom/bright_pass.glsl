@@ -0,0 +1,30 @@
+#pragma include "/fullscreen_quad.vert"
+
+#pragma shaderstage fragment
+#version 330 core
+
+uniform sampler2D colorBuffer;
+
+// how bright a color has to be before it starts to glow.
+uniform float threshold;
+
+in vec2 v_texcoord;
+
+out vec4 o_color;
+
+void main() {
+    // this target is half the size of the world, so average the four pixels
+    // that land on each of ours, to keep small bright spots from flickering.
+    vec2 texel = 0.5 / vec2(textureSize(colorBuffer, 0));
+    vec3 color = vec3(0.0);
+    color += texture(colorBuffer, v_texcoord + vec2(-texel.x, -texel.y)).rgb;
+    color += texture(colorBuffer, v_texcoord + vec2( texel.x, -texel.y)).rgb;
+    color += texture(colorBuffer, v_texcoord + vec2(-texel.x,  texel.y)).rgb;
+    color += texture(colorBuffer, v_texcoord + vec2( texel.x,  texel.y)).rgb;
+    color *= 0.25;
+
+    // only the part of the color above the threshold glows, and it keeps its hue.
+    float brightness = max(color.r, max(color.g, color.b));
+    float contribution = max(0.0, brightness - threshold) / max(brightness, 0.0001);
+    o_color = vec4(color * contribution, 1.0);
+}
@@ -3,14 +3,15 @@
 
 uniform float screen_width;
 uniform float screen_height;
+uniform float uiScale;
 
 in vec2 uv;
 out vec2 v_texcoord;
 
 void main() {
-    vec2 pos = uv;
-    pos.x /= (screen_width / screen_height);
-    pos /= screen_height / 16.0;
+    // the crosshair is 4 UI units across.
+    vec2 pos = uv * 4.0 * uiScale;
+    pos /= vec2(screen_width, screen_height);
 
     v_texcoord = 0.5 * uv + 0.5;
     gl_Position = vec4(pos, 0.0, 1.0);
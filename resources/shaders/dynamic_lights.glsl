@@ -0,0 +1,51 @@
+#pragma include "./fullscreen_quad.vert"
+
+#pragma shaderstage fragment
+#version 330 core
+
+uniform sampler2D depthBuffer;
+uniform sampler2D albedoBuffer;
+uniform sampler2D normalBuffer;
+
+// one column per light. the first row holds each light's position and radius,
+// and the second holds its color.
+uniform sampler2D lights;
+uniform int lightCount;
+
+uniform mat4 viewMatrix;
+uniform mat4 projectionMatrix;
+
+in vec2 v_texcoord;
+in vec2 v_uv;
+
+out vec4 o_color;
+
+void main() {
+    float depth = 2.0 * texture(depthBuffer, v_texcoord).r - 1.0;
+    // the sky is never lit
+    if (depth >= 1.0) {
+        discard;
+    }
+
+    vec3 albedo = texture(albedoBuffer, v_texcoord).rgb;
+    vec3 normal = texture(normalBuffer, v_texcoord).xyz;
+
+    vec4 viewPos = inverse(projectionMatrix) * vec4(v_uv, depth, 1.0);
+    viewPos /= viewPos.w;
+    vec3 worldPos = (inverse(viewMatrix) * viewPos).xyz;
+
+    vec3 light = vec3(0.0);
+    for (int i = 0; i < lightCount; ++i) {
+        vec4 posRadius = texelFetch(lights, ivec2(i, 0), 0);
+        vec3 color = texelFetch(lights, ivec2(i, 1), 0).rgb;
+
+        vec3 toLight = posRadius.xyz - worldPos;
+        float dist = length(toLight);
+        float falloff = clamp(1.0 - dist / posRadius.w, 0.0, 1.0);
+        float facing = max(0.0, dot(normal, toLight / max(dist, 0.0001)));
+        light += color * falloff * falloff * facing;
+    }
+
+    // this is added on top of the already lit terrain
+    o_color = vec4(albedo * light, 0.0);
+}
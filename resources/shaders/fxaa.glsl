@@ -0,0 +1,61 @@
+#pragma include "./fullscreen_quad.vert"
+
+#pragma shaderstage fragment
+#version 330 core
+
+// the finished, tonemapped scene.
+uniform sampler2D colorBuffer;
+
+in vec2 v_texcoord;
+
+out vec4 o_color;
+
+// keep the search from stretching too far in flat, dark areas, where the
+// gradient is tiny.
+#define FXAA_REDUCE_MIN (1.0 / 128.0)
+#define FXAA_REDUCE_MUL (1.0 / 8.0)
+// the furthest along an edge that we look, in pixels.
+#define FXAA_SPAN_MAX 8.0
+
+float luma(vec3 color) {
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(colorBuffer, 0));
+
+    vec3 colorM = texture(colorBuffer, v_texcoord).rgb;
+    float lumaM = luma(colorM);
+    float lumaNW = luma(texture(colorBuffer, v_texcoord + vec2(-1.0, -1.0) * texel).rgb);
+    float lumaNE = luma(texture(colorBuffer, v_texcoord + vec2( 1.0, -1.0) * texel).rgb);
+    float lumaSW = luma(texture(colorBuffer, v_texcoord + vec2(-1.0,  1.0) * texel).rgb);
+    float lumaSE = luma(texture(colorBuffer, v_texcoord + vec2( 1.0,  1.0) * texel).rgb);
+
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    // the direction along the edge, which is perpendicular to the luma gradient
+    vec2 dir = vec2(
+        -((lumaNW + lumaNE) - (lumaSW + lumaSE)),
+         ((lumaNW + lumaSW) - (lumaNE + lumaSE)));
+
+    float dirReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    float rcpDirMin = 1.0 / (min(abs(dir.x), abs(dir.y)) + dirReduce);
+    dir = clamp(dir * rcpDirMin, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * texel;
+
+    vec3 colorA = 0.5 * (
+        texture(colorBuffer, v_texcoord + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture(colorBuffer, v_texcoord + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 colorB = colorA * 0.5 + 0.25 * (
+        texture(colorBuffer, v_texcoord + dir * -0.5).rgb +
+        texture(colorBuffer, v_texcoord + dir * 0.5).rgb);
+
+    // the wider blur can reach past the edge into something else entirely, in
+    // which case we fall back to the narrower one.
+    float lumaB = luma(colorB);
+    if (lumaB < lumaMin || lumaB > lumaMax) {
+        o_color = vec4(colorA, 1.0);
+    } else {
+        o_color = vec4(colorB, 1.0);
+    }
+}
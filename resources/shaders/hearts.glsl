@@ -0,0 +1,44 @@
+#pragma include "./fullscreen_quad.vert"
+
+#pragma shaderstage fragment
+#version 330 core
+
+uniform uvec2 screenDimensions;
+uniform float uiScale;
+uniform float health;
+uniform float maxHealth;
+uniform sampler2D heartTexture;
+
+out vec4 o_color;
+
+// each heart is this many texels wide, including a gap between it and the next heart.
+const float HEART_STRIDE = 10.0;
+// the heart texture is this many texels across.
+const float HEART_SIZE = 9.0;
+
+void main() {
+    // each texel of the heart texture is one UI unit.
+    float scale = uiScale;
+
+    // every heart represents 2 points of health.
+    float heartCount = ceil(maxHealth / 2.0);
+    float barWidth = heartCount * HEART_STRIDE - (HEART_STRIDE - HEART_SIZE);
+    vec2 origin = vec2(0.5 * (float(screenDimensions.x) - scale * barWidth), 8.0 * scale);
+
+    vec2 texel = (gl_FragCoord.xy - origin) / scale;
+    float index = floor(texel.x / HEART_STRIDE);
+    vec2 local = vec2(texel.x - index * HEART_STRIDE, texel.y);
+    if (index < 0.0 || index >= heartCount || local.x >= HEART_SIZE || local.y < 0.0 || local.y >= HEART_SIZE) {
+        discard;
+    }
+
+    vec4 heart = texture(heartTexture, local / HEART_SIZE);
+    if (heart.a == 0.0) {
+        discard;
+    }
+
+    // round health up to the nearest half heart, so that a sliver of health still shows up.
+    float filled = clamp(ceil(health) - 2.0 * index, 0.0, 2.0) / 2.0;
+    vec3 tint = local.x / HEART_SIZE < filled ? vec3(0.85, 0.08, 0.08) : vec3(0.2, 0.2, 0.2);
+    o_color = vec4(heart.rgb * tint, heart.a);
+}
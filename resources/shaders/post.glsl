@@ -11,10 +11,36 @@ uniform sampler2D colorBuffer;
 uniform sampler2D depthBuffer;
 
 uniform uvec2 screenDimensions;
+// how big the world was drawn compared to the window.
+uniform float renderScale;
 uniform vec3 cameraPosWorld;
 uniform mat4 viewMatrix;
 uniform mat4 projectionMatrix;
 
+// when the camera is inside of a fluid, this fog is used instead of the usual distance fog. a
+// density of 0 means that the camera is not submerged.
+uniform vec3 submergedFogColor;
+uniform float submergedFogDensity;
+
+// how much rain or snow is falling, from 0 to 1. precipitation thickens the
+// distance fog and washes it out.
+uniform float precipitationStrength;
+
+// the bright parts of the image, blurred by increasing amounts.
+uniform sampler2D bloom0;
+uniform sampler2D bloom1;
+uniform sampler2D bloom2;
+uniform sampler2D bloom3;
+uniform float bloomStrength;
+
+// colors are multiplied by the exposure before being squeezed into the range
+// that the screen can show by the tonemapper.
+uniform float exposure;
+uniform int tonemapper;
+
+#define TONEMAPPER_ACES 0
+#define TONEMAPPER_REINHARD 1
+
 uniform uint elapsedSeconds;
 uniform float elapsedSubseconds;
 
@@ -35,10 +61,64 @@ float fogFactorExp2(float density, float t) {
     return clamp(1.0 - exp(-(n * n)), 0.0, 1.0);
 }
 
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+vec3 tonemapAces(vec3 x) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+vec3 tonemapReinhard(vec3 x) {
+    return x / (1.0 + x);
+}
+
+vec3 tonemap(vec3 color) {
+    color *= exposure;
+    if (tonemapper == TONEMAPPER_REINHARD) {
+        return tonemapReinhard(color);
+    }
+    return tonemapAces(color);
+}
+
+// Matt Pettineo's Catmull-Rom filter, which stays sharper than a linear one
+// when the world is stretched over the window. it leans on linear filtering
+// to get away with 5 samples instead of 16, by leaving out the corners.
+vec3 sampleCatmullRom(sampler2D tex, vec2 uv) {
+    vec2 texSize = vec2(textureSize(tex, 0));
+    vec2 samplePos = uv * texSize;
+    vec2 texPos1 = floor(samplePos - 0.5) + 0.5;
+    vec2 f = samplePos - texPos1;
+
+    vec2 w0 = f * (-0.5 + f * (1.0 - 0.5 * f));
+    vec2 w1 = 1.0 + f * f * (-2.5 + 1.5 * f);
+    vec2 w2 = f * (0.5 + f * (2.0 - 1.5 * f));
+    vec2 w3 = f * f * (-0.5 + 0.5 * f);
+
+    vec2 w12 = w1 + w2;
+    vec2 texPos0 = (texPos1 - 1.0) / texSize;
+    vec2 texPos3 = (texPos1 + 2.0) / texSize;
+    vec2 texPos12 = (texPos1 + w2 / w12) / texSize;
+
+    vec3 result = texture(tex, vec2(texPos12.x, texPos0.y)).rgb * w12.x * w0.y;
+    result += texture(tex, vec2(texPos0.x, texPos12.y)).rgb * w0.x * w12.y;
+    result += texture(tex, texPos12).rgb * w12.x * w12.y;
+    result += texture(tex, vec2(texPos3.x, texPos12.y)).rgb * w3.x * w12.y;
+    result += texture(tex, vec2(texPos12.x, texPos3.y)).rgb * w12.x * w3.y;
+
+    // without the corners, the weights don't quite add up to one
+    float weight = w12.x * w0.y + w0.x * w12.y + w12.x * w12.y + w3.x * w12.y + w12.x * w3.y;
+    return max(result / weight, 0.0);
+}
+
 const highp float NOISE_GRANULARITY = 0.2/255.0;
 
 void main() {
-    vec3 originalColor = texture2D(colorBuffer, v_texcoord).rgb;
+    vec3 originalColor = renderScale < 1.0
+        ? sampleCatmullRom(colorBuffer, v_texcoord)
+        : texture2D(colorBuffer, v_texcoord).rgb;
     vec3 color = originalColor;
     float depth = 2.0 * texture2D(depthBuffer, v_texcoord).r - 1.0;
 
@@ -51,11 +131,24 @@ void main() {
     vec3 worldPos = (inverse(viewMatrix) * viewPos).xyz;
 
     float distToSurface = length(worldPos - cameraPosWorld) / DAY_NIGHT(900.0, 900.0);
-    float fogStrength = fogFactorExp(0.4, distToSurface);
+    float fogStrength = fogFactorExp(mix(0.4, WET_FOG_DENSITY, precipitationStrength), distToSurface);
     // float fogStrength = 0.0;
 
     vec3 fogColor = DAY_NIGHT(FOG_COLOR, FOG_COLOR_NIGHT);
+    fogColor = mix(fogColor, DAY_NIGHT(WET_FOG_COLOR, FOG_COLOR_NIGHT), precipitationStrength);
+    if (submergedFogDensity > 0.0) {
+        fogStrength = fogFactorExp(submergedFogDensity, distToSurface);
+        fogColor = submergedFogColor;
+    }
     vec3 finalColor = mix(color, fogColor, fogStrength);
+
+    vec3 bloom = texture(bloom0, v_texcoord).rgb;
+    bloom += texture(bloom1, v_texcoord).rgb;
+    bloom += texture(bloom2, v_texcoord).rgb;
+    bloom += texture(bloom3, v_texcoord).rgb;
+    finalColor += bloomStrength * 0.25 * bloom;
+
+    finalColor = tonemap(finalColor);
     finalColor += mix(-NOISE_GRANULARITY, NOISE_GRANULARITY, random(vec2(v_texcoord.x, v_texcoord.y + elapsedSubseconds)));
     
     o_color = vec4(finalColor, 1.0);
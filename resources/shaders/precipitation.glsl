@@ -0,0 +1,29 @@
+#pragma shaderstage vertex
+#version 330 core
+
+uniform mat4 view;
+uniform mat4 projection;
+
+in vec3 pos;
+in float alpha;
+
+out float v_alpha;
+
+void main() {
+    gl_Position = projection * view * vec4(pos, 1.0);
+    v_alpha = alpha;
+}
+
+#pragma shaderstage fragment
+#version 330 core
+
+// rain streaks and snowflakes are each drawn in a single flat color.
+uniform vec4 color;
+
+in float v_alpha;
+
+out vec4 o_color;
+
+void main() {
+    o_color = vec4(color.rgb, color.a * v_alpha);
+}
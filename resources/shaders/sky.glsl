@@ -15,6 +15,12 @@ uniform mat4 viewMatrix;
 uniform uint elapsedSeconds;
 uniform float elapsedSubseconds;
 
+// how much rain or snow is falling, from 0 to 1.
+uniform float precipitationStrength;
+
+// points towards the sun.
+uniform vec3 sunDirection;
+
 float elapsedTime() {
     return float(elapsedSeconds) + elapsedSubseconds;
 }
@@ -92,5 +98,19 @@ void main() {
         color = mix(color, cloudColor, cloudFactor);
     }
 
+    vec3 wetColor = DAY_NIGHT(WET_FOG_COLOR, FOG_COLOR_NIGHT);
+    color = mix(color, wetColor, WET_SKY_FACTOR * precipitationStrength);
+
+    // the sun is much brighter than anything else in the sky, so that it
+    // blooms. clouds and precipitation hide it.
+    float sunCloseness = dot(rayDirWorld.xyz, sunDirection);
+    float sunDisc = smoothstep(cos(SUN_ANGULAR_RADIUS * 1.2), cos(SUN_ANGULAR_RADIUS), sunCloseness);
+    float sunHalo = pow(max(0.0, sunCloseness), 256.0);
+    float sunVisibility = 1.0 - precipitationStrength;
+    if (p.intersects && distanceToEdge <= CLOUD_PLANE_DISTANCE_CUTOFF) {
+        sunVisibility *= 1.0 - smoothstep(0.15, 0.4, densityAt(p.point));
+    }
+    color += sunVisibility * SUN_COLOR * (SUN_INTENSITY * sunDisc + sunHalo);
+
     b_color = vec4(color, 1.0);
 }
@@ -9,19 +9,19 @@ uniform mat4 view;
 uniform mat4 projection;
 
 out float vSkyLight;
-out float vBlockLight;
+out vec3 vBlockLight;
 out float vStaticBrightness;
 out vec3 vWorldPos;
+out vec3 vNormal;
+out float vViewDepth;
 out vec2 vTextureUv;
 flat out int vTextureId;
+out vec2 vClimate;
+flat out int vTinted;
 
 #define AO_MIN_BRIGHTNESS 0.3
 #define AO_ATTENUATION 1.5
 
-float contribution(bool contribute, float strength) {
-    return 1.0 - float(contribute) * strength;
-}
-
 uniform uint elapsedSeconds;
 uniform float elapsedSubseconds;
 
@@ -29,17 +29,35 @@ float elapsedTime() {
     return 2.0 * (float(elapsedSeconds) + elapsedSubseconds);
 }
 
+// the frame count and frame time of each animated texture, at its first layer.
+uniform sampler2D textureAnimations;
+
+// animated textures have each of their frames in the layers right after their
+// first one, so this picks out the layer for the frame being shown right now.
+int animatedTextureId(int textureId) {
+    vec2 animation = texelFetch(textureAnimations, ivec2(textureId, 0), 0).xy;
+    if (animation.x <= 1.0) {
+        return textureId;
+    }
+
+    float seconds = float(elapsedSeconds) + elapsedSubseconds;
+    return textureId + int(mod(floor(seconds / animation.y), animation.x));
+}
+
 void main() {
     TerrainVertex vertex = unpackVertex();
 
-    vec3 worldPos = (model * vec4(vertex.modelPos, 1.0)).xyz;
+    vec3 worldPos = (model * vec4(vertex.modelPos, 1.0)).xyz + chunk_origin;
     vWorldPos = worldPos;
 
     if (vertex.windSway) {
         worldPos.xz += windTotal(worldPos, elapsedTime());
     }
 
-    gl_Position = projection * view * vec4(worldPos, 1.0);
+    vec4 viewPos = view * vec4(worldPos, 1.0);
+    vViewDepth = -viewPos.z;
+    vNormal = vertex.modelNormal;
+    gl_Position = projection * viewPos;
 
     float brightness = 1.0;
 
@@ -48,29 +66,26 @@ void main() {
     aoFactor = pow(aoFactor, AO_ATTENUATION);
     brightness *= aoFactor;
 
-    // directional lighting
-    float directionFactor = 1.0;
-    directionFactor *= contribution(vertex.axis == AXIS_X, 0.3); // X
-    directionFactor *= contribution(vertex.axis == AXIS_Y && vertex.axisSign == SIGN_POSITIVE, 0.0); // top
-    directionFactor *= contribution(vertex.axis == AXIS_Y && vertex.axisSign == SIGN_NEGATIVE, 0.5); // bottom
-    directionFactor *= contribution(vertex.axis == AXIS_Z, 0.4); // Z
-    brightness *= directionFactor;
-
     vBlockLight = vertex.blockLight;
     vSkyLight = vertex.skyLight;
     vStaticBrightness = brightness;
 
     vTextureUv = vertex.textureCoordinates;
-    vTextureId = vertex.textureId;
+    vTextureId = animatedTextureId(vertex.textureId);
+    vClimate = vertex.climate;
+    vTinted = int(vertex.tinted);
 }
 
 #pragma shaderstage fragment
 #version 330 core
 
 #pragma include "wind.glsl"
+#pragma include "shadows.glsl"
 #pragma include "/adjustables.glsl"
 
 uniform sampler2DArray albedo_maps;
+// how much tinted blocks are tinted, by temperature along x and humidity along y
+uniform sampler2D colormap;
 
 uniform uint elapsedSeconds;
 uniform float elapsedSubseconds;
@@ -80,13 +95,21 @@ float elapsedTime() {
 }
 
 in float vStaticBrightness;
-in float vBlockLight;
+in vec3 vBlockLight;
 in float vSkyLight;
 in vec2 vTextureUv;
 flat in int vTextureId;
+in vec2 vClimate;
+flat in int vTinted;
 in vec3 vWorldPos;
+in vec3 vNormal;
+in float vViewDepth;
 
 out vec3 b_color;
+// the surface's unlit color and its normal, so that dynamic lights can be drawn
+// on top of the terrain afterwards.
+out vec3 b_albedo;
+out vec3 b_normal;
 
 // const highp float NOISE_GRANULARITY = 0.2/255.0;
 
@@ -96,11 +119,34 @@ out vec3 b_color;
 // lower values mean that zero brightness is darker.
 #define LIGHT_MIN_BRIGHNESS 0.04
 
+// how bright sky light is in the sun's shadow, compared to in direct sunlight.
+#define SHADOW_BRIGHTNESS 0.55
+
+// surfaces right next to a light source are pushed past full brightness by up
+// to this much, so that they glow.
+#define BLOCK_LIGHT_GLOW 1.5
+
+// how bright faces that point straight away from the light are, compared to
+// faces that point right at it.
+#define FACE_MIN_BRIGHTNESS 0.45
+
+// faces are lit by how directly they face the sun, so that the terrain's shape
+// shows up differently over the day. once the sun has set, the light comes
+// from straight above instead, like it does from the rest of the sky.
+float faceShading(vec3 normal, float sunStrength) {
+    vec3 lightDirection = normalize(mix(vec3(0.0, 1.0, 0.0), sunDirection, sunStrength));
+    float facing = 0.5 + 0.5 * dot(normal, lightDirection);
+    return mix(FACE_MIN_BRIGHTNESS, 1.0, facing);
+}
+
 void main() {
     vec4 fragmentColor = texture(albedo_maps, vec3(vTextureUv, vTextureId));
     if (fragmentColor.a < 0.5) {
         discard;
     }
+    if (vTinted != 0) {
+        fragmentColor.rgb *= texture(colormap, vClimate).rgb;
+    }
 
     float cloudFactor = 1.0 - smoothstep(0.15, 0.4, cloudDensity(vec3(vWorldPos.x, 1000.0, vWorldPos.z), elapsedTime()));
     cloudFactor = mix(0.3, 1.0, pow(cloudFactor, 8.0));
@@ -109,16 +155,24 @@ void main() {
     float dayNightFactor = DAY_NIGHT_FACTOR(elapsedTime()); // [0, 1]
 
     float skyLightFactor = mix(LIGHT_MIN_BRIGHNESS, 1.0, pow(vSkyLight * DAY_NIGHT_FACTOR(elapsedTime()), LIGHT_ATTENUATION)); // [min, skyLight]
-    float blockLightFactor = mix(LIGHT_MIN_BRIGHNESS, 1.0, pow(vBlockLight, LIGHT_ATTENUATION)); // [min, blockLight]
+    vec3 blockLightFactor = mix(vec3(LIGHT_MIN_BRIGHNESS), vec3(1.0), pow(vBlockLight, vec3(LIGHT_ATTENUATION))); // [min, blockLight]
+    blockLightFactor += BLOCK_LIGHT_GLOW * pow(vBlockLight, vec3(8.0)); // [min, 1 + glow]
 
-    float brightness = 0.0;
+    float skyBrightness = 0.0;
+    skyBrightness = max(skyBrightness, skyLightFactor);
+    skyBrightness *= cloudFactor;
 
-    // [bmin, blockLight]
-    brightness = max(brightness, skyLightFactor);
-    brightness *= cloudFactor;
-    brightness = max(brightness, blockLightFactor);
+    // shadows only block the sun, not the rest of the sky, and they fade away
+    // as the sun sets.
+    float sunStrength = smoothstep(0.0, 0.2, sunDirection.y);
+    float shadowFactor = mix(SHADOW_BRIGHTNESS, 1.0, sunlight(vWorldPos, vNormal, vViewDepth));
+    skyBrightness *= mix(1.0, shadowFactor, sunStrength);
 
-    brightness *= vStaticBrightness;
+    // each channel of block light only brightens its own color, so colored light
+    // tints whatever it lands on, unless the sky is brighter than it anyways.
+    vec3 brightness = max(vec3(skyBrightness), blockLightFactor);
+
+    brightness *= vStaticBrightness * faceShading(vNormal, sunStrength);
 
     // if (cloudFactor > 0.001 && cloudFactor < 0.005) {
     //     fragmentColor.rgb += vec3(1.0);
@@ -130,6 +184,9 @@ void main() {
     //     fragmentColor.rgb += vec3(0.01);
     // } 
     // fragmentColor.rgb = vec3(cloudFactor);
+    b_albedo = fragmentColor.rgb * vStaticBrightness;
+    b_normal = vNormal;
+
     fragmentColor.rgb *= brightness;
     // fragmentColor.rgb = vec3(vTextureUv / 32.0, 1.0);
 
@@ -0,0 +1,36 @@
+#pragma include "unpack.glsl"
+
+#pragma shaderstage vertex
+
+uniform mat4 model;
+uniform mat4 lightViewProjection;
+
+out vec2 vTextureUv;
+flat out int vTextureId;
+
+void main() {
+    TerrainVertex vertex = unpackVertex();
+
+    // foliage doesn't sway in the shadow pass, its shadows are blurry enough
+    // that nobody will notice.
+    vec3 worldPos = (model * vec4(vertex.modelPos, 1.0)).xyz + chunk_origin;
+    gl_Position = lightViewProjection * vec4(worldPos, 1.0);
+
+    vTextureUv = vertex.textureCoordinates;
+    vTextureId = vertex.textureId;
+}
+
+#pragma shaderstage fragment
+#version 330 core
+
+uniform sampler2DArray albedo_maps;
+
+in vec2 vTextureUv;
+flat in int vTextureId;
+
+void main() {
+    // cutout blocks like leaves only cast shadows where they're opaque
+    if (texture(albedo_maps, vec3(vTextureUv, vTextureId)).a < 0.5) {
+        discard;
+    }
+}
@@ -0,0 +1,66 @@
+// sampling the sun's shadow maps, see `render_shadows` in the renderer.
+
+uniform sampler2DShadow shadowMap0;
+uniform sampler2DShadow shadowMap1;
+uniform sampler2DShadow shadowMap2;
+uniform mat4 shadowMatrix0;
+uniform mat4 shadowMatrix1;
+uniform mat4 shadowMatrix2;
+
+// how far from the camera each cascade reaches
+uniform vec3 shadowCascadeDistances;
+// zero when shadows are turned off
+uniform int shadowCascadeCount;
+// points towards the sun
+uniform vec3 sunDirection;
+
+// points are pushed out along their normal before looking them up in a shadow
+// map, so that surfaces don't shadow themselves. this is multiplied by the
+// cascade's distance, since further cascades have bigger texels.
+#define SHADOW_NORMAL_OFFSET_SCALE 0.004
+
+// how many texels in each direction are averaged to soften shadow edges
+#define SHADOW_PCF_RADIUS 1
+
+float sampleShadowMap(sampler2DShadow shadowMap, mat4 shadowMatrix, vec3 worldPos) {
+    vec4 lightPos = shadowMatrix * vec4(worldPos, 1.0);
+    vec3 coords = 0.5 * (lightPos.xyz / lightPos.w) + 0.5;
+    if (any(lessThan(coords, vec3(0.0))) || any(greaterThan(coords, vec3(1.0)))) {
+        return 1.0;
+    }
+
+    vec2 texel = 1.0 / vec2(textureSize(shadowMap, 0));
+    float lit = 0.0;
+    for (int x = -SHADOW_PCF_RADIUS; x <= SHADOW_PCF_RADIUS; ++x) {
+        for (int y = -SHADOW_PCF_RADIUS; y <= SHADOW_PCF_RADIUS; ++y) {
+            lit += texture(shadowMap, vec3(coords.xy + vec2(x, y) * texel, coords.z));
+        }
+    }
+
+    float width = float(2 * SHADOW_PCF_RADIUS + 1);
+    return lit / (width * width);
+}
+
+// how much direct sunlight reaches a point, from 0 (completely shadowed) to 1.
+float sunlight(vec3 worldPos, vec3 normal, float viewDepth) {
+    // faces pointing away from the sun are always in their own shadow
+    if (dot(normal, sunDirection) <= 0.0) {
+        return 0.0;
+    }
+
+    for (int i = 0; i < shadowCascadeCount; ++i) {
+        if (viewDepth > shadowCascadeDistances[i]) {
+            continue;
+        }
+
+        vec3 offsetPos = worldPos + normal * SHADOW_NORMAL_OFFSET_SCALE * shadowCascadeDistances[i];
+        switch (i) {
+            case 0: return sampleShadowMap(shadowMap0, shadowMatrix0, offsetPos);
+            case 1: return sampleShadowMap(shadowMap1, shadowMatrix1, offsetPos);
+            case 2: return sampleShadowMap(shadowMap2, shadowMatrix2, offsetPos);
+        }
+    }
+
+    // past the last cascade, nothing is shadowed
+    return 1.0;
+}
@@ -11,6 +11,10 @@
 
 in uint pos_ao;
 in uint light_flags_side_id;
+in uint tint;
+// where the chunk section that this vertex belongs to starts, for when several
+// sections are drawn at once.
+in vec3 chunk_origin;
 
 struct TerrainVertex {
     vec3 modelPos;
@@ -21,10 +25,14 @@ struct TerrainVertex {
     int  textureId;
     vec2 textureCoordinates;
 
-    float blockLight;
+    vec3  blockLight;
     float skyLight;
     float ao;
     bool  windSway;
+
+    bool tinted;
+    // the temperature and humidity of the block's climate
+    vec2 climate;
 };
 
 TerrainVertex unpackVertex() {
@@ -44,13 +52,24 @@ TerrainVertex unpackVertex() {
     float y  = float(BITS(pos_ao, 12, 10)) / 16.0;
     float x  = float(BITS(pos_ao, 22, 10)) / 16.0;
 
-    int textureId    = int  (BITS(light_flags_side_id, 0, 16));
+    int textureId    = int  (BITS(light_flags_side_id, 0, 12));
+    float blueLight  = float(BITS(light_flags_side_id, 12, 4)) / 16.0;
     int axis         = int  (BITS(light_flags_side_id, 16, 2));
     int axisSign     = int  (BITS(light_flags_side_id, 18, 1));
+    float greenLight = float(BITS(light_flags_side_id, 19, 4)) / 16.0;
     bool windSway    = bool (BITS(light_flags_side_id, 23, 1));
-    float blockLight = float(BITS(light_flags_side_id, 24, 4)) / 16.0;
+    float redLight   = float(BITS(light_flags_side_id, 24, 4)) / 16.0;
     float skyLight   = float(BITS(light_flags_side_id, 28, 4)) / 16.0;
 
+    vec3 blockLight = vec3(redLight, greenLight, blueLight);
+
+    float temperature = float(BITS(tint, 0, 7)) / 127.0;
+    float humidity    = float(BITS(tint, 7, 8)) / 255.0;
+    bool tinted       = bool (BITS(tint, 15, 1));
+    bool swapUv       = bool (BITS(tint, 16, 1));
+    bool flipU        = bool (BITS(tint, 17, 1));
+    bool flipV        = bool (BITS(tint, 18, 1));
+
     vec3 modelPos = vec3(x, y, z);
     vec3 modelNormal = normalTable[axis];
     modelNormal *= signTable[axisSign];
@@ -62,6 +81,11 @@ TerrainVertex unpackVertex() {
         vec2(x, y)
     );
     vec2 textureCoordinates = uvTable[axis];
+    // blocks that face different ways turn their textures along with them
+    if (swapUv) {
+        textureCoordinates = textureCoordinates.yx;
+    }
+    textureCoordinates *= vec2(flipU ? -1.0 : 1.0, flipV ? -1.0 : 1.0);
 
 
     return TerrainVertex(
@@ -74,7 +98,9 @@ TerrainVertex unpackVertex() {
         blockLight,
         skyLight,
         ao,
-        windSway
+        windSway,
+        tinted,
+        vec2(temperature, humidity)
     );
 }
 
@@ -0,0 +1,41 @@
+#pragma shaderstage vertex
+#version 330 core
+
+uniform mat4 view;
+uniform mat4 projection;
+
+in vec3 pos;
+in vec2 uv;
+in float layer;
+in vec4 color;
+
+out vec2 v_uv;
+out float v_layer;
+out vec4 v_color;
+
+void main() {
+    gl_Position = projection * view * vec4(pos, 1.0);
+    v_uv = uv;
+    v_layer = layer;
+    v_color = color;
+}
+
+#pragma shaderstage fragment
+#version 330 core
+
+uniform sampler2DArray blockTextures;
+
+in vec2 v_uv;
+in float v_layer;
+in vec4 v_color;
+
+out vec4 o_color;
+
+void main() {
+    o_color = v_color * texture(blockTextures, vec3(v_uv, v_layer));
+    // block textures with holes in them, like grass, shouldn't leave
+    // see-through squares behind.
+    if (o_color.a < 0.1) {
+        discard;
+    }
+}
@@ -0,0 +1,44 @@
+#pragma shaderstage vertex
+#version 330 core
+
+uniform uvec2 screenDimensions;
+// how many pixels each UI unit takes up, see `UiScale`.
+uniform float uiScale;
+
+in vec2 pos;
+in vec2 uv;
+in float layer;
+in vec4 color;
+
+out vec2 v_uv;
+out float v_layer;
+out vec4 v_color;
+
+void main() {
+    vec2 screen = vec2(screenDimensions);
+    vec2 pixel = floor(0.5 * screen) + uiScale * pos;
+
+    v_uv = uv;
+    v_layer = layer;
+    v_color = color;
+    gl_Position = vec4(2.0 * pixel / screen - 1.0, 0.0, 1.0);
+}
+
+#pragma shaderstage fragment
+#version 330 core
+
+uniform sampler2DArray blockTextures;
+
+in vec2 v_uv;
+in float v_layer;
+in vec4 v_color;
+
+out vec4 o_color;
+
+void main() {
+    if (v_layer < 0.0) {
+        o_color = v_color;
+    } else {
+        o_color = v_color * texture(blockTextures, vec3(v_uv, v_layer));
+    }
+}